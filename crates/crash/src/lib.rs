@@ -0,0 +1,252 @@
+//! Crash reports for the desktop app and the sync server.
+//!
+//! The panic hook installed here writes every panic to a JSON file in a crash
+//! directory before the default hook runs, so the report is on disk even when
+//! the panic takes the process down. Reports are kept locally whatever else is
+//! configured; the newest `MAX_REPORTS` are kept. They can be listed for users
+//! to share, and turned into events for a Sentry-compatible service when the
+//! user or operator has opted in to uploading. This crate only builds those
+//! requests; each binary sends them with its own HTTP client.
+
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Reports kept in a crash directory; older ones are deleted
+pub const MAX_REPORTS: usize = 50;
+
+/// Disambiguates reports written in the same millisecond
+static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// A panic, as written to the crash directory
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CrashReport {
+    /// File name without `.json`; sorts by time
+    pub id: String,
+    /// RFC 3339
+    pub occurred_at: String,
+    /// Which binary panicked, e.g. `beck-desktop`
+    pub app: String,
+    pub version: String,
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: Option<String>,
+    pub thread: Option<String>,
+    pub backtrace: String,
+    /// Whether it has been sent to the crash reporting service
+    #[serde(default)]
+    pub uploaded: bool,
+}
+
+impl CrashReport {
+    /// A report for a panic happening now
+    fn new(app: &str, version: &str, message: String, location: Option<String>) -> Self {
+        let now = Utc::now();
+        CrashReport {
+            id: format!(
+                "{}-{}-{}",
+                now.format("%Y%m%dT%H%M%S%3fZ"),
+                std::process::id(),
+                SEQUENCE.fetch_add(1, Ordering::Relaxed)
+            ),
+            occurred_at: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            app: app.to_string(),
+            version: version.to_string(),
+            message,
+            location,
+            thread: std::thread::current().name().map(str::to_string),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            uploaded: false,
+        }
+    }
+
+    fn from_panic(info: &PanicHookInfo, app: &str, version: &str) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+        CrashReport::new(app, version, message, location)
+    }
+}
+
+/// Write a report for every panic to `dir`, then run the hook that was
+/// installed before (by default, printing the panic to stderr)
+pub fn install_panic_hook(dir: PathBuf, app: &'static str, version: &'static str) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = CrashReport::from_panic(info, app, version);
+        if let Err(err) = write_report(&dir, &report) {
+            eprintln!("[crash] failed to write crash report: {}", err);
+        }
+        previous(info);
+    }));
+}
+
+fn report_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+/// Save a report, deleting the oldest beyond `MAX_REPORTS`
+pub fn write_report(dir: &Path, report: &CrashReport) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let json = serde_json::to_vec_pretty(report).map_err(io::Error::other)?;
+    fs::write(report_path(dir, &report.id), json)?;
+
+    let mut ids = report_ids(dir)?;
+    if ids.len() > MAX_REPORTS {
+        ids.sort();
+        for id in &ids[..ids.len() - MAX_REPORTS] {
+            let _ = fs::remove_file(report_path(dir, id));
+        }
+    }
+    Ok(())
+}
+
+fn report_ids(dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name();
+        if let Some(id) = name.to_str().and_then(|n| n.strip_suffix(".json")) {
+            ids.push(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Up to `limit` reports, newest first. Files that can't be read are skipped.
+pub fn recent_reports(dir: &Path, limit: usize) -> io::Result<Vec<CrashReport>> {
+    let mut ids = report_ids(dir)?;
+    ids.sort_by(|a, b| b.cmp(a));
+    Ok(ids
+        .iter()
+        .filter_map(|id| fs::read(report_path(dir, id)).ok())
+        .filter_map(|json| serde_json::from_slice(&json).ok())
+        .take(limit)
+        .collect())
+}
+
+/// Reports not sent to the crash reporting service yet, oldest first
+pub fn pending_uploads(dir: &Path) -> io::Result<Vec<CrashReport>> {
+    let mut reports = recent_reports(dir, MAX_REPORTS)?;
+    reports.retain(|report| !report.uploaded);
+    reports.reverse();
+    Ok(reports)
+}
+
+/// Record that a report was sent
+pub fn mark_uploaded(dir: &Path, report: &CrashReport) -> io::Result<()> {
+    let mut report = report.clone();
+    report.uploaded = true;
+    let json = serde_json::to_vec_pretty(&report).map_err(io::Error::other)?;
+    fs::write(report_path(dir, &report.id), json)
+}
+
+/// Where and how to send events, from a Sentry DSN
+/// (`https://<public key>@<host>/<project id>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dsn {
+    /// The project's event store endpoint
+    pub store_url: String,
+    pub public_key: String,
+}
+
+impl Dsn {
+    pub fn parse(dsn: &str) -> Result<Dsn, String> {
+        let invalid = || format!("Invalid crash reporting DSN: {}", dsn);
+        let (scheme, rest) = dsn.trim().split_once("://").ok_or_else(invalid)?;
+        if scheme != "https" && scheme != "http" {
+            return Err(invalid());
+        }
+        let (credentials, location) = rest.split_once('@').ok_or_else(invalid)?;
+        // A secret key after `:` is deprecated and not needed
+        let public_key = credentials.split(':').next().unwrap_or_default();
+        let (host_and_path, project) = location
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .ok_or_else(invalid)?;
+        if public_key.is_empty()
+            || host_and_path.is_empty()
+            || project.is_empty()
+            || !project.bytes().all(|b| b.is_ascii_alphanumeric())
+        {
+            return Err(invalid());
+        }
+        Ok(Dsn {
+            store_url: format!("{}://{}/api/{}/store/", scheme, host_and_path, project),
+            public_key: public_key.to_string(),
+        })
+    }
+
+    /// `X-Sentry-Auth` header value for a client such as `beck-server/0.1.0`
+    pub fn auth_header(&self, client: &str) -> String {
+        format!(
+            "Sentry sentry_version=7, sentry_client={}, sentry_key={}",
+            client, self.public_key
+        )
+    }
+}
+
+fn event_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// The event for a crash report
+pub fn crash_event(report: &CrashReport) -> Value {
+    json!({
+        "event_id": event_id(),
+        "timestamp": report.occurred_at,
+        "platform": "native",
+        "level": "fatal",
+        "logger": report.app,
+        "release": format!("{}@{}", report.app, report.version),
+        "exception": {
+            "values": [{
+                "type": "panic",
+                "value": report.message,
+                "mechanism": { "type": "panic", "handled": false },
+            }],
+        },
+        "tags": {
+            "thread": report.thread,
+            "location": report.location,
+        },
+        "extra": {
+            "report_id": report.id,
+            "backtrace": report.backtrace,
+        },
+    })
+}
+
+/// The event for an error that didn't crash anything, e.g. a failed request
+pub fn error_event(app: &str, version: &str, message: &str, tags: &[(&str, &str)]) -> Value {
+    let tags: serde_json::Map<String, Value> = tags
+        .iter()
+        .map(|(key, value)| (key.to_string(), Value::from(*value)))
+        .collect();
+    json!({
+        "event_id": event_id(),
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "platform": "native",
+        "level": "error",
+        "logger": app,
+        "release": format!("{}@{}", app, version),
+        "message": { "formatted": message },
+        "tags": tags,
+    })
+}
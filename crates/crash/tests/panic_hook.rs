@@ -0,0 +1,31 @@
+//! The panic hook is process-wide, so it is tested in its own binary where no
+//! other test can panic while it is installed.
+
+use beck_crash::recent_reports;
+
+#[test]
+fn panics_are_written_by_the_hook() {
+    let dir = std::env::temp_dir().join(format!("beck-crash-hook-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    beck_crash::install_panic_hook(dir.clone(), "beck-test", "1.0.0");
+    let result = std::thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(|| panic!("sync failed"))
+        .unwrap()
+        .join();
+    let _ = std::panic::take_hook();
+    assert!(result.is_err());
+
+    let reports = recent_reports(&dir, 10).unwrap();
+    let report = reports
+        .iter()
+        .find(|r| r.thread.as_deref() == Some("worker"))
+        .expect("no report from the panicking thread");
+    assert_eq!(report.message, "sync failed");
+    assert!(report
+        .location
+        .as_deref()
+        .unwrap()
+        .contains("panic_hook.rs"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,105 @@
+//! "Linked from" sections for exported notes.
+//!
+//! An exported note can list the notes that link to it, each linking to the
+//! file that note would be exported as. Exports name files after note titles
+//! with `export_file_name`, so the links resolve when notes are exported side
+//! by side.
+
+use crate::html::escape;
+
+/// Heading of the generated section
+const HEADING: &str = "Linked from";
+
+/// Longest file name written, in characters
+const MAX_NAME_LEN: usize = 100;
+
+/// A note linking to the exported one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backlink {
+    pub title: String,
+    /// Relative path of the linking note's export
+    pub href: String,
+}
+
+/// A title or folder name made safe to use as a file name on every platform
+pub fn export_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .take(MAX_NAME_LEN)
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    if cleaned.is_empty() {
+        "Untitled".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Percent-encode the characters that would end a Markdown link destination
+fn encode_href(href: &str) -> String {
+    let mut out = String::with_capacity(href.len());
+    for c in href.chars() {
+        match c {
+            ' ' => out.push_str("%20"),
+            '(' => out.push_str("%28"),
+            ')' => out.push_str("%29"),
+            '<' => out.push_str("%3C"),
+            '>' => out.push_str("%3E"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn link_title(title: &str) -> &str {
+    let title = title.trim();
+    if title.is_empty() {
+        "Untitled"
+    } else {
+        title
+    }
+}
+
+/// A Markdown "Linked from" section to append to a note, or nothing when no
+/// note links to it
+pub fn backlinks_markdown(backlinks: &[Backlink]) -> String {
+    if backlinks.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("\n## {}\n\n", HEADING);
+    for link in backlinks {
+        let title = link_title(&link.title)
+            .replace('\\', "\\\\")
+            .replace('[', "\\[")
+            .replace(']', "\\]");
+        out.push_str(&format!("- [{}]({})\n", title, encode_href(&link.href)));
+    }
+    out
+}
+
+/// An HTML "Linked from" section to append to a note, or nothing when no
+/// note links to it
+pub fn backlinks_html(backlinks: &[Backlink]) -> String {
+    if backlinks.is_empty() {
+        return String::new();
+    }
+    let items: String = backlinks
+        .iter()
+        .map(|link| {
+            format!(
+                "<li><a href=\"{}\">{}</a></li>",
+                escape(&encode_href(&link.href)),
+                escape(link_title(&link.title))
+            )
+        })
+        .collect();
+    format!(
+        "<section class=\"backlinks\"><h2>{}</h2><ul>{}</ul></section>",
+        HEADING, items
+    )
+}
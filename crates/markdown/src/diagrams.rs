@@ -0,0 +1,91 @@
+//! Diagram code blocks in note content.
+//!
+//! The editor stores a Mermaid or PlantUML diagram as an ordinary code block
+//! whose language is `mermaid` or `plantuml`. Rendering them needs external
+//! tools, so this module only finds the blocks and swaps in whatever the
+//! caller rendered for them; blocks the caller couldn't render stay code.
+
+use std::ops::Range;
+
+use crate::html::{self, escape, Node};
+
+/// Code block languages that are diagrams
+pub const DIAGRAM_LANGUAGES: &[&str] = &["mermaid", "plantuml"];
+
+/// A diagram code block
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagram {
+    pub language: String,
+    pub source: String,
+}
+
+/// Diagram blocks with their byte ranges in the HTML, in document order
+fn diagram_blocks(content: &str) -> Vec<(Range<usize>, Diagram)> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = content[pos..].find("<pre") {
+        let start = pos + offset;
+        let Some(close) = content[start..].find("</pre>") else {
+            break;
+        };
+        let end = start + close + "</pre>".len();
+        pos = end;
+
+        let nodes = html::parse(&content[start..end]);
+        let Some(Node::Element(pre)) = nodes.first() else {
+            continue;
+        };
+        let code = pre.children.iter().find_map(|node| match node {
+            Node::Element(el) if el.name == "code" => Some(el),
+            _ => None,
+        });
+        let Some(code) = code else { continue };
+        let language = code.attr("class").and_then(|class| {
+            class
+                .split_whitespace()
+                .find_map(|c| c.strip_prefix("language-"))
+        });
+        if let Some(language) = language.filter(|l| DIAGRAM_LANGUAGES.contains(l)) {
+            blocks.push((
+                start..end,
+                Diagram {
+                    language: language.to_string(),
+                    source: code.text(),
+                },
+            ));
+        }
+    }
+    blocks
+}
+
+/// Diagram blocks in note HTML, in document order
+pub fn find_diagrams(content: &str) -> Vec<Diagram> {
+    diagram_blocks(content)
+        .into_iter()
+        .map(|(_, diagram)| diagram)
+        .collect()
+}
+
+/// Replace diagram blocks with the SVG `render` returns for them, wrapped in a
+/// `<figure class="diagram">`. Blocks it returns `None` for are kept.
+pub fn replace_diagrams(
+    content: &str,
+    mut render: impl FnMut(&Diagram) -> Option<String>,
+) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut last = 0;
+    for (range, diagram) in diagram_blocks(content) {
+        let Some(svg) = render(&diagram) else {
+            continue;
+        };
+        out.push_str(&content[last..range.start]);
+        out.push_str(&format!(
+            "<figure class=\"diagram\" data-language=\"{}\">{}</figure>",
+            escape(&diagram.language),
+            svg
+        ));
+        last = range.end;
+    }
+    out.push_str(&content[last..]);
+    out
+}
@@ -0,0 +1,58 @@
+//! Standalone documents for exported notes.
+//!
+//! The desktop app and the sync server both export notes through
+//! `render_note`, so a note exported from either is the same file.
+
+use serde::{Deserialize, Serialize};
+
+use crate::backlinks::{backlinks_html, backlinks_markdown, Backlink};
+use crate::html::escape;
+use crate::math::render_math;
+use crate::to_markdown::html_to_markdown;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    /// File extension of an export, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// Render a note as a standalone Markdown or HTML document, ending with a
+/// "Linked from" section when `backlinks` isn't empty. HTML exports have their
+/// LaTeX math rendered as MathML.
+pub fn render_note(
+    title: &str,
+    content: &str,
+    format: ExportFormat,
+    backlinks: &[Backlink],
+) -> String {
+    match format {
+        ExportFormat::Markdown => {
+            let body = html_to_markdown(content);
+            let body = format!("{}{}", body, backlinks_markdown(backlinks));
+            if title.trim().is_empty() {
+                body
+            } else {
+                format!("# {}\n\n{}", title.trim(), body)
+            }
+        }
+        ExportFormat::Html => format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n{}{}\n</body>\n</html>\n",
+            escape(title),
+            escape(title),
+            render_math(content),
+            backlinks_html(backlinks)
+        ),
+    }
+}
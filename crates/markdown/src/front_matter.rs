@@ -0,0 +1,120 @@
+//! YAML front matter at the top of Markdown files.
+//!
+//! Only what notes have a place for is read: `title`, `folder` and `tags`.
+//! This isn't a YAML parser; it understands flat `key: value` lines, quoted
+//! values and lists written either as `[a, b]` or as `- a` lines. Other keys
+//! are ignored.
+
+use crate::html::escape;
+
+/// Note metadata from a front matter block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    /// A folder id, or a path of folder names such as `Team/Weekly`
+    pub folder: Option<String>,
+    /// Without a leading `#`
+    pub tags: Vec<String>,
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+fn scalar(value: &str) -> Option<String> {
+    let value = unquote(value).trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Items of an inline value: `[a, b]`, or `a, b` / `a b` for tags
+fn inline_list(value: &str) -> Vec<String> {
+    let value = value.trim();
+    let items: Vec<&str> = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(inner) => inner.split(',').collect(),
+        None => value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .collect(),
+    };
+    items.into_iter().filter_map(tag).collect()
+}
+
+fn tag(item: &str) -> Option<String> {
+    let tag = unquote(item).trim().trim_start_matches('#').trim();
+    (!tag.is_empty()).then(|| tag.replace(char::is_whitespace, "-"))
+}
+
+/// Split a front matter block off the start of Markdown, returning its
+/// metadata and the rest of the document. Markdown without a block (one
+/// opened by a `---` first line and closed by a `---` or `...` line) is
+/// returned whole, with empty metadata.
+pub fn split_front_matter(markdown: &str) -> (FrontMatter, &str) {
+    let text = markdown.strip_prefix('\u{feff}').unwrap_or(markdown);
+    let Some((first, rest)) = text.split_once('\n') else {
+        return (FrontMatter::default(), markdown);
+    };
+    if first.trim_end() != "---" {
+        return (FrontMatter::default(), markdown);
+    }
+
+    let mut offset = 0;
+    let mut block = None;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            block = Some((&rest[..offset], &rest[offset + line.len()..]));
+            break;
+        }
+        offset += line.len();
+    }
+    let Some((block, body)) = block else {
+        return (FrontMatter::default(), markdown);
+    };
+
+    let mut meta = FrontMatter::default();
+    // The key whose `- item` lines are being read
+    let mut list_key: Option<String> = None;
+    for line in block.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(item) = trimmed.strip_prefix('-') {
+            if list_key.as_deref() == Some("tags") {
+                meta.tags.extend(tag(item));
+            }
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else {
+            list_key = None;
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        match key.as_str() {
+            "title" => meta.title = scalar(value),
+            "folder" => meta.folder = scalar(value),
+            "tags" => meta.tags.extend(inline_list(value)),
+            _ => {}
+        }
+        list_key = value.trim().is_empty().then_some(key);
+    }
+    (meta, body)
+}
+
+/// Tags as a paragraph of hashtags, the form tags take in note text, or an
+/// empty string without tags
+pub fn tags_html(tags: &[String]) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+    let tags: Vec<String> = tags.iter().map(|tag| format!("#{}", escape(tag))).collect();
+    format!("<p>{}</p>", tags.join(" "))
+}
@@ -0,0 +1,45 @@
+//! Highlighted passages in note content.
+//!
+//! The editor marks highlighted text with `<mark>`. A color picked by the user
+//! is carried in `data-color`, or failing that in an inline `background-color`
+//! style; plain highlights have no color.
+
+use crate::html::{self, Element};
+
+/// A highlighted passage, in document order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    /// Lowercased CSS color, if the highlight has one
+    pub color: Option<String>,
+    pub text: String,
+}
+
+/// Color of a `<mark>` element
+fn mark_color(el: &Element) -> Option<String> {
+    let color = el.attr("data-color").or_else(|| {
+        el.attr("style")?.split(';').find_map(|decl| {
+            let (prop, value) = decl.split_once(':')?;
+            (prop.trim().eq_ignore_ascii_case("background-color")).then_some(value)
+        })
+    })?;
+    let color = color.trim().to_ascii_lowercase();
+    (!color.is_empty()).then_some(color)
+}
+
+/// Extract highlighted passages from note HTML, skipping empty marks
+pub fn extract_highlights(content: &str) -> Vec<Highlight> {
+    let nodes = html::parse(content);
+    let mut highlights = Vec::new();
+    html::walk(&nodes, &mut |el: &Element| {
+        if el.name == "mark" {
+            let text = el.text().trim().to_string();
+            if !text.is_empty() {
+                highlights.push(Highlight {
+                    color: mark_color(el),
+                    text,
+                });
+            }
+        }
+    });
+    highlights
+}
@@ -0,0 +1,339 @@
+//! A small, forgiving HTML parser for editor output.
+//!
+//! Note content is produced by TipTap, so it is well-formed and free of scripts
+//! and comments in practice. This parser builds a simple element tree from it and
+//! tolerates stray or unclosed tags instead of failing.
+
+/// A parsed HTML node
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// An HTML element with its attributes and children
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// Look up an attribute value by (lowercase) name
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn has_attr(&self, name: &str) -> bool {
+        self.attrs.iter().any(|(k, _)| k == name)
+    }
+
+    /// Concatenated text of all descendants
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        collect_text(&self.children, &mut out);
+        out
+    }
+}
+
+fn collect_text(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(t),
+            Node::Element(el) => collect_text(&el.children, out),
+        }
+    }
+}
+
+/// Elements that never have children or a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose content is raw text rather than markup
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+fn is_void(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name)
+}
+
+/// Parse an HTML fragment into a list of top-level nodes
+pub fn parse(html: &str) -> Vec<Node> {
+    let mut stack: Vec<Element> = vec![Element {
+        name: String::new(),
+        attrs: Vec::new(),
+        children: Vec::new(),
+    }];
+    let bytes = html.as_bytes();
+    let mut pos = 0;
+
+    while pos < html.len() {
+        if bytes[pos] == b'<' {
+            let rest = &html[pos..];
+
+            if let Some(after) = rest.strip_prefix("<!--") {
+                pos += 4 + after.find("-->").map(|i| i + 3).unwrap_or(after.len());
+                continue;
+            }
+            if rest.starts_with("<!") || rest.starts_with("<?") {
+                pos += rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+                continue;
+            }
+            if let Some(after) = rest.strip_prefix("</") {
+                let end = after.find('>').unwrap_or(after.len());
+                let name = after[..end].trim().to_ascii_lowercase();
+                pos += 2 + (end + 1).min(after.len());
+                close_element(&mut stack, &name);
+                continue;
+            }
+            if let Some((name, attrs, self_closing, consumed)) = parse_start_tag(rest) {
+                pos += consumed;
+                let element = Element {
+                    name: name.clone(),
+                    attrs,
+                    children: Vec::new(),
+                };
+                if self_closing || is_void(&name) {
+                    push_node(&mut stack, Node::Element(element));
+                } else if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                    // Skip raw text content entirely; it's never note content
+                    let close = format!("</{}", name);
+                    let lower = html[pos..].to_ascii_lowercase();
+                    let skip = lower.find(&close).unwrap_or(lower.len());
+                    pos += skip;
+                    pos += html[pos..]
+                        .find('>')
+                        .map(|i| i + 1)
+                        .unwrap_or(html.len() - pos);
+                } else {
+                    stack.push(element);
+                }
+                continue;
+            }
+        }
+
+        // Text run up to the next tag
+        let from = if bytes[pos] == b'<' { pos + 1 } else { pos };
+        let next = html[from..]
+            .find('<')
+            .map(|i| from + i)
+            .unwrap_or(html.len());
+        push_node(&mut stack, Node::Text(decode_entities(&html[pos..next])));
+        pos = next;
+    }
+
+    while stack.len() > 1 {
+        let el = stack.pop().unwrap();
+        push_node(&mut stack, Node::Element(el));
+    }
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+fn push_node(stack: &mut [Element], node: Node) {
+    let parent = stack.last_mut().expect("root element");
+    // Merge adjacent text so entity boundaries don't split runs
+    if let (Node::Text(t), Some(Node::Text(prev))) = (&node, parent.children.last_mut()) {
+        prev.push_str(t);
+        return;
+    }
+    parent.children.push(node);
+}
+
+fn close_element(stack: &mut Vec<Element>, name: &str) {
+    // Ignore end tags with no matching open element
+    if !stack.iter().skip(1).any(|el| el.name == name) {
+        return;
+    }
+    while stack.len() > 1 {
+        let el = stack.pop().unwrap();
+        let done = el.name == name;
+        push_node(stack, Node::Element(el));
+        if done {
+            break;
+        }
+    }
+}
+
+/// A parsed start tag: (name, attrs, self_closing, bytes consumed)
+type StartTag = (String, Vec<(String, String)>, bool, usize);
+
+/// Parse `<name attr="v" ...>`
+fn parse_start_tag(input: &str) -> Option<StartTag> {
+    let bytes = input.as_bytes();
+    let mut i = 1;
+    let name_start = i;
+    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+        i += 1;
+    }
+    if i == name_start {
+        return None;
+    }
+    let name = input[name_start..i].to_ascii_lowercase();
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            return Some((name, attrs, self_closing, i));
+        }
+        match bytes[i] {
+            b'>' => return Some((name, attrs, self_closing, i + 1)),
+            b'/' => {
+                self_closing = true;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let key_start = i;
+        while i < bytes.len()
+            && !bytes[i].is_ascii_whitespace()
+            && !matches!(bytes[i], b'=' | b'>' | b'/')
+        {
+            i += 1;
+        }
+        let key = input[key_start..i].to_ascii_lowercase();
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let mut value = String::new();
+        if i < bytes.len() && bytes[i] == b'=' {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if i < bytes.len() && (bytes[i] == b'"' || bytes[i] == b'\'') {
+                let quote = bytes[i];
+                let start = i + 1;
+                let end = input[start..]
+                    .bytes()
+                    .position(|b| b == quote)
+                    .map(|p| start + p)
+                    .unwrap_or(input.len());
+                value = decode_entities(&input[start..end]);
+                i = (end + 1).min(input.len());
+            } else {
+                let start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+                value = decode_entities(&input[start..i]);
+            }
+        }
+        if key.is_empty() {
+            i += 1;
+        } else {
+            self_closing = false;
+            attrs.push((key, value));
+        }
+    }
+}
+
+/// Decode the HTML entities editors actually emit, plus numeric references
+pub fn decode_entities(input: &str) -> String {
+    if !input.contains('&') {
+        return input.to_string();
+    }
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let semi = rest.bytes().take(12).position(|b| b == b';');
+        let decoded = semi.and_then(|semi| {
+            let entity = &rest[1..semi];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            ch.map(|c| (c, semi + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escape text for use in HTML content or double-quoted attributes
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Visit every element in document order
+pub fn walk<'a>(nodes: &'a [Node], visit: &mut impl FnMut(&'a Element)) {
+    for node in nodes {
+        if let Node::Element(el) = node {
+            visit(el);
+            walk(&el.children, visit);
+        }
+    }
+}
+
+/// Serialize nodes back to HTML
+pub fn serialize(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    serialize_into(nodes, &mut out);
+    out
+}
+
+fn serialize_into(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(&escape(t)),
+            Node::Element(el) => {
+                out.push('<');
+                out.push_str(&el.name);
+                for (key, value) in &el.attrs {
+                    out.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+                }
+                out.push('>');
+                if is_void(&el.name) {
+                    continue;
+                }
+                serialize_into(&el.children, out);
+                out.push_str("</");
+                out.push_str(&el.name);
+                out.push('>');
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Conversion between the editor's HTML and Markdown.
+//!
+//! Notes are stored as TipTap HTML. This crate turns that HTML into CommonMark
+//! with GFM extensions (task lists, tables, strikethrough) for exports, and turns
+//! Markdown back into HTML in the shapes TipTap produces so imported notes open
+//! in the editor as if they had been typed there. It is shared by the desktop
+//! app and the sync server so both sides convert notes identically. It also
+//! extracts highlighted passages, which both sides index, renders the LaTeX math
+//! notes contain as MathML for HTML exports, finds diagram code blocks for the
+//! server to render, strips editor markup from notes for printing, writes
+//! the "Linked from" sections exports can end with, applies the redaction
+//! rules that sanitize shared exports, reads and ticks off task list
+//! items for the task manager integrations, reads the front matter of
+//! Markdown files, diffs conflicting versions of a note against their
+//! common base and builds notes' CRDT documents from their HTML.
+
+mod backlinks;
+mod diagrams;
+mod export;
+mod front_matter;
+mod highlights;
+pub mod html;
+mod math;
+mod merge;
+mod print;
+mod redact;
+mod tasks;
+mod text;
+mod to_html;
+mod to_markdown;
+mod ydoc;
+
+pub use backlinks::{backlinks_html, backlinks_markdown, export_file_name, Backlink};
+pub use diagrams::{find_diagrams, replace_diagrams, Diagram, DIAGRAM_LANGUAGES};
+pub use export::{render_note, ExportFormat};
+pub use front_matter::{split_front_matter, tags_html, FrontMatter};
+pub use highlights::{extract_highlights, Highlight};
+pub use math::{latex_to_mathml, render_math};
+pub use merge::{
+    merge_blocks, split_blocks, three_way_diff, ChunkStatus, MergeChunk, ThreeWayDiff,
+};
+pub use print::print_html;
+pub use redact::{RedactionRule, Redactor, DEFAULT_REPLACEMENT};
+pub use tasks::{extract_tasks, set_task_checked, Task};
+pub use text::html_to_text;
+pub use to_html::markdown_to_html;
+pub use to_markdown::html_to_markdown;
+pub use ydoc::{replace_content, seed_state};
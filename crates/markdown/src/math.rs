@@ -0,0 +1,821 @@
+//! LaTeX math in note content.
+//!
+//! The editor keeps formulas as LaTeX source in the text, `$…$` inline and
+//! `$$…$$` for display math, and only renders them with KaTeX on screen.
+//! Exports turn them into MathML so they show up as formulas in any browser,
+//! and in PDFs printed from one, without shipping scripts or fonts. The
+//! converter covers what people write in notes: scripts, fractions, roots,
+//! Greek letters and common symbols, delimiters, accents and matrices.
+//! Commands it doesn't know are kept as source text, and the original LaTeX is
+//! attached to every formula as an annotation.
+
+use crate::html::{decode_entities, escape};
+
+/// A piece of a text run split at math delimiters
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment<'a> {
+    Text(&'a str),
+    Math { source: &'a str, display: bool },
+}
+
+/// Split text at math delimiters the way the editor finds them: `$$…$$` is
+/// display math and `$…$` inline math. Neither may contain a `$`, and inline
+/// math stays on one line.
+pub(crate) fn split_math(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut plain_start = 0;
+    let mut pos = 0;
+
+    while let Some(offset) = text[pos..].find('$') {
+        let start = pos + offset;
+        let display = text[start..].strip_prefix("$$").and_then(|after| {
+            let len = after.find('$')?;
+            let source = &after[..len];
+            let valid = after[len..].starts_with("$$")
+                && !source.is_empty()
+                && !source.starts_with('\n')
+                && !source.ends_with('\n');
+            valid.then_some((source, true, start + len + 4))
+        });
+        let found = display.or_else(|| {
+            let after = &text[start + 1..];
+            let len = after.find(['$', '\n'])?;
+            let source = &after[..len];
+            (after[len..].starts_with('$') && !source.is_empty()).then_some((
+                source,
+                false,
+                start + len + 2,
+            ))
+        });
+
+        match found {
+            Some((source, display, end)) => {
+                if plain_start < start {
+                    segments.push(Segment::Text(&text[plain_start..start]));
+                }
+                segments.push(Segment::Math { source, display });
+                plain_start = end;
+                pos = end;
+            }
+            None => pos = start + 1,
+        }
+    }
+
+    if plain_start < text.len() {
+        segments.push(Segment::Text(&text[plain_start..]));
+    }
+    segments
+}
+
+/// Replace the math in note HTML with MathML. Code and rendered diagrams are
+/// left alone.
+pub fn render_math(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut skip_depth = 0usize;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+            let tag = &rest[..end];
+            let closing = tag.starts_with("</");
+            let name: String = tag
+                .trim_start_matches(['<', '/'])
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            if matches!(name.as_str(), "code" | "pre" | "svg") {
+                if closing {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else {
+                    skip_depth += 1;
+                }
+            }
+            out.push_str(tag);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = rest.find('<').unwrap_or(rest.len());
+        let text = &rest[..end];
+        if skip_depth > 0 {
+            out.push_str(text);
+        } else {
+            for segment in split_math(text) {
+                match segment {
+                    Segment::Text(text) => out.push_str(text),
+                    Segment::Math { source, display } => {
+                        out.push_str(&latex_to_mathml(&decode_entities(source), display))
+                    }
+                }
+            }
+        }
+        rest = &rest[end..];
+    }
+    out
+}
+
+/// Convert LaTeX source to a MathML `<math>` element
+pub fn latex_to_mathml(source: &str, display: bool) -> String {
+    let mut parser = TexParser::new(source, display);
+    let body = parser.row(Stop::End);
+    format!(
+        "<math display=\"{}\"><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { "block" } else { "inline" },
+        group(body),
+        escape(source.trim())
+    )
+}
+
+/// How a symbol command is rendered
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Identifier,
+    Operator,
+    /// A large operator; `limits` puts its scripts above and below in display math
+    Large {
+        limits: bool,
+    },
+}
+
+fn symbol(name: &str) -> Option<(&'static str, Kind)> {
+    use Kind::*;
+    let symbol = match name {
+        "alpha" => ("α", Identifier),
+        "beta" => ("β", Identifier),
+        "gamma" => ("γ", Identifier),
+        "delta" => ("δ", Identifier),
+        "epsilon" => ("ϵ", Identifier),
+        "varepsilon" => ("ε", Identifier),
+        "zeta" => ("ζ", Identifier),
+        "eta" => ("η", Identifier),
+        "theta" => ("θ", Identifier),
+        "vartheta" => ("ϑ", Identifier),
+        "iota" => ("ι", Identifier),
+        "kappa" => ("κ", Identifier),
+        "lambda" => ("λ", Identifier),
+        "mu" => ("μ", Identifier),
+        "nu" => ("ν", Identifier),
+        "xi" => ("ξ", Identifier),
+        "pi" => ("π", Identifier),
+        "varpi" => ("ϖ", Identifier),
+        "rho" => ("ρ", Identifier),
+        "varrho" => ("ϱ", Identifier),
+        "sigma" => ("σ", Identifier),
+        "varsigma" => ("ς", Identifier),
+        "tau" => ("τ", Identifier),
+        "upsilon" => ("υ", Identifier),
+        "phi" => ("ϕ", Identifier),
+        "varphi" => ("φ", Identifier),
+        "chi" => ("χ", Identifier),
+        "psi" => ("ψ", Identifier),
+        "omega" => ("ω", Identifier),
+        "Gamma" => ("Γ", Identifier),
+        "Delta" => ("Δ", Identifier),
+        "Theta" => ("Θ", Identifier),
+        "Lambda" => ("Λ", Identifier),
+        "Xi" => ("Ξ", Identifier),
+        "Pi" => ("Π", Identifier),
+        "Sigma" => ("Σ", Identifier),
+        "Upsilon" => ("Υ", Identifier),
+        "Phi" => ("Φ", Identifier),
+        "Psi" => ("Ψ", Identifier),
+        "Omega" => ("Ω", Identifier),
+        "infty" => ("∞", Identifier),
+        "partial" => ("∂", Identifier),
+        "nabla" => ("∇", Identifier),
+        "hbar" => ("ℏ", Identifier),
+        "ell" => ("ℓ", Identifier),
+        "emptyset" | "varnothing" => ("∅", Identifier),
+        "aleph" => ("ℵ", Identifier),
+        "Re" => ("ℜ", Identifier),
+        "Im" => ("ℑ", Identifier),
+        "triangle" => ("△", Identifier),
+        "pm" => ("±", Operator),
+        "mp" => ("∓", Operator),
+        "times" => ("×", Operator),
+        "div" => ("÷", Operator),
+        "cdot" => ("⋅", Operator),
+        "ast" => ("∗", Operator),
+        "star" => ("⋆", Operator),
+        "circ" => ("∘", Operator),
+        "bullet" => ("∙", Operator),
+        "le" | "leq" => ("≤", Operator),
+        "ge" | "geq" => ("≥", Operator),
+        "ne" | "neq" => ("≠", Operator),
+        "approx" => ("≈", Operator),
+        "equiv" => ("≡", Operator),
+        "sim" => ("∼", Operator),
+        "simeq" => ("≃", Operator),
+        "cong" => ("≅", Operator),
+        "propto" => ("∝", Operator),
+        "ll" => ("≪", Operator),
+        "gg" => ("≫", Operator),
+        "in" => ("∈", Operator),
+        "notin" => ("∉", Operator),
+        "ni" => ("∋", Operator),
+        "subset" => ("⊂", Operator),
+        "subseteq" => ("⊆", Operator),
+        "supset" => ("⊃", Operator),
+        "supseteq" => ("⊇", Operator),
+        "cup" => ("∪", Operator),
+        "cap" => ("∩", Operator),
+        "setminus" => ("∖", Operator),
+        "wedge" | "land" => ("∧", Operator),
+        "vee" | "lor" => ("∨", Operator),
+        "neg" | "lnot" => ("¬", Operator),
+        "forall" => ("∀", Operator),
+        "exists" => ("∃", Operator),
+        "to" | "rightarrow" => ("→", Operator),
+        "leftarrow" | "gets" => ("←", Operator),
+        "leftrightarrow" => ("↔", Operator),
+        "Rightarrow" => ("⇒", Operator),
+        "Leftarrow" => ("⇐", Operator),
+        "Leftrightarrow" => ("⇔", Operator),
+        "implies" => ("⟹", Operator),
+        "iff" => ("⟺", Operator),
+        "mapsto" => ("↦", Operator),
+        "uparrow" => ("↑", Operator),
+        "downarrow" => ("↓", Operator),
+        "mid" => ("∣", Operator),
+        "parallel" => ("∥", Operator),
+        "perp" => ("⊥", Operator),
+        "angle" => ("∠", Operator),
+        "oplus" => ("⊕", Operator),
+        "otimes" => ("⊗", Operator),
+        "therefore" => ("∴", Operator),
+        "because" => ("∵", Operator),
+        "cdots" => ("⋯", Operator),
+        "ldots" | "dots" => ("…", Operator),
+        "vdots" => ("⋮", Operator),
+        "ddots" => ("⋱", Operator),
+        "langle" => ("⟨", Operator),
+        "rangle" => ("⟩", Operator),
+        "lfloor" => ("⌊", Operator),
+        "rfloor" => ("⌋", Operator),
+        "lceil" => ("⌈", Operator),
+        "rceil" => ("⌉", Operator),
+        "vert" | "lvert" | "rvert" => ("|", Operator),
+        "Vert" | "lVert" | "rVert" | "|" => ("‖", Operator),
+        "lbrace" | "{" => ("{", Operator),
+        "rbrace" | "}" => ("}", Operator),
+        "colon" => (":", Operator),
+        "prime" => ("′", Operator),
+        "sum" => ("∑", Large { limits: true }),
+        "prod" => ("∏", Large { limits: true }),
+        "coprod" => ("∐", Large { limits: true }),
+        "bigcup" => ("⋃", Large { limits: true }),
+        "bigcap" => ("⋂", Large { limits: true }),
+        "bigoplus" => ("⨁", Large { limits: true }),
+        "bigotimes" => ("⨂", Large { limits: true }),
+        "bigvee" => ("⋁", Large { limits: true }),
+        "bigwedge" => ("⋀", Large { limits: true }),
+        "int" => ("∫", Large { limits: false }),
+        "iint" => ("∬", Large { limits: false }),
+        "iiint" => ("∭", Large { limits: false }),
+        "oint" => ("∮", Large { limits: false }),
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Operator names set upright; the second value is whether they take limits
+fn function(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => {
+            Some(true)
+        }
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "dim" | "deg" | "hom" | "ker" | "arg" => {
+            Some(false)
+        }
+        _ => None,
+    }
+}
+
+fn accent(name: &str) -> Option<&'static str> {
+    let mark = match name {
+        "hat" | "widehat" => "^",
+        "bar" | "overline" => "‾",
+        "vec" | "overrightarrow" => "→",
+        "dot" => "˙",
+        "ddot" => "¨",
+        "tilde" | "widetilde" => "~",
+        _ => return None,
+    };
+    Some(mark)
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    let width = match name {
+        "," => "0.1667em",
+        ":" | ">" => "0.2222em",
+        ";" => "0.2778em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        "!" => "-0.1667em",
+        _ => return None,
+    };
+    Some(width)
+}
+
+fn mathvariant(name: &str) -> Option<&'static str> {
+    let variant = match name {
+        "mathrm" => "normal",
+        "mathbf" | "boldsymbol" => "bold",
+        "mathit" => "italic",
+        "mathbb" => "double-struck",
+        "mathcal" => "script",
+        "mathfrak" => "fraktur",
+        "mathsf" => "sans-serif",
+        "mathtt" => "monospace",
+        _ => return None,
+    };
+    Some(variant)
+}
+
+/// Wrap several nodes in an `<mrow>`; a single node needs none
+fn group(nodes: Vec<String>) -> String {
+    if nodes.len() == 1 {
+        nodes.into_iter().next().unwrap_or_default()
+    } else {
+        format!("<mrow>{}</mrow>", nodes.concat())
+    }
+}
+
+fn mo(text: &str) -> String {
+    format!("<mo>{}</mo>", escape(text))
+}
+
+/// What ends the row being parsed
+#[derive(Clone, Copy, PartialEq)]
+enum Stop {
+    End,
+    Brace,
+    /// `\right`
+    Right,
+    /// `&`, `\\` or `\end` inside an environment
+    Cell,
+}
+
+/// A parsed atom, before its scripts are attached
+struct Atom {
+    node: String,
+    limits: bool,
+}
+
+impl Atom {
+    fn plain(node: String) -> Self {
+        Atom {
+            node,
+            limits: false,
+        }
+    }
+}
+
+struct TexParser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+}
+
+impl TexParser {
+    fn new(source: &str, display: bool) -> Self {
+        TexParser {
+            chars: source.chars().collect(),
+            pos: 0,
+            display,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Name of the command at the cursor, without consuming it
+    fn peek_command(&self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let name: String = self.chars[self.pos + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        if name.is_empty() {
+            self.chars.get(self.pos + 1).map(|c| c.to_string())
+        } else {
+            Some(name)
+        }
+    }
+
+    /// Consume the command at the cursor and return its name
+    fn command_name(&mut self) -> String {
+        let name = self.peek_command().unwrap_or_default();
+        self.pos += 1 + name.chars().count().max(1);
+        name
+    }
+
+    fn row(&mut self, stop: Stop) -> Vec<String> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.peek() else { break };
+            if c == '}' {
+                self.pos += 1;
+                if stop == Stop::Brace {
+                    break;
+                }
+                continue;
+            }
+            if stop == Stop::Cell && c == '&' {
+                break;
+            }
+            let command = self.peek_command();
+            match (stop, command.as_deref()) {
+                (Stop::Right, Some("right")) | (Stop::Cell, Some("\\" | "end")) => break,
+                _ => {}
+            }
+            if let Some(atom) = self.atom() {
+                let node = self.scripts(atom);
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    /// A single argument: a braced group, one digit or letter, or a command
+    fn argument(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                group(self.row(Stop::Brace))
+            }
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                format!("<mn>{}</mn>", c)
+            }
+            Some(_) => self
+                .atom()
+                .map(|atom| atom.node)
+                .unwrap_or_else(|| "<mrow></mrow>".to_string()),
+            None => "<mrow></mrow>".to_string(),
+        }
+    }
+
+    /// The source of a braced group, or of the next character
+    fn raw_argument(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                let mut depth = 1;
+                let mut raw = String::new();
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    match c {
+                        '\\' => {
+                            raw.push(c);
+                            if let Some(next) = self.peek() {
+                                raw.push(next);
+                                self.pos += 1;
+                            }
+                            continue;
+                        }
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    raw.push(c);
+                }
+                raw
+            }
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// `[…]` after a command, if present
+    fn optional_argument(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        if self.peek() != Some('[') {
+            return None;
+        }
+        let close = self.chars[self.pos..].iter().position(|&c| c == ']')?;
+        let raw: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+        self.pos += close + 1;
+        Some(self.convert(&raw))
+    }
+
+    fn convert(&self, source: &str) -> String {
+        group(TexParser::new(source, self.display).row(Stop::End))
+    }
+
+    /// The delimiter after `\left`, `\right` or `\big`; `.` is none
+    fn delimiter(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let c = self.peek()?;
+        if c == '\\' {
+            let name = self.command_name();
+            return Some(match symbol(&name) {
+                Some((text, _)) => text.to_string(),
+                None => name,
+            });
+        }
+        self.pos += 1;
+        (c != '.').then(|| c.to_string())
+    }
+
+    fn atom(&mut self) -> Option<Atom> {
+        let c = self.peek()?;
+        match c {
+            '{' => {
+                self.pos += 1;
+                Some(Atom::plain(group(self.row(Stop::Brace))))
+            }
+            '\\' => self.command(),
+            // A script with no base
+            '^' | '_' => Some(Atom::plain("<mrow></mrow>".to_string())),
+            '~' => {
+                self.pos += 1;
+                Some(Atom::plain("<mspace width=\"0.25em\"/>".to_string()))
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                if number == "." {
+                    return Some(Atom::plain(mo(".")));
+                }
+                Some(Atom::plain(format!("<mn>{}</mn>", number)))
+            }
+            c if c.is_alphabetic() => {
+                self.pos += 1;
+                Some(Atom::plain(format!("<mi>{}</mi>", c)))
+            }
+            '\'' => {
+                self.pos += 1;
+                Some(Atom::plain(mo("′")))
+            }
+            c => {
+                self.pos += 1;
+                Some(Atom::plain(mo(&c.to_string())))
+            }
+        }
+    }
+
+    fn command(&mut self) -> Option<Atom> {
+        let name = self.command_name();
+        if let Some((text, kind)) = symbol(&name) {
+            let atom = match kind {
+                Kind::Identifier => Atom::plain(format!("<mi>{}</mi>", text)),
+                Kind::Operator => Atom::plain(mo(text)),
+                Kind::Large { limits } => Atom {
+                    node: format!("<mo largeop=\"true\">{}</mo>", text),
+                    limits,
+                },
+            };
+            return Some(atom);
+        }
+        if let Some(limits) = function(&name) {
+            return Some(Atom {
+                node: format!("<mi>{}</mi>", name),
+                limits,
+            });
+        }
+        if let Some(width) = space(&name) {
+            return Some(Atom::plain(format!("<mspace width=\"{}\"/>", width)));
+        }
+        if let Some(mark) = accent(&name) {
+            let base = self.argument();
+            return Some(Atom::plain(format!(
+                "<mover accent=\"true\">{}{}</mover>",
+                base,
+                mo(mark)
+            )));
+        }
+        if let Some(variant) = mathvariant(&name) {
+            let raw = self.raw_argument();
+            let node = if !raw.is_empty() && raw.chars().all(char::is_alphanumeric) {
+                format!("<mi mathvariant=\"{}\">{}</mi>", variant, escape(&raw))
+            } else {
+                format!(
+                    "<mstyle mathvariant=\"{}\">{}</mstyle>",
+                    variant,
+                    self.convert(&raw)
+                )
+            };
+            return Some(Atom::plain(node));
+        }
+
+        let node = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let top = self.argument();
+                let bottom = self.argument();
+                format!(
+                    "<mrow>{}<mfrac linethickness=\"0\">{}{}</mfrac>{}</mrow>",
+                    mo("("),
+                    top,
+                    bottom,
+                    mo(")")
+                )
+            }
+            "sqrt" => match self.optional_argument() {
+                Some(index) => format!("<mroot>{}{}</mroot>", self.argument(), index),
+                None => format!("<msqrt>{}</msqrt>", self.argument()),
+            },
+            "text" | "textrm" | "textit" | "textbf" | "mbox" => {
+                format!("<mtext>{}</mtext>", escape(&self.raw_argument()))
+            }
+            "operatorname" => format!("<mi>{}</mi>", escape(&self.raw_argument())),
+            "underline" => format!(
+                "<munder accentunder=\"true\">{}{}</munder>",
+                self.argument(),
+                mo("_")
+            ),
+            "left" => {
+                let open = self.delimiter();
+                let inner = self.row(Stop::Right);
+                let close = if self.peek_command().as_deref() == Some("right") {
+                    self.command_name();
+                    self.delimiter()
+                } else {
+                    None
+                };
+                let fence = |d: Option<String>| {
+                    d.map(|d| format!("<mo fence=\"true\">{}</mo>", escape(&d)))
+                        .unwrap_or_default()
+                };
+                format!(
+                    "<mrow>{}{}{}</mrow>",
+                    fence(open),
+                    inner.concat(),
+                    fence(close)
+                )
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" => mo(&self.delimiter()?),
+            "begin" => {
+                let env = self.raw_argument();
+                self.environment(&env)
+            }
+            // A stray `\right` or `\end`
+            "right" => {
+                self.delimiter();
+                return None;
+            }
+            "end" => {
+                self.raw_argument();
+                return None;
+            }
+            "label" | "tag" => {
+                self.raw_argument();
+                return None;
+            }
+            "\\" | "displaystyle" | "textstyle" | "limits" | "nolimits" | "nonumber" => {
+                return None
+            }
+            "$" | "%" | "#" | "&" | "_" => mo(&name),
+            _ => format!("<mtext>\\{}</mtext>", escape(&name)),
+        };
+        Some(Atom::plain(node))
+    }
+
+    /// Rows and cells up to `\end{…}`, as an `<mtable>` with the environment's
+    /// delimiters
+    fn environment(&mut self, name: &str) -> String {
+        if name == "array" {
+            // Column spec
+            self.raw_argument();
+        }
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        loop {
+            let mut cells = Vec::new();
+            loop {
+                cells.push(group(self.row(Stop::Cell)));
+                if self.peek() == Some('&') {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            rows.push(cells);
+            match self.peek_command().as_deref() {
+                Some("\\") => {
+                    self.command_name();
+                }
+                Some("end") => {
+                    self.command_name();
+                    self.raw_argument();
+                    break;
+                }
+                _ => break,
+            }
+        }
+        // A trailing `\\` leaves an empty last row
+        if rows.len() > 1 && rows.last().is_some_and(|row| row == &["<mrow></mrow>"]) {
+            rows.pop();
+        }
+
+        let align = match name.trim_end_matches('*') {
+            "aligned" | "align" | "split" => " columnalign=\"right left\"",
+            "cases" => " columnalign=\"left left\"",
+            _ => "",
+        };
+        let table = format!(
+            "<mtable{}>{}</mtable>",
+            align,
+            rows.iter()
+                .map(|cells| format!(
+                    "<mtr>{}</mtr>",
+                    cells
+                        .iter()
+                        .map(|cell| format!("<mtd>{}</mtd>", cell))
+                        .collect::<String>()
+                ))
+                .collect::<String>()
+        );
+        let (open, close) = match name {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => return table,
+        };
+        let fence = |d: &str| {
+            if d.is_empty() {
+                String::new()
+            } else {
+                format!("<mo fence=\"true\">{}</mo>", escape(d))
+            }
+        };
+        format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close))
+    }
+
+    /// Attach any `_`, `^` and prime scripts following an atom
+    fn scripts(&mut self, base: Atom) -> String {
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.argument());
+                }
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.argument());
+                }
+                Some('\'') if sup.is_none() => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    sup = Some(mo(&primes));
+                }
+                _ => break,
+            }
+        }
+
+        let (under, over, both) = if base.limits && self.display {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        match (sub, sup) {
+            (None, None) => base.node,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base.node, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base.node, sup),
+            (Some(sub), Some(sup)) => {
+                format!("<{0}>{1}{2}{3}</{0}>", both, base.node, sub, sup)
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Three-way merge of note content, for resolving sync conflicts.
+//!
+//! Versions are compared block by block: each top-level block element of the
+//! HTML (a paragraph, heading, list, ...) is one unit, and a run of loose
+//! text and inline elements between them is another. Both edited versions are diffed against the
+//! version they started from, and the result is a list of chunks that are
+//! unchanged, changed on one side, changed the same way on both, or changed
+//! differently on both (a conflict). Without conflicts the merged content is
+//! ready to save; otherwise a merge UI lets the user pick or edit the
+//! conflicting chunks.
+
+use serde::Serialize;
+
+use crate::html::{self, Node};
+use crate::to_markdown::is_block;
+
+/// How a chunk differs between the versions
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkStatus {
+    Unchanged,
+    /// Only the local version changed it
+    Local,
+    /// Only the remote version changed it
+    Remote,
+    /// Both changed it the same way
+    Both,
+    Conflict,
+}
+
+/// A run of blocks and what each version has there. Blocks are HTML.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct MergeChunk {
+    pub status: ChunkStatus,
+    pub base: Vec<String>,
+    pub local: Vec<String>,
+    pub remote: Vec<String>,
+}
+
+impl MergeChunk {
+    /// The blocks to keep, unless the chunk is a conflict
+    pub fn resolved(&self) -> Option<&[String]> {
+        match self.status {
+            ChunkStatus::Unchanged | ChunkStatus::Remote => Some(&self.remote),
+            ChunkStatus::Local | ChunkStatus::Both => Some(&self.local),
+            ChunkStatus::Conflict => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ThreeWayDiff {
+    pub chunks: Vec<MergeChunk>,
+    pub conflicts: usize,
+    /// The content with both sides' changes, if none conflict
+    pub merged: Option<String>,
+}
+
+/// Top-level blocks of note HTML. Text and inline elements between block
+/// elements are kept together as one block. Whitespace between blocks is
+/// dropped.
+pub fn split_blocks(content: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut loose: Vec<Node> = Vec::new();
+    for node in html::parse(content) {
+        match node {
+            _ if is_block(&node) => {
+                if !loose.is_empty() {
+                    blocks.push(html::serialize(&loose));
+                    loose.clear();
+                }
+                blocks.push(html::serialize(&[node]));
+            }
+            Node::Text(ref text) if text.trim().is_empty() && loose.is_empty() => {}
+            _ => loose.push(node),
+        }
+    }
+    if !loose.is_empty() {
+        blocks.push(html::serialize(&loose));
+    }
+    blocks
+}
+
+/// Diff two edited versions of note HTML against the version both started
+/// from. Without that version, the blocks both share stand in for it, so
+/// blocks added on one side merge and blocks changed on both conflict.
+pub fn three_way_diff(base: Option<&str>, local: &str, remote: &str) -> ThreeWayDiff {
+    let local = split_blocks(local);
+    let remote = split_blocks(remote);
+    let base = match base {
+        Some(base) => split_blocks(base),
+        None => lcs_pairs(&local, &remote)
+            .iter()
+            .zip(&local)
+            .filter(|(pair, _)| pair.is_some())
+            .map(|(_, block)| block.clone())
+            .collect(),
+    };
+    let chunks = merge_blocks(&base, &local, &remote);
+    let conflicts = chunks
+        .iter()
+        .filter(|chunk| chunk.status == ChunkStatus::Conflict)
+        .count();
+    let merged = chunks
+        .iter()
+        .map(|chunk| chunk.resolved().map(|blocks| blocks.concat()))
+        .collect::<Option<String>>();
+    ThreeWayDiff {
+        chunks,
+        conflicts,
+        merged,
+    }
+}
+
+/// For each item of `a`, the index of the item of `b` it is paired with in a
+/// longest common subsequence
+fn lcs_pairs(a: &[String], b: &[String]) -> Vec<Option<usize>> {
+    // lengths[i][j]: LCS length of a[i..] and b[j..]
+    let mut lengths = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = vec![None; a.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// diff3: runs of base blocks kept in place by both sides are unchanged;
+/// what lies between them is classified by which side changed it
+pub fn merge_blocks(base: &[String], local: &[String], remote: &[String]) -> Vec<MergeChunk> {
+    let to_local = lcs_pairs(base, local);
+    let to_remote = lcs_pairs(base, remote);
+    let mut chunks = Vec::new();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // Blocks in step on all three sides
+        let mut stable = 0;
+        while i + stable < base.len()
+            && to_local[i + stable] == Some(j + stable)
+            && to_remote[i + stable] == Some(k + stable)
+        {
+            stable += 1;
+        }
+        if stable > 0 {
+            chunks.push(MergeChunk {
+                status: ChunkStatus::Unchanged,
+                base: base[i..i + stable].to_vec(),
+                local: local[j..j + stable].to_vec(),
+                remote: remote[k..k + stable].to_vec(),
+            });
+            i += stable;
+            j += stable;
+            k += stable;
+        }
+
+        // The next base block both sides kept, or the ends
+        let next = (i..base.len()).find_map(|n| Some((n, to_local[n]?, to_remote[n]?)));
+        let (ni, nj, nk) = next.unwrap_or((base.len(), local.len(), remote.len()));
+        if (ni, nj, nk) == (i, j, k) {
+            break;
+        }
+        chunks.push(changed_chunk(&base[i..ni], &local[j..nj], &remote[k..nk]));
+        (i, j, k) = (ni, nj, nk);
+    }
+    chunks
+}
+
+fn changed_chunk(base: &[String], local: &[String], remote: &[String]) -> MergeChunk {
+    let status = if local == remote {
+        ChunkStatus::Both
+    } else if local == base {
+        ChunkStatus::Remote
+    } else if remote == base {
+        ChunkStatus::Local
+    } else {
+        ChunkStatus::Conflict
+    };
+    MergeChunk {
+        status,
+        base: base.to_vec(),
+        local: local.to_vec(),
+        remote: remote.to_vec(),
+    }
+}
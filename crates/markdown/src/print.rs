@@ -0,0 +1,122 @@
+//! Print-ready note content.
+//!
+//! Printing a note, or exporting it to PDF through a browser, needs the note's
+//! markup without the editor's scaffolding: data attributes, task checkboxes
+//! and embedded players mean nothing on paper. `print_html` strips those,
+//! draws task items as ☑/☐ and lets the caller resolve image and link URLs to
+//! something the printing browser can load. Diagrams and math are left to the
+//! caller, as for exports.
+
+use crate::html::{self, Element, Node};
+
+/// Attributes kept on printed elements
+const KEPT_ATTRS: &[&str] = &[
+    "class", "style", "href", "src", "alt", "title", "width", "height", "colspan", "rowspan",
+    "start", "type",
+];
+
+/// Interactive elements dropped with their content
+const DROPPED_ELEMENTS: &[&str] = &[
+    "button", "input", "textarea", "select", "iframe", "object", "embed",
+];
+
+/// Media elements replaced by a placeholder
+const MEDIA_ELEMENTS: &[&str] = &["audio", "video"];
+
+/// Rewrite note HTML for printing. `resolve` maps an image `src` or link
+/// `href` to the URL to print with; images it returns `None` for become a
+/// placeholder and such links are reduced to their text.
+pub fn print_html(content: &str, mut resolve: impl FnMut(&str) -> Option<String>) -> String {
+    let nodes = clean_nodes(html::parse(content), &mut resolve);
+    html::serialize(&nodes)
+}
+
+fn placeholder(kind: &str, label: &str) -> Node {
+    let text = if label.is_empty() {
+        format!("[{}]", kind)
+    } else {
+        format!("[{}: {}]", kind, label)
+    };
+    Node::Element(Element {
+        name: "span".to_string(),
+        attrs: vec![("class".to_string(), "missing-asset".to_string())],
+        children: vec![Node::Text(text)],
+    })
+}
+
+fn clean_nodes(nodes: Vec<Node>, resolve: &mut impl FnMut(&str) -> Option<String>) -> Vec<Node> {
+    let mut out = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match node {
+            Node::Text(_) => out.push(node),
+            Node::Element(el) => out.extend(clean_element(el, resolve)),
+        }
+    }
+    out
+}
+
+fn clean_element(mut el: Element, resolve: &mut impl FnMut(&str) -> Option<String>) -> Vec<Node> {
+    if DROPPED_ELEMENTS.contains(&el.name.as_str()) {
+        return Vec::new();
+    }
+    if MEDIA_ELEMENTS.contains(&el.name.as_str()) {
+        let label = el.attr("title").unwrap_or_default().to_string();
+        return vec![placeholder(&el.name, &label)];
+    }
+
+    let task = (el.name == "li" && el.attr("data-type") == Some("taskItem"))
+        .then(|| el.attr("data-checked") == Some("true"));
+    let task_list = el.name == "ul" && el.attr("data-type") == Some("taskList");
+    el.attrs
+        .retain(|(key, _)| KEPT_ATTRS.contains(&key.as_str()));
+
+    match el.name.as_str() {
+        "img" => {
+            let Some(src) = el.attr("src").and_then(&mut *resolve) else {
+                let alt = el.attr("alt").unwrap_or_default().to_string();
+                return vec![placeholder("image", &alt)];
+            };
+            set_attr(&mut el, "src", src);
+        }
+        "a" => match el.attr("href").and_then(&mut *resolve) {
+            Some(href) => set_attr(&mut el, "href", href),
+            None => return clean_nodes(el.children, resolve),
+        },
+        // The checkbox lives in a label; the glyph below replaces it
+        "label" if el.text().trim().is_empty() => return Vec::new(),
+        _ => {}
+    }
+
+    if task_list {
+        add_class(&mut el, "task-list");
+    }
+    let mut children = clean_nodes(std::mem::take(&mut el.children), resolve);
+    if let Some(checked) = task {
+        add_class(&mut el, if checked { "task done" } else { "task" });
+        children.insert(
+            0,
+            Node::Element(Element {
+                name: "span".to_string(),
+                attrs: vec![("class".to_string(), "task-box".to_string())],
+                children: vec![Node::Text(if checked { "☑ " } else { "☐ " }.to_string())],
+            }),
+        );
+    }
+    el.children = children;
+    vec![Node::Element(el)]
+}
+
+fn set_attr(el: &mut Element, name: &str, value: String) {
+    match el.attrs.iter_mut().find(|(k, _)| k == name) {
+        Some((_, v)) => *v = value,
+        None => el.attrs.push((name.to_string(), value)),
+    }
+}
+
+fn add_class(el: &mut Element, class: &str) {
+    let value = match el.attr("class") {
+        Some(existing) if !existing.is_empty() => format!("{} {}", existing, class),
+        _ => class.to_string(),
+    };
+    set_attr(el, "class", value);
+}
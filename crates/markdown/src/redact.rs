@@ -0,0 +1,196 @@
+//! Redaction of note content for sanitized exports.
+//!
+//! A rule either replaces text matching a pattern (an email address, a phone
+//! number, a project codename, ...) or removes the blocks tagged with a
+//! hashtag such as `#private`: the paragraph, heading, list item, quote or
+//! code block the tag appears in. Blocks left empty by a removal, like a list
+//! item whose only paragraph was tagged, go too. Patterns are matched against
+//! text and the `href`, `src`, `title` and `alt` attributes, so an address
+//! doesn't survive in a `mailto:` link; other markup is left alone.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::html::{self, Element, Node};
+
+/// What a match is replaced with when a rule doesn't say
+pub const DEFAULT_REPLACEMENT: &str = "[redacted]";
+
+/// Patterns available by name
+const PRESETS: &[(&str, &str)] = &[
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    // International, `(area code)` or 3-3-4 numbers, not dates or amounts
+    (
+        "phone",
+        r"\+\d[\d ().-]{6,}\d|\(\d{2,4}\) ?\d[\d .-]{4,}\d|\b\d{3}[ .-]\d{3}[ .-]\d{4}\b",
+    ),
+];
+
+/// Elements removed whole when they contain a redacted tag
+const TAGGED_BLOCKS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "tr",
+    "blockquote",
+    "pre",
+];
+
+/// Attributes patterns are applied to
+const REDACTED_ATTRS: &[&str] = &["href", "src", "title", "alt"];
+
+/// Elements that count as content even without text
+const EMBEDS: &[&str] = &["img", "hr", "iframe", "video", "audio", "svg", "math"];
+
+/// A redaction rule, as configured. Exactly one of `pattern`, `preset` and
+/// `tag` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionRule {
+    /// Regular expression to replace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// A built-in pattern: `email` or `phone`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Hashtag whose blocks are removed, with or without the `#`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Replacement for pattern and preset matches (default `[redacted]`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+}
+
+/// Compiled redaction rules
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    replacements: Vec<(Regex, String)>,
+    tags: Vec<Regex>,
+}
+
+impl Redactor {
+    /// Compile rules, failing on the first invalid one
+    pub fn new(rules: &[RedactionRule]) -> Result<Self, String> {
+        let mut redactor = Redactor::default();
+        for (i, rule) in rules.iter().enumerate() {
+            let replacement = || {
+                rule.replacement
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_REPLACEMENT.to_string())
+            };
+            match (&rule.pattern, &rule.preset, &rule.tag) {
+                (Some(pattern), None, None) => {
+                    let regex = Regex::new(pattern)
+                        .map_err(|e| format!("Rule {}: invalid pattern: {}", i + 1, e))?;
+                    redactor.replacements.push((regex, replacement()));
+                }
+                (None, Some(preset), None) => {
+                    let (_, pattern) = PRESETS
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case(preset.trim()))
+                        .ok_or_else(|| format!("Rule {}: unknown preset \"{}\"", i + 1, preset))?;
+                    let regex = Regex::new(pattern).expect("preset patterns are valid");
+                    redactor.replacements.push((regex, replacement()));
+                }
+                (None, None, Some(tag)) => {
+                    let tag = tag.trim().trim_start_matches('#');
+                    if tag.is_empty() || tag.chars().any(char::is_whitespace) {
+                        return Err(format!("Rule {}: invalid tag \"{}\"", i + 1, tag));
+                    }
+                    let regex = Regex::new(&format!(
+                        r"(?i)(?:^|[^\w#])#{}(?:$|[^\w/-])",
+                        regex::escape(tag)
+                    ))
+                    .map_err(|e| format!("Rule {}: invalid tag: {}", i + 1, e))?;
+                    redactor.tags.push(regex);
+                }
+                _ => {
+                    return Err(format!(
+                        "Rule {}: set exactly one of pattern, preset and tag",
+                        i + 1
+                    ))
+                }
+            }
+        }
+        Ok(redactor)
+    }
+
+    /// Whether there is nothing to redact
+    pub fn is_empty(&self) -> bool {
+        self.replacements.is_empty() && self.tags.is_empty()
+    }
+
+    /// Apply the pattern rules to plain text, such as a note title
+    pub fn redact_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (regex, replacement) in &self.replacements {
+            if regex.is_match(&text) {
+                text = regex
+                    .replace_all(&text, regex::NoExpand(replacement))
+                    .into_owned();
+            }
+        }
+        text
+    }
+
+    /// Apply all rules to note HTML
+    pub fn redact_html(&self, content: &str) -> String {
+        if self.is_empty() {
+            return content.to_string();
+        }
+        html::serialize(&self.redact_nodes(html::parse(content)))
+    }
+
+    fn redact_nodes(&self, nodes: Vec<Node>) -> Vec<Node> {
+        nodes
+            .into_iter()
+            .filter_map(|node| self.redact_node(node))
+            .collect()
+    }
+
+    /// The node with rules applied, or `None` if it is removed
+    fn redact_node(&self, node: Node) -> Option<Node> {
+        let mut el = match node {
+            Node::Text(text) => return Some(Node::Text(self.redact_text(&text))),
+            Node::Element(el) => el,
+        };
+        let count = el.children.len();
+        // Children first, so a tag removes the innermost block it is in
+        el.children = self.redact_nodes(std::mem::take(&mut el.children));
+        let removed = el.children.len() < count;
+        if TAGGED_BLOCKS.contains(&el.name.as_str()) {
+            if self.is_tagged(&el) || (removed && is_blank(&el.children)) {
+                return None;
+            }
+        } else if removed && el.children.is_empty() && el.name != "td" && el.name != "th" {
+            return None;
+        }
+        for (key, value) in el.attrs.iter_mut() {
+            if REDACTED_ATTRS.contains(&key.as_str()) {
+                *value = self.redact_text(value);
+            }
+        }
+        Some(Node::Element(el))
+    }
+
+    fn is_tagged(&self, el: &Element) -> bool {
+        !self.tags.is_empty() && self.is_tagged_text(&el.text())
+    }
+
+    /// Whether plain text carries a tag whose blocks are removed
+    pub fn is_tagged_text(&self, text: &str) -> bool {
+        self.tags.iter().any(|tag| tag.is_match(text))
+    }
+}
+
+/// Whether nodes have neither text nor embedded content
+fn is_blank(nodes: &[Node]) -> bool {
+    nodes.iter().all(|node| match node {
+        Node::Text(text) => text.trim().is_empty(),
+        Node::Element(el) => !EMBEDS.contains(&el.name.as_str()) && is_blank(&el.children),
+    })
+}
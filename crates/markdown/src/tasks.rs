@@ -0,0 +1,120 @@
+//! Task list items in note content.
+//!
+//! The editor writes task lists as `<ul data-type="taskList">` with one
+//! `<li data-type="taskItem" data-checked="true|false">` per task: a label
+//! holding the checkbox, then a `<div>` with the item's content, which may
+//! contain nested lists. A nested item is a task of its own, so its text is
+//! not part of its parent's.
+
+use crate::html::{self, Element, Node};
+
+/// A task list item, in document order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    /// Position among all task items in the note, including empty ones
+    pub index: usize,
+    /// The item's text on one line, without nested items
+    pub text: String,
+    pub checked: bool,
+}
+
+fn is_task_item(el: &Element) -> bool {
+    el.name == "li" && el.attr("data-type") == Some("taskItem")
+}
+
+/// Text of a task item, leaving out its checkbox label and nested lists
+fn item_text(el: &Element) -> String {
+    fn collect(nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Element(el) if matches!(el.name.as_str(), "ul" | "ol" | "label") => {}
+                Node::Element(el) => {
+                    // Block boundaries separate words
+                    out.push(' ');
+                    collect(&el.children, out);
+                }
+            }
+        }
+    }
+    let mut text = String::new();
+    collect(&el.children, &mut text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Extract the tasks of note HTML, skipping items without text
+pub fn extract_tasks(content: &str) -> Vec<Task> {
+    let nodes = html::parse(content);
+    let mut tasks = Vec::new();
+    let mut index = 0;
+    html::walk(&nodes, &mut |el: &Element| {
+        if is_task_item(el) {
+            let text = item_text(el);
+            if !text.is_empty() {
+                tasks.push(Task {
+                    index,
+                    text,
+                    checked: el.attr("data-checked") == Some("true"),
+                });
+            }
+            index += 1;
+        }
+    });
+    tasks
+}
+
+/// Set the checked state of the task at `index` (as in [`Task::index`]).
+/// Returns the new HTML, or `None` if the note has no such task.
+pub fn set_task_checked(content: &str, index: usize, checked: bool) -> Option<String> {
+    fn visit(nodes: &mut [Node], target: usize, seen: &mut usize, checked: bool) -> bool {
+        for node in nodes {
+            let Node::Element(el) = node else {
+                continue;
+            };
+            if is_task_item(el) {
+                if *seen == target {
+                    set_checked(el, checked);
+                    return true;
+                }
+                *seen += 1;
+            }
+            if visit(&mut el.children, target, seen, checked) {
+                return true;
+            }
+        }
+        false
+    }
+    let mut nodes = html::parse(content);
+    visit(&mut nodes, index, &mut 0, checked).then(|| html::serialize(&nodes))
+}
+
+/// Mark a task item and its checkbox checked or unchecked
+fn set_checked(item: &mut Element, checked: bool) {
+    let value = if checked { "true" } else { "false" };
+    match item.attrs.iter_mut().find(|(key, _)| key == "data-checked") {
+        Some((_, v)) => *v = value.to_string(),
+        None => item
+            .attrs
+            .push(("data-checked".to_string(), value.to_string())),
+    }
+    for node in item.children.iter_mut() {
+        let Node::Element(label) = node else {
+            continue;
+        };
+        if label.name != "label" {
+            continue;
+        }
+        for node in label.children.iter_mut() {
+            if let Node::Element(input) = node {
+                if input.name == "input" {
+                    input.attrs.retain(|(key, _)| key != "checked");
+                    if checked {
+                        input
+                            .attrs
+                            .push(("checked".to_string(), "checked".to_string()));
+                    }
+                }
+            }
+        }
+    }
+}
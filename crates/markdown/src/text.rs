@@ -0,0 +1,71 @@
+//! HTML to plain text, used for search snippets and seeding CRDT documents.
+
+use crate::html::{self, Node};
+use crate::to_markdown::is_block;
+
+/// Extract readable text from an HTML fragment, one line per block
+pub fn html_to_text(html: &str) -> String {
+    let nodes = html::parse(html);
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    collect(&nodes, &mut current, &mut lines);
+    flush(&mut current, &mut lines);
+    lines.join("\n")
+}
+
+fn collect(nodes: &[Node], current: &mut String, lines: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => current.push_str(text),
+            Node::Element(el) if el.name == "br" => flush(current, lines),
+            Node::Element(el) if el.name == "img" => {
+                if let Some(alt) = el.attr("alt").filter(|a| !a.is_empty()) {
+                    current.push_str(alt);
+                }
+            }
+            Node::Element(el) if el.name == "pre" => {
+                // Keep code line breaks as they are
+                flush(current, lines);
+                lines.extend(el.text().trim_end_matches('\n').lines().map(str::to_string));
+            }
+            Node::Element(el) if el.name == "tr" => {
+                // One line per row, cells separated by tabs
+                flush(current, lines);
+                let cells: Vec<String> = el
+                    .children
+                    .iter()
+                    .filter_map(|n| match n {
+                        Node::Element(cell) => Some(collapse(&cell.text())),
+                        _ => None,
+                    })
+                    .collect();
+                if cells.iter().any(|c| !c.is_empty()) {
+                    lines.push(cells.join("\t"));
+                }
+            }
+            Node::Element(el) => {
+                let block = is_block(node);
+                if block {
+                    flush(current, lines);
+                }
+                collect(&el.children, current, lines);
+                if block {
+                    flush(current, lines);
+                }
+            }
+        }
+    }
+}
+
+fn flush(current: &mut String, lines: &mut Vec<String>) {
+    let line = collapse(current);
+    let line = line.trim();
+    if !line.is_empty() {
+        lines.push(line.to_string());
+    }
+    current.clear();
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
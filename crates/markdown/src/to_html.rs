@@ -0,0 +1,330 @@
+//! CommonMark/GFM to HTML conversion producing the node shapes the TipTap editor
+//! emits, so imported Markdown opens in the editor unchanged.
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::html::escape;
+
+/// Which wrapper a list item needs closed
+#[derive(Clone, Copy, PartialEq)]
+enum ItemKind {
+    Plain,
+    Task,
+}
+
+#[derive(Default)]
+struct Renderer {
+    out: String,
+    /// Open list items; inline content directly inside one gets an implicit `<p>`
+    items: Vec<ItemKind>,
+    /// Whether a `<p>` was opened implicitly for the innermost item
+    implicit_p: Vec<bool>,
+    task_lists: Vec<bool>,
+    in_table_head: bool,
+    code_block: Option<String>,
+    /// Alt text collected while inside an image
+    image: Option<(String, String, String)>,
+    /// Paragraph nesting inside the innermost item (0 = directly in the item)
+    paragraph_depth: usize,
+    /// Set when the current paragraph only wraps a single image and is omitted
+    skipped_paragraph: Vec<bool>,
+}
+
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_MATH
+}
+
+/// Convert Markdown to editor-compatible HTML
+pub fn markdown_to_html(markdown: &str) -> String {
+    let events: Vec<Event> = Parser::new_ext(markdown, parser_options()).collect();
+    let mut r = Renderer::default();
+
+    for (i, event) in events.iter().enumerate() {
+        r.event(event, &events[i + 1..]);
+    }
+
+    r.out
+}
+
+impl Renderer {
+    fn event(&mut self, event: &Event, rest: &[Event]) {
+        if let Some(code) = self.code_block.as_mut() {
+            match event {
+                Event::Text(text) => {
+                    code.push_str(text);
+                    return;
+                }
+                Event::End(TagEnd::CodeBlock) => {}
+                _ => return,
+            }
+        }
+        if let Some((alt, _, _)) = self.image.as_mut() {
+            match event {
+                Event::Text(text) | Event::Code(text) => {
+                    alt.push_str(text);
+                    return;
+                }
+                Event::End(TagEnd::Image) => {}
+                _ => return,
+            }
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag, rest),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                self.ensure_paragraph();
+                self.out.push_str(&escape(text));
+            }
+            Event::Code(code) => {
+                self.ensure_paragraph();
+                self.out.push_str(&format!("<code>{}</code>", escape(code)));
+            }
+            // The editor keeps math as delimited LaTeX in the text
+            Event::InlineMath(math) => {
+                self.ensure_paragraph();
+                self.out.push_str(&format!("${}$", escape(math)));
+            }
+            Event::DisplayMath(math) => {
+                self.ensure_paragraph();
+                let math = math.split_whitespace().collect::<Vec<_>>().join(" ");
+                self.out.push_str(&format!("$${}$$", escape(&math)));
+            }
+            Event::Html(raw) => self.out.push_str(raw),
+            Event::InlineHtml(raw) => {
+                self.ensure_paragraph();
+                self.out.push_str(raw);
+            }
+            Event::SoftBreak => self.out.push(' '),
+            Event::HardBreak => self.out.push_str("<br>"),
+            Event::Rule => {
+                self.close_implicit_paragraph();
+                self.out.push_str("<hr>");
+            }
+            Event::TaskListMarker(_) | Event::FootnoteReference(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: &Tag, rest: &[Event]) {
+        match tag {
+            Tag::Paragraph => {
+                self.close_implicit_paragraph();
+                // TipTap images are block nodes, so drop a paragraph that only wraps one
+                let only_image = matches!(rest.first(), Some(Event::Start(Tag::Image { .. })))
+                    && rest
+                        .iter()
+                        .position(|e| matches!(e, Event::End(TagEnd::Image)))
+                        .is_some_and(|end| {
+                            matches!(rest.get(end + 1), Some(Event::End(TagEnd::Paragraph)))
+                        });
+                self.skipped_paragraph.push(only_image);
+                self.paragraph_depth += 1;
+                if !only_image {
+                    self.out.push_str("<p>");
+                }
+            }
+            Tag::Heading { level, .. } => {
+                self.close_implicit_paragraph();
+                self.paragraph_depth += 1;
+                self.out.push_str(&format!("<{}>", level));
+            }
+            Tag::BlockQuote(_) => {
+                self.close_implicit_paragraph();
+                self.out.push_str("<blockquote>");
+            }
+            Tag::CodeBlock(kind) => {
+                self.close_implicit_paragraph();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                if language.is_empty() {
+                    self.out.push_str("<pre><code>");
+                } else {
+                    self.out.push_str(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        escape(&language)
+                    ));
+                }
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.close_implicit_paragraph();
+                let task = starts_task_item(rest);
+                self.task_lists.push(task);
+                match start {
+                    Some(1) => self.out.push_str("<ol>"),
+                    Some(n) => self.out.push_str(&format!("<ol start=\"{}\">", n)),
+                    None if task => self.out.push_str("<ul data-type=\"taskList\">"),
+                    None => self.out.push_str("<ul>"),
+                }
+            }
+            Tag::Item => {
+                let in_task_list = self.task_lists.last().copied().unwrap_or(false);
+                if in_task_list {
+                    let checked = task_marker(rest).unwrap_or(false);
+                    self.out.push_str(&format!(
+                        "<li data-type=\"taskItem\" data-checked=\"{}\"><label><input type=\"checkbox\"{}><span></span></label><div>",
+                        checked,
+                        if checked { " checked=\"checked\"" } else { "" }
+                    ));
+                    self.items.push(ItemKind::Task);
+                } else {
+                    self.out.push_str("<li>");
+                    self.items.push(ItemKind::Plain);
+                }
+                self.implicit_p.push(false);
+            }
+            Tag::Table(_) => {
+                self.close_implicit_paragraph();
+                self.out.push_str("<table><tbody>");
+            }
+            Tag::TableHead => {
+                self.in_table_head = true;
+                self.out.push_str("<tr>");
+            }
+            Tag::TableRow => self.out.push_str("<tr>"),
+            Tag::TableCell => {
+                let cell = if self.in_table_head { "th" } else { "td" };
+                self.paragraph_depth += 1;
+                self.out.push_str(&format!("<{}><p>", cell));
+            }
+            Tag::Emphasis => self.inline_open("<em>"),
+            Tag::Strong => self.inline_open("<strong>"),
+            Tag::Strikethrough => self.inline_open("<s>"),
+            Tag::Link {
+                dest_url, title, ..
+            } => {
+                let mut open = format!("<a href=\"{}\"", escape(dest_url));
+                if !title.is_empty() {
+                    open.push_str(&format!(" title=\"{}\"", escape(title)));
+                }
+                open.push('>');
+                self.inline_open(&open);
+            }
+            Tag::Image {
+                dest_url, title, ..
+            } => {
+                self.ensure_paragraph();
+                self.image = Some((String::new(), dest_url.to_string(), title.to_string()));
+            }
+            Tag::HtmlBlock
+            | Tag::FootnoteDefinition(_)
+            | Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition
+            | Tag::MetadataBlock(_) => {}
+        }
+    }
+
+    fn end(&mut self, tag: &TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.paragraph_depth = self.paragraph_depth.saturating_sub(1);
+                if !self.skipped_paragraph.pop().unwrap_or(false) {
+                    self.out.push_str("</p>");
+                }
+            }
+            TagEnd::Heading(level) => {
+                self.paragraph_depth = self.paragraph_depth.saturating_sub(1);
+                self.out.push_str(&format!("</{}>", level));
+            }
+            TagEnd::BlockQuote(_) => self.out.push_str("</blockquote>"),
+            TagEnd::CodeBlock => {
+                let code = self.code_block.take().unwrap_or_default();
+                let code = code.strip_suffix('\n').unwrap_or(&code);
+                self.out.push_str(&escape(code));
+                self.out.push_str("</code></pre>");
+            }
+            TagEnd::List(ordered) => {
+                self.task_lists.pop();
+                self.out.push_str(if *ordered { "</ol>" } else { "</ul>" });
+            }
+            TagEnd::Item => {
+                self.close_implicit_paragraph();
+                self.implicit_p.pop();
+                match self.items.pop() {
+                    Some(ItemKind::Task) => self.out.push_str("</div></li>"),
+                    _ => self.out.push_str("</li>"),
+                }
+            }
+            TagEnd::Table => self.out.push_str("</tbody></table>"),
+            TagEnd::TableHead => {
+                self.in_table_head = false;
+                self.out.push_str("</tr>");
+            }
+            TagEnd::TableRow => self.out.push_str("</tr>"),
+            TagEnd::TableCell => {
+                let cell = if self.in_table_head { "th" } else { "td" };
+                self.paragraph_depth = self.paragraph_depth.saturating_sub(1);
+                self.out.push_str(&format!("</p></{}>", cell));
+            }
+            TagEnd::Emphasis => self.out.push_str("</em>"),
+            TagEnd::Strong => self.out.push_str("</strong>"),
+            TagEnd::Strikethrough => self.out.push_str("</s>"),
+            TagEnd::Link => self.out.push_str("</a>"),
+            TagEnd::Image => {
+                if let Some((alt, src, title)) = self.image.take() {
+                    let mut img = format!("<img src=\"{}\" alt=\"{}\"", escape(&src), escape(&alt));
+                    if !title.is_empty() {
+                        img.push_str(&format!(" title=\"{}\"", escape(&title)));
+                    }
+                    img.push('>');
+                    self.out.push_str(&img);
+                }
+            }
+            TagEnd::HtmlBlock
+            | TagEnd::FootnoteDefinition
+            | TagEnd::DefinitionList
+            | TagEnd::DefinitionListTitle
+            | TagEnd::DefinitionListDefinition
+            | TagEnd::MetadataBlock(_) => {}
+        }
+    }
+
+    fn inline_open(&mut self, tag: &str) {
+        self.ensure_paragraph();
+        self.out.push_str(tag);
+    }
+
+    /// Tight list items carry inline content with no paragraph; TipTap always
+    /// wraps list item content in `<p>`, so open one on demand.
+    fn ensure_paragraph(&mut self) {
+        if self.paragraph_depth > 0 || self.items.is_empty() {
+            return;
+        }
+        if let Some(open) = self.implicit_p.last_mut() {
+            if !*open {
+                *open = true;
+                self.out.push_str("<p>");
+            }
+        }
+    }
+
+    fn close_implicit_paragraph(&mut self) {
+        if let Some(open) = self.implicit_p.last_mut() {
+            if *open {
+                *open = false;
+                self.out.push_str("</p>");
+            }
+        }
+    }
+}
+
+/// Checked state of the task marker at the start of a list item, if any
+fn task_marker(rest: &[Event]) -> Option<bool> {
+    rest.iter().take(2).find_map(|e| match e {
+        Event::TaskListMarker(checked) => Some(*checked),
+        _ => None,
+    })
+}
+
+/// Whether the list starting here is a task list (its first item has a marker)
+fn starts_task_item(rest: &[Event]) -> bool {
+    matches!(rest.first(), Some(Event::Start(Tag::Item))) && task_marker(&rest[1..]).is_some()
+}
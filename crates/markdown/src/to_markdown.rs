@@ -0,0 +1,496 @@
+//! HTML (TipTap output) to CommonMark/GFM conversion.
+
+use crate::html::{self, Element, Node};
+use crate::math::{split_math, Segment};
+
+/// Elements rendered as blocks; everything else is treated as inline content
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Inline elements kept as raw HTML because Markdown has no syntax for them
+const PASSTHROUGH_INLINE: &[&str] = &["mark", "u", "sub", "sup", "kbd"];
+
+pub(crate) fn is_block(node: &Node) -> bool {
+    matches!(node, Node::Element(el) if BLOCK_ELEMENTS.contains(&el.name.as_str()))
+}
+
+fn is_blank_text(node: &Node) -> bool {
+    matches!(node, Node::Text(t) if t.trim().is_empty())
+}
+
+/// Convert an HTML fragment to Markdown
+pub fn html_to_markdown(html: &str) -> String {
+    let nodes = html::parse(html);
+    let blocks = render_blocks(&nodes);
+    if blocks.is_empty() {
+        return String::new();
+    }
+    let mut out = blocks.join("\n\n");
+    out.push('\n');
+    out
+}
+
+/// Render a sequence of sibling nodes as Markdown blocks
+fn render_blocks(nodes: &[Node]) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut inline_run: Vec<&Node> = Vec::new();
+
+    let flush = |run: &mut Vec<&Node>, blocks: &mut Vec<String>| {
+        if run.is_empty() {
+            return;
+        }
+        let text = render_inline_nodes(run.iter().copied());
+        let text = text.trim();
+        if !text.is_empty() {
+            blocks.push(text.to_string());
+        }
+        run.clear();
+    };
+
+    for node in nodes {
+        if is_block(node) {
+            flush(&mut inline_run, &mut blocks);
+            if let Node::Element(el) = node {
+                if let Some(block) = render_block(el) {
+                    blocks.push(block);
+                }
+            }
+        } else if !(inline_run.is_empty() && is_blank_text(node)) {
+            inline_run.push(node);
+        }
+    }
+    flush(&mut inline_run, &mut blocks);
+
+    blocks
+}
+
+fn render_block(el: &Element) -> Option<String> {
+    let block = match el.name.as_str() {
+        "p" => render_inline_nodes(el.children.iter()).trim().to_string(),
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = el.name[1..].parse::<usize>().unwrap_or(1);
+            let text = render_inline_nodes(el.children.iter());
+            format!("{} {}", "#".repeat(level), text.trim())
+        }
+        "hr" => "---".to_string(),
+        "pre" => render_code_block(el),
+        "blockquote" => {
+            let inner = render_blocks(&el.children).join("\n\n");
+            inner
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        "ul" | "ol" => render_list(el),
+        "table" => render_table(el),
+        _ => render_blocks(&el.children).join("\n\n"),
+    };
+
+    if block.is_empty() {
+        None
+    } else {
+        Some(block)
+    }
+}
+
+fn render_code_block(pre: &Element) -> String {
+    let code = pre.children.iter().find_map(|n| match n {
+        Node::Element(el) if el.name == "code" => Some(el),
+        _ => None,
+    });
+    let language = code
+        .and_then(|c| c.attr("class"))
+        .and_then(|class| {
+            class
+                .split_whitespace()
+                .find_map(|c| c.strip_prefix("language-"))
+        })
+        .or_else(|| pre.attr("data-language"))
+        .unwrap_or("");
+    let text = code.map(|c| c.text()).unwrap_or_else(|| pre.text());
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+
+    let fence = "`".repeat(longest_run(text, '`').max(2) + 1);
+    format!("{fence}{language}\n{text}\n{fence}")
+}
+
+fn longest_run(text: &str, ch: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == ch {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+pub(crate) fn is_task_list(el: &Element) -> bool {
+    el.attr("data-type") == Some("taskList")
+}
+
+fn render_list(list: &Element) -> String {
+    let ordered = list.name == "ol";
+    let task_list = is_task_list(list);
+    let mut number = list
+        .attr("start")
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(1);
+
+    let mut items = Vec::new();
+    for node in &list.children {
+        let Node::Element(item) = node else { continue };
+        if item.name != "li" {
+            continue;
+        }
+
+        let marker = if task_list || item.attr("data-type") == Some("taskItem") {
+            let checked = item.attr("data-checked") == Some("true");
+            format!("- [{}] ", if checked { 'x' } else { ' ' })
+        } else if ordered {
+            let m = format!("{}. ", number);
+            number += 1;
+            m
+        } else {
+            "- ".to_string()
+        };
+
+        let body = render_list_item_body(item);
+        let indent = " ".repeat(if task_list { 2 } else { marker.len() });
+        let mut rendered = marker.clone();
+        for (i, line) in body.lines().enumerate() {
+            if i > 0 {
+                rendered.push('\n');
+                if !line.is_empty() {
+                    rendered.push_str(&indent);
+                }
+            }
+            rendered.push_str(line);
+        }
+        items.push(rendered.trim_end().to_string());
+    }
+
+    items.join("\n")
+}
+
+/// Render a list item's content, unwrapping TipTap's task item `<label>`/`<div>` wrapper
+fn render_list_item_body(item: &Element) -> String {
+    let content: Vec<Node> = item
+        .children
+        .iter()
+        .filter(|n| !matches!(n, Node::Element(el) if el.name == "label" || el.name == "input"))
+        .flat_map(|n| match n {
+            Node::Element(el) if el.name == "div" && item.attr("data-type") == Some("taskItem") => {
+                el.children.clone()
+            }
+            other => vec![other.clone()],
+        })
+        .collect();
+
+    let blocks = render_blocks(&content);
+    let mut body = String::new();
+    for (i, block) in blocks.iter().enumerate() {
+        if i > 0 {
+            // Nested lists stay tight against their parent item
+            let nested_list = block.starts_with("- ")
+                || block
+                    .split_once(". ")
+                    .is_some_and(|(n, _)| n.parse::<u64>().is_ok());
+            body.push_str(if nested_list { "\n" } else { "\n\n" });
+        }
+        body.push_str(block);
+    }
+    body
+}
+
+fn render_table(table: &Element) -> String {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut header_row = false;
+    collect_rows(&table.children, &mut rows, &mut header_row);
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0).max(1);
+    for row in rows.iter_mut() {
+        row.resize(columns, String::new());
+    }
+    if !header_row {
+        // GFM tables require a header; synthesize an empty one
+        rows.insert(0, vec![String::new(); columns]);
+    }
+
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(format!("| {} |", row.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}|", vec![" --- "; columns].join("|")));
+        }
+    }
+    lines.join("\n")
+}
+
+fn collect_rows(nodes: &[Node], rows: &mut Vec<Vec<String>>, header_row: &mut bool) {
+    for node in nodes {
+        let Node::Element(el) = node else { continue };
+        match el.name.as_str() {
+            "thead" | "tbody" | "tfoot" => collect_rows(&el.children, rows, header_row),
+            "tr" => {
+                let mut cells = Vec::new();
+                let mut all_th = true;
+                for cell in &el.children {
+                    let Node::Element(cell) = cell else { continue };
+                    if cell.name != "td" && cell.name != "th" {
+                        continue;
+                    }
+                    all_th &= cell.name == "th";
+                    cells.push(render_table_cell(cell));
+                }
+                if rows.is_empty() && all_th && !cells.is_empty() {
+                    *header_row = true;
+                }
+                rows.push(cells);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn render_table_cell(cell: &Element) -> String {
+    let parts: Vec<String> = cell
+        .children
+        .iter()
+        .map(|n| match n {
+            Node::Element(el) if is_block(n) => render_inline_nodes(el.children.iter()),
+            other => render_inline_nodes(std::iter::once(other)),
+        })
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    parts.join("<br>").replace('|', "\\|").replace('\n', " ")
+}
+
+/// Render inline nodes to Markdown
+fn render_inline_nodes<'a>(nodes: impl Iterator<Item = &'a Node>) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        render_inline(node, &mut out);
+    }
+    out
+}
+
+fn render_inline(node: &Node, out: &mut String) {
+    match node {
+        Node::Text(text) => push_text(text, out),
+        Node::Element(el) => match el.name.as_str() {
+            "strong" | "b" => wrap(el, "**", out),
+            "em" | "i" => wrap(el, "*", out),
+            "s" | "del" | "strike" => wrap(el, "~~", out),
+            "code" => {
+                let text = el.text();
+                let ticks = "`".repeat(longest_run(&text, '`') + 1);
+                let pad = if text.starts_with('`') || text.ends_with('`') {
+                    " "
+                } else {
+                    ""
+                };
+                out.push_str(&format!("{ticks}{pad}{text}{pad}{ticks}"));
+            }
+            "a" => {
+                let inner = render_inline_nodes(el.children.iter());
+                let href = el.attr("href").unwrap_or("");
+                out.push_str(&format!(
+                    "[{}]({})",
+                    inner,
+                    link_target(href, el.attr("title"))
+                ));
+            }
+            "img" => {
+                let alt = escape_markdown(el.attr("alt").unwrap_or(""), false);
+                let src = el.attr("src").unwrap_or("");
+                out.push_str(&format!(
+                    "![{}]({})",
+                    alt,
+                    link_target(src, el.attr("title"))
+                ));
+            }
+            "br" => out.push_str("\\\n"),
+            "input" | "label" => {}
+            name if PASSTHROUGH_INLINE.contains(&name) => {
+                out.push('<');
+                out.push_str(name);
+                for (key, value) in &el.attrs {
+                    out.push_str(&format!(" {}=\"{}\"", key, html::escape(value)));
+                }
+                out.push('>');
+                for child in &el.children {
+                    render_inline(child, out);
+                }
+                out.push_str(&format!("</{}>", name));
+            }
+            _ => {
+                let block = is_block(node);
+                if block && !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+                for child in &el.children {
+                    render_inline(child, out);
+                }
+            }
+        },
+    }
+}
+
+/// Wrap an element's rendered content in a delimiter, keeping surrounding
+/// whitespace outside the delimiters so emphasis stays valid
+fn wrap(el: &Element, delimiter: &str, out: &mut String) {
+    let inner = render_inline_nodes(el.children.iter());
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        out.push_str(&inner);
+        return;
+    }
+    if inner.starts_with(char::is_whitespace) {
+        out.push(' ');
+    }
+    out.push_str(delimiter);
+    out.push_str(trimmed);
+    out.push_str(delimiter);
+    if inner.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn link_target(url: &str, title: Option<&str>) -> String {
+    let url = if url.contains([' ', '(', ')']) {
+        format!("<{}>", url)
+    } else {
+        url.to_string()
+    };
+    match title {
+        Some(title) if !title.is_empty() => format!("{} \"{}\"", url, title.replace('"', "\\\"")),
+        _ => url,
+    }
+}
+
+fn push_text(text: &str, out: &mut String) {
+    // Collapse HTML whitespace, keeping non-breaking spaces intact
+    let mut collapsed = String::with_capacity(text.len());
+    let mut last_space = out.ends_with(' ') || out.is_empty() || out.ends_with('\n');
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !last_space {
+                collapsed.push(' ');
+            }
+            last_space = true;
+        } else {
+            collapsed.push(c);
+            last_space = false;
+        }
+    }
+    // Math is LaTeX source and goes out as written
+    for segment in split_math(&collapsed) {
+        match segment {
+            Segment::Text(text) => {
+                let at_line_start = out.is_empty() || out.ends_with('\n');
+                out.push_str(&escape_markdown(text, at_line_start));
+            }
+            Segment::Math { source, display } => {
+                let delimiter = if display { "$$" } else { "$" };
+                out.push_str(&format!("{delimiter}{source}{delimiter}"));
+            }
+        }
+    }
+}
+
+/// Backslash-escape characters that would otherwise be read as Markdown syntax
+fn escape_markdown(text: &str, at_line_start: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| chars[j]);
+        let next = chars.get(i + 1).copied();
+        let escape = match c {
+            '\\' | '*' | '`' | '[' | ']' | '<' | '>' | '~' => true,
+            // Intraword underscores never start emphasis
+            '_' => {
+                !(prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric))
+            }
+            '&' => looks_like_entity(&chars[i..]),
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+
+    if at_line_start {
+        // Text that would start a heading, list item or setext underline
+        let digits = chars.iter().take_while(|c| c.is_ascii_digit()).count();
+        let ordered_marker = digits > 0 && matches!(chars.get(digits), Some('.') | Some(')'));
+        let block_marker = match chars.first() {
+            Some('#') | Some('=') | Some('+') => true,
+            Some('-') => matches!(chars.get(1), None | Some(' ') | Some('-')),
+            _ => false,
+        };
+        if ordered_marker {
+            out.insert(digits, '\\');
+        } else if block_marker {
+            out.insert(0, '\\');
+        }
+    }
+
+    out
+}
+
+fn looks_like_entity(chars: &[char]) -> bool {
+    let body: String = chars.iter().skip(1).take(10).collect();
+    match body.find(';') {
+        Some(end) if end > 0 => body[..end]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '#'),
+        _ => false,
+    }
+}
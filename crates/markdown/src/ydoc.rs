@@ -0,0 +1,328 @@
+//! Building a note's CRDT document from its HTML.
+//!
+//! The editor keeps a note in the Yjs `XmlFragment` named `content`, laid out
+//! the way y-prosemirror maps TipTap's schema: an `XmlElement` per node, named
+//! after the node type and carrying its attributes, and an `XmlText` per run of
+//! text whose marks are formatting attributes (`bold: {}`, `link: {href}`).
+//! Notes created on the server get a document built the same way, so the
+//! first editor to open one sees what it would have saved itself, and notes
+//! rewritten as HTML on the desktop have their document's content replaced
+//! with it. Elements the schema has no node for (tables, `div`s) are
+//! unwrapped, keeping their text as paragraphs, and headings deeper than the
+//! editor's three levels become level 3.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::html::{self, Element, Node};
+use yrs::{
+    branch::{Branch, BranchPtr},
+    types::Attrs,
+    updates::encoder::Encode,
+    Any, Doc, Map, MapRef, ReadTxn, StateVector, Text, Transact, TransactionMut, XmlElementPrelim,
+    XmlElementRef, XmlFragment, XmlFragmentRef, XmlTextPrelim, XmlTextRef,
+};
+
+/// Deepest heading level the editor allows
+const MAX_HEADING_LEVEL: u8 = 3;
+
+/// Elements that start a block of their own
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "hr",
+    "div",
+    "table",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "th",
+    "td",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "figure",
+];
+
+/// Encoded document state and state vector for note HTML, as stored in
+/// `crdt_states`
+pub fn seed_state(content: &str) -> (Vec<u8>, Vec<u8>) {
+    let doc = Doc::new();
+    let fragment = doc.get_or_insert_xml_fragment("content");
+    {
+        let mut txn = doc.transact_mut();
+        blocks(&mut txn, &fragment, &html::parse(content));
+    }
+    let txn = doc.transact();
+    (
+        txn.encode_state_as_update_v1(&StateVector::default()),
+        txn.state_vector().encode_v1(),
+    )
+}
+
+/// Replace the content of a note's document with its HTML, as an edit that
+/// merges with the document's history like any other
+pub fn replace_content(txn: &mut TransactionMut, fragment: &XmlFragmentRef, content: &str) {
+    let len = fragment.len(txn);
+    fragment.remove_range(txn, 0, len);
+    blocks(txn, fragment, &html::parse(content));
+}
+
+fn is_block(el: &Element) -> bool {
+    BLOCK_ELEMENTS.contains(&el.name.as_str())
+}
+
+/// Set a node attribute. `Xml::insert_attribute` only stores strings, but
+/// ProseMirror compares attributes by value (a heading's level is a number,
+/// a task's `checked` a boolean); attributes are the element's map entries, so
+/// typed ones are written through a map view of it.
+fn set_attr(txn: &mut TransactionMut, el: &XmlElementRef, name: &str, value: impl Into<Any>) {
+    let branch: &Branch = el.as_ref();
+    MapRef::from(BranchPtr::from(branch)).insert(txn, name, value.into());
+}
+
+fn push_element<P: XmlFragment>(txn: &mut TransactionMut, parent: &P, name: &str) -> XmlElementRef {
+    parent.push_back(txn, XmlElementPrelim::empty(name))
+}
+
+/// Add block nodes for `nodes`, wrapping runs of inline content in paragraphs
+fn blocks<P: XmlFragment>(txn: &mut TransactionMut, parent: &P, nodes: &[Node]) {
+    let mut run: Vec<&Node> = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Element(el) if is_block(el) => {
+                paragraph(txn, parent, &run);
+                run.clear();
+                block(txn, parent, el);
+            }
+            _ => run.push(node),
+        }
+    }
+    paragraph(txn, parent, &run);
+}
+
+/// A paragraph for inline content, unless it is only whitespace
+fn paragraph<P: XmlFragment>(txn: &mut TransactionMut, parent: &P, run: &[&Node]) {
+    let empty = run.iter().all(|node| match node {
+        Node::Text(text) => text.trim().is_empty(),
+        Node::Element(_) => false,
+    });
+    if !empty {
+        let p = push_element(txn, parent, "paragraph");
+        let mut text = None;
+        for node in run {
+            inline(txn, &p, node, &Attrs::new(), &mut text);
+        }
+    }
+}
+
+/// Children of a node whose content must start with a paragraph
+fn block_content(txn: &mut TransactionMut, el: &XmlElementRef, nodes: &[Node]) {
+    blocks(txn, el, nodes);
+    if el.len(txn) == 0 {
+        push_element(txn, el, "paragraph");
+    }
+}
+
+fn block<P: XmlFragment>(txn: &mut TransactionMut, parent: &P, el: &Element) {
+    match el.name.as_str() {
+        "p" => {
+            let p = push_element(txn, parent, "paragraph");
+            inline_children(txn, &p, el);
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = el.name[1..]
+                .parse::<u8>()
+                .unwrap_or(1)
+                .min(MAX_HEADING_LEVEL);
+            let heading = push_element(txn, parent, "heading");
+            set_attr(txn, &heading, "level", f64::from(level));
+            inline_children(txn, &heading, el);
+        }
+        "ul" | "ol" => {
+            let task_list = el.attr("data-type") == Some("taskList");
+            let list = match (el.name.as_str(), task_list) {
+                (_, true) => push_element(txn, parent, "taskList"),
+                ("ol", _) => {
+                    let list = push_element(txn, parent, "orderedList");
+                    if let Some(start) = el.attr("start").and_then(|s| s.parse::<u32>().ok()) {
+                        set_attr(txn, &list, "start", f64::from(start));
+                    }
+                    list
+                }
+                _ => push_element(txn, parent, "bulletList"),
+            };
+            for node in &el.children {
+                match node {
+                    Node::Element(li) if li.name == "li" => list_item(txn, &list, li, task_list),
+                    Node::Text(text) if text.trim().is_empty() => {}
+                    // Stray content gets an item of its own
+                    other => {
+                        let item = push_element(
+                            txn,
+                            &list,
+                            if task_list { "taskItem" } else { "listItem" },
+                        );
+                        block_content(txn, &item, std::slice::from_ref(other));
+                    }
+                }
+            }
+            if list.len(txn) == 0 {
+                let item =
+                    push_element(txn, &list, if task_list { "taskItem" } else { "listItem" });
+                push_element(txn, &item, "paragraph");
+            }
+        }
+        "blockquote" => {
+            let quote = push_element(txn, parent, "blockquote");
+            block_content(txn, &quote, &el.children);
+        }
+        "pre" => {
+            let code_block = push_element(txn, parent, "codeBlock");
+            let code = el.children.iter().find_map(|node| match node {
+                Node::Element(code) if code.name == "code" => Some(code),
+                _ => None,
+            });
+            if let Some(language) = code.and_then(|code| code.attr("class")).and_then(|class| {
+                class
+                    .split_whitespace()
+                    .find_map(|c| c.strip_prefix("language-"))
+            }) {
+                set_attr(txn, &code_block, "language", language);
+            }
+            let mut source = String::new();
+            collect_text(&el.children, &mut source);
+            if !source.is_empty() {
+                code_block.push_back(txn, XmlTextPrelim::new(source));
+            }
+        }
+        "hr" => {
+            push_element(txn, parent, "horizontalRule");
+        }
+        _ => blocks(txn, parent, &el.children),
+    }
+}
+
+fn list_item(txn: &mut TransactionMut, list: &XmlElementRef, li: &Element, task: bool) {
+    if !task {
+        let item = push_element(txn, list, "listItem");
+        block_content(txn, &item, &li.children);
+        return;
+    }
+    let item = push_element(txn, list, "taskItem");
+    set_attr(
+        txn,
+        &item,
+        "checked",
+        li.attr("data-checked") == Some("true"),
+    );
+    // The label only holds the checkbox
+    let content: Vec<Node> = li
+        .children
+        .iter()
+        .filter(|node| !matches!(node, Node::Element(el) if el.name == "label"))
+        .cloned()
+        .collect();
+    block_content(txn, &item, &content);
+}
+
+fn collect_text(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Element(el) => collect_text(&el.children, out),
+        }
+    }
+}
+
+fn inline_children(txn: &mut TransactionMut, parent: &XmlElementRef, el: &Element) {
+    let mut text = None;
+    for node in &el.children {
+        inline(txn, parent, node, &Attrs::new(), &mut text);
+    }
+}
+
+/// Mark for an inline element, as the formatting attribute y-prosemirror uses
+fn mark(el: &Element) -> Option<(&'static str, Any)> {
+    let mut attrs = HashMap::new();
+    let name = match el.name.as_str() {
+        "strong" | "b" => "bold",
+        "em" | "i" => "italic",
+        "s" | "del" | "strike" => "strike",
+        "u" => "underline",
+        "code" => "code",
+        "a" => {
+            attrs.insert("href".to_string(), Any::from(el.attr("href")?));
+            "link"
+        }
+        _ => return None,
+    };
+    Some((name, Any::Map(Arc::new(attrs))))
+}
+
+/// Add inline content to a node. `text` is the text node being appended to,
+/// which a non-text node ends.
+fn inline(
+    txn: &mut TransactionMut,
+    parent: &XmlElementRef,
+    node: &Node,
+    marks: &Attrs,
+    text: &mut Option<XmlTextRef>,
+) {
+    match node {
+        Node::Text(chunk) => {
+            // Whitespace collapses as in the browser, and none opens a line
+            let mut collapsed = String::with_capacity(chunk.len());
+            for c in chunk.chars() {
+                match c.is_whitespace() {
+                    true if collapsed.ends_with(' ')
+                        || (collapsed.is_empty() && text.is_none()) => {}
+                    true => collapsed.push(' '),
+                    false => collapsed.push(c),
+                }
+            }
+            if collapsed.is_empty() {
+                return;
+            }
+            let target = text.get_or_insert_with(|| parent.push_back(txn, XmlTextPrelim::new("")));
+            let len = target.len(txn);
+            target.insert_with_attributes(txn, len, &collapsed, marks.clone());
+        }
+        Node::Element(el) => match el.name.as_str() {
+            "br" => {
+                push_element(txn, parent, "hardBreak");
+                *text = None;
+            }
+            "img" => {
+                let image = push_element(txn, parent, "image");
+                for name in ["src", "alt", "title"] {
+                    if let Some(value) = el.attr(name) {
+                        set_attr(txn, &image, name, value);
+                    }
+                }
+                *text = None;
+            }
+            "input" => {}
+            _ => {
+                let mut marks = marks.clone();
+                if let Some((name, value)) = mark(el) {
+                    marks.insert(name.into(), value);
+                }
+                for child in &el.children {
+                    inline(txn, parent, child, &marks, text);
+                }
+            }
+        },
+    }
+}
@@ -0,0 +1,32 @@
+use beck_markdown::{render_note, Backlink, ExportFormat};
+
+#[test]
+fn markdown_export_has_title_heading() {
+    assert_eq!(
+        render_note(
+            "  Plan ",
+            "<p>Ship <strong>it</strong></p>",
+            ExportFormat::Markdown,
+            &[]
+        ),
+        "# Plan\n\nShip **it**\n"
+    );
+    assert_eq!(
+        render_note("", "<p>Body</p>", ExportFormat::Markdown, &[]),
+        "Body\n"
+    );
+}
+
+#[test]
+fn html_export_is_a_standalone_document() {
+    let backlinks = [Backlink {
+        title: "Other".to_string(),
+        href: "Other.html".to_string(),
+    }];
+    let html = render_note("A <b>", "<p>Body</p>", ExportFormat::Html, &backlinks);
+    assert!(html.starts_with("<!DOCTYPE html>\n"));
+    assert!(html.contains("<title>A &lt;b&gt;</title>"));
+    assert!(html.contains("<p>Body</p>"));
+    assert!(html.contains("href=\"Other.html\""));
+    assert_eq!(ExportFormat::Html.extension(), "html");
+}
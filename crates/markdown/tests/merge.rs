@@ -0,0 +1,109 @@
+use beck_markdown::{merge_blocks, split_blocks, three_way_diff, ChunkStatus};
+
+fn blocks(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+fn statuses(base: &[&str], local: &[&str], remote: &[&str]) -> Vec<ChunkStatus> {
+    merge_blocks(&blocks(base), &blocks(local), &blocks(remote))
+        .iter()
+        .map(|chunk| chunk.status)
+        .collect()
+}
+
+#[test]
+fn splits_top_level_blocks() {
+    assert_eq!(
+        split_blocks("<h1>Title</h1>\n<p>One &amp; two</p><ul><li><p>Item</p></li></ul>"),
+        blocks(&[
+            "<h1>Title</h1>",
+            "<p>One &amp; two</p>",
+            "<ul><li><p>Item</p></li></ul>"
+        ])
+    );
+    assert_eq!(
+        split_blocks("loose <b>text</b> and <a href=\"x\">a link</a><p>Para</p><i>tail</i>"),
+        blocks(&[
+            "loose <b>text</b> and <a href=\"x\">a link</a>",
+            "<p>Para</p>",
+            "<i>tail</i>"
+        ])
+    );
+    assert!(split_blocks("").is_empty());
+}
+
+#[test]
+fn classifies_changes_by_side() {
+    use ChunkStatus::*;
+    assert_eq!(statuses(&["a", "b"], &["a", "b"], &["a", "b"]), [Unchanged]);
+    assert_eq!(
+        statuses(&["a", "b", "c"], &["a", "B", "c"], &["a", "b", "c"]),
+        [Unchanged, Local, Unchanged]
+    );
+    assert_eq!(
+        statuses(&["a", "b", "c"], &["a", "b", "c", "d"], &["x", "b", "c"]),
+        [Remote, Unchanged, Local]
+    );
+    assert_eq!(
+        statuses(&["a", "b"], &["a", "c"], &["a", "c"]),
+        [Unchanged, Both]
+    );
+    assert_eq!(
+        statuses(&["a", "b", "c"], &["a", "L", "c"], &["a", "R", "c"]),
+        [Unchanged, Conflict, Unchanged]
+    );
+    // Deleted on one side, untouched on the other
+    assert_eq!(
+        statuses(&["a", "b"], &["a"], &["a", "b"]),
+        [Unchanged, Local]
+    );
+    // Both sides added different blocks at the same place
+    assert_eq!(statuses(&[], &["l"], &["r"]), [Conflict]);
+}
+
+#[test]
+fn merges_changes_that_do_not_conflict() {
+    let diff = three_way_diff(
+        Some("<p>one</p><p>two</p><p>three</p>"),
+        "<p>one!</p><p>two</p><p>three</p>",
+        "<p>one</p><p>two</p><p>three</p><p>four</p>",
+    );
+    assert_eq!(diff.conflicts, 0);
+    assert_eq!(
+        diff.merged.as_deref(),
+        Some("<p>one!</p><p>two</p><p>three</p><p>four</p>")
+    );
+}
+
+#[test]
+fn conflicting_changes_are_not_merged() {
+    let diff = three_way_diff(
+        Some("<p>one</p><p>two</p>"),
+        "<p>one</p><p>local</p>",
+        "<p>one</p><p>remote</p>",
+    );
+    assert_eq!(diff.conflicts, 1);
+    assert_eq!(diff.merged, None);
+    let conflict = &diff.chunks[1];
+    assert_eq!(conflict.base, blocks(&["<p>two</p>"]));
+    assert_eq!(conflict.local, blocks(&["<p>local</p>"]));
+    assert_eq!(conflict.remote, blocks(&["<p>remote</p>"]));
+    assert_eq!(conflict.resolved(), None);
+}
+
+#[test]
+fn without_a_base_shared_blocks_stand_in_for_it() {
+    let diff = three_way_diff(
+        None,
+        "<p>one</p><p>mine</p><p>two</p>",
+        "<p>one</p><p>two</p><p>theirs</p>",
+    );
+    assert_eq!(diff.conflicts, 0);
+    assert_eq!(
+        diff.merged.as_deref(),
+        Some("<p>one</p><p>mine</p><p>two</p><p>theirs</p>")
+    );
+
+    let diff = three_way_diff(None, "<p>one</p><p>mine</p>", "<p>one</p><p>theirs</p>");
+    assert_eq!(diff.conflicts, 1);
+}
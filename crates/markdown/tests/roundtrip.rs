@@ -0,0 +1,345 @@
+use beck_markdown::{
+    backlinks_html, backlinks_markdown, export_file_name, extract_highlights, extract_tasks,
+    find_diagrams, html_to_markdown, html_to_text, latex_to_mathml, markdown_to_html, print_html,
+    render_math, replace_diagrams, set_task_checked, split_front_matter, tags_html, Backlink,
+    Diagram, FrontMatter, RedactionRule, Redactor,
+};
+
+/// Markdown that survives md -> html -> md unchanged
+fn assert_markdown_roundtrip(md: &str) {
+    let html = markdown_to_html(md);
+    assert_eq!(html_to_markdown(&html), md, "via html: {}", html);
+}
+
+/// Editor HTML that survives html -> md -> html unchanged
+fn assert_html_roundtrip(html: &str) {
+    let md = html_to_markdown(html);
+    assert_eq!(markdown_to_html(&md), html, "via markdown: {}", md);
+}
+
+#[test]
+fn paragraphs_and_inline_marks() {
+    assert_markdown_roundtrip(
+        "Some **bold**, *italic*, ~~struck~~ and `code`.\n\nSecond paragraph.\n",
+    );
+    assert_html_roundtrip("<p>Some <strong>bold</strong> and <em>italic</em> text</p><p>Next</p>");
+}
+
+#[test]
+fn headings_rules_and_quotes() {
+    assert_markdown_roundtrip("# Title\n\n## Section\n\n---\n\n> Quoted text\n");
+    assert_html_roundtrip("<h1>Title</h1><h3>Sub</h3><hr><blockquote><p>Quoted</p></blockquote>");
+}
+
+#[test]
+fn links_and_line_breaks() {
+    assert_markdown_roundtrip("A [link](https://example.com \"Example\") here\\\nnext line\n");
+    assert_html_roundtrip("<p><a href=\"https://example.com\">link</a><br>next</p>");
+}
+
+#[test]
+fn bullet_and_ordered_lists() {
+    assert_markdown_roundtrip("- one\n- two\n  - nested\n");
+    assert_markdown_roundtrip("3. three\n4. four\n");
+    assert_html_roundtrip("<ul><li><p>one</p></li><li><p>two</p></li></ul>");
+    assert_html_roundtrip("<ol start=\"5\"><li><p>five</p></li></ol>");
+}
+
+#[test]
+fn task_lists() {
+    assert_markdown_roundtrip("- [ ] todo\n- [x] done\n");
+    assert_html_roundtrip(concat!(
+        "<ul data-type=\"taskList\">",
+        "<li data-type=\"taskItem\" data-checked=\"true\"><label><input type=\"checkbox\" checked=\"checked\"><span></span></label><div><p>done</p></div></li>",
+        "<li data-type=\"taskItem\" data-checked=\"false\"><label><input type=\"checkbox\"><span></span></label><div><p>todo</p></div></li>",
+        "</ul>"
+    ));
+}
+
+#[test]
+fn code_blocks() {
+    assert_markdown_roundtrip("```rust\nfn main() {\n    println!(\"<hi>\");\n}\n```\n");
+    assert_markdown_roundtrip("````\n```\nnested fence\n```\n````\n");
+    assert_html_roundtrip("<pre><code class=\"language-js\">let a = 1 &lt; 2;</code></pre>");
+}
+
+#[test]
+fn images() {
+    assert_markdown_roundtrip("![A cat](asset://localhost/cat.png)\n");
+    assert_html_roundtrip("<img src=\"asset://localhost/cat.png\" alt=\"A cat\"><p>Caption</p>");
+}
+
+#[test]
+fn tables() {
+    assert_markdown_roundtrip("| Name | Value |\n| --- | --- |\n| a | 1 |\n| b \\| c | 2 |\n");
+    assert_html_roundtrip(concat!(
+        "<table><tbody>",
+        "<tr><th><p>Name</p></th><th><p>Value</p></th></tr>",
+        "<tr><td><p>a</p></td><td><p>1</p></td></tr>",
+        "</tbody></table>"
+    ));
+}
+
+#[test]
+fn special_characters_are_escaped() {
+    assert_html_roundtrip("<p>2 * 3 = 6, a_b_c and [not a link]</p>");
+    assert_html_roundtrip("<p># not a heading</p>");
+    assert_html_roundtrip("<p>AT&amp;T &lt;tag&gt;</p>");
+}
+
+#[test]
+fn plain_text_extraction() {
+    let html = "<h1>Title</h1><p>Hello&nbsp;<strong>world</strong></p><ul><li><p>item</p></li></ul><pre><code>a\n  b</code></pre>";
+    assert_eq!(html_to_text(html), "Title\nHello world\nitem\na\n  b");
+    assert_eq!(
+        html_to_text("<table><tbody><tr><th><p>A</p></th><th><p>B</p></th></tr></tbody></table>"),
+        "A\tB"
+    );
+}
+
+#[test]
+fn highlights_are_extracted_with_colors() {
+    let html = "<p>Plain <mark>first</mark> and <mark data-color=\"#FFC078\">second</mark></p>\
+                <p><mark style=\"background-color: Lime; color: inherit\">third</mark><mark> </mark></p>";
+    let found: Vec<_> = extract_highlights(html)
+        .into_iter()
+        .map(|h| (h.color, h.text))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (None, "first".to_string()),
+            (Some("#ffc078".to_string()), "second".to_string()),
+            (Some("lime".to_string()), "third".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn tasks_are_extracted_and_checked() {
+    let html = concat!(
+        "<ul data-type=\"taskList\">",
+        "<li data-type=\"taskItem\" data-checked=\"true\"><label><input type=\"checkbox\" checked=\"checked\"><span></span></label><div><p>Send <strong>invoice</strong></p></div></li>",
+        "<li data-type=\"taskItem\" data-checked=\"false\"><label><input type=\"checkbox\"><span></span></label><div><p></p></div></li>",
+        "<li data-type=\"taskItem\" data-checked=\"false\"><label><input type=\"checkbox\"><span></span></label><div><p>Plan trip</p>",
+        "<ul data-type=\"taskList\"><li data-type=\"taskItem\" data-checked=\"false\"><label><input type=\"checkbox\"><span></span></label><div><p>Book hotel</p></div></li></ul>",
+        "</div></li></ul>"
+    );
+    let found: Vec<_> = extract_tasks(html)
+        .into_iter()
+        .map(|t| (t.index, t.text, t.checked))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (0, "Send invoice".to_string(), true),
+            (2, "Plan trip".to_string(), false),
+            (3, "Book hotel".to_string(), false),
+        ]
+    );
+
+    let ticked = set_task_checked(html, 3, true).unwrap();
+    assert!(ticked.contains("data-checked=\"true\"><label><input type=\"checkbox\" checked=\"checked\"><span></span></label><div><p>Book hotel"));
+    let unticked = set_task_checked(&ticked, 0, false).unwrap();
+    let states: Vec<_> = extract_tasks(&unticked)
+        .into_iter()
+        .map(|t| t.checked)
+        .collect();
+    assert_eq!(states, vec![false, false, true]);
+    assert!(!unticked.contains("checked=\"checked\"><span></span></label><div><p>Send"));
+    assert_eq!(set_task_checked(html, 4, true), None);
+}
+
+#[test]
+fn math_is_kept_as_latex() {
+    assert_html_roundtrip("<p>Euler: $e^{i\\pi} + 1 = 0$ and $a_{n+1} = a_n * 2$</p>");
+    assert_html_roundtrip("<p>$$\\sum_{k=1}^{n} k = \\frac{n(n+1)}{2}$$</p>");
+    assert_eq!(
+        html_to_markdown("<p>$x_1$ costs $5 and *x*</p>"),
+        "$x_1$ costs $5 and \\*x\\*\n"
+    );
+}
+
+#[test]
+fn math_renders_as_mathml() {
+    assert_eq!(
+        latex_to_mathml("x^2", false),
+        "<math display=\"inline\"><semantics><msup><mi>x</mi><mn>2</mn></msup><annotation encoding=\"application/x-tex\">x^2</annotation></semantics></math>"
+    );
+    let html =
+        render_math("<p>Area $\\pi r^2$, cost $5</p><pre><code>echo $HOME $PATH</code></pre>");
+    assert!(html.starts_with("<p>Area <math display=\"inline\"><semantics><mrow><mi>π</mi><msup><mi>r</mi><mn>2</mn></msup></mrow>"));
+    assert!(html.ends_with(", cost $5</p><pre><code>echo $HOME $PATH</code></pre>"));
+
+    let fraction = latex_to_mathml("\\frac12 + \\sqrt[3]{x}", true);
+    assert!(fraction.contains(
+        "<mfrac><mn>1</mn><mn>2</mn></mfrac><mo>+</mo><mroot><mi>x</mi><mn>3</mn></mroot>"
+    ));
+    let sum = latex_to_mathml("\\sum_{i=0}^n i", true);
+    assert!(sum.contains("<munderover><mo largeop=\"true\">∑</mo>"));
+    let matrix = latex_to_mathml("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}", true);
+    assert!(matrix.contains("<mo fence=\"true\">(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable><mo fence=\"true\">)</mo>"));
+    assert!(
+        latex_to_mathml("a < \\unknown", false).contains("<mo>&lt;</mo><mtext>\\unknown</mtext>")
+    );
+}
+
+#[test]
+fn diagram_blocks_are_found_and_replaced() {
+    let html =
+        "<p>Flow</p><pre><code class=\"language-mermaid\">graph TD\n  A --&gt; B</code></pre>\
+                <pre><code class=\"language-rust\">fn main() {}</code></pre>\
+                <pre><code class=\"language-plantuml\">@startuml\nA -&gt; B\n@enduml</code></pre>";
+    assert_eq!(
+        find_diagrams(html),
+        vec![
+            Diagram {
+                language: "mermaid".to_string(),
+                source: "graph TD\n  A --> B".to_string(),
+            },
+            Diagram {
+                language: "plantuml".to_string(),
+                source: "@startuml\nA -> B\n@enduml".to_string(),
+            },
+        ]
+    );
+
+    let replaced = replace_diagrams(html, |d| {
+        (d.language == "mermaid").then(|| "<svg></svg>".to_string())
+    });
+    assert!(replaced.starts_with(
+        "<p>Flow</p><figure class=\"diagram\" data-language=\"mermaid\"><svg></svg></figure><pre><code class=\"language-rust\">"
+    ));
+    assert!(replaced.ends_with(
+        "<pre><code class=\"language-plantuml\">@startuml\nA -&gt; B\n@enduml</code></pre>"
+    ));
+}
+
+#[test]
+fn print_html_strips_editor_markup() {
+    let html = "<ul data-type=\"taskList\"><li data-type=\"taskItem\" data-checked=\"true\">\
+                <label><input type=\"checkbox\" checked=\"checked\"><span></span></label>\
+                <div><p>Done</p></div></li></ul>\
+                <p><img src=\"asset://localhost/a.png\" alt=\"Plot\"><img src=\"gone.png\" alt=\"Lost\">\
+                <a href=\"beck://note/1\">Other note</a> <a href=\"https://example.com\">site</a></p>\
+                <video src=\"clip.mp4\"></video>";
+    let printed = print_html(html, |url| match url {
+        "asset://localhost/a.png" => Some("https://host/api/assets/1".to_string()),
+        url if url.starts_with("https://") => Some(url.to_string()),
+        _ => None,
+    });
+    assert_eq!(
+        printed,
+        "<ul class=\"task-list\"><li class=\"task done\"><span class=\"task-box\">☑ </span>\
+         <div><p>Done</p></div></li></ul>\
+         <p><img src=\"https://host/api/assets/1\" alt=\"Plot\"><span class=\"missing-asset\">[image: Lost]</span>\
+         Other note <a href=\"https://example.com\">site</a></p>\
+         <span class=\"missing-asset\">[video]</span>"
+    );
+}
+
+#[test]
+fn backlink_sections() {
+    let links = vec![
+        Backlink {
+            title: "Plan [draft]".to_string(),
+            href: format!("{}.md", export_file_name("Plan [draft]")),
+        },
+        Backlink {
+            title: "Q&A".to_string(),
+            href: format!("{}.md", export_file_name("Q/A: notes")),
+        },
+    ];
+    assert_eq!(
+        backlinks_markdown(&links),
+        "\n## Linked from\n\n- [Plan \\[draft\\]](Plan%20[draft].md)\n- [Q&A](Q-A-%20notes.md)\n"
+    );
+    assert_eq!(
+        backlinks_html(&links[1..]),
+        "<section class=\"backlinks\"><h2>Linked from</h2><ul><li><a href=\"Q-A-%20notes.md\">Q&amp;A</a></li></ul></section>"
+    );
+    assert_eq!(backlinks_markdown(&[]), "");
+}
+
+#[test]
+fn redaction_replaces_patterns() {
+    let redactor = Redactor::new(&[
+        RedactionRule {
+            preset: Some("email".to_string()),
+            ..Default::default()
+        },
+        RedactionRule {
+            pattern: Some(r"Project \w+".to_string()),
+            replacement: Some("Project X".to_string()),
+            ..Default::default()
+        },
+    ])
+    .unwrap();
+    assert_eq!(
+        redactor.redact_html(
+            "<p>Ask <a href=\"mailto:ann@example.com\" class=\"link\">ann@example.com</a> about Project Falcon</p>"
+        ),
+        "<p>Ask <a href=\"mailto:[redacted]\" class=\"link\">[redacted]</a> about Project X</p>"
+    );
+    assert_eq!(
+        redactor.redact_text("Project Falcon notes"),
+        "Project X notes"
+    );
+    assert!(Redactor::new(&[RedactionRule::default()]).is_err());
+    assert!(Redactor::new(&[RedactionRule {
+        pattern: Some("(".to_string()),
+        ..Default::default()
+    }])
+    .is_err());
+}
+
+#[test]
+fn redaction_removes_tagged_blocks() {
+    let redactor = Redactor::new(&[RedactionRule {
+        tag: Some("#private".to_string()),
+        ..Default::default()
+    }])
+    .unwrap();
+    assert_eq!(
+        redactor.redact_html(
+            "<h2>Plan</h2><p>Salary talk #Private</p><p>Public #privateer</p>\
+             <ul><li><p>keep</p></li><li><p>drop #private</p></li></ul>\
+             <ul><li><p>#private</p></li></ul>"
+        ),
+        "<h2>Plan</h2><p>Public #privateer</p><ul><li><p>keep</p></li></ul>"
+    );
+    let unchanged = "<p>Nothing to hide</p><p></p>";
+    assert_eq!(redactor.redact_html(unchanged), unchanged);
+}
+
+#[test]
+fn front_matter_is_split_off() {
+    let (meta, body) = split_front_matter(concat!(
+        "---\n",
+        "title: \"Weekly: notes\"\n",
+        "folder: Team/Weekly\n",
+        "author: ci\n",
+        "tags:\n",
+        "  - release\n",
+        "  - '#ci'\n",
+        "---\n",
+        "# Heading\n"
+    ));
+    assert_eq!(
+        meta,
+        FrontMatter {
+            title: Some("Weekly: notes".to_string()),
+            folder: Some("Team/Weekly".to_string()),
+            tags: vec!["release".to_string(), "ci".to_string()],
+        }
+    );
+    assert_eq!(body, "# Heading\n");
+
+    let (meta, _) = split_front_matter("---\ntags: [a, \"b c\"]\n...\nText");
+    assert_eq!(meta.tags, ["a", "b-c"]);
+    assert_eq!(tags_html(&meta.tags), "<p>#a #b-c</p>");
+
+    // A rule without a closing fence isn't front matter
+    let text = "---\ntitle: no\n\nText\n";
+    assert_eq!(split_front_matter(text), (FrontMatter::default(), text));
+}
@@ -0,0 +1,47 @@
+use beck_markdown::{replace_content, seed_state};
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Transact, Update};
+
+/// A document loaded from an encoded state, with its `content` fragment's XML
+fn load(state: &[u8]) -> (Doc, String) {
+    let doc = Doc::new();
+    let fragment = doc.get_or_insert_xml_fragment("content");
+    doc.transact_mut()
+        .apply_update(Update::decode_v1(state).unwrap());
+    let xml = fragment.get_string(&doc.transact());
+    (doc, xml)
+}
+
+#[test]
+fn seeds_editor_layout() {
+    let (state, _) = seed_state("<h1>Title</h1><p>Some <strong>bold</strong> text</p>");
+    let (_, xml) = load(&state);
+    assert!(xml.starts_with("<heading level=\"1\">Title</heading><paragraph>"));
+    assert!(xml.contains("<bold>bold</bold>"));
+}
+
+#[test]
+fn replaced_content_merges_without_duplicating() {
+    let (seed, seed_sv) = seed_state("<p>Old text</p>");
+
+    // One device rewrites the note as HTML
+    let (doc, _) = load(&seed);
+    let fragment = doc.get_or_insert_xml_fragment("content");
+    {
+        let mut txn = doc.transact_mut();
+        replace_content(&mut txn, &fragment, "<p>New text</p>");
+    }
+    let edit = doc
+        .transact()
+        .encode_diff_v1(&StateVector::decode_v1(&seed_sv).unwrap());
+
+    // Another device with the old document merges the edit
+    let (other, _) = load(&seed);
+    other
+        .transact_mut()
+        .apply_update(Update::decode_v1(&edit).unwrap());
+    let xml = other
+        .get_or_insert_xml_fragment("content")
+        .get_string(&other.transact());
+    assert_eq!(xml, "<paragraph>New text</paragraph>");
+}
@@ -0,0 +1,2 @@
+-- Composite index for keyset pagination of the notes change feed
+CREATE INDEX IF NOT EXISTS idx_notes_updated_at_id ON notes (updated_at, id);
@@ -0,0 +1,35 @@
+-- Kanban boards whose cards are notes.
+-- No foreign keys between these tables: sync may deliver a card before the
+-- column or note it references.
+
+CREATE TABLE IF NOT EXISTS boards (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    folder_id UUID NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    is_deleted BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS board_columns (
+    id UUID PRIMARY KEY,
+    board_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    position DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    is_deleted BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS board_cards (
+    id UUID PRIMARY KEY,
+    board_id UUID NOT NULL,
+    column_id UUID NOT NULL,
+    note_id UUID NOT NULL,
+    position DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    is_deleted BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS idx_boards_updated_at ON boards (updated_at);
+CREATE INDEX IF NOT EXISTS idx_board_columns_updated_at ON board_columns (updated_at);
+CREATE INDEX IF NOT EXISTS idx_board_cards_updated_at ON board_cards (updated_at);
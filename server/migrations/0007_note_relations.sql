@@ -0,0 +1,16 @@
+-- Typed relations between notes (references, blocks, part_of).
+-- Identified by the (source, target, kind) triple so the same edge created on
+-- two devices converges to one row.
+
+CREATE TABLE IF NOT EXISTS note_relations (
+    source_note_id UUID NOT NULL,
+    target_note_id UUID NOT NULL,
+    kind TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    is_deleted BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (source_note_id, target_note_id, kind)
+);
+
+CREATE INDEX IF NOT EXISTS idx_note_relations_target ON note_relations (target_note_id);
+CREATE INDEX IF NOT EXISTS idx_note_relations_updated_at ON note_relations (updated_at);
@@ -0,0 +1,13 @@
+-- Files uploaded to the server (images, audio, other attachments).
+-- The bytes live on disk under ASSETS_DIR, named by id.
+
+CREATE TABLE IF NOT EXISTS assets (
+    id UUID PRIMARY KEY,
+    note_id UUID,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_assets_note_id ON assets (note_id);
@@ -0,0 +1,11 @@
+-- Resized copies of uploaded images, stored on disk as "<asset id>_<variant>".
+
+CREATE TABLE IF NOT EXISTS asset_variants (
+    asset_id UUID NOT NULL REFERENCES assets(id) ON DELETE CASCADE,
+    variant TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (asset_id, variant)
+);
@@ -0,0 +1,17 @@
+-- Resumable uploads in progress. Received bytes are appended to
+-- "<ASSETS_DIR>/uploads/<id>.part" until the session is completed.
+
+CREATE TABLE IF NOT EXISTS upload_sessions (
+    id UUID PRIMARY KEY,
+    note_id UUID,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    total_bytes BIGINT NOT NULL,
+    received_bytes BIGINT NOT NULL DEFAULT 0,
+    sha256 TEXT,
+    keep_location BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_sessions_updated_at ON upload_sessions (updated_at);
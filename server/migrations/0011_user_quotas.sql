@@ -0,0 +1,19 @@
+-- Per-user storage quotas. Notes and assets record the user who created them
+-- so usage can be counted; rows written before this migration have no owner
+-- and count against nobody.
+
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS owner TEXT;
+ALTER TABLE assets ADD COLUMN IF NOT EXISTS owner TEXT;
+ALTER TABLE upload_sessions ADD COLUMN IF NOT EXISTS owner TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_notes_owner ON notes (owner);
+CREATE INDEX IF NOT EXISTS idx_assets_owner ON assets (owner);
+
+-- NULL limits fall back to the server defaults; unlimited overrides both
+CREATE TABLE IF NOT EXISTS user_quotas (
+    username TEXT PRIMARY KEY,
+    notes_bytes_limit BIGINT,
+    assets_bytes_limit BIGINT,
+    unlimited BOOLEAN NOT NULL DEFAULT false,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,85 @@
+-- Server-side change feed: every accepted change to a synced row appends an
+-- entry with a global sequence number, mirroring the desktop oplog. Triggers
+-- record changes, so every write path (REST, sync, WebSocket, import) feeds it
+-- and LWW upserts that lose (no row updated) do not.
+--
+-- Appends take a transaction-scoped advisory lock, so a writer only draws a
+-- sequence number once every earlier writer has committed. Sequence order is
+-- then commit order, and a reader resuming after a sequence number can never
+-- miss a change that commits later with a smaller one.
+
+CREATE TABLE IF NOT EXISTS changes (
+    seq BIGSERIAL PRIMARY KEY,
+    -- "note", "folder", "board", "board_column", "board_card" or "relation"
+    entity TEXT NOT NULL,
+    -- Row id; relations use "source|target|kind"
+    entity_id TEXT NOT NULL,
+    -- "upsert" or "delete"
+    op TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_changes_entity_seq ON changes (entity, seq);
+
+CREATE OR REPLACE FUNCTION record_change() RETURNS trigger AS $$
+DECLARE
+    row_data RECORD;
+    row_id TEXT;
+    row_op TEXT;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        row_data := OLD;
+        row_op := 'delete';
+    ELSE
+        IF TG_OP = 'UPDATE' AND OLD IS NOT DISTINCT FROM NEW THEN
+            RETURN NULL;
+        END IF;
+        row_data := NEW;
+        row_op := CASE WHEN NEW.is_deleted THEN 'delete' ELSE 'upsert' END;
+    END IF;
+
+    IF TG_TABLE_NAME = 'note_relations' THEN
+        row_id := row_data.source_note_id::text || '|' || row_data.target_note_id::text || '|' || row_data.kind;
+    ELSE
+        row_id := row_data.id::text;
+    END IF;
+
+    PERFORM pg_advisory_xact_lock(hashtext('changes'));
+    INSERT INTO changes (entity, entity_id, op) VALUES (TG_ARGV[0], row_id, row_op);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS notes_record_change ON notes;
+CREATE TRIGGER notes_record_change AFTER INSERT OR UPDATE OR DELETE ON notes
+    FOR EACH ROW EXECUTE FUNCTION record_change('note');
+DROP TRIGGER IF EXISTS folders_record_change ON folders;
+CREATE TRIGGER folders_record_change AFTER INSERT OR UPDATE OR DELETE ON folders
+    FOR EACH ROW EXECUTE FUNCTION record_change('folder');
+DROP TRIGGER IF EXISTS boards_record_change ON boards;
+CREATE TRIGGER boards_record_change AFTER INSERT OR UPDATE OR DELETE ON boards
+    FOR EACH ROW EXECUTE FUNCTION record_change('board');
+DROP TRIGGER IF EXISTS board_columns_record_change ON board_columns;
+CREATE TRIGGER board_columns_record_change AFTER INSERT OR UPDATE OR DELETE ON board_columns
+    FOR EACH ROW EXECUTE FUNCTION record_change('board_column');
+DROP TRIGGER IF EXISTS board_cards_record_change ON board_cards;
+CREATE TRIGGER board_cards_record_change AFTER INSERT OR UPDATE OR DELETE ON board_cards
+    FOR EACH ROW EXECUTE FUNCTION record_change('board_card');
+DROP TRIGGER IF EXISTS note_relations_record_change ON note_relations;
+CREATE TRIGGER note_relations_record_change AFTER INSERT OR UPDATE OR DELETE ON note_relations
+    FOR EACH ROW EXECUTE FUNCTION record_change('relation');
+
+-- Seed the feed with the current state so reading from seq 0 sees every row
+INSERT INTO changes (entity, entity_id, op)
+SELECT 'folder', id::text, CASE WHEN is_deleted THEN 'delete' ELSE 'upsert' END FROM folders
+UNION ALL
+SELECT 'note', id::text, CASE WHEN is_deleted THEN 'delete' ELSE 'upsert' END FROM notes
+UNION ALL
+SELECT 'board', id::text, CASE WHEN is_deleted THEN 'delete' ELSE 'upsert' END FROM boards
+UNION ALL
+SELECT 'board_column', id::text, CASE WHEN is_deleted THEN 'delete' ELSE 'upsert' END FROM board_columns
+UNION ALL
+SELECT 'board_card', id::text, CASE WHEN is_deleted THEN 'delete' ELSE 'upsert' END FROM board_cards
+UNION ALL
+SELECT 'relation', source_note_id::text || '|' || target_note_id::text || '|' || kind,
+       CASE WHEN is_deleted THEN 'delete' ELSE 'upsert' END FROM note_relations;
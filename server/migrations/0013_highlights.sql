@@ -0,0 +1,23 @@
+-- Highlighted passages extracted from note content. The index is refreshed
+-- from the change feed: `highlight_index_state.last_seq` is the last note
+-- change it has caught up with.
+
+CREATE TABLE IF NOT EXISTS highlights (
+    id BIGSERIAL PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    -- Zero-based index of the highlight within the note
+    position INTEGER NOT NULL,
+    -- Lowercased CSS color; NULL for plain highlights
+    color TEXT,
+    text TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_highlights_note_id ON highlights (note_id);
+CREATE INDEX IF NOT EXISTS idx_highlights_color ON highlights (color);
+
+CREATE TABLE IF NOT EXISTS highlight_index_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_seq BIGINT NOT NULL
+);
+
+INSERT INTO highlight_index_state (id, last_seq) VALUES (1, 0) ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,16 @@
+-- Asset files stored once per distinct content, on disk under ASSETS_DIR as
+-- "blobs/<sha256>" (variants as "blobs/<sha256>_<variant>"). Each row in
+-- `assets` referencing a blob counts towards `ref_count`; the blob is removed
+-- with its last reference. Assets from before this migration keep
+-- `blob_hash` NULL and their files named by asset id.
+
+CREATE TABLE IF NOT EXISTS asset_blobs (
+    hash TEXT PRIMARY KEY,
+    size_bytes BIGINT NOT NULL,
+    ref_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+ALTER TABLE assets ADD COLUMN IF NOT EXISTS blob_hash TEXT REFERENCES asset_blobs(hash);
+
+CREATE INDEX IF NOT EXISTS idx_assets_blob_hash ON assets (blob_hash);
@@ -0,0 +1,32 @@
+-- Proposed changes to notes ("merge requests"). A proposal carries the full
+-- title and content it would write and the note version (`updated_at`) it was
+-- made against; it is applied only while the note is still at that version.
+
+CREATE TABLE IF NOT EXISTS change_requests (
+    id UUID PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    base_updated_at TIMESTAMPTZ NOT NULL,
+    -- "open", "applied", "rejected" or "withdrawn"
+    status TEXT NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'applied', 'rejected', 'withdrawn')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    resolved_by TEXT,
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_change_requests_note_id ON change_requests (note_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_change_requests_status ON change_requests (status, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS change_request_comments (
+    id BIGSERIAL PRIMARY KEY,
+    change_request_id UUID NOT NULL REFERENCES change_requests(id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_change_request_comments_request ON change_request_comments (change_request_id, created_at);
@@ -0,0 +1,3 @@
+-- Activity statistics aggregate the change feed by time.
+
+CREATE INDEX IF NOT EXISTS idx_changes_recorded_at ON changes (recorded_at);
@@ -0,0 +1,14 @@
+-- Notes and folders a user follows. Watching a folder covers the notes
+-- directly in it.
+
+CREATE TABLE IF NOT EXISTS watches (
+    id BIGSERIAL PRIMARY KEY,
+    username TEXT NOT NULL,
+    note_id UUID REFERENCES notes(id) ON DELETE CASCADE,
+    folder_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK ((note_id IS NULL) <> (folder_id IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_watches_user_note ON watches (username, note_id) WHERE note_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_watches_user_folder ON watches (username, folder_id) WHERE folder_id IS NOT NULL;
@@ -0,0 +1,22 @@
+-- Note templates shared by everyone on the server (meeting notes, incident
+-- reports, ...). Deletion is a soft delete so clients pulling incrementally
+-- see it.
+
+CREATE TABLE IF NOT EXISTS templates (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    -- Note HTML with `{{ ... }}` placeholders, filled in by clients
+    content TEXT NOT NULL,
+    updated_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    is_deleted BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_templates_name ON templates (lower(name)) WHERE NOT is_deleted;
+CREATE INDEX IF NOT EXISTS idx_templates_updated_at ON templates (updated_at);
+
+DROP TRIGGER IF EXISTS templates_record_change ON templates;
+CREATE TRIGGER templates_record_change AFTER INSERT OR UPDATE OR DELETE ON templates
+    FOR EACH ROW EXECUTE FUNCTION record_change('template');
@@ -0,0 +1,104 @@
+-- Per-note history for the History panel. Triggers record note revisions and
+-- CRDT snapshots so every write path (REST, sync, WebSocket, import) is
+-- covered. Handlers that know who is writing set `beck.actor` for their
+-- transaction; otherwise the actor is unknown. History starts with this
+-- migration: notes written before it have none.
+
+CREATE TABLE IF NOT EXISTS note_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    -- "created", "updated", "deleted" or "restored"
+    kind TEXT NOT NULL,
+    -- What changed: any of "title", "content", "folder" and "canvas"
+    fields TEXT[] NOT NULL DEFAULT '{}',
+    -- The note after the change
+    title TEXT NOT NULL,
+    folder_id UUID,
+    content_length INTEGER NOT NULL,
+    actor TEXT,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_note_revisions_note ON note_revisions (note_id, recorded_at DESC);
+
+-- One snapshot per editing session: CRDT writes within 10 minutes of a
+-- snapshot's start update it instead of adding another. Only the latest 50
+-- snapshots of a note are kept.
+CREATE TABLE IF NOT EXISTS crdt_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    ydoc_state BYTEA NOT NULL,
+    -- Everyone known to have written during the session
+    actors TEXT[] NOT NULL DEFAULT '{}',
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_crdt_snapshots_note ON crdt_snapshots (note_id, recorded_at DESC);
+
+CREATE OR REPLACE FUNCTION record_note_revision() RETURNS trigger AS $$
+DECLARE
+    changed TEXT[] := '{}';
+    revision_kind TEXT;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        revision_kind := CASE WHEN NEW.is_deleted THEN 'deleted' ELSE 'created' END;
+    ELSE
+        IF NEW.title IS DISTINCT FROM OLD.title THEN changed := array_append(changed, 'title'); END IF;
+        IF NEW.content IS DISTINCT FROM OLD.content THEN changed := array_append(changed, 'content'); END IF;
+        IF NEW.folder_id IS DISTINCT FROM OLD.folder_id THEN changed := array_append(changed, 'folder'); END IF;
+        IF NEW.is_canvas IS DISTINCT FROM OLD.is_canvas THEN changed := array_append(changed, 'canvas'); END IF;
+        IF NEW.is_deleted AND NOT OLD.is_deleted THEN
+            revision_kind := 'deleted';
+        ELSIF OLD.is_deleted AND NOT NEW.is_deleted THEN
+            revision_kind := 'restored';
+        ELSIF cardinality(changed) > 0 THEN
+            revision_kind := 'updated';
+        ELSE
+            -- Only the timestamp moved
+            RETURN NULL;
+        END IF;
+    END IF;
+
+    INSERT INTO note_revisions (note_id, kind, fields, title, folder_id, content_length, actor)
+    VALUES (NEW.id, revision_kind, changed, NEW.title, NEW.folder_id, length(NEW.content),
+            NULLIF(current_setting('beck.actor', true), ''));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS notes_record_revision ON notes;
+CREATE TRIGGER notes_record_revision AFTER INSERT OR UPDATE ON notes
+    FOR EACH ROW EXECUTE FUNCTION record_note_revision();
+
+CREATE OR REPLACE FUNCTION record_crdt_snapshot() RETURNS trigger AS $$
+DECLARE
+    session_id BIGINT;
+    writer TEXT := NULLIF(current_setting('beck.actor', true), '');
+BEGIN
+    SELECT id INTO session_id FROM crdt_snapshots
+    WHERE note_id = NEW.note_id AND started_at > now() - interval '10 minutes'
+    ORDER BY started_at DESC
+    LIMIT 1;
+
+    IF session_id IS NULL THEN
+        INSERT INTO crdt_snapshots (note_id, ydoc_state, actors)
+        VALUES (NEW.note_id, NEW.ydoc_state, CASE WHEN writer IS NULL THEN '{}' ELSE ARRAY[writer] END);
+        DELETE FROM crdt_snapshots
+        WHERE note_id = NEW.note_id AND id NOT IN (
+            SELECT id FROM crdt_snapshots WHERE note_id = NEW.note_id ORDER BY started_at DESC LIMIT 50
+        );
+    ELSE
+        UPDATE crdt_snapshots SET
+            ydoc_state = NEW.ydoc_state,
+            actors = CASE WHEN writer IS NULL OR writer = ANY(actors) THEN actors ELSE array_append(actors, writer) END,
+            recorded_at = now()
+        WHERE id = session_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS crdt_states_record_snapshot ON crdt_states;
+CREATE TRIGGER crdt_states_record_snapshot AFTER INSERT OR UPDATE ON crdt_states
+    FOR EACH ROW EXECUTE FUNCTION record_crdt_snapshot();
@@ -0,0 +1,14 @@
+-- Each user's own arrangement of notes: pins and manual order within a
+-- folder. Kept per user so one collaborator reordering a shared folder doesn't
+-- rearrange everyone else's sidebar.
+
+CREATE TABLE IF NOT EXISTS user_note_layout (
+    username TEXT NOT NULL,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    pinned BOOLEAN NOT NULL DEFAULT false,
+    -- Manual position among the notes of the note's folder; NULL keeps the
+    -- client's default order
+    position INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (username, note_id)
+);
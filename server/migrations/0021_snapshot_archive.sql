@@ -0,0 +1,45 @@
+-- Cold storage for old CRDT snapshots. The snapshot trigger no longer drops
+-- snapshots beyond a note's latest 50: the server's archiver moves everything
+-- past the latest `SNAPSHOT_HOT_LIMIT` of each note here, gzipped, so
+-- `crdt_snapshots` stays small while deep history stays available through
+-- the history API.
+
+CREATE TABLE IF NOT EXISTS crdt_snapshot_archive (
+    -- The snapshot's id in crdt_snapshots, kept so links to it stay valid
+    id BIGINT PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    -- Gzipped ydoc_state
+    ydoc_state_gz BYTEA NOT NULL,
+    -- Uncompressed size, in bytes
+    size INTEGER NOT NULL,
+    actors TEXT[] NOT NULL DEFAULT '{}',
+    started_at TIMESTAMPTZ NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_crdt_snapshot_archive_note ON crdt_snapshot_archive (note_id, recorded_at DESC);
+
+CREATE OR REPLACE FUNCTION record_crdt_snapshot() RETURNS trigger AS $$
+DECLARE
+    session_id BIGINT;
+    writer TEXT := NULLIF(current_setting('beck.actor', true), '');
+BEGIN
+    SELECT id INTO session_id FROM crdt_snapshots
+    WHERE note_id = NEW.note_id AND started_at > now() - interval '10 minutes'
+    ORDER BY started_at DESC
+    LIMIT 1;
+
+    IF session_id IS NULL THEN
+        INSERT INTO crdt_snapshots (note_id, ydoc_state, actors)
+        VALUES (NEW.note_id, NEW.ydoc_state, CASE WHEN writer IS NULL THEN '{}' ELSE ARRAY[writer] END);
+    ELSE
+        UPDATE crdt_snapshots SET
+            ydoc_state = NEW.ydoc_state,
+            actors = CASE WHEN writer IS NULL OR writer = ANY(actors) THEN actors ELSE array_append(actors, writer) END,
+            recorded_at = now()
+        WHERE id = session_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
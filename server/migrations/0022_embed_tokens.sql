@@ -0,0 +1,17 @@
+-- Share tokens for read-only embeds of a single note (`GET /embed/<token>`).
+-- Only a hash of each token is stored; the token itself is shown once, when
+-- it is created. Deleting a row revokes the token.
+
+CREATE TABLE IF NOT EXISTS embed_tokens (
+    id UUID PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    -- SHA-256 of the token, hex
+    token_hash TEXT NOT NULL UNIQUE,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- NULL never expires
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_embed_tokens_note ON embed_tokens (note_id);
@@ -0,0 +1,54 @@
+-- Workspace retention policy, for teams with compliance requirements.
+-- A single row, edited by admins through `PUT /api/admin/retention`.
+-- Triggers enforce it on hard deletion of notes and assets, whoever deletes:
+-- a legal hold refuses every deletion, and a minimum retention refuses
+-- deleting notes changed (or trashed) and assets uploaded less than
+-- `min_retention_days` ago. Rows that cascade from a note, such as its
+-- history, go with it only when the note itself may go.
+
+CREATE TABLE IF NOT EXISTS retention_policy (
+    id BOOLEAN PRIMARY KEY DEFAULT true CHECK (id),
+    min_retention_days INTEGER NOT NULL DEFAULT 0 CHECK (min_retention_days >= 0),
+    legal_hold BOOLEAN NOT NULL DEFAULT false,
+    legal_hold_reason TEXT,
+    updated_by TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO retention_policy (id) VALUES (true) ON CONFLICT DO NOTHING;
+
+-- Raises SQLSTATE BK423, which the API reports as 423 Locked
+CREATE OR REPLACE FUNCTION enforce_retention() RETURNS trigger AS $$
+DECLARE
+    policy retention_policy%ROWTYPE;
+    kept_since TIMESTAMPTZ;
+BEGIN
+    SELECT * INTO policy FROM retention_policy WHERE id;
+    IF NOT FOUND THEN
+        RETURN OLD;
+    END IF;
+    IF policy.legal_hold THEN
+        RAISE EXCEPTION 'legal hold: % % cannot be deleted', TG_TABLE_NAME, OLD.id
+            USING ERRCODE = 'BK423';
+    END IF;
+    -- Separate statements: OLD only has the columns of its own table
+    IF TG_TABLE_NAME = 'notes' THEN
+        kept_since := OLD.updated_at;
+    ELSE
+        kept_since := OLD.created_at;
+    END IF;
+    IF kept_since > now() - make_interval(days => policy.min_retention_days) THEN
+        RAISE EXCEPTION 'retention: % % is kept for % days', TG_TABLE_NAME, OLD.id, policy.min_retention_days
+            USING ERRCODE = 'BK423';
+    END IF;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS notes_enforce_retention ON notes;
+CREATE TRIGGER notes_enforce_retention BEFORE DELETE ON notes
+    FOR EACH ROW EXECUTE FUNCTION enforce_retention();
+
+DROP TRIGGER IF EXISTS assets_enforce_retention ON assets;
+CREATE TRIGGER assets_enforce_retention BEFORE DELETE ON assets
+    FOR EACH ROW EXECUTE FUNCTION enforce_retention();
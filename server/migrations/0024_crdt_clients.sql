@@ -0,0 +1,16 @@
+-- Which user each Yjs client id belongs to, for attributing CRDT content.
+-- Clients register their stable id (`POST /api/crdt/clients`); the WebSocket
+-- also records the sender of an update made by a single unknown client.
+-- A client id belongs to the first user it was seen with.
+
+CREATE TABLE IF NOT EXISTS crdt_clients (
+    -- Yjs client ids are unsigned 32-bit integers
+    client_id BIGINT PRIMARY KEY CHECK (client_id >= 0),
+    username TEXT NOT NULL,
+    -- Free-form device label given at registration
+    device TEXT,
+    registered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_crdt_clients_username ON crdt_clients (username);
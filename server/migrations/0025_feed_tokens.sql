@@ -0,0 +1,17 @@
+-- Tokens for per-folder Atom and JSON feeds (`GET /feeds/<token>/atom`,
+-- `/feeds/<token>/json`). As with embed tokens, only a hash is stored and
+-- deleting a row revokes the feed.
+
+CREATE TABLE IF NOT EXISTS feed_tokens (
+    id UUID PRIMARY KEY,
+    folder_id UUID NOT NULL REFERENCES folders(id) ON DELETE CASCADE,
+    -- SHA-256 of the token, hex
+    token_hash TEXT NOT NULL UNIQUE,
+    -- List notes in subfolders too
+    include_subfolders BOOLEAN NOT NULL DEFAULT true,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_feed_tokens_folder ON feed_tokens (folder_id);
@@ -0,0 +1,28 @@
+-- The server never hard-deletes notes (deleting one only trashes it), so the
+-- retention trigger on notes guarded no real path, and it measured the
+-- minimum retention from `updated_at`, which clients set. Notes are left to
+-- the clients that purge them, which follow the policy from
+-- `GET /api/retention`; the server enforces it on assets only, whose
+-- `created_at` it sets itself.
+
+DROP TRIGGER IF EXISTS notes_enforce_retention ON notes;
+
+CREATE OR REPLACE FUNCTION enforce_retention() RETURNS trigger AS $$
+DECLARE
+    policy retention_policy%ROWTYPE;
+BEGIN
+    SELECT * INTO policy FROM retention_policy WHERE id;
+    IF NOT FOUND THEN
+        RETURN OLD;
+    END IF;
+    IF policy.legal_hold THEN
+        RAISE EXCEPTION 'legal hold: % % cannot be deleted', TG_TABLE_NAME, OLD.id
+            USING ERRCODE = 'BK423';
+    END IF;
+    IF OLD.created_at > now() - make_interval(days => policy.min_retention_days) THEN
+        RAISE EXCEPTION 'retention: % % is kept for % days', TG_TABLE_NAME, OLD.id, policy.min_retention_days
+            USING ERRCODE = 'BK423';
+    END IF;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
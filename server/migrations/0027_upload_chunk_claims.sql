@@ -0,0 +1,7 @@
+-- A chunk being appended to a resumable upload claims its session, so other
+-- chunks for the same upload are refused without holding a row lock while
+-- the body streams in. The writer refreshes its claim as data arrives; a
+-- claim that stopped being refreshed (the writer died mid-chunk) lapses.
+
+ALTER TABLE upload_sessions ADD COLUMN IF NOT EXISTS chunk_claim UUID;
+ALTER TABLE upload_sessions ADD COLUMN IF NOT EXISTS chunk_claimed_at TIMESTAMPTZ;
@@ -0,0 +1,108 @@
+//! Reconciling a client's asset files with the server's in one request.
+//!
+//! Asset files are stored once per distinct content, named by SHA-256 (see
+//! `blobs`). A client sends the hashes of the files it has to
+//! `POST /api/assets/manifest` and gets back the ones the server lacks, which
+//! it should upload (`POST /api/assets?sha256=` links any that turn up in the
+//! meantime without sending them), and the server's files it lacks, with the
+//! assets using them, which it can download. Nothing is listed file by file.
+//!
+//! JPEGs are stored without their location data, so a client that hashes a
+//! JPEG as taken may see it both missing and extra; it should hash what it
+//! downloaded, not what it uploaded. Assets uploaded before files were
+//! deduplicated have no hash and are listed separately.
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, AppState};
+
+/// Most hashes accepted in one manifest
+const MAX_MANIFEST_ENTRIES: usize = 25_000;
+
+#[derive(Debug, Deserialize)]
+pub struct ManifestRequest {
+    /// Hex SHA-256 of every asset file the client has
+    pub hashes: Vec<String>,
+}
+
+/// Content the server has and the client doesn't
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExtraBlob {
+    pub hash: String,
+    pub size_bytes: i64,
+    /// Assets using the content, oldest first; any of them downloads it
+    pub asset_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestDiff {
+    /// Hashes the server lacks, in the order sent
+    pub missing: Vec<String>,
+    pub extra: Vec<ExtraBlob>,
+    /// Hashes both sides have
+    pub matched: usize,
+    /// Assets stored before deduplication, which can't be compared by hash
+    pub unhashed: Vec<Uuid>,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to compare asset manifest");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn is_sha256_hex(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// POST /api/assets/manifest
+///
+/// 400 for hashes that aren't hex SHA-256, 413 for more than
+/// `MAX_MANIFEST_ENTRIES` of them.
+pub async fn compare_manifest(
+    State(state): State<AppState>,
+    AuthUser(_user): AuthUser,
+    Json(request): Json<ManifestRequest>,
+) -> Result<Json<ManifestDiff>, StatusCode> {
+    if request.hashes.len() > MAX_MANIFEST_ENTRIES {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    if !request.hashes.iter().all(|hash| is_sha256_hex(hash)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut hashes: Vec<String> = request.hashes.iter().map(|hash| hash.to_ascii_lowercase()).collect();
+    let mut seen = std::collections::HashSet::new();
+    hashes.retain(|hash| seen.insert(hash.clone()));
+
+    let missing: Vec<String> = sqlx::query_scalar(
+        "SELECT c.hash FROM unnest($1::text[]) WITH ORDINALITY AS c(hash, position)
+         WHERE NOT EXISTS (SELECT 1 FROM asset_blobs b WHERE b.hash = c.hash)
+         ORDER BY c.position",
+    )
+    .bind(&hashes)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let extra = sqlx::query_as::<_, ExtraBlob>(
+        "SELECT b.hash, b.size_bytes, array_agg(a.id ORDER BY a.created_at, a.id) AS asset_ids
+         FROM asset_blobs b
+         JOIN assets a ON a.blob_hash = b.hash
+         LEFT JOIN unnest($1::text[]) AS c(hash) ON c.hash = b.hash
+         WHERE c.hash IS NULL
+         GROUP BY b.hash, b.size_bytes
+         ORDER BY b.hash",
+    )
+    .bind(&hashes)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let unhashed: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM assets WHERE blob_hash IS NULL ORDER BY created_at, id")
+        .fetch_all(&state.pool)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(ManifestDiff { matched: hashes.len() - missing.len(), missing, extra, unhashed }))
+}
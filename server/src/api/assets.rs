@@ -0,0 +1,578 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tokio::{fs, io::AsyncWriteExt};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+use uuid::Uuid;
+
+use super::{error::ApiError, retention::retention_error};
+use crate::{
+    auth::AuthUser,
+    blobs,
+    db::models::{Asset, AssetVariant},
+    images,
+    quota::{self, QuotaKind},
+    AppState,
+};
+
+/// Largest file accepted in a single request; bigger files go through a
+/// resumable upload session (see `uploads`)
+pub const MAX_UPLOAD_BYTES: u64 = 32 * 1024 * 1024;
+
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Types served inline besides audio and video: ones a browser displays
+/// without running anything in them. Anything else an uploader claims (HTML,
+/// SVG, scripts) is served as an attachment so it can't run on this origin.
+const INLINE_CONTENT_TYPES: &[&str] =
+    &["image/png", "image/jpeg", "image/gif", "image/webp", "image/avif", "image/bmp", "application/pdf"];
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub filename: Option<String>,
+    pub note_id: Option<Uuid>,
+    /// Keep GPS coordinates in JPEG metadata (stripped by default)
+    #[serde(default)]
+    pub keep_location: bool,
+    /// Hex SHA-256 of the file, to skip the upload if the server already has it
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    /// Ask the browser to save the file instead of displaying it
+    #[serde(default)]
+    pub download: bool,
+    /// Serve a resized variant ("thumbnail", "medium") instead of the original
+    pub variant: Option<String>,
+}
+
+/// Asset metadata with URLs for the original and its variants
+#[derive(Debug, Serialize)]
+pub struct AssetInfo {
+    #[serde(flatten)]
+    pub asset: Asset,
+    pub url: String,
+    pub variants: Vec<VariantInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VariantInfo {
+    pub name: String,
+    pub url: String,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub size_bytes: i64,
+}
+
+fn asset_info(asset: Asset, variants: Vec<AssetVariant>) -> AssetInfo {
+    let url = format!("/api/assets/{}", asset.id);
+    AssetInfo {
+        variants: variants
+            .into_iter()
+            .map(|v| VariantInfo {
+                url: format!("{}?variant={}", url, v.variant),
+                name: v.variant,
+                content_type: v.content_type,
+                width: v.width,
+                height: v.height,
+                size_bytes: v.size_bytes,
+            })
+            .collect(),
+        url,
+        asset,
+    }
+}
+
+/// Where an asset's file lives: its blob, or for assets stored before
+/// deduplication a file named by asset id
+pub fn asset_path(state: &AppState, asset_id: Uuid, blob_hash: Option<&str>) -> std::path::PathBuf {
+    match blob_hash {
+        Some(hash) => blobs::blob_path(&state.assets_dir, hash),
+        None => state.assets_dir.join(asset_id.to_string()),
+    }
+}
+
+pub fn variant_path(state: &AppState, asset_id: Uuid, blob_hash: Option<&str>, variant: &str) -> std::path::PathBuf {
+    match blob_hash {
+        Some(hash) => blobs::blob_variant_path(&state.assets_dir, hash, variant),
+        None => state.assets_dir.join(format!("{}_{}", asset_id, variant)),
+    }
+}
+
+/// POST /api/assets?filename=...&note_id=...&sha256=...
+///
+/// The request body is the raw file; its `Content-Type` is stored and served back.
+/// Images get thumbnail and medium variants, and GPS data is erased from JPEGs
+/// unless `keep_location=true`. When `sha256` names content the server already
+/// stores, the asset is created from it right away without reading the body.
+pub async fn upload_asset(
+    State(state): State<AppState>,
+    AuthUser(owner): AuthUser,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<AssetInfo>, ApiError> {
+    let id = Uuid::new_v4();
+    // Refuse early when the declared size already doesn't fit
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok());
+    if let Some(declared) = declared {
+        ensure_asset_room(&state, &owner, declared).await?;
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+        .to_string();
+    let filename = query
+        .filename
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| id.to_string());
+
+    let mut new = NewAsset {
+        id,
+        owner: Some(owner),
+        note_id: query.note_id,
+        filename,
+        content_type,
+        size_bytes: 0,
+        keep_location: query.keep_location,
+    };
+    if let Some(hash) = query.sha256.as_deref() {
+        let hash = hash.trim().to_ascii_lowercase();
+        if let Some(info) = link_existing(&state, &new, &hash).await? {
+            return Ok(Json(info));
+        }
+    }
+
+    // Stream to a temporary file so a failed upload never leaves a partial asset
+    let partial = state.assets_dir.join(format!("{}.part", id));
+    let size = match write_body(&partial, body).await {
+        Ok(size) => size,
+        Err(status) => {
+            let _ = fs::remove_file(&partial).await;
+            return Err(status.into());
+        }
+    };
+    if let Some(owner) = &new.owner {
+        if let Err(err) = ensure_asset_room(&state, owner, size as i64).await {
+            let _ = fs::remove_file(&partial).await;
+            return Err(err);
+        }
+    }
+
+    new.size_bytes = size as i64;
+    let info = store_asset(&state, new, &partial).await?;
+    Ok(Json(info))
+}
+
+async fn ensure_asset_room(state: &AppState, owner: &str, size: i64) -> Result<(), ApiError> {
+    let mut conn = state.pool.acquire().await.map_err(|err| {
+        tracing::error!(?err, "failed to acquire connection");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    quota::ensure_room(&mut conn, &state.quotas, owner, QuotaKind::Assets, size).await
+}
+
+/// A fully received upload waiting to become an asset
+pub struct NewAsset {
+    pub id: Uuid,
+    pub owner: Option<String>,
+    pub note_id: Option<Uuid>,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub keep_location: bool,
+}
+
+fn db_error(context: &'static str) -> impl Fn(sqlx::Error) -> StatusCode {
+    move |err| {
+        tracing::error!(?err, "{}", context);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+async fn insert_asset(conn: &mut PgConnection, new: &NewAsset, hash: &str, size_bytes: i64) -> Result<Asset, sqlx::Error> {
+    sqlx::query_as::<_, Asset>(
+        "INSERT INTO assets (id, note_id, filename, content_type, size_bytes, owner, blob_hash, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+         RETURNING id, note_id, filename, content_type, size_bytes, blob_hash, created_at",
+    )
+    .bind(new.id)
+    .bind(new.note_id)
+    .bind(&new.filename)
+    .bind(&new.content_type)
+    .bind(size_bytes)
+    .bind(&new.owner)
+    .bind(hash)
+    .fetch_one(&mut *conn)
+    .await
+}
+
+/// Give a new asset the variant records of another asset sharing its blob
+async fn copy_variants(conn: &mut PgConnection, asset_id: Uuid, hash: &str) -> Result<Vec<AssetVariant>, sqlx::Error> {
+    sqlx::query_as::<_, AssetVariant>(
+        "INSERT INTO asset_variants (asset_id, variant, content_type, width, height, size_bytes)
+         SELECT DISTINCT ON (v.variant) $1, v.variant, v.content_type, v.width, v.height, v.size_bytes
+         FROM asset_variants v JOIN assets a ON a.id = v.asset_id
+         WHERE a.blob_hash = $2 AND a.id <> $1
+         ORDER BY v.variant
+         RETURNING asset_id, variant, content_type, width, height, size_bytes",
+    )
+    .bind(asset_id)
+    .bind(hash)
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Create an asset from content already stored under `hash`, if there is any.
+/// Returns `None` when the content has to be uploaded.
+pub async fn link_existing(state: &AppState, new: &NewAsset, hash: &str) -> Result<Option<AssetInfo>, ApiError> {
+    // A JPEG is stored without its location data, so the hash of the file as
+    // uploaded can only match content stored with its location kept
+    if new.content_type.starts_with("image/jpeg") && !new.keep_location {
+        return Ok(None);
+    }
+
+    let mut tx = state.pool.begin().await.map_err(db_error("failed to open transaction"))?;
+    let Some(size_bytes) = blobs::acquire_existing(&mut tx, hash).await.map_err(db_error("failed to reference blob"))? else {
+        return Ok(None);
+    };
+    if let Some(owner) = &new.owner {
+        quota::ensure_room(&mut tx, &state.quotas, owner, QuotaKind::Assets, size_bytes).await?;
+    }
+    let asset = insert_asset(&mut tx, new, hash, size_bytes).await.map_err(db_error("failed to record asset"))?;
+    let variants = copy_variants(&mut tx, new.id, hash).await.map_err(db_error("failed to record image variants"))?;
+    tx.commit().await.map_err(db_error("failed to commit asset"))?;
+
+    Ok(Some(asset_info(asset, variants)))
+}
+
+/// Move a received file into the blob store (or drop it if the same content
+/// is already stored), generating image variants for new content, and record
+/// the asset
+pub async fn store_asset(state: &AppState, new: NewAsset, file: &std::path::Path) -> Result<AssetInfo, StatusCode> {
+    let processable = images::is_processable(&new.content_type) && new.size_bytes as usize <= images::MAX_PROCESS_BYTES;
+    if processable && !new.keep_location && new.content_type.starts_with("image/jpeg") {
+        strip_location(file).await;
+    }
+    let hash = blobs::sha256_file(file.to_path_buf()).await?;
+
+    // The blob row stays locked until commit, so an identical upload running
+    // at the same time waits and then reuses this one
+    let mut tx = state.pool.begin().await.map_err(db_error("failed to open transaction"))?;
+    let size_bytes = fs::metadata(file).await.map(|m| m.len() as i64).unwrap_or(new.size_bytes);
+    let created = blobs::acquire(&mut tx, &hash, size_bytes).await.map_err(db_error("failed to record blob"))?;
+
+    if !created {
+        let _ = fs::remove_file(file).await;
+        let asset = insert_asset(&mut tx, &new, &hash, size_bytes).await.map_err(db_error("failed to record asset"))?;
+        let variants = copy_variants(&mut tx, new.id, &hash).await.map_err(db_error("failed to record image variants"))?;
+        tx.commit().await.map_err(db_error("failed to commit asset"))?;
+        return Ok(asset_info(asset, variants));
+    }
+
+    let encoded = if processable { build_variants(file).await } else { Vec::new() };
+    fs::create_dir_all(blobs::blobs_dir(&state.assets_dir)).await.map_err(|err| {
+        tracing::error!(?err, "failed to create blob directory");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    fs::rename(file, blobs::blob_path(&state.assets_dir, &hash)).await.map_err(|err| {
+        tracing::error!(?err, "failed to store uploaded asset");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let asset = insert_asset(&mut tx, &new, &hash, size_bytes).await.map_err(db_error("failed to record asset"))?;
+
+    let mut variants = Vec::new();
+    for variant in encoded {
+        if let Err(err) = fs::write(blobs::blob_variant_path(&state.assets_dir, &hash, variant.name), &variant.bytes).await {
+            tracing::error!(?err, variant = variant.name, "failed to write image variant");
+            continue;
+        }
+        let record = sqlx::query_as::<_, AssetVariant>(
+            "INSERT INTO asset_variants (asset_id, variant, content_type, width, height, size_bytes)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING asset_id, variant, content_type, width, height, size_bytes",
+        )
+        .bind(new.id)
+        .bind(variant.name)
+        .bind(variant.content_type)
+        .bind(variant.width as i32)
+        .bind(variant.height as i32)
+        .bind(variant.bytes.len() as i64)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error("failed to record image variant"))?;
+        variants.push(record);
+    }
+    tx.commit().await.map_err(db_error("failed to commit asset"))?;
+
+    Ok(asset_info(asset, variants))
+}
+
+/// Erase GPS data from an uploaded JPEG in place
+async fn strip_location(path: &std::path::Path) {
+    let mut data = match fs::read(path).await {
+        Ok(data) => data,
+        Err(err) => {
+            tracing::error!(?err, "failed to read uploaded image");
+            return;
+        }
+    };
+    if images::strip_jpeg_gps(&mut data) {
+        if let Err(err) = fs::write(path, &data).await {
+            tracing::error!(?err, "failed to write image without location data");
+        }
+    }
+}
+
+/// Build the resized variants of an uploaded image. Images that fail to
+/// decode are kept as uploaded, without variants.
+async fn build_variants(path: &std::path::Path) -> Vec<images::EncodedVariant> {
+    let data = match fs::read(path).await {
+        Ok(data) => data,
+        Err(err) => {
+            tracing::error!(?err, "failed to read uploaded image");
+            return Vec::new();
+        }
+    };
+
+    match tokio::task::spawn_blocking(move || images::make_variants(&data)).await {
+        Ok(Ok(variants)) => variants,
+        Ok(Err(err)) => {
+            tracing::warn!(?err, "could not decode uploaded image; storing without variants");
+            Vec::new()
+        }
+        Err(err) => {
+            tracing::error!(?err, "image processing task failed");
+            Vec::new()
+        }
+    }
+}
+
+/// DELETE /api/assets/:id
+///
+/// Removes the asset. Its file is only deleted once no other asset shares
+/// the same content. Only the uploader or an admin may delete an asset, and
+/// only an admin one uploaded before owners were recorded (403 otherwise).
+/// 423 if the retention policy keeps the asset.
+pub async fn delete_asset(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let asset = fetch_asset(&state, &id).await?;
+    let owner: Option<String> = sqlx::query_scalar("SELECT owner FROM assets WHERE id = $1")
+        .bind(asset.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error("failed to fetch asset owner"))?
+        .flatten();
+    if owner.as_deref() != Some(user.0.as_str()) {
+        user.require_admin(&state)?;
+    }
+    let variant_names: Vec<String> = sqlx::query_scalar("SELECT variant FROM asset_variants WHERE asset_id = $1")
+        .bind(asset.id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(db_error("failed to fetch asset variants"))?;
+
+    let mut tx = state.pool.begin().await.map_err(db_error("failed to open transaction"))?;
+    let deleted = sqlx::query("DELETE FROM assets WHERE id = $1")
+        .bind(asset.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(retention_error("failed to delete asset"))?;
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let released = match &asset.blob_hash {
+        Some(hash) => blobs::release(&mut tx, hash).await.map_err(db_error("failed to release blob"))?,
+        None => false,
+    };
+    tx.commit().await.map_err(db_error("failed to commit asset deletion"))?;
+
+    match &asset.blob_hash {
+        Some(hash) if released => blobs::remove_files(&state.pool, &state.assets_dir, hash).await,
+        Some(_) => {}
+        None => {
+            let _ = fs::remove_file(asset_path(&state, asset.id, None)).await;
+            for variant in &variant_names {
+                let _ = fs::remove_file(variant_path(&state, asset.id, None, variant)).await;
+            }
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/assets/:id/info
+pub async fn get_asset_info(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<AssetInfo>, StatusCode> {
+    let asset = fetch_asset(&state, &id).await?;
+    let variants = sqlx::query_as::<_, AssetVariant>(
+        "SELECT asset_id, variant, content_type, width, height, size_bytes FROM asset_variants WHERE asset_id = $1 ORDER BY width ASC",
+    )
+    .bind(asset.id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to fetch asset variants");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(asset_info(asset, variants)))
+}
+
+async fn fetch_asset(state: &AppState, id: &str) -> Result<Asset, StatusCode> {
+    let asset_id = Uuid::parse_str(id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    sqlx::query_as::<_, Asset>(
+        "SELECT id, note_id, filename, content_type, size_bytes, blob_hash, created_at FROM assets WHERE id = $1",
+    )
+    .bind(asset_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to fetch asset");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Write a request body to `path`, enforcing `MAX_UPLOAD_BYTES`
+async fn write_body(path: &std::path::Path, body: Body) -> Result<u64, StatusCode> {
+    let mut file = fs::File::create(path).await.map_err(|err| {
+        tracing::error!(?err, "failed to create asset file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let mut stream = body.into_data_stream();
+    let mut size: u64 = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        size += chunk.len() as u64;
+        if size > MAX_UPLOAD_BYTES {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        file.write_all(&chunk).await.map_err(|err| {
+            tracing::error!(?err, "failed to write asset file");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+    file.flush().await.map_err(|err| {
+        tracing::error!(?err, "failed to flush asset file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(size)
+}
+
+/// GET /api/assets/:id
+///
+/// Honours `Range` (answering `206 Partial Content`) and conditional headers so
+/// audio and video can be streamed and seeked. Images, PDFs, audio and video are
+/// served inline unless `?download=true`, anything else as an attachment; the
+/// browser is told not to sniff the type and to sandbox the file either way.
+/// `?variant=thumbnail|medium` serves a resized image instead of the original.
+pub async fn download_asset(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    let asset = fetch_asset(&state, &id).await?;
+
+    let (path, content_type) = match query.variant.as_deref() {
+        None => (asset_path(&state, asset.id, asset.blob_hash.as_deref()), asset.content_type.clone()),
+        Some(variant) => {
+            let content_type: String = sqlx::query_scalar(
+                "SELECT content_type FROM asset_variants WHERE asset_id = $1 AND variant = $2",
+            )
+            .bind(asset.id)
+            .bind(variant)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, "failed to fetch asset variant");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+            (variant_path(&state, asset.id, asset.blob_hash.as_deref(), variant), content_type)
+        }
+    };
+    if !fs::try_exists(&path).await.unwrap_or(false) {
+        tracing::error!(asset_id = %asset.id, "asset file missing from disk");
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // ServeFile handles Range, If-Range and If-Modified-Since for us
+    let mut response = match ServeFile::new(&path).oneshot(request).await {
+        Ok(response) => response.map(Body::new),
+        Err(never) => match never {},
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static("sandbox"));
+    if response.status().is_success() {
+        let headers = response.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&content_type) {
+            headers.insert(header::CONTENT_TYPE, value);
+        }
+        let download = query.download || !served_inline(&content_type);
+        let disposition = content_disposition(&asset.filename, download);
+        if let Ok(value) = HeaderValue::from_str(&disposition) {
+            headers.insert(header::CONTENT_DISPOSITION, value);
+        }
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("private, max-age=31536000, immutable"),
+        );
+    }
+
+    Ok(response.into_response())
+}
+
+/// Whether a file of this type may be displayed rather than downloaded
+fn served_inline(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    INLINE_CONTENT_TYPES.contains(&essence.as_str()) || essence.starts_with("audio/") || essence.starts_with("video/")
+}
+
+/// `Content-Disposition` with an ASCII fallback name and the exact UTF-8 name (RFC 6266)
+fn content_disposition(filename: &str, download: bool) -> String {
+    let kind = if download { "attachment" } else { "inline" };
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' ' => c,
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::with_capacity(filename.len());
+    for byte in filename.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", kind, fallback, encoded)
+}
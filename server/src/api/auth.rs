@@ -6,6 +6,7 @@ use crate::{auth::jwt, AppState};
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
+    #[allow(dead_code)] // Unused until real credential checks are implemented
     pub password: String,
 }
 
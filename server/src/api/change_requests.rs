@@ -0,0 +1,439 @@
+//! Proposed changes to notes, reviewed before they are written.
+//!
+//! Anyone signed in can propose a new title and content for a note. The
+//! proposal records the note's `updated_at` it was made against, like a
+//! `conflict=check` save, and can be discussed in comments. A reviewer (the
+//! note's owner or an admin) applies or rejects it; applying fails with a
+//! version conflict if the note changed since, and the author revises the
+//! proposal against the current note. The author can also withdraw it.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        error::ApiError,
+        history,
+        notes::VersionConflict,
+        sync_crdt::{NoteMetadata, WsMessage},
+    },
+    auth::AuthUser,
+    db::models::Note,
+    quota::{self, QuotaKind},
+    titles,
+    AppState,
+};
+
+/// Default and maximum number of change requests listed
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 500;
+
+const CHANGE_REQUEST_COLUMNS: &str =
+    "id, note_id, author, title, content, base_updated_at, status, created_at, updated_at, resolved_by, resolved_at";
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ChangeRequest {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub author: String,
+    pub title: String,
+    pub content: String,
+    /// The note version the change was proposed against
+    pub base_updated_at: DateTime<Utc>,
+    /// "open", "applied", "rejected" or "withdrawn"
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Comment {
+    pub id: i64,
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A change request with its discussion and the note as it is now, to diff against
+#[derive(Debug, Serialize)]
+pub struct ChangeRequestDetail {
+    #[serde(flatten)]
+    pub request: ChangeRequest,
+    pub comments: Vec<Comment>,
+    /// `None` once the note has been deleted
+    pub current: Option<Note>,
+    /// The note changed since the proposal was made, so it can't be applied as is
+    pub stale: bool,
+    /// The signed-in user may apply or reject it
+    pub can_review: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeRequestInput {
+    /// Defaults to the note's current title
+    pub title: Option<String>,
+    pub content: String,
+    /// `updated_at` of the note version the change was made against
+    pub base_updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentInput {
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    /// "open" (default), "applied", "rejected", "withdrawn" or "all"
+    pub status: Option<String>,
+    pub author: Option<String>,
+    pub limit: Option<i64>,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to access change requests");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Whether `user` may apply or reject changes to a note owned by `owner`
+fn can_review(state: &AppState, user: &str, owner: Option<&str>) -> bool {
+    owner == Some(user) || state.quotas.is_admin(user)
+}
+
+async fn load_note(conn: &mut sqlx::PgConnection, note_id: Uuid, lock: bool) -> Result<Option<(Note, Option<String>)>, StatusCode> {
+    let query = if lock {
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner FROM notes WHERE id = $1 AND is_deleted = false FOR UPDATE"
+    } else {
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner FROM notes WHERE id = $1 AND is_deleted = false"
+    };
+    let row = sqlx::query_as::<_, (Uuid, String, String, Option<Uuid>, DateTime<Utc>, bool, bool, Option<String>)>(query)
+        .bind(note_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(db_error)?;
+    Ok(row.map(|(id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner)| {
+        (
+            Note {
+                id,
+                title,
+                content,
+                folder_id,
+                updated_at,
+                is_deleted,
+                is_canvas,
+            },
+            owner,
+        )
+    }))
+}
+
+async fn load_request(conn: &mut sqlx::PgConnection, id: Uuid, lock: bool) -> Result<ChangeRequest, StatusCode> {
+    let query = format!(
+        "SELECT {} FROM change_requests WHERE id = $1{}",
+        CHANGE_REQUEST_COLUMNS,
+        if lock { " FOR UPDATE" } else { "" }
+    );
+    sqlx::query_as::<_, ChangeRequest>(&query)
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn detail(state: &AppState, user: &str, id: Uuid) -> Result<ChangeRequestDetail, StatusCode> {
+    let mut conn = state.pool.acquire().await.map_err(db_error)?;
+    let request = load_request(&mut conn, id, false).await?;
+    let comments = sqlx::query_as::<_, Comment>(
+        "SELECT id, author, body, created_at FROM change_request_comments WHERE change_request_id = $1 ORDER BY created_at ASC, id ASC",
+    )
+    .bind(id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    let note = load_note(&mut conn, request.note_id, false).await?;
+    let stale = note.as_ref().is_none_or(|(note, _)| note.updated_at != request.base_updated_at);
+    let can_review = note.as_ref().is_some_and(|(_, owner)| can_review(state, user, owner.as_deref()));
+    Ok(ChangeRequestDetail {
+        request,
+        comments,
+        current: note.map(|(note, _)| note),
+        stale,
+        can_review,
+    })
+}
+
+/// POST /api/notes/:id/change-requests — propose a change to a note
+pub async fn create_change_request(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(note_id): Path<Uuid>,
+    Json(input): Json<ChangeRequestInput>,
+) -> Result<Json<ChangeRequestDetail>, StatusCode> {
+    let mut conn = state.pool.acquire().await.map_err(db_error)?;
+    let (note, _) = load_note(&mut conn, note_id, false).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if note.is_canvas {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO change_requests (id, note_id, author, title, content, base_updated_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(id)
+    .bind(note_id)
+    .bind(&user)
+    .bind(input.title.unwrap_or(note.title))
+    .bind(&input.content)
+    .bind(input.base_updated_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    drop(conn);
+
+    Ok(Json(detail(&state, &user, id).await?))
+}
+
+/// GET /api/notes/:id/change-requests — every change request for a note, newest first
+pub async fn list_note_change_requests(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Path(note_id): Path<Uuid>,
+) -> Result<Json<Vec<ChangeRequest>>, StatusCode> {
+    let query = format!(
+        "SELECT {} FROM change_requests WHERE note_id = $1 ORDER BY created_at DESC",
+        CHANGE_REQUEST_COLUMNS
+    );
+    let requests = sqlx::query_as::<_, ChangeRequest>(&query)
+        .bind(note_id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(db_error)?;
+    Ok(Json(requests))
+}
+
+/// GET /api/change-requests — the review queue across notes, newest first
+pub async fn list_change_requests(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<ChangeRequest>>, StatusCode> {
+    let status = match query.status.as_deref() {
+        None => Some("open"),
+        Some("all") => None,
+        Some(status @ ("open" | "applied" | "rejected" | "withdrawn")) => Some(status),
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let sql = format!(
+        "SELECT {} FROM change_requests
+         WHERE ($1::text IS NULL OR status = $1) AND ($2::text IS NULL OR author = $2)
+         ORDER BY created_at DESC
+         LIMIT $3",
+        CHANGE_REQUEST_COLUMNS
+    );
+    let requests = sqlx::query_as::<_, ChangeRequest>(&sql)
+        .bind(status)
+        .bind(&query.author)
+        .bind(limit)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(db_error)?;
+    Ok(Json(requests))
+}
+
+/// GET /api/change-requests/:id — a change request with its comments and the current note
+pub async fn get_change_request(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ChangeRequestDetail>, StatusCode> {
+    Ok(Json(detail(&state, &user, id).await?))
+}
+
+/// PUT /api/change-requests/:id — the author revises an open proposal,
+/// usually against a newer version of the note
+pub async fn update_change_request(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+    Json(input): Json<ChangeRequestInput>,
+) -> Result<Json<ChangeRequestDetail>, StatusCode> {
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let request = load_request(&mut tx, id, true).await?;
+    if request.author != user {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if request.status != "open" {
+        return Err(StatusCode::CONFLICT);
+    }
+    sqlx::query(
+        "UPDATE change_requests SET title = $2, content = $3, base_updated_at = $4, updated_at = now() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(input.title.unwrap_or(request.title))
+    .bind(&input.content)
+    .bind(input.base_updated_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(Json(detail(&state, &user, id).await?))
+}
+
+/// POST /api/change-requests/:id/comments
+pub async fn add_comment(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+    Json(input): Json<CommentInput>,
+) -> Result<Json<Comment>, StatusCode> {
+    let body = input.body.trim();
+    if body.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut conn = state.pool.acquire().await.map_err(db_error)?;
+    load_request(&mut conn, id, false).await?;
+    let comment = sqlx::query_as::<_, Comment>(
+        "INSERT INTO change_request_comments (change_request_id, author, body) VALUES ($1, $2, $3)
+         RETURNING id, author, body, created_at",
+    )
+    .bind(id)
+    .bind(&user)
+    .bind(body)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(comment))
+}
+
+/// POST /api/change-requests/:id/apply — write the proposal to the note.
+/// 409 with the current note if it changed since the proposal was made.
+pub async fn apply_change_request(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Note>, ApiError> {
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    history::set_actor(&mut tx, &user).await.map_err(db_error)?;
+    let request = load_request(&mut tx, id, true).await?;
+    if request.status != "open" {
+        return Err(StatusCode::CONFLICT.into());
+    }
+    let (current, owner) = load_note(&mut tx, request.note_id, true).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if !can_review(&state, &user, owner.as_deref()) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+    if current.updated_at != request.base_updated_at {
+        return Err(ApiError::VersionConflict(VersionConflict {
+            error: "version_conflict",
+            message: "The note was changed since this change was proposed".to_string(),
+            current,
+        }));
+    }
+
+    let previous_usage = match &owner {
+        Some(owner) => quota::usage(&mut tx, owner, QuotaKind::Notes).await.map_err(db_error)?,
+        None => 0,
+    };
+    if state.unique_titles {
+        titles::check(&mut tx, current.id, &request.title, current.folder_id, false).await?;
+    }
+    let note = sqlx::query_as::<_, Note>(
+        "UPDATE notes SET title = $2, content = $3, updated_at = now() WHERE id = $1
+         RETURNING id, title, content, folder_id, updated_at, is_deleted, is_canvas",
+    )
+    .bind(current.id)
+    .bind(&request.title)
+    .bind(&request.content)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    // Dropping the transaction on a quota error rolls the change back
+    if let Some(owner) = &owner {
+        quota::check(&mut tx, &state.quotas, owner, QuotaKind::Notes, previous_usage).await?;
+    }
+    sqlx::query(
+        "UPDATE change_requests SET status = 'applied', resolved_by = $2, resolved_at = now(), updated_at = now() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(&user)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    if let Some(hub) = &state.sync_hub {
+        let meta = NoteMetadata {
+            id: note.id,
+            title: note.title.clone(),
+            content: note.content.clone(),
+            folder_id: note.folder_id,
+            is_deleted: note.is_deleted,
+            is_canvas: note.is_canvas,
+            updated_at: note.updated_at,
+        };
+        if let Ok(payload) = serde_json::to_string(&meta) {
+            let _ = hub.broadcast(WsMessage::NoteMetadata { payload }).await;
+        }
+    }
+
+    Ok(Json(note))
+}
+
+/// Close an open change request without applying it
+async fn close(state: &AppState, user: &str, id: Uuid, status: &str) -> Result<ChangeRequestDetail, StatusCode> {
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let request = load_request(&mut tx, id, true).await?;
+    if request.status != "open" {
+        return Err(StatusCode::CONFLICT);
+    }
+    let allowed = if status == "withdrawn" {
+        request.author == user
+    } else {
+        let owner = load_note(&mut tx, request.note_id, false).await?.and_then(|(_, owner)| owner);
+        can_review(state, user, owner.as_deref())
+    };
+    if !allowed {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    sqlx::query(
+        "UPDATE change_requests SET status = $2, resolved_by = $3, resolved_at = now(), updated_at = now() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(status)
+    .bind(user)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    detail(state, user, id).await
+}
+
+/// POST /api/change-requests/:id/reject — a reviewer declines the proposal
+pub async fn reject_change_request(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ChangeRequestDetail>, StatusCode> {
+    Ok(Json(close(&state, &user, id, "rejected").await?))
+}
+
+/// POST /api/change-requests/:id/withdraw — the author takes the proposal back
+pub async fn withdraw_change_request(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ChangeRequestDetail>, StatusCode> {
+    Ok(Json(close(&state, &user, id, "withdrawn").await?))
+}
@@ -0,0 +1,106 @@
+use axum::{extract::{Query, State}, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use crate::AppState;
+
+/// Default and maximum page sizes for the change feed
+const DEFAULT_LIMIT: i64 = 500;
+const MAX_LIMIT: i64 = 1000;
+
+/// Entities recorded in the feed
+pub const ENTITIES: &[&str] = &["note", "folder", "board", "board_column", "board_card", "relation", "template"];
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeFeedQuery {
+    /// Only return changes with a sequence number greater than this
+    #[serde(default)]
+    pub after_seq: i64,
+    /// Only return changes to one entity type
+    pub entity: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// One accepted change, recorded by the `record_change` trigger
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Change {
+    pub seq: i64,
+    pub entity: String,
+    /// Row id; relations use `source|target|kind`
+    pub entity_id: String,
+    /// "upsert" or "delete"
+    pub op: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangeFeedResponse {
+    pub changes: Vec<Change>,
+    /// Pass back as `after_seq` to resume after the last returned change
+    pub last_seq: i64,
+    /// More changes are available right now; fetch again with `last_seq`
+    pub has_more: bool,
+}
+
+/// Distinct ids of one entity changed after `after_seq`, with the highest
+/// sequence number seen (or `after_seq` if nothing changed)
+pub async fn changed_entity_ids(
+    conn: &mut PgConnection,
+    entity: &str,
+    after_seq: i64,
+) -> Result<(Vec<String>, i64), sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT entity_id, MAX(seq) FROM changes
+         WHERE entity = $1 AND seq > $2
+         GROUP BY entity_id",
+    )
+    .bind(entity)
+    .bind(after_seq)
+    .fetch_all(conn)
+    .await?;
+
+    let last_seq = rows.iter().map(|(_, seq)| *seq).max().unwrap_or(after_seq);
+    Ok((rows.into_iter().map(|(id, _)| id).collect(), last_seq))
+}
+
+/// GET /api/changes?after_seq=
+///
+/// Changes in sequence order, which is also commit order (see the `changes`
+/// migration), so a client resuming from `last_seq` never skips a change.
+pub async fn list_changes(
+    State(state): State<AppState>,
+    Query(query): Query<ChangeFeedQuery>,
+) -> Result<Json<ChangeFeedResponse>, axum::http::StatusCode> {
+    if query.entity.as_deref().is_some_and(|e| !ENTITIES.contains(&e)) {
+        return Err(axum::http::StatusCode::BAD_REQUEST);
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let rows = sqlx::query_as::<_, Change>(
+        "SELECT seq, entity, entity_id, op, recorded_at FROM changes
+         WHERE seq > $1
+           AND ($2::text IS NULL OR entity = $2)
+         ORDER BY seq ASC
+         LIMIT $3",
+    )
+    .bind(query.after_seq)
+    .bind(&query.entity)
+    .bind(limit + 1)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to list changes");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let has_more = rows.len() as i64 > limit;
+    let changes: Vec<Change> = rows.into_iter().take(limit as usize).collect();
+    let last_seq = changes.last().map_or(query.after_seq, |c| c.seq);
+
+    Ok(Json(ChangeFeedResponse {
+        changes,
+        last_seq,
+        has_more,
+    }))
+}
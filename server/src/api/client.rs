@@ -0,0 +1,33 @@
+use std::env;
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+use crate::AppState;
+
+/// Desktop release information, configured by the operator
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientRelease {
+    /// Newest desktop version available
+    pub latest_version: Option<String>,
+    /// Oldest desktop version that can still sync with this server
+    pub min_supported_version: Option<String>,
+    pub release_notes_url: Option<String>,
+}
+
+impl ClientRelease {
+    /// `CLIENT_LATEST_VERSION`, `CLIENT_MIN_VERSION` and `CLIENT_RELEASE_NOTES_URL`
+    pub fn from_env() -> Self {
+        let var = |key: &str| env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        ClientRelease {
+            latest_version: var("CLIENT_LATEST_VERSION"),
+            min_supported_version: var("CLIENT_MIN_VERSION"),
+            release_notes_url: var("CLIENT_RELEASE_NOTES_URL"),
+        }
+    }
+}
+
+/// GET /api/client/latest
+pub async fn latest_client(State(state): State<AppState>) -> Json<ClientRelease> {
+    Json(state.client_release.as_ref().clone())
+}
@@ -0,0 +1,242 @@
+//! Who wrote what in a note's CRDT document.
+//!
+//! Every piece of text in a Yjs document carries the id of the client that
+//! inserted it. Clients keep a stable id per user and device (the desktop app
+//! derives one per window) and register it with `POST /api/crdt/clients`; the
+//! WebSocket also records the sender of an update made by a single client it
+//! hasn't seen (see `sync_crdt`). `GET /api/notes/:id/contributors` diffs the
+//! note's current state against an empty snapshot, which marks every visible
+//! run of text with its client, and maps clients back to users.
+//!
+//! Only text still in the note is attributed; deleted text is garbage
+//! collected by the editors, and formatting changes or embeds aren't counted.
+//! Text from clients that never registered is reported with `user: null`.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+use yrs::types::text::YChange;
+use yrs::updates::decoder::Decode;
+use yrs::{Any, Doc, Out, ReadTxn, Snapshot, Text, Transact, Update, XmlFragment, XmlOut, XmlTextRef};
+
+use crate::{auth::AuthUser, AppState};
+
+/// Longest device label stored
+const MAX_DEVICE_LEN: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct ClientRegistration {
+    /// The Yjs client id, an unsigned 32-bit integer
+    pub client_id: u32,
+    /// Label for the device, such as its name
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CrdtClient {
+    pub client_id: i64,
+    pub username: String,
+    pub device: Option<String>,
+    pub registered_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// A run of text inserted by one client
+#[derive(Debug, Serialize)]
+pub struct Portion {
+    /// Index of the text node (paragraph, heading, list item, ...) in
+    /// document order
+    pub block: usize,
+    pub client_id: u64,
+    pub user: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Contributor {
+    /// `None` for clients nobody registered
+    pub user: Option<String>,
+    pub client_ids: Vec<u64>,
+    /// Characters of the note's current text they wrote
+    pub characters: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteContributors {
+    /// Most characters first
+    pub contributors: Vec<Contributor>,
+    /// The note's text in document order
+    pub portions: Vec<Portion>,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query CRDT clients");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// POST /api/crdt/clients
+///
+/// Register a Yjs client id as the signed-in user's. Registering it again
+/// refreshes it; a client id another user registered is a 409.
+pub async fn register_client(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Json(input): Json<ClientRegistration>,
+) -> Result<Json<CrdtClient>, StatusCode> {
+    let device: Option<String> = input
+        .device
+        .as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.chars().take(MAX_DEVICE_LEN).collect());
+    let client = sqlx::query_as::<_, CrdtClient>(
+        "INSERT INTO crdt_clients (client_id, username, device)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (client_id) DO UPDATE SET
+            device = COALESCE(EXCLUDED.device, crdt_clients.device),
+            last_seen_at = now()
+         WHERE crdt_clients.username = EXCLUDED.username
+         RETURNING client_id, username, device, registered_at, last_seen_at",
+    )
+    .bind(i64::from(input.client_id))
+    .bind(&user)
+    .bind(device)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::CONFLICT)?;
+    Ok(Json(client))
+}
+
+/// Record `user` as the owner of the client that made `update`, if it was made
+/// by a single client nobody has claimed yet
+pub async fn record_sender(pool: &PgPool, update: &[u8], user: &str) -> Result<(), sqlx::Error> {
+    let Some(client_id) = sole_client(update) else {
+        return Ok(());
+    };
+    sqlx::query(
+        "INSERT INTO crdt_clients (client_id, username)
+         VALUES ($1, $2)
+         ON CONFLICT (client_id) DO UPDATE SET last_seen_at = now()
+         WHERE crdt_clients.username = EXCLUDED.username",
+    )
+    .bind(i64::from(client_id))
+    .bind(user)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The client that made an update, if only one did
+fn sole_client(update: &[u8]) -> Option<u32> {
+    let state_vector = Update::decode_v1(update).ok()?.state_vector();
+    let mut clients = state_vector.iter();
+    match (clients.next(), clients.next()) {
+        (Some((&client_id, _)), None) => u32::try_from(client_id).ok(),
+        _ => None,
+    }
+}
+
+/// The visible text of a note's document as (block, client, text) runs
+fn attribute(ydoc_state: &[u8]) -> Result<Vec<(usize, u64, String)>, yrs::encoding::read::Error> {
+    let update = Update::decode_v1(ydoc_state)?;
+    let doc = Doc::new();
+    let fragment = doc.get_or_insert_xml_fragment("content");
+    let mut txn = doc.transact_mut();
+    txn.apply_update(update);
+
+    let texts: Vec<XmlTextRef> = fragment
+        .successors(&txn)
+        .filter_map(|node| match node {
+            XmlOut::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect();
+    // Everything visible now is "added" since the empty snapshot, tagged with
+    // the id of the item it comes from
+    let current = txn.snapshot();
+    let empty = Snapshot::default();
+    let mut runs: Vec<(usize, u64, String)> = Vec::new();
+    for (block, text) in texts.iter().enumerate() {
+        for chunk in text.diff_range(&mut txn, Some(&current), Some(&empty), YChange::identity) {
+            let (Out::Any(Any::String(s)), Some(change)) = (&chunk.insert, &chunk.ychange) else {
+                continue;
+            };
+            match runs.last_mut() {
+                Some((b, client, run)) if *b == block && *client == change.id.client => run.push_str(s),
+                _ => runs.push((block, change.id.client, s.to_string())),
+            }
+        }
+    }
+    Ok(runs)
+}
+
+/// GET /api/notes/:id/contributors
+pub async fn note_contributors(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<NoteContributors>, StatusCode> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM notes WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(db_error)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let ydoc_state: Option<Vec<u8>> = sqlx::query_scalar("SELECT ydoc_state FROM crdt_states WHERE note_id = $1")
+        .bind(id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?;
+    let runs = match ydoc_state {
+        Some(ydoc_state) => attribute(&ydoc_state).map_err(|err| {
+            tracing::error!(?err, %id, "CRDT state is corrupt");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?,
+        None => Vec::new(),
+    };
+
+    let mut client_ids: Vec<i64> = runs.iter().map(|(_, client, _)| *client as i64).collect();
+    client_ids.sort_unstable();
+    client_ids.dedup();
+    let users: HashMap<u64, String> = sqlx::query_as::<_, (i64, String)>(
+        "SELECT client_id, username FROM crdt_clients WHERE client_id = ANY($1)",
+    )
+    .bind(&client_ids)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|(client_id, username)| (client_id as u64, username))
+    .collect();
+
+    let mut contributors: Vec<Contributor> = Vec::new();
+    let portions: Vec<Portion> = runs
+        .into_iter()
+        .map(|(block, client_id, text)| {
+            let user = users.get(&client_id).cloned();
+            let characters = text.chars().count();
+            match contributors.iter_mut().find(|c| c.user == user) {
+                Some(contributor) => {
+                    contributor.characters += characters;
+                    if !contributor.client_ids.contains(&client_id) {
+                        contributor.client_ids.push(client_id);
+                    }
+                }
+                None => contributors.push(Contributor { user: user.clone(), client_ids: vec![client_id], characters }),
+            }
+            Portion { block, client_id, user, text }
+        })
+        .collect();
+    contributors.sort_by_key(|c| std::cmp::Reverse(c.characters));
+    Ok(Json(NoteContributors { contributors, portions }))
+}
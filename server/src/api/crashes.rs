@@ -0,0 +1,37 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use beck_crash::CrashReport;
+use serde::Deserialize;
+
+use crate::{auth::AuthUser, AppState};
+
+/// Reports listed when no limit is given
+const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct CrashQuery {
+    pub limit: Option<usize>,
+}
+
+/// GET /api/admin/crashes?limit=
+///
+/// The server's recent panics, newest first, with backtraces (see
+/// `crash_reports`).
+pub async fn list_crashes(
+    State(state): State<AppState>,
+    AuthUser(username): AuthUser,
+    Query(query): Query<CrashQuery>,
+) -> Result<Json<Vec<CrashReport>>, StatusCode> {
+    if !state.quotas.is_admin(&username) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(beck_crash::MAX_REPORTS);
+    let reports = beck_crash::recent_reports(&state.crash_reporting.dir, limit).map_err(|err| {
+        tracing::error!(?err, "failed to read crash reports");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(reports))
+}
@@ -0,0 +1,243 @@
+//! Read-only embeds of single notes, for iframing in wikis and dashboards.
+//!
+//! A signed-in user creates an embed token for a note; `GET /embed/<token>`
+//! then serves that note, and nothing else, to anyone holding the token: a
+//! minimal HTML page without app chrome, rendered like printouts (editor
+//! markup stripped, diagrams and math rendered, redaction rules applied) with
+//! a screen stylesheet. The page is locked down with a Content Security Policy
+//! that allows no scripts, sandboxes it and limits who may frame it to
+//! `EMBED_FRAME_ANCESTORS` (default any site). Tokens can expire and are
+//! revoked by deleting them.
+
+use std::env;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use beck_markdown::html::escape;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::export::printable_body;
+use crate::{auth::AuthUser, blobs, db::models::Note, AppState};
+
+/// Longest lifetime a token can be created with
+const MAX_EXPIRY_DAYS: i64 = 3650;
+
+/// Styles inlined into embeds: compact, and transparent so the page blends
+/// into the host
+const EMBED_STYLE: &str = "body { font-family: -apple-system, 'Segoe UI', Roboto, Helvetica, Arial, sans-serif; font-size: 15px; line-height: 1.5; color: #1f2328; background: transparent; margin: 0; padding: 1em; }
+h1 { font-size: 1.5em; margin-top: 0; }
+img, svg, video { max-width: 100%; height: auto; }
+pre, code { font-family: Menlo, Consolas, monospace; font-size: 0.9em; }
+pre { white-space: pre-wrap; background: #f6f8fa; padding: 0.75em; border-radius: 4px; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d0d7de; padding: 0.25em 0.5em; text-align: left; vertical-align: top; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 3px solid #d0d7de; color: #57606a; }
+ul.task-list { list-style: none; padding-left: 0; }
+li.task > div, li.task > div > p:first-child { display: inline; }
+li.task.done { color: #57606a; }
+.missing-asset { color: #777; font-style: italic; }
+";
+
+/// Where embeds may be framed, configured by the operator
+#[derive(Debug, Clone)]
+pub struct EmbedConfig {
+    /// CSP `frame-ancestors` sources
+    pub frame_ancestors: String,
+}
+
+impl EmbedConfig {
+    /// `EMBED_FRAME_ANCESTORS`, space-separated origins (default `*`)
+    pub fn from_env() -> Self {
+        EmbedConfig {
+            frame_ancestors: env::var("EMBED_FRAME_ANCESTORS")
+                .ok()
+                .map(|v| v.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| "*".to_string()),
+        }
+    }
+
+    fn content_security_policy(&self) -> String {
+        format!(
+            "default-src 'none'; img-src 'self' data: https:; media-src 'self' https:; style-src 'unsafe-inline'; \
+             base-uri 'none'; form-action 'none'; frame-ancestors {}; sandbox allow-popups allow-popups-to-escape-sandbox",
+            self.frame_ancestors
+        )
+    }
+}
+
+/// An embed token, without the token itself
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Embed {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A new embed token; the only time the token is returned
+#[derive(Debug, Serialize)]
+pub struct CreatedEmbed {
+    #[serde(flatten)]
+    pub embed: Embed,
+    pub token: String,
+    /// `/embed/<token>`, relative to the server
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedInput {
+    /// Omit for a token that doesn't expire
+    pub expires_in_days: Option<i64>,
+}
+
+const EMBED_COLUMNS: &str = "id, note_id, created_by, created_at, expires_at, last_used_at";
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query embeds");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// A fresh random token
+pub(crate) fn new_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// POST /api/notes/:id/embeds
+///
+/// Create an embed token for a note. Canvas notes can't be embedded (422).
+pub async fn create_embed(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(note_id): Path<Uuid>,
+    Json(input): Json<EmbedInput>,
+) -> Result<Json<CreatedEmbed>, StatusCode> {
+    let is_canvas: bool = sqlx::query_scalar("SELECT is_canvas FROM notes WHERE id = $1 AND NOT is_deleted")
+        .bind(note_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if is_canvas {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let expires_at = match input.expires_in_days {
+        Some(days) if !(1..=MAX_EXPIRY_DAYS).contains(&days) => return Err(StatusCode::BAD_REQUEST),
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+
+    let token = new_token();
+    let embed = sqlx::query_as::<_, Embed>(&format!(
+        "INSERT INTO embed_tokens (id, note_id, token_hash, created_by, expires_at)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {}",
+        EMBED_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(note_id)
+    .bind(blobs::sha256_bytes(token.as_bytes()))
+    .bind(&user)
+    .bind(expires_at)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(CreatedEmbed { embed, path: format!("/embed/{}", token), token }))
+}
+
+/// GET /api/notes/:id/embeds
+pub async fn list_embeds(
+    State(state): State<AppState>,
+    Path(note_id): Path<Uuid>,
+) -> Result<Json<Vec<Embed>>, StatusCode> {
+    let embeds = sqlx::query_as::<_, Embed>(&format!(
+        "SELECT {} FROM embed_tokens WHERE note_id = $1 ORDER BY created_at DESC",
+        EMBED_COLUMNS
+    ))
+    .bind(note_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(embeds))
+}
+
+/// DELETE /api/embeds/:id
+///
+/// Revoke an embed token. Only its creator or an admin can.
+pub async fn delete_embed(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let created_by: String = sqlx::query_scalar("SELECT created_by FROM embed_tokens WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if created_by != user && !state.quotas.is_admin(&user) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    sqlx::query("DELETE FROM embed_tokens WHERE id = $1")
+        .bind(id)
+        .execute(&state.pool)
+        .await
+        .map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /embed/:token
+///
+/// The token's note as a standalone, sandboxed HTML page. Unknown, expired and
+/// revoked tokens, and deleted notes, are a 404.
+pub async fn embed_note(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    let note = sqlx::query_as::<_, Note>(
+        "UPDATE embed_tokens e SET last_used_at = now()
+         FROM notes n
+         WHERE e.token_hash = $1 AND n.id = e.note_id
+           AND (e.expires_at IS NULL OR e.expires_at > now())
+           AND NOT n.is_deleted AND NOT n.is_canvas
+         RETURNING n.id, n.title, n.content, n.folder_id, n.updated_at, n.is_deleted, n.is_canvas",
+    )
+    .bind(blobs::sha256_bytes(token.trim().as_bytes()))
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut note = note;
+    note.title = state.redaction.redact_text(&note.title);
+    note.content = state.redaction.redact_html(&note.content);
+    let body = printable_body(&state, &note, &headers).await.map_err(db_error)?;
+
+    let title = escape(note.title.trim());
+    let heading = if title.is_empty() { String::new() } else { format!("<h1>{}</h1>\n", title) };
+    let document = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<meta name=\"robots\" content=\"noindex\">\n<base target=\"_blank\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}{}\n</body>\n</html>\n",
+        title, EMBED_STYLE, heading, body
+    );
+    let csp = HeaderValue::from_str(&state.embeds.content_security_policy()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8")),
+            (header::CONTENT_SECURITY_POLICY, csp),
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+            (header::REFERRER_POLICY, HeaderValue::from_static("no-referrer")),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+            (HeaderName::from_static("x-robots-tag"), HeaderValue::from_static("noindex")),
+        ],
+        document,
+    ))
+}
@@ -0,0 +1,45 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use crate::{api::notes::VersionConflict, quota::QuotaExceeded, titles::TitleConflict};
+
+/// Error for handlers that can fail with more than a bare status code.
+/// Converts from `StatusCode`, so `?` keeps working on existing error paths.
+#[derive(Debug)]
+pub enum ApiError {
+    Status(StatusCode),
+    Quota(QuotaExceeded),
+    TitleConflict(TitleConflict),
+    VersionConflict(VersionConflict),
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError::Status(status)
+    }
+}
+
+impl From<QuotaExceeded> for ApiError {
+    fn from(err: QuotaExceeded) -> Self {
+        ApiError::Quota(err)
+    }
+}
+
+impl From<TitleConflict> for ApiError {
+    fn from(err: TitleConflict) -> Self {
+        ApiError::TitleConflict(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            ApiError::Status(status) => status.into_response(),
+            ApiError::Quota(err) => err.into_response(),
+            ApiError::TitleConflict(err) => err.into_response(),
+            ApiError::VersionConflict(err) => err.into_response(),
+        }
+    }
+}
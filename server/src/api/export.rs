@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use beck_markdown::{
+    export_file_name,
+    html::{self, escape},
+    render_note, Backlink, ExportFormat,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, db::models::Note, diagrams, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// End the export with a "Linked from" section
+    #[serde(default)]
+    pub backlinks: bool,
+}
+
+/// Live notes linking to `note_id`, by title, as links to their exports in
+/// `format` next to this one
+async fn export_backlinks(state: &AppState, note_id: Uuid, format: ExportFormat) -> Result<Vec<Backlink>, sqlx::Error> {
+    let titles: Vec<String> = sqlx::query_scalar(
+        "SELECT title FROM notes
+         WHERE is_deleted = false AND id <> $1 AND content LIKE '%beck://note/' || $1::text || '%'
+         ORDER BY lower(title), id",
+    )
+    .bind(note_id)
+    .fetch_all(&state.pool)
+    .await?;
+    Ok(titles
+        .into_iter()
+        .map(|title| Backlink {
+            href: format!("{}.{}", export_file_name(&title), format.extension()),
+            title,
+        })
+        .collect())
+}
+
+/// GET /api/notes/:id/export?format=markdown|html&backlinks=true
+///
+/// HTML exports show Mermaid and PlantUML blocks as SVG when the server has a
+/// renderer for them (see `diagrams`). With `backlinks`, the export lists the
+/// notes linking to this one, linked by the file names they'd be exported as.
+/// The note is redacted first (see `redaction`).
+pub async fn export_note(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let note_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let note = sqlx::query_as::<_, Note>(
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas FROM notes WHERE id = $1 AND is_deleted = false",
+    )
+    .bind(note_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to fetch note for export");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut note = note;
+    note.title = state.redaction.redact_text(&note.title);
+    note.content = state.redaction.redact_html(&note.content);
+    if query.format == ExportFormat::Html {
+        let rendered = diagrams::render_all(&state.diagrams, &state.assets_dir, &note.content).await;
+        note.content = beck_markdown::replace_diagrams(&note.content, |d| rendered.get(d).cloned());
+    }
+
+    let backlinks = if query.backlinks {
+        export_backlinks(&state, note_id, query.format).await.map_err(|err| {
+            tracing::error!(?err, "failed to fetch backlinks for export");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    } else {
+        Vec::new()
+    };
+
+    let content_type = match query.format {
+        ExportFormat::Markdown => "text/markdown; charset=utf-8",
+        ExportFormat::Html => "text/html; charset=utf-8",
+    };
+
+    Ok(([(header::CONTENT_TYPE, content_type)], render_note(&note.title, &note.content, query.format, &backlinks)))
+}
+
+/// Styles inlined into printed notes: paper margins, page-break hints and
+/// link targets spelled out, since they can't be clicked on paper
+const PRINT_STYLE: &str = "@page { margin: 2cm; }
+body { font-family: Georgia, 'Times New Roman', serif; font-size: 11pt; line-height: 1.5; color: #000; max-width: 46em; margin: 0 auto; }
+h1, h2, h3, h4, h5, h6 { break-after: avoid; page-break-after: avoid; line-height: 1.2; }
+p, li { orphans: 3; widows: 3; }
+pre, table, figure, img, blockquote, .task { break-inside: avoid; page-break-inside: avoid; }
+img, svg { max-width: 100%; height: auto; }
+pre, code { font-family: Menlo, Consolas, monospace; font-size: 9.5pt; }
+pre { white-space: pre-wrap; border: 1px solid #ccc; padding: 0.5em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #999; padding: 0.25em 0.5em; text-align: left; vertical-align: top; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 3px solid #ccc; }
+ul.task-list { list-style: none; padding-left: 0; }
+li.task > div, li.task > div > p:first-child { display: inline; }
+li.task.done { color: #555; }
+.missing-asset { color: #777; font-style: italic; }
+a { color: inherit; }
+a[href^=\"http\"]::after { content: \" (\" attr(href) \")\"; font-size: 85%; color: #555; word-break: break-all; }
+";
+
+/// File name of a desktop `asset://` (or Windows `asset.localhost`) URI
+fn asset_file(uri: &str) -> Option<&str> {
+    if !(uri.starts_with("asset://") || uri.contains("asset.localhost/")) {
+        return None;
+    }
+    let file = uri.rsplit('/').next()?.split(['?', '#']).next()?;
+    (!file.is_empty()).then_some(file)
+}
+
+/// `scheme://host` the client reached us at, for absolute asset URLs
+fn request_origin(headers: &HeaderMap) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    let proto = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(str::trim)
+        .unwrap_or("http");
+    Some(format!("{}://{}", proto, host))
+}
+
+/// Server asset ids for the desktop asset files a note references, preferring
+/// assets uploaded for this note when several share a file name
+async fn asset_ids(state: &AppState, note_id: Uuid, content: &str) -> Result<HashMap<String, Uuid>, sqlx::Error> {
+    let mut files = Vec::new();
+    html::walk(&html::parse(content), &mut |el| {
+        let uri = match el.name.as_str() {
+            "img" => el.attr("src"),
+            "a" => el.attr("href"),
+            _ => None,
+        };
+        if let Some(file) = uri.and_then(asset_file) {
+            files.push(file.to_string());
+        }
+    });
+    if files.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<(String, Uuid)> = sqlx::query_as(
+        "SELECT DISTINCT ON (filename) filename, id FROM assets
+         WHERE filename = ANY($1)
+         ORDER BY filename, (note_id = $2) DESC NULLS LAST, created_at DESC",
+    )
+    .bind(&files)
+    .bind(note_id)
+    .fetch_all(&state.pool)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// A note's content stripped of editor markup, with diagrams and math
+/// rendered and asset URLs made absolute, for printing and embedding
+pub(crate) async fn printable_body(state: &AppState, note: &Note, headers: &HeaderMap) -> Result<String, sqlx::Error> {
+    let assets = asset_ids(state, note.id, &note.content).await?;
+    let origin = request_origin(headers).unwrap_or_default();
+    let body = beck_markdown::print_html(&note.content, |url| {
+        if url.starts_with("http://") || url.starts_with("https://") || url.starts_with("data:") || url.starts_with("mailto:") {
+            Some(url.to_string())
+        } else if url.starts_with("/api/") {
+            Some(format!("{}{}", origin, url))
+        } else {
+            let id = asset_file(url).and_then(|file| assets.get(file))?;
+            Some(format!("{}/api/assets/{}", origin, id))
+        }
+    });
+    let rendered = diagrams::render_all(&state.diagrams, &state.assets_dir, &body).await;
+    let body = beck_markdown::replace_diagrams(&body, |d| rendered.get(d).cloned());
+    Ok(beck_markdown::render_math(&body))
+}
+
+/// GET /api/notes/:id/print
+///
+/// The note as a self-contained HTML document for printing, used by the web
+/// app's print flow and for PDF exports. Editor markup is stripped, styles are
+/// inlined, diagrams and math are rendered as for HTML exports and asset URLs
+/// are made absolute. Links to other notes are printed as plain text. The note
+/// is redacted like exports.
+pub async fn print_note(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    let note_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let note = sqlx::query_as::<_, Note>(
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas FROM notes WHERE id = $1 AND is_deleted = false",
+    )
+    .bind(note_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to fetch note for printing");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let mut note = note;
+    note.title = state.redaction.redact_text(&note.title);
+    note.content = state.redaction.redact_html(&note.content);
+
+    let body = printable_body(&state, &note, &headers).await.map_err(|err| {
+        tracing::error!(?err, "failed to look up assets for printing");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let title = escape(note.title.trim());
+    let heading = if title.is_empty() { String::new() } else { format!("<h1>{}</h1>\n", title) };
+    let document = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}{}\n</body>\n</html>\n",
+        title, PRINT_STYLE, heading, body
+    );
+    Ok(([(header::CONTENT_TYPE, "text/html; charset=utf-8")], document))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenderDiagramRequest {
+    pub language: String,
+    pub source: String,
+}
+
+/// POST /api/diagrams/render
+///
+/// Render one diagram to SVG, for clients and site generators publishing
+/// notes. 422 when the language has no renderer or rendering failed.
+pub async fn render_diagram(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Json(req): Json<RenderDiagramRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !beck_markdown::DIAGRAM_LANGUAGES.contains(&req.language.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let diagram = beck_markdown::Diagram {
+        language: req.language,
+        source: req.source,
+    };
+    let svg = diagrams::render(&state.diagrams, &state.assets_dir, &diagram)
+        .await
+        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+    Ok(([(header::CONTENT_TYPE, "image/svg+xml")], svg))
+}
@@ -0,0 +1,311 @@
+//! Per-folder feeds of recently changed notes, for feed readers and
+//! automations.
+//!
+//! A signed-in user creates a feed token for a folder; `GET /feeds/<token>/atom`
+//! and `/feeds/<token>/json` (JSON Feed 1.1) then list the folder's most
+//! recently changed notes, optionally with those of its subfolders, to anyone
+//! holding the token. Each entry has the note's title, a plain-text excerpt
+//! with the redaction rules applied, when it last changed and a deep link
+//! (`beck://note/<id>`) that opens it in the app. Tokens are revoked by
+//! deleting them.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use beck_markdown::html::escape;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::embeds::new_token;
+use crate::{auth::AuthUser, blobs, AppState};
+
+/// Notes listed in a feed
+const FEED_LENGTH: i64 = 50;
+
+/// Characters of note text in an excerpt
+const EXCERPT_CHARS: usize = 280;
+
+/// A feed token, without the token itself
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Feed {
+    pub id: Uuid,
+    pub folder_id: Uuid,
+    pub include_subfolders: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A new feed token; the only time the token is returned
+#[derive(Debug, Serialize)]
+pub struct CreatedFeed {
+    #[serde(flatten)]
+    pub feed: Feed,
+    pub token: String,
+    /// `/feeds/<token>/atom`, relative to the server
+    pub atom_path: String,
+    /// `/feeds/<token>/json`, relative to the server
+    pub json_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedInput {
+    /// Default true
+    pub include_subfolders: Option<bool>,
+}
+
+/// A note as listed in a feed
+struct FeedEntry {
+    id: Uuid,
+    title: String,
+    excerpt: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct JsonFeed {
+    version: &'static str,
+    title: String,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    summary: String,
+    date_modified: String,
+}
+
+const FEED_COLUMNS: &str = "id, folder_id, include_subfolders, created_by, created_at, last_used_at";
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query feeds");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// POST /api/folders/:id/feeds
+pub async fn create_feed(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(folder_id): Path<Uuid>,
+    Json(input): Json<FeedInput>,
+) -> Result<Json<CreatedFeed>, StatusCode> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM folders WHERE id = $1 AND NOT is_deleted)")
+        .bind(folder_id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(db_error)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let token = new_token();
+    let feed = sqlx::query_as::<_, Feed>(&format!(
+        "INSERT INTO feed_tokens (id, folder_id, token_hash, include_subfolders, created_by)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {}",
+        FEED_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(folder_id)
+    .bind(blobs::sha256_bytes(token.as_bytes()))
+    .bind(input.include_subfolders.unwrap_or(true))
+    .bind(&user)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(CreatedFeed {
+        feed,
+        atom_path: format!("/feeds/{}/atom", token),
+        json_path: format!("/feeds/{}/json", token),
+        token,
+    }))
+}
+
+/// GET /api/folders/:id/feeds
+pub async fn list_feeds(
+    State(state): State<AppState>,
+    Path(folder_id): Path<Uuid>,
+) -> Result<Json<Vec<Feed>>, StatusCode> {
+    let feeds = sqlx::query_as::<_, Feed>(&format!(
+        "SELECT {} FROM feed_tokens WHERE folder_id = $1 ORDER BY created_at DESC",
+        FEED_COLUMNS
+    ))
+    .bind(folder_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(feeds))
+}
+
+/// DELETE /api/feeds/:id
+///
+/// Revoke a feed token. Only its creator or an admin can.
+pub async fn delete_feed(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let created_by: String = sqlx::query_scalar("SELECT created_by FROM feed_tokens WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if created_by != user && !state.quotas.is_admin(&user) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    sqlx::query("DELETE FROM feed_tokens WHERE id = $1")
+        .bind(id)
+        .execute(&state.pool)
+        .await
+        .map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The token's feed id and folder name, and the folder's recently changed
+/// notes. Unknown and revoked tokens, and deleted folders, are a 404.
+async fn load_feed(state: &AppState, token: &str) -> Result<(Uuid, String, Vec<FeedEntry>), StatusCode> {
+    let (feed_id, folder_id, include_subfolders, folder_name): (Uuid, Uuid, bool, String) = sqlx::query_as(
+        "UPDATE feed_tokens t SET last_used_at = now()
+         FROM folders f
+         WHERE t.token_hash = $1 AND f.id = t.folder_id AND NOT f.is_deleted
+         RETURNING t.id, t.folder_id, t.include_subfolders, f.name",
+    )
+    .bind(blobs::sha256_bytes(token.trim().as_bytes()))
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(db_error)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let rows: Vec<(Uuid, String, String, DateTime<Utc>)> = sqlx::query_as(
+        "WITH RECURSIVE scope(id) AS (
+             SELECT $1::uuid
+             UNION
+             SELECT f.id FROM folders f JOIN scope s ON f.parent_id = s.id
+             WHERE $2 AND NOT f.is_deleted
+         )
+         SELECT n.id, n.title, n.content, GREATEST(n.updated_at, c.updated_at) AS last_modified_at
+         FROM notes n
+         LEFT JOIN crdt_states c ON c.note_id = n.id
+         WHERE NOT n.is_deleted AND n.folder_id IN (SELECT id FROM scope)
+         ORDER BY last_modified_at DESC, n.id
+         LIMIT $3",
+    )
+    .bind(folder_id)
+    .bind(include_subfolders)
+    .bind(FEED_LENGTH)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let entries = rows
+        .into_iter()
+        .map(|(id, title, content, updated_at)| FeedEntry {
+            id,
+            title: state.redaction.redact_text(&title),
+            excerpt: excerpt(&state.redaction.redact_html(&content)),
+            updated_at,
+        })
+        .collect();
+    Ok((feed_id, state.redaction.redact_text(&folder_name), entries))
+}
+
+/// The start of a note's text, on one line
+fn excerpt(content: &str) -> String {
+    let text = beck_markdown::html_to_text(content);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let line = words.join(" ");
+    if line.chars().count() <= EXCERPT_CHARS {
+        return line;
+    }
+    let mut cut: String = line.chars().take(EXCERPT_CHARS).collect();
+    if let Some(space) = cut.rfind(' ') {
+        cut.truncate(space);
+    }
+    cut.push('…');
+    cut
+}
+
+fn deep_link(note_id: Uuid) -> String {
+    format!("beck://note/{}", note_id)
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn display_title(title: &str) -> &str {
+    match title.trim() {
+        "" => "Untitled",
+        title => title,
+    }
+}
+
+/// GET /feeds/:token/atom
+pub async fn atom_feed(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (feed_id, folder_name, entries) = load_feed(&state, &token).await?;
+    let updated = entries.first().map_or_else(Utc::now, |entry| entry.updated_at);
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n<id>urn:uuid:{}</id>\n<title>{}</title>\n<updated>{}</updated>\n<author><name>Beck</name></author>\n",
+        feed_id,
+        escape(display_title(&folder_name)),
+        timestamp(updated)
+    );
+    for entry in &entries {
+        xml.push_str(&format!(
+            "<entry>\n<id>urn:uuid:{}</id>\n<title>{}</title>\n<updated>{}</updated>\n<link href=\"{}\"/>\n<summary>{}</summary>\n</entry>\n",
+            entry.id,
+            escape(display_title(&entry.title)),
+            timestamp(entry.updated_at),
+            escape(&deep_link(entry.id)),
+            escape(&entry.excerpt)
+        ));
+    }
+    xml.push_str("</feed>\n");
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/atom+xml; charset=utf-8")),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        xml,
+    ))
+}
+
+/// GET /feeds/:token/json
+pub async fn json_feed(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (_, folder_name, entries) = load_feed(&state, &token).await?;
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: display_title(&folder_name).to_string(),
+        items: entries
+            .into_iter()
+            .map(|entry| JsonFeedItem {
+                id: entry.id.to_string(),
+                url: deep_link(entry.id),
+                title: display_title(&entry.title).to_string(),
+                summary: entry.excerpt,
+                date_modified: timestamp(entry.updated_at),
+            })
+            .collect(),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/feed+json")),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        Json(feed),
+    ))
+}
@@ -0,0 +1,128 @@
+//! Index of highlighted passages across all notes.
+//!
+//! Rather than hooking every write path, the index catches up from the change
+//! feed before each listing: notes changed since the last refresh are
+//! re-parsed and their highlights replaced.
+
+use axum::{extract::{Query, State}, Json};
+use beck_markdown::extract_highlights;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{api::changes::changed_entity_ids, AppState};
+
+/// Default and maximum number of highlights returned
+const DEFAULT_LIMIT: i64 = 500;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct HighlightQuery {
+    pub note_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+    pub color: Option<String>,
+    /// Substring of the highlighted text (case-insensitive)
+    pub query: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Highlight {
+    pub id: i64,
+    pub note_id: Uuid,
+    pub note_title: String,
+    pub folder_id: Option<Uuid>,
+    /// Lowercased CSS color; `None` for plain highlights
+    pub color: Option<String>,
+    pub text: String,
+    /// Zero-based index of the highlight within the note
+    pub position: i32,
+}
+
+fn db_error(err: sqlx::Error) -> axum::http::StatusCode {
+    tracing::error!(?err, "failed to refresh highlights");
+    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Re-index notes changed since the last refresh
+async fn refresh_index(state: &AppState) -> Result<(), axum::http::StatusCode> {
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let last_seq: i64 = sqlx::query_scalar("SELECT last_seq FROM highlight_index_state WHERE id = 1 FOR UPDATE")
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    let (changed, seq) = changed_entity_ids(&mut tx, "note", last_seq).await.map_err(db_error)?;
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let note_ids: Vec<Uuid> = changed.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect();
+    sqlx::query("DELETE FROM highlights WHERE note_id = ANY($1)")
+        .bind(&note_ids)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let notes: Vec<(Uuid, String)> =
+        sqlx::query_as("SELECT id, content FROM notes WHERE id = ANY($1) AND is_deleted = false")
+            .bind(&note_ids)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    for (note_id, content) in notes {
+        for (position, highlight) in extract_highlights(&content).into_iter().enumerate() {
+            sqlx::query("INSERT INTO highlights (note_id, position, color, text) VALUES ($1, $2, $3, $4)")
+                .bind(note_id)
+                .bind(position as i32)
+                .bind(&highlight.color)
+                .bind(&highlight.text)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+    }
+
+    sqlx::query("UPDATE highlight_index_state SET last_seq = $1 WHERE id = 1")
+        .bind(seq)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)
+}
+
+/// GET /api/highlights?note_id=&folder_id=&color=&query=
+pub async fn list_highlights(
+    State(state): State<AppState>,
+    Query(query): Query<HighlightQuery>,
+) -> Result<Json<Vec<Highlight>>, axum::http::StatusCode> {
+    refresh_index(&state).await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let color = query.color.as_deref().map(|c| c.trim().to_ascii_lowercase());
+    let text = query.query.as_deref().map(str::trim).filter(|q| !q.is_empty());
+
+    let records = sqlx::query_as::<_, Highlight>(
+        "SELECT h.id, h.note_id, n.title AS note_title, n.folder_id, h.color, h.text, h.position
+         FROM highlights h
+         JOIN notes n ON n.id = h.note_id
+         WHERE n.is_deleted = false
+           AND ($1::uuid IS NULL OR h.note_id = $1)
+           AND ($2::uuid IS NULL OR n.folder_id = $2)
+           AND ($3::text IS NULL OR h.color = $3)
+           AND ($4::text IS NULL OR strpos(lower(h.text), lower($4)) > 0)
+         ORDER BY n.updated_at DESC, h.position ASC
+         LIMIT $5",
+    )
+    .bind(query.note_id)
+    .bind(query.folder_id)
+    .bind(color)
+    .bind(text)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to list highlights");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(records))
+}
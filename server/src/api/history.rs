@@ -0,0 +1,242 @@
+//! Per-note history, merged from what the server records about a note.
+//!
+//! Revisions (metadata and content changes) and CRDT snapshots (collaborative
+//! editing sessions) are written by triggers (see the `note_history`
+//! migration); change requests supply proposals, comments and reviews. The
+//! triggers take the actor from `beck.actor`, which write paths that know the
+//! user set with `set_actor`. Old snapshots are moved to cold storage by
+//! `snapshots` and listed and served from there.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{snapshots, AppState};
+
+/// Default and maximum number of entries returned
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 500;
+
+/// Record `user` as the author of the transaction's writes in note history
+pub async fn set_actor(conn: &mut PgConnection, user: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('beck.actor', $1, true)")
+        .bind(user)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Only entries strictly older than this, to page back in time
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+/// One event in a note's history
+#[derive(Debug, Serialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum HistoryEntry {
+    /// The note row changed
+    Revision {
+        at: DateTime<Utc>,
+        actor: Option<String>,
+        /// `created`, `updated`, `deleted` or `restored`
+        kind: String,
+        /// What changed: `title`, `content`, `folder`, `canvas`
+        fields: Vec<String>,
+        title: String,
+        folder_id: Option<Uuid>,
+        content_length: i32,
+    },
+    /// A collaborative editing session, timestamped at its last write
+    Snapshot {
+        at: DateTime<Utc>,
+        /// Fetch the document state with `GET /api/notes/:id/history/snapshots/:snapshot_id`
+        snapshot_id: i64,
+        /// Kept in cold storage
+        archived: bool,
+        started_at: DateTime<Utc>,
+        /// Everyone known to have edited during the session
+        actors: Vec<String>,
+        /// Size of the document state, in bytes
+        size: i32,
+    },
+    /// A change request was opened, commented on or resolved
+    ChangeRequest {
+        at: DateTime<Utc>,
+        actor: Option<String>,
+        /// `opened`, `commented`, `applied`, `rejected` or `withdrawn`
+        kind: String,
+        change_request_id: Uuid,
+        title: String,
+    },
+}
+
+impl HistoryEntry {
+    fn at(&self) -> DateTime<Utc> {
+        match self {
+            HistoryEntry::Revision { at, .. }
+            | HistoryEntry::Snapshot { at, .. }
+            | HistoryEntry::ChangeRequest { at, .. } => *at,
+        }
+    }
+}
+
+type RevisionRow = (DateTime<Utc>, Option<String>, String, Vec<String>, String, Option<Uuid>, i32);
+type SnapshotRow = (DateTime<Utc>, i64, bool, DateTime<Utc>, Vec<String>, i32);
+type ChangeRequestRow = (DateTime<Utc>, Option<String>, String, Uuid, String);
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to load note history");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// GET /api/notes/:id/history?before=&limit=
+///
+/// The note's revisions, CRDT snapshots and change request activity, newest
+/// first. Page back by passing the oldest `at` returned as `before`. Deleted
+/// notes keep their history until they are purged.
+pub async fn note_history(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryEntry>>, StatusCode> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM notes WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(db_error)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let before = query.before.unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let revisions: Vec<RevisionRow> = sqlx::query_as(
+        "SELECT recorded_at, actor, kind, fields, title, folder_id, content_length
+         FROM note_revisions
+         WHERE note_id = $1 AND recorded_at < $2
+         ORDER BY recorded_at DESC
+         LIMIT $3",
+    )
+    .bind(id)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let snapshots: Vec<SnapshotRow> = sqlx::query_as(
+        "SELECT * FROM (
+             SELECT recorded_at, id, false AS archived, started_at, actors, length(ydoc_state)
+             FROM crdt_snapshots WHERE note_id = $1
+             UNION ALL
+             SELECT recorded_at, id, true, started_at, actors, size
+             FROM crdt_snapshot_archive WHERE note_id = $1
+         ) snapshots
+         WHERE recorded_at < $2
+         ORDER BY recorded_at DESC
+         LIMIT $3",
+    )
+    .bind(id)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let change_requests: Vec<ChangeRequestRow> = sqlx::query_as(
+        "SELECT * FROM (
+             SELECT created_at AS at, author AS actor, 'opened' AS kind, id, title
+             FROM change_requests WHERE note_id = $1
+             UNION ALL
+             SELECT resolved_at, resolved_by, status, id, title
+             FROM change_requests WHERE note_id = $1 AND resolved_at IS NOT NULL
+             UNION ALL
+             SELECT c.created_at, c.author, 'commented', r.id, r.title
+             FROM change_request_comments c
+             JOIN change_requests r ON r.id = c.change_request_id
+             WHERE r.note_id = $1
+         ) events
+         WHERE at < $2
+         ORDER BY at DESC
+         LIMIT $3",
+    )
+    .bind(id)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut entries: Vec<HistoryEntry> = revisions
+        .into_iter()
+        .map(|(at, actor, kind, fields, title, folder_id, content_length)| HistoryEntry::Revision {
+            at,
+            actor,
+            kind,
+            fields,
+            title,
+            folder_id,
+            content_length,
+        })
+        .chain(snapshots.into_iter().map(|(at, snapshot_id, archived, started_at, actors, size)| HistoryEntry::Snapshot {
+            at,
+            snapshot_id,
+            archived,
+            started_at,
+            actors,
+            size,
+        }))
+        .chain(change_requests.into_iter().map(|(at, actor, kind, change_request_id, title)| {
+            HistoryEntry::ChangeRequest { at, actor, kind, change_request_id, title }
+        }))
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.at()));
+    entries.truncate(limit as usize);
+    Ok(Json(entries))
+}
+
+/// GET /api/notes/:id/history/snapshots/:snapshot_id
+///
+/// The Yjs document state saved by a snapshot, as `application/octet-stream`,
+/// from the hot table or cold storage.
+pub async fn snapshot_state(
+    State(state): State<AppState>,
+    Path((id, snapshot_id)): Path<(Uuid, i64)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let hot: Option<Vec<u8>> =
+        sqlx::query_scalar("SELECT ydoc_state FROM crdt_snapshots WHERE id = $1 AND note_id = $2")
+            .bind(snapshot_id)
+            .bind(id)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(db_error)?;
+    let ydoc_state = match hot {
+        Some(ydoc_state) => ydoc_state,
+        None => {
+            let archived: Vec<u8> = sqlx::query_scalar(
+                "SELECT ydoc_state_gz FROM crdt_snapshot_archive WHERE id = $1 AND note_id = $2",
+            )
+            .bind(snapshot_id)
+            .bind(id)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(db_error)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+            snapshots::decompress(&archived).map_err(|err| {
+                tracing::error!(?err, snapshot_id, "archived snapshot is corrupt");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], ydoc_state))
+}
@@ -0,0 +1,193 @@
+//! Per-user pins and manual note order.
+//!
+//! Pin state and manual order are preferences of the person looking at a
+//! folder, not properties of the notes, so they are stored per user instead of
+//! on the shared rows. Notes a user never pinned or ordered have no entry and
+//! keep the client's default order, after the ordered ones.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, AppState};
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NoteLayout {
+    pub note_id: Uuid,
+    pub folder_id: Option<Uuid>,
+    pub pinned: bool,
+    /// Zero-based manual position within the folder; `None` if unordered
+    pub position: Option<i32>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LayoutQuery {
+    /// Only notes in this folder
+    pub folder_id: Option<Uuid>,
+    /// Only notes outside any folder
+    #[serde(default)]
+    pub root: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinRequest {
+    pub pinned: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderRequest {
+    /// Folder being ordered; omit for notes outside any folder
+    pub folder_id: Option<Uuid>,
+    /// Notes of the folder in the order to show them
+    pub note_ids: Vec<Uuid>,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query note layout");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+const LAYOUT_COLUMNS: &str = "l.note_id, n.folder_id, l.pinned, l.position, l.updated_at
+     FROM user_note_layout l
+     JOIN notes n ON n.id = l.note_id AND n.is_deleted = false";
+
+/// GET /api/layout?folder_id=
+///
+/// The signed-in user's pins and manual positions, for every folder or for
+/// one (`folder_id`, or `root=true` for notes outside any folder).
+pub async fn get_layout(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<LayoutQuery>,
+) -> Result<Json<Vec<NoteLayout>>, StatusCode> {
+    let layout = sqlx::query_as::<_, NoteLayout>(&format!(
+        "SELECT {}
+         WHERE l.username = $1
+           AND ($2::uuid IS NULL OR n.folder_id = $2)
+           AND (NOT $3 OR n.folder_id IS NULL)
+           AND (l.pinned OR l.position IS NOT NULL)
+         ORDER BY n.folder_id NULLS FIRST, l.position NULLS LAST",
+        LAYOUT_COLUMNS
+    ))
+    .bind(&user)
+    .bind(query.folder_id)
+    .bind(query.root)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(layout))
+}
+
+/// PUT /api/notes/:id/pin
+///
+/// Pin or unpin a note for the signed-in user only
+pub async fn set_pinned(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+    Json(req): Json<PinRequest>,
+) -> Result<Json<NoteLayout>, StatusCode> {
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM notes WHERE id = $1 AND is_deleted = false)")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    sqlx::query(
+        "INSERT INTO user_note_layout (username, note_id, pinned) VALUES ($1, $2, $3)
+         ON CONFLICT (username, note_id) DO UPDATE SET pinned = EXCLUDED.pinned, updated_at = now()",
+    )
+    .bind(&user)
+    .bind(id)
+    .bind(req.pinned)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let layout = sqlx::query_as::<_, NoteLayout>(&format!(
+        "SELECT {} WHERE l.username = $1 AND l.note_id = $2",
+        LAYOUT_COLUMNS
+    ))
+    .bind(&user)
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+    Ok(Json(layout))
+}
+
+/// PUT /api/layout/order
+///
+/// Set the signed-in user's manual order for a folder. The listed notes take
+/// positions in the order given; the folder's other notes lose theirs. Every
+/// listed note must be a live note of the folder.
+pub async fn set_order(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Json(req): Json<OrderRequest>,
+) -> Result<Json<Vec<NoteLayout>>, StatusCode> {
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let in_folder: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM notes
+         WHERE id = ANY($1) AND is_deleted = false AND folder_id IS NOT DISTINCT FROM $2",
+    )
+    .bind(&req.note_ids)
+    .bind(req.folder_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let mut unique = req.note_ids.clone();
+    unique.sort();
+    unique.dedup();
+    if unique.len() != req.note_ids.len() || in_folder as usize != req.note_ids.len() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    sqlx::query(
+        "UPDATE user_note_layout l SET position = NULL, updated_at = now()
+         FROM notes n
+         WHERE l.username = $1 AND n.id = l.note_id AND n.folder_id IS NOT DISTINCT FROM $2
+           AND l.position IS NOT NULL AND NOT (l.note_id = ANY($3))",
+    )
+    .bind(&user)
+    .bind(req.folder_id)
+    .bind(&req.note_ids)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    sqlx::query(
+        "INSERT INTO user_note_layout (username, note_id, position)
+         SELECT $1, ids.note_id, (ids.ordinality - 1)::int
+         FROM unnest($2::uuid[]) WITH ORDINALITY AS ids(note_id, ordinality)
+         ON CONFLICT (username, note_id) DO UPDATE SET position = EXCLUDED.position, updated_at = now()",
+    )
+    .bind(&user)
+    .bind(&req.note_ids)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let layout = sqlx::query_as::<_, NoteLayout>(&format!(
+        "SELECT {}
+         WHERE l.username = $1 AND n.folder_id IS NOT DISTINCT FROM $2
+           AND (l.pinned OR l.position IS NOT NULL)
+         ORDER BY l.position NULLS LAST",
+        LAYOUT_COLUMNS
+    ))
+    .bind(&user)
+    .bind(req.folder_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+    Ok(Json(layout))
+}
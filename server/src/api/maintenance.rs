@@ -0,0 +1,39 @@
+use axum::{extract::State, http::StatusCode, Json};
+
+use crate::{
+    api::sync_crdt::WsMessage,
+    auth::AuthUser,
+    maintenance::{MaintenanceStatus, MaintenanceUpdate},
+    AppState,
+};
+
+/// GET /api/maintenance — whether the server is accepting writes
+pub async fn get_maintenance(State(state): State<AppState>) -> Json<MaintenanceStatus> {
+    Json(state.maintenance.status())
+}
+
+/// PUT /api/admin/maintenance — switch maintenance mode and notify connected clients
+pub async fn set_maintenance(
+    State(state): State<AppState>,
+    AuthUser(username): AuthUser,
+    Json(payload): Json<MaintenanceUpdate>,
+) -> Result<Json<MaintenanceStatus>, StatusCode> {
+    if !state.quotas.is_admin(&username) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let status = state.maintenance.update(payload);
+    tracing::info!(admin = %username, enabled = status.enabled, "updated maintenance mode");
+
+    if let Some(hub) = &state.sync_hub {
+        let _ = hub
+            .broadcast(WsMessage::Maintenance {
+                enabled: status.enabled,
+                message: status.message.clone(),
+                retry_after_secs: status.retry_after_secs,
+            })
+            .await;
+    }
+
+    Ok(Json(status))
+}
@@ -0,0 +1,126 @@
+//! Creating notes from Markdown, for scripts and CI jobs.
+//!
+//! `POST /api/notes/markdown` takes a Markdown document as the request body and
+//! creates a note from it, converted with the shared `beck_markdown` crate the
+//! way the desktop app imports Markdown files. Front matter can set the
+//! `title`, the `folder` (an id or a path of folder names like `Team/Weekly`)
+//! and `tags`, which are added to the note as a closing line of hashtags. The
+//! `title` and `folder_id` query parameters take precedence over it. Without a
+//! title, a leading `# Heading` becomes the title, else "Untitled". The note
+//! gets its CRDT state straight away (see `ydoc`), so it opens in editors with
+//! its formatting.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use beck_markdown::{markdown_to_html, split_front_matter, tags_html};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    api::{error::ApiError, history, notes},
+    auth::AuthUser,
+    db::models::Note,
+    quota::{self, QuotaKind},
+    titles, AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct MarkdownQuery {
+    pub title: Option<String>,
+    pub folder_id: Option<Uuid>,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to create note from Markdown");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Title from a leading `# Heading`, and the Markdown after it
+fn heading_title(markdown: &str) -> Option<(String, &str)> {
+    let rest = markdown.trim_start().strip_prefix("# ")?;
+    let (heading, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let heading = heading.trim();
+    (!heading.is_empty()).then(|| (heading.to_string(), body))
+}
+
+/// A live folder, by id or by a path of names from the top level
+/// (case-insensitive)
+async fn resolve_folder(state: &AppState, folder: &str) -> Result<Option<Uuid>, StatusCode> {
+    if let Ok(id) = Uuid::parse_str(folder.trim()) {
+        return sqlx::query_scalar("SELECT id FROM folders WHERE id = $1 AND is_deleted = false")
+            .bind(id)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(db_error);
+    }
+    let mut parent: Option<Uuid> = None;
+    for name in folder.split('/').map(str::trim).filter(|name| !name.is_empty()) {
+        let id: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM folders
+             WHERE parent_id IS NOT DISTINCT FROM $1 AND lower(name) = lower($2) AND is_deleted = false
+             ORDER BY created_at, id
+             LIMIT 1",
+        )
+        .bind(parent)
+        .bind(name)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        parent = Some(id);
+    }
+    Ok(parent)
+}
+
+/// POST /api/notes/markdown?title=&folder_id=
+///
+/// 201 with the created note; 422 for a folder that doesn't exist.
+pub async fn create_note(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<MarkdownQuery>,
+    markdown: String,
+) -> Result<(StatusCode, Json<Note>), ApiError> {
+    let (meta, body) = split_front_matter(&markdown);
+    let (title, body) = match query.title.or(meta.title).filter(|title| !title.trim().is_empty()) {
+        Some(title) => (title, body),
+        None => heading_title(body).unwrap_or_else(|| ("Untitled".to_string(), body)),
+    };
+    let folder_id = match query.folder_id.map(|id| id.to_string()).or(meta.folder) {
+        Some(folder) => Some(resolve_folder(&state, &folder).await?.ok_or(StatusCode::UNPROCESSABLE_ENTITY)?),
+        None => None,
+    };
+    let content = format!("{}{}", markdown_to_html(body), tags_html(&meta.tags));
+    let id = Uuid::new_v4();
+
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let previous_usage = quota::usage(&mut tx, &user, QuotaKind::Notes).await.map_err(db_error)?;
+    history::set_actor(&mut tx, &user).await.map_err(db_error)?;
+    if state.unique_titles {
+        titles::check(&mut tx, id, &title, folder_id, false).await?;
+    }
+    let record = sqlx::query_as::<_, Note>(
+        "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner)
+         VALUES ($1, $2, $3, $4, now(), false, false, $5)
+         RETURNING id, title, content, folder_id, updated_at, is_deleted, is_canvas",
+    )
+    .bind(id)
+    .bind(&title)
+    .bind(&content)
+    .bind(folder_id)
+    .bind(&user)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    quota::check(&mut tx, &state.quotas, &user, QuotaKind::Notes, previous_usage).await?;
+    notes::seed_crdt_state(&mut tx, id, &record.content).await.map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    notes::broadcast_metadata(&state, &record).await;
+    Ok((StatusCode::CREATED, Json(record)))
+}
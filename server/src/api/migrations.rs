@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{auth::AuthUser, migrations::{self, MigrationStatus}, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct MigrationQuery {
+    /// Also replay the migrations into a shadow schema (see
+    /// `migrations::validate_shadow`)
+    #[serde(default)]
+    pub validate: bool,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to check migrations");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// GET /api/admin/migrations?validate=
+///
+/// Applied, pending and divergent migrations. A running server has applied
+/// everything it knows, so pending migrations show up here when the database
+/// is shared with an older build. Validation never touches the live schema;
+/// `beck-server migrate --check` also dry-runs the pending migrations.
+pub async fn get_migrations(
+    State(state): State<AppState>,
+    AuthUser(username): AuthUser,
+    Query(query): Query<MigrationQuery>,
+) -> Result<Json<MigrationStatus>, StatusCode> {
+    if !state.quotas.is_admin(&username) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let mut status = migrations::status(&state.pool).await.map_err(db_error)?;
+    if query.validate {
+        status.validation = Some(migrations::validate_shadow(&state.pool).await.map_err(db_error)?);
+    }
+    Ok(Json(status))
+}
@@ -1,26 +1,116 @@
-use axum::{routing::{get, post}, Router};
+use axum::{middleware, routing::{delete, get, post, put}, Router};
 
 use crate::AppState;
 
+pub mod asset_manifest;
+pub mod assets;
 pub mod auth;
+pub mod change_requests;
+pub mod changes;
+pub mod client;
+pub mod contributors;
+pub mod crashes;
+pub mod embeds;
+pub mod error;
+pub mod export;
+pub mod feeds;
 pub mod folders;
+pub mod highlights;
+pub mod history;
+pub mod layout;
+pub mod maintenance;
+pub mod markdown;
+pub mod migrations;
 pub mod notes;
+pub mod quotas;
+pub mod retention;
+pub mod stale;
+pub mod stats;
 pub mod sync;
+pub mod sync_boards;
 pub mod sync_crdt;
 pub mod sync_folders;
+pub mod sync_relations;
+pub mod templates;
+pub mod uploads;
+pub mod watches;
+pub mod workspace;
 
-pub fn router() -> Router<AppState> {
+pub fn router(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/health", get(|| async { "ok" }))
         .route("/auth", post(auth::login))
+        .route("/client/latest", get(client::latest_client))
+        .route("/maintenance", get(maintenance::get_maintenance))
+        .route("/admin/maintenance", put(maintenance::set_maintenance))
+        .route("/admin/migrations", get(migrations::get_migrations))
+        .route("/admin/crashes", get(crashes::list_crashes))
+        .route("/retention", get(retention::get_retention))
+        .route("/admin/retention", put(retention::set_retention))
         .route("/notes", get(notes::list_notes).post(notes::save_note))
+        .route("/notes/changes", get(notes::list_note_changes))
+        .route("/notes/markdown", post(markdown::create_note))
+        .route("/notes/stale", get(stale::list_stale_notes))
+        .route("/changes", get(changes::list_changes))
+        .route("/highlights", get(highlights::list_highlights))
+        .route("/stats/activity", get(stats::get_activity))
         .route("/notes/:id", get(notes::get_note).delete(notes::delete_note))
+        .route("/notes/:id/export", get(export::export_note))
+        .route("/notes/:id/print", get(export::print_note))
+        .route("/notes/:id/history", get(history::note_history))
+        .route("/notes/:id/history/snapshots/:snapshot_id", get(history::snapshot_state))
+        .route("/notes/:id/contributors", get(contributors::note_contributors))
+        .route("/notes/:id/embeds", get(embeds::list_embeds).post(embeds::create_embed))
+        .route("/embeds/:id", delete(embeds::delete_embed))
+        .route("/notes/:id/pin", put(layout::set_pinned))
+        .route("/layout", get(layout::get_layout))
+        .route("/layout/order", put(layout::set_order))
+        .route(
+            "/notes/:id/change-requests",
+            get(change_requests::list_note_change_requests).post(change_requests::create_change_request),
+        )
+        .route("/change-requests", get(change_requests::list_change_requests))
+        .route(
+            "/change-requests/:id",
+            get(change_requests::get_change_request).put(change_requests::update_change_request),
+        )
+        .route("/change-requests/:id/comments", post(change_requests::add_comment))
+        .route("/change-requests/:id/apply", post(change_requests::apply_change_request))
+        .route("/change-requests/:id/reject", post(change_requests::reject_change_request))
+        .route("/change-requests/:id/withdraw", post(change_requests::withdraw_change_request))
+        .route("/diagrams/render", post(export::render_diagram))
+        .route("/assets", post(assets::upload_asset))
+        .route("/assets/manifest", post(asset_manifest::compare_manifest))
+        .route("/assets/uploads", post(uploads::create_upload))
+        .route(
+            "/assets/uploads/:id",
+            get(uploads::get_upload).patch(uploads::upload_chunk).delete(uploads::cancel_upload),
+        )
+        .route("/assets/uploads/:id/complete", post(uploads::complete_upload))
+        .route("/assets/:id", get(assets::download_asset).delete(assets::delete_asset))
+        .route("/assets/:id/info", get(assets::get_asset_info))
+        .route("/usage", get(quotas::get_usage))
+        .route("/admin/users/:username/quota", get(quotas::get_user_quota).put(quotas::set_user_quota))
+        .route("/admin/workspace/export", get(workspace::export_workspace))
+        .route("/admin/workspace/import", post(workspace::import_workspace))
+        .route("/templates", get(templates::list_templates).post(templates::save_template))
+        .route("/templates/:id", delete(templates::delete_template))
+        .route("/watches", get(watches::list_watches).post(watches::create_watch))
+        .route("/watches/activity", get(watches::watch_activity))
+        .route("/watches/:id", delete(watches::delete_watch))
         .route("/folders", get(folders::list_folders).post(folders::save_folder))
         .route("/folders/:id", get(folders::get_folder).delete(folders::delete_folder))
+        .route("/folders/:id/feeds", get(feeds::list_feeds).post(feeds::create_feed))
+        .route("/feeds/:id", delete(feeds::delete_feed))
         .route("/sync", post(sync::sync_notes))
         .route("/sync/folders", post(sync_folders::sync_folders))
+        .route("/sync/boards", post(sync_boards::sync_boards))
+        .route("/sync/relations", post(sync_relations::sync_relations))
         // CRDT sync endpoints
         .route("/sync/crdt", post(sync_crdt::sync_crdt))
+        .route("/crdt/clients", post(contributors::register_client))
         .route("/crdt/:note_id", get(sync_crdt::get_crdt_state))
         .route("/ws", get(sync_crdt::ws_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), crate::crash_reports::report_server_errors))
+        .route_layer(middleware::from_fn_with_state(state, crate::maintenance::guard))
 }
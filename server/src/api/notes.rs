@@ -1,11 +1,23 @@
-use axum::{extract::{Path, Query, State}, Json};
-use serde::Deserialize;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
 use uuid::Uuid;
-use yrs::{Doc, ReadTxn, Transact, StateVector, XmlFragment as XmlFragmentTrait, XmlFragmentRef, XmlTextPrelim, XmlElementPrelim};
-use yrs::types::xml::XmlIn;
-use yrs::updates::encoder::Encode;
 
-use crate::{db::models::Note, AppState, api::sync_crdt::{WsMessage, NoteMetadata}};
+use crate::{
+    api::{error::ApiError, history, sync_crdt::{NoteMetadata, WsMessage}},
+    auth::AuthUser,
+    db::models::Note,
+    quota::{self, QuotaKind},
+    titles,
+    AppState,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct NoteInput {
@@ -15,7 +27,44 @@ pub struct NoteInput {
     pub folder_id: Option<Uuid>,
     pub is_deleted: Option<bool>,
     pub is_canvas: Option<bool>,
-    pub updated_at: Option<String>,
+    /// Ignored by `conflict=stamp`; the note's own timestamp for `lww`; the
+    /// version the client last saw for `check`
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// How `POST /api/notes` treats a note that changed on the server
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveMode {
+    /// Always write and stamp `updated_at = now()` (the default)
+    #[default]
+    Stamp,
+    /// Last writer wins by the client's `updated_at`, like `/api/sync`: the
+    /// write only lands if it is newer than the stored note
+    Lww,
+    /// Write only if the stored note is still at the client's `updated_at`;
+    /// otherwise 409 with the current note
+    Check,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveQuery {
+    #[serde(default)]
+    pub conflict: SaveMode,
+}
+
+/// 409 body for a `conflict=check` save against a note that moved on
+#[derive(Debug, Serialize)]
+pub struct VersionConflict {
+    pub error: &'static str,
+    pub message: String,
+    pub current: Note,
+}
+
+impl IntoResponse for VersionConflict {
+    fn into_response(self) -> Response {
+        (StatusCode::CONFLICT, Json(self)).into_response()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -68,12 +117,164 @@ pub async fn list_notes(
     Ok(Json(records))
 }
 
-pub async fn get_note(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<Note>, axum::http::StatusCode> {
+/// Default and maximum page sizes for the changes feed
+const CHANGES_DEFAULT_LIMIT: i64 = 500;
+const CHANGES_MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    /// Only return notes changed strictly after this instant
+    pub since: Option<DateTime<Utc>>,
+    /// Opaque continuation token from a previous page (takes precedence over `since`)
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    Upserted,
+    Deleted,
+}
+
+/// Lightweight change record for thin clients (no note body)
+#[derive(Debug, Serialize)]
+pub struct NoteChange {
+    pub id: Uuid,
+    pub change_type: ChangeType,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteChangesResponse {
+    pub changes: Vec<NoteChange>,
+    /// Pass back as `cursor` to resume after the last returned change; always
+    /// set, even when nothing changed
+    pub next_cursor: Option<String>,
+    /// More changes are available right now; fetch again with `next_cursor`
+    pub has_more: bool,
+    pub server_time: DateTime<Utc>,
+}
+
+/// Where a page of changes starts
+enum ChangesFrom {
+    /// After this sequence number in the change feed
+    Seq(i64),
+    /// After this instant
+    Time(DateTime<Utc>),
+}
+
+/// Cursors are the change-feed sequence number (see `changes`) of the last
+/// change returned. Sequence order is commit order, so unlike `updated_at`,
+/// which is stamped when a write's transaction starts, a change that commits
+/// later can't land behind the cursor.
+fn encode_changes_cursor(seq: i64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("seq:{}", seq))
+}
+
+/// Cursors from before they were sequence numbers (`updated_at|id`) resume
+/// from their timestamp, like `since`
+fn decode_changes_cursor(cursor: &str) -> Option<ChangesFrom> {
+    let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    if let Some(seq) = raw.strip_prefix("seq:") {
+        return seq.parse().ok().map(ChangesFrom::Seq);
+    }
+    let (ts, _) = raw.split_once('|')?;
+    let updated_at = DateTime::parse_from_rfc3339(ts).ok()?.with_timezone(&Utc);
+    Some(ChangesFrom::Time(updated_at))
+}
+
+/// List note changes (including deletions) without note bodies, in the order
+/// they committed. A note changed several times is listed once, at its last
+/// change.
+pub async fn list_note_changes(
+    State(state): State<AppState>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<NoteChangesResponse>, axum::http::StatusCode> {
+    let limit = query.limit.unwrap_or(CHANGES_DEFAULT_LIMIT).clamp(1, CHANGES_MAX_LIMIT);
+    let server_time = Utc::now();
+    let db_error = |err: sqlx::Error| {
+        tracing::error!(?err, "failed to list note changes");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let from = match query.cursor.as_deref() {
+        Some(cursor) => Some(decode_changes_cursor(cursor).ok_or(axum::http::StatusCode::BAD_REQUEST)?),
+        None => query.since.map(ChangesFrom::Time),
+    };
+    let after_seq = match from {
+        Some(ChangesFrom::Seq(seq)) => seq,
+        // Start before the first change recorded after the instant; changes
+        // recorded just before it may come again, but none are skipped
+        Some(ChangesFrom::Time(since)) => sqlx::query_scalar(
+            "SELECT COALESCE(
+                (SELECT MIN(seq) FROM changes WHERE entity = 'note' AND recorded_at > $1),
+                (SELECT MAX(seq) + 1 FROM changes),
+                1
+             ) - 1",
+        )
+        .bind(since)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(db_error)?,
+        None => 0,
+    };
+
+    let rows = sqlx::query_as::<_, (i64, Uuid, bool, DateTime<Utc>)>(
+        "SELECT c.seq, n.id, n.is_deleted, n.updated_at
+         FROM (
+            SELECT entity_id, MAX(seq) AS seq FROM changes
+            WHERE entity = 'note' AND seq > $1
+            GROUP BY entity_id
+         ) c
+         JOIN notes n ON n.id::text = c.entity_id
+         ORDER BY c.seq ASC
+         LIMIT $2",
+    )
+    .bind(after_seq)
+    .bind(limit + 1)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    let has_more = rows.len() as i64 > limit;
+    let rows: Vec<_> = rows.into_iter().take(limit as usize).collect();
+    let last_seq = rows.last().map_or(after_seq, |(seq, ..)| *seq);
+    let changes = rows
+        .into_iter()
+        .map(|(_, id, is_deleted, updated_at)| NoteChange {
+            id,
+            change_type: if is_deleted { ChangeType::Deleted } else { ChangeType::Upserted },
+            updated_at,
+        })
+        .collect();
+
+    Ok(Json(NoteChangesResponse {
+        changes,
+        next_cursor: Some(encode_changes_cursor(last_seq)),
+        has_more,
+        server_time,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetNoteQuery {
+    /// Return the note even if it is in the trash
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+pub async fn get_note(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<GetNoteQuery>,
+) -> Result<Json<Note>, axum::http::StatusCode> {
     let note_id = Uuid::parse_str(&id).map_err(|_| axum::http::StatusCode::BAD_REQUEST)?;
     let record = sqlx::query_as::<_, Note>(
-        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas FROM notes WHERE id = $1",
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas FROM notes WHERE id = $1 AND ($2 OR is_deleted = false)",
     )
     .bind(note_id)
+    .bind(query.include_deleted)
     .fetch_optional(&state.pool)
     .await
     .map_err(|err| {
@@ -87,30 +288,125 @@ pub async fn get_note(State(state): State<AppState>, Path(id): Path<String>) ->
     }
 }
 
-pub async fn save_note(State(state): State<AppState>, Json(note): Json<NoteInput>) -> Result<Json<Note>, axum::http::StatusCode> {
+/// POST /api/notes?conflict=stamp|lww|check (see `SaveMode`)
+pub async fn save_note(
+    State(state): State<AppState>,
+    AuthUser(owner): AuthUser,
+    Query(query): Query<SaveQuery>,
+    Json(note): Json<NoteInput>,
+) -> Result<Json<Note>, ApiError> {
     let id = note.id.unwrap_or_else(Uuid::new_v4);
     let is_deleted = note.is_deleted.unwrap_or(false);
     let is_canvas = note.is_canvas.unwrap_or(false);
+    if query.conflict == SaveMode::Lww && note.updated_at.is_none() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
 
-    let record = sqlx::query_as::<_, Note>(
-        "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas) VALUES ($1, $2, $3, $4, now(), $5, $6)
-         ON CONFLICT (id) DO UPDATE SET title = EXCLUDED.title, content = EXCLUDED.content, folder_id = EXCLUDED.folder_id, updated_at = now(), is_deleted = EXCLUDED.is_deleted, is_canvas = EXCLUDED.is_canvas
-         RETURNING id, title, content, folder_id, updated_at, is_deleted, is_canvas",
-    )
-    .bind(id)
-    .bind(&note.title)
-    .bind(&note.content)
-    .bind(note.folder_id)
-    .bind(is_deleted)
-    .bind(is_canvas)
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|err| {
+    let mut tx = state.pool.begin().await.map_err(|err| {
+        tracing::error!(?err, "failed to open transaction");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let previous_usage = quota::usage(&mut tx, &owner, QuotaKind::Notes).await.map_err(|err| {
+        tracing::error!(?err, "failed to read note usage");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    history::set_actor(&mut tx, &owner).await.map_err(|err| {
+        tracing::error!(?err, "failed to record history actor");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if state.unique_titles {
+        titles::check(&mut tx, id, &note.title, note.folder_id, is_deleted).await?;
+    }
+
+    if query.conflict == SaveMode::Check {
+        let current = sqlx::query_as::<_, Note>(
+            "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas FROM notes WHERE id = $1 FOR UPDATE",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to load note for version check");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if let Some(current) = current {
+            if note.updated_at != Some(current.updated_at) {
+                return Err(ApiError::VersionConflict(VersionConflict {
+                    error: "version_conflict",
+                    message: "The note was changed since this version was loaded".to_string(),
+                    current,
+                }));
+            }
+        }
+    }
+
+    // With LWW an older write doesn't land and the stored note is returned
+    let (upsert, timestamp) = match query.conflict {
+        SaveMode::Lww => (
+            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner) VALUES ($1, $2, $3, $4, $8, $5, $6, $7)
+             ON CONFLICT (id) DO UPDATE SET title = EXCLUDED.title, content = EXCLUDED.content, folder_id = EXCLUDED.folder_id, updated_at = EXCLUDED.updated_at, is_deleted = EXCLUDED.is_deleted, is_canvas = EXCLUDED.is_canvas, owner = COALESCE(notes.owner, EXCLUDED.owner)
+             WHERE notes.updated_at < EXCLUDED.updated_at
+             RETURNING id, title, content, folder_id, updated_at, is_deleted, is_canvas",
+            note.updated_at,
+        ),
+        SaveMode::Stamp | SaveMode::Check => (
+            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner) VALUES ($1, $2, $3, $4, now(), $5, $6, $7)
+             ON CONFLICT (id) DO UPDATE SET title = EXCLUDED.title, content = EXCLUDED.content, folder_id = EXCLUDED.folder_id, updated_at = now(), is_deleted = EXCLUDED.is_deleted, is_canvas = EXCLUDED.is_canvas, owner = COALESCE(notes.owner, EXCLUDED.owner)
+             RETURNING id, title, content, folder_id, updated_at, is_deleted, is_canvas",
+            None,
+        ),
+    };
+    let mut upsert = sqlx::query_as::<_, Note>(upsert)
+        .bind(id)
+        .bind(&note.title)
+        .bind(&note.content)
+        .bind(note.folder_id)
+        .bind(is_deleted)
+        .bind(is_canvas)
+        .bind(&owner);
+    if let Some(timestamp) = timestamp {
+        upsert = upsert.bind(timestamp);
+    }
+    let written = upsert.fetch_optional(&mut *tx).await.map_err(|err| {
         tracing::error!(?err, "failed to save note");
         axum::http::StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    // Broadcast metadata update via WebSocket
+    let Some(record) = written else {
+        let current = sqlx::query_as::<_, Note>(
+            "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas FROM notes WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to load note after a stale write");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        return Ok(Json(current));
+    };
+
+    // Notes created over REST get a CRDT state too, so they sync
+    if !note.content.is_empty() && !is_canvas {
+        seed_crdt_state(&mut tx, id, &note.content).await.map_err(|err| {
+            tracing::error!(?err, %id, "failed to seed CRDT state");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+    // Dropping the transaction on a quota error rolls the save back
+    quota::check(&mut tx, &state.quotas, &owner, QuotaKind::Notes, previous_usage).await?;
+    tx.commit().await.map_err(|err| {
+        tracing::error!(?err, "failed to commit note");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    broadcast_metadata(&state, &record).await;
+
+    Ok(Json(record))
+}
+
+/// Tell connected clients about a note's new metadata
+pub(crate) async fn broadcast_metadata(state: &AppState, record: &Note) {
     if let Some(hub) = &state.sync_hub {
         let meta = NoteMetadata {
             id: record.id,
@@ -125,75 +421,32 @@ pub async fn save_note(State(state): State<AppState>, Json(note): Json<NoteInput
             let _ = hub.broadcast(WsMessage::NoteMetadata { payload }).await;
         }
     }
-
-    // Also create/update CRDT state if content is provided
-    // This ensures notes created via the REST API have CRDT states for sync
-    if !note.content.is_empty() && !is_canvas {
-        // Check if CRDT state already exists
-        let existing_crdt: Option<Vec<u8>> = sqlx::query_scalar(
-            "SELECT ydoc_state FROM crdt_states WHERE note_id = $1"
-        )
-        .bind(id)
-        .fetch_optional(&state.pool)
-        .await
-        .unwrap_or(None);
-
-        if existing_crdt.is_none() {
-            // Create initial CRDT state from content using XmlFragment
-            // This matches the client's Yjs structure (TipTap uses XmlFragment)
-            let doc = Doc::new();
-            {
-                let fragment: XmlFragmentRef = doc.get_or_insert_xml_fragment("content");
-                let mut txn = doc.transact_mut();
-                // Create a paragraph element with the text content
-                // This is a simplified structure - full HTML parsing would be better
-                // but the client will sync proper rich text structure on first edit
-                let plain_text = html_to_text(&note.content);
-                if !plain_text.is_empty() {
-                    // Insert a paragraph with text content using the correct API
-                    let text_prelim = XmlTextPrelim::new(&plain_text);
-                    let p_prelim = XmlElementPrelim::new("paragraph", vec![XmlIn::Text(text_prelim.into())]);
-                    fragment.insert(&mut txn, 0, p_prelim);
-                }
-            }
-            let ydoc_state = doc.transact().encode_state_as_update_v1(&StateVector::default());
-            let state_vector = doc.transact().state_vector().encode_v1();
-
-            let _ = sqlx::query(
-                "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
-                 VALUES ($1, $2, $3, now())
-                 ON CONFLICT (note_id) DO NOTHING"
-            )
-            .bind(id)
-            .bind(&ydoc_state)
-            .bind(&state_vector)
-            .execute(&state.pool)
-            .await;
-        }
-    }
-
-    Ok(Json(record))
 }
 
-/// Simple HTML to text conversion for initial CRDT seeding
-fn html_to_text(html: &str) -> String {
-    // Basic HTML tag stripping - a proper implementation would use an HTML parser
-    let mut result = html.to_string();
-    // Replace common block elements with newlines
-    for tag in &["</p>", "</div>", "</h1>", "</h2>", "</h3>", "</h4>", "</h5>", "</h6>", "<br>", "<br/>", "<br />"] {
-        result = result.replace(tag, "\n");
+/// Give a note without CRDT state one built from its HTML (see `beck_markdown::seed_state`).
+/// Notes that have one keep it; it is authoritative for their content. Runs
+/// in the transaction that writes the note, so a note never commits without
+/// the state it syncs through.
+pub(crate) async fn seed_crdt_state(conn: &mut PgConnection, id: Uuid, content: &str) -> Result<(), sqlx::Error> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM crdt_states WHERE note_id = $1)")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if exists {
+        return Ok(());
     }
-    // Remove all remaining HTML tags
-    let re = regex::Regex::new(r"<[^>]+>").unwrap();
-    result = re.replace_all(&result, "").to_string();
-    // Decode common HTML entities
-    result = result.replace("&nbsp;", " ")
-        .replace("&amp;", "&")
-        .replace("&lt;", "<")
-        .replace("&gt;", ">")
-        .replace("&quot;", "\"");
-    // Trim excess whitespace
-    result.trim().to_string()
+    let (ydoc_state, state_vector) = beck_markdown::seed_state(content);
+    sqlx::query(
+        "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
+         VALUES ($1, $2, $3, now())
+         ON CONFLICT (note_id) DO NOTHING"
+    )
+    .bind(id)
+    .bind(&ydoc_state)
+    .bind(&state_vector)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
 }
 
 pub async fn delete_note(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
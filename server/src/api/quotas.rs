@@ -0,0 +1,101 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    quota::{self, Limits, QuotaKind},
+    AppState,
+};
+
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    pub username: String,
+    pub notes_bytes: i64,
+    pub assets_bytes: i64,
+    pub limits: Limits,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuotaOverride {
+    /// `None` falls back to the server default
+    pub notes_bytes_limit: Option<i64>,
+    pub assets_bytes_limit: Option<i64>,
+    #[serde(default)]
+    pub unlimited: bool,
+}
+
+async fn usage_for(state: &AppState, username: &str) -> Result<UsageResponse, StatusCode> {
+    let mut conn = state.pool.acquire().await.map_err(|err| {
+        tracing::error!(?err, "failed to acquire connection");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let db_error = |err: sqlx::Error| {
+        tracing::error!(?err, "failed to read storage usage");
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    Ok(UsageResponse {
+        username: username.to_string(),
+        notes_bytes: quota::usage(&mut conn, username, QuotaKind::Notes).await.map_err(db_error)?,
+        assets_bytes: quota::usage(&mut conn, username, QuotaKind::Assets).await.map_err(db_error)?,
+        limits: quota::limits_for(&mut conn, &state.quotas, username).await.map_err(db_error)?,
+    })
+}
+
+/// GET /api/usage — storage used by the signed-in user and their limits
+pub async fn get_usage(
+    State(state): State<AppState>,
+    AuthUser(username): AuthUser,
+) -> Result<Json<UsageResponse>, StatusCode> {
+    Ok(Json(usage_for(&state, &username).await?))
+}
+
+/// GET /api/admin/users/:username/quota
+pub async fn get_user_quota(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(username): Path<String>,
+) -> Result<Json<UsageResponse>, StatusCode> {
+    user.require_admin(&state)?;
+    Ok(Json(usage_for(&state, &username).await?))
+}
+
+/// PUT /api/admin/users/:username/quota — override a user's limits
+pub async fn set_user_quota(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(username): Path<String>,
+    Json(payload): Json<QuotaOverride>,
+) -> Result<Json<UsageResponse>, StatusCode> {
+    user.require_admin(&state)?;
+    if payload.notes_bytes_limit.is_some_and(|b| b < 0) || payload.assets_bytes_limit.is_some_and(|b| b < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    sqlx::query(
+        "INSERT INTO user_quotas (username, notes_bytes_limit, assets_bytes_limit, unlimited, updated_at)
+         VALUES ($1, $2, $3, $4, now())
+         ON CONFLICT (username) DO UPDATE SET
+            notes_bytes_limit = EXCLUDED.notes_bytes_limit,
+            assets_bytes_limit = EXCLUDED.assets_bytes_limit,
+            unlimited = EXCLUDED.unlimited,
+            updated_at = now()",
+    )
+    .bind(&username)
+    .bind(payload.notes_bytes_limit)
+    .bind(payload.assets_bytes_limit)
+    .bind(payload.unlimited)
+    .execute(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to save quota override");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tracing::info!(admin = %user.0, %username, "updated storage quota");
+    Ok(Json(usage_for(&state, &username).await?))
+}
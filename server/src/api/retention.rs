@@ -0,0 +1,124 @@
+//! Workspace retention policy.
+//!
+//! The policy (see the `retention` migrations) is enforced by a trigger on
+//! hard deletion of assets, so every path that deletes one is covered; a
+//! refusal surfaces as SQLSTATE `BK423`, which handlers report as 423 Locked
+//! with `retention_error`. The server never hard-deletes notes, so clients
+//! read the policy to apply it to their own purges, such as emptying the
+//! desktop trash; only admins can change it.
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, AppState};
+
+/// SQLSTATE raised by the retention triggers
+const RETENTION_SQLSTATE: &str = "BK423";
+
+/// Longest minimum retention accepted, about a century
+const MAX_RETENTION_DAYS: i32 = 36_500;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RetentionPolicy {
+    /// Assets uploaded (and, on clients, notes trashed) more recently than
+    /// this can't be deleted
+    pub min_retention_days: i32,
+    /// Nothing can be deleted while set
+    pub legal_hold: bool,
+    pub legal_hold_reason: Option<String>,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RetentionUpdate {
+    pub min_retention_days: i32,
+    pub legal_hold: bool,
+    pub legal_hold_reason: Option<String>,
+}
+
+const POLICY_COLUMNS: &str = "min_retention_days, legal_hold, legal_hold_reason, updated_by, updated_at";
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query the retention policy");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Whether `err` is a deletion refused by the retention policy
+pub fn is_retention_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(e) if e.code().as_deref() == Some(RETENTION_SQLSTATE))
+}
+
+/// Map a deletion error to 423 Locked when the retention policy refused it
+pub fn retention_error(context: &'static str) -> impl Fn(sqlx::Error) -> StatusCode {
+    move |err| {
+        if is_retention_violation(&err) {
+            tracing::info!(%err, "{} refused by the retention policy", context);
+            StatusCode::LOCKED
+        } else {
+            tracing::error!(?err, "{}", context);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// GET /api/retention
+pub async fn get_retention(State(state): State<AppState>) -> Result<Json<RetentionPolicy>, StatusCode> {
+    let policy = sqlx::query_as::<_, RetentionPolicy>(&format!(
+        "SELECT {} FROM retention_policy WHERE id",
+        POLICY_COLUMNS
+    ))
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(policy))
+}
+
+/// PUT /api/admin/retention
+///
+/// Replace the policy. A legal hold needs a reason, so whoever meets the
+/// refusal later knows why.
+pub async fn set_retention(
+    State(state): State<AppState>,
+    AuthUser(username): AuthUser,
+    Json(update): Json<RetentionUpdate>,
+) -> Result<Json<RetentionPolicy>, StatusCode> {
+    if !state.quotas.is_admin(&username) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if !(0..=MAX_RETENTION_DAYS).contains(&update.min_retention_days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let reason = update.legal_hold_reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+    if update.legal_hold && reason.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let policy = sqlx::query_as::<_, RetentionPolicy>(&format!(
+        "INSERT INTO retention_policy (id, min_retention_days, legal_hold, legal_hold_reason, updated_by, updated_at)
+         VALUES (true, $1, $2, $3, $4, now())
+         ON CONFLICT (id) DO UPDATE SET
+            min_retention_days = EXCLUDED.min_retention_days,
+            legal_hold = EXCLUDED.legal_hold,
+            legal_hold_reason = EXCLUDED.legal_hold_reason,
+            updated_by = EXCLUDED.updated_by,
+            updated_at = now()
+         RETURNING {}",
+        POLICY_COLUMNS
+    ))
+    .bind(update.min_retention_days)
+    .bind(update.legal_hold)
+    .bind(if update.legal_hold { reason } else { None })
+    .bind(&username)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    tracing::info!(
+        admin = %username,
+        min_retention_days = policy.min_retention_days,
+        legal_hold = policy.legal_hold,
+        "updated the retention policy"
+    );
+    Ok(Json(policy))
+}
@@ -0,0 +1,89 @@
+//! Stale notes, for periodic cleanup of the workspace.
+//!
+//! The server doesn't know when a note was last read, so a note is stale when
+//! neither it nor its collaborative document has been written for the given
+//! number of months. The desktop app also counts notes opened on the device.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Months, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::AppState;
+
+const DEFAULT_LIMIT: i64 = 200;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct StaleQuery {
+    /// Months without writes
+    pub months: u32,
+    /// Comma-separated folder ids (e.g. archive folders) whose notes, and those
+    /// of their subfolders, are left out
+    pub exclude_folders: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StaleNote {
+    pub id: Uuid,
+    pub title: String,
+    pub folder_id: Option<Uuid>,
+    /// Last write to the note or its collaborative document
+    pub last_modified_at: DateTime<Utc>,
+}
+
+/// GET /api/notes/stale?months=6&exclude_folders=&limit=
+///
+/// Live notes not written in the last `months` months, least recently written
+/// first.
+pub async fn list_stale_notes(
+    State(state): State<AppState>,
+    Query(query): Query<StaleQuery>,
+) -> Result<Json<Vec<StaleNote>>, StatusCode> {
+    if query.months == 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let cutoff = Utc::now().checked_sub_months(Months::new(query.months)).ok_or(StatusCode::BAD_REQUEST)?;
+    let excluded = query
+        .exclude_folders
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(Uuid::parse_str)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let notes = sqlx::query_as::<_, StaleNote>(
+        "WITH RECURSIVE excluded(id) AS (
+             SELECT id FROM folders WHERE id = ANY($2)
+             UNION
+             SELECT f.id FROM folders f JOIN excluded e ON f.parent_id = e.id
+         )
+         SELECT n.id, n.title, n.folder_id, GREATEST(n.updated_at, c.updated_at) AS last_modified_at
+         FROM notes n
+         LEFT JOIN crdt_states c ON c.note_id = n.id
+         WHERE NOT n.is_deleted
+           AND GREATEST(n.updated_at, c.updated_at) < $1
+           AND (n.folder_id IS NULL OR n.folder_id NOT IN (SELECT id FROM excluded))
+         ORDER BY last_modified_at, n.id
+         LIMIT $3",
+    )
+    .bind(cutoff)
+    .bind(&excluded)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to list stale notes");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(notes))
+}
@@ -0,0 +1,149 @@
+//! Workspace activity, aggregated from the change feed.
+//!
+//! Every accepted change is already recorded in `changes`, so a heatmap is a
+//! count of feed entries per UTC day. Folder breakdowns join note changes to
+//! the note's current folder; a note moved between folders takes its history
+//! with it, and notes purged from the database count under no folder.
+
+use std::collections::BTreeMap;
+
+use axum::{extract::{Query, State}, http::StatusCode, Json};
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{api::changes::ENTITIES, AppState};
+
+const DEFAULT_RANGE: &str = "1y";
+
+/// Longest range served, in days
+const MAX_RANGE_DAYS: i64 = 731;
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityQuery {
+    /// `<n>d`, `<n>w`, `<n>m` (30 days) or `<n>y` (365 days), ending today
+    pub range: Option<String>,
+    /// Only count changes to one entity type
+    pub entity: Option<String>,
+    /// Only count changes to notes in this folder
+    pub folder_id: Option<Uuid>,
+    /// Also break note changes down by folder
+    #[serde(default)]
+    pub by_folder: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityDay {
+    pub date: NaiveDate,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FolderActivity {
+    /// `None` for notes outside any folder
+    pub folder_id: Option<Uuid>,
+    pub total: i64,
+    pub days: Vec<ActivityDay>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityResponse {
+    /// First and last day covered, inclusive
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total: i64,
+    /// Days with at least one change, in date order
+    pub days: Vec<ActivityDay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folders: Option<Vec<FolderActivity>>,
+}
+
+/// Days covered by a range like `90d` or `1y`
+fn range_days(range: &str) -> Option<i64> {
+    let range = range.trim();
+    let unit = range.chars().last()?;
+    let n: i64 = range[..range.len() - unit.len_utf8()].parse().ok()?;
+    let days = match unit.to_ascii_lowercase() {
+        'd' => n,
+        'w' => n.checked_mul(7)?,
+        'm' => n.checked_mul(30)?,
+        'y' => n.checked_mul(365)?,
+        _ => return None,
+    };
+    (1..=MAX_RANGE_DAYS).contains(&days).then_some(days)
+}
+
+/// GET /api/stats/activity?range=1y
+///
+/// Per-day change counts for contribution-style heatmaps. Filtering by folder
+/// or breaking down by folder counts note changes only.
+pub async fn get_activity(
+    State(state): State<AppState>,
+    Query(query): Query<ActivityQuery>,
+) -> Result<Json<ActivityResponse>, StatusCode> {
+    let days = range_days(query.range.as_deref().unwrap_or(DEFAULT_RANGE)).ok_or(StatusCode::BAD_REQUEST)?;
+    let per_folder = query.folder_id.is_some() || query.by_folder;
+    let entity = match query.entity.as_deref() {
+        Some(e) if !ENTITIES.contains(&e) => return Err(StatusCode::BAD_REQUEST),
+        Some(e) if per_folder && e != "note" => return Err(StatusCode::BAD_REQUEST),
+        Some(e) => Some(e),
+        None if per_folder => Some("note"),
+        None => None,
+    };
+
+    let to = Utc::now().date_naive();
+    let from = to - Duration::days(days - 1);
+    let since = from.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+
+    let rows: Vec<(NaiveDate, Option<Uuid>, i64)> = sqlx::query_as(
+        "SELECT (c.recorded_at AT TIME ZONE 'UTC')::date AS day,
+                CASE WHEN $4 THEN n.folder_id END AS folder,
+                COUNT(*)
+         FROM changes c
+         LEFT JOIN notes n ON c.entity = 'note' AND n.id::text = c.entity_id
+         WHERE c.recorded_at >= $1
+           AND ($2::text IS NULL OR c.entity = $2)
+           AND ($3::uuid IS NULL OR n.folder_id = $3)
+         GROUP BY day, folder
+         ORDER BY day",
+    )
+    .bind(since)
+    .bind(entity)
+    .bind(query.folder_id)
+    .bind(query.by_folder)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to aggregate activity");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut by_day: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    let mut by_folder: BTreeMap<Option<Uuid>, Vec<ActivityDay>> = BTreeMap::new();
+    for (date, folder_id, count) in rows {
+        *by_day.entry(date).or_default() += count;
+        if query.by_folder {
+            by_folder.entry(folder_id).or_default().push(ActivityDay { date, count });
+        }
+    }
+
+    let folders = query.by_folder.then(|| {
+        let mut folders: Vec<FolderActivity> = by_folder
+            .into_iter()
+            .map(|(folder_id, days)| FolderActivity {
+                folder_id,
+                total: days.iter().map(|d| d.count).sum(),
+                days,
+            })
+            .collect();
+        folders.sort_by_key(|f| std::cmp::Reverse(f.total));
+        folders
+    });
+    Ok(Json(ActivityResponse {
+        from,
+        to,
+        total: by_day.values().sum(),
+        days: by_day.into_iter().map(|(date, count)| ActivityDay { date, count }).collect(),
+        folders,
+    }))
+}
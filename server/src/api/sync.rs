@@ -4,7 +4,13 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use uuid::Uuid;
 
-use crate::{db::models::Note, AppState};
+use crate::{
+    api::{error::ApiError, history},
+    auth::AuthUser,
+    db::models::Note,
+    quota::{self, QuotaKind},
+    AppState,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct SyncRequest {
@@ -29,11 +35,33 @@ pub struct SyncResponse {
     pub last_sync: DateTime<Utc>,
 }
 
-pub async fn sync_notes(State(state): State<AppState>, Json(payload): Json<SyncRequest>) -> Result<Json<SyncResponse>, axum::http::StatusCode> {
+pub async fn sync_notes(
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+    Json(payload): Json<SyncRequest>,
+) -> Result<Json<SyncResponse>, ApiError> {
+    // Anyone may pull, but pushed notes count against the pusher's quota
+    let owner = user.map(|AuthUser(name)| name);
+    if owner.is_none() && !payload.notes.is_empty() {
+        return Err(axum::http::StatusCode::UNAUTHORIZED.into());
+    }
     let mut tx = state.pool.begin().await.map_err(|err| {
         tracing::error!(?err, "failed to open transaction");
         axum::http::StatusCode::INTERNAL_SERVER_ERROR
     })?;
+    let previous_usage = match &owner {
+        Some(owner) => quota::usage(&mut tx, owner, QuotaKind::Notes).await.map_err(|err| {
+            tracing::error!(?err, "failed to read note usage");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })?,
+        None => 0,
+    };
+    if let Some(owner) = &owner {
+        history::set_actor(&mut tx, owner).await.map_err(|err| {
+            tracing::error!(?err, "failed to record history actor");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
 
     // Collect IDs of notes the client pushed – we'll exclude these from the pull
     // to avoid echoing back exactly what the client sent.
@@ -42,33 +70,40 @@ pub async fn sync_notes(State(state): State<AppState>, Json(payload): Json<SyncR
     // Apply incoming changes (upserts) with last-writer-wins semantics
     for note in &payload.notes {
         let res = sqlx::query(
-            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
              ON CONFLICT (id) DO UPDATE SET
                 title = EXCLUDED.title,
                 content = EXCLUDED.content,
                 folder_id = EXCLUDED.folder_id,
                 updated_at = EXCLUDED.updated_at,
                 is_deleted = EXCLUDED.is_deleted,
-                is_canvas = EXCLUDED.is_canvas
+                is_canvas = EXCLUDED.is_canvas,
+                owner = COALESCE(notes.owner, EXCLUDED.owner)
              WHERE notes.updated_at < EXCLUDED.updated_at",
         )
-        .bind(&note.id)
+        .bind(note.id)
         .bind(&note.title)
         .bind(&note.content)
-        .bind(&note.folder_id)
+        .bind(note.folder_id)
         .bind(note.updated_at)
         .bind(note.is_deleted)
         .bind(note.is_canvas)
+        .bind(&owner)
         .execute(&mut *tx)
         .await;
 
         if let Err(err) = res {
             tracing::error!(?err, "failed to upsert note during sync");
-            return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR.into());
         }
     }
 
+    // Reject the whole push if it takes the user over quota
+    if let Some(owner) = &owner {
+        quota::check(&mut tx, &state.quotas, owner, QuotaKind::Notes, previous_usage).await?;
+    }
+
     // Pull newer changes from server
     let all_pulled = if let Some(since) = payload.since {
         sqlx::query_as::<_, Note>(
@@ -0,0 +1,171 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::{
+    db::models::{Board, BoardCard, BoardColumn},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SyncBoardsRequest {
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub boards: Vec<Board>,
+    #[serde(default)]
+    pub columns: Vec<BoardColumn>,
+    #[serde(default)]
+    pub cards: Vec<BoardCard>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncBoardsResponse {
+    pub boards: Vec<Board>,
+    pub columns: Vec<BoardColumn>,
+    pub cards: Vec<BoardCard>,
+    pub last_sync: DateTime<Utc>,
+}
+
+fn internal_error(err: sqlx::Error, what: &'static str) -> axum::http::StatusCode {
+    tracing::error!(?err, "{}", what);
+    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+}
+
+pub async fn sync_boards(
+    State(state): State<AppState>,
+    Json(payload): Json<SyncBoardsRequest>,
+) -> Result<Json<SyncBoardsResponse>, axum::http::StatusCode> {
+    tracing::info!(
+        since = ?payload.since,
+        boards = payload.boards.len(),
+        columns = payload.columns.len(),
+        cards = payload.cards.len(),
+        "sync_boards request received"
+    );
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|err| internal_error(err, "failed to open transaction"))?;
+
+    // Don't echo back rows the client just pushed
+    let pushed: HashSet<Uuid> = payload
+        .boards
+        .iter()
+        .map(|b| b.id)
+        .chain(payload.columns.iter().map(|c| c.id))
+        .chain(payload.cards.iter().map(|c| c.id))
+        .collect();
+
+    // Apply incoming changes with last-writer-wins semantics
+    for board in &payload.boards {
+        sqlx::query(
+            "INSERT INTO boards (id, name, folder_id, created_at, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name,
+                folder_id = EXCLUDED.folder_id,
+                updated_at = EXCLUDED.updated_at,
+                is_deleted = EXCLUDED.is_deleted
+             WHERE boards.updated_at < EXCLUDED.updated_at",
+        )
+        .bind(board.id)
+        .bind(&board.name)
+        .bind(board.folder_id)
+        .bind(board.created_at)
+        .bind(board.updated_at)
+        .bind(board.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| internal_error(err, "failed to upsert board during sync"))?;
+    }
+
+    for column in &payload.columns {
+        sqlx::query(
+            "INSERT INTO board_columns (id, board_id, name, position, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name,
+                position = EXCLUDED.position,
+                updated_at = EXCLUDED.updated_at,
+                is_deleted = EXCLUDED.is_deleted
+             WHERE board_columns.updated_at < EXCLUDED.updated_at",
+        )
+        .bind(column.id)
+        .bind(column.board_id)
+        .bind(&column.name)
+        .bind(column.position)
+        .bind(column.updated_at)
+        .bind(column.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| internal_error(err, "failed to upsert board column during sync"))?;
+    }
+
+    for card in &payload.cards {
+        sqlx::query(
+            "INSERT INTO board_cards (id, board_id, column_id, note_id, position, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (id) DO UPDATE SET
+                column_id = EXCLUDED.column_id,
+                position = EXCLUDED.position,
+                updated_at = EXCLUDED.updated_at,
+                is_deleted = EXCLUDED.is_deleted
+             WHERE board_cards.updated_at < EXCLUDED.updated_at",
+        )
+        .bind(card.id)
+        .bind(card.board_id)
+        .bind(card.column_id)
+        .bind(card.note_id)
+        .bind(card.position)
+        .bind(card.updated_at)
+        .bind(card.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| internal_error(err, "failed to upsert board card during sync"))?;
+    }
+
+    // Pull everything changed since the client's last sync (or everything)
+    let since = payload.since.unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+    let boards = sqlx::query_as::<_, Board>(
+        "SELECT id, name, folder_id, created_at, updated_at, is_deleted
+         FROM boards WHERE updated_at > $1",
+    )
+    .bind(since)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|err| internal_error(err, "failed to pull boards"))?;
+
+    let columns = sqlx::query_as::<_, BoardColumn>(
+        "SELECT id, board_id, name, position, updated_at, is_deleted
+         FROM board_columns WHERE updated_at > $1",
+    )
+    .bind(since)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|err| internal_error(err, "failed to pull board columns"))?;
+
+    let cards = sqlx::query_as::<_, BoardCard>(
+        "SELECT id, board_id, column_id, note_id, position, updated_at, is_deleted
+         FROM board_cards WHERE updated_at > $1",
+    )
+    .bind(since)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|err| internal_error(err, "failed to pull board cards"))?;
+
+    tx.commit()
+        .await
+        .map_err(|err| internal_error(err, "failed to commit board sync"))?;
+
+    Ok(Json(SyncBoardsResponse {
+        boards: boards.into_iter().filter(|b| !pushed.contains(&b.id)).collect(),
+        columns: columns.into_iter().filter(|c| !pushed.contains(&c.id)).collect(),
+        cards: cards.into_iter().filter(|c| !pushed.contains(&c.id)).collect(),
+        last_sync: Utc::now(),
+    }))
+}
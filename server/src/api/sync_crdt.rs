@@ -9,6 +9,8 @@ use axum::{
 use chrono::{DateTime, Utc};
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
@@ -17,7 +19,12 @@ use yrs::{Doc, ReadTxn, Transact, Update, StateVector};
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
 
-use crate::AppState;
+use crate::{
+    api::{contributors, error::ApiError, history},
+    auth::AuthUser,
+    quota::{self, QuotaKind},
+    AppState,
+};
 
 // ============================================================================
 // Types for CRDT Sync
@@ -82,13 +89,43 @@ pub enum WsMessage {
     SyncResponse { payload: String },
     /// Note metadata update
     NoteMetadata { payload: String },
+    /// A note the connection's user watches was saved; same payload as
+    /// `NoteMetadata`
+    WatchedChange { payload: String },
+    /// Ephemeral canvas state (drag previews, selection boxes) for the other
+    /// participants of a canvas note. Relayed to the other connections
+    /// subscribed to the note and never stored; `session` and `user` are set
+    /// by the server.
+    CanvasPresence {
+        note_id: String,
+        payload: String,
+        #[serde(default)]
+        session: Option<String>,
+        #[serde(default)]
+        user: Option<String>,
+    },
+    /// A participant left a canvas note; drop their ephemeral state
+    CanvasLeave {
+        note_id: String,
+        session: String,
+        user: Option<String>,
+    },
+    /// Maintenance mode changed; clients should hold writes while enabled
+    Maintenance { enabled: bool, message: String, retry_after_secs: u64 },
     /// Error message
     Error { message: String },
 }
 
+/// Largest canvas presence payload relayed, in bytes
+const MAX_CANVAS_PAYLOAD: usize = 16 * 1024;
+
+/// Row shape for note metadata queries
+type NoteMetadataRow = (Uuid, String, String, Option<Uuid>, bool, bool, DateTime<Utc>);
+
 /// Query params for WebSocket connection
 #[derive(Debug, Deserialize)]
 pub struct WsQuery {
+    /// Identifies the user for watch notifications
     pub token: Option<String>,
 }
 
@@ -139,21 +176,19 @@ pub async fn get_crdt_state(
 // HTTP Endpoint for CRDT Sync (Fallback/Initial Sync)
 // ============================================================================
 
+/// POST /api/sync/crdt
+///
+/// Anyone may pull, but pushing updates or metadata needs a signed-in user:
+/// what is pushed counts against their notes quota, and a push that would take
+/// them over it is refused as a whole (413).
 pub async fn sync_crdt(
     State(state): State<AppState>,
+    user: Option<AuthUser>,
     Json(payload): Json<CrdtSyncRequest>,
-) -> Result<Json<CrdtSyncResponse>, axum::http::StatusCode> {
+) -> Result<Json<CrdtSyncResponse>, ApiError> {
     use base64::{engine::general_purpose::STANDARD, Engine};
 
-    let mut tx = state.pool.begin().await.map_err(|err| {
-        tracing::error!(?err, "failed to open transaction");
-        axum::http::StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    let mut response_updates: HashMap<String, String> = HashMap::new();
-    let mut response_metadata: Vec<NoteMetadata> = Vec::new();
-
-    // Process incoming updates from the client
+    let mut updates = Vec::with_capacity(payload.updates.len());
     for (note_id_str, base64_update) in &payload.updates {
         let note_id: Uuid = note_id_str.parse().map_err(|_| {
             tracing::error!("invalid note_id: {}", note_id_str);
@@ -164,93 +199,35 @@ pub async fn sync_crdt(
             tracing::error!(?err, "failed to decode base64 update");
             axum::http::StatusCode::BAD_REQUEST
         })?;
+        updates.push((note_id, update));
+    }
 
-        // Get existing state if any
-        let existing: Option<CrdtState> = sqlx::query_as(
-            "SELECT note_id, ydoc_state, state_vector, updated_at FROM crdt_states WHERE note_id = $1"
-        )
-        .bind(note_id)
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(|err| {
-            tracing::error!(?err, "failed to fetch existing crdt state");
+    let mut tx = state.pool.begin().await.map_err(|err| {
+        tracing::error!(?err, "failed to open transaction");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if !updates.is_empty() || !payload.metadata.is_empty() {
+        let AuthUser(owner) = user.ok_or(axum::http::StatusCode::UNAUTHORIZED)?;
+        history::set_actor(&mut tx, &owner).await.map_err(|err| {
+            tracing::error!(?err, "failed to record history actor");
             axum::http::StatusCode::INTERNAL_SERVER_ERROR
         })?;
-
-        // Merge or insert the update using yrs
-        let doc = Doc::new();
-        {
-            let mut txn = doc.transact_mut();
-            
-            // Apply existing state if present
-            if let Some(existing_state) = existing {
-                if let Ok(update) = Update::decode_v1(&existing_state.ydoc_state) {
-                     txn.apply_update(update);
-                }
-            }
-            
-            // Apply incoming update
-            if let Ok(update) = Update::decode_v1(&update) {
-                txn.apply_update(update);
-            }
-        }
-
-        let new_state = doc.transact().encode_state_as_update_v1(&StateVector::default());
-        let state_vector = doc.transact().state_vector().encode_v1();
-
-        sqlx::query(
-            "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
-             VALUES ($1, $2, $3, now())
-             ON CONFLICT (note_id) DO UPDATE SET
-                ydoc_state = EXCLUDED.ydoc_state,
-                state_vector = EXCLUDED.state_vector,
-                updated_at = EXCLUDED.updated_at"
-        )
-        .bind(note_id)
-        .bind(&new_state)
-        .bind(&state_vector)
-        .execute(&mut *tx)
-        .await
-        .map_err(|err| {
-            tracing::error!(?err, "failed to upsert crdt state");
+        let previous_usage = quota::usage(&mut tx, &owner, QuotaKind::Notes).await.map_err(|err| {
+            tracing::error!(?err, "failed to read note usage");
             axum::http::StatusCode::INTERNAL_SERVER_ERROR
         })?;
-
-        // Broadcast update to other connected clients
-        if let Some(hub) = &state.sync_hub {
-            let _ = hub.broadcast_update(note_id, &update).await;
-        }
-    }
-
-    // Apply metadata updates
-    for meta in &payload.metadata {
-          sqlx::query(
-                "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas)
-                 VALUES ($1, $2, $3, $4, $5, $6, $7)
-                 ON CONFLICT (id) DO UPDATE SET
-                     title = EXCLUDED.title,
-                     content = EXCLUDED.content,
-                     folder_id = EXCLUDED.folder_id,
-                     is_deleted = EXCLUDED.is_deleted,
-                     is_canvas = EXCLUDED.is_canvas,
-                     updated_at = EXCLUDED.updated_at
-                 WHERE notes.updated_at < EXCLUDED.updated_at"
-          )
-          .bind(meta.id)
-          .bind(&meta.title)
-          .bind(&meta.content)
-          .bind(meta.folder_id)
-          .bind(meta.updated_at)
-          .bind(meta.is_deleted)
-          .bind(meta.is_canvas)
-        .execute(&mut *tx)
-        .await
-        .map_err(|err| {
-            tracing::error!(?err, "failed to upsert note metadata");
+        store_pushed(&mut tx, &owner, &updates, &payload.metadata).await.map_err(|err| {
+            tracing::error!(?err, "failed to store pushed changes");
             axum::http::StatusCode::INTERNAL_SERVER_ERROR
         })?;
+        // Dropping the transaction on a quota error rolls the push back
+        quota::check(&mut tx, &state.quotas, &owner, QuotaKind::Notes, previous_usage).await?;
     }
 
+    let mut response_updates: HashMap<String, String> = HashMap::new();
+    let mut response_metadata: Vec<NoteMetadata> = Vec::new();
+
     // Calculate diffs for each note the client knows about
     for (note_id_str, client_sv_base64) in &payload.state_vectors {
         let note_id: Uuid = match note_id_str.parse() {
@@ -323,9 +300,9 @@ pub async fn sync_crdt(
     };
 
     for (note_id, ydoc_state) in new_notes {
-        if !response_updates.contains_key(&note_id.to_string()) {
-            response_updates.insert(note_id.to_string(), STANDARD.encode(&ydoc_state));
-        }
+        response_updates
+            .entry(note_id.to_string())
+            .or_insert_with(|| STANDARD.encode(&ydoc_state));
     }
 
     // Collect all note IDs the client knows about from the incoming metadata
@@ -337,7 +314,7 @@ pub async fn sync_crdt(
     // This ensures new notes created on the server are sent to the client
     let all_server_notes: Vec<NoteMetadata> = if client_metadata_ids.is_empty() {
         // Client has nothing, send all notes (including deletions)
-        sqlx::query_as::<_, (Uuid, String, String, Option<Uuid>, bool, bool, DateTime<Utc>)>(
+        sqlx::query_as::<_, NoteMetadataRow>(
             "SELECT id, title, content, folder_id, is_deleted, is_canvas, updated_at FROM notes"
         )
         .fetch_all(&mut *tx)
@@ -353,7 +330,7 @@ pub async fn sync_crdt(
         .collect()
     } else {
         // Send notes the client doesn't have, plus notes with newer metadata
-        sqlx::query_as::<_, (Uuid, String, String, Option<Uuid>, bool, bool, DateTime<Utc>)>(
+        sqlx::query_as::<_, NoteMetadataRow>(
             "SELECT id, title, content, folder_id, is_deleted, is_canvas, updated_at FROM notes"
         )
         .fetch_all(&mut *tx)
@@ -384,7 +361,7 @@ pub async fn sync_crdt(
         
         if should_include {
             // If this note has CRDT state but isn't in response_updates yet, add it
-            if !response_updates.contains_key(&note.id.to_string()) {
+            if let Entry::Vacant(slot) = response_updates.entry(note.id.to_string()) {
                 let crdt_state: Option<Vec<u8>> = sqlx::query_scalar(
                     "SELECT ydoc_state FROM crdt_states WHERE note_id = $1"
                 )
@@ -397,7 +374,7 @@ pub async fn sync_crdt(
                 })?;
                 
                 if let Some(state) = crdt_state {
-                    response_updates.insert(note.id.to_string(), STANDARD.encode(&state));
+                    slot.insert(STANDARD.encode(&state));
                 }
             }
             
@@ -410,6 +387,13 @@ pub async fn sync_crdt(
         axum::http::StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    // Broadcast the pushed updates to other connected clients once they landed
+    if let Some(hub) = &state.sync_hub {
+        for (note_id, update) in &updates {
+            let _ = hub.broadcast_update(*note_id, update).await;
+        }
+    }
+
     Ok(Json(CrdtSyncResponse {
         updates: response_updates,
         metadata: response_metadata,
@@ -417,6 +401,120 @@ pub async fn sync_crdt(
     }))
 }
 
+/// Store what a client pushed: updates are merged into the notes' CRDT
+/// documents and metadata is upserted last-writer-wins. Notes written before
+/// owners were recorded become `owner`'s (see `quota`).
+async fn store_pushed(
+    conn: &mut PgConnection,
+    owner: &str,
+    updates: &[(Uuid, Vec<u8>)],
+    metadata: &[NoteMetadata],
+) -> Result<(), sqlx::Error> {
+    for (note_id, update) in updates {
+        let existing: Option<Vec<u8>> = sqlx::query_scalar(
+            "SELECT ydoc_state FROM crdt_states WHERE note_id = $1 FOR UPDATE"
+        )
+        .bind(note_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        // Merge using yrs
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            if let Some(existing) = existing.and_then(|state| Update::decode_v1(&state).ok()) {
+                txn.apply_update(existing);
+            }
+            if let Ok(update) = Update::decode_v1(update) {
+                txn.apply_update(update);
+            }
+        }
+        let new_state = doc.transact().encode_state_as_update_v1(&StateVector::default());
+        let state_vector = doc.transact().state_vector().encode_v1();
+
+        sqlx::query(
+            "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
+             VALUES ($1, $2, $3, now())
+             ON CONFLICT (note_id) DO UPDATE SET
+                ydoc_state = EXCLUDED.ydoc_state,
+                state_vector = EXCLUDED.state_vector,
+                updated_at = EXCLUDED.updated_at"
+        )
+        .bind(note_id)
+        .bind(&new_state)
+        .bind(&state_vector)
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query("UPDATE notes SET owner = $2 WHERE id = $1 AND owner IS NULL")
+            .bind(note_id)
+            .bind(owner)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    for meta in metadata {
+        sqlx::query(
+            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (id) DO UPDATE SET
+                 title = EXCLUDED.title,
+                 content = EXCLUDED.content,
+                 folder_id = EXCLUDED.folder_id,
+                 is_deleted = EXCLUDED.is_deleted,
+                 is_canvas = EXCLUDED.is_canvas,
+                 updated_at = EXCLUDED.updated_at,
+                 owner = COALESCE(notes.owner, EXCLUDED.owner)
+             WHERE notes.updated_at < EXCLUDED.updated_at"
+        )
+        .bind(meta.id)
+        .bind(&meta.title)
+        .bind(&meta.content)
+        .bind(meta.folder_id)
+        .bind(meta.updated_at)
+        .bind(meta.is_deleted)
+        .bind(meta.is_canvas)
+        .bind(owner)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Store what a client pushed over its socket in one transaction, as its user
+/// and within their notes quota. The error is the message to send back.
+async fn store_ws_push(
+    state: &AppState,
+    actor: Option<&str>,
+    updates: &[(Uuid, Vec<u8>)],
+    metadata: &[NoteMetadata],
+) -> Result<(), &'static str> {
+    let Some(actor) = actor else {
+        return Err("Sign in to push changes");
+    };
+    let failed = |err: sqlx::Error| {
+        tracing::error!(?err, "failed to store pushed changes");
+        "Failed to store the changes"
+    };
+    let mut tx = state.pool.begin().await.map_err(failed)?;
+    history::set_actor(&mut tx, actor).await.map_err(failed)?;
+    let previous_usage = quota::usage(&mut tx, actor, QuotaKind::Notes).await.map_err(failed)?;
+    store_pushed(&mut tx, actor, updates, metadata).await.map_err(failed)?;
+    match quota::check(&mut tx, &state.quotas, actor, QuotaKind::Notes, previous_usage).await {
+        Ok(()) => {}
+        Err(ApiError::Quota(_)) => return Err("Storage quota exceeded"),
+        Err(_) => return Err("Failed to store the changes"),
+    }
+    tx.commit().await.map_err(failed)?;
+    Ok(())
+}
+
+/// Send an error to the client on this connection
+async fn send_error(response_tx: &tokio::sync::mpsc::Sender<String>, message: &str) {
+    if let Ok(json) = serde_json::to_string(&WsMessage::Error { message: message.to_string() }) {
+        let _ = response_tx.send(json).await;
+    }
+}
+
 // ============================================================================
 // WebSocket Handler for Real-time Sync
 // ============================================================================
@@ -426,13 +524,17 @@ pub async fn ws_handler(
     Query(query): Query<WsQuery>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    // TODO: Validate JWT token from query.token
-    // For now, accept all connections
-
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+    // TODO: Require a valid JWT token
+    // For now, accept all connections; only a valid token gets watch notifications
+    let user = query
+        .token
+        .and_then(|token| crate::auth::jwt::decode_token(&state.jwt_secret, token.trim()).ok())
+        .map(|claims| claims.sub);
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state, user))
 }
 
-async fn handle_socket(socket: WebSocket, state: AppState) {
+async fn handle_socket(socket: WebSocket, state: AppState, user: Option<String>) {
     tracing::info!("ws connection opened");
     let (mut sender, mut receiver) = socket.split();
 
@@ -456,6 +558,14 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     let (response_tx, mut response_rx) = tokio::sync::mpsc::channel::<String>(32);
 
     let subscribed_notes_clone = subscribed_notes.clone();
+    let watches = state.watches.clone();
+    let actor = user.clone();
+
+    // Identifies this connection's canvas presence to other participants
+    let session = Uuid::new_v4().to_string();
+    let own_session = session.clone();
+    // Canvas notes this connection has sent presence for
+    let mut canvas_notes: std::collections::HashSet<Uuid> = std::collections::HashSet::new();
 
     // Spawn task to handle sending (broadcasts + responses)
     let send_task = tokio::spawn(async move {
@@ -475,22 +585,45 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                             // Broadcast metadata to everyone so they see new notes or title changes
                             true
                         },
+                        WsMessage::Maintenance { .. } => true,
+                        WsMessage::CanvasPresence { note_id, session: Some(from), .. }
+                        | WsMessage::CanvasLeave { note_id, session: from, .. } => {
+                            from != &own_session
+                                && match note_id.parse::<Uuid>() {
+                                    Ok(uuid) => subscribed_notes_clone.read().await.contains(&uuid),
+                                    Err(_) => false,
+                                }
+                        }
                         _ => false,
                     };
+                    // Saves to notes the user watches are also announced as such
+                    let watched = match (&msg, &user) {
+                        (WsMessage::NoteMetadata { payload }, Some(user)) => serde_json::from_str::<NoteMetadata>(payload)
+                            .ok()
+                            .filter(|meta| watches.is_watching(user, meta.id, meta.folder_id))
+                            .map(|_| WsMessage::WatchedChange { payload: payload.clone() }),
+                        _ => None,
+                    };
 
-                    if should_send {
-                        if let Ok(json) = serde_json::to_string(&msg) {
+                    let outgoing = should_send.then_some(&msg).into_iter().chain(watched.as_ref());
+                    let mut closed = false;
+                    for msg in outgoing {
+                        if let Ok(json) = serde_json::to_string(msg) {
                             tracing::info!(?json, "sending ws message");
-                            if sender.send(Message::Text(json.into())).await.is_err() {
+                            if sender.send(Message::Text(json)).await.is_err() {
+                                closed = true;
                                 break;
                             }
                         }
                     }
+                    if closed {
+                        break;
+                    }
                 }
                 // Handle response messages from the receiver task
                 Some(json) = response_rx.recv() => {
                     tracing::info!(?json, "sending ws message");
-                    if sender.send(Message::Text(json.into())).await.is_err() {
+                    if sender.send(Message::Text(json)).await.is_err() {
                         break;
                     }
                 }
@@ -529,64 +662,48 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                 if let Ok(uuid) = note_id.parse::<Uuid>() {
                     tracing::info!(?uuid, "unsubscribing from note");
                     subscribed_notes.write().await.remove(&uuid);
+                    if canvas_notes.remove(&uuid) {
+                        let _ = hub
+                            .broadcast(WsMessage::CanvasLeave { note_id, session: session.clone(), user: actor.clone() })
+                            .await;
+                    }
+                }
+            }
+            WsMessage::CanvasPresence { note_id, payload, .. } => {
+                let Ok(uuid) = note_id.parse::<Uuid>() else {
+                    continue;
+                };
+                if payload.len() > MAX_CANVAS_PAYLOAD {
+                    tracing::warn!(?uuid, size = payload.len(), "canvas presence payload too large");
+                    continue;
+                }
+                // Only participants of the note, i.e. subscribers, are relayed
+                if !subscribed_notes.read().await.contains(&uuid) {
+                    continue;
                 }
+                canvas_notes.insert(uuid);
+                let _ = hub
+                    .broadcast(WsMessage::CanvasPresence {
+                        note_id,
+                        payload,
+                        session: Some(session.clone()),
+                        user: actor.clone(),
+                    })
+                    .await;
             }
             WsMessage::Update { note_id, payload } => {
                 use base64::{engine::general_purpose::STANDARD, Engine};
                 if let (Ok(uuid), Ok(update)) = (note_id.parse::<Uuid>(), STANDARD.decode(&payload)) {
                     tracing::info!(?uuid, "received update for note");
-                    
-                    // Store update in database with a transaction to prevent race conditions
-                    let mut tx = match state.pool.begin().await {
-                        Ok(t) => t,
-                        Err(err) => {
-                            tracing::error!(?err, "failed to start transaction for update");
-                            continue;
-                        }
-                    };
 
-                    // Read existing state with FOR UPDATE lock
-                    let existing: Option<Vec<u8>> = sqlx::query_scalar(
-                        "SELECT ydoc_state FROM crdt_states WHERE note_id = $1 FOR UPDATE"
-                    )
-                    .bind(uuid)
-                    .fetch_optional(&mut *tx)
-                    .await
-                    .unwrap_or(None);
-
-                    // Merge using yrs
-                    let doc = Doc::new();
-                    {
-                        let mut txn = doc.transact_mut();
-                        if let Some(existing_state) = existing {
-                             if let Ok(u) = Update::decode_v1(&existing_state) {
-                                 txn.apply_update(u);
-                             }
-                        }
-                        if let Ok(u) = Update::decode_v1(&update) {
-                            txn.apply_update(u);
+                    if let Some(actor) = &actor {
+                        if let Err(err) = contributors::record_sender(&state.pool, &update, actor).await {
+                            tracing::error!(?err, "failed to record the update's client");
                         }
                     }
-
-                    let new_state = doc.transact().encode_state_as_update_v1(&StateVector::default());
-                    let state_vector = doc.transact().state_vector().encode_v1();
-
-                    let _ = sqlx::query(
-                        "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
-                         VALUES ($1, $2, $3, now())
-                         ON CONFLICT (note_id) DO UPDATE SET
-                            ydoc_state = EXCLUDED.ydoc_state,
-                            state_vector = EXCLUDED.state_vector,
-                            updated_at = EXCLUDED.updated_at"
-                    )
-                    .bind(uuid)
-                    .bind(&new_state)
-                    .bind(&state_vector)
-                    .execute(&mut *tx)
-                    .await;
-
-                    if let Err(err) = tx.commit().await {
-                        tracing::error!(?err, "failed to commit transaction for update");
+                    
+                    if let Err(message) = store_ws_push(&state, actor.as_deref(), &[(uuid, update)], &[]).await {
+                        send_error(&response_tx, message).await;
                         continue;
                     }
 
@@ -598,27 +715,10 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
             WsMessage::NoteMetadata { payload } => {
                 if let Ok(meta) = serde_json::from_str::<NoteMetadata>(&payload) {
                     tracing::info!(?meta.id, "received metadata update");
-                    
-                    let _ = sqlx::query(
-                        "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas)
-                         VALUES ($1, $2, $3, $4, $5, $6, $7)
-                         ON CONFLICT (id) DO UPDATE SET
-                             title = EXCLUDED.title,
-                             content = EXCLUDED.content,
-                             folder_id = EXCLUDED.folder_id,
-                             is_deleted = EXCLUDED.is_deleted,
-                             is_canvas = EXCLUDED.is_canvas,
-                             updated_at = EXCLUDED.updated_at"
-                    )
-                    .bind(meta.id)
-                    .bind(&meta.title)
-                    .bind(&meta.content)
-                    .bind(meta.folder_id)
-                    .bind(meta.updated_at)
-                    .bind(meta.is_deleted)
-                    .bind(meta.is_canvas)
-                    .execute(&state.pool)
-                    .await;
+                    if let Err(message) = store_ws_push(&state, actor.as_deref(), &[], std::slice::from_ref(&meta)).await {
+                        send_error(&response_tx, message).await;
+                        continue;
+                    }
 
                     // Broadcast metadata to other clients
                     let _ = hub.broadcast(WsMessage::NoteMetadata { payload: payload.to_string() }).await;
@@ -633,95 +733,24 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                     let mut response_updates: HashMap<String, String> = HashMap::new();
                     let mut response_metadata: Vec<NoteMetadata> = Vec::new();
                     
-                    // Process incoming updates from the client with a transaction
-                    for (note_id_str, base64_update) in &request.updates {
-                        if let (Ok(note_id), Ok(update)) = (
-                            note_id_str.parse::<Uuid>(),
-                            STANDARD.decode(base64_update)
-                        ) {
-                            let mut tx = match state.pool.begin().await {
-                                Ok(t) => t,
-                                Err(err) => {
-                                    tracing::error!(?err, "failed to start transaction for sync update");
-                                    continue;
-                                }
-                            };
-
-                            // Get existing state with lock
-                            let existing: Option<Vec<u8>> = sqlx::query_scalar(
-                                "SELECT ydoc_state FROM crdt_states WHERE note_id = $1 FOR UPDATE"
-                            )
-                            .bind(note_id)
-                            .fetch_optional(&mut *tx)
-                            .await
-                            .unwrap_or(None);
-
-                            // Merge using yrs
-                            let doc = Doc::new();
-                            {
-                                let mut txn = doc.transact_mut();
-                                if let Some(existing_state) = existing {
-                                    if let Ok(u) = Update::decode_v1(&existing_state) {
-                                        txn.apply_update(u);
-                                    }
-                                }
-                                if let Ok(u) = Update::decode_v1(&update) {
-                                    txn.apply_update(u);
+                    // Store what the client pushed; it still gets the sync response if
+                    // that is refused
+                    let updates: Vec<(Uuid, Vec<u8>)> = request
+                        .updates
+                        .iter()
+                        .filter_map(|(note_id, update)| Some((note_id.parse().ok()?, STANDARD.decode(update).ok()?)))
+                        .collect();
+                    if !updates.is_empty() || !request.metadata.is_empty() {
+                        match store_ws_push(&state, actor.as_deref(), &updates, &request.metadata).await {
+                            Ok(()) => {
+                                for (note_id, update) in &updates {
+                                    let _ = hub.broadcast_update(*note_id, update).await;
                                 }
                             }
-
-                            let new_state = doc.transact().encode_state_as_update_v1(&StateVector::default());
-                            let state_vector = doc.transact().state_vector().encode_v1();
-
-                            let _ = sqlx::query(
-                                "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
-                                 VALUES ($1, $2, $3, now())
-                                 ON CONFLICT (note_id) DO UPDATE SET
-                                    ydoc_state = EXCLUDED.ydoc_state,
-                                    state_vector = EXCLUDED.state_vector,
-                                    updated_at = EXCLUDED.updated_at"
-                            )
-                            .bind(note_id)
-                            .bind(&new_state)
-                            .bind(&state_vector)
-                            .execute(&mut *tx)
-                            .await;
-
-                            if let Err(err) = tx.commit().await {
-                                tracing::error!(?err, "failed to commit transaction for sync update");
-                                continue;
-                            }
-
-                            // Broadcast to other clients
-                            let _ = hub.broadcast_update(note_id, &update).await;
+                            Err(message) => send_error(&response_tx, message).await,
                         }
                     }
 
-                    // Process incoming metadata from the client
-                    for meta in &request.metadata {
-                        let _ = sqlx::query(
-                            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas)
-                             VALUES ($1, $2, $3, $4, $5, $6, $7)
-                             ON CONFLICT (id) DO UPDATE SET
-                                 title = EXCLUDED.title,
-                                 content = EXCLUDED.content,
-                                 folder_id = EXCLUDED.folder_id,
-                                 is_deleted = EXCLUDED.is_deleted,
-                                 is_canvas = EXCLUDED.is_canvas,
-                                 updated_at = EXCLUDED.updated_at
-                             WHERE notes.updated_at < EXCLUDED.updated_at"
-                        )
-                        .bind(meta.id)
-                        .bind(&meta.title)
-                        .bind(&meta.content)
-                        .bind(meta.folder_id)
-                        .bind(meta.updated_at)
-                        .bind(meta.is_deleted)
-                        .bind(meta.is_canvas)
-                        .execute(&state.pool)
-                        .await;
-                    }
-
                     // Calculate diffs for notes client knows about
                     for (note_id_str, client_sv_base64) in &request.state_vectors {
                         if let (Ok(note_id), Ok(client_sv_bytes)) = (
@@ -773,13 +802,13 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                     };
 
                     for (note_id, ydoc_state) in new_notes {
-                        if !response_updates.contains_key(&note_id.to_string()) {
-                            response_updates.insert(note_id.to_string(), STANDARD.encode(&ydoc_state));
-                        }
+                        response_updates
+                            .entry(note_id.to_string())
+                            .or_insert_with(|| STANDARD.encode(&ydoc_state));
                     }
 
                     // Fetch metadata
-                    let all_notes: Vec<(Uuid, String, String, Option<Uuid>, bool, bool, DateTime<Utc>)> = 
+                    let all_notes: Vec<NoteMetadataRow> =
                         sqlx::query_as(
                             "SELECT id, title, content, folder_id, is_deleted, is_canvas, updated_at FROM notes"
                         )
@@ -826,6 +855,11 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     // Cleanup
     tracing::info!("ws connection closed");
     send_task.abort();
+    for uuid in canvas_notes {
+        let _ = hub
+            .broadcast(WsMessage::CanvasLeave { note_id: uuid.to_string(), session: session.clone(), user: actor.clone() })
+            .await;
+    }
 }
 
 // ============================================================================
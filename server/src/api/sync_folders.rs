@@ -63,9 +63,9 @@ pub async fn sync_folders(
                 is_deleted = EXCLUDED.is_deleted
              WHERE folders.updated_at < EXCLUDED.updated_at",
         )
-        .bind(&folder.id)
+        .bind(folder.id)
         .bind(&folder.name)
-        .bind(&folder.parent_id)
+        .bind(folder.parent_id)
         .bind(folder.created_at)
         .bind(folder.updated_at)
         .bind(folder.is_deleted)
@@ -0,0 +1,97 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::{db::models::NoteRelation, AppState};
+
+/// Relation kinds clients may send
+const RELATION_KINDS: &[&str] = &["references", "blocks", "part_of"];
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRelationsRequest {
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub relations: Vec<NoteRelation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncRelationsResponse {
+    pub pulled: Vec<NoteRelation>,
+    pub last_sync: DateTime<Utc>,
+}
+
+pub async fn sync_relations(
+    State(state): State<AppState>,
+    Json(payload): Json<SyncRelationsRequest>,
+) -> Result<Json<SyncRelationsResponse>, axum::http::StatusCode> {
+    if payload
+        .relations
+        .iter()
+        .any(|r| !RELATION_KINDS.contains(&r.kind.as_str()))
+    {
+        return Err(axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.pool.begin().await.map_err(|err| {
+        tracing::error!(?err, "failed to open transaction");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Don't echo back the edges the client just pushed
+    let pushed: HashSet<(Uuid, Uuid, String)> = payload
+        .relations
+        .iter()
+        .map(|r| (r.source_note_id, r.target_note_id, r.kind.clone()))
+        .collect();
+
+    for relation in &payload.relations {
+        sqlx::query(
+            "INSERT INTO note_relations (source_note_id, target_note_id, kind, created_at, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (source_note_id, target_note_id, kind) DO UPDATE SET
+                updated_at = EXCLUDED.updated_at,
+                is_deleted = EXCLUDED.is_deleted
+             WHERE note_relations.updated_at < EXCLUDED.updated_at",
+        )
+        .bind(relation.source_note_id)
+        .bind(relation.target_note_id)
+        .bind(&relation.kind)
+        .bind(relation.created_at)
+        .bind(relation.updated_at)
+        .bind(relation.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to upsert relation during sync");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+
+    let pulled = sqlx::query_as::<_, NoteRelation>(
+        "SELECT source_note_id, target_note_id, kind, created_at, updated_at, is_deleted
+         FROM note_relations
+         WHERE updated_at > $1",
+    )
+    .bind(payload.since.unwrap_or(DateTime::<Utc>::MIN_UTC))
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "failed to pull relations");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|err| {
+        tracing::error!(?err, "failed to commit relation sync");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(SyncRelationsResponse {
+        pulled: pulled
+            .into_iter()
+            .filter(|r| !pushed.contains(&(r.source_note_id, r.target_note_id, r.kind.clone())))
+            .collect(),
+        last_sync: Utc::now(),
+    }))
+}
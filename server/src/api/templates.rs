@@ -0,0 +1,160 @@
+//! Note templates shared across the workspace.
+//!
+//! Anyone signed in can add, edit or delete a shared template; clients pull
+//! the list alongside sync and offer it next to their own templates. Template
+//! content is note HTML with `{{ ... }}` placeholders, stored as given: clients
+//! fill in the placeholders when creating a note from it.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, AppState};
+
+/// Longest template name accepted, in characters
+const MAX_NAME_LEN: usize = 200;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Template {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub content: String,
+    /// Who saved the current version
+    pub updated_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateQuery {
+    /// Only templates changed after this time, including deletions
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateInput {
+    /// Omit to add a template
+    pub id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub content: String,
+}
+
+const TEMPLATE_COLUMNS: &str = "id, name, description, content, updated_by, created_at, updated_at, is_deleted";
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query templates");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// GET /api/templates?since=
+///
+/// Live templates by name, or with `since` everything changed after it
+/// (deleted templates included) for incremental pulls.
+pub async fn list_templates(
+    State(state): State<AppState>,
+    Query(query): Query<TemplateQuery>,
+) -> Result<Json<Vec<Template>>, StatusCode> {
+    let templates = match query.since {
+        Some(since) => sqlx::query_as::<_, Template>(&format!(
+            "SELECT {} FROM templates WHERE updated_at > $1 ORDER BY updated_at",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(since)
+        .fetch_all(&state.pool)
+        .await,
+        None => sqlx::query_as::<_, Template>(&format!(
+            "SELECT {} FROM templates WHERE NOT is_deleted ORDER BY lower(name)",
+            TEMPLATE_COLUMNS
+        ))
+        .fetch_all(&state.pool)
+        .await,
+    }
+    .map_err(db_error)?;
+    Ok(Json(templates))
+}
+
+/// POST /api/templates
+///
+/// Add a template (omit `id`) or replace one. Names are unique, ignoring
+/// case, among live templates; reusing one is a 409.
+pub async fn save_template(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Json(input): Json<TemplateInput>,
+) -> Result<Json<Template>, StatusCode> {
+    let name = input.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let id = input.id.unwrap_or_else(Uuid::new_v4);
+    let description = input.description.as_deref().map(str::trim).filter(|d| !d.is_empty());
+
+    let taken: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM templates WHERE lower(name) = lower($1) AND id <> $2 AND NOT is_deleted)",
+    )
+    .bind(name)
+    .bind(id)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    if taken {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let template = sqlx::query_as::<_, Template>(&format!(
+        "INSERT INTO templates (id, name, description, content, updated_by)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            description = EXCLUDED.description,
+            content = EXCLUDED.content,
+            updated_by = EXCLUDED.updated_by,
+            updated_at = now(),
+            is_deleted = false
+         RETURNING {}",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(id)
+    .bind(name)
+    .bind(description)
+    .bind(&input.content)
+    .bind(&user)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|err| match err {
+        // Lost a race with another save of the same name
+        sqlx::Error::Database(ref e) if e.is_unique_violation() => StatusCode::CONFLICT,
+        err => db_error(err),
+    })?;
+    Ok(Json(template))
+}
+
+/// DELETE /api/templates/:id
+pub async fn delete_template(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query(
+        "UPDATE templates SET is_deleted = true, updated_by = $2, updated_at = now()
+         WHERE id = $1 AND NOT is_deleted",
+    )
+    .bind(id)
+    .bind(&user)
+    .execute(&state.pool)
+    .await
+    .map_err(db_error)?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,476 @@
+//! Resumable uploads for large assets.
+//!
+//! A tus-style protocol: the client creates a session with the file's size
+//! (and optionally its SHA-256), appends chunks with `PATCH` at the offset the
+//! server reports, and finalizes once every byte has arrived. After a dropped
+//! connection the client asks for the current offset and continues from there.
+
+use std::io::SeekFrom;
+use std::path::{Path as FsPath, PathBuf};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+use super::assets::{self, AssetInfo, NewAsset};
+use super::error::ApiError;
+use crate::{
+    auth::AuthUser,
+    blobs,
+    db::models::UploadSession,
+    quota::{self, QuotaKind},
+    AppState,
+};
+
+/// Largest file accepted through a resumable upload
+pub const MAX_RESUMABLE_BYTES: i64 = 4 * 1024 * 1024 * 1024;
+
+/// Sessions without activity for this long are discarded
+const SESSION_TTL_HOURS: i64 = 24;
+
+/// Suggested chunk size for clients
+const CHUNK_SIZE: i64 = 8 * 1024 * 1024;
+
+/// A chunk's claim on its session lapses this long after it was last refreshed
+const CLAIM_TTL_SECS: f64 = 120.0;
+
+/// How often a chunk being written refreshes its claim
+const CLAIM_REFRESH: Duration = Duration::from_secs(30);
+
+static UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadRequest {
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub note_id: Option<Uuid>,
+    pub size: i64,
+    /// Hex SHA-256 of the whole file, checked when the upload is completed
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub keep_location: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CompleteUploadRequest {
+    /// Hex SHA-256 of the whole file, if not given when the session was created
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadStatus {
+    pub id: Uuid,
+    pub offset: i64,
+    pub size: i64,
+    pub chunk_size: i64,
+    /// Set when the server already had the content: the asset was created
+    /// and there is nothing to upload
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asset: Option<AssetInfo>,
+}
+
+impl UploadStatus {
+    fn from_session(session: &UploadSession) -> Self {
+        UploadStatus {
+            id: session.id,
+            offset: session.received_bytes,
+            size: session.total_bytes,
+            chunk_size: CHUNK_SIZE,
+            asset: None,
+        }
+    }
+
+    /// JSON status with the offset repeated in an `Upload-Offset` header
+    fn into_response(self) -> Response {
+        let offset = HeaderValue::from(self.offset);
+        ([(UPLOAD_OFFSET.clone(), offset)], Json(self)).into_response()
+    }
+}
+
+fn uploads_dir(state: &AppState) -> PathBuf {
+    state.assets_dir.join("uploads")
+}
+
+fn part_path(state: &AppState, id: Uuid) -> PathBuf {
+    uploads_dir(state).join(format!("{}.part", id))
+}
+
+fn parse_sha256(value: &str) -> Result<String, StatusCode> {
+    let value = value.trim().to_ascii_lowercase();
+    if value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(value)
+    } else {
+        Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+fn db_error(context: &'static str) -> impl Fn(sqlx::Error) -> StatusCode {
+    move |err| {
+        tracing::error!(?err, "{}", context);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// POST /api/assets/uploads
+///
+/// The full size is reserved against the user's asset quota for as long as the
+/// session exists. When `sha256` names content the server already stores, the
+/// asset is created at once and returned in `asset` instead of a session.
+pub async fn create_upload(
+    State(state): State<AppState>,
+    AuthUser(owner): AuthUser,
+    Json(payload): Json<CreateUploadRequest>,
+) -> Result<Response, ApiError> {
+    if payload.size <= 0 || payload.filename.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    if payload.size > MAX_RESUMABLE_BYTES {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+    let sha256 = payload.sha256.as_deref().map(parse_sha256).transpose()?;
+
+    prune_expired_sessions(&state).await;
+
+    if let Some(hash) = &sha256 {
+        let new = NewAsset {
+            id: Uuid::new_v4(),
+            owner: Some(owner.clone()),
+            note_id: payload.note_id,
+            filename: payload.filename.trim().to_string(),
+            content_type: payload.content_type.clone().unwrap_or_else(|| assets::DEFAULT_CONTENT_TYPE.to_string()),
+            size_bytes: payload.size,
+            keep_location: payload.keep_location,
+        };
+        if let Some(info) = assets::link_existing(&state, &new, hash).await? {
+            let status = UploadStatus {
+                id: info.asset.id,
+                offset: info.asset.size_bytes,
+                size: info.asset.size_bytes,
+                chunk_size: CHUNK_SIZE,
+                asset: Some(info),
+            };
+            return Ok(status.into_response());
+        }
+    }
+
+    {
+        let mut conn = state.pool.acquire().await.map_err(db_error("failed to acquire connection"))?;
+        quota::ensure_room(&mut conn, &state.quotas, &owner, QuotaKind::Assets, payload.size).await?;
+    }
+
+    fs::create_dir_all(uploads_dir(&state)).await.map_err(|err| {
+        tracing::error!(?err, "failed to create uploads directory");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let id = Uuid::new_v4();
+    fs::File::create(part_path(&state, id)).await.map_err(|err| {
+        tracing::error!(?err, "failed to create upload file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let session = sqlx::query_as::<_, UploadSession>(
+        "INSERT INTO upload_sessions (id, note_id, filename, content_type, total_bytes, received_bytes, sha256, keep_location, owner, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, 0, $6, $7, $8, now(), now())
+         RETURNING id, note_id, filename, content_type, total_bytes, received_bytes, sha256, keep_location, owner, created_at, updated_at",
+    )
+    .bind(id)
+    .bind(payload.note_id)
+    .bind(payload.filename.trim())
+    .bind(payload.content_type.as_deref().unwrap_or(assets::DEFAULT_CONTENT_TYPE))
+    .bind(payload.size)
+    .bind(sha256)
+    .bind(payload.keep_location)
+    .bind(&owner)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error("failed to create upload session"))?;
+
+    Ok(UploadStatus::from_session(&session).into_response())
+}
+
+/// GET /api/assets/uploads/:id — where to resume from
+pub async fn get_upload(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let session = sqlx::query_as::<_, UploadSession>(
+        "SELECT id, note_id, filename, content_type, total_bytes, received_bytes, sha256, keep_location, owner, created_at, updated_at
+         FROM upload_sessions WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(db_error("failed to fetch upload session"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(UploadStatus::from_session(&session).into_response())
+}
+
+/// PATCH /api/assets/uploads/:id
+///
+/// Appends the body at `Upload-Offset`, which must equal the bytes received so
+/// far (409 otherwise, or while another chunk for the session is arriving).
+/// Bytes that arrived before a dropped connection are kept.
+pub async fn upload_chunk(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, StatusCode> {
+    let offset: i64 = headers
+        .get(&UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    // Claim the session for this chunk; the claim keeps concurrent chunks out
+    // without a transaction open while the body streams in
+    let claim = Uuid::new_v4();
+    let session = sqlx::query_as::<_, UploadSession>(
+        "UPDATE upload_sessions SET chunk_claim = $3, chunk_claimed_at = now()
+         WHERE id = $1 AND received_bytes = $2
+           AND (chunk_claim IS NULL OR chunk_claimed_at < now() - make_interval(secs => $4))
+         RETURNING id, note_id, filename, content_type, total_bytes, received_bytes, sha256, keep_location, owner, created_at, updated_at",
+    )
+    .bind(id)
+    .bind(offset)
+    .bind(claim)
+    .bind(CLAIM_TTL_SECS)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(db_error("failed to claim upload session"))?;
+    let Some(session) = session else {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM upload_sessions WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&state.pool)
+            .await
+            .map_err(db_error("failed to fetch upload session"))?;
+        return Err(if exists { StatusCode::CONFLICT } else { StatusCode::NOT_FOUND });
+    };
+
+    // Written in a task of its own, so a dropped connection still records the
+    // bytes that arrived and releases the claim
+    let session = tokio::spawn(write_chunk(state.pool.clone(), part_path(&state, id), session, claim, body))
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "upload chunk task failed");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })??;
+
+    Ok(UploadStatus::from_session(&session).into_response())
+}
+
+/// Append a claimed chunk, then record what arrived and release the claim,
+/// unless it lapsed and another chunk took the session over
+async fn write_chunk(pool: PgPool, path: PathBuf, mut session: UploadSession, claim: Uuid, body: Body) -> Result<UploadSession, StatusCode> {
+    let appended = append_body(&pool, &path, &session, claim, body).await;
+
+    let received = session.received_bytes + appended.as_ref().copied().unwrap_or(0);
+    let released = sqlx::query(
+        "UPDATE upload_sessions SET received_bytes = $3, chunk_claim = NULL, chunk_claimed_at = NULL, updated_at = now()
+         WHERE id = $1 AND chunk_claim = $2",
+    )
+    .bind(session.id)
+    .bind(claim)
+    .bind(received)
+    .execute(&pool)
+    .await
+    .map_err(db_error("failed to update upload session"))?;
+    appended?;
+    if released.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+    session.received_bytes = received;
+    Ok(session)
+}
+
+/// Keep a chunk's claim on its session; 409 if it lapsed and was taken
+async fn refresh_claim(pool: &PgPool, id: Uuid, claim: Uuid) -> Result<(), StatusCode> {
+    let refreshed = sqlx::query("UPDATE upload_sessions SET chunk_claimed_at = now() WHERE id = $1 AND chunk_claim = $2")
+        .bind(id)
+        .bind(claim)
+        .execute(pool)
+        .await
+        .map_err(db_error("failed to refresh upload claim"))?;
+    if refreshed.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+/// Append a chunk to the session file and return how many bytes were stored.
+/// The file is first cut back to the recorded offset, discarding anything
+/// written by a request that failed before it could be recorded. The claim is
+/// refreshed before writing whenever it is due, so a writer whose claim lapsed
+/// stops before it writes over the chunk that took the session over.
+async fn append_body(pool: &PgPool, path: &FsPath, session: &UploadSession, claim: Uuid, body: Body) -> Result<i64, StatusCode> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to open upload file");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    file.set_len(session.received_bytes as u64).await.map_err(|err| {
+        tracing::error!(?err, "failed to truncate upload file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    file.seek(SeekFrom::End(0)).await.map_err(|err| {
+        tracing::error!(?err, "failed to seek upload file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let remaining = session.total_bytes - session.received_bytes;
+    let mut written: i64 = 0;
+    let mut refreshed_at = Instant::now();
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let Ok(chunk) = chunk else {
+            // Connection dropped mid-chunk; keep what arrived so the client can resume
+            break;
+        };
+        if written + chunk.len() as i64 > remaining {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        if refreshed_at.elapsed() >= CLAIM_REFRESH {
+            refresh_claim(pool, session.id, claim).await?;
+            refreshed_at = Instant::now();
+        }
+        file.write_all(&chunk).await.map_err(|err| {
+            tracing::error!(?err, "failed to write upload chunk");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        written += chunk.len() as i64;
+    }
+    file.flush().await.map_err(|err| {
+        tracing::error!(?err, "failed to flush upload file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(written)
+}
+
+/// POST /api/assets/uploads/:id/complete
+///
+/// Checks that every byte arrived and, when a hash is known, that it matches;
+/// a mismatch discards the session (422) so the client starts over.
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<CompleteUploadRequest>>,
+) -> Result<Json<AssetInfo>, StatusCode> {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+
+    let mut tx = state.pool.begin().await.map_err(db_error("failed to open transaction"))?;
+    let session = sqlx::query_as::<_, UploadSession>(
+        "SELECT id, note_id, filename, content_type, total_bytes, received_bytes, sha256, keep_location, owner, created_at, updated_at
+         FROM upload_sessions WHERE id = $1 FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_error("failed to fetch upload session"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    if session.received_bytes != session.total_bytes {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let path = part_path(&state, id);
+    let expected = match payload.sha256.as_deref() {
+        Some(hash) => Some(parse_sha256(hash)?),
+        None => session.sha256.clone(),
+    };
+    if let Some(expected) = expected {
+        let actual = blobs::sha256_file(path.clone()).await?;
+        if actual != expected {
+            tracing::warn!(upload_id = %id, "upload hash mismatch; discarding session");
+            sqlx::query("DELETE FROM upload_sessions WHERE id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error("failed to delete upload session"))?;
+            tx.commit().await.map_err(db_error("failed to commit upload"))?;
+            let _ = fs::remove_file(&path).await;
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    let info = assets::store_asset(
+        &state,
+        NewAsset {
+            id: session.id,
+            owner: session.owner,
+            note_id: session.note_id,
+            filename: session.filename,
+            content_type: session.content_type,
+            size_bytes: session.total_bytes,
+            keep_location: session.keep_location,
+        },
+        &path,
+    )
+    .await?;
+
+    sqlx::query("DELETE FROM upload_sessions WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error("failed to delete upload session"))?;
+    tx.commit().await.map_err(db_error("failed to commit upload"))?;
+
+    Ok(Json(info))
+}
+
+/// DELETE /api/assets/uploads/:id
+pub async fn cancel_upload(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM upload_sessions WHERE id = $1")
+        .bind(id)
+        .execute(&state.pool)
+        .await
+        .map_err(db_error("failed to delete upload session"))?;
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let _ = fs::remove_file(part_path(&state, id)).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drop sessions that have been idle longer than the TTL, with their files
+async fn prune_expired_sessions(state: &AppState) {
+    let expired: Vec<Uuid> = match sqlx::query_scalar(
+        "DELETE FROM upload_sessions WHERE updated_at < now() - make_interval(hours => $1) RETURNING id",
+    )
+    .bind(SESSION_TTL_HOURS as i32)
+    .fetch_all(&state.pool)
+    .await
+    {
+        Ok(ids) => ids,
+        Err(err) => {
+            tracing::error!(?err, "failed to prune upload sessions");
+            return;
+        }
+    };
+    for id in expired {
+        let _ = fs::remove_file(part_path(state, id)).await;
+    }
+}
@@ -0,0 +1,255 @@
+//! Watching notes and folders.
+//!
+//! A user can watch any note, or a folder to follow the notes directly in it.
+//! Changes reach watchers two ways: WebSocket connections opened with a valid
+//! token get a `watched_change` message when a note they watch is saved, and
+//! `GET /api/watches/activity` digests the change feed since a cursor for
+//! clients that were offline. Watches are also kept in memory (`WatchIndex`)
+//! so the WebSocket fan-out doesn't query the database per message.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, AppState};
+
+/// What one user watches
+#[derive(Debug, Default)]
+struct UserWatches {
+    notes: HashSet<Uuid>,
+    folders: HashSet<Uuid>,
+}
+
+/// In-memory copy of the `watches` table, by user
+#[derive(Debug, Default)]
+pub struct WatchIndex {
+    users: RwLock<HashMap<String, UserWatches>>,
+}
+
+impl WatchIndex {
+    pub async fn load(pool: &sqlx::PgPool) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(String, Option<Uuid>, Option<Uuid>)> =
+            sqlx::query_as("SELECT username, note_id, folder_id FROM watches")
+                .fetch_all(pool)
+                .await?;
+        let index = WatchIndex::default();
+        for (user, note_id, folder_id) in rows {
+            index.insert(&user, note_id, folder_id);
+        }
+        Ok(index)
+    }
+
+    fn insert(&self, user: &str, note_id: Option<Uuid>, folder_id: Option<Uuid>) {
+        let mut users = self.users.write().unwrap();
+        let watches = users.entry(user.to_string()).or_default();
+        watches.notes.extend(note_id);
+        watches.folders.extend(folder_id);
+    }
+
+    fn remove(&self, user: &str, note_id: Option<Uuid>, folder_id: Option<Uuid>) {
+        let mut users = self.users.write().unwrap();
+        if let Some(watches) = users.get_mut(user) {
+            if let Some(id) = note_id {
+                watches.notes.remove(&id);
+            }
+            if let Some(id) = folder_id {
+                watches.folders.remove(&id);
+            }
+        }
+    }
+
+    /// Whether `user` watches the note or the folder it is in
+    pub fn is_watching(&self, user: &str, note_id: Uuid, folder_id: Option<Uuid>) -> bool {
+        let users = self.users.read().unwrap();
+        users.get(user).is_some_and(|w| {
+            w.notes.contains(&note_id) || folder_id.is_some_and(|f| w.folders.contains(&f))
+        })
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Watch {
+    pub id: i64,
+    pub note_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+    /// Title of the note or name of the folder
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWatchRequest {
+    pub note_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchActivityQuery {
+    /// Only report changes after this change-feed sequence number
+    #[serde(default)]
+    pub after_seq: i64,
+}
+
+/// A watched note changed since the cursor
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct WatchedNoteActivity {
+    pub note_id: Uuid,
+    pub title: String,
+    pub folder_id: Option<Uuid>,
+    pub is_deleted: bool,
+    /// Changes recorded since the cursor
+    pub changes: i64,
+    pub last_changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchActivityResponse {
+    pub notes: Vec<WatchedNoteActivity>,
+    /// Pass back as `after_seq` next time
+    pub last_seq: i64,
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "failed to query watches");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+const WATCH_COLUMNS: &str = "w.id, w.note_id, w.folder_id, COALESCE(n.title, f.name) AS name, w.created_at
+     FROM watches w
+     LEFT JOIN notes n ON n.id = w.note_id
+     LEFT JOIN folders f ON f.id = w.folder_id";
+
+/// GET /api/watches
+pub async fn list_watches(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Watch>>, StatusCode> {
+    let watches = sqlx::query_as::<_, Watch>(&format!(
+        "SELECT {} WHERE w.username = $1 ORDER BY w.created_at DESC",
+        WATCH_COLUMNS
+    ))
+    .bind(&user)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(watches))
+}
+
+/// POST /api/watches
+///
+/// Watch a note or a folder (exactly one of `note_id` and `folder_id`).
+/// Watching something already watched returns the existing watch.
+pub async fn create_watch(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Json(req): Json<CreateWatchRequest>,
+) -> Result<Json<Watch>, StatusCode> {
+    if req.note_id.is_some() == req.folder_id.is_some() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let exists: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM notes WHERE id = $1 AND is_deleted = false)
+             OR EXISTS(SELECT 1 FROM folders WHERE id = $2 AND is_deleted = false)",
+    )
+    .bind(req.note_id)
+    .bind(req.folder_id)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    sqlx::query(
+        "INSERT INTO watches (username, note_id, folder_id) VALUES ($1, $2, $3)
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(&user)
+    .bind(req.note_id)
+    .bind(req.folder_id)
+    .execute(&state.pool)
+    .await
+    .map_err(db_error)?;
+    state.watches.insert(&user, req.note_id, req.folder_id);
+
+    let watch = sqlx::query_as::<_, Watch>(&format!(
+        "SELECT {} WHERE w.username = $1 AND (w.note_id = $2 OR w.folder_id = $3)",
+        WATCH_COLUMNS
+    ))
+    .bind(&user)
+    .bind(req.note_id)
+    .bind(req.folder_id)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(watch))
+}
+
+/// DELETE /api/watches/:id
+pub async fn delete_watch(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let removed: Option<(Option<Uuid>, Option<Uuid>)> = sqlx::query_as(
+        "DELETE FROM watches WHERE id = $1 AND username = $2 RETURNING note_id, folder_id",
+    )
+    .bind(id)
+    .bind(&user)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(db_error)?;
+    let (note_id, folder_id) = removed.ok_or(StatusCode::NOT_FOUND)?;
+    state.watches.remove(&user, note_id, folder_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/watches/activity?after_seq=
+///
+/// Watched notes changed after `after_seq`, most recently changed first, for
+/// digests. Notes count as watched by the folder they are in now.
+pub async fn watch_activity(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<WatchActivityQuery>,
+) -> Result<Json<WatchActivityResponse>, StatusCode> {
+    // Changes commit in sequence order (see the `changes` migration), so
+    // nothing can appear below this bound after we read it
+    let last_seq: i64 = sqlx::query_scalar("SELECT GREATEST(COALESCE(MAX(seq), 0), $1) FROM changes")
+        .bind(query.after_seq)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(db_error)?;
+
+    let notes = sqlx::query_as::<_, WatchedNoteActivity>(
+        "SELECT n.id AS note_id, n.title, n.folder_id, n.is_deleted,
+                COUNT(*) AS changes, MAX(c.recorded_at) AS last_changed_at
+         FROM changes c
+         JOIN notes n ON n.id::text = c.entity_id
+         WHERE c.entity = 'note' AND c.seq > $2 AND c.seq <= $3
+           AND EXISTS (
+               SELECT 1 FROM watches w
+               WHERE w.username = $1 AND (w.note_id = n.id OR w.folder_id = n.folder_id)
+           )
+         GROUP BY n.id
+         ORDER BY MAX(c.seq) DESC",
+    )
+    .bind(&user)
+    .bind(query.after_seq)
+    .bind(last_seq)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(WatchActivityResponse { notes, last_seq }))
+}
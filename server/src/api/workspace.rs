@@ -0,0 +1,675 @@
+//! Whole-workspace export and import for moving data between servers.
+//!
+//! The archive is a gzipped JSON document holding every table the app syncs
+//! plus the asset files themselves, so a self-hosted instance can be migrated
+//! with two requests instead of a `pg_dump` and a copy of `ASSETS_DIR`.
+//! Per-user data (note/asset owners and quota overrides) is only included when
+//! asked for.
+
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use futures::StreamExt;
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+use super::assets::{asset_path, variant_path};
+use crate::{
+    auth::AuthUser,
+    blobs,
+    db::models::{AssetVariant, Board, BoardCard, BoardColumn, Folder, NoteRelation},
+    AppState,
+};
+
+const ARCHIVE_FORMAT: &str = "beck-workspace";
+const ARCHIVE_VERSION: u32 = 1;
+
+/// Largest archive accepted by the import endpoint (compressed)
+const MAX_IMPORT_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Largest archive accepted once decompressed. The archive is read into memory,
+/// so this bounds what an import can take, however well it compresses.
+const MAX_ARCHIVE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct ArchivedNote {
+    id: Uuid,
+    title: String,
+    content: String,
+    folder_id: Option<Uuid>,
+    updated_at: DateTime<Utc>,
+    is_deleted: bool,
+    is_canvas: bool,
+    owner: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct ArchivedCrdtState {
+    note_id: Uuid,
+    #[serde(with = "base64_bytes")]
+    ydoc_state: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    state_vector: Vec<u8>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct ArchivedAsset {
+    id: Uuid,
+    note_id: Option<Uuid>,
+    filename: String,
+    content_type: String,
+    size_bytes: i64,
+    created_at: DateTime<Utc>,
+    owner: Option<String>,
+    /// Stored blob; the archive carries the contents instead
+    #[serde(skip)]
+    blob_hash: Option<String>,
+    /// File contents; filled in after the row is read
+    #[sqlx(skip)]
+    #[serde(with = "base64_bytes")]
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedVariant {
+    #[serde(flatten)]
+    variant: AssetVariant,
+    #[serde(with = "base64_bytes")]
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct ArchivedQuota {
+    username: String,
+    notes_bytes_limit: Option<i64>,
+    assets_bytes_limit: Option<i64>,
+    unlimited: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkspaceArchive {
+    format: String,
+    version: u32,
+    exported_at: DateTime<Utc>,
+    includes_users: bool,
+    folders: Vec<Folder>,
+    notes: Vec<ArchivedNote>,
+    crdt_states: Vec<ArchivedCrdtState>,
+    boards: Vec<Board>,
+    board_columns: Vec<BoardColumn>,
+    board_cards: Vec<BoardCard>,
+    note_relations: Vec<NoteRelation>,
+    assets: Vec<ArchivedAsset>,
+    asset_variants: Vec<ArchivedVariant>,
+    #[serde(default)]
+    user_quotas: Vec<ArchivedQuota>,
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// Keep note/asset owners and per-user quota overrides
+    #[serde(default)]
+    pub include_users: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub folders: usize,
+    pub notes: usize,
+    pub crdt_states: usize,
+    pub boards: usize,
+    pub board_columns: usize,
+    pub board_cards: usize,
+    pub note_relations: usize,
+    pub assets: usize,
+    pub asset_variants: usize,
+    pub user_quotas: usize,
+}
+
+/// An import body spooled to disk, removed however the import ends
+struct Spool(PathBuf);
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Read an import body into an archive. The body is spooled to a file in
+/// `assets_dir` and decompressed from there, so neither the compressed nor the
+/// decompressed archive is held in memory as bytes.
+async fn read_archive(assets_dir: &Path, body: Body) -> Result<WorkspaceArchive, StatusCode> {
+    let write_error = |err: std::io::Error| {
+        tracing::error!(?err, "failed to spool workspace archive");
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let spool = Spool(assets_dir.join(format!("workspace-import-{}.json.gz", Uuid::new_v4())));
+    let mut file = fs::File::create(&spool.0).await.map_err(write_error)?;
+    let mut received: u64 = 0;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        received += chunk.len() as u64;
+        if received > MAX_IMPORT_BYTES {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        file.write_all(&chunk).await.map_err(write_error)?;
+    }
+    file.flush().await.map_err(write_error)?;
+    drop(file);
+
+    let file = std::fs::File::open(&spool.0).map_err(write_error)?;
+    tokio::task::spawn_blocking(move || -> Result<WorkspaceArchive, StatusCode> {
+        let mut json = BufReader::new(GzDecoder::new(BufReader::new(file)).take(MAX_ARCHIVE_BYTES));
+        serde_json::from_reader(&mut json).map_err(|err| {
+            if json.get_ref().limit() == 0 {
+                return StatusCode::PAYLOAD_TOO_LARGE;
+            }
+            tracing::warn!(%err, "rejected workspace archive");
+            StatusCode::BAD_REQUEST
+        })
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "workspace import task panicked");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+}
+
+fn db_error(err: sqlx::Error) -> StatusCode {
+    tracing::error!(?err, "workspace archive query failed");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// An asset file's contents, or `None` if it is gone because the asset was
+/// deleted after the export's snapshot was taken
+async fn read_file(path: std::path::PathBuf) -> Result<Option<Vec<u8>>, StatusCode> {
+    match fs::read(&path).await {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            tracing::warn!(?path, "asset file removed during export, skipping it");
+            Ok(None)
+        }
+        Err(err) => {
+            tracing::error!(?err, ?path, "failed to read asset file for export");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// GET /api/admin/workspace/export?include_users=true
+///
+/// Responds with a gzipped JSON archive of every folder, note, board,
+/// relation and asset on the server. All tables are read from one snapshot,
+/// so rows written during the export don't leave the archive inconsistent.
+pub async fn export_workspace(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    user.require_admin(&state)?;
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let mut notes: Vec<ArchivedNote> = sqlx::query_as(
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner FROM notes ORDER BY updated_at",
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let listed: Vec<ArchivedAsset> = sqlx::query_as(
+        "SELECT id, note_id, filename, content_type, size_bytes, created_at, owner, blob_hash FROM assets ORDER BY created_at",
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let variants: Vec<AssetVariant> = sqlx::query_as(
+        "SELECT asset_id, variant, content_type, width, height, size_bytes FROM asset_variants",
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let mut assets = Vec::with_capacity(listed.len());
+    for mut asset in listed {
+        if let Some(data) = read_file(asset_path(&state, asset.id, asset.blob_hash.as_deref())).await? {
+            asset.data = data;
+            assets.push(asset);
+        }
+    }
+    let blob_hashes: HashMap<Uuid, Option<&str>> =
+        assets.iter().map(|a| (a.id, a.blob_hash.as_deref())).collect();
+    let mut asset_variants = Vec::with_capacity(variants.len());
+    for variant in variants {
+        let Some(blob_hash) = blob_hashes.get(&variant.asset_id).copied() else {
+            continue;
+        };
+        if let Some(data) = read_file(variant_path(&state, variant.asset_id, blob_hash, &variant.variant)).await? {
+            asset_variants.push(ArchivedVariant { variant, data });
+        }
+    }
+
+    let user_quotas = if query.include_users {
+        sqlx::query_as("SELECT username, notes_bytes_limit, assets_bytes_limit, unlimited FROM user_quotas")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?
+    } else {
+        for note in &mut notes {
+            note.owner = None;
+        }
+        for asset in &mut assets {
+            asset.owner = None;
+        }
+        Vec::new()
+    };
+
+    let archive = WorkspaceArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        includes_users: query.include_users,
+        folders: sqlx::query_as("SELECT id, name, parent_id, created_at, updated_at, is_deleted FROM folders")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?,
+        notes,
+        crdt_states: sqlx::query_as("SELECT note_id, ydoc_state, state_vector, updated_at FROM crdt_states")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?,
+        boards: sqlx::query_as("SELECT id, name, folder_id, created_at, updated_at, is_deleted FROM boards")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?,
+        board_columns: sqlx::query_as(
+            "SELECT id, board_id, name, position, updated_at, is_deleted FROM board_columns",
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?,
+        board_cards: sqlx::query_as(
+            "SELECT id, board_id, column_id, note_id, position, updated_at, is_deleted FROM board_cards",
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?,
+        note_relations: sqlx::query_as(
+            "SELECT source_note_id, target_note_id, kind, created_at, updated_at, is_deleted FROM note_relations",
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?,
+        assets,
+        asset_variants,
+        user_quotas,
+    };
+    tx.commit().await.map_err(db_error)?;
+
+    let exported_at = archive.exported_at;
+    let body = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, &archive)?;
+        encoder.flush()?;
+        encoder.finish()
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "workspace export task panicked");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to encode workspace archive");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tracing::info!(admin = %user.0, bytes = body.len(), "exported workspace");
+    let filename = format!("beck-workspace-{}.json.gz", exported_at.format("%Y%m%d-%H%M%S"));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    ))
+}
+
+/// POST /api/admin/workspace/import
+///
+/// The body is an archive from `export_workspace`. Rows are upserted by id and
+/// stamped with the time of the import, so clients pull them like any change.
+/// A row already on the server is only overwritten if it last changed before
+/// the archived version: re-importing an archive doesn't undo edits made since,
+/// but does overwrite rows that haven't changed since it was exported. Assets
+/// and quota overrides are always overwritten. Rows on the server with other
+/// ids are left alone.
+pub async fn import_workspace(
+    State(state): State<AppState>,
+    user: AuthUser,
+    body: Body,
+) -> Result<Json<ImportSummary>, StatusCode> {
+    user.require_admin(&state)?;
+
+    let archive = read_archive(&state.assets_dir, body).await?;
+
+    if archive.format != ARCHIVE_FORMAT || archive.version > ARCHIVE_VERSION {
+        tracing::warn!(format = %archive.format, version = archive.version, "unsupported workspace archive");
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let mut tx = state.pool.begin().await.map_err(db_error)?;
+    let mut summary = ImportSummary::default();
+
+    // Parents before children so the folder foreign key is satisfied
+    let mut folders = archive.folders;
+    let mut inserted = std::collections::HashSet::new();
+    while !folders.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = folders
+            .into_iter()
+            .partition(|f| f.parent_id.is_none_or(|p| inserted.contains(&p)));
+        // Folders whose parent is missing from the archive go in as roots
+        let (ready, waiting) = if ready.is_empty() {
+            (waiting, Vec::new())
+        } else {
+            (ready, waiting)
+        };
+        for folder in ready {
+            let parent_id = folder.parent_id.filter(|p| inserted.contains(p));
+            let written = sqlx::query(
+                "INSERT INTO folders (id, name, parent_id, created_at, updated_at, is_deleted)
+                 VALUES ($1, $2, $3, $4, now(), $6)
+                 ON CONFLICT (id) DO UPDATE SET
+                    name = EXCLUDED.name, parent_id = EXCLUDED.parent_id,
+                    updated_at = now(), is_deleted = EXCLUDED.is_deleted
+                 WHERE folders.updated_at < $5",
+            )
+            .bind(folder.id)
+            .bind(&folder.name)
+            .bind(parent_id)
+            .bind(folder.created_at)
+            .bind(folder.updated_at)
+            .bind(folder.is_deleted)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+            inserted.insert(folder.id);
+            summary.folders += written.rows_affected() as usize;
+        }
+        folders = waiting;
+    }
+
+    for note in &archive.notes {
+        let written = sqlx::query(
+            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, owner)
+             VALUES ($1, $2, $3, $4, now(), $6, $7, $8)
+             ON CONFLICT (id) DO UPDATE SET
+                title = EXCLUDED.title, content = EXCLUDED.content, folder_id = EXCLUDED.folder_id,
+                updated_at = now(), is_deleted = EXCLUDED.is_deleted,
+                is_canvas = EXCLUDED.is_canvas, owner = EXCLUDED.owner
+             WHERE notes.updated_at < $5",
+        )
+        .bind(note.id)
+        .bind(&note.title)
+        .bind(&note.content)
+        .bind(note.folder_id)
+        .bind(note.updated_at)
+        .bind(note.is_deleted)
+        .bind(note.is_canvas)
+        .bind(&note.owner)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.notes += written.rows_affected() as usize;
+    }
+
+    for crdt in &archive.crdt_states {
+        let written = sqlx::query(
+            "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
+             VALUES ($1, $2, $3, now())
+             ON CONFLICT (note_id) DO UPDATE SET
+                ydoc_state = EXCLUDED.ydoc_state, state_vector = EXCLUDED.state_vector,
+                updated_at = now()
+             WHERE crdt_states.updated_at < $4",
+        )
+        .bind(crdt.note_id)
+        .bind(&crdt.ydoc_state)
+        .bind(&crdt.state_vector)
+        .bind(crdt.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.crdt_states += written.rows_affected() as usize;
+    }
+
+    for board in &archive.boards {
+        let written = sqlx::query(
+            "INSERT INTO boards (id, name, folder_id, created_at, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, now(), $6)
+             ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name, folder_id = EXCLUDED.folder_id,
+                updated_at = now(), is_deleted = EXCLUDED.is_deleted
+             WHERE boards.updated_at < $5",
+        )
+        .bind(board.id)
+        .bind(&board.name)
+        .bind(board.folder_id)
+        .bind(board.created_at)
+        .bind(board.updated_at)
+        .bind(board.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.boards += written.rows_affected() as usize;
+    }
+
+    for column in &archive.board_columns {
+        let written = sqlx::query(
+            "INSERT INTO board_columns (id, board_id, name, position, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, now(), $6)
+             ON CONFLICT (id) DO UPDATE SET
+                board_id = EXCLUDED.board_id, name = EXCLUDED.name, position = EXCLUDED.position,
+                updated_at = now(), is_deleted = EXCLUDED.is_deleted
+             WHERE board_columns.updated_at < $5",
+        )
+        .bind(column.id)
+        .bind(column.board_id)
+        .bind(&column.name)
+        .bind(column.position)
+        .bind(column.updated_at)
+        .bind(column.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.board_columns += written.rows_affected() as usize;
+    }
+
+    for card in &archive.board_cards {
+        let written = sqlx::query(
+            "INSERT INTO board_cards (id, board_id, column_id, note_id, position, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, $5, now(), $7)
+             ON CONFLICT (id) DO UPDATE SET
+                board_id = EXCLUDED.board_id, column_id = EXCLUDED.column_id, note_id = EXCLUDED.note_id,
+                position = EXCLUDED.position, updated_at = now(), is_deleted = EXCLUDED.is_deleted
+             WHERE board_cards.updated_at < $6",
+        )
+        .bind(card.id)
+        .bind(card.board_id)
+        .bind(card.column_id)
+        .bind(card.note_id)
+        .bind(card.position)
+        .bind(card.updated_at)
+        .bind(card.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.board_cards += written.rows_affected() as usize;
+    }
+
+    for relation in &archive.note_relations {
+        let written = sqlx::query(
+            "INSERT INTO note_relations (source_note_id, target_note_id, kind, created_at, updated_at, is_deleted)
+             VALUES ($1, $2, $3, $4, now(), $6)
+             ON CONFLICT (source_note_id, target_note_id, kind) DO UPDATE SET
+                updated_at = now(), is_deleted = EXCLUDED.is_deleted
+             WHERE note_relations.updated_at < $5",
+        )
+        .bind(relation.source_note_id)
+        .bind(relation.target_note_id)
+        .bind(&relation.kind)
+        .bind(relation.created_at)
+        .bind(relation.updated_at)
+        .bind(relation.is_deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.note_relations += written.rows_affected() as usize;
+    }
+
+    // Imported files go into the blob store like uploads; an asset replaced
+    // by the import drops its reference to the blob it had
+    let mut blob_hashes: HashMap<Uuid, String> = HashMap::new();
+    let mut released = Vec::new();
+    for asset in &archive.assets {
+        let hash = blobs::sha256_bytes(&asset.data);
+        blobs::acquire(&mut tx, &hash, asset.data.len() as i64)
+            .await
+            .map_err(db_error)?;
+        let previous: Option<Option<String>> = sqlx::query_scalar("SELECT blob_hash FROM assets WHERE id = $1")
+            .bind(asset.id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query(
+            "INSERT INTO assets (id, note_id, filename, content_type, size_bytes, created_at, owner, blob_hash)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (id) DO UPDATE SET
+                note_id = EXCLUDED.note_id, filename = EXCLUDED.filename,
+                content_type = EXCLUDED.content_type, size_bytes = EXCLUDED.size_bytes, owner = EXCLUDED.owner,
+                blob_hash = EXCLUDED.blob_hash",
+        )
+        .bind(asset.id)
+        .bind(asset.note_id)
+        .bind(&asset.filename)
+        .bind(&asset.content_type)
+        .bind(asset.data.len() as i64)
+        .bind(asset.created_at)
+        .bind(&asset.owner)
+        .bind(&hash)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        if let Some(Some(previous)) = previous {
+            if blobs::release(&mut tx, &previous).await.map_err(db_error)? {
+                released.push(previous);
+            }
+        }
+        blob_hashes.insert(asset.id, hash);
+        summary.assets += 1;
+    }
+
+    for archived in &archive.asset_variants {
+        let variant = &archived.variant;
+        sqlx::query(
+            "INSERT INTO asset_variants (asset_id, variant, content_type, width, height, size_bytes)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (asset_id, variant) DO UPDATE SET
+                content_type = EXCLUDED.content_type, width = EXCLUDED.width,
+                height = EXCLUDED.height, size_bytes = EXCLUDED.size_bytes",
+        )
+        .bind(variant.asset_id)
+        .bind(&variant.variant)
+        .bind(&variant.content_type)
+        .bind(variant.width)
+        .bind(variant.height)
+        .bind(archived.data.len() as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.asset_variants += 1;
+    }
+
+    for quota in &archive.user_quotas {
+        sqlx::query(
+            "INSERT INTO user_quotas (username, notes_bytes_limit, assets_bytes_limit, unlimited, updated_at)
+             VALUES ($1, $2, $3, $4, now())
+             ON CONFLICT (username) DO UPDATE SET
+                notes_bytes_limit = EXCLUDED.notes_bytes_limit,
+                assets_bytes_limit = EXCLUDED.assets_bytes_limit,
+                unlimited = EXCLUDED.unlimited,
+                updated_at = now()",
+        )
+        .bind(&quota.username)
+        .bind(quota.notes_bytes_limit)
+        .bind(quota.assets_bytes_limit)
+        .bind(quota.unlimited)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        summary.user_quotas += 1;
+    }
+
+    // Write files before committing so a failed write leaves no rows
+    // pointing at missing files
+    let write_error = |err: std::io::Error| {
+        tracing::error!(?err, "failed to write imported asset file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    fs::create_dir_all(blobs::blobs_dir(&state.assets_dir))
+        .await
+        .map_err(write_error)?;
+    for asset in &archive.assets {
+        fs::write(asset_path(&state, asset.id, blob_hashes.get(&asset.id).map(String::as_str)), &asset.data)
+            .await
+            .map_err(write_error)?;
+    }
+    for archived in &archive.asset_variants {
+        let variant = &archived.variant;
+        let blob_hash = blob_hashes.get(&variant.asset_id).map(String::as_str);
+        fs::write(variant_path(&state, variant.asset_id, blob_hash, &variant.variant), &archived.data)
+            .await
+            .map_err(write_error)?;
+    }
+
+    tx.commit().await.map_err(db_error)?;
+    for hash in &released {
+        blobs::remove_files(&state.pool, &state.assets_dir, hash).await;
+    }
+
+    tracing::info!(
+        admin = %user.0,
+        notes = summary.notes,
+        assets = summary.assets,
+        exported_at = %archive.exported_at,
+        "imported workspace"
+    );
+    Ok(Json(summary))
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,3 +24,12 @@ pub fn encode_token(secret: &Arc<String>, subject: &str) -> Result<String, jsonw
     let header = Header::new(Algorithm::HS256);
     encode(&header, &claims, &EncodingKey::from_secret(secret.as_bytes()))
 }
+
+pub fn decode_token(secret: &Arc<String>, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    )?;
+    Ok(data.claims)
+}
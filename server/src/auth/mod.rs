@@ -1 +1,43 @@
 pub mod jwt;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, StatusCode},
+};
+
+use crate::AppState;
+
+/// The user named by a valid `Authorization: Bearer <token>` header.
+///
+/// Rejects with 401 when the header is missing or the token is invalid; take
+/// `Option<AuthUser>` in handlers that also serve anonymous requests.
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub String);
+
+impl AuthUser {
+    /// 403 unless the user is listed in `ADMIN_USERS`
+    pub fn require_admin(&self, state: &AppState) -> Result<(), StatusCode> {
+        if state.quotas.is_admin(&self.0) {
+            Ok(())
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        let claims = jwt::decode_token(&state.jwt_secret, token.trim()).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        Ok(AuthUser(claims.sub))
+    }
+}
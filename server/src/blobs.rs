@@ -0,0 +1,143 @@
+//! Content-addressed storage for asset files.
+//!
+//! Each distinct file is stored once under `ASSETS_DIR/blobs`, named by its
+//! SHA-256, and `asset_blobs.ref_count` counts the assets pointing at it.
+//! Uploading content the server already has only adds a reference, and
+//! deleting an asset only removes the file once no other asset uses it.
+//! Image variants belong to the blob too, so they are shared the same way.
+//! Creating a blob and removing its files take the same advisory lock on the
+//! hash, so an upload can't write a file that a removal then deletes.
+
+use std::path::{Path, PathBuf};
+
+use axum::http::StatusCode;
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use tokio::fs;
+
+use crate::images;
+
+pub fn blobs_dir(assets_dir: &Path) -> PathBuf {
+    assets_dir.join("blobs")
+}
+
+pub fn blob_path(assets_dir: &Path, hash: &str) -> PathBuf {
+    blobs_dir(assets_dir).join(hash)
+}
+
+pub fn blob_variant_path(assets_dir: &Path, hash: &str, variant: &str) -> PathBuf {
+    blobs_dir(assets_dir).join(format!("{}_{}", hash, variant))
+}
+
+/// Lowercase hex SHA-256 of a file
+pub async fn sha256_file(path: PathBuf) -> Result<String, StatusCode> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok::<_, std::io::Error>(hex::encode(hasher.finalize()))
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "hashing task failed");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to hash file");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+pub fn sha256_bytes(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Transaction-scoped lock on a blob's hash, taken before its files are
+/// written or removed
+async fn lock_hash(conn: &mut PgConnection, hash: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('asset_blobs'), hashtext($1))")
+        .bind(hash)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Add a reference to a blob, creating its row if needed. Returns true when
+/// the blob is new and its file still has to be written before the
+/// transaction commits; until then removals of the same hash wait.
+pub async fn acquire(conn: &mut PgConnection, hash: &str, size_bytes: i64) -> Result<bool, sqlx::Error> {
+    lock_hash(conn, hash).await?;
+    let ref_count: i32 = sqlx::query_scalar(
+        "INSERT INTO asset_blobs (hash, size_bytes, ref_count, created_at)
+         VALUES ($1, $2, 1, now())
+         ON CONFLICT (hash) DO UPDATE SET ref_count = asset_blobs.ref_count + 1
+         RETURNING ref_count",
+    )
+    .bind(hash)
+    .bind(size_bytes)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(ref_count == 1)
+}
+
+/// Add a reference to a blob only if it is already stored. Returns its size.
+pub async fn acquire_existing(conn: &mut PgConnection, hash: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("UPDATE asset_blobs SET ref_count = ref_count + 1 WHERE hash = $1 RETURNING size_bytes")
+        .bind(hash)
+        .fetch_optional(&mut *conn)
+        .await
+}
+
+/// Drop a reference to a blob. Returns true when it was the last one; the row
+/// is gone and the files should be removed with `remove_files` once the
+/// transaction commits.
+pub async fn release(conn: &mut PgConnection, hash: &str) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query(
+        "DELETE FROM asset_blobs WHERE hash = $1 AND ref_count <= 1",
+    )
+    .bind(hash)
+    .execute(&mut *conn)
+    .await?;
+    if deleted.rows_affected() > 0 {
+        return Ok(true);
+    }
+    sqlx::query("UPDATE asset_blobs SET ref_count = ref_count - 1 WHERE hash = $1")
+        .bind(hash)
+        .execute(&mut *conn)
+        .await?;
+    Ok(false)
+}
+
+/// Remove a released blob's file and variants, unless an upload stored the
+/// same content again in the meantime. The hash stays locked while the files
+/// go, so an upload of the same content waits and then writes them afresh.
+pub async fn remove_files(pool: &sqlx::PgPool, assets_dir: &Path, hash: &str) {
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            tracing::error!(?err, "failed to open transaction to remove blob");
+            return;
+        }
+    };
+    if let Err(err) = lock_hash(&mut tx, hash).await {
+        tracing::error!(?err, "failed to lock blob before removing it");
+        return;
+    }
+    match sqlx::query_scalar::<_, i32>("SELECT 1 FROM asset_blobs WHERE hash = $1")
+        .bind(hash)
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(_)) => return,
+        Err(err) => {
+            tracing::error!(?err, "failed to check blob before removing it");
+            return;
+        }
+    }
+    let _ = fs::remove_file(blob_path(assets_dir, hash)).await;
+    for (variant, _) in images::VARIANTS {
+        let _ = fs::remove_file(blob_variant_path(assets_dir, hash, variant)).await;
+    }
+    let _ = tx.commit().await;
+}
@@ -0,0 +1,136 @@
+//! Crash and error reporting.
+//!
+//! Panics are always written to `CRASH_REPORT_DIR` (default
+//! `./data/crash-reports`) by the hook from `beck_crash`, and a request whose
+//! handler panics is answered with a 500 instead of a dropped connection.
+//! Admins list the recent reports with `GET /api/admin/crashes`.
+//!
+//! Uploading is opt-in: with `SENTRY_DSN` set, crash reports are sent to that
+//! Sentry-compatible service (at startup, then every minute, so reports left
+//! by a crash that took the server down go out once it is back), and so is
+//! every 5xx response from the API, tagged with its method, route and status.
+//! Routes are reported as templates (`/api/notes/:id`), so tokens in paths
+//! aren't sent.
+
+use std::{env, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use beck_crash::{CrashReport, Dsn};
+use serde_json::Value;
+
+use crate::AppState;
+
+/// Name and version sent with reports
+const APP: &str = "beck-server";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How often new crash reports are looked for
+const UPLOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct CrashReporting {
+    pub dir: PathBuf,
+    dsn: Option<Dsn>,
+    client: reqwest::Client,
+}
+
+impl CrashReporting {
+    /// From `CRASH_REPORT_DIR` and `SENTRY_DSN`. An invalid DSN stops the
+    /// server from starting rather than dropping reports silently.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let dir = PathBuf::from(env::var("CRASH_REPORT_DIR").unwrap_or_else(|_| "./data/crash-reports".into()));
+        let dsn = match env::var("SENTRY_DSN") {
+            Ok(dsn) if !dsn.trim().is_empty() => Some(Dsn::parse(&dsn).map_err(|e| anyhow::anyhow!("SENTRY_DSN: {}", e))?),
+            _ => None,
+        };
+        if dsn.is_some() {
+            tracing::info!("uploading crash reports and server errors");
+        }
+        let client = reqwest::Client::builder().timeout(SEND_TIMEOUT).build()?;
+        Ok(CrashReporting { dir, dsn, client })
+    }
+
+    pub fn install_panic_hook(&self) {
+        beck_crash::install_panic_hook(self.dir.clone(), APP, VERSION);
+    }
+
+    /// Send the crash reports that haven't been sent
+    async fn upload_pending(&self, dsn: &Dsn) {
+        let reports: Vec<CrashReport> = match beck_crash::pending_uploads(&self.dir) {
+            Ok(reports) => reports,
+            Err(err) => {
+                tracing::error!(?err, "failed to read crash reports");
+                return;
+            }
+        };
+        for report in reports {
+            if let Err(err) = send(&self.client, dsn, &beck_crash::crash_event(&report)).await {
+                // Try again on the next round
+                tracing::warn!(?err, id = %report.id, "failed to upload crash report");
+                return;
+            }
+            if let Err(err) = beck_crash::mark_uploaded(&self.dir, &report) {
+                tracing::error!(?err, id = %report.id, "failed to mark crash report uploaded");
+            }
+        }
+    }
+
+    /// Report a failed request in the background
+    fn report_error(&self, method: &str, route: &str, status: u16) {
+        let Some(dsn) = self.dsn.clone() else {
+            return;
+        };
+        let message = format!("{} {} answered {}", method, route, status);
+        let event = beck_crash::error_event(APP, VERSION, &message, &[("method", method), ("route", route), ("status", &status.to_string())]);
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            if let Err(err) = send(&client, &dsn, &event).await {
+                tracing::warn!(?err, "failed to report server error");
+            }
+        });
+    }
+}
+
+async fn send(client: &reqwest::Client, dsn: &Dsn, event: &Value) -> Result<(), reqwest::Error> {
+    client
+        .post(&dsn.store_url)
+        .header("X-Sentry-Auth", dsn.auth_header(&format!("{}/{}", APP, VERSION)))
+        .json(event)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Upload crash reports on an interval, starting right away, if uploading is
+/// configured
+pub fn spawn_uploader(reporting: Arc<CrashReporting>) {
+    if reporting.dsn.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(UPLOAD_INTERVAL);
+        loop {
+            ticks.tick().await;
+            if let Some(dsn) = &reporting.dsn {
+                reporting.upload_pending(dsn).await;
+            }
+        }
+    });
+}
+
+/// Middleware for the API router: report 5xx responses
+pub async fn report_server_errors(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string());
+    let response = next.run(request).await;
+    if response.status().is_server_error() {
+        state.crash_reporting.report_error(&method, route.as_deref().unwrap_or_default(), response.status().as_u16());
+    }
+    response
+}
@@ -22,3 +22,86 @@ pub struct Folder {
     pub updated_at: DateTime<Utc>,
     pub is_deleted: bool,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Board {
+    pub id: Uuid,
+    pub name: String,
+    pub folder_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BoardColumn {
+    pub id: Uuid,
+    pub board_id: Uuid,
+    pub name: String,
+    pub position: f64,
+    pub updated_at: DateTime<Utc>,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BoardCard {
+    pub id: Uuid,
+    pub board_id: Uuid,
+    pub column_id: Uuid,
+    pub note_id: Uuid,
+    pub position: f64,
+    pub updated_at: DateTime<Utc>,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NoteRelation {
+    pub source_note_id: Uuid,
+    pub target_note_id: Uuid,
+    /// "references", "blocks" or "part_of"
+    pub kind: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Asset {
+    pub id: Uuid,
+    pub note_id: Option<Uuid>,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    /// SHA-256 of the stored file; `None` for assets stored before files were
+    /// deduplicated
+    pub blob_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AssetVariant {
+    pub asset_id: Uuid,
+    /// "thumbnail" or "medium"
+    pub variant: String,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub size_bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UploadSession {
+    pub id: Uuid,
+    pub note_id: Option<Uuid>,
+    pub filename: String,
+    pub content_type: String,
+    pub total_bytes: i64,
+    pub received_bytes: i64,
+    /// Expected lowercase hex SHA-256 of the whole file, if the client sent one
+    pub sha256: Option<String>,
+    pub keep_location: bool,
+    /// User the upload counts against, if authenticated
+    pub owner: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
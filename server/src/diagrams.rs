@@ -0,0 +1,200 @@
+//! Rendering of diagram code blocks to SVG for HTML exports.
+//!
+//! Mermaid and PlantUML blocks are rendered by command-line tools configured
+//! with `MERMAID_COMMAND` (e.g. `mmdc -i - -o - -e svg`) and `PLANTUML_COMMAND`
+//! (e.g. `plantuml -tsvg -pipe`); a language without a command stays a code
+//! block. Each run is sandboxed: it starts in an empty temporary directory
+//! with a cleared environment, gets the source on stdin and must write SVG to
+//! stdout within `DIAGRAM_TIMEOUT_SECS`, or it is killed. Output carrying
+//! scripts is refused. Rendered SVG is cached under `ASSETS_DIR/diagrams`,
+//! named by the SHA-256 of the language and source, so each diagram is only
+//! rendered once.
+
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+
+use beck_markdown::Diagram;
+use tokio::{fs, io::AsyncWriteExt, process::Command, sync::Semaphore};
+use uuid::Uuid;
+
+use crate::blobs;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+/// Renderers running at once
+const MAX_CONCURRENT_RENDERS: usize = 2;
+
+/// Larger output is discarded
+const MAX_SVG_BYTES: usize = 5 * 1024 * 1024;
+
+pub struct DiagramConfig {
+    /// Program and arguments for each diagram language
+    commands: HashMap<&'static str, Vec<String>>,
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+}
+
+impl DiagramConfig {
+    /// `MERMAID_COMMAND`, `PLANTUML_COMMAND` and `DIAGRAM_TIMEOUT_SECS`
+    pub fn from_env() -> Self {
+        let command = |key: &str| {
+            env::var(key)
+                .ok()
+                .map(|v| v.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+                .filter(|args| !args.is_empty())
+        };
+        let mut commands = HashMap::new();
+        if let Some(args) = command("MERMAID_COMMAND") {
+            commands.insert("mermaid", args);
+        }
+        if let Some(args) = command("PLANTUML_COMMAND") {
+            commands.insert("plantuml", args);
+        }
+        let timeout = env::var("DIAGRAM_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_TIMEOUT_SECS);
+        DiagramConfig {
+            commands,
+            timeout: Duration::from_secs(timeout),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_RENDERS)),
+        }
+    }
+
+    pub fn supports(&self, language: &str) -> bool {
+        self.commands.contains_key(language)
+    }
+}
+
+fn cache_path(assets_dir: &Path, diagram: &Diagram) -> PathBuf {
+    let key = blobs::sha256_bytes(format!("{}\n{}", diagram.language, diagram.source).as_bytes());
+    assets_dir.join("diagrams").join(format!("{}.svg", key))
+}
+
+/// SVG for a diagram, from the cache or a fresh render. `None` when its
+/// language has no renderer or rendering failed.
+pub async fn render(config: &DiagramConfig, assets_dir: &Path, diagram: &Diagram) -> Option<String> {
+    let args = config.commands.get(diagram.language.as_str())?;
+    let path = cache_path(assets_dir, diagram);
+    if let Ok(svg) = fs::read_to_string(&path).await {
+        return Some(svg);
+    }
+
+    let _permit = config.permits.acquire().await.ok()?;
+    let svg = match run_sandboxed(args, &diagram.source, config.timeout).await {
+        Ok(svg) => svg,
+        Err(err) => {
+            tracing::warn!(language = %diagram.language, %err, "failed to render diagram");
+            return None;
+        }
+    };
+
+    // Write under a temporary name so a concurrent reader never sees half a file
+    if let Some(dir) = path.parent() {
+        let tmp = dir.join(format!(".{}.tmp", Uuid::new_v4()));
+        let written = async {
+            fs::create_dir_all(dir).await?;
+            fs::write(&tmp, &svg).await?;
+            fs::rename(&tmp, &path).await
+        }
+        .await;
+        if let Err(err) = written {
+            tracing::warn!(?err, "failed to cache rendered diagram");
+            let _ = fs::remove_file(&tmp).await;
+        }
+    }
+    Some(svg)
+}
+
+/// Render every diagram in note HTML, keyed for `beck_markdown::replace_diagrams`
+pub async fn render_all(config: &DiagramConfig, assets_dir: &Path, content: &str) -> HashMap<Diagram, String> {
+    let mut rendered = HashMap::new();
+    for diagram in beck_markdown::find_diagrams(content) {
+        if rendered.contains_key(&diagram) || !config.supports(&diagram.language) {
+            continue;
+        }
+        if let Some(svg) = render(config, assets_dir, &diagram).await {
+            rendered.insert(diagram, svg);
+        }
+    }
+    rendered
+}
+
+async fn run_sandboxed(args: &[String], source: &str, timeout: Duration) -> Result<String, String> {
+    let dir = env::temp_dir().join(format!("beck-diagram-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("failed to create sandbox directory: {}", e))?;
+    let result = run_in(&dir, args, source, timeout).await;
+    let _ = fs::remove_dir_all(&dir).await;
+    result
+}
+
+async fn run_in(dir: &Path, args: &[String], source: &str, timeout: Duration) -> Result<String, String> {
+    let (program, rest) = args.split_first().ok_or("empty renderer command")?;
+    let mut child = Command::new(program)
+        .args(rest)
+        .current_dir(dir)
+        .env_clear()
+        .env("PATH", env::var_os("PATH").unwrap_or_default())
+        .env("HOME", dir)
+        .env("TMPDIR", dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", program, e))?;
+
+    let mut stdin = child.stdin.take().ok_or("renderer has no stdin")?;
+    let input = source.as_bytes().to_vec();
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(&input).await;
+    });
+
+    // Dropping the child on timeout kills it
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| format!("renderer timed out after {}s", timeout.as_secs()))?
+        .map_err(|e| format!("renderer failed: {}", e))?;
+    writer.abort();
+
+    if !output.status.success() {
+        return Err(format!("renderer exited with {}", output.status));
+    }
+    if output.stdout.len() > MAX_SVG_BYTES {
+        return Err("rendered diagram is too large".to_string());
+    }
+    let text = String::from_utf8(output.stdout).map_err(|_| "renderer output is not UTF-8".to_string())?;
+    sanitize(&text)
+}
+
+/// The `<svg>` element of renderer output, refusing anything with scripts
+fn sanitize(output: &str) -> Result<String, String> {
+    let start = output.find("<svg").ok_or("renderer output is not SVG")?;
+    let end = output.rfind("</svg>").ok_or("renderer output is not SVG")? + "</svg>".len();
+    let svg = &output[start..end.max(start)];
+
+    let lower = svg.to_ascii_lowercase();
+    if lower.contains("<script") || lower.contains("javascript:") || has_event_handler(&lower) {
+        return Err("rendered diagram contains scripts".to_string());
+    }
+    Ok(svg.to_string())
+}
+
+/// Whether markup has an `on…=` attribute
+fn has_event_handler(markup: &str) -> bool {
+    let bytes = markup.as_bytes();
+    markup.match_indices("on").any(|(i, _)| {
+        let after_space = i > 0 && bytes[i - 1].is_ascii_whitespace();
+        let name_len = bytes[i + 2..].iter().take_while(|b| b.is_ascii_lowercase()).count();
+        let rest = &markup[i + 2 + name_len..];
+        after_space && name_len > 0 && rest.trim_start().starts_with('=')
+    })
+}
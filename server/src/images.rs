@@ -0,0 +1,198 @@
+//! Processing for uploaded images: resized variants and location stripping.
+
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageReader};
+
+/// Variants generated for every uploaded image: (name, longest side in pixels)
+pub const VARIANTS: &[(&str, u32)] = &[("thumbnail", 320), ("medium", 1280)];
+
+/// Images larger than this are stored as-is without variants
+pub const MAX_PROCESS_BYTES: usize = 50 * 1024 * 1024;
+
+const JPEG_QUALITY: u8 = 82;
+
+/// A resized copy of an uploaded image
+pub struct EncodedVariant {
+    pub name: &'static str,
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Whether an uploaded content type is a raster image we can decode
+pub fn is_processable(content_type: &str) -> bool {
+    matches!(
+        content_type.split(';').next().unwrap_or("").trim(),
+        "image/jpeg" | "image/png" | "image/gif" | "image/webp"
+    )
+}
+
+/// Decode an image, honouring its EXIF orientation, and encode every variant.
+/// Variants are never upscaled; images with transparency are encoded as PNG,
+/// everything else as JPEG.
+pub fn make_variants(data: &[u8]) -> image::ImageResult<Vec<EncodedVariant>> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut source = DynamicImage::from_decoder(decoder)?;
+    source.apply_orientation(orientation);
+
+    VARIANTS
+        .iter()
+        .map(|&(name, max_dimension)| {
+            let resized = if source.width() <= max_dimension && source.height() <= max_dimension {
+                source.clone()
+            } else {
+                source.resize(max_dimension, max_dimension, FilterType::Triangle)
+            };
+            encode(name, &resized)
+        })
+        .collect()
+}
+
+fn encode(name: &'static str, img: &DynamicImage) -> image::ImageResult<EncodedVariant> {
+    let mut bytes = Vec::new();
+    let content_type = if img.color().has_alpha() {
+        img.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+        "image/png"
+    } else {
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY).encode_image(&img.to_rgb8())?;
+        "image/jpeg"
+    };
+    Ok(EncodedVariant {
+        name,
+        content_type,
+        width: img.width(),
+        height: img.height(),
+        bytes,
+    })
+}
+
+/// Erase GPS metadata from a JPEG's Exif segment in place.
+///
+/// The GPS directory and the values it points to are zeroed and the directory
+/// is left empty, so the file keeps its size and every other tag (orientation,
+/// camera, date) while no longer revealing where it was taken. Returns whether
+/// GPS data was found.
+pub fn strip_jpeg_gps(data: &mut [u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut pos = 2;
+    let mut stripped = false;
+
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        // Start of scan / end of image: metadata segments all come before
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        // Standalone markers carry no length
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) || marker == 0xFF {
+            pos += if marker == 0xFF { 1 } else { 2 };
+            continue;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let end = (pos + 2 + len).min(data.len());
+        if marker == 0xE1 && data[pos + 4..end].starts_with(b"Exif\0\0") {
+            stripped |= strip_tiff_gps(&mut data[pos + 10..end]);
+        }
+        pos = end;
+    }
+
+    stripped
+}
+
+/// TIFF structure reader for one byte order
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn zero(&mut self, start: usize, len: usize) {
+        let end = start.saturating_add(len).min(self.data.len());
+        if start < end {
+            self.data[start..end].fill(0);
+        }
+    }
+}
+
+/// Size in bytes of one value of a TIFF field type
+fn tiff_type_size(field_type: u16) -> usize {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+const GPS_IFD_TAG: u16 = 0x8825;
+const IFD_ENTRY_LEN: usize = 12;
+
+fn strip_tiff_gps(data: &mut [u8]) -> bool {
+    let little_endian = match data.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+    let mut tiff = Tiff { data, little_endian };
+
+    let Some(ifd0) = tiff.u32_at(4).map(|o| o as usize) else {
+        return false;
+    };
+    let Some(count) = tiff.u16_at(ifd0) else {
+        return false;
+    };
+    let gps_ifd = (0..count as usize)
+        .map(|i| ifd0 + 2 + i * IFD_ENTRY_LEN)
+        .find(|&entry| tiff.u16_at(entry) == Some(GPS_IFD_TAG))
+        .and_then(|entry| tiff.u32_at(entry + 8))
+        .map(|o| o as usize);
+    let Some(gps_ifd) = gps_ifd else {
+        return false;
+    };
+    let Some(gps_count) = tiff.u16_at(gps_ifd).map(|c| c as usize) else {
+        return false;
+    };
+
+    // Values wider than four bytes live outside the entry; erase those first
+    for i in 0..gps_count {
+        let entry = gps_ifd + 2 + i * IFD_ENTRY_LEN;
+        let (Some(field_type), Some(values)) = (tiff.u16_at(entry + 2), tiff.u32_at(entry + 4)) else {
+            break;
+        };
+        let size = tiff_type_size(field_type).saturating_mul(values as usize);
+        if size > 4 {
+            if let Some(offset) = tiff.u32_at(entry + 8) {
+                tiff.zero(offset as usize, size);
+            }
+        }
+    }
+
+    // An empty directory whose next-IFD pointer is zero
+    tiff.zero(gps_ifd, 2 + gps_count * IFD_ENTRY_LEN + 4);
+    true
+}
@@ -1,10 +1,8 @@
 use std::{env, net::SocketAddr, path::PathBuf, sync::Arc};
 
-use axum::{
-    routing::{get, post},
-    Router,
-};
+use axum::Router;
 use tower_http::{
+    catch_panic::CatchPanicLayer,
     cors::CorsLayer,
     services::{ServeDir, ServeFile},
     trace::TraceLayer,
@@ -12,9 +10,24 @@ use tower_http::{
 
 mod api;
 mod auth;
+mod blobs;
+mod crash_reports;
 mod db;
+mod diagrams;
+mod images;
+mod maintenance;
+mod migrations;
+mod quota;
+mod redaction;
+mod snapshots;
+mod titles;
 
-use api::sync_crdt::SyncHub;
+use api::{client::ClientRelease, embeds::EmbedConfig, sync_crdt::SyncHub, watches::WatchIndex};
+use crash_reports::CrashReporting;
+use diagrams::DiagramConfig;
+use beck_markdown::Redactor;
+use maintenance::{Maintenance, MaintenanceStatus};
+use quota::QuotaConfig;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -22,42 +35,91 @@ pub struct AppState {
     pub jwt_secret: Arc<String>,
     pub static_dir: Arc<PathBuf>,
     pub index_html: Arc<PathBuf>,
+    pub assets_dir: Arc<PathBuf>,
+    pub quotas: Arc<QuotaConfig>,
+    pub client_release: Arc<ClientRelease>,
+    pub embeds: Arc<EmbedConfig>,
+    pub maintenance: Maintenance,
+    /// Refuse saves that duplicate a note title within a folder
+    pub unique_titles: bool,
     pub sync_hub: Option<Arc<SyncHub>>,
+    pub diagrams: Arc<DiagramConfig>,
+    pub watches: Arc<WatchIndex>,
+    /// Applied to every export and printout
+    pub redaction: Arc<Redactor>,
+    pub crash_reporting: Arc<CrashReporting>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
     tracing_subscriber::fmt::init();
+    let crash_reporting = Arc::new(CrashReporting::from_env()?);
+    crash_reporting.install_panic_hook();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is required");
+
+    // `beck-server migrate [--check]` migrates, or checks, and exits
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        let pool = db::connect_pool(&database_url).await?;
+        if args.iter().any(|arg| arg == "--check") {
+            let safe = migrations::check(&pool).await?;
+            std::process::exit(if safe { 0 } else { 1 });
+        }
+        migrations::run(&pool).await?;
+        return Ok(());
+    }
+
     let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "dev-secret-change-me".into());
     let static_dir = env::var("STATIC_DIR").unwrap_or_else(|_| "./static".into());
     let static_dir_path = PathBuf::from(&static_dir);
     let index_html_path = static_dir_path.join("index.html");
+    let assets_dir = PathBuf::from(env::var("ASSETS_DIR").unwrap_or_else(|_| "./data/assets".into()));
+    std::fs::create_dir_all(&assets_dir)?;
+
+    let redaction = redaction::from_env()?;
 
     let pool = db::connect_pool(&database_url).await?;
 
     // Run migrations on startup to ensure schema is present
-    sqlx::migrate!("./migrations").run(&pool).await?;
+    migrations::run(&pool).await?;
 
     // Initialize the sync hub for WebSocket real-time sync
     let sync_hub = Arc::new(SyncHub::new());
+    let watches = Arc::new(WatchIndex::load(&pool).await?);
 
     let state = AppState {
         pool,
         jwt_secret: Arc::new(jwt_secret),
         static_dir: Arc::new(static_dir_path.clone()),
         index_html: Arc::new(index_html_path.clone()),
+        assets_dir: Arc::new(assets_dir),
+        quotas: Arc::new(QuotaConfig::from_env()),
+        client_release: Arc::new(ClientRelease::from_env()),
+        embeds: Arc::new(EmbedConfig::from_env()),
+        maintenance: Maintenance::new(MaintenanceStatus::from_env()),
+        unique_titles: titles::enabled_from_env(),
         sync_hub: Some(sync_hub),
+        diagrams: Arc::new(DiagramConfig::from_env()),
+        watches,
+        redaction: Arc::new(redaction),
+        crash_reporting: crash_reporting.clone(),
     };
 
+    crash_reports::spawn_uploader(crash_reporting);
+    snapshots::spawn_archiver(state.clone(), snapshots::ArchivePolicy::from_env());
+
     let serve_dir = ServeDir::new(static_dir_path)
         .not_found_service(ServeFile::new(index_html_path));
 
     let app = Router::new()
-        .nest("/api", api::router())
+        .nest("/api", api::router(state.clone()))
+        .route("/embed/:token", axum::routing::get(api::embeds::embed_note))
+        .route("/feeds/:token/atom", axum::routing::get(api::feeds::atom_feed))
+        .route("/feeds/:token/json", axum::routing::get(api::feeds::json_feed))
         .fallback_service(serve_dir)
+        .layer(CatchPanicLayer::new())
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .with_state(state);
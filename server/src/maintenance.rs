@@ -0,0 +1,137 @@
+//! Maintenance mode.
+//!
+//! While enabled, mutating API requests are refused with a 503, a
+//! `Retry-After` header and a JSON body clients can recognise, so they keep
+//! changes queued locally and retry instead of surfacing an opaque failure.
+//! Reads keep working. It starts from `MAINTENANCE_MODE` and can be toggled at
+//! runtime by an admin via `PUT /api/admin/maintenance`.
+
+use std::{
+    env,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+const DEFAULT_MESSAGE: &str = "The server is undergoing maintenance. Changes will sync once it is back.";
+
+/// Requests under these paths (relative to `/api`) are allowed during
+/// maintenance so users can still sign in, admins can switch it off, and a
+/// workspace can be imported while clients are held back
+const EXEMPT_PATHS: &[&str] = &["/auth", "/admin/maintenance", "/admin/workspace"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub message: String,
+    pub retry_after_secs: u64,
+}
+
+impl MaintenanceStatus {
+    /// `MAINTENANCE_MODE`, `MAINTENANCE_MESSAGE` and `MAINTENANCE_RETRY_AFTER` (seconds)
+    pub fn from_env() -> Self {
+        let enabled = env::var("MAINTENANCE_MODE")
+            .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+            .unwrap_or(false);
+        MaintenanceStatus {
+            enabled,
+            message: env::var("MAINTENANCE_MESSAGE")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+            retry_after_secs: env::var("MAINTENANCE_RETRY_AFTER")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+        }
+    }
+}
+
+/// Shared, runtime-toggleable maintenance state
+#[derive(Debug, Clone)]
+pub struct Maintenance(Arc<RwLock<MaintenanceStatus>>);
+
+/// Changes to apply to the current status; omitted fields are kept
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceUpdate {
+    pub enabled: bool,
+    pub message: Option<String>,
+    pub retry_after_secs: Option<u64>,
+}
+
+impl Maintenance {
+    pub fn new(status: MaintenanceStatus) -> Self {
+        Maintenance(Arc::new(RwLock::new(status)))
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn update(&self, update: MaintenanceUpdate) -> MaintenanceStatus {
+        let mut status = self.0.write().unwrap_or_else(|e| e.into_inner());
+        status.enabled = update.enabled;
+        if let Some(message) = update.message.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()) {
+            status.message = message;
+        }
+        if let Some(secs) = update.retry_after_secs {
+            status.retry_after_secs = secs;
+        }
+        status.clone()
+    }
+}
+
+/// Body of the 503 returned to refused requests
+#[derive(Debug, Serialize)]
+pub struct MaintenanceResponse {
+    pub error: &'static str,
+    pub message: String,
+    pub retry_after_secs: u64,
+}
+
+impl IntoResponse for MaintenanceResponse {
+    fn into_response(self) -> Response {
+        let retry_after = self.retry_after_secs.to_string();
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, retry_after)],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+fn is_mutating(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Middleware for the API router: refuse writes while maintenance is on
+pub async fn guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if is_mutating(request.method()) {
+        let path = request.uri().path();
+        let exempt = EXEMPT_PATHS
+            .iter()
+            .any(|p| path == *p || path.strip_prefix(p).is_some_and(|rest| rest.starts_with('/')));
+        let status = state.maintenance.status();
+        if status.enabled && !exempt {
+            return MaintenanceResponse {
+                error: "maintenance",
+                message: status.message,
+                retry_after_secs: status.retry_after_secs,
+            }
+            .into_response();
+        }
+    }
+    next.run(request).await
+}
@@ -0,0 +1,218 @@
+//! Schema migrations, and checking them before they run.
+//!
+//! The server applies pending migrations at startup, but first compares the
+//! database's migration history with the migrations it was built with and
+//! refuses to start if they diverge: a migration that was applied but edited
+//! since (checksum mismatch), applied but unknown to this build (a downgrade,
+//! or a build from another branch), or that failed halfway. Upgrading past
+//! that needs a person, not a retry.
+//!
+//! `beck-server migrate --check` also dry-runs the migrations without changing
+//! anything: every migration is replayed into an empty shadow schema, and the
+//! pending ones are applied to the real schema, each inside a transaction that
+//! is rolled back. The second check catches migrations that would fail on the
+//! data already there; it takes the same locks as the real upgrade while it
+//! runs, so only the CLI does it. `GET /api/admin/migrations?validate=true`
+//! runs just the shadow replay, which is safe on a live server.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{migrate::Migrator, Connection, Executor, PgConnection, PgPool};
+
+/// The migrations this build applies
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Schema the full history is replayed into when validating
+const SHADOW_SCHEMA: &str = "beck_migration_check";
+
+#[derive(Debug, Serialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Divergence {
+    pub version: i64,
+    pub description: String,
+    /// `checksum_mismatch`, `unknown` or `failed`
+    pub problem: &'static str,
+}
+
+/// Result of a dry run
+#[derive(Debug, Serialize)]
+pub struct Validation {
+    /// Why replaying every migration into an empty schema failed
+    pub shadow_error: Option<String>,
+    /// Why applying the pending migrations to the database failed. Only
+    /// checked by `migrate --check`.
+    pub pending_error: Option<String>,
+}
+
+impl Validation {
+    pub fn passed(&self) -> bool {
+        self.shadow_error.is_none() && self.pending_error.is_none()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<PendingMigration>,
+    /// History that doesn't match this build; the server won't start
+    pub divergent: Vec<Divergence>,
+    /// Only when asked to validate
+    pub validation: Option<Validation>,
+}
+
+impl MigrationStatus {
+    pub fn is_ok(&self) -> bool {
+        self.divergent.is_empty() && self.validation.as_ref().is_none_or(Validation::passed)
+    }
+}
+
+type HistoryRow = (i64, String, DateTime<Utc>, bool, Vec<u8>);
+
+/// Compare the database's migration history with this build's migrations
+pub async fn status(pool: &PgPool) -> Result<MigrationStatus, sqlx::Error> {
+    let has_history: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+    let history: Vec<HistoryRow> = if has_history {
+        sqlx::query_as(
+            "SELECT version, description, installed_on, success, checksum FROM _sqlx_migrations ORDER BY version",
+        )
+        .fetch_all(pool)
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let known: HashMap<i64, &[u8]> = MIGRATOR.iter().map(|m| (m.version, &*m.checksum)).collect();
+    let mut applied = Vec::new();
+    let mut divergent = Vec::new();
+    for (version, description, installed_on, success, checksum) in history {
+        let problem = match known.get(&version) {
+            None => Some("unknown"),
+            Some(_) if !success => Some("failed"),
+            Some(expected) if *expected != checksum.as_slice() => Some("checksum_mismatch"),
+            Some(_) => None,
+        };
+        match problem {
+            Some(problem) => divergent.push(Divergence { version, description, problem }),
+            None => applied.push(AppliedMigration { version, description, installed_on }),
+        }
+    }
+    let recorded: Vec<i64> = applied.iter().map(|m| m.version).chain(divergent.iter().map(|d| d.version)).collect();
+    let pending = MIGRATOR
+        .iter()
+        .filter(|m| !recorded.contains(&m.version))
+        .map(|m| PendingMigration { version: m.version, description: m.description.to_string() })
+        .collect();
+    Ok(MigrationStatus { applied, pending, divergent, validation: None })
+}
+
+/// Replay every migration into an empty shadow schema. The real schema isn't
+/// touched, so this is safe to run against a live database.
+pub async fn validate_shadow(pool: &PgPool) -> Result<Validation, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let shadow_error = replay_into_shadow(&mut conn).await.err();
+    Ok(Validation { shadow_error, pending_error: None })
+}
+
+/// Dry-run the migrations, leaving the database as it was. Applying the
+/// pending ones takes the upgrade's locks, so this is for `migrate --check`.
+pub async fn validate(pool: &PgPool, status: &MigrationStatus) -> Result<Validation, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let shadow_error = replay_into_shadow(&mut conn).await.err();
+    let pending: Vec<i64> = status.pending.iter().map(|m| m.version).collect();
+    let pending_error = apply_rolled_back(&mut conn, &pending).await.err();
+    Ok(Validation { shadow_error, pending_error })
+}
+
+/// Apply every migration to an empty schema, then roll back
+async fn replay_into_shadow(conn: &mut PgConnection) -> Result<(), String> {
+    let mut tx = conn.begin().await.map_err(|e| e.to_string())?;
+    // `public` stays on the path for extensions such as uuid-ossp; new
+    // objects go to the shadow schema, which is first
+    tx.execute(format!("CREATE SCHEMA {0}; SET LOCAL search_path TO {0}, public", SHADOW_SCHEMA).as_str())
+        .await
+        .map_err(|e| e.to_string())?;
+    for migration in MIGRATOR.iter() {
+        tx.execute(&*migration.sql)
+            .await
+            .map_err(|e| format!("{} ({}): {}", migration.version, migration.description, e))?;
+    }
+    tx.rollback().await.map_err(|e| e.to_string())
+}
+
+/// Apply the given migrations to the database, then roll back
+async fn apply_rolled_back(conn: &mut PgConnection, versions: &[i64]) -> Result<(), String> {
+    if versions.is_empty() {
+        return Ok(());
+    }
+    let mut tx = conn.begin().await.map_err(|e| e.to_string())?;
+    for migration in MIGRATOR.iter().filter(|m| versions.contains(&m.version)) {
+        tx.execute(&*migration.sql)
+            .await
+            .map_err(|e| format!("{} ({}): {}", migration.version, migration.description, e))?;
+    }
+    tx.rollback().await.map_err(|e| e.to_string())
+}
+
+/// Refuse to go on if the history diverges, then apply pending migrations
+pub async fn run(pool: &PgPool) -> anyhow::Result<()> {
+    let status = status(pool).await?;
+    if !status.divergent.is_empty() {
+        anyhow::bail!(
+            "the database's migration history doesn't match this build ({}); run `beck-server migrate --check` for details",
+            describe(&status.divergent)
+        );
+    }
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// `beck-server migrate --check`: print the status and validation. Returns
+/// whether it is safe to upgrade.
+pub async fn check(pool: &PgPool) -> anyhow::Result<bool> {
+    let mut status = status(pool).await?;
+    status.validation = Some(validate(pool, &status).await?);
+
+    println!("{} applied, {} pending", status.applied.len(), status.pending.len());
+    for migration in &status.pending {
+        println!("  pending   {} {}", migration.version, migration.description);
+    }
+    for divergence in &status.divergent {
+        println!("  DIVERGENT {} {}: {}", divergence.version, divergence.description, divergence.problem);
+    }
+    if let Some(validation) = &status.validation {
+        match &validation.shadow_error {
+            Some(err) => println!("replaying all migrations into an empty schema failed: {}", err),
+            None => println!("all migrations apply cleanly to an empty schema"),
+        }
+        match (&validation.pending_error, status.pending.is_empty()) {
+            (Some(err), _) => println!("applying the pending migrations to this database failed: {}", err),
+            (None, false) => println!("the pending migrations apply cleanly to this database"),
+            (None, true) => {}
+        }
+    }
+    Ok(status.is_ok())
+}
+
+fn describe(divergent: &[Divergence]) -> String {
+    divergent
+        .iter()
+        .map(|d| format!("{} {}", d.version, d.problem))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,194 @@
+//! Per-user storage quotas.
+//!
+//! Note bytes (title, content and CRDT document of live notes) and asset bytes
+//! (originals plus image variants) are counted against the user who created
+//! them; notes written before owners were recorded go to the next user who
+//! writes them. Defaults come from the environment; a `user_quotas` row
+//! overrides them per user, and users listed in `ADMIN_USERS` are never limited.
+
+use std::{collections::HashSet, env};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use sqlx::PgConnection;
+
+use crate::api::error::ApiError;
+
+#[derive(Debug, Clone, Default)]
+pub struct QuotaConfig {
+    pub default_notes_bytes: Option<i64>,
+    pub default_assets_bytes: Option<i64>,
+    pub admins: HashSet<String>,
+}
+
+impl QuotaConfig {
+    /// `DEFAULT_NOTES_QUOTA_BYTES`, `DEFAULT_ASSETS_QUOTA_BYTES` (unset = unlimited)
+    /// and the comma-separated `ADMIN_USERS`
+    pub fn from_env() -> Self {
+        let bytes = |key: &str| env::var(key).ok().and_then(|v| v.trim().parse().ok());
+        QuotaConfig {
+            default_notes_bytes: bytes("DEFAULT_NOTES_QUOTA_BYTES"),
+            default_assets_bytes: bytes("DEFAULT_ASSETS_QUOTA_BYTES"),
+            admins: env::var("ADMIN_USERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|u| u.trim().to_string())
+                .filter(|u| !u.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_admin(&self, username: &str) -> bool {
+        self.admins.contains(username)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaKind {
+    Notes,
+    Assets,
+}
+
+/// Effective limits for a user; `None` means unlimited
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Limits {
+    pub notes_bytes: Option<i64>,
+    pub assets_bytes: Option<i64>,
+    pub unlimited: bool,
+}
+
+impl Limits {
+    pub fn for_kind(&self, kind: QuotaKind) -> Option<i64> {
+        match kind {
+            QuotaKind::Notes => self.notes_bytes,
+            QuotaKind::Assets => self.assets_bytes,
+        }
+    }
+}
+
+/// A write rejected because it would take a user over their quota (413)
+#[derive(Debug, Serialize)]
+pub struct QuotaExceeded {
+    pub error: &'static str,
+    pub kind: QuotaKind,
+    pub used_bytes: i64,
+    pub limit_bytes: i64,
+}
+
+impl QuotaExceeded {
+    fn new(kind: QuotaKind, used_bytes: i64, limit_bytes: i64) -> Self {
+        QuotaExceeded {
+            error: "quota_exceeded",
+            kind,
+            used_bytes,
+            limit_bytes,
+        }
+    }
+}
+
+impl IntoResponse for QuotaExceeded {
+    fn into_response(self) -> Response {
+        (StatusCode::PAYLOAD_TOO_LARGE, Json(self)).into_response()
+    }
+}
+
+pub async fn limits_for(conn: &mut PgConnection, config: &QuotaConfig, username: &str) -> Result<Limits, sqlx::Error> {
+    if config.is_admin(username) {
+        return Ok(Limits {
+            notes_bytes: None,
+            assets_bytes: None,
+            unlimited: true,
+        });
+    }
+
+    let row: Option<(Option<i64>, Option<i64>, bool)> = sqlx::query_as(
+        "SELECT notes_bytes_limit, assets_bytes_limit, unlimited FROM user_quotas WHERE username = $1",
+    )
+    .bind(username)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(match row {
+        Some((_, _, true)) => Limits {
+            notes_bytes: None,
+            assets_bytes: None,
+            unlimited: true,
+        },
+        Some((notes, assets, false)) => Limits {
+            notes_bytes: notes.or(config.default_notes_bytes),
+            assets_bytes: assets.or(config.default_assets_bytes),
+            unlimited: false,
+        },
+        None => Limits {
+            notes_bytes: config.default_notes_bytes,
+            assets_bytes: config.default_assets_bytes,
+            unlimited: false,
+        },
+    })
+}
+
+pub async fn usage(conn: &mut PgConnection, username: &str, kind: QuotaKind) -> Result<i64, sqlx::Error> {
+    let sql = match kind {
+        QuotaKind::Notes => {
+            "SELECT (COALESCE((SELECT SUM(octet_length(title) + octet_length(content)) FROM notes WHERE owner = $1 AND is_deleted = false), 0)
+                   + COALESCE((SELECT SUM(octet_length(c.ydoc_state)) FROM crdt_states c JOIN notes n ON n.id = c.note_id WHERE n.owner = $1 AND n.is_deleted = false), 0))::BIGINT"
+        }
+        QuotaKind::Assets => {
+            "SELECT (COALESCE((SELECT SUM(size_bytes) FROM assets WHERE owner = $1), 0)
+                   + COALESCE((SELECT SUM(v.size_bytes) FROM asset_variants v JOIN assets a ON a.id = v.asset_id WHERE a.owner = $1), 0)
+                   + COALESCE((SELECT SUM(total_bytes) FROM upload_sessions WHERE owner = $1), 0))::BIGINT"
+        }
+    };
+    sqlx::query_scalar(sql).bind(username).fetch_one(&mut *conn).await
+}
+
+fn db_error(err: sqlx::Error) -> ApiError {
+    tracing::error!(?err, "failed to check storage quota");
+    ApiError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Error if a write already made would leave the user over quota.
+///
+/// `previous` is the usage before the write: a write that doesn't grow usage
+/// is always allowed, so users over quota can still delete or shrink notes.
+pub async fn check(
+    conn: &mut PgConnection,
+    config: &QuotaConfig,
+    username: &str,
+    kind: QuotaKind,
+    previous: i64,
+) -> Result<(), ApiError> {
+    let limits = limits_for(&mut *conn, config, username).await.map_err(db_error)?;
+    let Some(limit) = limits.for_kind(kind) else {
+        return Ok(());
+    };
+    let used = usage(&mut *conn, username, kind).await.map_err(db_error)?;
+    if used > limit && used > previous {
+        return Err(QuotaExceeded::new(kind, used, limit).into());
+    }
+    Ok(())
+}
+
+/// Error if adding `additional` bytes would take the user over quota
+pub async fn ensure_room(
+    conn: &mut PgConnection,
+    config: &QuotaConfig,
+    username: &str,
+    kind: QuotaKind,
+    additional: i64,
+) -> Result<(), ApiError> {
+    let limits = limits_for(&mut *conn, config, username).await.map_err(db_error)?;
+    let Some(limit) = limits.for_kind(kind) else {
+        return Ok(());
+    };
+    let used = usage(&mut *conn, username, kind).await.map_err(db_error)?;
+    if used + additional > limit {
+        return Err(QuotaExceeded::new(kind, used + additional, limit).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! Redaction rules for exports.
+//!
+//! `EXPORT_REDACTION_RULES` holds a JSON array of rules in the same shape the
+//! desktop app stores, e.g.
+//! `[{"preset":"email"},{"tag":"private"},{"pattern":"ACME-\\d+","replacement":"ACME-…"}]`.
+//! Every export and printout is sanitized with them (see
+//! `beck_markdown::Redactor`). Invalid rules stop the server from starting
+//! rather than letting unredacted exports out.
+
+use std::env;
+
+use anyhow::Context;
+use beck_markdown::{RedactionRule, Redactor};
+
+/// Rules from `EXPORT_REDACTION_RULES`; none when it is unset or empty
+pub fn from_env() -> anyhow::Result<Redactor> {
+    let rules: Vec<RedactionRule> = match env::var("EXPORT_REDACTION_RULES") {
+        Ok(json) if !json.trim().is_empty() => {
+            serde_json::from_str(&json).context("EXPORT_REDACTION_RULES is not a JSON array of rules")?
+        }
+        _ => Vec::new(),
+    };
+    let redactor = Redactor::new(&rules).map_err(|e| anyhow::anyhow!("EXPORT_REDACTION_RULES: {}", e))?;
+    if !rules.is_empty() {
+        tracing::info!(rules = rules.len(), "redacting exports");
+    }
+    Ok(redactor)
+}
@@ -0,0 +1,132 @@
+//! Cold storage for old CRDT snapshots.
+//!
+//! Every collaborative editing session leaves a snapshot in `crdt_snapshots`
+//! (see the `note_history` migration). A background task moves all but the
+//! latest `SNAPSHOT_HOT_LIMIT` (default 50) snapshots of each note to
+//! `crdt_snapshot_archive`, gzipped, every `SNAPSHOT_ARCHIVE_INTERVAL_MINS`
+//! (default 60). Archived snapshots keep their ids and are listed and served
+//! by the history API like hot ones, only slower to fetch.
+
+use std::{
+    env,
+    io::{Read, Write},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use uuid::Uuid;
+
+use crate::AppState;
+
+const DEFAULT_HOT_LIMIT: i64 = 50;
+const DEFAULT_INTERVAL_MINS: u64 = 60;
+
+/// Snapshots moved per transaction
+const BATCH_SIZE: i64 = 200;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArchivePolicy {
+    /// Snapshots of a note kept in the hot table
+    pub hot_limit: i64,
+    pub interval: Duration,
+}
+
+impl ArchivePolicy {
+    /// `SNAPSHOT_HOT_LIMIT` and `SNAPSHOT_ARCHIVE_INTERVAL_MINS`
+    pub fn from_env() -> Self {
+        let var = |key: &str| env::var(key).ok().and_then(|v| v.trim().parse::<i64>().ok());
+        ArchivePolicy {
+            hot_limit: var("SNAPSHOT_HOT_LIMIT").unwrap_or(DEFAULT_HOT_LIMIT).max(1),
+            interval: Duration::from_secs(
+                var("SNAPSHOT_ARCHIVE_INTERVAL_MINS")
+                    .map(|m| m.max(1) as u64)
+                    .unwrap_or(DEFAULT_INTERVAL_MINS)
+                    * 60,
+            ),
+        }
+    }
+}
+
+type HotSnapshot = (i64, Uuid, Vec<u8>, Vec<String>, DateTime<Utc>, DateTime<Utc>);
+
+fn compress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+pub fn decompress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Move one batch of snapshots beyond the hot limit to the archive. Returns
+/// how many were moved.
+async fn archive_batch(state: &AppState, hot_limit: i64) -> anyhow::Result<usize> {
+    let mut tx = state.pool.begin().await?;
+    let snapshots: Vec<HotSnapshot> = sqlx::query_as(
+        "SELECT id, note_id, ydoc_state, actors, started_at, recorded_at FROM (
+             SELECT s.*, row_number() OVER (PARTITION BY note_id ORDER BY started_at DESC) AS rank
+             FROM crdt_snapshots s
+         ) ranked
+         WHERE rank > $1
+         ORDER BY id
+         LIMIT $2",
+    )
+    .bind(hot_limit)
+    .bind(BATCH_SIZE)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (id, note_id, ydoc_state, actors, started_at, recorded_at) in &snapshots {
+        sqlx::query(
+            "INSERT INTO crdt_snapshot_archive (id, note_id, ydoc_state_gz, size, actors, started_at, recorded_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(id)
+        .bind(note_id)
+        .bind(compress(ydoc_state)?)
+        .bind(ydoc_state.len() as i32)
+        .bind(actors)
+        .bind(started_at)
+        .bind(recorded_at)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM crdt_snapshots WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(snapshots.len())
+}
+
+/// Archive everything beyond the hot limit
+pub async fn archive_old_snapshots(state: &AppState, policy: ArchivePolicy) -> anyhow::Result<usize> {
+    let mut total = 0;
+    loop {
+        let moved = archive_batch(state, policy.hot_limit).await?;
+        total += moved;
+        if moved < BATCH_SIZE as usize {
+            return Ok(total);
+        }
+    }
+}
+
+/// Run the archiver on the policy's interval, starting right away
+pub fn spawn_archiver(state: AppState, policy: ArchivePolicy) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(policy.interval);
+        loop {
+            ticks.tick().await;
+            match archive_old_snapshots(&state, policy).await {
+                Ok(0) => {}
+                Ok(moved) => tracing::info!(moved, "archived CRDT snapshots"),
+                Err(err) => tracing::error!(?err, "failed to archive CRDT snapshots"),
+            }
+        }
+    });
+}
@@ -0,0 +1,137 @@
+//! Optional unique note titles within a folder.
+//!
+//! With `UNIQUE_NOTE_TITLES` set, a save that would give a live note the same
+//! title (case-insensitive, ignoring surrounding whitespace) as another note in
+//! the same folder is refused with a 409 listing free alternatives. Only saves
+//! that change a note's title or folder are checked, so duplicates created
+//! before the mode was switched on can still be edited.
+
+use std::{collections::HashSet, env};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::api::error::ApiError;
+
+/// How many alternative titles a conflict suggests
+const SUGGESTION_COUNT: usize = 3;
+
+/// `UNIQUE_NOTE_TITLES=true` turns the check on
+pub fn enabled_from_env() -> bool {
+    env::var("UNIQUE_NOTE_TITLES")
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+/// A save rejected because another note in the folder has the title (409)
+#[derive(Debug, Serialize)]
+pub struct TitleConflict {
+    pub error: &'static str,
+    pub message: String,
+    pub title: String,
+    pub folder_id: Option<Uuid>,
+    pub existing_note_id: Uuid,
+    pub suggestions: Vec<String>,
+}
+
+impl IntoResponse for TitleConflict {
+    fn into_response(self) -> Response {
+        (StatusCode::CONFLICT, Json(self)).into_response()
+    }
+}
+
+/// Split a trailing ` N` counter off a title: "Plan 2" -> ("Plan", 2)
+fn split_counter(title: &str) -> (&str, u32) {
+    if let Some((base, n)) = title.rsplit_once(' ') {
+        if let Ok(n) = n.parse::<u32>() {
+            if !base.trim().is_empty() {
+                return (base.trim_end(), n);
+            }
+        }
+    }
+    (title, 1)
+}
+
+/// Numbered variants of `title` not present in `taken` (lowercased)
+fn suggest(title: &str, taken: &HashSet<String>) -> Vec<String> {
+    let (base, start) = split_counter(title);
+    (start + 1..)
+        .map(|n| format!("{} {}", base, n))
+        .filter(|candidate| !taken.contains(&candidate.to_lowercase()))
+        .take(SUGGESTION_COUNT)
+        .collect()
+}
+
+fn db_error(err: sqlx::Error) -> ApiError {
+    tracing::error!(?err, "failed to check note title");
+    ApiError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Error if saving note `id` as `title` in `folder_id` would duplicate a title.
+/// Untitled and deleted notes are never checked.
+pub async fn check(
+    conn: &mut PgConnection,
+    id: Uuid,
+    title: &str,
+    folder_id: Option<Uuid>,
+    is_deleted: bool,
+) -> Result<(), ApiError> {
+    let title = title.trim();
+    if title.is_empty() || is_deleted {
+        return Ok(());
+    }
+
+    let current: Option<(String, Option<Uuid>, bool)> =
+        sqlx::query_as("SELECT title, folder_id, is_deleted FROM notes WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(db_error)?;
+    if let Some((current_title, current_folder, false)) = current {
+        if current_title.trim().to_lowercase() == title.to_lowercase() && current_folder == folder_id {
+            return Ok(());
+        }
+    }
+
+    let existing: Option<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM notes
+         WHERE folder_id IS NOT DISTINCT FROM $1 AND is_deleted = false AND id <> $2
+           AND lower(btrim(title)) = lower($3)
+         LIMIT 1",
+    )
+    .bind(folder_id)
+    .bind(id)
+    .bind(title)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(db_error)?;
+    let Some(existing_note_id) = existing else {
+        return Ok(());
+    };
+
+    let taken: Vec<String> = sqlx::query_scalar(
+        "SELECT lower(btrim(title)) FROM notes
+         WHERE folder_id IS NOT DISTINCT FROM $1 AND is_deleted = false AND id <> $2",
+    )
+    .bind(folder_id)
+    .bind(id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(db_error)?;
+
+    Err(TitleConflict {
+        error: "title_conflict",
+        message: format!("A note titled \"{}\" already exists in this folder", title),
+        title: title.to_string(),
+        folder_id,
+        existing_note_id,
+        suggestions: suggest(title, &taken.into_iter().collect()),
+    }
+    .into())
+}
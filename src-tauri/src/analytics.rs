@@ -0,0 +1,181 @@
+//! Private, on-device record of how notes are used.
+//!
+//! The frontend reports when a note is opened and how long an editing session
+//! lasted; `get_activity_heatmap` sums them per local day for an activity view
+//! of one's note-taking. Events live in `note_activity`, which is not part of
+//! any synced table, so they stay on the device. Only with `analytics_sync`
+//! turned on are the per-day totals (never individual notes) journaled and
+//! included in the pending changes pushed to the server.
+
+use chrono::{Duration, Local, NaiveDate};
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::Serialize;
+
+use crate::database::{now_rfc3339, Database};
+use crate::oplog::{record_op, OpEntity, OpKind, OPLOG_ENTITY_IDS};
+use crate::settings::ANALYTICS_SYNC;
+
+/// Days covered by a heatmap when no start is given
+const DEFAULT_RANGE_DAYS: i64 = 365;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    Open,
+    Edit,
+}
+
+impl ActivityKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ActivityKind::Open => "open",
+            ActivityKind::Edit => "edit",
+        }
+    }
+}
+
+/// Totals for one local day
+#[derive(Debug, Serialize, Clone)]
+pub struct ActivityDay {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub opens: i64,
+    pub edits: i64,
+    pub edit_seconds: i64,
+    /// Distinct notes opened or edited
+    pub notes: i64,
+}
+
+pub fn ensure_analytics_schema(conn: &Connection) -> SqliteResult<()> {
+    // No foreign key: a deleted note's activity still counts for its day
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_activity (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            day TEXT NOT NULL,
+            duration_seconds INTEGER NOT NULL DEFAULT 0,
+            recorded_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_activity_day ON note_activity(day)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Select clause summing `note_activity` rows into `ActivityDay` columns
+const DAY_TOTALS: &str = "SELECT day,
+        SUM(kind = 'open'),
+        SUM(kind = 'edit'),
+        SUM(duration_seconds),
+        COUNT(DISTINCT note_id)
+     FROM note_activity";
+
+fn row_to_activity_day(row: &rusqlite::Row) -> SqliteResult<ActivityDay> {
+    Ok(ActivityDay {
+        day: row.get(0)?,
+        opens: row.get(1)?,
+        edits: row.get(2)?,
+        edit_seconds: row.get(3)?,
+        notes: row.get(4)?,
+    })
+}
+
+fn parse_day(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date: {}", value))
+}
+
+impl Database {
+    fn analytics_sync_enabled(&self) -> SqliteResult<bool> {
+        Ok(self
+            .get_setting(ANALYTICS_SYNC)?
+            .is_some_and(|v| matches!(v.trim(), "1" | "true")))
+    }
+
+    /// Record that a note was opened, or edited for `duration_seconds`
+    pub fn record_note_activity(
+        &self,
+        note_id: &str,
+        kind: ActivityKind,
+        duration_seconds: i64,
+    ) -> SqliteResult<()> {
+        let sync = self.analytics_sync_enabled()?;
+        let day = Local::now().date_naive().format("%Y-%m-%d").to_string();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO note_activity (note_id, kind, day, duration_seconds, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                note_id,
+                kind.as_str(),
+                &day,
+                duration_seconds.max(0),
+                now_rfc3339()
+            ],
+        )?;
+        if sync {
+            record_op(&conn, OpEntity::ActivityDay, &day, OpKind::Upsert)?;
+        }
+        Ok(())
+    }
+
+    /// Days with activity between `from` and `to` (inclusive), oldest first
+    pub fn get_activity_days(&self, from: &str, to: &str) -> SqliteResult<Vec<ActivityDay>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE day >= ?1 AND day <= ?2 GROUP BY day ORDER BY day ASC",
+            DAY_TOTALS
+        ))?;
+        let rows = stmt.query_map(params![from, to], row_to_activity_day)?;
+        rows.collect()
+    }
+
+    /// Totals of the days journaled in an op range (see `oplog`)
+    pub fn get_activity_in_oplog(
+        &self,
+        after_op: i64,
+        up_to_op: i64,
+    ) -> SqliteResult<Vec<ActivityDay>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE day IN ({}) GROUP BY day ORDER BY day ASC",
+            DAY_TOTALS, OPLOG_ENTITY_IDS
+        ))?;
+        let rows = stmt.query_map(
+            params![OpEntity::ActivityDay.as_str(), after_op, up_to_op],
+            row_to_activity_day,
+        )?;
+        rows.collect()
+    }
+}
+
+/// Per-day activity from `from` to `to` (`YYYY-MM-DD`, inclusive). `to`
+/// defaults to today and `from` to a year before it. Days without activity
+/// are left out.
+pub fn activity_heatmap(
+    db: &Database,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<ActivityDay>, String> {
+    let to = match to {
+        Some(to) => parse_day(to)?,
+        None => Local::now().date_naive(),
+    };
+    let from = match from {
+        Some(from) => parse_day(from)?,
+        None => to - Duration::days(DEFAULT_RANGE_DAYS - 1),
+    };
+    if from > to {
+        return Err("The start of the range is after its end".to_string());
+    }
+    db.get_activity_days(
+        &from.format("%Y-%m-%d").to_string(),
+        &to.format("%Y-%m-%d").to_string(),
+    )
+    .map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,320 @@
+//! Passphrase lock for the app.
+//!
+//! With a passphrase set, the app starts locked and locks itself again after
+//! the configured minutes without a command from the webview. While locked,
+//! every command other than those below is rejected before it runs, the same
+//! way guest mode rejects writes, so the webview can't read notes around the
+//! lock screen; messages from the sync server that carry notes aren't passed
+//! on either (see `ws_hub`). The state lives here rather than in the frontend.
+//! The scheduler locks an idle app even if the webview stays quiet, and tells
+//! windows with `app://locked`; review reminders are held back while locked.
+//!
+//! Only a PBKDF2 hash of the passphrase is stored, in the `app_lock` setting,
+//! which the generic settings commands refuse to read or write. The lock
+//! keeps the app closed to whoever sits at the computer; it doesn't encrypt
+//! the vault on disk. Desktop platforms offer Tauri no biometric prompt, so
+//! unlocking takes the passphrase.
+
+use ring::{digest, pbkdf2, rand::SecureRandom, rand::SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::ipc::Invoke;
+
+use crate::commands::CommandError;
+use crate::database::Database;
+
+/// Settings key holding the lock configuration
+pub const APP_LOCK_SETTING: &str = "app_lock";
+
+/// Event emitted when the app locks itself
+pub const LOCKED_EVENT: &str = "app://locked";
+
+/// Commands that work while the app is locked
+const UNLOCKED_COMMANDS: &[&str] = &["get_app_lock_status", "unlock_app", "lock_app"];
+
+/// Commands that don't count as activity, so polling the lock doesn't keep
+/// the app open
+const PASSIVE_COMMANDS: &[&str] = &["get_app_lock_status"];
+
+/// Idle minutes before locking when none are configured
+const DEFAULT_IDLE_MINUTES: u32 = 15;
+
+/// Shortest passphrase accepted
+const MIN_PASSPHRASE_LEN: usize = 6;
+
+/// PBKDF2-HMAC-SHA256 rounds for new passphrases
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Wait after a wrong passphrase, to slow down guessing
+pub const FAILED_UNLOCK_DELAY: Duration = Duration::from_secs(1);
+
+/// The stored configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct LockConfig {
+    /// Hex
+    salt: String,
+    /// Hex
+    hash: String,
+    iterations: u32,
+    idle_minutes: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AppLockStatus {
+    /// Whether a passphrase is set
+    pub enabled: bool,
+    pub locked: bool,
+    /// Minutes without activity before the app locks; 0 only locks on request
+    /// and at startup
+    pub idle_minutes: u32,
+}
+
+struct LockState {
+    config: Option<LockConfig>,
+    locked: bool,
+    last_activity: Instant,
+}
+
+/// The lock, kept as managed state and shared with the command guard
+#[derive(Clone)]
+pub struct AppLock {
+    state: Arc<Mutex<LockState>>,
+}
+
+impl Default for AppLock {
+    fn default() -> Self {
+        AppLock {
+            state: Arc::new(Mutex::new(LockState {
+                config: None,
+                locked: false,
+                last_activity: Instant::now(),
+            })),
+        }
+    }
+}
+
+impl LockState {
+    fn idle_timeout(&self) -> Option<Duration> {
+        let minutes = self.config.as_ref()?.idle_minutes;
+        (minutes > 0).then(|| Duration::from_secs(u64::from(minutes) * 60))
+    }
+
+    /// Lock if the idle timeout has passed; returns whether it just locked
+    fn lock_if_idle(&mut self) -> bool {
+        let idle = self
+            .idle_timeout()
+            .is_some_and(|timeout| self.last_activity.elapsed() >= timeout);
+        if idle && !self.locked {
+            self.locked = true;
+            return true;
+        }
+        false
+    }
+
+    fn status(&self) -> AppLockStatus {
+        AppLockStatus {
+            enabled: self.config.is_some(),
+            locked: self.locked,
+            idle_minutes: self.config.as_ref().map_or(0, |c| c.idle_minutes),
+        }
+    }
+}
+
+fn hash_passphrase(passphrase: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut hash = vec![0u8; digest::SHA256_OUTPUT_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN),
+        salt,
+        passphrase.as_bytes(),
+        &mut hash,
+    );
+    hash
+}
+
+fn verify(config: &LockConfig, passphrase: &str) -> bool {
+    let (Ok(salt), Ok(hash)) = (hex::decode(&config.salt), hex::decode(&config.hash)) else {
+        return false;
+    };
+    let Some(iterations) = NonZeroU32::new(config.iterations) else {
+        return false;
+    };
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        passphrase.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+fn new_config(passphrase: &str, idle_minutes: u32) -> Result<LockConfig, String> {
+    let mut salt = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| "Failed to generate a salt".to_string())?;
+    Ok(LockConfig {
+        salt: hex::encode(salt),
+        hash: hex::encode(hash_passphrase(passphrase, &salt, PBKDF2_ITERATIONS)),
+        iterations: PBKDF2_ITERATIONS,
+        idle_minutes,
+    })
+}
+
+fn read_config(db: &Database) -> Result<Option<LockConfig>, String> {
+    let json = db
+        .get_setting(APP_LOCK_SETTING)
+        .map_err(|e| format!("Database error: {}", e))?;
+    json.map(|json| {
+        serde_json::from_str(&json).map_err(|e| format!("Invalid app lock setting: {}", e))
+    })
+    .transpose()
+}
+
+impl AppLock {
+    /// Load the configuration once the database is open. A vault with a
+    /// passphrase starts locked; one whose setting can't be read too, rather
+    /// than open.
+    pub fn load(&self, db: &Database) {
+        let mut state = self.state.lock().unwrap();
+        match read_config(db) {
+            Ok(config) => {
+                state.locked = config.is_some();
+                state.config = config;
+            }
+            Err(err) => {
+                eprintln!("[app_lock] {}", err);
+                state.locked = true;
+            }
+        }
+        state.last_activity = Instant::now();
+    }
+
+    pub fn status(&self) -> AppLockStatus {
+        self.state.lock().unwrap().status()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state.lock().unwrap().locked
+    }
+
+    /// Lock now. Without a passphrase there is nothing to unlock with, so
+    /// that is an error.
+    pub fn lock(&self) -> Result<AppLockStatus, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.config.is_none() {
+            return Err("Set a passphrase before locking the app".to_string());
+        }
+        state.locked = true;
+        Ok(state.status())
+    }
+
+    /// Lock if the app has been idle too long; returns whether it just locked
+    pub fn lock_if_idle(&self) -> bool {
+        self.state.lock().unwrap().lock_if_idle()
+    }
+
+    /// Unlock with the passphrase. A wrong one is an error; callers should
+    /// wait `FAILED_UNLOCK_DELAY` before answering.
+    pub fn unlock(&self, passphrase: &str) -> Result<AppLockStatus, String> {
+        let config = self.state.lock().unwrap().config.clone();
+        let Some(config) = config else {
+            return Ok(self.status());
+        };
+        // Hashing takes a while; don't hold the state meanwhile
+        if !verify(&config, passphrase) {
+            return Err("Wrong passphrase".to_string());
+        }
+        let mut state = self.state.lock().unwrap();
+        state.locked = false;
+        state.last_activity = Instant::now();
+        Ok(state.status())
+    }
+
+    /// Set, change or remove the passphrase (`passphrase: None`), or only the
+    /// idle timeout (`passphrase` equal to `current`). Changing an existing
+    /// lock takes the current passphrase.
+    pub fn configure(
+        &self,
+        db: &Database,
+        current: Option<&str>,
+        passphrase: Option<&str>,
+        idle_minutes: Option<u32>,
+    ) -> Result<AppLockStatus, String> {
+        let existing = self.state.lock().unwrap().config.clone();
+        if let Some(existing) = &existing {
+            if !current.is_some_and(|current| verify(existing, current)) {
+                return Err("Enter the current passphrase to change the lock".to_string());
+            }
+        }
+        let config = match passphrase {
+            Some(passphrase) => {
+                if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+                    return Err(format!(
+                        "Use a passphrase of at least {} characters",
+                        MIN_PASSPHRASE_LEN
+                    ));
+                }
+                let idle_minutes = idle_minutes
+                    .or(existing.as_ref().map(|c| c.idle_minutes))
+                    .unwrap_or(DEFAULT_IDLE_MINUTES);
+                Some(new_config(passphrase, idle_minutes)?)
+            }
+            None => None,
+        };
+        let json = config
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| format!("Failed to encode app lock setting: {}", e))?;
+        db.set_setting(APP_LOCK_SETTING, json.as_deref())
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let mut state = self.state.lock().unwrap();
+        state.config = config;
+        state.locked = false;
+        state.last_activity = Instant::now();
+        Ok(state.status())
+    }
+
+    /// Whether a command may run, counting it as activity if so
+    fn admit(&self, command: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.lock_if_idle();
+        if state.locked && !UNLOCKED_COMMANDS.contains(&command) {
+            return false;
+        }
+        if !PASSIVE_COMMANDS.contains(&command) {
+            state.last_activity = Instant::now();
+        }
+        true
+    }
+}
+
+/// Error for settings commands given the lock's own key
+pub fn check_setting_key(key: &str) -> Result<(), String> {
+    if key == APP_LOCK_SETTING {
+        return Err("The app lock is changed with set_app_lock".to_string());
+    }
+    Ok(())
+}
+
+/// Wrap the command handler so a locked app rejects every command that
+/// isn't needed to unlock it
+pub fn guard<F>(lock: AppLock, handler: F) -> impl Fn(Invoke) -> bool + Send + Sync + 'static
+where
+    F: Fn(Invoke) -> bool + Send + Sync + 'static,
+{
+    move |invoke: Invoke| {
+        if !lock.admit(invoke.message.command()) {
+            invoke.resolver.reject(CommandError {
+                message: "The app is locked; unlock it to continue".to_string(),
+            });
+            return true;
+        }
+        handler(invoke)
+    }
+}
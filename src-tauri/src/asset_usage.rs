@@ -0,0 +1,196 @@
+//! Disk usage report for the assets folder.
+//!
+//! Summarises how much space assets take, broken down by file type, and lists
+//! the largest files together with the notes that embed them.
+
+use rusqlite::{params, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::attachments::get_previews_dir;
+use crate::database::{assets, Database};
+use crate::settings::ASSET_QUOTA_BYTES;
+
+/// Default number of largest assets to report
+const DEFAULT_LARGEST: usize = 20;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExtensionUsage {
+    pub extension: String,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NoteRef {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LargeAsset {
+    pub id: String,
+    pub file_name: String,
+    pub extension: String,
+    pub size: u64,
+    pub uri: String,
+    /// Notes whose content embeds this asset; empty means it is orphaned
+    pub notes: Vec<NoteRef>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AssetUsage {
+    /// Everything under the assets folder, including generated previews
+    pub total_bytes: u64,
+    pub asset_count: u64,
+    pub preview_bytes: u64,
+    /// Largest extension first
+    pub by_extension: Vec<ExtensionUsage>,
+    pub largest: Vec<LargeAsset>,
+    pub quota_bytes: Option<u64>,
+    pub over_quota: bool,
+}
+
+impl Database {
+    /// Live notes whose content references an asset ID
+    fn notes_embedding_asset(&self, asset_id: &str) -> SqliteResult<Vec<NoteRef>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, title FROM notes
+             WHERE is_deleted = 0 AND instr(content, ?1) > 0
+             ORDER BY updated_at DESC",
+        )?;
+        let rows = stmt.query_map(params![asset_id], |row| {
+            Ok(NoteRef {
+                id: row.get(0)?,
+                title: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Original file name recorded for an attachment, if any
+    fn attachment_file_name(&self, asset_id: &str) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT file_name FROM attachments WHERE id = ?1",
+            params![asset_id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Build the usage report, listing up to `largest` of the biggest assets
+pub fn get_usage(
+    db: &Database,
+    app_data_dir: &Path,
+    largest: Option<usize>,
+) -> Result<AssetUsage, String> {
+    let assets_dir = assets::get_assets_dir(app_data_dir);
+    let mut files = Vec::new();
+
+    if assets_dir.exists() {
+        let entries = fs::read_dir(&assets_dir)
+            .map_err(|e| format!("Failed to read assets directory: {}", e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let id = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let extension = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            files.push((id, extension, metadata.len(), path));
+        }
+    }
+
+    let mut by_extension: BTreeMap<String, ExtensionUsage> = BTreeMap::new();
+    for (_, extension, size, _) in &files {
+        let usage = by_extension
+            .entry(extension.clone())
+            .or_insert_with(|| ExtensionUsage {
+                extension: extension.clone(),
+                count: 0,
+                bytes: 0,
+            });
+        usage.count += 1;
+        usage.bytes += size;
+    }
+    let mut by_extension: Vec<ExtensionUsage> = by_extension.into_values().collect();
+    by_extension.sort_by_key(|u| Reverse(u.bytes));
+
+    let asset_bytes: u64 = files.iter().map(|(_, _, size, _)| size).sum();
+    let preview_bytes = dir_size(&get_previews_dir(app_data_dir));
+    let total_bytes = asset_bytes + preview_bytes;
+
+    files.sort_by_key(|f| Reverse(f.2));
+    let mut largest_assets = Vec::new();
+    for (id, extension, size, path) in files.iter().take(largest.unwrap_or(DEFAULT_LARGEST)) {
+        let notes = db
+            .notes_embedding_asset(id)
+            .map_err(|e| format!("Database error: {}", e))?;
+        let file_name = db
+            .attachment_file_name(id)
+            .map_err(|e| format!("Database error: {}", e))?
+            .unwrap_or_else(|| {
+                path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            });
+        largest_assets.push(LargeAsset {
+            id: id.clone(),
+            file_name,
+            extension: extension.clone(),
+            size: *size,
+            uri: format!(
+                "asset://localhost/{}",
+                path.to_string_lossy().replace('\\', "/")
+            ),
+            notes,
+        });
+    }
+
+    let quota_bytes = db
+        .get_setting_i64(ASSET_QUOTA_BYTES)
+        .map_err(|e| format!("Database error: {}", e))?
+        .filter(|q| *q > 0)
+        .map(|q| q as u64);
+
+    Ok(AssetUsage {
+        total_bytes,
+        asset_count: files.len() as u64,
+        preview_bytes,
+        by_extension,
+        largest: largest_assets,
+        quota_bytes,
+        over_quota: quota_bytes.is_some_and(|q| total_bytes > q),
+    })
+}
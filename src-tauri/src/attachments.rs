@@ -0,0 +1,536 @@
+//! Non-image attachments: storage, preview rendering and text extraction.
+//!
+//! Attached files are stored in `.assets` alongside images. On save, a preview
+//! image and the file's text are extracted so attachments can be shown inline and
+//! found by search. Plain-text formats are always indexed; PDFs need the
+//! `pdf-preview` feature, which renders the first page and reads the text layer
+//! through the system pdfium library. Audio recordings have their duration
+//! probed so voice memos can be listed without loading them.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+use crate::database::{assets, now_rfc3339, Database};
+
+/// Extracted text is capped so one huge file can't bloat the database
+const MAX_TEXT_BYTES: usize = 1024 * 1024;
+
+/// Maximum number of results returned by an attachment search
+const SEARCH_LIMIT: i64 = 100;
+
+/// Extensions read as UTF-8 text for the search index
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "csv", "tsv", "json", "log", "xml", "yaml", "yml", "toml", "html",
+    "htm",
+];
+
+/// Audio formats accepted for recordings
+pub const AUDIO_EXTENSIONS: &[&str] = &["m4a", "ogg", "webm"];
+
+/// A stored attachment with its extracted preview
+#[derive(Debug, Serialize, Clone)]
+pub struct Attachment {
+    pub id: String,
+    pub uri: String,
+    pub path: String,
+    pub file_name: String,
+    pub extension: String,
+    pub size: i64,
+    /// Local URI of the rendered preview image, if one could be produced
+    pub preview_uri: Option<String>,
+    pub has_text: bool,
+    /// Playback length for audio attachments
+    pub duration_ms: Option<i64>,
+}
+
+/// An attachment whose text matched a search, with the notes that embed it
+#[derive(Debug, Serialize, Clone)]
+pub struct AttachmentMatch {
+    pub attachment: Attachment,
+    pub snippet: String,
+    pub note_ids: Vec<String>,
+}
+
+pub fn ensure_attachments_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            id TEXT PRIMARY KEY NOT NULL,
+            file_name TEXT NOT NULL,
+            extension TEXT NOT NULL,
+            size INTEGER NOT NULL DEFAULT 0,
+            preview_path TEXT,
+            text_content TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    let mut stmt = conn.prepare("PRAGMA table_info(attachments)")?;
+    let mut rows = stmt.query([])?;
+    let mut has_duration = false;
+    while let Some(row) = rows.next()? {
+        let col_name: String = row.get(1)?;
+        if col_name == "duration_ms" {
+            has_duration = true;
+            break;
+        }
+    }
+
+    if !has_duration {
+        conn.execute("ALTER TABLE attachments ADD COLUMN duration_ms INTEGER", [])?;
+    }
+
+    Ok(())
+}
+
+/// Get the directory rendered previews are written to
+pub fn get_previews_dir(app_data_dir: &Path) -> PathBuf {
+    assets::get_assets_dir(app_data_dir).join("previews")
+}
+
+fn local_uri(path: &Path) -> String {
+    format!(
+        "asset://localhost/{}",
+        path.to_string_lossy().replace('\\', "/")
+    )
+}
+
+struct AttachmentRow {
+    id: String,
+    file_name: String,
+    extension: String,
+    size: i64,
+    preview_path: Option<String>,
+    has_text: bool,
+    duration_ms: Option<i64>,
+}
+
+impl AttachmentRow {
+    fn into_attachment(self, app_data_dir: &Path) -> Attachment {
+        let path =
+            assets::get_assets_dir(app_data_dir).join(format!("{}.{}", self.id, self.extension));
+        Attachment {
+            uri: local_uri(&path),
+            path: path.to_string_lossy().to_string(),
+            preview_uri: self
+                .preview_path
+                .as_deref()
+                .map(|p| local_uri(Path::new(p))),
+            id: self.id,
+            file_name: self.file_name,
+            extension: self.extension,
+            size: self.size,
+            has_text: self.has_text,
+            duration_ms: self.duration_ms,
+        }
+    }
+}
+
+fn row_to_attachment_row(row: &rusqlite::Row) -> SqliteResult<AttachmentRow> {
+    Ok(AttachmentRow {
+        id: row.get(0)?,
+        file_name: row.get(1)?,
+        extension: row.get(2)?,
+        size: row.get(3)?,
+        preview_path: row.get(4)?,
+        has_text: row.get::<_, i32>(5)? != 0,
+        duration_ms: row.get(6)?,
+    })
+}
+
+impl Database {
+    fn insert_attachment(
+        &self,
+        row: &AttachmentRow,
+        text_content: Option<&str>,
+    ) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO attachments
+                (id, file_name, extension, size, preview_path, text_content, created_at, duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                &row.id,
+                &row.file_name,
+                &row.extension,
+                row.size,
+                &row.preview_path,
+                text_content,
+                now_rfc3339(),
+                row.duration_ms
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_attachment_row(&self, id: &str) -> SqliteResult<Option<AttachmentRow>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, file_name, extension, size, preview_path, text_content IS NOT NULL, duration_ms
+             FROM attachments WHERE id = ?1",
+            params![id],
+            row_to_attachment_row,
+        )
+        .optional()
+    }
+
+    fn delete_attachment_row(&self, id: &str) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let preview: Option<Option<String>> = conn
+            .query_row(
+                "SELECT preview_path FROM attachments WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        conn.execute("DELETE FROM attachments WHERE id = ?1", params![id])?;
+        Ok(preview.flatten())
+    }
+
+    /// Attachments whose extracted text contains `query`, with a short snippet
+    fn search_attachment_rows(&self, query: &str) -> SqliteResult<Vec<(AttachmentRow, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, file_name, extension, size, preview_path, 1, duration_ms, text_content
+             FROM attachments
+             WHERE instr(lower(text_content), lower(?1)) > 0
+             ORDER BY created_at DESC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![query, SEARCH_LIMIT], |row| {
+            let text: String = row.get(7)?;
+            Ok((row_to_attachment_row(row)?, snippet_around(&text, query)))
+        })?;
+        rows.collect()
+    }
+
+    /// Audio attachments embedded in a note's content, oldest first
+    fn audio_rows_for_note(&self, note_id: &str) -> SqliteResult<Vec<AttachmentRow>> {
+        let conn = self.conn.lock().unwrap();
+        let placeholders = AUDIO_EXTENSIONS
+            .iter()
+            .map(|ext| format!("'{}'", ext))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT a.id, a.file_name, a.extension, a.size, a.preview_path,
+                    a.text_content IS NOT NULL, a.duration_ms
+             FROM attachments a
+             JOIN notes n ON n.id = ?1
+             WHERE a.extension IN ({}) AND instr(n.content, a.id) > 0
+             ORDER BY a.created_at ASC",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(params![note_id], row_to_attachment_row)?;
+        rows.collect()
+    }
+
+    /// IDs of live notes whose content references an asset
+    fn notes_referencing_asset(&self, asset_id: &str) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id FROM notes WHERE is_deleted = 0 AND instr(content, ?1) > 0")?;
+        let rows = stmt.query_map(params![asset_id], |row| row.get(0))?;
+        rows.collect()
+    }
+}
+
+/// Up to ~160 characters of `text` around the first case-insensitive match
+fn snippet_around(text: &str, query: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let haystack: Vec<char> = text.to_lowercase().chars().collect();
+    let needle: Vec<char> = query.to_lowercase().chars().collect();
+    // Lowercasing can change lengths for a few scripts; fall back to the start
+    let at = if haystack.len() == chars.len() && !needle.is_empty() {
+        haystack
+            .windows(needle.len())
+            .position(|w| w == needle.as_slice())
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    let start = at.saturating_sub(60);
+    let end = (at + needle.len() + 100).min(chars.len());
+    let snippet: String = chars[start..end].iter().collect();
+    snippet.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Read text from a plain-text format, stripping markup from HTML
+fn extract_plain_text(path: &Path, extension: &str) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_TEXT_BYTES)]).to_string();
+    if extension == "html" || extension == "htm" {
+        Some(beck_markdown::html_to_text(&text))
+    } else {
+        Some(text)
+    }
+}
+
+#[cfg(feature = "pdf-preview")]
+mod pdf {
+    use pdfium_render::prelude::*;
+    use std::path::Path;
+
+    /// Width of the rendered first-page preview, in pixels
+    const PREVIEW_WIDTH: i32 = 800;
+
+    /// Render the first page to `preview_path` and return the document text
+    pub fn extract(path: &Path, preview_path: &Path) -> Result<(bool, String), String> {
+        let bindings = Pdfium::bind_to_system_library()
+            .map_err(|e| format!("pdfium library not available: {}", e))?;
+        let pdfium = Pdfium::new(bindings);
+        let document = pdfium
+            .load_pdf_from_file(path, None)
+            .map_err(|e| format!("Failed to open PDF: {}", e))?;
+
+        let mut rendered = false;
+        if let Ok(page) = document.pages().first() {
+            let config = PdfRenderConfig::new().set_target_width(PREVIEW_WIDTH);
+            let image = page
+                .render_with_config(&config)
+                .map_err(|e| format!("Failed to render PDF page: {}", e))?
+                .as_image();
+            image
+                .save_with_format(preview_path, image::ImageFormat::Png)
+                .map_err(|e| format!("Failed to write PDF preview: {}", e))?;
+            rendered = true;
+        }
+
+        let mut text = String::new();
+        for page in document.pages().iter() {
+            if let Ok(page_text) = page.text() {
+                text.push_str(&page_text.all());
+                text.push('\n');
+            }
+            if text.len() >= super::MAX_TEXT_BYTES {
+                break;
+            }
+        }
+
+        Ok((rendered, text))
+    }
+}
+
+/// Read an audio file's duration from its container, in milliseconds.
+///
+/// Uses the frame count from the track header when present; WebM recordings from
+/// the browser usually lack it, so fall back to the end timestamp of the last packet.
+fn probe_duration_ms(path: &Path, extension: &str) -> Option<i64> {
+    let file = fs::File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    let mut format = probed.format;
+    let track = format.default_track()?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let time_base = params
+        .time_base
+        .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))?;
+
+    let frames = match params.n_frames {
+        Some(frames) => frames,
+        None => {
+            let mut end = 0;
+            while let Ok(packet) = format.next_packet() {
+                if packet.track_id() == track_id {
+                    end = end.max(packet.ts() + packet.dur());
+                }
+            }
+            end
+        }
+    };
+
+    let time = time_base.calc_time(frames);
+    Some((time.seconds as f64 * 1000.0 + time.frac * 1000.0).round() as i64)
+}
+
+/// Produce a preview image and/or text for a stored attachment
+fn extract(path: &Path, extension: &str, preview_path: &Path) -> (bool, Option<String>) {
+    if TEXT_EXTENSIONS.contains(&extension) {
+        return (false, extract_plain_text(path, extension));
+    }
+
+    #[cfg(feature = "pdf-preview")]
+    if extension == "pdf" {
+        return match pdf::extract(path, preview_path) {
+            Ok((rendered, text)) => (rendered, Some(text).filter(|t| !t.trim().is_empty())),
+            Err(err) => {
+                eprintln!("[attachments] PDF extraction failed: {}", err);
+                (false, None)
+            }
+        };
+    }
+
+    let _ = preview_path;
+    (false, None)
+}
+
+/// Copy a file into the assets folder and index its preview and text
+pub fn save_from_path(
+    db: &Database,
+    app_data_dir: &Path,
+    source: &Path,
+) -> Result<Attachment, String> {
+    let data = fs::read(source).map_err(|e| format!("Failed to read file: {}", e))?;
+    let file_name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "attachment".to_string());
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("bin")
+        .to_ascii_lowercase();
+
+    save_bytes(db, app_data_dir, &data, &file_name, &extension)
+}
+
+/// Store raw bytes as an attachment and index its preview, text and duration
+pub fn save_bytes(
+    db: &Database,
+    app_data_dir: &Path,
+    data: &[u8],
+    file_name: &str,
+    extension: &str,
+) -> Result<Attachment, String> {
+    let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+    let asset = assets::save_image_bytes(app_data_dir, data, &extension)?;
+
+    let previews_dir = get_previews_dir(app_data_dir);
+    fs::create_dir_all(&previews_dir)
+        .map_err(|e| format!("Failed to create previews directory: {}", e))?;
+    let preview_path = previews_dir.join(format!("{}.png", asset.id));
+
+    let (rendered, text) = extract(Path::new(&asset.path), &extension, &preview_path);
+    let text = text.map(|t| truncate_to_boundary(t, MAX_TEXT_BYTES));
+    let duration_ms = if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+        probe_duration_ms(Path::new(&asset.path), &extension)
+    } else {
+        None
+    };
+
+    let row = AttachmentRow {
+        id: asset.id,
+        file_name: file_name.to_string(),
+        extension,
+        size: data.len() as i64,
+        preview_path: rendered.then(|| preview_path.to_string_lossy().to_string()),
+        has_text: text.is_some(),
+        duration_ms,
+    };
+    db.insert_attachment(&row, text.as_deref())
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(row.into_attachment(app_data_dir))
+}
+
+/// Save an audio recording, rejecting formats the player can't handle
+pub fn save_audio(
+    db: &Database,
+    app_data_dir: &Path,
+    data: &[u8],
+    extension: &str,
+) -> Result<Attachment, String> {
+    let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+    if !AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(format!(
+            "Unsupported audio format: {} (expected one of {})",
+            extension,
+            AUDIO_EXTENSIONS.join(", ")
+        ));
+    }
+    let file_name = format!(
+        "Recording {}.{}",
+        chrono::Local::now().format("%Y-%m-%d %H-%M-%S"),
+        extension
+    );
+    save_bytes(db, app_data_dir, data, &file_name, &extension)
+}
+
+/// Audio attachments embedded in a note
+pub fn list_audio_for_note(
+    db: &Database,
+    app_data_dir: &Path,
+    note_id: &str,
+) -> Result<Vec<Attachment>, String> {
+    db.audio_rows_for_note(note_id)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|r| r.into_attachment(app_data_dir))
+                .collect()
+        })
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+fn truncate_to_boundary(mut text: String, max: usize) -> String {
+    if text.len() > max {
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+/// Look up a stored attachment
+pub fn get(db: &Database, app_data_dir: &Path, id: &str) -> Result<Option<Attachment>, String> {
+    db.get_attachment_row(id)
+        .map(|row| row.map(|r| r.into_attachment(app_data_dir)))
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Search attachment text
+pub fn search(
+    db: &Database,
+    app_data_dir: &Path,
+    query: &str,
+) -> Result<Vec<AttachmentMatch>, String> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows = db
+        .search_attachment_rows(query.trim())
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut matches = Vec::with_capacity(rows.len());
+    for (row, snippet) in rows {
+        let note_ids = db
+            .notes_referencing_asset(&row.id)
+            .map_err(|e| format!("Database error: {}", e))?;
+        matches.push(AttachmentMatch {
+            attachment: row.into_attachment(app_data_dir),
+            snippet,
+            note_ids,
+        });
+    }
+    Ok(matches)
+}
+
+/// Drop the index row and rendered preview for a deleted asset
+pub fn remove(db: &Database, asset_id: &str) -> Result<(), String> {
+    let preview = db
+        .delete_attachment_row(asset_id)
+        .map_err(|e| format!("Database error: {}", e))?;
+    if let Some(preview) = preview {
+        let _ = fs::remove_file(preview);
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! Single-file database snapshots.
+//!
+//! Backups are written with `VACUUM INTO`, which copies a consistent view of
+//! the database inside one read transaction, so a snapshot can be taken while
+//! the app keeps writing (WAL mode lets both run at once). The copy is written
+//! under a temporary name, opened read-only and checked with
+//! `PRAGMA integrity_check`, and only then renamed into place, so every file
+//! in the backups folder is a verified snapshot. After each backup the oldest
+//! ones beyond the `backup_retention` setting are deleted.
+
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::Database;
+use crate::settings::BACKUP_RETENTION;
+
+/// Backups kept when `backup_retention` is not set
+const DEFAULT_RETENTION: usize = 10;
+
+const BACKUP_PREFIX: &str = "notes-";
+const BACKUP_EXTENSION: &str = "db";
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub path: String,
+    pub size_bytes: u64,
+    /// RFC 3339 time the snapshot was taken, from the file name
+    pub created_at: String,
+}
+
+/// Get the backups directory path
+pub fn get_backups_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("backups")
+}
+
+impl Database {
+    /// Write a consistent copy of the database to `path`, which must not exist
+    fn vacuum_into(&self, path: &Path) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "VACUUM INTO ?1",
+            params![path.to_string_lossy().to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Number of backups to keep; 0 keeps all of them
+    fn backup_retention(&self) -> rusqlite::Result<usize> {
+        Ok(self
+            .get_setting_i64(BACKUP_RETENTION)?
+            .map_or(DEFAULT_RETENTION, |n| n.max(0) as usize))
+    }
+}
+
+/// Open a snapshot read-only and make sure SQLite considers it intact
+pub(crate) fn verify_backup(path: &Path) -> Result<(), String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open backup: {}", e))?;
+    let result: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to check backup: {}", e))?;
+    if result != "ok" {
+        return Err(format!("Backup failed integrity check: {}", result));
+    }
+    conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| format!("Backup is missing the notes table: {}", e))?;
+    Ok(())
+}
+
+/// Snapshot time encoded in a backup file name
+fn backup_time(file_name: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let stamp = file_name
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_EXTENSION)?
+        .strip_suffix('.')?;
+    chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Verified backups in the backups folder, newest first
+pub fn list_backups(app_data_dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    let backups_dir = get_backups_dir(app_data_dir);
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(&backups_dir)
+        .map_err(|e| format!("Failed to read backups directory: {}", e))?;
+
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let created_at = backup_time(&file_name)?;
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| BackupInfo {
+                path: entry.path().to_string_lossy().to_string(),
+                file_name,
+                size_bytes: metadata.len(),
+                created_at: created_at.to_rfc3339(),
+            })
+        })
+        .collect();
+    // The timestamped names sort chronologically
+    backups.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    Ok(backups)
+}
+
+/// Delete the oldest backups beyond `keep`. Returns how many were removed.
+fn prune_backups(app_data_dir: &Path, keep: usize) -> Result<usize, String> {
+    if keep == 0 {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for backup in list_backups(app_data_dir)?.iter().skip(keep) {
+        fs::remove_file(&backup.path)
+            .map_err(|e| format!("Failed to delete old backup {}: {}", backup.file_name, e))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Take a verified snapshot of the database and apply the retention limit
+pub fn create_backup(db: &Database, app_data_dir: &Path) -> Result<BackupInfo, String> {
+    let backups_dir = get_backups_dir(app_data_dir);
+    fs::create_dir_all(&backups_dir)
+        .map_err(|e| format!("Failed to create backups directory: {}", e))?;
+
+    let now = chrono::Utc::now();
+    let file_name = format!(
+        "{}{}.{}",
+        BACKUP_PREFIX,
+        now.format("%Y%m%d-%H%M%S"),
+        BACKUP_EXTENSION
+    );
+    let path = backups_dir.join(&file_name);
+    if path.exists() {
+        return Err("A backup was already taken this second; try again".to_string());
+    }
+
+    // VACUUM INTO refuses to overwrite, so clear out any earlier failed attempt
+    let partial = backups_dir.join(format!("{}.partial", file_name));
+    let _ = fs::remove_file(&partial);
+
+    let written = db
+        .vacuum_into(&partial)
+        .map_err(|e| format!("Failed to write backup: {}", e))
+        .and_then(|_| verify_backup(&partial))
+        .and_then(|_| {
+            fs::rename(&partial, &path).map_err(|e| format!("Failed to save backup: {}", e))
+        });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(err);
+    }
+
+    let keep = db
+        .backup_retention()
+        .map_err(|e| format!("Database error: {}", e))?;
+    prune_backups(app_data_dir, keep)?;
+
+    let size_bytes = fs::metadata(&path)
+        .map_err(|e| format!("Failed to read backup: {}", e))?
+        .len();
+    Ok(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        size_bytes,
+        created_at: backup_time(&file_name).unwrap_or(now).to_rfc3339(),
+        file_name,
+    })
+}
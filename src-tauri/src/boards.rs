@@ -0,0 +1,674 @@
+//! Kanban boards whose cards are notes.
+//!
+//! A board has ordered columns, and each card places a note in a column. Boards,
+//! columns and cards sync like folders: every row carries `updated_at` and a
+//! soft-delete flag, and conflicting edits resolve last-writer-wins.
+//!
+//! Rows reference each other without foreign keys because sync can deliver a
+//! card before the note or column it points at.
+
+use rusqlite::{params, Connection, Result as SqliteResult, Transaction};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::database::{now_rfc3339, Database};
+use crate::oplog::{record_op, record_ops_for, OpEntity, OpKind, OPLOG_ENTITY_IDS};
+use crate::timestamp::Timestamp;
+
+/// Columns created for a new board
+const DEFAULT_COLUMNS: &[&str] = &["To Do", "In Progress", "Done"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Board {
+    pub id: String,
+    pub name: String,
+    /// Folder the board was created from, if any
+    pub folder_id: Option<String>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BoardColumn {
+    pub id: String,
+    pub board_id: String,
+    pub name: String,
+    /// Sort key; fractional so a column can be moved between two others
+    pub position: f64,
+    pub updated_at: Timestamp,
+    pub is_deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BoardCard {
+    pub id: String,
+    pub board_id: String,
+    pub column_id: String,
+    pub note_id: String,
+    /// Sort key within the column
+    pub position: f64,
+    pub updated_at: Timestamp,
+    pub is_deleted: bool,
+}
+
+/// A card with the note fields the board view displays
+#[derive(Debug, Serialize, Clone)]
+pub struct BoardCardView {
+    #[serde(flatten)]
+    pub card: BoardCard,
+    pub note_title: String,
+    pub note_preview: String,
+}
+
+/// A board with its live columns and cards
+#[derive(Debug, Serialize, Clone)]
+pub struct BoardDetail {
+    pub board: Board,
+    pub columns: Vec<BoardColumn>,
+    pub cards: Vec<BoardCardView>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardInput {
+    pub id: Option<String>,
+    pub name: String,
+    pub folder_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardColumnInput {
+    pub id: Option<String>,
+    pub board_id: String,
+    pub name: String,
+    /// Appended after the last column when omitted
+    pub position: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardCardInput {
+    pub id: Option<String>,
+    pub board_id: String,
+    pub column_id: String,
+    pub note_id: String,
+    /// Appended to the end of the column when omitted
+    pub position: Option<f64>,
+}
+
+/// Board rows exchanged with the sync server
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BoardSyncPayload {
+    pub boards: Vec<Board>,
+    pub columns: Vec<BoardColumn>,
+    pub cards: Vec<BoardCard>,
+}
+
+pub fn ensure_boards_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS boards (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            folder_id TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            is_deleted INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS board_columns (
+            id TEXT PRIMARY KEY NOT NULL,
+            board_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            position REAL NOT NULL,
+            updated_at TEXT NOT NULL,
+            is_deleted INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS board_cards (
+            id TEXT PRIMARY KEY NOT NULL,
+            board_id TEXT NOT NULL,
+            column_id TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            position REAL NOT NULL,
+            updated_at TEXT NOT NULL,
+            is_deleted INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_board_columns_board_id ON board_columns(board_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_board_cards_board_id ON board_cards(board_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_board_cards_note_id ON board_cards(note_id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn row_to_board(row: &rusqlite::Row) -> SqliteResult<Board> {
+    Ok(Board {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        folder_id: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+        is_deleted: row.get::<_, i32>(5)? != 0,
+    })
+}
+
+fn row_to_column(row: &rusqlite::Row) -> SqliteResult<BoardColumn> {
+    Ok(BoardColumn {
+        id: row.get(0)?,
+        board_id: row.get(1)?,
+        name: row.get(2)?,
+        position: row.get(3)?,
+        updated_at: row.get(4)?,
+        is_deleted: row.get::<_, i32>(5)? != 0,
+    })
+}
+
+fn row_to_card(row: &rusqlite::Row) -> SqliteResult<BoardCard> {
+    Ok(BoardCard {
+        id: row.get(0)?,
+        board_id: row.get(1)?,
+        column_id: row.get(2)?,
+        note_id: row.get(3)?,
+        position: row.get(4)?,
+        updated_at: row.get(5)?,
+        is_deleted: row.get::<_, i32>(6)? != 0,
+    })
+}
+
+const BOARD_COLUMNS: &str = "id, name, folder_id, created_at, updated_at, is_deleted";
+const COLUMN_COLUMNS: &str = "id, board_id, name, position, updated_at, is_deleted";
+const CARD_COLUMNS: &str = "id, board_id, column_id, note_id, position, updated_at, is_deleted";
+
+fn get_board_row(conn: &Connection, id: &str) -> SqliteResult<Board> {
+    conn.query_row(
+        &format!("SELECT {} FROM boards WHERE id = ?1", BOARD_COLUMNS),
+        params![id],
+        row_to_board,
+    )
+}
+
+/// Position after the last live row matching `filter_column = value`
+fn next_position(
+    conn: &Connection,
+    table: &str,
+    filter_column: &str,
+    value: &str,
+) -> SqliteResult<f64> {
+    let max: Option<f64> = conn.query_row(
+        &format!(
+            "SELECT MAX(position) FROM {} WHERE {} = ?1 AND is_deleted = 0",
+            table, filter_column
+        ),
+        params![value],
+        |row| row.get(0),
+    )?;
+    Ok(max.map(|m| m + 1.0).unwrap_or(0.0))
+}
+
+fn insert_column(
+    conn: &Connection,
+    board_id: &str,
+    name: &str,
+    position: f64,
+    now: &str,
+) -> SqliteResult<()> {
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO board_columns (id, board_id, name, position, updated_at, is_deleted)
+         VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+        params![&id, board_id, name, position, now],
+    )?;
+    record_op(conn, OpEntity::BoardColumn, &id, OpKind::Upsert)
+}
+
+impl Database {
+    /// All live boards, most recently updated first
+    pub fn get_boards(&self) -> SqliteResult<Vec<Board>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM boards WHERE is_deleted = 0 ORDER BY updated_at DESC",
+            BOARD_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], row_to_board)?;
+        rows.collect()
+    }
+
+    /// A board with its columns and the cards whose notes still exist
+    pub fn get_board(&self, id: &str) -> SqliteResult<Option<BoardDetail>> {
+        let conn = self.conn.lock().unwrap();
+        let board = match get_board_row(&conn, id) {
+            Ok(board) if !board.is_deleted => board,
+            Ok(_) | Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM board_columns
+             WHERE board_id = ?1 AND is_deleted = 0
+             ORDER BY position ASC",
+            COLUMN_COLUMNS
+        ))?;
+        let columns = stmt
+            .query_map(params![id], row_to_column)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.board_id, c.column_id, c.note_id, c.position, c.updated_at,
+                    c.is_deleted, n.title, n.preview
+             FROM board_cards c
+             JOIN notes n ON n.id = c.note_id
+             JOIN board_columns col ON col.id = c.column_id AND col.is_deleted = 0
+             WHERE c.board_id = ?1 AND c.is_deleted = 0 AND n.is_deleted = 0
+             ORDER BY c.position ASC",
+        )?;
+        let cards = stmt
+            .query_map(params![id], |row| {
+                Ok(BoardCardView {
+                    card: row_to_card(row)?,
+                    note_title: row.get(7)?,
+                    note_preview: row.get(8)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(Some(BoardDetail {
+            board,
+            columns,
+            cards,
+        }))
+    }
+
+    /// Create or rename a board. New boards get the default columns.
+    pub fn save_board(&self, input: BoardInput) -> SqliteResult<Board> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = now_rfc3339();
+        let is_new = input.id.is_none();
+        let id = input.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        tx.execute(
+            "INSERT INTO boards (id, name, folder_id, created_at, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?4, 0)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                folder_id = excluded.folder_id,
+                updated_at = excluded.updated_at,
+                is_deleted = 0",
+            params![&id, &input.name, &input.folder_id, &now],
+        )?;
+        record_op(&tx, OpEntity::Board, &id, OpKind::Upsert)?;
+
+        if is_new {
+            for (i, name) in DEFAULT_COLUMNS.iter().enumerate() {
+                insert_column(&tx, &id, name, i as f64, &now)?;
+            }
+        }
+
+        let board = get_board_row(&tx, &id)?;
+        tx.commit()?;
+        Ok(board)
+    }
+
+    /// Create a board for a folder with one card per note, all in the first column
+    pub fn create_board_from_folder(&self, folder_id: &str, name: &str) -> SqliteResult<Board> {
+        let board = self.save_board(BoardInput {
+            id: None,
+            name: name.to_string(),
+            folder_id: Some(folder_id.to_string()),
+        })?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = now_rfc3339();
+        let first_column: String = tx.query_row(
+            "SELECT id FROM board_columns WHERE board_id = ?1 ORDER BY position ASC LIMIT 1",
+            params![&board.id],
+            |row| row.get(0),
+        )?;
+        let note_ids = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM notes WHERE folder_id = ?1 AND is_deleted = 0 ORDER BY updated_at DESC",
+            )?;
+            let rows = stmt.query_map(params![folder_id], |row| row.get::<_, String>(0))?;
+            rows.collect::<SqliteResult<Vec<_>>>()?
+        };
+        for (i, note_id) in note_ids.iter().enumerate() {
+            let card_id = Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO board_cards (id, board_id, column_id, note_id, position, updated_at, is_deleted)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)",
+                params![&card_id, &board.id, &first_column, note_id, i as f64, &now],
+            )?;
+            record_op(&tx, OpEntity::BoardCard, &card_id, OpKind::Upsert)?;
+        }
+        tx.commit()?;
+        Ok(board)
+    }
+
+    /// Soft-delete a board along with its columns and cards
+    pub fn delete_board(&self, id: &str) -> SqliteResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = now_rfc3339();
+        let deleted = tx.execute(
+            "UPDATE boards SET is_deleted = 1, updated_at = ?2 WHERE id = ?1 AND is_deleted = 0",
+            params![id, &now],
+        )?;
+        tx.execute(
+            "UPDATE board_columns SET is_deleted = 1, updated_at = ?2 WHERE board_id = ?1 AND is_deleted = 0",
+            params![id, &now],
+        )?;
+        tx.execute(
+            "UPDATE board_cards SET is_deleted = 1, updated_at = ?2 WHERE board_id = ?1 AND is_deleted = 0",
+            params![id, &now],
+        )?;
+        if deleted > 0 {
+            record_op(&tx, OpEntity::Board, id, OpKind::Delete)?;
+        }
+        record_ops_for(
+            &tx,
+            OpEntity::BoardColumn,
+            OpKind::Delete,
+            "SELECT id FROM board_columns WHERE board_id = ?1 AND is_deleted = 1 AND updated_at = ?2",
+            params![id, &now],
+        )?;
+        record_ops_for(
+            &tx,
+            OpEntity::BoardCard,
+            OpKind::Delete,
+            "SELECT id FROM board_cards WHERE board_id = ?1 AND is_deleted = 1 AND updated_at = ?2",
+            params![id, &now],
+        )?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Create, rename or move a column
+    pub fn save_board_column(&self, input: BoardColumnInput) -> SqliteResult<BoardColumn> {
+        let conn = self.conn.lock().unwrap();
+        let now = now_rfc3339();
+        let id = input.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let position = match input.position {
+            Some(position) => position,
+            None => next_position(&conn, "board_columns", "board_id", &input.board_id)?,
+        };
+
+        conn.execute(
+            "INSERT INTO board_columns (id, board_id, name, position, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                position = CASE WHEN ?6 THEN excluded.position ELSE board_columns.position END,
+                updated_at = excluded.updated_at,
+                is_deleted = 0",
+            params![
+                &id,
+                &input.board_id,
+                &input.name,
+                position,
+                &now,
+                input.position.is_some()
+            ],
+        )?;
+        record_op(&conn, OpEntity::BoardColumn, &id, OpKind::Upsert)?;
+
+        conn.query_row(
+            &format!("SELECT {} FROM board_columns WHERE id = ?1", COLUMN_COLUMNS),
+            params![&id],
+            row_to_column,
+        )
+    }
+
+    /// Soft-delete a column and the cards in it
+    pub fn delete_board_column(&self, id: &str) -> SqliteResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = now_rfc3339();
+        let deleted = tx.execute(
+            "UPDATE board_columns SET is_deleted = 1, updated_at = ?2 WHERE id = ?1 AND is_deleted = 0",
+            params![id, &now],
+        )?;
+        tx.execute(
+            "UPDATE board_cards SET is_deleted = 1, updated_at = ?2 WHERE column_id = ?1 AND is_deleted = 0",
+            params![id, &now],
+        )?;
+        if deleted > 0 {
+            record_op(&tx, OpEntity::BoardColumn, id, OpKind::Delete)?;
+        }
+        record_ops_for(
+            &tx,
+            OpEntity::BoardCard,
+            OpKind::Delete,
+            "SELECT id FROM board_cards WHERE column_id = ?1 AND is_deleted = 1 AND updated_at = ?2",
+            params![id, &now],
+        )?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Add a note to a board, or move an existing card to another column/position
+    pub fn save_board_card(&self, input: BoardCardInput) -> SqliteResult<BoardCard> {
+        let conn = self.conn.lock().unwrap();
+        let now = now_rfc3339();
+        let id = input.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let position = match input.position {
+            Some(position) => position,
+            None => next_position(&conn, "board_cards", "column_id", &input.column_id)?,
+        };
+
+        conn.execute(
+            "INSERT INTO board_cards (id, board_id, column_id, note_id, position, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)
+             ON CONFLICT(id) DO UPDATE SET
+                column_id = excluded.column_id,
+                position = excluded.position,
+                updated_at = excluded.updated_at,
+                is_deleted = 0",
+            params![
+                &id,
+                &input.board_id,
+                &input.column_id,
+                &input.note_id,
+                position,
+                &now
+            ],
+        )?;
+        record_op(&conn, OpEntity::BoardCard, &id, OpKind::Upsert)?;
+
+        conn.query_row(
+            &format!("SELECT {} FROM board_cards WHERE id = ?1", CARD_COLUMNS),
+            params![&id],
+            row_to_card,
+        )
+    }
+
+    /// Remove a card from its board (the note itself is untouched)
+    pub fn delete_board_card(&self, id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(
+            "UPDATE board_cards SET is_deleted = 1, updated_at = ?2 WHERE id = ?1 AND is_deleted = 0",
+            params![id, now_rfc3339()],
+        )?;
+        if deleted > 0 {
+            record_op(&conn, OpEntity::BoardCard, id, OpKind::Delete)?;
+        }
+        Ok(deleted > 0)
+    }
+
+    /// Board rows changed since a timestamp (RFC3339), including deletions
+    pub fn get_boards_updated_since(&self, since: Option<&str>) -> SqliteResult<BoardSyncPayload> {
+        let conn = self.conn.lock().unwrap();
+        let since = since.unwrap_or("");
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM boards WHERE updated_at > ?1 ORDER BY updated_at ASC",
+            BOARD_COLUMNS
+        ))?;
+        let boards = stmt
+            .query_map(params![since], row_to_board)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM board_columns WHERE updated_at > ?1 ORDER BY updated_at ASC",
+            COLUMN_COLUMNS
+        ))?;
+        let columns = stmt
+            .query_map(params![since], row_to_column)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM board_cards WHERE updated_at > ?1 ORDER BY updated_at ASC",
+            CARD_COLUMNS
+        ))?;
+        let cards = stmt
+            .query_map(params![since], row_to_card)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(BoardSyncPayload {
+            boards,
+            columns,
+            cards,
+        })
+    }
+
+    /// Board rows journaled in the oplog between two op ids, including deletions
+    pub fn get_boards_in_oplog(
+        &self,
+        after_op: i64,
+        up_to_op: i64,
+    ) -> SqliteResult<BoardSyncPayload> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM boards WHERE id IN ({}) ORDER BY updated_at ASC",
+            BOARD_COLUMNS, OPLOG_ENTITY_IDS
+        ))?;
+        let boards = stmt
+            .query_map(
+                params![OpEntity::Board.as_str(), after_op, up_to_op],
+                row_to_board,
+            )?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM board_columns WHERE id IN ({}) ORDER BY updated_at ASC",
+            COLUMN_COLUMNS, OPLOG_ENTITY_IDS
+        ))?;
+        let columns = stmt
+            .query_map(
+                params![OpEntity::BoardColumn.as_str(), after_op, up_to_op],
+                row_to_column,
+            )?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM board_cards WHERE id IN ({}) ORDER BY updated_at ASC",
+            CARD_COLUMNS, OPLOG_ENTITY_IDS
+        ))?;
+        let cards = stmt
+            .query_map(
+                params![OpEntity::BoardCard.as_str(), after_op, up_to_op],
+                row_to_card,
+            )?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(BoardSyncPayload {
+            boards,
+            columns,
+            cards,
+        })
+    }
+
+    /// Apply board rows from a remote sync. Uses last-writer-wins based on updated_at.
+    pub fn apply_sync_boards(&self, payload: BoardSyncPayload) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        apply_boards(&tx, payload)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn apply_boards(tx: &Transaction, payload: BoardSyncPayload) -> SqliteResult<()> {
+    for board in payload.boards {
+        tx.execute(
+            "INSERT INTO boards (id, name, folder_id, created_at, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                folder_id = excluded.folder_id,
+                updated_at = excluded.updated_at,
+                is_deleted = excluded.is_deleted
+             WHERE excluded.updated_at > boards.updated_at",
+            params![
+                board.id,
+                board.name,
+                board.folder_id,
+                board.created_at,
+                board.updated_at,
+                board.is_deleted as i32,
+            ],
+        )?;
+    }
+
+    for column in payload.columns {
+        tx.execute(
+            "INSERT INTO board_columns (id, board_id, name, position, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                position = excluded.position,
+                updated_at = excluded.updated_at,
+                is_deleted = excluded.is_deleted
+             WHERE excluded.updated_at > board_columns.updated_at",
+            params![
+                column.id,
+                column.board_id,
+                column.name,
+                column.position,
+                column.updated_at,
+                column.is_deleted as i32,
+            ],
+        )?;
+    }
+
+    for card in payload.cards {
+        tx.execute(
+            "INSERT INTO board_cards (id, board_id, column_id, note_id, position, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET
+                column_id = excluded.column_id,
+                position = excluded.position,
+                updated_at = excluded.updated_at,
+                is_deleted = excluded.is_deleted
+             WHERE excluded.updated_at > board_cards.updated_at",
+            params![
+                card.id,
+                card.board_id,
+                card.column_id,
+                card.note_id,
+                card.position,
+                card.updated_at,
+                card.is_deleted as i32,
+            ],
+        )?;
+    }
+
+    Ok(())
+}
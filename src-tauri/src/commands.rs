@@ -1,7 +1,58 @@
+use crate::analytics::{self, ActivityDay, ActivityKind};
+use crate::app_lock::{self, AppLock, AppLockStatus};
+use crate::asset_usage;
+use crate::attachments;
+use crate::backups::{self, BackupInfo};
+use crate::boards::{
+    Board, BoardCard, BoardCardInput, BoardColumn, BoardColumnInput, BoardDetail, BoardInput,
+    BoardSyncPayload,
+};
+use crate::crash_reports;
+use crate::crdt_clients::{self, CrdtClientId};
+use crate::data_dir::{self, DataDir, StartupError};
+use crate::data_import::{self, ClipboardTable, CsvImportOptions, DataImportReport};
 use crate::database::{
     assets, CrdtState, CrdtStateInput, Database, Folder, FolderInput, Note, NoteInput, NoteSummary,
 };
-use tauri::{Manager, State};
+use crate::export::{self, ExportFormat};
+use crate::export_profiles::{self, ExportProfile, ExportProfileInput, ExportProfileReport};
+use crate::flashcards::Card;
+use crate::guest::GuestMode;
+use crate::highlights::{self, Highlight, HighlightFilter};
+use crate::incoming::{self, IncomingChange, IncomingChangeDiff};
+use crate::integrity::{self, IntegrityFix, IntegrityReport};
+use crate::keywords::{KeywordSuggestion, VaultKeyword};
+use crate::locks::NoteLocks;
+use crate::mirror::{self, MirrorReport};
+use crate::note_sync::{self, PausedNote};
+use crate::offline::{self, OfflineNote, OfflineSummary};
+use crate::oplog::{OplogEntry, PendingChanges};
+use crate::publish::{self, PublishDelivery, PublishTarget, PublishTargetInput};
+use crate::recovery::{self, RecoveryState, RecoveryStatus, SalvageReport};
+use crate::reindex::{self, RebuildReport, REINDEX_PROGRESS_EVENT};
+use crate::relations::{NoteRelation, NoteRelations, RelationKind};
+use crate::remote_cache;
+use crate::retention::{self, RetentionPolicy};
+use crate::review::{self, ReviewNote, StaleNote};
+use crate::scripting::{Hook, ScriptHost, ScriptInfo};
+use crate::settings;
+use crate::snippets::{self, CodeSnippet};
+use crate::sync_profiles::{self, ActiveSyncProfile, SyncProfile, SyncProfileInput};
+use crate::task_integrations::{
+    self, ExportedTask, TaskIntegration, TaskIntegrationInput, TaskPushReport, TaskSyncReport,
+};
+use crate::template_library::{self, NoteTemplate, TemplateInput};
+use crate::templates::{self, RenderedTemplate, TemplateContext};
+use crate::timestamp::Timestamp;
+use crate::titles::TitleConflict;
+use crate::trash::{self, ArchivedNote, PurgeReport};
+use crate::unfurl::{self, LinkPreview};
+use crate::updates::{self, UpdateCheck};
+use crate::ws_hub::{WsHub, WsStatus};
+use beck_crash::CrashReport;
+use beck_markdown::RedactionRule;
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, Manager, State};
 
 /// Error type for command responses
 #[derive(Debug, serde::Serialize)]
@@ -23,6 +74,32 @@ impl From<String> for CommandError {
     }
 }
 
+/// The data directory the app opened, which may be a location the user chose
+/// instead of the platform default
+fn app_data_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, CommandError> {
+    app_handle
+        .try_state::<DataDir>()
+        .map(|dir| dir.0.clone())
+        .ok_or_else(|| CommandError {
+            message: "The data directory is unavailable".to_string(),
+        })
+}
+
+/// Error from `save_note`: either a plain command error or a title conflict
+/// the frontend can offer to resolve with one of the suggested titles
+#[derive(Debug, serde::Serialize)]
+#[serde(untagged)]
+pub enum SaveNoteError {
+    Command(CommandError),
+    TitleConflict(TitleConflict),
+}
+
+impl<E: Into<CommandError>> From<E> for SaveNoteError {
+    fn from(err: E) -> Self {
+        SaveNoteError::Command(err.into())
+    }
+}
+
 // ============================================================================
 // Note Commands
 // ============================================================================
@@ -33,10 +110,15 @@ pub async fn get_all_notes(db: State<'_, Database>) -> Result<Vec<NoteSummary>,
     db.get_all_notes().map_err(|e| e.into())
 }
 
-/// Get a single note by ID
+/// Get a single note by ID. Deleted notes are hidden unless `include_deleted` is set.
 #[tauri::command]
-pub async fn get_note(db: State<'_, Database>, id: String) -> Result<Option<Note>, CommandError> {
-    db.get_note_by_id(&id).map_err(|e| e.into())
+pub async fn get_note(
+    db: State<'_, Database>,
+    id: String,
+    include_deleted: Option<bool>,
+) -> Result<Option<Note>, CommandError> {
+    db.get_note(&id, include_deleted.unwrap_or(false))
+        .map_err(|e| e.into())
 }
 
 /// Get notes by folder ID (pass null for root-level notes)
@@ -49,46 +131,156 @@ pub async fn get_notes_by_folder(
         .map_err(|e| e.into())
 }
 
-/// Save a note (create or update)
+/// Save a note (create or update). Fails while an export/import job holds the note.
+/// Notes in a folder with a publish target are sent to it in the background.
 #[tauri::command]
-pub async fn save_note(db: State<'_, Database>, note: NoteInput) -> Result<Note, CommandError> {
-    db.save_note(note).map_err(|e| e.into())
+pub async fn save_note(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    scripts: State<'_, ScriptHost>,
+    note: NoteInput,
+) -> Result<Note, SaveNoteError> {
+    // The title check and the lookup run under the lock, so they see the notes
+    // as they are written
+    let id = note.id.clone();
+    let saved = locks.write_with_ids(
+        || match id.as_deref() {
+            Some(id) => Ok::<_, CommandError>(db.save_note_ids(id)?),
+            None => Ok(Vec::new()),
+        },
+        || {
+            if db.unique_titles_enabled()? {
+                if let Some(conflict) = db.find_title_conflict(&note)? {
+                    return Ok(Err(conflict));
+                }
+            }
+            let created = match note.id.as_deref() {
+                Some(id) => db.get_note_by_id(id)?.is_none(),
+                None => true,
+            };
+            Ok(Ok((db.save_note(note)?, created)))
+        },
+    )?;
+    let (saved, created) = saved.map_err(SaveNoteError::TitleConflict)?;
+
+    if created {
+        scripts.dispatch(&db, Hook::NoteCreated, Some(&saved));
+    }
+    scripts.dispatch(&db, Hook::NoteSaved, Some(&saved));
+
+    match publish::prepare(&db, &saved) {
+        Ok(Some(job)) => {
+            tauri::async_runtime::spawn(async move {
+                let db = app_handle.state::<Database>();
+                if let Err(err) = publish::deliver(&db, job).await {
+                    eprintln!("Failed to publish note: {}", err);
+                }
+            });
+        }
+        Ok(None) => {}
+        Err(err) => eprintln!("Failed to queue note for publishing: {}", err),
+    }
+    Ok(saved)
 }
 
 /// Delete a note by ID
 #[tauri::command]
-pub async fn delete_note(db: State<'_, Database>, id: String) -> Result<bool, CommandError> {
-    db.delete_note(&id).map_err(|e| e.into())
+pub async fn delete_note(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+) -> Result<bool, CommandError> {
+    locks.write([&id], || db.delete_note(&id).map_err(|e| e.into()))
 }
 
 /// Move a note to a folder
 #[tauri::command]
 pub async fn move_note(
     db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
     id: String,
     folder_id: Option<String>,
 ) -> Result<(), CommandError> {
-    db.move_note(&id, folder_id.as_deref())
-        .map_err(|e| e.into())
+    locks.write([&id], || {
+        db.move_note(&id, folder_id.as_deref())
+            .map_err(|e| e.into())
+    })
 }
 
 /// Get notes updated since an RFC3339 timestamp. Includes deleted notes.
 #[tauri::command]
 pub async fn get_notes_updated_since(
     db: State<'_, Database>,
-    since: Option<String>,
+    since: Option<Timestamp>,
 ) -> Result<Vec<Note>, CommandError> {
-    db.get_notes_updated_since(since.as_deref())
+    db.get_notes_updated_since(since.as_ref().map(Timestamp::as_str))
         .map_err(|e| e.into())
 }
 
-/// Apply notes pulled from a remote sync.
+/// Apply notes pulled from a remote sync. Fails while a job holds any of the
+/// notes, so the pull is retried.
 #[tauri::command]
 pub async fn apply_sync_notes(
     db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
     notes: Vec<Note>,
 ) -> Result<(), CommandError> {
-    db.apply_sync_notes(notes).map_err(|e| e.into())
+    let ids: Vec<String> = notes.iter().map(|note| note.id.clone()).collect();
+    locks.write(ids, || db.apply_sync_notes(notes).map_err(|e| e.into()))
+}
+
+/// Synced edits waiting for review (see `incoming`), newest first
+#[tauri::command]
+pub async fn list_incoming_changes(
+    db: State<'_, Database>,
+    include_resolved: Option<bool>,
+) -> Result<Vec<IncomingChange>, CommandError> {
+    db.list_incoming_changes(include_resolved.unwrap_or(false))
+        .map_err(|e| e.into())
+}
+
+/// Apply a queued incoming change to the local note
+#[tauri::command]
+pub async fn accept_incoming_change(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+) -> Result<Note, CommandError> {
+    incoming::accept(&db, &locks, &id).map_err(|e| e.into())
+}
+
+/// Keep the local version over a pending change, or undo an accepted or
+/// merged one
+#[tauri::command]
+pub async fn revert_incoming_change(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+) -> Result<Note, CommandError> {
+    incoming::revert(&db, &locks, &id).map_err(|e| e.into())
+}
+
+/// Three-way diff of a queued change against the note's sync base and its
+/// current local version, for merging instead of picking one side
+#[tauri::command]
+pub async fn get_incoming_change_diff(
+    db: State<'_, Database>,
+    id: String,
+) -> Result<IncomingChangeDiff, CommandError> {
+    incoming::diff(&db, &id).map_err(|e| e.into())
+}
+
+/// Resolve a queued change with the merged title and content
+#[tauri::command]
+pub async fn merge_incoming_change(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+    title: String,
+    content: String,
+) -> Result<Note, CommandError> {
+    incoming::merge(&db, &locks, &id, &title, &content).map_err(|e| e.into())
 }
 
 // ============================================================================
@@ -131,17 +323,25 @@ pub async fn save_folder(
 
 /// Delete a folder by ID
 #[tauri::command]
-pub async fn delete_folder(db: State<'_, Database>, id: String) -> Result<(), CommandError> {
-    db.delete_folder(&id).map_err(|e| e.into())
+pub async fn delete_folder(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+) -> Result<(), CommandError> {
+    // Deleting a folder trashes the notes in it
+    locks.write_with_ids(
+        || Ok::<_, CommandError>(db.folder_note_ids(&id)?),
+        || db.delete_folder(&id).map_err(|e| e.into()),
+    )
 }
 
 /// Get folders updated since an RFC3339 timestamp. Includes deleted folders.
 #[tauri::command]
 pub async fn get_folders_updated_since(
     db: State<'_, Database>,
-    since: Option<String>,
+    since: Option<Timestamp>,
 ) -> Result<Vec<Folder>, CommandError> {
-    db.get_folders_updated_since(since.as_deref())
+    db.get_folders_updated_since(since.as_ref().map(Timestamp::as_str))
         .map_err(|e| e.into())
 }
 
@@ -176,9 +376,7 @@ pub async fn save_image_asset(
     base64_data: String,
     file_extension: String,
 ) -> Result<assets::AssetResult, CommandError> {
-    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| CommandError {
-        message: format!("Failed to get app data directory: {}", e),
-    })?;
+    let app_data_dir = app_data_dir(&app_handle)?;
 
     assets::save_image_asset(&app_data_dir, &base64_data, &file_extension).map_err(|e| e.into())
 }
@@ -190,9 +388,7 @@ pub async fn save_image_bytes(
     data: Vec<u8>,
     file_extension: String,
 ) -> Result<assets::AssetResult, CommandError> {
-    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| CommandError {
-        message: format!("Failed to get app data directory: {}", e),
-    })?;
+    let app_data_dir = app_data_dir(&app_handle)?;
 
     assets::save_image_bytes(&app_data_dir, &data, &file_extension).map_err(|e| e.into())
 }
@@ -203,9 +399,7 @@ pub async fn save_image_from_path(
     app_handle: tauri::AppHandle,
     path: String,
 ) -> Result<assets::AssetResult, CommandError> {
-    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| CommandError {
-        message: format!("Failed to get app data directory: {}", e),
-    })?;
+    let app_data_dir = app_data_dir(&app_handle)?;
 
     let data = std::fs::read(&path).map_err(|e| CommandError {
         message: format!("Failed to read file: {}", e),
@@ -224,13 +418,89 @@ pub async fn save_image_from_path(
 #[tauri::command]
 pub async fn delete_asset(
     app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
     asset_id: String,
 ) -> Result<bool, CommandError> {
-    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| CommandError {
-        message: format!("Failed to get app data directory: {}", e),
-    })?;
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    let deleted = assets::delete_asset(&app_data_dir, &asset_id)?;
+    attachments::remove(&db, &asset_id)?;
+    Ok(deleted)
+}
+
+/// Save a non-image file as an attachment, extracting a preview image and
+/// text for search where the format allows
+#[tauri::command]
+pub async fn save_attachment_from_path(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    path: String,
+) -> Result<attachments::Attachment, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    attachments::save_from_path(&db, &app_data_dir, Path::new(&path)).map_err(|e| e.into())
+}
+
+/// Get a stored attachment and its preview by asset ID
+#[tauri::command]
+pub async fn get_attachment(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    asset_id: String,
+) -> Result<Option<attachments::Attachment>, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    attachments::get(&db, &app_data_dir, &asset_id).map_err(|e| e.into())
+}
+
+/// Save an audio recording (m4a, ogg or webm) as an attachment with its duration
+#[tauri::command]
+pub async fn save_audio_bytes(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    data: Vec<u8>,
+    file_extension: String,
+) -> Result<attachments::Attachment, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    attachments::save_audio(&db, &app_data_dir, &data, &file_extension).map_err(|e| e.into())
+}
+
+/// List the audio recordings embedded in a note
+#[tauri::command]
+pub async fn list_audio_assets_for_note(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<Vec<attachments::Attachment>, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    attachments::list_audio_for_note(&db, &app_data_dir, &note_id).map_err(|e| e.into())
+}
+
+/// Report disk usage of the assets folder: totals, per-extension breakdown and the
+/// `largest` biggest assets with the notes that embed them
+#[tauri::command]
+pub async fn get_asset_usage(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    largest: Option<usize>,
+) -> Result<asset_usage::AssetUsage, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    asset_usage::get_usage(&db, &app_data_dir, largest).map_err(|e| e.into())
+}
+
+/// Search the text extracted from attachments
+#[tauri::command]
+pub async fn search_attachments(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    query: String,
+) -> Result<Vec<attachments::AttachmentMatch>, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
 
-    assets::delete_asset(&app_data_dir, &asset_id).map_err(|e| e.into())
+    attachments::search(&db, &app_data_dir, &query).map_err(|e| e.into())
 }
 
 /// List all assets
@@ -238,9 +508,7 @@ pub async fn delete_asset(
 pub async fn list_assets(
     app_handle: tauri::AppHandle,
 ) -> Result<Vec<assets::AssetResult>, CommandError> {
-    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| CommandError {
-        message: format!("Failed to get app data directory: {}", e),
-    })?;
+    let app_data_dir = app_data_dir(&app_handle)?;
 
     assets::list_assets(&app_data_dir).map_err(|e| e.into())
 }
@@ -248,9 +516,7 @@ pub async fn list_assets(
 /// Get the assets directory path (for debugging/info)
 #[tauri::command]
 pub async fn get_assets_path(app_handle: tauri::AppHandle) -> Result<String, CommandError> {
-    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| CommandError {
-        message: format!("Failed to get app data directory: {}", e),
-    })?;
+    let app_data_dir = app_data_dir(&app_handle)?;
 
     let assets_dir = assets::get_assets_dir(&app_data_dir);
     Ok(assets_dir.to_string_lossy().to_string())
@@ -264,16 +530,19 @@ pub async fn get_assets_path(app_handle: tauri::AppHandle) -> Result<String, Com
 #[tauri::command]
 pub async fn save_crdt_state(
     db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
     note_id: String,
     ydoc_state: Vec<u8>,
     state_vector: Vec<u8>,
 ) -> Result<CrdtState, CommandError> {
-    db.save_crdt_state(CrdtStateInput {
-        note_id,
-        ydoc_state,
-        state_vector,
+    locks.write([note_id.clone()], || {
+        db.save_crdt_state(CrdtStateInput {
+            note_id,
+            ydoc_state,
+            state_vector,
+        })
+        .map_err(|e| e.into())
     })
-    .map_err(|e| e.into())
 }
 
 /// Get CRDT state for a note
@@ -305,18 +574,21 @@ pub async fn get_crdt_states_for_notes(
 #[tauri::command]
 pub async fn delete_crdt_state(
     db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
     note_id: String,
 ) -> Result<bool, CommandError> {
-    db.delete_crdt_state(&note_id).map_err(|e| e.into())
+    locks.write([&note_id], || {
+        db.delete_crdt_state(&note_id).map_err(|e| e.into())
+    })
 }
 
 /// Get CRDT states updated since a timestamp
 #[tauri::command]
 pub async fn get_crdt_states_updated_since(
     db: State<'_, Database>,
-    since: Option<String>,
+    since: Option<Timestamp>,
 ) -> Result<Vec<CrdtState>, CommandError> {
-    db.get_crdt_states_updated_since(since.as_deref())
+    db.get_crdt_states_updated_since(since.as_ref().map(Timestamp::as_str))
         .map_err(|e| e.into())
 }
 
@@ -324,9 +596,1440 @@ pub async fn get_crdt_states_updated_since(
 #[tauri::command]
 pub async fn apply_crdt_update(
     db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
     note_id: String,
     update: Vec<u8>,
 ) -> Result<(), CommandError> {
-    db.apply_crdt_update(&note_id, &update)
+    locks.write([&note_id], || {
+        db.apply_crdt_update(&note_id, &update)
+            .map_err(|e| e.into())
+    })
+}
+
+/// The Yjs client id the calling window's editors should use, registered with
+/// the sync server so the content they write is attributed to the user
+#[tauri::command]
+pub async fn get_crdt_client_id(
+    window: tauri::Window,
+    db: State<'_, Database>,
+) -> Result<CrdtClientId, CommandError> {
+    crdt_clients::register(&db, window.label())
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Oplog Commands
+// ============================================================================
+
+/// Local changes not yet pushed to the server, with the op id they run up to
+#[tauri::command]
+pub async fn get_pending_changes(db: State<'_, Database>) -> Result<PendingChanges, CommandError> {
+    db.get_pending_changes().map_err(|e| e.into())
+}
+
+/// Record that the changes up to `up_to_op` were pushed successfully
+#[tauri::command]
+pub async fn mark_oplog_pushed(db: State<'_, Database>, up_to_op: i64) -> Result<(), CommandError> {
+    db.mark_oplog_pushed(up_to_op).map_err(|e| e.into())
+}
+
+/// Most recent oplog entries (newest first), for diagnostics
+#[tauri::command]
+pub async fn get_oplog_entries(
+    db: State<'_, Database>,
+    limit: Option<usize>,
+) -> Result<Vec<OplogEntry>, CommandError> {
+    db.get_oplog_entries(limit.unwrap_or(200))
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Template Commands
+// ============================================================================
+
+/// Fill in a template's `{{ ... }}` placeholders (dates, title, clipboard) and
+/// report where `{{cursor}}` was. See `templates` for the grammar.
+#[tauri::command]
+pub async fn render_template(
+    template: String,
+    title: Option<String>,
+    clipboard: Option<String>,
+) -> Result<RenderedTemplate, CommandError> {
+    Ok(templates::render(
+        &template,
+        &TemplateContext { title, clipboard },
+    ))
+}
+
+/// Local templates and the sync server's shared ones, by name. A local
+/// template hides a shared one with the same name.
+#[tauri::command]
+pub async fn list_templates(db: State<'_, Database>) -> Result<Vec<NoteTemplate>, CommandError> {
+    db.list_note_templates().map_err(|e| e.into())
+}
+
+/// Add a local template (omit `id`) or update one
+#[tauri::command]
+pub async fn save_template(
+    db: State<'_, Database>,
+    template: TemplateInput,
+) -> Result<NoteTemplate, CommandError> {
+    db.save_local_template(template).map_err(|e| e.into())
+}
+
+/// Delete a local template. Returns false if there was none with this id.
+#[tauri::command]
+pub async fn delete_template(db: State<'_, Database>, id: String) -> Result<bool, CommandError> {
+    db.delete_local_template(&id).map_err(|e| e.into())
+}
+
+/// Fetch the shared templates from the active sync server, replacing the
+/// cached ones. Call alongside sync; returns how many there are.
+#[tauri::command]
+pub async fn pull_workspace_templates(db: State<'_, Database>) -> Result<usize, CommandError> {
+    template_library::pull_workspace_templates(&db)
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Scripting Commands
+// ============================================================================
+
+/// List the scripts in the scripts folder with the hooks each one defines
+/// and any compile or run error
+#[tauri::command]
+pub async fn list_scripts(scripts: State<'_, ScriptHost>) -> Result<Vec<ScriptInfo>, CommandError> {
+    Ok(scripts.list()?)
+}
+
+/// Load the scripts folder again after scripts were added or edited
+#[tauri::command]
+pub async fn reload_scripts(
+    scripts: State<'_, ScriptHost>,
+) -> Result<Vec<ScriptInfo>, CommandError> {
+    Ok(scripts.reload()?)
+}
+
+// ============================================================================
+// Integrity Commands
+// ============================================================================
+
+/// Find links to missing or trashed notes and references to missing asset
+/// files. With `fix`, broken elements are removed ("remove") or marked with
+/// `data-broken` ("flag"); links to trashed notes are left alone.
+#[tauri::command]
+pub async fn check_vault_integrity(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    guest: State<'_, GuestMode>,
+    fix: Option<IntegrityFix>,
+) -> Result<IntegrityReport, CommandError> {
+    if fix.is_some() {
+        guest.check()?;
+    }
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    integrity::check_vault_integrity(&db, &locks, &app_data_dir, fix).map_err(|e| e.into())
+}
+
+/// Rebuild the snippet, link, highlight, keyword and flashcard indexes and note
+/// previews from note content, emitting `app://reindex-progress` as it goes
+#[tauri::command]
+pub async fn rebuild_indexes(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+) -> Result<RebuildReport, CommandError> {
+    reindex::rebuild_indexes(&db, |progress| {
+        let _ = app_handle.emit(REINDEX_PROGRESS_EVENT, progress);
+    })
+    .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Backup Commands
+// ============================================================================
+
+/// Snapshot the database into the backups folder. The copy is verified before
+/// it is kept and old backups beyond the retention setting are deleted.
+#[tauri::command]
+pub async fn create_backup(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+) -> Result<BackupInfo, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    backups::create_backup(&db, &app_data_dir).map_err(|e| e.into())
+}
+
+/// List the backups in the backups folder, newest first
+#[tauri::command]
+pub async fn list_backups(app_handle: tauri::AppHandle) -> Result<Vec<BackupInfo>, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    backups::list_backups(&app_data_dir).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Mirror Commands
+// ============================================================================
+
+/// Update the Markdown mirror in the `mirror_dir` directory now instead of
+/// waiting for the scheduler
+#[tauri::command]
+pub async fn mirror_vault_now(db: State<'_, Database>) -> Result<MirrorReport, CommandError> {
+    let dir = mirror::mirror_dir(&db)?.ok_or_else(|| CommandError {
+        message: "Choose a mirror folder first".to_string(),
+    })?;
+    mirror::mirror_vault(&db, &dir).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Publish Commands
+// ============================================================================
+
+/// Send notes saved in a folder (and by default its subfolders) to a webhook
+#[tauri::command]
+pub async fn set_publish_target(
+    db: State<'_, Database>,
+    target: PublishTargetInput,
+) -> Result<PublishTarget, CommandError> {
+    db.set_publish_target(&target).map_err(|e| e.into())
+}
+
+/// Stop publishing a folder's notes
+#[tauri::command]
+pub async fn remove_publish_target(
+    db: State<'_, Database>,
+    folder_id: String,
+) -> Result<bool, CommandError> {
+    db.remove_publish_target(&folder_id).map_err(|e| e.into())
+}
+
+#[tauri::command]
+pub async fn list_publish_targets(
+    db: State<'_, Database>,
+) -> Result<Vec<PublishTarget>, CommandError> {
+    db.list_publish_targets().map_err(|e| e.into())
+}
+
+/// Outcome of each note's latest delivery, optionally for one folder's target
+#[tauri::command]
+pub async fn list_publish_deliveries(
+    db: State<'_, Database>,
+    folder_id: Option<String>,
+) -> Result<Vec<PublishDelivery>, CommandError> {
+    db.list_publish_deliveries(folder_id.as_deref())
+        .map_err(|e| e.into())
+}
+
+/// Publish a note right away, e.g. to retry a failed delivery
+#[tauri::command]
+pub async fn publish_note_now(
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<PublishDelivery, CommandError> {
+    publish::publish_note(&db, &note_id)
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Task Integration Commands
+// ============================================================================
+
+/// Task managers the current user has set up
+#[tauri::command]
+pub async fn list_task_integrations(
+    db: State<'_, Database>,
+) -> Result<Vec<TaskIntegration>, CommandError> {
+    let owner = task_integrations::current_owner(&db)?;
+    db.list_task_integrations(&owner).map_err(|e| e.into())
+}
+
+/// Add or update a task manager; its password or token goes to the keyring
+#[tauri::command]
+pub async fn save_task_integration(
+    db: State<'_, Database>,
+    integration: TaskIntegrationInput,
+) -> Result<TaskIntegration, CommandError> {
+    task_integrations::save_integration(&db, &integration).map_err(|e| e.into())
+}
+
+#[tauri::command]
+pub async fn remove_task_integration(
+    db: State<'_, Database>,
+    id: String,
+) -> Result<bool, CommandError> {
+    task_integrations::remove_integration(&db, &id).map_err(|e| e.into())
+}
+
+/// Send a note's open tasks to a task manager and update those sent before
+#[tauri::command]
+pub async fn push_note_tasks(
+    db: State<'_, Database>,
+    integration_id: String,
+    note_id: String,
+) -> Result<TaskPushReport, CommandError> {
+    task_integrations::push_note_tasks(&db, &integration_id, &note_id)
+        .await
         .map_err(|e| e.into())
 }
+
+/// Tasks sent from a note, and whether they are done
+#[tauri::command]
+pub async fn list_exported_tasks(
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<Vec<ExportedTask>, CommandError> {
+    let owner = task_integrations::current_owner(&db)?;
+    db.list_exported_tasks(&owner, &note_id)
+        .map_err(|e| e.into())
+}
+
+/// Tick the items of tasks completed in a task manager now, rather than at
+/// the next poll
+#[tauri::command]
+pub async fn sync_task_completions(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+) -> Result<TaskSyncReport, CommandError> {
+    task_integrations::sync_completions(&db, &locks)
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Offline Commands
+// ============================================================================
+
+/// Keep a note's content on this device. An evicted note is fetched from
+/// `server_url` first.
+#[tauri::command]
+pub async fn pin_offline(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    note_id: String,
+    server_url: Option<String>,
+    token: Option<String>,
+) -> Result<OfflineNote, CommandError> {
+    let server = server_url.as_deref().map(|url| (url, token.as_deref()));
+    offline::set_pinned(&db, &locks, &note_id, true, server)
+        .await
+        .map_err(|e| e.into())
+}
+
+/// Let a pinned note be evicted again when space runs short
+#[tauri::command]
+pub async fn unpin_offline(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    note_id: String,
+) -> Result<OfflineNote, CommandError> {
+    offline::set_pinned(&db, &locks, &note_id, false, None)
+        .await
+        .map_err(|e| e.into())
+}
+
+/// Drop a synced note's content from this device; it is fetched again with
+/// `restore_offline` when opened
+#[tauri::command]
+pub async fn evict_offline(
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<OfflineNote, CommandError> {
+    offline::evict(&db, &note_id).map_err(|e| e.into())
+}
+
+/// Fetch an evicted note's content and CRDT state from the server
+#[tauri::command]
+pub async fn restore_offline(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    note_id: String,
+    server_url: String,
+    token: Option<String>,
+) -> Result<Note, CommandError> {
+    offline::restore(&db, &locks, &server_url, token.as_deref(), &note_id)
+        .await
+        .map_err(|e| e.into())
+}
+
+/// Storage budget, bytes in use and which notes are pinned or evicted
+#[tauri::command]
+pub async fn get_offline_status(db: State<'_, Database>) -> Result<OfflineSummary, CommandError> {
+    offline::summary(&db).map_err(|e| e.into())
+}
+
+/// Evict notes now until the content fits the budget; returns their ids
+#[tauri::command]
+pub async fn prune_offline(db: State<'_, Database>) -> Result<Vec<String>, CommandError> {
+    offline::prune(&db).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Startup Commands
+// ============================================================================
+
+/// Why the app could not open its data directory, or null when it started
+/// normally
+#[tauri::command]
+pub async fn get_startup_error(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<StartupError>, CommandError> {
+    Ok(app_handle
+        .try_state::<StartupError>()
+        .map(|err| err.inner().clone()))
+}
+
+/// The directory holding the database and assets
+#[tauri::command]
+pub async fn get_data_dir(app_handle: tauri::AppHandle) -> Result<String, CommandError> {
+    Ok(app_data_dir(&app_handle)?.to_string_lossy().to_string())
+}
+
+/// Keep the vault in `path` from now on and restart. An existing vault is not
+/// moved; the app opens whatever is at the new location.
+#[tauri::command]
+pub async fn choose_data_dir(
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<(), CommandError> {
+    data_dir::choose(&app_handle, Path::new(&path))?;
+    app_handle.restart()
+}
+
+// ============================================================================
+// Recovery Commands
+// ============================================================================
+
+/// The recovery state, or an error when the database opened normally
+fn recovery_state(app_handle: &tauri::AppHandle) -> Result<State<'_, RecoveryState>, CommandError> {
+    app_handle
+        .try_state::<RecoveryState>()
+        .ok_or_else(|| CommandError {
+            message: "The database opened normally; recovery is only available in safe mode"
+                .to_string(),
+        })
+}
+
+/// Why the app started in safe mode and which backups could be restored, or
+/// null when the database opened normally
+#[tauri::command]
+pub async fn get_recovery_status(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<RecoveryStatus>, CommandError> {
+    let Some(state) = app_handle.try_state::<RecoveryState>() else {
+        return Ok(None);
+    };
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    Ok(Some(recovery::status(&state, &app_data_dir)))
+}
+
+/// Write every row that can still be read from the broken database to a
+/// JSON file at `path`
+#[tauri::command]
+pub async fn salvage_database(
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<SalvageReport, CommandError> {
+    recovery_state(&app_handle)?;
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    recovery::salvage(&app_data_dir, Path::new(&path)).map_err(|e| e.into())
+}
+
+/// Replace the broken database with the newest usable backup and restart
+#[tauri::command]
+pub async fn restore_latest_backup(app_handle: tauri::AppHandle) -> Result<(), CommandError> {
+    recovery_state(&app_handle)?;
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    recovery::restore_latest_backup(&app_data_dir)?;
+    app_handle.restart()
+}
+
+/// Move the broken database aside and restart with an empty one
+#[tauri::command]
+pub async fn start_fresh_database(app_handle: tauri::AppHandle) -> Result<(), CommandError> {
+    recovery_state(&app_handle)?;
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    recovery::start_fresh(&app_data_dir)?;
+    app_handle.restart()
+}
+
+// ============================================================================
+// Remote Cache Commands
+// ============================================================================
+
+/// Fetch an asset from the sync server through the local cache.
+/// Returns a local URI the frontend can render.
+#[tauri::command]
+pub async fn fetch_remote_asset(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    url: String,
+    token: Option<String>,
+) -> Result<remote_cache::CachedResource, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    remote_cache::fetch(&db, &app_data_dir, &url, token.as_deref())
+        .await
+        .map_err(|e| e.into())
+}
+
+/// Fetch a shared note (JSON) from the sync server through the local cache
+#[tauri::command]
+pub async fn fetch_shared_note(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    url: String,
+    token: Option<String>,
+) -> Result<Note, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    remote_cache::fetch_note(&db, &app_data_dir, &url, token.as_deref())
+        .await
+        .map_err(|e| e.into())
+}
+
+/// Clear all cached remote assets and notes. Returns the number of bytes freed.
+#[tauri::command]
+pub async fn clear_remote_cache(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+) -> Result<u64, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    remote_cache::clear(&db, &app_data_dir).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Link Preview Commands
+// ============================================================================
+
+/// Fetch title, description and preview image for a pasted URL so the editor
+/// can show a link card. Cached results are reused unless `refresh` is set.
+#[tauri::command]
+pub async fn unfurl_url(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    url: String,
+    refresh: Option<bool>,
+) -> Result<LinkPreview, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+
+    unfurl::unfurl(&db, &app_data_dir, &url, refresh.unwrap_or(false))
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Update Commands
+// ============================================================================
+
+/// Compare this build with the desktop release advertised by the sync server
+#[tauri::command]
+pub async fn check_client_update(server_url: String) -> Result<UpdateCheck, CommandError> {
+    updates::check(&server_url).await.map_err(|e| e.into())
+}
+
+// ============================================================================
+// Board Commands
+// ============================================================================
+
+/// Get all kanban boards
+#[tauri::command]
+pub async fn get_boards(db: State<'_, Database>) -> Result<Vec<Board>, CommandError> {
+    db.get_boards().map_err(|e| e.into())
+}
+
+/// Get a board with its columns and cards
+#[tauri::command]
+pub async fn get_board(
+    db: State<'_, Database>,
+    id: String,
+) -> Result<Option<BoardDetail>, CommandError> {
+    db.get_board(&id).map_err(|e| e.into())
+}
+
+/// Save a board (create or rename). New boards get default columns.
+#[tauri::command]
+pub async fn save_board(db: State<'_, Database>, board: BoardInput) -> Result<Board, CommandError> {
+    db.save_board(board).map_err(|e| e.into())
+}
+
+/// Create a board showing a folder's notes as cards
+#[tauri::command]
+pub async fn create_board_from_folder(
+    db: State<'_, Database>,
+    folder_id: String,
+    name: String,
+) -> Result<Board, CommandError> {
+    db.create_board_from_folder(&folder_id, &name)
+        .map_err(|e| e.into())
+}
+
+/// Delete a board with its columns and cards (notes are kept)
+#[tauri::command]
+pub async fn delete_board(db: State<'_, Database>, id: String) -> Result<bool, CommandError> {
+    db.delete_board(&id).map_err(|e| e.into())
+}
+
+/// Save a board column (create, rename or reorder)
+#[tauri::command]
+pub async fn save_board_column(
+    db: State<'_, Database>,
+    column: BoardColumnInput,
+) -> Result<BoardColumn, CommandError> {
+    db.save_board_column(column).map_err(|e| e.into())
+}
+
+/// Delete a board column and its cards
+#[tauri::command]
+pub async fn delete_board_column(
+    db: State<'_, Database>,
+    id: String,
+) -> Result<bool, CommandError> {
+    db.delete_board_column(&id).map_err(|e| e.into())
+}
+
+/// Add a note to a board or move its card
+#[tauri::command]
+pub async fn save_board_card(
+    db: State<'_, Database>,
+    card: BoardCardInput,
+) -> Result<BoardCard, CommandError> {
+    db.save_board_card(card).map_err(|e| e.into())
+}
+
+/// Remove a card from a board
+#[tauri::command]
+pub async fn delete_board_card(db: State<'_, Database>, id: String) -> Result<bool, CommandError> {
+    db.delete_board_card(&id).map_err(|e| e.into())
+}
+
+/// Get board rows updated since a timestamp (RFC3339). Includes deletions.
+#[tauri::command]
+pub async fn get_boards_updated_since(
+    db: State<'_, Database>,
+    since: Option<Timestamp>,
+) -> Result<BoardSyncPayload, CommandError> {
+    db.get_boards_updated_since(since.as_ref().map(Timestamp::as_str))
+        .map_err(|e| e.into())
+}
+
+/// Apply board rows pulled from a remote sync.
+#[tauri::command]
+pub async fn apply_sync_boards(
+    db: State<'_, Database>,
+    payload: BoardSyncPayload,
+) -> Result<(), CommandError> {
+    db.apply_sync_boards(payload).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Relation Commands
+// ============================================================================
+
+/// Relate two notes (references, blocks or part_of)
+#[tauri::command]
+pub async fn add_note_relation(
+    db: State<'_, Database>,
+    source_note_id: String,
+    target_note_id: String,
+    kind: RelationKind,
+) -> Result<NoteRelation, CommandError> {
+    db.add_note_relation(&source_note_id, &target_note_id, kind)
+        .map_err(|e| e.into())
+}
+
+/// Remove a relation between two notes
+#[tauri::command]
+pub async fn remove_note_relation(
+    db: State<'_, Database>,
+    source_note_id: String,
+    target_note_id: String,
+    kind: RelationKind,
+) -> Result<bool, CommandError> {
+    db.remove_note_relation(&source_note_id, &target_note_id, kind)
+        .map_err(|e| e.into())
+}
+
+/// Get a note's relations in both directions, optionally filtered by kind
+#[tauri::command]
+pub async fn get_note_relations(
+    db: State<'_, Database>,
+    note_id: String,
+    kind: Option<RelationKind>,
+) -> Result<NoteRelations, CommandError> {
+    db.get_note_relations(&note_id, kind).map_err(|e| e.into())
+}
+
+/// Get relations updated since a timestamp (RFC3339). Includes removals.
+#[tauri::command]
+pub async fn get_relations_updated_since(
+    db: State<'_, Database>,
+    since: Option<Timestamp>,
+) -> Result<Vec<NoteRelation>, CommandError> {
+    db.get_relations_updated_since(since.as_ref().map(Timestamp::as_str))
+        .map_err(|e| e.into())
+}
+
+/// Apply relations pulled from a remote sync.
+#[tauri::command]
+pub async fn apply_sync_relations(
+    db: State<'_, Database>,
+    relations: Vec<NoteRelation>,
+) -> Result<(), CommandError> {
+    db.apply_sync_relations(relations).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Review Commands
+// ============================================================================
+
+/// Set a note's review date (RFC3339 or YYYY-MM-DD); pass null to clear it
+#[tauri::command]
+pub async fn set_note_review_at(
+    db: State<'_, Database>,
+    note_id: String,
+    review_at: Option<String>,
+) -> Result<(), CommandError> {
+    let review_at = review_at
+        .map(|value| review::parse_review_date(&value, false))
+        .transpose()?;
+    db.set_note_review_at(&note_id, review_at)
+        .map_err(|e| e.into())
+}
+
+/// Get a note's review date, if set
+#[tauri::command]
+pub async fn get_note_review_at(
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<Option<String>, CommandError> {
+    db.get_note_review_at(&note_id).map_err(|e| e.into())
+}
+
+/// Notes due for review on or before `date` (RFC3339 or YYYY-MM-DD, defaults to now)
+#[tauri::command]
+pub async fn get_notes_due_for_review(
+    db: State<'_, Database>,
+    date: Option<String>,
+) -> Result<Vec<ReviewNote>, CommandError> {
+    let until = match date {
+        Some(value) => review::parse_review_date(&value, true)?,
+        None => chrono::Utc::now(),
+    };
+    db.get_notes_due_for_review(until).map_err(|e| e.into())
+}
+
+/// Notes neither edited nor opened in the last `threshold_months` months,
+/// least recently touched first, to suggest for review or cleanup. Notes in
+/// `exclude_folder_ids` (e.g. archive folders) and their subfolders are left
+/// out.
+#[tauri::command]
+pub async fn get_stale_notes(
+    db: State<'_, Database>,
+    threshold_months: u32,
+    exclude_folder_ids: Option<Vec<String>>,
+    limit: Option<usize>,
+) -> Result<Vec<StaleNote>, CommandError> {
+    review::stale_notes(
+        &db,
+        threshold_months,
+        &exclude_folder_ids.unwrap_or_default(),
+        limit,
+    )
+    .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Flashcard Commands
+// ============================================================================
+
+/// Flashcards due for review now, most overdue first
+#[tauri::command]
+pub async fn get_due_cards(
+    db: State<'_, Database>,
+    limit: Option<i64>,
+) -> Result<Vec<Card>, CommandError> {
+    db.get_due_cards(chrono::Utc::now(), limit)
+        .map_err(|e| e.into())
+}
+
+/// Flashcards extracted from a note
+#[tauri::command]
+pub async fn get_note_cards(
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<Vec<Card>, CommandError> {
+    db.get_note_cards(&note_id).map_err(|e| e.into())
+}
+
+/// Grade a flashcard review (0 = forgot, 5 = perfect) and reschedule it with SM-2
+#[tauri::command]
+pub async fn grade_card(
+    db: State<'_, Database>,
+    card_id: String,
+    grade: u8,
+) -> Result<Card, CommandError> {
+    if grade > 5 {
+        return Err(CommandError {
+            message: format!("Grade must be between 0 and 5, got {}", grade),
+        });
+    }
+    db.grade_card(&card_id, grade)?.ok_or_else(|| CommandError {
+        message: format!("Card not found: {}", card_id),
+    })
+}
+
+// ============================================================================
+// Settings Commands
+// ============================================================================
+
+/// Get a setting value by key
+#[tauri::command]
+pub async fn get_setting(
+    db: State<'_, Database>,
+    key: String,
+) -> Result<Option<String>, CommandError> {
+    app_lock::check_setting_key(&key)?;
+    db.get_setting(&key).map_err(|e| e.into())
+}
+
+/// Whether the app was started read-only with `--guest`
+#[tauri::command]
+pub async fn get_guest_mode(guest: State<'_, GuestMode>) -> Result<bool, CommandError> {
+    Ok(guest.0)
+}
+
+/// Set a setting value; pass null to clear it
+#[tauri::command]
+pub async fn set_setting(
+    db: State<'_, Database>,
+    key: String,
+    value: Option<String>,
+) -> Result<(), CommandError> {
+    app_lock::check_setting_key(&key)?;
+    db.set_setting(&key, value.as_deref()).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Crash Report Commands
+// ============================================================================
+
+/// Recent panics of the app, newest first, for the user to share
+#[tauri::command]
+pub async fn get_recent_crashes(
+    app_handle: tauri::AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<CrashReport>, CommandError> {
+    let dir = crash_reports::crash_dir(&app_data_dir(&app_handle)?);
+    let limit = limit.unwrap_or(20).min(beck_crash::MAX_REPORTS);
+    beck_crash::recent_reports(&dir, limit).map_err(|e| CommandError {
+        message: format!("Failed to read crash reports: {}", e),
+    })
+}
+
+/// Opt in to uploading crash reports to a Sentry-compatible DSN, or pass null
+/// to keep them on this device
+#[tauri::command]
+pub async fn set_crash_report_dsn(
+    db: State<'_, Database>,
+    dsn: Option<String>,
+) -> Result<(), CommandError> {
+    let dsn = dsn.filter(|dsn| !dsn.trim().is_empty());
+    if let Some(dsn) = &dsn {
+        beck_crash::Dsn::parse(dsn)?;
+    }
+    db.set_setting(settings::CRASH_REPORT_DSN, dsn.as_deref().map(str::trim))
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// App Lock Commands
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_app_lock_status(lock: State<'_, AppLock>) -> Result<AppLockStatus, CommandError> {
+    Ok(lock.status())
+}
+
+/// Set, change or remove the app lock passphrase (`passphrase: null`) and the
+/// idle minutes before it locks. An existing lock needs `current_passphrase`.
+#[tauri::command]
+pub async fn set_app_lock(
+    db: State<'_, Database>,
+    lock: State<'_, AppLock>,
+    current_passphrase: Option<String>,
+    passphrase: Option<String>,
+    idle_minutes: Option<u32>,
+) -> Result<AppLockStatus, CommandError> {
+    lock.configure(
+        &db,
+        current_passphrase.as_deref(),
+        passphrase.as_deref(),
+        idle_minutes,
+    )
+    .map_err(|e| e.into())
+}
+
+/// Lock the app now
+#[tauri::command]
+pub async fn lock_app(lock: State<'_, AppLock>) -> Result<AppLockStatus, CommandError> {
+    lock.lock().map_err(|e| e.into())
+}
+
+/// Unlock the app with its passphrase
+#[tauri::command]
+pub async fn unlock_app(
+    lock: State<'_, AppLock>,
+    passphrase: String,
+) -> Result<AppLockStatus, CommandError> {
+    match lock.unlock(&passphrase) {
+        Ok(status) => Ok(status),
+        Err(err) => {
+            tokio::time::sleep(app_lock::FAILED_UNLOCK_DELAY).await;
+            Err(err.into())
+        }
+    }
+}
+
+// ============================================================================
+// Export Commands
+// ============================================================================
+
+/// Export a note to a Markdown or HTML file at the given path. With
+/// `backlinks`, the file ends with a "Linked from" section.
+#[tauri::command]
+pub async fn export_note(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+    format: ExportFormat,
+    backlinks: Option<bool>,
+    path: String,
+) -> Result<(), CommandError> {
+    export::export_note(
+        &db,
+        &locks,
+        &id,
+        format,
+        backlinks.unwrap_or(false),
+        Path::new(&path),
+    )
+    .map_err(|e| e.into())
+}
+
+/// Redaction rules applied to exported and published notes
+#[tauri::command]
+pub async fn get_redaction_rules(
+    db: State<'_, Database>,
+) -> Result<Vec<RedactionRule>, CommandError> {
+    export::redaction_rules(&db).map_err(|e| e.into())
+}
+
+/// Replace the redaction rules; an empty list turns redaction off
+#[tauri::command]
+pub async fn set_redaction_rules(
+    db: State<'_, Database>,
+    rules: Vec<RedactionRule>,
+) -> Result<(), CommandError> {
+    export::set_redaction_rules(&db, &rules).map_err(|e| e.into())
+}
+
+/// Export profiles, optionally only those attached to one folder
+#[tauri::command]
+pub async fn list_export_profiles(
+    db: State<'_, Database>,
+    folder_id: Option<String>,
+) -> Result<Vec<ExportProfile>, CommandError> {
+    db.list_export_profiles(folder_id.as_deref())
+        .map_err(|e| e.into())
+}
+
+/// Add an export profile, or update it when `profile.id` is set. With
+/// `interval_minutes`, the scheduler runs it that often.
+#[tauri::command]
+pub async fn save_export_profile(
+    db: State<'_, Database>,
+    profile: ExportProfileInput,
+) -> Result<ExportProfile, CommandError> {
+    export_profiles::save_profile(&db, &profile).map_err(|e| e.into())
+}
+
+#[tauri::command]
+pub async fn delete_export_profile(
+    db: State<'_, Database>,
+    id: String,
+) -> Result<bool, CommandError> {
+    db.delete_export_profile(&id).map_err(|e| e.into())
+}
+
+/// Run an export profile now, writing its folder's notes to its destination
+#[tauri::command]
+pub async fn run_export_profile(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+) -> Result<ExportProfileReport, CommandError> {
+    let app_data_dir = app_data_dir(&app_handle)?;
+    export_profiles::run_profile(&db, &locks, &app_data_dir, &id).map_err(|e| e.into())
+}
+
+/// Import a note from another app (e.g. Evernote, Notion). `source` and
+/// `external_id` identify the original item; re-importing it updates the
+/// existing note instead of creating a duplicate.
+#[tauri::command]
+pub async fn import_note(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    source: String,
+    external_id: String,
+    note: NoteInput,
+) -> Result<Note, CommandError> {
+    let existing = db.find_external_ref(&source, &external_id)?;
+    let _lock = locks.lock(existing, "import")?;
+    db.save_imported_note(&source, &external_id, note)
+        .map_err(|e| e.into())
+}
+
+/// Import a Markdown file as a note, updating it if the file was imported before
+#[tauri::command]
+pub async fn import_markdown_file(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    path: String,
+    folder_id: Option<String>,
+) -> Result<Note, CommandError> {
+    export::import_markdown(&db, &locks, Path::new(&path), folder_id).map_err(|e| e.into())
+}
+
+/// Import a CSV file as one table note, or one note per row with `mode: "rows"`.
+/// The encoding and delimiter are detected unless `options.delimiter` is set.
+#[tauri::command]
+pub async fn import_csv_file(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    path: String,
+    folder_id: Option<String>,
+    options: Option<CsvImportOptions>,
+) -> Result<DataImportReport, CommandError> {
+    data_import::import_csv(
+        &db,
+        &locks,
+        Path::new(&path),
+        folder_id,
+        &options.unwrap_or_default(),
+    )
+    .map_err(|e| e.into())
+}
+
+/// Import the events of an ICS calendar as one dated note each
+#[tauri::command]
+pub async fn import_ics_file(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    path: String,
+    folder_id: Option<String>,
+) -> Result<DataImportReport, CommandError> {
+    data_import::import_ics(&db, &locks, Path::new(&path), folder_id).map_err(|e| e.into())
+}
+
+/// Recognise pasted text as a table (copied spreadsheet cells, CSV) so the
+/// editor can insert it as one. Returns `None` for ordinary text.
+#[tauri::command]
+pub async fn parse_tabular_clipboard(text: String) -> Result<Option<ClipboardTable>, CommandError> {
+    Ok(data_import::parse_clipboard_table(&text))
+}
+
+// ============================================================================
+// Code Snippet Commands
+// ============================================================================
+
+/// Search code blocks across notes, optionally filtered by language
+#[tauri::command]
+pub async fn search_code(
+    db: State<'_, Database>,
+    query: String,
+    language: Option<String>,
+) -> Result<Vec<CodeSnippet>, CommandError> {
+    db.search_code(&query, language.as_deref())
+        .map_err(|e| e.into())
+}
+
+/// Write code snippets to files in a directory, one file per block.
+/// Optionally limited to a single note and/or language. Returns the written paths.
+#[tauri::command]
+pub async fn export_code_snippets(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    directory: String,
+    note_id: Option<String>,
+    language: Option<String>,
+) -> Result<Vec<String>, CommandError> {
+    let snippets = db.get_code_snippets(note_id.as_deref(), language.as_deref())?;
+    let _lock = locks.lock(snippets.iter().map(|s| s.note_id.as_str()), "export")?;
+    let written = snippets::export_snippets(&snippets, Path::new(&directory))?;
+    Ok(written
+        .into_iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect())
+}
+
+// ============================================================================
+// Highlight Commands
+// ============================================================================
+
+/// Highlighted passages across notes, optionally filtered by note, folder,
+/// color or text
+#[tauri::command]
+pub async fn list_highlights(
+    db: State<'_, Database>,
+    filter: Option<HighlightFilter>,
+) -> Result<Vec<Highlight>, CommandError> {
+    db.list_highlights(&filter.unwrap_or_default())
+        .map_err(|e| e.into())
+}
+
+/// Write highlights to a Markdown file at `path`, grouped by note with links
+/// back to each note. `scope` takes the same filters as `list_highlights`;
+/// omit it to export every highlight. Returns the number exported.
+#[tauri::command]
+pub async fn export_highlights(
+    db: State<'_, Database>,
+    scope: Option<HighlightFilter>,
+    path: String,
+) -> Result<usize, CommandError> {
+    highlights::export_highlights(&db, &scope.unwrap_or_default(), Path::new(&path))
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Keyword Commands
+// ============================================================================
+
+/// The most frequent terms across the vault (stop words removed, inflections
+/// merged), for a tag cloud or topics view. Returns 50 terms by default.
+#[tauri::command]
+pub async fn get_vault_keywords(
+    db: State<'_, Database>,
+    limit: Option<usize>,
+) -> Result<Vec<VaultKeyword>, CommandError> {
+    db.get_vault_keywords(limit).map_err(|e| e.into())
+}
+
+/// Terms that characterize a note compared to the rest of the vault, as tag
+/// suggestions
+#[tauri::command]
+pub async fn suggest_note_keywords(
+    db: State<'_, Database>,
+    note_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<KeywordSuggestion>, CommandError> {
+    db.suggest_note_keywords(&note_id, limit)
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Sync Profile Commands
+// ============================================================================
+
+/// Configured sync servers
+#[tauri::command]
+pub async fn list_sync_profiles(db: State<'_, Database>) -> Result<Vec<SyncProfile>, CommandError> {
+    db.list_sync_profiles().map_err(|e| e.into())
+}
+
+/// The profile being synced with, its token and last pull time; `None` until
+/// a profile is added
+#[tauri::command]
+pub async fn get_active_sync_profile(
+    db: State<'_, Database>,
+) -> Result<Option<ActiveSyncProfile>, CommandError> {
+    sync_profiles::active_profile(&db).map_err(|e| e.into())
+}
+
+/// Add a profile (omit `id`) or update one. A `token` is stored in the
+/// system keyring.
+#[tauri::command]
+pub async fn save_sync_profile(
+    db: State<'_, Database>,
+    profile: SyncProfileInput,
+    token: Option<String>,
+) -> Result<SyncProfile, CommandError> {
+    let saved = sync_profiles::save_profile(&db, profile)?;
+    if let Some(token) = token {
+        sync_profiles::set_token(&db, &saved.id, Some(&token))?;
+    }
+    Ok(saved)
+}
+
+/// Store a profile's token after signing in, or forget it when `token` is omitted
+#[tauri::command]
+pub async fn set_sync_profile_token(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    hub: State<'_, WsHub>,
+    id: String,
+    token: Option<String>,
+) -> Result<(), CommandError> {
+    sync_profiles::set_token(&db, &id, token.as_deref())?;
+    hub.reconnect_if_running(&app_handle, &db)
+        .map_err(|e| e.into())
+}
+
+/// Remove a profile other than the active one
+#[tauri::command]
+pub async fn delete_sync_profile(db: State<'_, Database>, id: String) -> Result<(), CommandError> {
+    sync_profiles::delete_profile(&db, &id).map_err(|e| e.into())
+}
+
+/// Sync with another profile's server from now on. Pending changes and the
+/// last pull time are tracked per profile.
+#[tauri::command]
+pub async fn switch_sync_profile(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    hub: State<'_, WsHub>,
+    id: String,
+) -> Result<ActiveSyncProfile, CommandError> {
+    let active = sync_profiles::switch_profile(&db, &id)?;
+    hub.reconnect_if_running(&app_handle, &db)?;
+    Ok(active)
+}
+
+/// Record the server time of a successful pull for the active profile
+#[tauri::command]
+pub async fn record_sync_pull(
+    db: State<'_, Database>,
+    server_time: Timestamp,
+) -> Result<(), CommandError> {
+    sync_profiles::record_pull(&db, &server_time).map_err(|e| e.into())
+}
+
+// ============================================================================
+// Per-Note Sync Commands
+// ============================================================================
+
+/// Leave a note out of syncing until it is resumed
+#[tauri::command]
+pub async fn pause_sync_for_note(db: State<'_, Database>, id: String) -> Result<(), CommandError> {
+    db.pause_note_sync(&id).map_err(|e| e.into())
+}
+
+/// Sync a paused note again; its current version is pushed on the next sync.
+/// Returns false if the note wasn't paused.
+#[tauri::command]
+pub async fn resume_sync_for_note(
+    db: State<'_, Database>,
+    id: String,
+) -> Result<bool, CommandError> {
+    db.resume_note_sync(&id).map_err(|e| e.into())
+}
+
+/// Notes whose sync is paused, most recently paused first
+#[tauri::command]
+pub async fn list_sync_paused_notes(
+    db: State<'_, Database>,
+) -> Result<Vec<PausedNote>, CommandError> {
+    db.list_paused_notes().map_err(|e| e.into())
+}
+
+/// Save the local version of a note on the active sync server, replacing the
+/// server's copy
+#[tauri::command]
+pub async fn force_push_note(db: State<'_, Database>, id: String) -> Result<Note, CommandError> {
+    note_sync::force_push(&db, &id).await.map_err(|e| e.into())
+}
+
+/// Replace the local note with the active sync server's version
+#[tauri::command]
+pub async fn force_pull_note(
+    db: State<'_, Database>,
+    locks: State<'_, NoteLocks>,
+    id: String,
+) -> Result<Note, CommandError> {
+    note_sync::force_pull(&db, &locks, &id)
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Shared WebSocket Commands
+// ============================================================================
+
+/// Open the shared connection to the active sync server, replacing any
+/// existing one. Subscribing connects too; call this after signing in.
+#[tauri::command]
+pub async fn ws_connect(
+    app_handle: tauri::AppHandle,
+    db: State<'_, Database>,
+    hub: State<'_, WsHub>,
+) -> Result<WsStatus, CommandError> {
+    hub.connect(&app_handle, &db).map_err(|e| e.into())
+}
+
+/// Close the shared connection, keeping subscriptions for the next connect
+#[tauri::command]
+pub async fn ws_disconnect(
+    app_handle: tauri::AppHandle,
+    hub: State<'_, WsHub>,
+) -> Result<WsStatus, CommandError> {
+    hub.disconnect(&app_handle);
+    Ok(hub.status())
+}
+
+#[tauri::command]
+pub async fn ws_status(hub: State<'_, WsHub>) -> Result<WsStatus, CommandError> {
+    Ok(hub.status())
+}
+
+/// Receive a note's updates in the calling window as `app://ws-message`.
+/// Subscriptions are counted, so each subscribe needs its own unsubscribe.
+#[tauri::command]
+pub async fn ws_subscribe_note(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    db: State<'_, Database>,
+    hub: State<'_, WsHub>,
+    note_id: String,
+) -> Result<(), CommandError> {
+    hub.subscribe(&app_handle, &db, &note_id, window.label())
+        .map_err(|e| e.into())
+}
+
+/// Drop one of the calling window's subscriptions to a note. Returns false if
+/// it had none.
+#[tauri::command]
+pub async fn ws_unsubscribe_note(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    hub: State<'_, WsHub>,
+    note_id: String,
+) -> Result<bool, CommandError> {
+    Ok(hub.unsubscribe(&app_handle, &note_id, window.label()))
+}
+
+/// Send a note's CRDT update (base64) over the shared connection, batched
+/// with the note's other updates of the moment. Fails while disconnected;
+/// save through the REST endpoints instead.
+#[tauri::command]
+pub async fn ws_send_update(
+    hub: State<'_, WsHub>,
+    note_id: String,
+    payload: String,
+) -> Result<(), CommandError> {
+    hub.send_update(&note_id, &payload).map_err(|e| e.into())
+}
+
+/// Share the calling window's ephemeral canvas state (drag previews,
+/// selection boxes; any JSON the canvas understands) with the other
+/// participants of a canvas note it is subscribed to. Nothing is stored.
+#[tauri::command]
+pub async fn ws_send_canvas_presence(
+    app_handle: tauri::AppHandle,
+    window: tauri::Window,
+    hub: State<'_, WsHub>,
+    note_id: String,
+    payload: String,
+) -> Result<(), CommandError> {
+    hub.send_canvas_presence(&app_handle, &note_id, window.label(), &payload)
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Trash Commands
+// ============================================================================
+
+/// Permanently delete notes that have been in the trash for at least
+/// `older_than_days` (all of them by default). Deletions that haven't synced
+/// yet are kept, and so are notes the workspace's retention policy keeps;
+/// under a legal hold nothing is purged. With `archive_purged_notes` on, purged notes stay searchable.
+/// A large purge returns a `confirm_token` instead; call again with it as
+/// `confirm` to go ahead.
+#[tauri::command]
+pub async fn purge_trash(
+    db: State<'_, Database>,
+    older_than_days: Option<i64>,
+    confirm: Option<String>,
+) -> Result<PurgeReport, CommandError> {
+    trash::purge(&db, older_than_days.unwrap_or(0), confirm.as_deref()).map_err(|e| e.into())
+}
+
+/// Purged notes whose title or text contains `query`, most recently purged
+/// first; an empty query lists the archive
+#[tauri::command]
+pub async fn search_purged_notes(
+    db: State<'_, Database>,
+    query: String,
+) -> Result<Vec<ArchivedNote>, CommandError> {
+    db.search_archive(&query).map_err(|e| e.into())
+}
+
+/// Bring a purged note back from the archive as a new note
+#[tauri::command]
+pub async fn restore_purged_note(db: State<'_, Database>, id: i64) -> Result<Note, CommandError> {
+    trash::restore_archived(&db, id).map_err(|e| e.into())
+}
+
+/// The workspace retention policy purges follow, as last fetched
+#[tauri::command]
+pub async fn get_retention_policy(
+    db: State<'_, Database>,
+) -> Result<RetentionPolicy, CommandError> {
+    retention::cached_policy(&db).map_err(|e| e.into())
+}
+
+/// Fetch the retention policy from the sync server now
+#[tauri::command]
+pub async fn pull_retention_policy(
+    db: State<'_, Database>,
+) -> Result<RetentionPolicy, CommandError> {
+    retention::pull_retention_policy(&db)
+        .await
+        .map_err(|e| e.into())
+}
+
+// ============================================================================
+// Analytics Commands
+// ============================================================================
+
+/// Record that a note was opened (kept on this device only)
+#[tauri::command]
+pub async fn record_note_open(
+    db: State<'_, Database>,
+    note_id: String,
+) -> Result<(), CommandError> {
+    db.record_note_activity(&note_id, ActivityKind::Open, 0)
+        .map_err(|e| e.into())
+}
+
+/// Record an editing session of `duration_seconds` on a note
+#[tauri::command]
+pub async fn record_note_edit(
+    db: State<'_, Database>,
+    note_id: String,
+    duration_seconds: i64,
+) -> Result<(), CommandError> {
+    db.record_note_activity(&note_id, ActivityKind::Edit, duration_seconds)
+        .map_err(|e| e.into())
+}
+
+/// Opens, edits and editing time per day from `from` to `to` (`YYYY-MM-DD`,
+/// inclusive; the last year by default), for an activity heatmap
+#[tauri::command]
+pub async fn get_activity_heatmap(
+    db: State<'_, Database>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<ActivityDay>, CommandError> {
+    analytics::activity_heatmap(&db, from.as_deref(), to.as_deref()).map_err(|e| e.into())
+}
@@ -0,0 +1,97 @@
+//! Crash reports for the desktop app.
+//!
+//! Every panic is written to `crash-reports/` in the app data directory before
+//! the app goes down (see the `beck_crash` crate), so a crash in the middle of
+//! a sync leaves something to look at. `get_recent_crashes` lists them for the
+//! user to share. They only leave the device if the user sets a
+//! Sentry-compatible DSN in the `crash_report_dsn` setting; the scheduler then
+//! uploads reports that haven't been sent, including ones from before the
+//! setting was turned on.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use beck_crash::Dsn;
+
+use crate::database::Database;
+use crate::settings::CRASH_REPORT_DSN;
+
+/// Name and version sent with reports
+const APP: &str = "beck-desktop";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Set while an upload runs, so a slow service doesn't get the same reports
+/// twice
+static UPLOADING: AtomicBool = AtomicBool::new(false);
+
+pub fn crash_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("crash-reports")
+}
+
+pub fn install_panic_hook(app_data_dir: &Path) {
+    beck_crash::install_panic_hook(crash_dir(app_data_dir), APP, VERSION);
+}
+
+/// The DSN to upload to, if the user opted in
+pub fn upload_dsn(db: &Database) -> Result<Option<Dsn>, String> {
+    let dsn = db
+        .get_setting(CRASH_REPORT_DSN)
+        .map_err(|e| format!("Failed to read crash reporting setting: {}", e))?;
+    match dsn.filter(|dsn| !dsn.trim().is_empty()) {
+        Some(dsn) => Dsn::parse(&dsn).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Whether uploading is turned on and there are reports to send
+pub fn upload_due(db: &Database, app_data_dir: &Path) -> Result<Option<Dsn>, String> {
+    let Some(dsn) = upload_dsn(db)? else {
+        return Ok(None);
+    };
+    let pending = beck_crash::pending_uploads(&crash_dir(app_data_dir))
+        .map_err(|e| format!("Failed to read crash reports: {}", e))?;
+    Ok((!pending.is_empty() && !UPLOADING.load(Ordering::SeqCst)).then_some(dsn))
+}
+
+/// Send the reports that haven't been sent, oldest first, stopping at the
+/// first failure. Returns how many were sent.
+pub async fn upload_pending(app_data_dir: &Path, dsn: &Dsn) -> Result<usize, String> {
+    if UPLOADING.swap(true, Ordering::SeqCst) {
+        return Ok(0);
+    }
+    let result = send_pending(&crash_dir(app_data_dir), dsn).await;
+    UPLOADING.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn send_pending(dir: &Path, dsn: &Dsn) -> Result<usize, String> {
+    let reports = beck_crash::pending_uploads(dir)
+        .map_err(|e| format!("Failed to read crash reports: {}", e))?;
+    let client = reqwest::Client::builder()
+        .timeout(UPLOAD_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let auth = dsn.auth_header(&format!("{}/{}", APP, VERSION));
+
+    let mut sent = 0;
+    for report in reports {
+        let body = serde_json::to_vec(&beck_crash::crash_event(&report))
+            .map_err(|e| format!("Failed to encode crash report {}: {}", report.id, e))?;
+        client
+            .post(&dsn.store_url)
+            .header("X-Sentry-Auth", &auth)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to upload crash report {}: {}", report.id, e))?;
+        beck_crash::mark_uploaded(dir, &report)
+            .map_err(|e| format!("Failed to mark crash report {} uploaded: {}", report.id, e))?;
+        sent += 1;
+    }
+    Ok(sent)
+}
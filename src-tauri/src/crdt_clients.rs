@@ -0,0 +1,120 @@
+//! Stable Yjs client ids for this device's editors.
+//!
+//! Yjs tags everything a document client inserts with its client id, which is
+//! normally random per session. The sync server attributes note content to
+//! users by client id (`/api/notes/:id/contributors`), so editors here use an
+//! id derived from this device, the signed-in user and the window instead,
+//! and register it with the server. Windows get their own ids because two
+//! editors must never share one on the same note. Yjs picks a new id by itself
+//! if it sees its own in a remote update, so a clash costs attribution, not
+//! content.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::sync_profiles;
+
+/// Settings key holding this device's random id
+const DEVICE_ID_KEY: &str = "device_id";
+
+/// Timeout for registering a client id
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CrdtClientId {
+    pub client_id: u32,
+    /// Whether the sync server knows the id is the signed-in user's
+    pub registered: bool,
+}
+
+/// Body of `POST /api/crdt/clients`
+#[derive(Debug, Serialize)]
+struct ClientRegistration<'a> {
+    client_id: u32,
+    device: &'a str,
+}
+
+/// This device's id, created on first use
+fn device_id(db: &Database) -> Result<String, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if let Some(id) = db.get_setting(DEVICE_ID_KEY).map_err(db_err)? {
+        return Ok(id);
+    }
+    let id = Uuid::new_v4().to_string();
+    db.set_setting(DEVICE_ID_KEY, Some(&id)).map_err(db_err)?;
+    Ok(id)
+}
+
+/// The client id for a window's editors, for the active profile's user
+pub fn client_id(db: &Database, window: &str) -> Result<u32, String> {
+    let device = device_id(db)?;
+    let user = sync_profiles::active_profile(db)?
+        .and_then(|active| active.profile.username)
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    for part in [device.as_str(), user.as_str(), window] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    let hash = hasher.finalize();
+    Ok(u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]))
+}
+
+/// A window's client id, registered with the active sync server when signed
+/// in. Registration failing (offline, or the id taken by another user) is
+/// reported in `registered` rather than as an error, so editing still works.
+pub async fn register(db: &Database, window: &str) -> Result<CrdtClientId, String> {
+    let client_id = client_id(db, window)?;
+    let server = match sync_profiles::active_server(db) {
+        Ok((base, active)) => active.token.map(|token| (base, token)),
+        Err(_) => None,
+    };
+    let Some((base, token)) = server else {
+        return Ok(CrdtClientId {
+            client_id,
+            registered: false,
+        });
+    };
+    let device = device_id(db)?;
+    let url = format!("{}/api/crdt/clients", base);
+    let registered = match post_registration(&url, &token, client_id, &device).await {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("[crdt] registering client id {} failed: {}", client_id, err);
+            false
+        }
+    };
+    Ok(CrdtClientId {
+        client_id,
+        registered,
+    })
+}
+
+async fn post_registration(
+    url: &str,
+    token: &str,
+    client_id: u32,
+    device: &str,
+) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .timeout(REGISTER_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let body = serde_json::to_vec(&ClientRegistration { client_id, device })
+        .map_err(|e| format!("Failed to encode registration: {}", e))?;
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .bearer_auth(token)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server returned {} for {}", response.status(), url));
+    }
+    Ok(())
+}
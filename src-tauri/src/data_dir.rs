@@ -0,0 +1,130 @@
+//! Where the vault lives on disk.
+//!
+//! The data directory is normally the platform's app data directory. It can be
+//! moved by starting with `--data-dir <path>` or by choosing another location
+//! from the startup error screen, which is remembered in a small file in the
+//! app config directory. If the directory can't be created or written (e.g.
+//! permissions or a full disk), the app starts without a database and manages
+//! a [`StartupError`] the frontend shows instead of crashing at launch.
+
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+/// Command-line flag choosing the data directory for this run
+pub const DATA_DIR_FLAG: &str = "--data-dir";
+
+/// File in the app config directory holding a user-chosen data directory
+const LOCATION_FILE: &str = "data-location";
+
+/// File written and removed to check the directory is writable
+const PROBE_FILE: &str = ".write-test";
+
+/// The data directory the app opened, kept as managed state
+pub struct DataDir(pub PathBuf);
+
+/// Why the app could not start, kept as managed state
+#[derive(Debug, Serialize, Clone)]
+pub struct StartupError {
+    /// Always "data_dir_unavailable" for now
+    pub kind: &'static str,
+    /// Directory that could not be used, if one was resolved
+    pub path: Option<String>,
+    pub message: String,
+}
+
+impl StartupError {
+    fn data_dir(path: Option<&Path>, message: String) -> Self {
+        StartupError {
+            kind: "data_dir_unavailable",
+            path: path.map(|p| p.to_string_lossy().to_string()),
+            message,
+        }
+    }
+}
+
+/// `--data-dir <path>` or `--data-dir=<path>` from the command line
+fn from_args() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == DATA_DIR_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg
+            .strip_prefix(DATA_DIR_FLAG)
+            .and_then(|a| a.strip_prefix('='))
+        {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn location_file(app_handle: &AppHandle) -> Option<PathBuf> {
+    app_handle
+        .path()
+        .app_config_dir()
+        .ok()
+        .map(|dir| dir.join(LOCATION_FILE))
+}
+
+/// Data directory saved with `choose`, if any
+fn saved_location(app_handle: &AppHandle) -> Option<PathBuf> {
+    let contents = fs::read_to_string(location_file(app_handle)?).ok()?;
+    let path = contents.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Create `dir` if needed and make sure files can be written to it
+fn prepare(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create data directory {}: {}", dir.display(), e))?;
+    let probe = dir.join(PROBE_FILE);
+    fs::write(&probe, b"ok")
+        .map_err(|e| format!("Data directory {} is not writable: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Resolve and prepare the data directory: the command line wins over a saved
+/// location, which wins over the platform default. In guest mode it is only
+/// checked to exist, as nothing is written to it.
+pub fn open(app_handle: &AppHandle, guest: bool) -> Result<PathBuf, StartupError> {
+    let dir = match from_args().or_else(|| saved_location(app_handle)) {
+        Some(dir) => dir,
+        None => app_handle.path().app_data_dir().map_err(|e| {
+            StartupError::data_dir(None, format!("Failed to get app data directory: {}", e))
+        })?,
+    };
+    if guest {
+        if !dir.is_dir() {
+            let message = format!("Data directory {} does not exist", dir.display());
+            return Err(StartupError::data_dir(Some(&dir), message));
+        }
+    } else {
+        prepare(&dir).map_err(|message| StartupError::data_dir(Some(&dir), message))?;
+    }
+
+    // The asset protocol scope only covers the default location
+    if let Err(err) = app_handle
+        .asset_protocol_scope()
+        .allow_directory(&dir, true)
+    {
+        eprintln!("Failed to allow assets in {}: {}", dir.display(), err);
+    }
+    Ok(dir)
+}
+
+/// Use `dir` as the data directory from the next start on
+pub fn choose(app_handle: &AppHandle, dir: &Path) -> Result<(), String> {
+    prepare(dir)?;
+    let file = location_file(app_handle)
+        .ok_or_else(|| "Failed to get app config directory".to_string())?;
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create app config directory: {}", e))?;
+    }
+    fs::write(&file, dir.to_string_lossy().as_bytes())
+        .map_err(|e| format!("Failed to save data directory location: {}", e))
+}
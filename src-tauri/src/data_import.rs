@@ -0,0 +1,659 @@
+//! Import of CSV spreadsheets and ICS calendars into notes.
+//!
+//! A CSV file becomes either one note holding the whole sheet as a table, or
+//! one note per row with the row's columns listed as properties. The text
+//! encoding (UTF-8, UTF-16 with a byte order mark, or Windows-1252) and the
+//! delimiter (comma, semicolon, tab or pipe) are detected from the file.
+//!
+//! An ICS file becomes one note per event, titled with the event's date and
+//! summary. Like Markdown imports, every imported note is tracked in
+//! `external_refs`, so importing the same file again updates the notes it
+//! created instead of duplicating them.
+//!
+//! Pasted text goes through the same CSV parser: `parse_clipboard_table`
+//! recognises rows copied from a spreadsheet (tab-separated) or CSV text and
+//! returns them as a table for the editor to insert.
+
+use beck_markdown::html::escape;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::database::{Database, NoteInput};
+use crate::locks::NoteLocks;
+
+/// `external_refs` source for CSV imports
+const CSV_SOURCE: &str = "csv";
+
+/// `external_refs` source for ICS imports
+const ICS_SOURCE: &str = "ics";
+
+/// Delimiters tried when detecting a CSV dialect, in order of preference
+const DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+
+/// Lines looked at when detecting the delimiter
+const SNIFF_LINES: usize = 20;
+
+/// Longest clipboard text considered for a table, in bytes
+const MAX_CLIPBOARD_TABLE_LEN: usize = 1024 * 1024;
+
+/// Windows-1252 characters for bytes 0x80..=0x9F (the rest match Latin-1)
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvImportMode {
+    /// One note containing the sheet as a table
+    #[default]
+    Table,
+    /// One note per row, listing the columns as properties
+    Rows,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CsvImportOptions {
+    #[serde(default)]
+    pub mode: CsvImportMode,
+    /// Treat the first row as data rather than column names
+    #[serde(default)]
+    pub no_header: bool,
+    /// Column (by name, or by index when there is no header) used as the
+    /// title in rows mode; defaults to the first column
+    pub title_column: Option<String>,
+    /// Override delimiter detection
+    pub delimiter: Option<char>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportedNote {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DataImportReport {
+    pub notes: Vec<ImportedNote>,
+    pub created: usize,
+    pub updated: usize,
+    /// Rows without a title, or events without a summary or start date
+    pub skipped: usize,
+}
+
+/// Clipboard text recognised as a table
+#[derive(Debug, Serialize, Clone)]
+pub struct ClipboardTable {
+    /// The delimiter the text was split on
+    pub delimiter: char,
+    /// Whether the first row looks like column names
+    pub has_header: bool,
+    pub columns: usize,
+    /// Every row padded to `columns` cells, the header row first if any
+    pub rows: Vec<Vec<String>>,
+    /// The table in the editor's format, with a header row if `has_header`
+    pub html: String,
+}
+
+/// A note to be written, keyed by its external id
+struct PendingNote {
+    external_id: String,
+    title: String,
+    content: String,
+}
+
+/// Decode file bytes, honouring a byte order mark and falling back to
+/// Windows-1252 for text that isn't valid UTF-8
+fn decode_text(bytes: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(rest).into_owned();
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return utf16(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return utf16(rest, u16::from_be_bytes);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                _ => b as char,
+            })
+            .collect(),
+    }
+}
+
+/// Parse CSV text into records (RFC 4180: quoted fields may contain the
+/// delimiter, newlines and doubled quotes)
+fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    // Blank lines carry no data
+    records.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    records
+}
+
+/// Pick the delimiter that splits the first lines into the most consistent
+/// number of columns
+fn detect_delimiter(text: &str) -> char {
+    let sample: String = text
+        .lines()
+        .take(SNIFF_LINES)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut best = (',', 0usize);
+    for delimiter in DELIMITERS {
+        let records = parse_csv(&sample, delimiter);
+        let Some(first) = records.first() else {
+            continue;
+        };
+        let columns = first.len();
+        if columns < 2 {
+            continue;
+        }
+        // Rows matching the first row's width, weighted by the width
+        let score = records.iter().filter(|r| r.len() == columns).count() * columns;
+        if score > best.1 {
+            best = (delimiter, score);
+        }
+    }
+    best.0
+}
+
+/// Whether a cell holds a number (thousands separators, currency and percent
+/// signs allowed)
+fn is_numeric(cell: &str) -> bool {
+    let cleaned: String = cell
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ',' | ' ' | '\u{a0}' | '$' | '€' | '£' | '%'))
+        .collect();
+    !cleaned.is_empty() && cleaned.parse::<f64>().is_ok()
+}
+
+/// Guess whether the first row names the columns: every cell is filled in,
+/// none is a number and no two are the same
+fn looks_like_header(rows: &[Vec<String>]) -> bool {
+    let Some(first) = rows.first() else {
+        return false;
+    };
+    let mut seen = std::collections::HashSet::new();
+    rows.len() > 1
+        && first
+            .iter()
+            .all(|c| !c.trim().is_empty() && !is_numeric(c) && seen.insert(c.trim().to_lowercase()))
+}
+
+/// Recognise pasted text as a table. Tab-separated text (what spreadsheets
+/// copy) is a table even as a single row or with ragged rows; comma,
+/// semicolon or pipe separated text needs at least two rows of the same
+/// width, so ordinary prose with commas stays text. Returns `None` for text
+/// that isn't a table.
+pub fn parse_clipboard_table(text: &str) -> Option<ClipboardTable> {
+    if text.len() > MAX_CLIPBOARD_TABLE_LEN {
+        return None;
+    }
+    let text = text.trim_start_matches('\u{feff}');
+    let text = text.trim_end_matches(['\r', '\n']);
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    let delimiter = if text.lines().next()?.contains('\t') {
+        '\t'
+    } else {
+        detect_delimiter(text)
+    };
+    // Spreadsheets don't quote cells that merely start with a quote mark, so
+    // unbalanced quotes in tab-separated text mean it isn't quoted at all
+    let mut rows = if delimiter == '\t' && text.matches('"').count() % 2 == 1 {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.split('\t').map(str::to_string).collect())
+            .collect()
+    } else {
+        parse_csv(text, delimiter)
+    };
+
+    let columns = rows.iter().map(|r| r.len()).max()?;
+    if columns < 2 {
+        return None;
+    }
+    if delimiter != '\t' && (rows.len() < 2 || rows.iter().any(|r| r.len() != columns)) {
+        return None;
+    }
+
+    for row in &mut rows {
+        for cell in row.iter_mut() {
+            *cell = cell.trim().replace("\r\n", "\n");
+        }
+        row.resize(columns, String::new());
+    }
+    let has_header = looks_like_header(&rows);
+    let html = if has_header {
+        render_table(Some(&rows[0]), &rows[1..])
+    } else {
+        render_table(None, &rows)
+    };
+    Some(ClipboardTable {
+        delimiter,
+        has_header,
+        columns,
+        rows,
+        html,
+    })
+}
+
+fn table_cell(tag: &str, text: &str) -> String {
+    format!("<{}><p>{}</p></{}>", tag, escape(text.trim()), tag)
+}
+
+/// A table in the editor's format; the first row is the header row
+fn render_table(header: Option<&[String]>, rows: &[Vec<String>]) -> String {
+    let width = rows
+        .iter()
+        .map(|r| r.len())
+        .chain(header.map(|h| h.len()))
+        .max()
+        .unwrap_or(0);
+    let render_row = |row: &[String], tag: &str| {
+        let cells: String = (0..width)
+            .map(|i| table_cell(tag, row.get(i).map_or("", |s| s.as_str())))
+            .collect();
+        format!("<tr>{}</tr>", cells)
+    };
+    let mut html = String::from("<table><tbody>");
+    if let Some(header) = header {
+        html.push_str(&render_row(header, "th"));
+    }
+    for row in rows {
+        html.push_str(&render_row(row, "td"));
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+fn csv_notes(
+    path: &Path,
+    text: &str,
+    options: &CsvImportOptions,
+    skipped: &mut usize,
+) -> Result<Vec<PendingNote>, String> {
+    let delimiter = options.delimiter.unwrap_or_else(|| detect_delimiter(text));
+    let mut records = parse_csv(text, delimiter);
+    if records.is_empty() {
+        return Err("The CSV file has no rows".to_string());
+    }
+    let header = if options.no_header {
+        None
+    } else {
+        Some(records.remove(0))
+    };
+    let file_id = source_id(path);
+    let file_title = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    if options.mode == CsvImportMode::Table {
+        return Ok(vec![PendingNote {
+            external_id: file_id,
+            title: file_title,
+            content: render_table(header.as_deref(), &records),
+        }]);
+    }
+
+    let names: Vec<String> = match &header {
+        Some(header) => header.iter().map(|h| h.trim().to_string()).collect(),
+        None => Vec::new(),
+    };
+    let column_name = |i: usize| {
+        names
+            .get(i)
+            .filter(|n| !n.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("Column {}", i + 1))
+    };
+    let title_index = match &options.title_column {
+        None => 0,
+        Some(column) => names
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(column.trim()))
+            .or_else(|| column.trim().parse().ok())
+            .ok_or_else(|| format!("No column named {}", column))?,
+    };
+
+    let mut notes = Vec::new();
+    for (row_number, row) in records.iter().enumerate() {
+        let title = row
+            .get(title_index)
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty());
+        let Some(title) = title else {
+            *skipped += 1;
+            continue;
+        };
+        let properties: Vec<Vec<String>> = row
+            .iter()
+            .enumerate()
+            .filter(|(i, value)| *i != title_index && !value.trim().is_empty())
+            .map(|(i, value)| vec![column_name(i), value.clone()])
+            .collect();
+        let content = if properties.is_empty() {
+            String::new()
+        } else {
+            render_table(
+                Some(&["Property".to_string(), "Value".to_string()]),
+                &properties,
+            )
+        };
+        notes.push(PendingNote {
+            // Rows are identified by position, so re-importing an edited sheet
+            // updates rows in place
+            external_id: format!("{}#row{}", file_id, row_number + 1),
+            title: title.to_string(),
+            content,
+        });
+    }
+    Ok(notes)
+}
+
+/// Undo ICS text escaping
+fn unescape_ics(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// A content line: name, parameters and value
+struct IcsProperty {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl IcsProperty {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Unfold continuation lines and split each line into a property
+fn parse_ics_lines(text: &str) -> Vec<IcsProperty> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            // The value starts at the first colon outside a quoted parameter
+            let mut in_quotes = false;
+            let split = line.char_indices().find_map(|(i, c)| match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    None
+                }
+                ':' if !in_quotes => Some(i),
+                _ => None,
+            })?;
+            let (head, value) = (&line[..split], &line[split + 1..]);
+            let mut parts = head.split(';');
+            let name = parts.next()?.trim().to_ascii_uppercase();
+            let params = parts
+                .filter_map(|p| p.split_once('='))
+                .map(|(k, v)| (k.trim().to_string(), v.trim_matches('"').to_string()))
+                .collect();
+            Some(IcsProperty {
+                name,
+                params,
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// (sortable date, display text) of an ICS date or date-time
+fn ics_date(property: &IcsProperty) -> Option<(String, String)> {
+    let value = property.value.trim();
+    let date = chrono::NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()?;
+    let day = date.format("%Y-%m-%d").to_string();
+    let Some(time) = value.get(9..15) else {
+        return Some((day.clone(), day));
+    };
+    let time = chrono::NaiveTime::parse_from_str(time, "%H%M%S").ok()?;
+    let zone = if value.ends_with('Z') {
+        " UTC".to_string()
+    } else {
+        property
+            .param("TZID")
+            .map(|tz| format!(" ({})", tz))
+            .unwrap_or_default()
+    };
+    Some((
+        day.clone(),
+        format!("{} {}{}", day, time.format("%H:%M"), zone),
+    ))
+}
+
+fn ics_notes(path: &Path, text: &str, skipped: &mut usize) -> Result<Vec<PendingNote>, String> {
+    let properties = parse_ics_lines(text);
+    if !properties
+        .iter()
+        .any(|p| p.name == "BEGIN" && p.value.trim().eq_ignore_ascii_case("VCALENDAR"))
+    {
+        return Err("Not an iCalendar file".to_string());
+    }
+
+    let file_id = source_id(path);
+    let mut notes = Vec::new();
+    let mut event: Option<Vec<&IcsProperty>> = None;
+    let mut depth = 0;
+    for (index, property) in properties.iter().enumerate() {
+        let component = property.value.trim().to_ascii_uppercase();
+        match (property.name.as_str(), event.as_mut()) {
+            ("BEGIN", None) if component == "VEVENT" => event = Some(Vec::new()),
+            // Alarms and other nested components belong to the event, not to us
+            ("BEGIN", Some(_)) => depth += 1,
+            ("END", Some(_)) if depth > 0 => depth -= 1,
+            ("END", Some(props)) if component == "VEVENT" => {
+                let find = |name: &str| props.iter().copied().find(|p| p.name == name);
+                let summary = find("SUMMARY")
+                    .map(|p| unescape_ics(&p.value).trim().to_string())
+                    .filter(|s| !s.is_empty());
+                let start = find("DTSTART").and_then(ics_date);
+                let (Some(summary), Some((day, start))) = (summary, start) else {
+                    *skipped += 1;
+                    event = None;
+                    continue;
+                };
+
+                let mut content = format!("<p><strong>When:</strong> {}", escape(&start));
+                if let Some((_, end)) = find("DTEND").and_then(ics_date) {
+                    content.push_str(&format!(" – {}", escape(&end)));
+                }
+                content.push_str("</p>");
+                if let Some(location) = find("LOCATION").map(|p| unescape_ics(&p.value)) {
+                    if !location.trim().is_empty() {
+                        content.push_str(&format!(
+                            "<p><strong>Where:</strong> {}</p>",
+                            escape(location.trim())
+                        ));
+                    }
+                }
+                if let Some(description) = find("DESCRIPTION").map(|p| unescape_ics(&p.value)) {
+                    for paragraph in description.split('\n').filter(|l| !l.trim().is_empty()) {
+                        content.push_str(&format!("<p>{}</p>", escape(paragraph.trim())));
+                    }
+                }
+
+                // A recurring event's exceptions share its UID
+                let uid = find("UID")
+                    .map(|p| p.value.trim().to_string())
+                    .unwrap_or_else(|| format!("{}#event{}", file_id, index));
+                let external_id = match find("RECURRENCE-ID") {
+                    Some(recurrence) => format!("{}#{}", uid, recurrence.value.trim()),
+                    None => uid,
+                };
+                notes.push(PendingNote {
+                    external_id,
+                    title: format!("{} {}", day, summary),
+                    content,
+                });
+                event = None;
+            }
+            (_, Some(props)) if depth == 0 => props.push(property),
+            _ => {}
+        }
+    }
+    Ok(notes)
+}
+
+/// Stable id of an imported file
+fn source_id(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Save pending notes, locking the ones that already exist while they are
+/// rewritten
+fn save_notes(
+    db: &Database,
+    locks: &NoteLocks,
+    source: &str,
+    notes: Vec<PendingNote>,
+    folder_id: Option<String>,
+    skipped: usize,
+) -> Result<DataImportReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let mut existing = Vec::new();
+    for note in &notes {
+        if let Some(id) = db
+            .find_external_ref(source, &note.external_id)
+            .map_err(db_err)?
+        {
+            existing.push(id);
+        }
+    }
+    let _lock = locks.lock(existing.iter().map(String::as_str), "import")?;
+
+    let mut report = DataImportReport {
+        skipped,
+        ..Default::default()
+    };
+    for note in notes {
+        let saved = db
+            .save_imported_note(
+                source,
+                &note.external_id,
+                NoteInput {
+                    id: None,
+                    title: note.title,
+                    content: note.content,
+                    folder_id: folder_id.clone(),
+                    updated_at: None,
+                    is_deleted: false,
+                    is_canvas: false,
+                },
+            )
+            .map_err(db_err)?;
+        if existing.contains(&saved.id) {
+            report.updated += 1;
+        } else {
+            report.created += 1;
+        }
+        report.notes.push(ImportedNote {
+            id: saved.id,
+            title: saved.title,
+        });
+    }
+    Ok(report)
+}
+
+/// Import a CSV file as a table note or as one note per row
+pub fn import_csv(
+    db: &Database,
+    locks: &NoteLocks,
+    path: &Path,
+    folder_id: Option<String>,
+    options: &CsvImportOptions,
+) -> Result<DataImportReport, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read CSV file: {}", e))?;
+    let mut skipped = 0;
+    let notes = csv_notes(path, &decode_text(&bytes), options, &mut skipped)?;
+    save_notes(db, locks, CSV_SOURCE, notes, folder_id, skipped)
+}
+
+/// Import the events of an ICS calendar as dated notes
+pub fn import_ics(
+    db: &Database,
+    locks: &NoteLocks,
+    path: &Path,
+    folder_id: Option<String>,
+) -> Result<DataImportReport, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read calendar file: {}", e))?;
+    let mut skipped = 0;
+    let notes = ics_notes(path, &decode_text(&bytes), &mut skipped)?;
+    save_notes(db, locks, ICS_SOURCE, notes, folder_id, skipped)
+}
@@ -1,11 +1,37 @@
-use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result as SqliteResult};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use uuid::Uuid;
 
-fn now_rfc3339() -> String {
+use crate::analytics::ensure_analytics_schema;
+use crate::attachments::ensure_attachments_schema;
+use crate::boards::ensure_boards_schema;
+use crate::export_profiles::ensure_export_profiles_schema;
+use crate::external_refs::ensure_external_refs_schema;
+use crate::flashcards::{ensure_flashcards_schema, index_note_cards};
+use crate::highlights::{ensure_highlights_schema, index_note_highlights};
+use crate::incoming::{ensure_incoming_schema, queue_incoming_change, record_sync_base};
+use crate::keywords::{ensure_keywords_schema, index_note_terms};
+use crate::links::{ensure_links_schema, index_note_links, update_links_for_rename};
+use crate::mirror::ensure_mirror_schema;
+use crate::note_sync::{ensure_note_sync_schema, is_sync_paused, NOT_PAUSED};
+use crate::offline::{ensure_offline_schema, mark_note_present};
+use crate::oplog::{ensure_oplog_schema, record_op, OpEntity, OpKind, OPLOG_ENTITY_IDS};
+use crate::publish::ensure_publish_schema;
+use crate::relations::ensure_relations_schema;
+use crate::remote_cache::ensure_remote_cache_schema;
+use crate::review::ensure_review_schema;
+use crate::settings::ensure_settings_schema;
+use crate::snippets::{ensure_snippets_schema, index_note_snippets};
+use crate::task_integrations::ensure_task_integrations_schema;
+use crate::template_library::ensure_template_library_schema;
+use crate::timestamp::{normalize_timestamps, Timestamp};
+use crate::trash::ensure_trash_schema;
+use crate::unfurl::ensure_link_previews_schema;
+
+pub(crate) fn now_rfc3339() -> String {
     chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
 }
 
@@ -16,7 +42,7 @@ pub struct Note {
     pub title: String,
     pub content: String,
     pub folder_id: Option<String>,
-    pub updated_at: String,
+    pub updated_at: Timestamp,
     pub is_deleted: bool,
     pub is_canvas: bool,
 }
@@ -27,9 +53,11 @@ pub struct NoteSummary {
     pub id: String,
     pub title: String,
     pub folder_id: Option<String>,
-    pub updated_at: String,
+    pub updated_at: Timestamp,
     pub is_deleted: bool,
     pub is_canvas: bool,
+    /// Plain-text excerpt of the content for list views
+    pub preview: String,
 }
 
 /// Represents a folder in the database
@@ -38,8 +66,8 @@ pub struct Folder {
     pub id: String,
     pub name: String,
     pub parent_id: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
     pub is_deleted: bool,
 }
 
@@ -58,7 +86,7 @@ pub struct NoteInput {
     pub title: String,
     pub content: String,
     pub folder_id: Option<String>,
-    pub updated_at: Option<String>,
+    pub updated_at: Option<Timestamp>,
     pub is_deleted: bool,
     pub is_canvas: bool,
 }
@@ -69,7 +97,7 @@ pub struct CrdtState {
     pub note_id: String,
     pub ydoc_state: Vec<u8>,   // Full Yjs document state
     pub state_vector: Vec<u8>, // State vector for sync
-    pub updated_at: String,
+    pub updated_at: Timestamp,
 }
 
 /// Input for saving CRDT state
@@ -80,7 +108,31 @@ pub struct CrdtStateInput {
     pub state_vector: Vec<u8>,
 }
 
-fn note_row_to_note(row: &rusqlite::Row) -> SqliteResult<Note> {
+/// Database file name in the app data directory
+pub const DATABASE_FILE: &str = "notes.db";
+
+/// Maximum length of a note preview, in characters
+const PREVIEW_LENGTH: usize = 200;
+
+/// Build the list-view excerpt for note content: plain text, whitespace collapsed,
+/// cut at a word boundary
+pub(crate) fn make_preview(content: &str) -> String {
+    let text = beck_markdown::html_to_text(content)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.chars().count() <= PREVIEW_LENGTH {
+        return text;
+    }
+    let cut: String = text.chars().take(PREVIEW_LENGTH).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > PREVIEW_LENGTH / 2 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+pub(crate) fn note_row_to_note(row: &rusqlite::Row) -> SqliteResult<Note> {
     Ok(Note {
         id: row.get(0)?,
         title: row.get(1)?,
@@ -97,11 +149,14 @@ fn ensure_notes_schema(conn: &Connection) -> SqliteResult<()> {
     let mut stmt = conn.prepare("PRAGMA table_info(notes)")?;
     let mut rows = stmt.query([])?;
     let mut has_is_deleted = false;
+    let mut has_preview = false;
     while let Some(row) = rows.next()? {
         let col_name: String = row.get(1)?;
         if col_name == "is_deleted" {
             has_is_deleted = true;
-            break;
+        }
+        if col_name == "preview" {
+            has_preview = true;
         }
     }
 
@@ -116,6 +171,27 @@ fn ensure_notes_schema(conn: &Connection) -> SqliteResult<()> {
         )?;
     }
 
+    if !has_preview {
+        conn.execute(
+            "ALTER TABLE notes ADD COLUMN preview TEXT NOT NULL DEFAULT ''",
+            [],
+        )?;
+
+        // Backfill for existing rows.
+        let mut stmt = conn.prepare("SELECT id, content FROM notes")?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        for (id, content) in notes {
+            conn.execute(
+                "UPDATE notes SET preview = ?2 WHERE id = ?1",
+                params![id, make_preview(&content)],
+            )?;
+        }
+    }
+
     Ok(())
 }
 
@@ -188,6 +264,111 @@ fn ensure_crdt_schema(conn: &Connection) -> SqliteResult<()> {
     Ok(())
 }
 
+/// Refresh the derived indexes for a note after its content changes
+pub(crate) fn index_note_content(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+    is_deleted: bool,
+) -> SqliteResult<()> {
+    index_note_snippets(conn, note_id, content, is_deleted)?;
+    index_note_cards(conn, note_id, content, is_deleted)?;
+    index_note_links(conn, note_id, content, is_deleted)?;
+    index_note_highlights(conn, note_id, content, is_deleted)?;
+    index_note_terms(conn, note_id, content, is_deleted)?;
+    mark_note_present(conn, note_id)?;
+    Ok(())
+}
+
+/// Save a note (insert or update) on a connection the caller already holds,
+/// so the write can share a transaction with other changes. The row, its
+/// search index, its oplog entry and link updates are written together.
+pub(crate) fn save_note_in_tx(conn: &Connection, input: NoteInput) -> SqliteResult<Note> {
+    let updated_at = input.updated_at.unwrap_or_else(Timestamp::now);
+
+    let id = input.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let previous_title: Option<String> = conn
+        .query_row(
+            "SELECT title FROM notes WHERE id = ?1 AND is_deleted = 0",
+            params![&id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    conn.execute(
+        "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, preview)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title,
+            content = excluded.content,
+            folder_id = excluded.folder_id,
+            updated_at = excluded.updated_at,
+            is_deleted = excluded.is_deleted,
+            is_canvas = excluded.is_canvas,
+            preview = excluded.preview",
+        params![
+            &id,
+            &input.title,
+            &input.content,
+            &input.folder_id,
+            &updated_at,
+            input.is_deleted as i32,
+            input.is_canvas as i32,
+            make_preview(&input.content),
+        ],
+    )?;
+
+    index_note_content(conn, &id, &input.content, input.is_deleted)?;
+    let op = if input.is_deleted {
+        OpKind::Delete
+    } else {
+        OpKind::Upsert
+    };
+    record_op(conn, OpEntity::Note, &id, op)?;
+
+    // Keep link text in referring notes in step with a renamed note
+    if let Some(previous_title) = previous_title {
+        if previous_title != input.title && !input.is_deleted {
+            update_links_for_rename(conn, &id, &previous_title, &input.title)?;
+        }
+    }
+
+    Ok(Note {
+        id,
+        title: input.title,
+        content: input.content,
+        folder_id: input.folder_id,
+        updated_at,
+        is_deleted: input.is_deleted,
+        is_canvas: input.is_canvas,
+    })
+}
+
+/// Ids of the live notes in the folder subtree, for `in_folder_subtree`
+const FOLDER_NOTES: &str =
+    "SELECT id FROM notes WHERE is_deleted = 0 AND folder_id IN (SELECT id FROM descendants)";
+
+/// Run `select` over the subtree of `folder_id`, which it sees as the
+/// `descendants` table of folder ids (the folder itself included)
+fn in_folder_subtree(
+    conn: &Connection,
+    folder_id: &str,
+    select: &str,
+) -> SqliteResult<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "WITH RECURSIVE descendants(id) AS (
+            SELECT id FROM folders WHERE id = ?1
+            UNION ALL
+            SELECT f.id FROM folders f
+            JOIN descendants d ON f.parent_id = d.id
+        )
+        {}",
+        select
+    ))?;
+    let rows = stmt.query_map(params![folder_id], |row| row.get(0))?;
+    rows.collect()
+}
+
 /// Database wrapper for thread-safe access
 pub struct Database {
     pub conn: Mutex<Connection>,
@@ -197,10 +378,15 @@ impl Database {
     /// Initialize the database connection and create tables
     pub fn new(app_data_dir: &PathBuf) -> SqliteResult<Self> {
         // Ensure the app data directory exists
-        fs::create_dir_all(app_data_dir).expect("Failed to create app data directory");
+        fs::create_dir_all(app_data_dir).map_err(|e| {
+            rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+                Some(format!("Failed to create app data directory: {}", e)),
+            )
+        })?;
 
         // Create the database file path
-        let db_path = app_data_dir.join("notes.db");
+        let db_path = app_data_dir.join(DATABASE_FILE);
 
         // Open or create the database
         let conn = Connection::open(&db_path)?;
@@ -244,6 +430,31 @@ impl Database {
         ensure_notes_schema(&conn)?;
         ensure_folders_schema(&conn)?;
         ensure_crdt_schema(&conn)?;
+        ensure_remote_cache_schema(&conn)?;
+        ensure_link_previews_schema(&conn)?;
+        ensure_snippets_schema(&conn)?;
+        ensure_attachments_schema(&conn)?;
+        ensure_settings_schema(&conn)?;
+        ensure_external_refs_schema(&conn)?;
+        ensure_review_schema(&conn)?;
+        ensure_flashcards_schema(&conn)?;
+        ensure_boards_schema(&conn)?;
+        ensure_relations_schema(&conn)?;
+        ensure_links_schema(&conn)?;
+        ensure_highlights_schema(&conn)?;
+        ensure_keywords_schema(&conn)?;
+        ensure_mirror_schema(&conn)?;
+        ensure_offline_schema(&conn)?;
+        ensure_analytics_schema(&conn)?;
+        ensure_incoming_schema(&conn)?;
+        ensure_publish_schema(&conn)?;
+        ensure_task_integrations_schema(&conn)?;
+        ensure_trash_schema(&conn)?;
+        ensure_oplog_schema(&conn)?;
+        ensure_note_sync_schema(&conn)?;
+        ensure_template_library_schema(&conn)?;
+        ensure_export_profiles_schema(&conn)?;
+        normalize_timestamps(&conn)?;
 
         // Create indexes for common queries
         conn.execute(
@@ -261,11 +472,27 @@ impl Database {
         })
     }
 
+    /// Open the existing database for guest mode without changing it: no
+    /// tables are created or migrated, and SQLite refuses every write
+    pub fn open_read_only(app_data_dir: &Path) -> SqliteResult<Self> {
+        let conn = Connection::open_with_flags(
+            app_data_dir.join(DATABASE_FILE),
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.execute_batch("PRAGMA query_only = ON;")?;
+
+        Ok(Database {
+            conn: Mutex::new(conn),
+        })
+    }
+
     /// Get all notes from the database
     pub fn get_all_notes(&self) -> SqliteResult<Vec<NoteSummary>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, title, folder_id, updated_at, is_deleted, is_canvas
+            "SELECT id, title, folder_id, updated_at, is_deleted, is_canvas, preview
              FROM notes
              WHERE is_deleted = 0
              ORDER BY updated_at DESC",
@@ -279,6 +506,7 @@ impl Database {
                 updated_at: row.get(3)?,
                 is_deleted: row.get::<_, i32>(4)? != 0,
                 is_canvas: row.get::<_, i32>(5)? != 0,
+                preview: row.get(6)?,
             })
         })?;
 
@@ -292,76 +520,60 @@ impl Database {
 
     /// Save a note (insert or update)
     pub fn save_note(&self, input: NoteInput) -> SqliteResult<Note> {
-        let conn = self.conn.lock().unwrap();
-        let now = now_rfc3339();
-        let updated_at = input.updated_at.unwrap_or_else(|| now.clone());
-
-        let id = input.id.unwrap_or_else(|| Uuid::new_v4().to_string());
-
-        conn.execute(
-            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-             ON CONFLICT(id) DO UPDATE SET
-                title = excluded.title,
-                content = excluded.content,
-                folder_id = excluded.folder_id,
-                updated_at = excluded.updated_at,
-                is_deleted = excluded.is_deleted,
-                is_canvas = excluded.is_canvas",
-            params![
-                &id,
-                &input.title,
-                &input.content,
-                &input.folder_id,
-                &updated_at,
-                input.is_deleted as i32,
-                input.is_canvas as i32,
-            ],
-        )?;
-
-        Ok(Note {
-            id,
-            title: input.title,
-            content: input.content,
-            folder_id: input.folder_id,
-            updated_at,
-            is_deleted: input.is_deleted,
-            is_canvas: input.is_canvas,
-        })
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let note = save_note_in_tx(&tx, input)?;
+        tx.commit()?;
+        Ok(note)
     }
 
     /// Delete a note by ID
     pub fn delete_note(&self, id: &str) -> SqliteResult<bool> {
-        let conn = self.conn.lock().unwrap();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
         let now = now_rfc3339();
-        let rows_affected = conn.execute(
+        let rows_affected = tx.execute(
             "UPDATE notes SET is_deleted = 1, updated_at = ?2 WHERE id = ?1",
             params![id, now],
         )?;
+        if rows_affected > 0 {
+            index_note_content(&tx, id, "", true)?;
+            record_op(&tx, OpEntity::Note, id, OpKind::Delete)?;
+        }
+        tx.commit()?;
         Ok(rows_affected > 0)
     }
 
     /// Move a note to a different folder
     pub fn move_note(&self, id: &str, folder_id: Option<&str>) -> SqliteResult<()> {
-        let conn = self.conn.lock().unwrap();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
         let now = now_rfc3339();
-        conn.execute(
+        let rows_affected = tx.execute(
             "UPDATE notes SET folder_id = ?2, updated_at = ?3 WHERE id = ?1",
             params![id, folder_id, now],
         )?;
-        Ok(())
+        if rows_affected > 0 {
+            record_op(&tx, OpEntity::Note, id, OpKind::Upsert)?;
+        }
+        tx.commit()
     }
 
     /// Get a single note by ID
     pub fn get_note_by_id(&self, id: &str) -> SqliteResult<Option<Note>> {
+        self.get_note(id, false)
+    }
+
+    /// Get a single note by ID. Deleted notes are only returned with `include_deleted`.
+    pub fn get_note(&self, id: &str, include_deleted: bool) -> SqliteResult<Option<Note>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas
              FROM notes
-             WHERE id = ?1 AND is_deleted = 0",
+             WHERE id = ?1 AND (?2 OR is_deleted = 0)",
         )?;
 
-        let mut rows = stmt.query(params![id])?;
+        let mut rows = stmt.query(params![id, include_deleted])?;
 
         if let Some(row) = rows.next()? {
             Ok(Some(note_row_to_note(row)?))
@@ -383,13 +595,14 @@ impl Database {
                 updated_at: row.get(3)?,
                 is_deleted: row.get::<_, i32>(4)? != 0,
                 is_canvas: row.get::<_, i32>(5)? != 0,
+                preview: row.get(6)?,
             })
         };
 
         match folder_id {
             Some(fid) => {
                 let mut stmt = conn.prepare(
-                    "SELECT id, title, folder_id, updated_at, is_deleted, is_canvas
+                    "SELECT id, title, folder_id, updated_at, is_deleted, is_canvas, preview
                      FROM notes
                      WHERE folder_id = ?1 AND is_deleted = 0
                      ORDER BY updated_at DESC",
@@ -401,7 +614,7 @@ impl Database {
             }
             None => {
                 let mut stmt = conn.prepare(
-                    "SELECT id, title, folder_id, updated_at, is_deleted, is_canvas
+                    "SELECT id, title, folder_id, updated_at, is_deleted, is_canvas, preview
                      FROM notes
                      WHERE folder_id IS NULL AND is_deleted = 0
                      ORDER BY updated_at DESC",
@@ -450,13 +663,40 @@ impl Database {
         Ok(notes)
     }
 
+    /// Notes journaled in the oplog between two op ids, including deleted notes
+    pub fn get_notes_in_oplog(&self, after_op: i64, up_to_op: i64) -> SqliteResult<Vec<Note>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas
+             FROM notes
+             WHERE id IN ({}) AND {}
+             ORDER BY updated_at ASC",
+            OPLOG_ENTITY_IDS, NOT_PAUSED
+        ))?;
+        let rows = stmt.query_map(
+            params![OpEntity::Note.as_str(), after_op, up_to_op],
+            note_row_to_note,
+        )?;
+        rows.collect()
+    }
+
     /// Apply notes from a remote sync. Uses last-writer-wins based on updated_at.
+    /// With review turned on, edits to existing notes are queued instead (see
+    /// `incoming`); CRDT updates go through `apply_crdt_update` unreviewed.
     pub fn apply_sync_notes(&self, notes: Vec<Note>) -> SqliteResult<()> {
+        let review = self.review_incoming_enabled()?;
         let mut conn = self.conn.lock().unwrap();
         let tx = conn.transaction()?;
 
         for note in notes {
-            let mut folder_id = note.folder_id;
+            if is_sync_paused(&tx, &note.id)? {
+                continue;
+            }
+            if review && queue_incoming_change(&tx, &note)? {
+                continue;
+            }
+
+            let mut folder_id = note.folder_id.clone();
             if let Some(ref fid) = folder_id {
                 let exists: Option<i32> = tx
                     .query_row(
@@ -470,16 +710,17 @@ impl Database {
                 }
             }
 
-            tx.execute(
-                "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            let applied = tx.execute(
+                "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, preview)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
                  ON CONFLICT(id) DO UPDATE SET
                     title = excluded.title,
                     content = excluded.content,
                     folder_id = excluded.folder_id,
                     updated_at = excluded.updated_at,
                     is_deleted = excluded.is_deleted,
-                    is_canvas = excluded.is_canvas
+                    is_canvas = excluded.is_canvas,
+                    preview = excluded.preview
                  WHERE excluded.updated_at > notes.updated_at",
                 params![
                     note.id,
@@ -489,8 +730,13 @@ impl Database {
                     note.updated_at,
                     note.is_deleted as i32,
                     note.is_canvas as i32,
+                    make_preview(&note.content),
                 ],
             )?;
+            if applied > 0 {
+                index_note_content(&tx, &note.id, &note.content, note.is_deleted)?;
+                record_sync_base(&tx, &note)?;
+            }
         }
 
         tx.commit()?;
@@ -568,6 +814,7 @@ impl Database {
                 is_deleted = 0",
             params![id, input.name, input.parent_id, now, now],
         )?;
+        record_op(&conn, OpEntity::Folder, &id, OpKind::Upsert)?;
 
         // Return the canonical row (preserves existing created_at).
         let mut stmt = conn.prepare(
@@ -591,38 +838,41 @@ impl Database {
 
     /// Delete a folder by ID
     pub fn delete_folder(&self, folder_id: &str) -> SqliteResult<()> {
-        let conn = self.conn.lock().unwrap();
-
-        // Soft-delete folder and descendants.
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
         let now = now_rfc3339();
-        conn.execute(
-            "WITH RECURSIVE descendants(id) AS (
-                SELECT id FROM folders WHERE id = ?1
-                UNION ALL
-                SELECT f.id FROM folders f
-                JOIN descendants d ON f.parent_id = d.id
-            )
-            UPDATE folders
-            SET is_deleted = 1, updated_at = ?2
-            WHERE id IN (SELECT id FROM descendants)",
-            params![folder_id, &now],
-        )?;
 
-        // ALSO Soft-delete all notes in these folders
-        conn.execute(
-            "WITH RECURSIVE descendants(id) AS (
-                SELECT id FROM folders WHERE id = ?1
-                UNION ALL
-                SELECT f.id FROM folders f
-                JOIN descendants d ON f.parent_id = d.id
-            )
-            UPDATE notes
-            SET is_deleted = 1, updated_at = ?2
-            WHERE folder_id IN (SELECT id FROM descendants)",
-            params![folder_id, &now],
+        // Soft-delete the live folders of the subtree and their notes, and
+        // journal exactly those
+        let folder_ids = in_folder_subtree(
+            &tx,
+            folder_id,
+            "SELECT id FROM folders WHERE is_deleted = 0 AND id IN (SELECT id FROM descendants)",
         )?;
+        for id in &folder_ids {
+            tx.execute(
+                "UPDATE folders SET is_deleted = 1, updated_at = ?2 WHERE id = ?1",
+                params![id, &now],
+            )?;
+            record_op(&tx, OpEntity::Folder, id, OpKind::Delete)?;
+        }
 
-        Ok(())
+        for id in in_folder_subtree(&tx, folder_id, FOLDER_NOTES)? {
+            tx.execute(
+                "UPDATE notes SET is_deleted = 1, updated_at = ?2 WHERE id = ?1",
+                params![&id, &now],
+            )?;
+            index_note_content(&tx, &id, "", true)?;
+            record_op(&tx, OpEntity::Note, &id, OpKind::Delete)?;
+        }
+
+        tx.commit()
+    }
+
+    /// Live notes in a folder and its subfolders, which deleting it trashes
+    pub fn folder_note_ids(&self, folder_id: &str) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        in_folder_subtree(&conn, folder_id, FOLDER_NOTES)
     }
 
     /// Get all child folders of a parent folder
@@ -731,6 +981,32 @@ impl Database {
         Ok(folders)
     }
 
+    /// Folders journaled in the oplog between two op ids, including deleted folders
+    pub fn get_folders_in_oplog(&self, after_op: i64, up_to_op: i64) -> SqliteResult<Vec<Folder>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, parent_id, created_at, updated_at, is_deleted
+             FROM folders
+             WHERE id IN ({})
+             ORDER BY updated_at ASC",
+            OPLOG_ENTITY_IDS
+        ))?;
+        let rows = stmt.query_map(
+            params![OpEntity::Folder.as_str(), after_op, up_to_op],
+            |row| {
+                Ok(Folder {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    parent_id: row.get(2)?,
+                    created_at: row.get(3)?,
+                    updated_at: row.get(4)?,
+                    is_deleted: row.get::<_, i32>(5)? != 0,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
     /// Apply folders pulled from a remote sync. Uses last-writer-wins based on updated_at.
     pub fn apply_sync_folders(&self, folders: Vec<Folder>) -> SqliteResult<()> {
         let mut conn = self.conn.lock().unwrap();
@@ -768,7 +1044,7 @@ impl Database {
     /// Save CRDT state for a note
     pub fn save_crdt_state(&self, input: CrdtStateInput) -> SqliteResult<CrdtState> {
         let conn = self.conn.lock().unwrap();
-        let now = now_rfc3339();
+        let now = Timestamp::now();
 
         conn.execute(
             "INSERT INTO crdt_states (note_id, ydoc_state, state_vector, updated_at)
@@ -973,7 +1249,7 @@ impl Database {
 pub mod assets {
     use base64::{engine::general_purpose::STANDARD, Engine};
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use uuid::Uuid;
 
     /// Result of saving an asset
@@ -985,12 +1261,12 @@ pub mod assets {
     }
 
     /// Get the assets directory path
-    pub fn get_assets_dir(app_data_dir: &PathBuf) -> PathBuf {
+    pub fn get_assets_dir(app_data_dir: &Path) -> PathBuf {
         app_data_dir.join(".assets")
     }
 
     /// Ensure the assets directory exists
-    pub fn ensure_assets_dir(app_data_dir: &PathBuf) -> std::io::Result<PathBuf> {
+    pub fn ensure_assets_dir(app_data_dir: &Path) -> std::io::Result<PathBuf> {
         let assets_dir = get_assets_dir(app_data_dir);
         fs::create_dir_all(&assets_dir)?;
         Ok(assets_dir)
@@ -999,7 +1275,7 @@ pub mod assets {
     /// Save a base64-encoded image to the .assets folder
     /// Returns the asset ID and a local URI for the frontend
     pub fn save_image_asset(
-        app_data_dir: &PathBuf,
+        app_data_dir: &Path,
         base64_data: &str,
         file_extension: &str,
     ) -> Result<AssetResult, String> {
@@ -1043,7 +1319,7 @@ pub mod assets {
 
     /// Save raw bytes as an image asset
     pub fn save_image_bytes(
-        app_data_dir: &PathBuf,
+        app_data_dir: &Path,
         data: &[u8],
         file_extension: &str,
     ) -> Result<AssetResult, String> {
@@ -1069,15 +1345,23 @@ pub mod assets {
     }
 
     /// Delete an asset by its ID
-    pub fn delete_asset(app_data_dir: &PathBuf, asset_id: &str) -> Result<bool, String> {
+    pub fn delete_asset(app_data_dir: &Path, asset_id: &str) -> Result<bool, String> {
         let assets_dir = get_assets_dir(app_data_dir);
 
-        // Find and delete the asset file (checking common extensions)
-        let extensions = ["png", "jpg", "jpeg", "gif", "webp", "svg", "bmp"];
+        if !assets_dir.exists() {
+            return Ok(false);
+        }
+
+        // Assets are stored as `<id>.<ext>`; match on the stem so attachments
+        // of any type are found, not just images
+        let entries = fs::read_dir(&assets_dir)
+            .map_err(|e| format!("Failed to read assets directory: {}", e))?;
 
-        for ext in &extensions {
-            let file_path = assets_dir.join(format!("{}.{}", asset_id, ext));
-            if file_path.exists() {
+        for entry in entries.flatten() {
+            let file_path = entry.path();
+            if file_path.is_file()
+                && file_path.file_stem().and_then(|s| s.to_str()) == Some(asset_id)
+            {
                 fs::remove_file(&file_path)
                     .map_err(|e| format!("Failed to delete asset: {}", e))?;
                 return Ok(true);
@@ -1088,7 +1372,7 @@ pub mod assets {
     }
 
     /// List all assets in the .assets folder
-    pub fn list_assets(app_data_dir: &PathBuf) -> Result<Vec<AssetResult>, String> {
+    pub fn list_assets(app_data_dir: &Path) -> Result<Vec<AssetResult>, String> {
         let assets_dir = get_assets_dir(app_data_dir);
 
         if !assets_dir.exists() {
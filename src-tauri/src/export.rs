@@ -0,0 +1,170 @@
+//! Note export to and import from Markdown/HTML files.
+//!
+//! Conversion is done by the shared `beck_markdown` crate so files written here
+//! match what the sync server's export endpoint produces.
+//!
+//! Exported and published notes are sanitized with the redaction rules kept in
+//! the `export_redaction_rules` setting, e.g. replacing email addresses or
+//! dropping paragraphs tagged `#private`. The vault mirror is a private copy
+//! and is not redacted.
+
+pub use beck_markdown::ExportFormat;
+use beck_markdown::{export_file_name, Backlink, RedactionRule, Redactor};
+use rusqlite::params;
+use std::fs;
+use std::path::Path;
+
+use crate::database::{Database, Note, NoteInput};
+use crate::locks::NoteLocks;
+use crate::settings;
+
+/// `external_refs` source for files imported with `import_markdown`
+const MARKDOWN_SOURCE: &str = "markdown";
+
+/// Render a note as a standalone document in the given format (see
+/// `beck_markdown::render_note`)
+pub fn render_note(note: &Note, format: ExportFormat) -> String {
+    beck_markdown::render_note(&note.title, &note.content, format, &[])
+}
+
+/// The configured redaction rules, in order
+pub fn redaction_rules(db: &Database) -> Result<Vec<RedactionRule>, String> {
+    match db
+        .get_setting(settings::EXPORT_REDACTION_RULES)
+        .map_err(|e| format!("Database error: {}", e))?
+    {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid redaction rules setting: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replace the redaction rules; an empty list turns redaction off. Invalid
+/// rules are refused.
+pub fn set_redaction_rules(db: &Database, rules: &[RedactionRule]) -> Result<(), String> {
+    Redactor::new(rules)?;
+    let json = serde_json::to_string(rules)
+        .map_err(|e| format!("Failed to encode redaction rules: {}", e))?;
+    db.set_setting(
+        settings::EXPORT_REDACTION_RULES,
+        (!rules.is_empty()).then_some(json.as_str()),
+    )
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// A copy of the note with the redaction rules applied to its title and content
+pub fn redacted(db: &Database, note: &Note) -> Result<Note, String> {
+    let redactor = Redactor::new(&redaction_rules(db)?)?;
+    let mut note = note.clone();
+    if !redactor.is_empty() {
+        note.title = redactor.redact_text(&note.title);
+        note.content = redactor.redact_html(&note.content);
+    }
+    Ok(note)
+}
+
+impl Database {
+    /// Live notes linking to `id`, by title, as links to their exports in
+    /// `format` next to this one
+    fn export_backlinks(&self, id: &str, format: ExportFormat) -> rusqlite::Result<Vec<Backlink>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT n.id, n.title FROM note_links l
+             JOIN notes n ON n.id = l.source_note_id
+             WHERE l.target_note_id = ?1 AND n.id != ?1 AND n.is_deleted = 0
+             ORDER BY n.title COLLATE NOCASE, n.id",
+        )?;
+        let rows = stmt.query_map(params![id], |row| row.get::<_, String>(1))?;
+        rows.map(|title| {
+            let title = title?;
+            let href = format!("{}.{}", export_file_name(&title), format.extension());
+            Ok(Backlink { title, href })
+        })
+        .collect()
+    }
+}
+
+/// Write a note to `path` in the given format, redacted. With `backlinks`, it
+/// ends with a section linking to the notes that link to it, by the file
+/// names they'd be exported as. The note is locked until the file has been
+/// written.
+pub fn export_note(
+    db: &Database,
+    locks: &NoteLocks,
+    id: &str,
+    format: ExportFormat,
+    backlinks: bool,
+    path: &Path,
+) -> Result<(), String> {
+    let _lock = locks.lock([id], "export")?;
+    let note = db
+        .get_note_by_id(id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Note not found: {}", id))?;
+    let note = redacted(db, &note)?;
+    let backlinks = if backlinks {
+        db.export_backlinks(id, format)
+            .map_err(|e| format!("Database error: {}", e))?
+    } else {
+        Vec::new()
+    };
+
+    fs::write(
+        path,
+        beck_markdown::render_note(&note.title, &note.content, format, &backlinks),
+    )
+    .map_err(|e| format!("Failed to write export file: {}", e))
+}
+
+/// Create a note from a Markdown file.
+///
+/// A leading `# Heading` becomes the title; otherwise the file name is used.
+/// Importing the same file again updates the note it was first imported as.
+pub fn import_markdown(
+    db: &Database,
+    locks: &NoteLocks,
+    path: &Path,
+    folder_id: Option<String>,
+) -> Result<Note, String> {
+    let markdown =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read Markdown file: {}", e))?;
+
+    let trimmed = markdown.trim_start();
+    let (title, body) = match trimmed.strip_prefix("# ") {
+        Some(rest) => {
+            let (heading, body) = rest.split_once('\n').unwrap_or((rest, ""));
+            (heading.trim().to_string(), body)
+        }
+        None => (
+            path.file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Untitled".to_string()),
+            trimmed,
+        ),
+    };
+
+    let external_id = fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+
+    let existing = db
+        .find_external_ref(MARKDOWN_SOURCE, &external_id)
+        .map_err(|e| format!("Database error: {}", e))?;
+    let _lock = locks.lock(existing, "import")?;
+
+    db.save_imported_note(
+        MARKDOWN_SOURCE,
+        &external_id,
+        NoteInput {
+            id: None,
+            title,
+            content: beck_markdown::markdown_to_html(body),
+            folder_id,
+            updated_at: None,
+            is_deleted: false,
+            is_canvas: false,
+        },
+    )
+    .map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,497 @@
+//! Saved, repeatable exports of a folder.
+//!
+//! An export profile is attached to a folder and names everything an export
+//! needs: the format, what happens to images and attachments, whether the
+//! redaction rules apply and the directory to write to (a shared drive, a
+//! synced folder, ...). Running it writes every live note in the folder, and
+//! by default its subfolders, as `<subfolder path>/<title>.<ext>` under that
+//! directory, overwriting the files of earlier runs. Files it didn't write are
+//! left alone, and notes deleted since a run keep their old files. A profile
+//! with an interval is run by the scheduler once the interval has passed since
+//! its last run, e.g. every 10080 minutes for a weekly export.
+//!
+//! Assets can be kept as the app's `asset://` links, which only work on this
+//! computer, copied next to the notes into `assets/` with the links pointed at
+//! the copies, or left out.
+
+use beck_markdown::export_file_name;
+use beck_markdown::html::{self, Element, Node};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::database::{assets, now_rfc3339, Database, Folder};
+use crate::export::{self, ExportFormat};
+use crate::integrity::asset_file;
+use crate::locks::NoteLocks;
+use crate::mirror::folder_path;
+
+/// Directory under the destination that copied assets go to
+const ASSETS_DIR_NAME: &str = "assets";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetHandling {
+    /// Leave `asset://` links as they are
+    Keep,
+    /// Copy the files into `assets/` and link to the copies
+    Copy,
+    /// Drop images and embedded media; links to attachments keep their text
+    Omit,
+}
+
+impl AssetHandling {
+    fn name(self) -> &'static str {
+        match self {
+            AssetHandling::Keep => "keep",
+            AssetHandling::Copy => "copy",
+            AssetHandling::Omit => "omit",
+        }
+    }
+
+    fn parse(value: &str) -> AssetHandling {
+        match value {
+            "keep" => AssetHandling::Keep,
+            "omit" => AssetHandling::Omit,
+            _ => AssetHandling::Copy,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportProfile {
+    pub id: String,
+    pub name: String,
+    pub folder_id: String,
+    pub include_subfolders: bool,
+    pub format: ExportFormat,
+    pub assets: AssetHandling,
+    /// Apply the export redaction rules
+    pub redact: bool,
+    /// Directory the notes are written to
+    pub destination: String,
+    /// Minutes between scheduled runs; `None` runs only on request
+    pub interval_minutes: Option<i64>,
+    pub last_run_at: Option<String>,
+    /// Why the last run failed, if it did
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportProfileInput {
+    /// Omit to add a profile
+    pub id: Option<String>,
+    pub name: String,
+    pub folder_id: String,
+    pub include_subfolders: Option<bool>,
+    pub format: Option<ExportFormat>,
+    pub assets: Option<AssetHandling>,
+    pub redact: Option<bool>,
+    pub destination: String,
+    pub interval_minutes: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ExportProfileReport {
+    pub profile_id: String,
+    pub destination: String,
+    pub written: usize,
+    pub assets_copied: usize,
+    /// Assets linked from notes but missing from the vault
+    pub assets_missing: usize,
+}
+
+pub fn ensure_export_profiles_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS export_profiles (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            folder_id TEXT NOT NULL,
+            include_subfolders INTEGER NOT NULL DEFAULT 1,
+            format TEXT NOT NULL DEFAULT 'markdown',
+            assets TEXT NOT NULL DEFAULT 'copy',
+            redact INTEGER NOT NULL DEFAULT 1,
+            destination TEXT NOT NULL,
+            interval_minutes INTEGER,
+            last_run_at TEXT,
+            last_error TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+const PROFILE_COLUMNS: &str = "id, name, folder_id, include_subfolders, format, assets, redact, \
+     destination, interval_minutes, last_run_at, last_error, created_at, updated_at";
+
+fn format_name(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Markdown => "markdown",
+        ExportFormat::Html => "html",
+    }
+}
+
+fn row_to_profile(row: &rusqlite::Row) -> SqliteResult<ExportProfile> {
+    Ok(ExportProfile {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        folder_id: row.get(2)?,
+        include_subfolders: row.get::<_, i32>(3)? != 0,
+        format: match row.get::<_, String>(4)?.as_str() {
+            "html" => ExportFormat::Html,
+            _ => ExportFormat::Markdown,
+        },
+        assets: AssetHandling::parse(&row.get::<_, String>(5)?),
+        redact: row.get::<_, i32>(6)? != 0,
+        destination: row.get(7)?,
+        interval_minutes: row.get(8)?,
+        last_run_at: row.get(9)?,
+        last_error: row.get(10)?,
+        created_at: row.get(11)?,
+        updated_at: row.get(12)?,
+    })
+}
+
+impl Database {
+    pub fn save_export_profile(&self, input: &ExportProfileInput) -> SqliteResult<ExportProfile> {
+        let conn = self.conn.lock().unwrap();
+        let id = input
+            .id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let now = now_rfc3339();
+        conn.execute(
+            "INSERT INTO export_profiles
+                (id, name, folder_id, include_subfolders, format, assets, redact, destination,
+                 interval_minutes, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?10)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                folder_id = excluded.folder_id,
+                include_subfolders = excluded.include_subfolders,
+                format = excluded.format,
+                assets = excluded.assets,
+                redact = excluded.redact,
+                destination = excluded.destination,
+                interval_minutes = excluded.interval_minutes,
+                updated_at = excluded.updated_at",
+            params![
+                &id,
+                input.name.trim(),
+                &input.folder_id,
+                input.include_subfolders.unwrap_or(true) as i32,
+                format_name(input.format.unwrap_or(ExportFormat::Markdown)),
+                input.assets.unwrap_or(AssetHandling::Copy).name(),
+                input.redact.unwrap_or(true) as i32,
+                input.destination.trim(),
+                input.interval_minutes.filter(|m| *m > 0),
+                now,
+            ],
+        )?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM export_profiles WHERE id = ?1",
+                PROFILE_COLUMNS
+            ),
+            params![&id],
+            row_to_profile,
+        )
+    }
+
+    pub fn get_export_profile(&self, id: &str) -> SqliteResult<Option<ExportProfile>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM export_profiles WHERE id = ?1",
+                PROFILE_COLUMNS
+            ),
+            params![id],
+            row_to_profile,
+        )
+        .optional()
+    }
+
+    /// Profiles, optionally only those attached to one folder
+    pub fn list_export_profiles(
+        &self,
+        folder_id: Option<&str>,
+    ) -> SqliteResult<Vec<ExportProfile>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM export_profiles
+             WHERE ?1 IS NULL OR folder_id = ?1
+             ORDER BY name COLLATE NOCASE",
+            PROFILE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![folder_id], row_to_profile)?;
+        rows.collect()
+    }
+
+    pub fn delete_export_profile(&self, id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute("DELETE FROM export_profiles WHERE id = ?1", params![id])?;
+        Ok(removed > 0)
+    }
+
+    fn record_export_run(&self, id: &str, error: Option<&str>) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE export_profiles SET last_run_at = ?2, last_error = ?3 WHERE id = ?1",
+            params![id, now_rfc3339(), error],
+        )?;
+        Ok(())
+    }
+
+    /// Ids of live, loaded notes in a folder and optionally its subfolders
+    fn notes_for_export_profile(
+        &self,
+        folder_id: &str,
+        include_subfolders: bool,
+    ) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE scope(id, depth) AS (
+                SELECT ?1, 0
+                UNION
+                SELECT f.id, s.depth + 1 FROM folders f JOIN scope s ON f.parent_id = s.id
+                WHERE ?2 AND f.is_deleted = 0 AND s.depth < 64
+             )
+             SELECT id FROM notes
+             WHERE folder_id IN (SELECT id FROM scope)
+               AND is_deleted = 0 AND is_canvas = 0
+               AND id NOT IN (SELECT note_id FROM offline_notes WHERE evicted = 1)
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![folder_id, include_subfolders], |row| row.get(0))?;
+        rows.collect()
+    }
+}
+
+/// Add or update a profile, checking it can be run
+pub fn save_profile(db: &Database, input: &ExportProfileInput) -> Result<ExportProfile, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if input.name.trim().is_empty() {
+        return Err("An export profile needs a name".to_string());
+    }
+    if !Path::new(input.destination.trim()).is_absolute() {
+        return Err("Choose a destination folder for the export".to_string());
+    }
+    db.get_folder_by_id(&input.folder_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Folder not found: {}", input.folder_id))?;
+    if let Some(id) = &input.id {
+        db.get_export_profile(id)
+            .map_err(db_err)?
+            .ok_or_else(|| format!("Export profile not found: {}", id))?;
+    }
+    db.save_export_profile(input).map_err(db_err)
+}
+
+/// Copies assets for the notes of one run
+struct AssetCopier<'a> {
+    handling: AssetHandling,
+    vault_assets: &'a Path,
+    target: PathBuf,
+    copied: HashSet<String>,
+    missing: HashSet<String>,
+}
+
+impl AssetCopier<'_> {
+    /// A note's content with its asset links handled. `prefix` leads from the
+    /// note's file to the destination.
+    fn rewrite(&mut self, content: &str, prefix: &str) -> Result<String, String> {
+        if self.handling == AssetHandling::Keep {
+            return Ok(content.to_string());
+        }
+        let nodes = self.rewrite_nodes(html::parse(content), prefix)?;
+        Ok(html::serialize(&nodes))
+    }
+
+    fn rewrite_nodes(&mut self, nodes: Vec<Node>, prefix: &str) -> Result<Vec<Node>, String> {
+        let mut out = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let Node::Element(mut el) = node else {
+                out.push(node);
+                continue;
+            };
+            el.children = self.rewrite_nodes(std::mem::take(&mut el.children), prefix)?;
+            let attr = if el.name == "a" { "href" } else { "src" };
+            let Some(file) = el.attr(attr).and_then(asset_file).map(str::to_string) else {
+                out.push(Node::Element(el));
+                continue;
+            };
+            if self.handling == AssetHandling::Copy && self.copy(&file)? {
+                set_attr(
+                    &mut el,
+                    attr,
+                    format!("{}{}/{}", prefix, ASSETS_DIR_NAME, file),
+                );
+                out.push(Node::Element(el));
+            } else if el.name == "a" {
+                // Links keep their text, media go
+                out.extend(el.children);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Copy an asset once per run; false if the vault doesn't have it
+    fn copy(&mut self, file: &str) -> Result<bool, String> {
+        if self.copied.contains(file) {
+            return Ok(true);
+        }
+        let source = self.vault_assets.join(file);
+        if !source.is_file() {
+            self.missing.insert(file.to_string());
+            return Ok(false);
+        }
+        fs::create_dir_all(&self.target)
+            .map_err(|e| format!("Failed to create {}: {}", self.target.display(), e))?;
+        let dest = self.target.join(file);
+        fs::copy(&source, &dest)
+            .map_err(|e| format!("Failed to copy {}: {}", dest.display(), e))?;
+        self.copied.insert(file.to_string());
+        Ok(true)
+    }
+}
+
+fn set_attr(el: &mut Element, name: &str, value: String) {
+    match el.attrs.iter_mut().find(|(key, _)| key == name) {
+        Some((_, v)) => *v = value,
+        None => el.attrs.push((name.to_string(), value)),
+    }
+}
+
+/// Run a profile now and record the outcome on it
+pub fn run_profile(
+    db: &Database,
+    locks: &NoteLocks,
+    app_data_dir: &Path,
+    id: &str,
+) -> Result<ExportProfileReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let profile = db
+        .get_export_profile(id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Export profile not found: {}", id))?;
+    let result = export_profile(db, locks, app_data_dir, &profile);
+    db.record_export_run(id, result.as_ref().err().map(String::as_str))
+        .map_err(db_err)?;
+    result
+}
+
+fn export_profile(
+    db: &Database,
+    locks: &NoteLocks,
+    app_data_dir: &Path,
+    profile: &ExportProfile,
+) -> Result<ExportProfileReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let destination = PathBuf::from(&profile.destination);
+    fs::create_dir_all(&destination).map_err(|e| {
+        format!(
+            "Failed to create export folder {}: {}",
+            destination.display(),
+            e
+        )
+    })?;
+
+    let ids = db
+        .notes_for_export_profile(&profile.folder_id, profile.include_subfolders)
+        .map_err(db_err)?;
+    let _lock = locks.lock(ids.iter().map(String::as_str), "export")?;
+
+    let folders = db.get_all_folders().map_err(db_err)?;
+    let folders: HashMap<&str, &Folder> = folders.iter().map(|f| (f.id.as_str(), f)).collect();
+    let root = folder_path(&folders, Some(&profile.folder_id));
+    let extension = profile.format.extension();
+    let vault_assets = assets::get_assets_dir(app_data_dir);
+    let mut copier = AssetCopier {
+        handling: profile.assets,
+        vault_assets: &vault_assets,
+        target: destination.join(ASSETS_DIR_NAME),
+        copied: HashSet::new(),
+        missing: HashSet::new(),
+    };
+
+    let mut report = ExportProfileReport {
+        profile_id: profile.id.clone(),
+        destination: profile.destination.clone(),
+        ..Default::default()
+    };
+    let mut used: HashSet<String> = HashSet::new();
+    for id in &ids {
+        // The note may have been deleted since the list was read
+        let Some(note) = db.get_note_by_id(id).map_err(db_err)? else {
+            continue;
+        };
+        let mut note = if profile.redact {
+            export::redacted(db, &note)?
+        } else {
+            note
+        };
+        let folder = folder_path(&folders, note.folder_id.as_deref());
+        let folder = folder.strip_prefix(&root).unwrap_or(&folder).to_path_buf();
+        let base = export_file_name(&note.title);
+        let mut relative = folder.join(format!("{}.{}", base, extension));
+        let mut n = 2;
+        // Lowercased, since the destination may be case-insensitive
+        while !used.insert(relative.to_string_lossy().to_lowercase()) {
+            relative = folder.join(format!("{} ({}).{}", base, n, extension));
+            n += 1;
+        }
+
+        let prefix = "../".repeat(folder.components().count());
+        note.content = copier.rewrite(&note.content, &prefix)?;
+        let path = destination.join(&relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, export::render_note(&note, profile.format))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        report.written += 1;
+    }
+    report.assets_copied = copier.copied.len();
+    report.assets_missing = copier.missing.len();
+    Ok(report)
+}
+
+/// Run the profiles whose interval has passed since their last run. Failures
+/// are recorded on the profile and reported without stopping the others.
+pub fn run_due_profiles(
+    db: &Database,
+    locks: &NoteLocks,
+    app_data_dir: &Path,
+) -> Result<Vec<Result<ExportProfileReport, String>>, String> {
+    let now = chrono::Utc::now();
+    let due: Vec<ExportProfile> = db
+        .list_export_profiles(None)
+        .map_err(|e| format!("Database error: {}", e))?
+        .into_iter()
+        .filter(|profile| {
+            let Some(interval) = profile.interval_minutes else {
+                return false;
+            };
+            profile
+                .last_run_at
+                .as_deref()
+                .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+                .is_none_or(|at| {
+                    now.signed_duration_since(at) >= chrono::Duration::minutes(interval)
+                })
+        })
+        .collect();
+    Ok(due
+        .iter()
+        .map(|profile| run_profile(db, locks, app_data_dir, &profile.id))
+        .collect())
+}
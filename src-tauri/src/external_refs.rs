@@ -0,0 +1,82 @@
+//! Mapping from notes in other apps to the notes they were imported as.
+//!
+//! Every importer records `(source, external_id) -> note_id` so that importing the
+//! same export again updates the existing notes instead of creating duplicates.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+
+use crate::database::{now_rfc3339, Database, Note, NoteInput};
+
+pub fn ensure_external_refs_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS external_refs (
+            source TEXT NOT NULL,
+            external_id TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            imported_at TEXT NOT NULL,
+            PRIMARY KEY (source, external_id),
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_external_refs_note_id ON external_refs(note_id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+impl Database {
+    /// Note previously imported for an external ID, if any
+    pub fn find_external_ref(
+        &self,
+        source: &str,
+        external_id: &str,
+    ) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT note_id FROM external_refs WHERE source = ?1 AND external_id = ?2",
+            params![source, external_id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    fn put_external_ref(&self, source: &str, external_id: &str, note_id: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO external_refs (source, external_id, note_id, imported_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(source, external_id) DO UPDATE SET
+                note_id = excluded.note_id,
+                imported_at = excluded.imported_at",
+            params![source, external_id, note_id, now_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Save an imported note, updating the note created by an earlier import of
+    /// the same external item. An item re-imported without a folder stays where
+    /// the user moved it.
+    pub fn save_imported_note(
+        &self,
+        source: &str,
+        external_id: &str,
+        mut input: NoteInput,
+    ) -> SqliteResult<Note> {
+        if let Some(note_id) = self.find_external_ref(source, external_id)? {
+            if input.folder_id.is_none() {
+                input.folder_id = self
+                    .get_note_by_id(&note_id)?
+                    .and_then(|note| note.folder_id);
+            }
+            input.id = Some(note_id);
+        }
+
+        let note = self.save_note(input)?;
+        self.put_external_ref(source, external_id, &note.id)?;
+        Ok(note)
+    }
+}
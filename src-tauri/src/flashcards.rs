@@ -0,0 +1,414 @@
+//! Flashcards extracted from notes, scheduled with SM-2.
+//!
+//! Two patterns are recognised in note text:
+//!
+//! - A `Q:` line followed by an `A:` line makes a question/answer card.
+//! - Cloze deletions, `{{answer}}` or Anki-style `{{c1::answer::hint}}`, make one
+//!   card per deletion with the answer blanked out of its line.
+//!
+//! Cards are keyed by a hash of their question so editing a note keeps the review
+//! history of cards that didn't change.
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::database::{now_rfc3339, Database};
+
+/// Starting ease factor for new cards
+const INITIAL_EASE: f64 = 2.5;
+/// SM-2 never lets the ease factor drop below this
+const MIN_EASE: f64 = 1.3;
+/// Default number of cards returned by `get_due_cards`
+const DEFAULT_DUE_LIMIT: i64 = 50;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Card {
+    pub id: String,
+    pub note_id: String,
+    pub note_title: String,
+    /// "qa" or "cloze"
+    pub kind: String,
+    pub front: String,
+    pub back: String,
+    pub ease: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+    pub due_at: String,
+    pub last_reviewed_at: Option<String>,
+}
+
+/// A card parsed from note text, before scheduling state is attached
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedCard {
+    pub kind: &'static str,
+    pub front: String,
+    pub back: String,
+}
+
+impl ParsedCard {
+    fn key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.kind.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.front.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+pub fn ensure_flashcards_schema(conn: &Connection) -> SqliteResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'cards')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cards (
+            id TEXT PRIMARY KEY NOT NULL,
+            note_id TEXT NOT NULL,
+            card_key TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            front TEXT NOT NULL,
+            back TEXT NOT NULL,
+            ease REAL NOT NULL DEFAULT 2.5,
+            interval_days INTEGER NOT NULL DEFAULT 0,
+            repetitions INTEGER NOT NULL DEFAULT 0,
+            due_at TEXT NOT NULL,
+            last_reviewed_at TEXT,
+            created_at TEXT NOT NULL,
+            UNIQUE (note_id, card_key),
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_cards_due_at ON cards(due_at)",
+        [],
+    )?;
+
+    // Backfill existing notes the first time the table is created
+    if !exists {
+        let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE is_deleted = 0")?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        for (id, content) in notes {
+            index_note_cards(conn, &id, &content, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract Q/A and cloze cards from note HTML
+pub fn parse_cards(content: &str) -> Vec<ParsedCard> {
+    let text = beck_markdown::html_to_text(content);
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let mut cards = Vec::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(question) = strip_label(line, 'Q') {
+            if let Some(answer) = lines.get(i + 1).and_then(|next| strip_label(next, 'A')) {
+                if !question.is_empty() && !answer.is_empty() {
+                    cards.push(ParsedCard {
+                        kind: "qa",
+                        front: question.to_string(),
+                        back: answer.to_string(),
+                    });
+                }
+                i += 2;
+                continue;
+            }
+        }
+        cards.extend(parse_cloze_line(line));
+        i += 1;
+    }
+
+    cards
+}
+
+/// `Q: text` / `q: text` -> `text`
+fn strip_label(line: &str, label: char) -> Option<&str> {
+    let mut chars = line.chars();
+    let first = chars.next()?;
+    if !first.eq_ignore_ascii_case(&label) {
+        return None;
+    }
+    chars.as_str().strip_prefix(':').map(str::trim)
+}
+
+/// A cloze deletion within a line
+struct Cloze {
+    start: usize,
+    end: usize,
+    group: String,
+    answer: String,
+    hint: Option<String>,
+}
+
+fn find_clozes(line: &str) -> Vec<Cloze> {
+    let mut clozes = Vec::new();
+    let mut from = 0;
+    while let Some(open) = line[from..].find("{{").map(|i| from + i) {
+        let Some(close) = line[open + 2..].find("}}").map(|i| open + 2 + i) else {
+            break;
+        };
+        let inner = &line[open + 2..close];
+        let parts: Vec<&str> = inner.split("::").collect();
+        let (group, answer, hint) = match parts.as_slice() {
+            [group, answer] if is_cloze_group(group) => (group.to_string(), *answer, None),
+            [group, answer, hint] if is_cloze_group(group) => {
+                (group.to_string(), *answer, Some(hint.to_string()))
+            }
+            // Unnumbered deletions each get their own card
+            [answer] => (format!("#{}", clozes.len()), *answer, None),
+            _ => (format!("#{}", clozes.len()), inner, None),
+        };
+        if !answer.trim().is_empty() {
+            clozes.push(Cloze {
+                start: open,
+                end: close + 2,
+                group,
+                answer: answer.trim().to_string(),
+                hint,
+            });
+        }
+        from = close + 2;
+    }
+    clozes
+}
+
+fn is_cloze_group(s: &str) -> bool {
+    s.len() > 1 && s.starts_with('c') && s[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// One card per cloze group: the group's answers are blanked, others shown
+fn parse_cloze_line(line: &str) -> Vec<ParsedCard> {
+    let clozes = find_clozes(line);
+    let mut groups: Vec<&str> = Vec::new();
+    for cloze in &clozes {
+        if !groups.contains(&cloze.group.as_str()) {
+            groups.push(&cloze.group);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let mut front = String::new();
+            let mut answers = Vec::new();
+            let mut last = 0;
+            for cloze in &clozes {
+                front.push_str(&line[last..cloze.start]);
+                if cloze.group == group {
+                    match &cloze.hint {
+                        Some(hint) => front.push_str(&format!("[{}]", hint)),
+                        None => front.push_str("[...]"),
+                    }
+                    answers.push(cloze.answer.clone());
+                } else {
+                    front.push_str(&cloze.answer);
+                }
+                last = cloze.end;
+            }
+            front.push_str(&line[last..]);
+            ParsedCard {
+                kind: "cloze",
+                front,
+                back: answers.join(", "),
+            }
+        })
+        .collect()
+}
+
+/// Sync a note's cards with its content, keeping scheduling state for cards
+/// whose question is unchanged
+pub(crate) fn index_note_cards(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+    is_deleted: bool,
+) -> SqliteResult<()> {
+    let cards = if is_deleted {
+        Vec::new()
+    } else {
+        parse_cards(content)
+    };
+    let keys: Vec<String> = cards.iter().map(ParsedCard::key).collect();
+
+    // Drop cards no longer present in the note
+    let mut stmt = conn.prepare("SELECT id, card_key FROM cards WHERE note_id = ?1")?;
+    let existing = stmt
+        .query_map(params![note_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    for (id, key) in existing {
+        if !keys.contains(&key) {
+            conn.execute("DELETE FROM cards WHERE id = ?1", params![id])?;
+        }
+    }
+
+    let now = now_rfc3339();
+    for (card, key) in cards.iter().zip(&keys) {
+        conn.execute(
+            "INSERT INTO cards (id, note_id, card_key, kind, front, back, ease, due_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+             ON CONFLICT(note_id, card_key) DO UPDATE SET back = excluded.back",
+            params![
+                Uuid::new_v4().to_string(),
+                note_id,
+                key,
+                card.kind,
+                &card.front,
+                &card.back,
+                INITIAL_EASE,
+                &now
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// SM-2 scheduling state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub ease: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+}
+
+/// Apply an SM-2 review with a grade from 0 (blackout) to 5 (perfect recall)
+pub fn sm2(current: Schedule, grade: u8) -> Schedule {
+    let q = grade.min(5) as f64;
+    let (interval_days, repetitions) = if grade < 3 {
+        (1, 0)
+    } else {
+        let interval = match current.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (current.interval_days as f64 * current.ease).round() as i64,
+        };
+        (interval, current.repetitions + 1)
+    };
+    let ease = (current.ease + (0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02))).max(MIN_EASE);
+
+    Schedule {
+        ease,
+        interval_days,
+        repetitions,
+    }
+}
+
+const CARD_COLUMNS: &str = "c.id, c.note_id, n.title, c.kind, c.front, c.back, c.ease,
+    c.interval_days, c.repetitions, c.due_at, c.last_reviewed_at";
+
+fn row_to_card(row: &rusqlite::Row) -> SqliteResult<Card> {
+    Ok(Card {
+        id: row.get(0)?,
+        note_id: row.get(1)?,
+        note_title: row.get(2)?,
+        kind: row.get(3)?,
+        front: row.get(4)?,
+        back: row.get(5)?,
+        ease: row.get(6)?,
+        interval_days: row.get(7)?,
+        repetitions: row.get(8)?,
+        due_at: row.get(9)?,
+        last_reviewed_at: row.get(10)?,
+    })
+}
+
+impl Database {
+    /// Cards due at or before `now`, most overdue first
+    pub fn get_due_cards(&self, now: DateTime<Utc>, limit: Option<i64>) -> SqliteResult<Vec<Card>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM cards c
+             JOIN notes n ON n.id = c.note_id
+             WHERE n.is_deleted = 0 AND c.due_at <= ?1
+             ORDER BY c.due_at ASC
+             LIMIT ?2",
+            CARD_COLUMNS
+        ))?;
+        let rows = stmt.query_map(
+            params![
+                now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                limit.unwrap_or(DEFAULT_DUE_LIMIT)
+            ],
+            row_to_card,
+        )?;
+        rows.collect()
+    }
+
+    /// All cards extracted from a note
+    pub fn get_note_cards(&self, note_id: &str) -> SqliteResult<Vec<Card>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM cards c
+             JOIN notes n ON n.id = c.note_id
+             WHERE c.note_id = ?1
+             ORDER BY c.created_at ASC",
+            CARD_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![note_id], row_to_card)?;
+        rows.collect()
+    }
+
+    /// Record a review and reschedule the card. Returns None if the card doesn't exist.
+    pub fn grade_card(&self, card_id: &str, grade: u8) -> SqliteResult<Option<Card>> {
+        let conn = self.conn.lock().unwrap();
+        let current = conn.query_row(
+            "SELECT ease, interval_days, repetitions FROM cards WHERE id = ?1",
+            params![card_id],
+            |row| {
+                Ok(Schedule {
+                    ease: row.get(0)?,
+                    interval_days: row.get(1)?,
+                    repetitions: row.get(2)?,
+                })
+            },
+        );
+        let current = match current {
+            Ok(current) => current,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let next = sm2(current, grade);
+        let now = Utc::now();
+        let due_at = now + Duration::days(next.interval_days);
+        conn.execute(
+            "UPDATE cards SET ease = ?2, interval_days = ?3, repetitions = ?4,
+                due_at = ?5, last_reviewed_at = ?6
+             WHERE id = ?1",
+            params![
+                card_id,
+                next.ease,
+                next.interval_days,
+                next.repetitions,
+                due_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+            ],
+        )?;
+
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM cards c JOIN notes n ON n.id = c.note_id WHERE c.id = ?1",
+                CARD_COLUMNS
+            ),
+            params![card_id],
+            row_to_card,
+        )
+        .map(Some)
+    }
+}
@@ -0,0 +1,143 @@
+//! Read-only "guest" mode.
+//!
+//! Starting the app with `--guest` opens the vault for inspection only, e.g.
+//! a restored backup or someone else's exported vault. Every command that is
+//! not on the read-only allowlist below is rejected before it runs, so new
+//! commands are refused in guest mode until they are deliberately listed.
+//! Background jobs that write (review reminders, scheduled scripts) don't run,
+//! and the database is opened read-only without the usual migrations, so a
+//! vault from an older version is shown as is rather than upgraded.
+
+use tauri::ipc::Invoke;
+
+use crate::commands::CommandError;
+
+/// Command-line flag that starts the app in guest mode
+pub const GUEST_FLAG: &str = "--guest";
+
+/// Commands that never modify the vault
+const READ_ONLY_COMMANDS: &[&str] = &[
+    // Notes and folders
+    "get_all_notes",
+    "get_note",
+    "get_notes_by_folder",
+    "get_notes_updated_since",
+    "get_all_folders",
+    "get_folder",
+    "get_folders_by_parent",
+    "get_folders_updated_since",
+    // Assets
+    "list_assets",
+    "get_assets_path",
+    "get_attachment",
+    "search_attachments",
+    "list_audio_assets_for_note",
+    "get_asset_usage",
+    // CRDT and oplog
+    "get_crdt_state",
+    "get_all_crdt_states",
+    "get_crdt_states_for_notes",
+    "get_crdt_states_updated_since",
+    "get_pending_changes",
+    "list_sync_profiles",
+    "get_active_sync_profile",
+    "get_oplog_entries",
+    // Receiving updates; `ws_send_update` writes
+    "ws_connect",
+    "ws_disconnect",
+    "ws_status",
+    "ws_subscribe_note",
+    "ws_unsubscribe_note",
+    // Backups, templates, scripts
+    "list_backups",
+    "render_template",
+    "parse_tabular_clipboard",
+    "list_templates",
+    "list_scripts",
+    // Refuses `fix` itself in guest mode
+    "check_vault_integrity",
+    "check_client_update",
+    // Boards, relations, review, flashcards
+    "get_boards",
+    "get_board",
+    "get_boards_updated_since",
+    "get_note_relations",
+    "get_relations_updated_since",
+    "get_note_review_at",
+    "get_notes_due_for_review",
+    "get_stale_notes",
+    "get_due_cards",
+    "get_note_cards",
+    // Settings
+    "get_setting",
+    "get_guest_mode",
+    "get_app_lock_status",
+    "lock_app",
+    "unlock_app",
+    "get_offline_status",
+    "get_activity_heatmap",
+    "list_incoming_changes",
+    "get_incoming_change_diff",
+    "list_publish_targets",
+    "list_publish_deliveries",
+    "list_task_integrations",
+    "list_exported_tasks",
+    "get_recovery_status",
+    "get_startup_error",
+    "get_data_dir",
+    // Only saves where to find the vault, from the startup error screen
+    "choose_data_dir",
+    "get_recent_crashes",
+    // Exports only write outside the vault
+    "export_note",
+    "list_export_profiles",
+    "get_redaction_rules",
+    "search_code",
+    "export_code_snippets",
+    "list_highlights",
+    "export_highlights",
+    "get_vault_keywords",
+    "suggest_note_keywords",
+    "search_purged_notes",
+    "get_retention_policy",
+];
+
+/// Whether the app runs in guest mode, kept as managed state
+pub struct GuestMode(pub bool);
+
+impl GuestMode {
+    /// Guest mode as requested on the command line
+    pub fn from_args() -> Self {
+        GuestMode(std::env::args().skip(1).any(|arg| arg == GUEST_FLAG))
+    }
+
+    /// Fail if the app is in guest mode, for commands that only write
+    /// depending on their arguments
+    pub fn check(&self) -> Result<(), String> {
+        if self.0 {
+            return Err(rejection());
+        }
+        Ok(())
+    }
+}
+
+fn rejection() -> String {
+    "The vault is open read-only in guest mode; restart without --guest to make changes".to_string()
+}
+
+/// Wrap the command handler so guest mode rejects every command that isn't
+/// known to be read-only
+pub fn guard<F>(guest: bool, handler: F) -> impl Fn(Invoke) -> bool + Send + Sync + 'static
+where
+    F: Fn(Invoke) -> bool + Send + Sync + 'static,
+{
+    move |invoke: Invoke| {
+        if guest && !READ_ONLY_COMMANDS.contains(&invoke.message.command()) {
+            invoke.resolver.reject(CommandError {
+                message: rejection(),
+            });
+            return true;
+        }
+        handler(invoke)
+    }
+}
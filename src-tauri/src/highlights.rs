@@ -0,0 +1,221 @@
+//! Index of highlighted passages in note content.
+//!
+//! Every saved note is scanned for `<mark>` highlights, which are stored in
+//! `highlights` with their color so users can review everything they have
+//! highlighted across the vault, or export them as a Markdown digest.
+
+use beck_markdown::extract_highlights;
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::database::Database;
+use crate::links::NOTE_LINK_PREFIX;
+use crate::snippets::like_pattern;
+
+/// Maximum number of highlights returned by one listing
+const LIST_LIMIT: i64 = 1000;
+
+/// A highlighted passage extracted from a note
+#[derive(Debug, Serialize, Clone)]
+pub struct Highlight {
+    pub id: i64,
+    pub note_id: String,
+    pub note_title: String,
+    pub folder_id: Option<String>,
+    /// Lowercased CSS color; `None` for plain highlights
+    pub color: Option<String>,
+    pub text: String,
+    /// Zero-based index of the highlight within the note
+    pub position: i64,
+}
+
+/// Restricts `list_highlights`; unset fields match everything
+#[derive(Debug, Deserialize, Default)]
+pub struct HighlightFilter {
+    pub note_id: Option<String>,
+    pub folder_id: Option<String>,
+    pub color: Option<String>,
+    /// Substring of the highlighted text
+    pub query: Option<String>,
+}
+
+pub fn ensure_highlights_schema(conn: &Connection) -> SqliteResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'highlights')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS highlights (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            color TEXT,
+            text TEXT NOT NULL,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_highlights_note_id ON highlights(note_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_highlights_color ON highlights(color)",
+        [],
+    )?;
+
+    // Backfill existing notes the first time the index is created
+    if !exists {
+        let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE is_deleted = 0")?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        for (id, content) in notes {
+            index_note_highlights(conn, &id, &content, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the indexed highlights for a note. Deleted notes are dropped from the index.
+pub(crate) fn index_note_highlights(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+    is_deleted: bool,
+) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM highlights WHERE note_id = ?1",
+        params![note_id],
+    )?;
+    if is_deleted {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO highlights (note_id, position, color, text) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, highlight) in extract_highlights(content).into_iter().enumerate() {
+        stmt.execute(params![
+            note_id,
+            position as i64,
+            highlight.color,
+            highlight.text
+        ])?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Highlights across live notes, most recently edited notes first
+    pub fn list_highlights(&self, filter: &HighlightFilter) -> SqliteResult<Vec<Highlight>> {
+        self.query_highlights(filter, LIST_LIMIT)
+    }
+
+    /// Highlights matching `filter`; a negative `limit` returns all of them
+    fn query_highlights(
+        &self,
+        filter: &HighlightFilter,
+        limit: i64,
+    ) -> SqliteResult<Vec<Highlight>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT h.id, h.note_id, n.title, n.folder_id, h.color, h.text, h.position
+             FROM highlights h
+             JOIN notes n ON n.id = h.note_id
+             WHERE n.is_deleted = 0
+               AND (?1 IS NULL OR h.note_id = ?1)
+               AND (?2 IS NULL OR n.folder_id = ?2)
+               AND (?3 IS NULL OR h.color = ?3)
+               AND (?4 IS NULL OR h.text LIKE ?4 ESCAPE '\\')
+             ORDER BY n.updated_at DESC, h.note_id ASC, h.position ASC
+             LIMIT ?5",
+        )?;
+        let color = filter
+            .color
+            .as_deref()
+            .map(|c| c.trim().to_ascii_lowercase());
+        let query = filter
+            .query
+            .as_deref()
+            .filter(|q| !q.trim().is_empty())
+            .map(like_pattern);
+        let rows = stmt.query_map(
+            params![filter.note_id, filter.folder_id, color, query, limit],
+            |row| {
+                Ok(Highlight {
+                    id: row.get(0)?,
+                    note_id: row.get(1)?,
+                    note_title: row.get(2)?,
+                    folder_id: row.get(3)?,
+                    color: row.get(4)?,
+                    text: row.get(5)?,
+                    position: row.get(6)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+}
+
+/// Escape characters that would end a Markdown link label
+fn escape_link_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// Render highlights as a Markdown digest: one section per note, headed by a
+/// link back to the note, with its highlights in document order
+pub fn render_highlights_markdown(highlights: &[Highlight]) -> String {
+    let mut out = String::from("# Highlights\n");
+    let mut current_note: Option<&str> = None;
+    for highlight in highlights {
+        if current_note != Some(highlight.note_id.as_str()) {
+            let title = match highlight.note_title.trim() {
+                "" => "Untitled",
+                title => title,
+            };
+            out.push_str(&format!(
+                "\n## [{}]({}{})\n\n",
+                escape_link_text(title),
+                NOTE_LINK_PREFIX,
+                highlight.note_id
+            ));
+            current_note = Some(&highlight.note_id);
+        }
+        let text = highlight
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match &highlight.color {
+            Some(color) => out.push_str(&format!("- {} `{}`\n", text, color)),
+            None => out.push_str(&format!("- {}\n", text)),
+        }
+    }
+    out
+}
+
+/// Write the highlights matching `scope` to `path` as a Markdown digest.
+/// Returns the number of highlights written.
+pub fn export_highlights(
+    db: &Database,
+    scope: &HighlightFilter,
+    path: &Path,
+) -> Result<usize, String> {
+    let highlights = db
+        .query_highlights(scope, -1)
+        .map_err(|e| format!("Database error: {}", e))?;
+    fs::write(path, render_highlights_markdown(&highlights))
+        .map_err(|e| format!("Failed to write highlights file: {}", e))?;
+    Ok(highlights.len())
+}
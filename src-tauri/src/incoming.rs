@@ -0,0 +1,474 @@
+//! Review queue for changes other devices and collaborators make to notes.
+//!
+//! With `review_incoming_changes` turned on, a synced version of a note that
+//! would replace different local content is not applied. It is kept in
+//! `incoming_changes` together with the local version it would have replaced,
+//! until the user accepts it (the incoming version lands) or reverts it (the
+//! local version is saved again and wins on the next push). An accepted change
+//! can still be reverted later, which restores the version it replaced.
+//! New notes are applied directly; only edits and deletions are queued.
+//!
+//! The queue only covers versions pulled through note sync
+//! (`apply_sync_notes`). CRDT updates are not held back: editors open on a
+//! note merge the server's updates into their document as they arrive, before
+//! the backend sees them (`apply_crdt_update`), so with real-time sync the
+//! edits of collaborators on an open note land without review.
+//!
+//! Instead of picking one side, a merge UI can ask for a three-way diff of a
+//! queued change: its base is the last version of the note this device and the
+//! server agreed on, which is kept in `note_sync_bases` whenever a synced
+//! version is applied or a local edit is pushed, and recorded with the change
+//! when it is queued. Changes that don't conflict merge on their own; the
+//! merged result the user settles on is saved as a local edit and pushed.
+
+use beck_markdown::{three_way_diff, ThreeWayDiff};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::database::{
+    index_note_content, make_preview, note_row_to_note, now_rfc3339, save_note_in_tx, Database,
+    Note, NoteInput,
+};
+use crate::locks::NoteLocks;
+use crate::oplog::{OpEntity, OPLOG_ENTITY_IDS};
+use crate::settings::REVIEW_INCOMING_CHANGES;
+
+/// A queued incoming version of a note
+#[derive(Debug, Serialize, Clone)]
+pub struct IncomingChange {
+    pub id: String,
+    pub note_id: String,
+    /// "pending", "accepted", "merged" or "reverted"
+    pub status: String,
+    pub received_at: String,
+    pub resolved_at: Option<String>,
+    /// The local version when the change arrived
+    pub previous: Note,
+    pub incoming: Note,
+    /// The last version both sides had, if this device knew it
+    pub base: Option<Note>,
+}
+
+/// Three-way diff of a pending change against the current local note
+#[derive(Debug, Serialize, Clone)]
+pub struct IncomingChangeDiff {
+    pub change: IncomingChange,
+    pub local: Note,
+    pub title: TitleMerge,
+    pub content: ThreeWayDiff,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TitleMerge {
+    pub base: Option<String>,
+    pub local: String,
+    pub remote: String,
+    /// Unless both sides renamed the note differently
+    pub merged: Option<String>,
+}
+
+pub fn ensure_incoming_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS incoming_changes (
+            id TEXT PRIMARY KEY NOT NULL,
+            note_id TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            previous TEXT NOT NULL,
+            incoming TEXT NOT NULL,
+            received_at TEXT NOT NULL,
+            resolved_at TEXT,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_incoming_changes_note ON incoming_changes(note_id, status)",
+        [],
+    )?;
+
+    let mut stmt = conn.prepare("PRAGMA table_info(incoming_changes)")?;
+    let mut rows = stmt.query([])?;
+    let mut has_base = false;
+    while let Some(row) = rows.next()? {
+        let col_name: String = row.get(1)?;
+        if col_name == "base" {
+            has_base = true;
+            break;
+        }
+    }
+    if !has_base {
+        conn.execute("ALTER TABLE incoming_changes ADD COLUMN base TEXT", [])?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_sync_bases (
+            note_id TEXT PRIMARY KEY NOT NULL,
+            note TEXT NOT NULL,
+            recorded_at TEXT NOT NULL,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn to_json(note: &Note) -> SqliteResult<String> {
+    serde_json::to_string(note).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn from_json(column: usize, value: String) -> SqliteResult<Note> {
+    serde_json::from_str(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn row_to_incoming_change(row: &rusqlite::Row) -> SqliteResult<IncomingChange> {
+    Ok(IncomingChange {
+        id: row.get(0)?,
+        note_id: row.get(1)?,
+        status: row.get(2)?,
+        received_at: row.get(3)?,
+        resolved_at: row.get(4)?,
+        previous: from_json(5, row.get(5)?)?,
+        incoming: from_json(6, row.get(6)?)?,
+        base: row
+            .get::<_, Option<String>>(7)?
+            .map(|base| from_json(7, base))
+            .transpose()?,
+    })
+}
+
+const INCOMING_CHANGE_COLUMNS: &str =
+    "id, note_id, status, received_at, resolved_at, previous, incoming, base";
+
+/// Remember a version of a note as the one this device and the server agree
+/// on, the base of a later three-way diff
+pub(crate) fn record_sync_base(conn: &Connection, note: &Note) -> SqliteResult<()> {
+    conn.execute(
+        "INSERT INTO note_sync_bases (note_id, note, recorded_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(note_id) DO UPDATE SET
+            note = excluded.note,
+            recorded_at = excluded.recorded_at",
+        params![&note.id, to_json(note)?, now_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Record the current version of notes pushed with the ops after `after` up
+/// to `up_to` as their sync base. Notes edited again since aren't on the
+/// server as they are now, so they keep their base until the next push.
+pub(crate) fn record_pushed_bases(conn: &Connection, after: i64, up_to: i64) -> SqliteResult<()> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas
+         FROM notes
+         WHERE id IN ({})
+           AND id NOT IN (SELECT entity_id FROM oplog WHERE entity = ?1 AND op_id > ?3)",
+        OPLOG_ENTITY_IDS
+    ))?;
+    let notes = stmt
+        .query_map(
+            params![OpEntity::Note.as_str(), after, up_to],
+            note_row_to_note,
+        )?
+        .collect::<SqliteResult<Vec<Note>>>()?;
+    for note in &notes {
+        record_sync_base(conn, note)?;
+    }
+    Ok(())
+}
+
+/// Queue a synced note instead of applying it, if it would replace different
+/// local content. Returns whether it was queued. Called from
+/// `apply_sync_notes` when review is turned on.
+pub(crate) fn queue_incoming_change(conn: &Connection, note: &Note) -> SqliteResult<bool> {
+    let local = conn
+        .query_row(
+            "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas
+             FROM notes
+             WHERE id = ?1
+               AND id NOT IN (SELECT note_id FROM offline_notes WHERE evicted = 1)",
+            params![&note.id],
+            note_row_to_note,
+        )
+        .optional()?;
+    let Some(local) = local else {
+        return Ok(false);
+    };
+    if note.updated_at <= local.updated_at
+        || (note.title == local.title
+            && note.content == local.content
+            && note.is_deleted == local.is_deleted)
+    {
+        return Ok(false);
+    }
+
+    // A newer version replaces the one still waiting; the local version it
+    // would replace hasn't changed
+    let updated = conn.execute(
+        "UPDATE incoming_changes SET incoming = ?2, received_at = ?3
+         WHERE note_id = ?1 AND status = 'pending'",
+        params![&note.id, to_json(note)?, now_rfc3339()],
+    )?;
+    if updated == 0 {
+        conn.execute(
+            "INSERT INTO incoming_changes (id, note_id, previous, incoming, received_at, base)
+             VALUES (?1, ?2, ?3, ?4, ?5, (SELECT note FROM note_sync_bases WHERE note_id = ?2))",
+            params![
+                Uuid::new_v4().to_string(),
+                &note.id,
+                to_json(&local)?,
+                to_json(note)?,
+                now_rfc3339()
+            ],
+        )?;
+    }
+    Ok(true)
+}
+
+impl Database {
+    /// Whether synced edits wait in the review queue
+    pub fn review_incoming_enabled(&self) -> SqliteResult<bool> {
+        Ok(self
+            .get_setting(REVIEW_INCOMING_CHANGES)?
+            .is_some_and(|v| matches!(v.trim(), "1" | "true")))
+    }
+
+    /// Queued changes, newest first. Resolved ones are only included with
+    /// `include_resolved`.
+    pub fn list_incoming_changes(
+        &self,
+        include_resolved: bool,
+    ) -> SqliteResult<Vec<IncomingChange>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM incoming_changes
+             WHERE ?1 OR status = 'pending'
+             ORDER BY received_at DESC",
+            INCOMING_CHANGE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![include_resolved], row_to_incoming_change)?;
+        rows.collect()
+    }
+
+    fn get_incoming_change(&self, id: &str) -> SqliteResult<Option<IncomingChange>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM incoming_changes WHERE id = ?1",
+                INCOMING_CHANGE_COLUMNS
+            ),
+            params![id],
+            row_to_incoming_change,
+        )
+        .optional()
+    }
+
+    /// Write how a change was resolved and its new status in one
+    /// transaction. Fails without writing if the change no longer has the
+    /// status it was read with.
+    fn resolve_incoming_change<T>(
+        &self,
+        change: &IncomingChange,
+        status: &str,
+        write: impl FnOnce(&Connection) -> SqliteResult<T>,
+    ) -> Result<T, String> {
+        let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_err)?;
+        let value = write(&tx).map_err(db_err)?;
+        let updated = tx
+            .execute(
+                "UPDATE incoming_changes SET status = ?2, resolved_at = ?3
+                 WHERE id = ?1 AND status = ?4",
+                params![&change.id, status, now_rfc3339(), &change.status],
+            )
+            .map_err(db_err)?;
+        if updated == 0 {
+            return Err("The change was resolved in the meantime".to_string());
+        }
+        tx.commit().map_err(db_err)?;
+        Ok(value)
+    }
+}
+
+/// Write a synced version as if it had just been applied by sync: it keeps
+/// its timestamp and isn't journaled
+fn apply_incoming_note(conn: &Connection, note: &Note) -> SqliteResult<()> {
+    conn.execute(
+        "UPDATE notes SET
+            title = ?2,
+            content = ?3,
+            folder_id = (SELECT id FROM folders WHERE id = ?4 AND is_deleted = 0),
+            updated_at = ?5,
+            is_deleted = ?6,
+            is_canvas = ?7,
+            preview = ?8
+         WHERE id = ?1",
+        params![
+            &note.id,
+            &note.title,
+            &note.content,
+            &note.folder_id,
+            &note.updated_at,
+            note.is_deleted as i32,
+            note.is_canvas as i32,
+            make_preview(&note.content),
+        ],
+    )?;
+    index_note_content(conn, &note.id, &note.content, note.is_deleted)?;
+    record_sync_base(conn, note)
+}
+
+/// Save a version as a new local edit, so it is pushed and wins
+fn save_as_local_edit(conn: &Connection, note: &Note) -> SqliteResult<Note> {
+    save_note_in_tx(
+        conn,
+        NoteInput {
+            id: Some(note.id.clone()),
+            title: note.title.clone(),
+            content: note.content.clone(),
+            folder_id: note.folder_id.clone(),
+            updated_at: None,
+            is_deleted: note.is_deleted,
+            is_canvas: note.is_canvas,
+        },
+    )
+}
+
+fn pending_change(db: &Database, id: &str) -> Result<IncomingChange, String> {
+    let change = db
+        .get_incoming_change(id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Incoming change not found: {}", id))?;
+    if change.status != "pending" {
+        return Err(format!("The change was already {}", change.status));
+    }
+    Ok(change)
+}
+
+fn current_note(db: &Database, note_id: &str) -> Result<Note, String> {
+    db.get_note(note_id, true)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Note not found: {}", note_id))
+}
+
+/// Let a queued change land. If the note was edited locally after the change
+/// arrived, the incoming version is saved as a new local edit so it still
+/// reaches the server.
+pub fn accept(db: &Database, locks: &NoteLocks, id: &str) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let change = pending_change(db, id)?;
+    let local = current_note(db, &change.note_id)?;
+    locks.write_with_ids(
+        || db.save_note_ids(&change.note_id).map_err(db_err),
+        || {
+            db.resolve_incoming_change(&change, "accepted", |conn| {
+                if local.updated_at < change.incoming.updated_at {
+                    apply_incoming_note(conn, &change.incoming)
+                } else {
+                    save_as_local_edit(conn, &change.incoming).map(|_| ())
+                }
+            })
+        },
+    )?;
+    current_note(db, &change.note_id)
+}
+
+/// Undo a change: a pending one is dropped and the local version saved again
+/// so it overrides the incoming one on the server; an accepted or merged one
+/// restores the version it replaced.
+pub fn revert(db: &Database, locks: &NoteLocks, id: &str) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let change = db
+        .get_incoming_change(id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Incoming change not found: {}", id))?;
+    let restored = match change.status.as_str() {
+        "pending" => current_note(db, &change.note_id)?,
+        "accepted" | "merged" => change.previous.clone(),
+        status => return Err(format!("The change was already {}", status)),
+    };
+    locks.write_with_ids(
+        || db.save_note_ids(&change.note_id).map_err(db_err),
+        || {
+            db.resolve_incoming_change(&change, "reverted", |conn| {
+                save_as_local_edit(conn, &restored)
+            })
+        },
+    )
+}
+
+/// Pick the value both sides agree on, or the one that changed
+fn merge_value<T: PartialEq + Clone>(base: Option<&T>, local: &T, remote: &T) -> Option<T> {
+    if local == remote || base == Some(remote) {
+        Some(local.clone())
+    } else if base == Some(local) {
+        Some(remote.clone())
+    } else {
+        None
+    }
+}
+
+/// Three-way diff of a pending change: the base, the local note as it is now
+/// and the incoming version
+pub fn diff(db: &Database, id: &str) -> Result<IncomingChangeDiff, String> {
+    let change = pending_change(db, id)?;
+    let local = current_note(db, &change.note_id)?;
+    let base = change.base.as_ref();
+    let title = TitleMerge {
+        base: base.map(|base| base.title.clone()),
+        local: local.title.clone(),
+        remote: change.incoming.title.clone(),
+        merged: merge_value(
+            base.map(|base| &base.title),
+            &local.title,
+            &change.incoming.title,
+        ),
+    };
+    let content = three_way_diff(
+        base.map(|base| base.content.as_str()),
+        &local.content,
+        &change.incoming.content,
+    );
+    Ok(IncomingChangeDiff {
+        change,
+        local,
+        title,
+        content,
+    })
+}
+
+/// Resolve a pending change with merged content. The result is saved as a
+/// new local edit so it reaches the server, in the folder either side moved
+/// the note to (the local one if both did), and undeleted.
+pub fn merge(
+    db: &Database,
+    locks: &NoteLocks,
+    id: &str,
+    title: &str,
+    content: &str,
+) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let change = pending_change(db, id)?;
+    let local = current_note(db, &change.note_id)?;
+    let folder_id = merge_value(
+        change.base.as_ref().map(|base| &base.folder_id),
+        &local.folder_id,
+        &change.incoming.folder_id,
+    )
+    .unwrap_or_else(|| local.folder_id.clone());
+    let merged = Note {
+        title: title.to_string(),
+        content: content.to_string(),
+        folder_id,
+        is_deleted: false,
+        ..local
+    };
+    locks.write_with_ids(
+        || db.save_note_ids(&change.note_id).map_err(db_err),
+        || db.resolve_incoming_change(&change, "merged", |conn| save_as_local_edit(conn, &merged)),
+    )
+}
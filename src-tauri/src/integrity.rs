@@ -0,0 +1,358 @@
+//! Vault integrity check for broken note links and missing assets.
+//!
+//! Live notes are scanned for links to notes that no longer exist (or are in
+//! the trash) and for `asset://` images and attachment links whose file is
+//! missing from the assets folder. Optionally the problems are fixed in place,
+//! either by removing the offending element (a broken link keeps its text) or
+//! by flagging it with `data-broken="true"` for the editor to highlight.
+//! Links to trashed notes are only reported, since the note may be restored.
+
+use beck_markdown::html::{self, Element, Node};
+use rusqlite::{params, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use yrs::{TransactionMut, XmlFragmentRef};
+
+use crate::database::{assets, index_note_content, make_preview, now_rfc3339, Database};
+use crate::links::NOTE_LINK_PREFIX;
+use crate::locks::NoteLocks;
+use crate::oplog::{record_op, OpEntity, OpKind};
+use crate::ydoc::{self, edit_stored_doc, DocEdit};
+
+/// Attribute added to elements flagged by a fix
+const BROKEN_ATTR: &str = "data-broken";
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// Links to a note that does not exist on this device
+    MissingNote,
+    /// Links to a note that is in the trash
+    TrashedNote,
+    /// Embeds or links an asset whose file is missing
+    MissingAsset,
+}
+
+/// How `check_vault_integrity` should repair what it finds
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityFix {
+    /// Unwrap broken links to their text and drop missing images
+    Remove,
+    /// Mark broken elements with `data-broken="true"`
+    Flag,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct IntegrityIssue {
+    pub note_id: String,
+    pub note_title: String,
+    pub kind: IssueKind,
+    /// Missing note id or asset file name
+    pub target: String,
+    /// "a" or "img"
+    pub element: String,
+    /// Byte offset of the element in the note content before any fix
+    pub offset: usize,
+    /// Link text, or the image's alt text
+    pub text: String,
+    /// Whether the requested fix was applied to this element
+    pub fixed: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct IntegrityReport {
+    pub notes_scanned: usize,
+    pub issues: Vec<IntegrityIssue>,
+    /// Notes rewritten by the fix
+    pub fixed_notes: Vec<String>,
+}
+
+/// A checked element's location in note content
+struct Tag {
+    start: usize,
+    end: usize,
+    element: Element,
+}
+
+/// `<a>` and `<img>` start tags in note content, in document order
+fn scan_tags(content: &str) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    while let Some(i) = content[pos..].find('<') {
+        let start = pos + i;
+        let Some(len) = content[start..].find('>') else {
+            break;
+        };
+        let end = start + len + 1;
+        pos = end;
+
+        let raw = &content[start..end];
+        let name_end = raw[1..]
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .map_or(raw.len(), |n| n + 1);
+        if !matches!(raw[1..name_end].to_ascii_lowercase().as_str(), "a" | "img") {
+            continue;
+        }
+        if let Some(Node::Element(element)) = html::parse(raw).into_iter().next() {
+            tags.push(Tag {
+                start,
+                end,
+                element,
+            });
+        }
+    }
+    tags
+}
+
+/// File name of a local asset referenced by an `asset://` (or Windows
+/// `asset.localhost`) URI
+pub(crate) fn asset_file(uri: &str) -> Option<&str> {
+    if !(uri.starts_with("asset://") || uri.contains("asset.localhost/")) {
+        return None;
+    }
+    let (_, file) = uri.rsplit_once("/.assets/")?;
+    let file = file.split(['?', '#']).next()?;
+    (!file.is_empty() && !file.contains('/')).then_some(file)
+}
+
+/// Issue for one element, if it is broken: (kind, target)
+fn check_tag(
+    tag: &Tag,
+    notes: &HashMap<String, bool>,
+    asset_files: &HashSet<String>,
+) -> Option<(IssueKind, String)> {
+    let el = &tag.element;
+    let uri = match el.name.as_str() {
+        "a" => el.attr("href")?,
+        _ => el.attr("src")?,
+    };
+    if let Some(target) = uri.strip_prefix(NOTE_LINK_PREFIX) {
+        return match notes.get(target) {
+            None => Some((IssueKind::MissingNote, target.to_string())),
+            Some(true) => Some((IssueKind::TrashedNote, target.to_string())),
+            Some(false) => None,
+        };
+    }
+    let file = asset_file(uri)?;
+    (!asset_files.contains(file)).then(|| (IssueKind::MissingAsset, file.to_string()))
+}
+
+/// Apply `fix` to the elements of a note's CRDT document matching the given
+/// broken tags: links by href, images by src
+fn apply_doc_fix(
+    txn: &mut TransactionMut,
+    fragment: &XmlFragmentRef,
+    tags: &[&Tag],
+    fix: IntegrityFix,
+) -> bool {
+    let mut changed = false;
+    for tag in tags {
+        let el = &tag.element;
+        changed |= match (el.name.as_str(), fix) {
+            ("a", IntegrityFix::Remove) => el
+                .attr("href")
+                .is_some_and(|href| ydoc::unlink(txn, fragment, href)),
+            ("a", IntegrityFix::Flag) => el
+                .attr("href")
+                .is_some_and(|href| ydoc::set_link_attr(txn, fragment, href, BROKEN_ATTR, "true")),
+            (_, IntegrityFix::Remove) => el
+                .attr("src")
+                .is_some_and(|src| ydoc::remove_images(txn, fragment, src)),
+            (_, IntegrityFix::Flag) => el
+                .attr("src")
+                .is_some_and(|src| ydoc::set_image_attr(txn, fragment, src, BROKEN_ATTR, "true")),
+        };
+    }
+    changed
+}
+
+/// Apply `fix` to the given broken tags, working backwards so earlier
+/// offsets stay valid
+fn apply_fix(content: &str, tags: &[&Tag], fix: IntegrityFix) -> String {
+    let mut out = content.to_string();
+    for tag in tags.iter().rev() {
+        match fix {
+            IntegrityFix::Flag => {
+                if tag.element.has_attr(BROKEN_ATTR) {
+                    continue;
+                }
+                let raw = &content[tag.start..tag.end];
+                let at = if raw.ends_with("/>") {
+                    tag.end - 2
+                } else {
+                    tag.end - 1
+                };
+                out.insert_str(at, &format!(" {}=\"true\"", BROKEN_ATTR));
+            }
+            IntegrityFix::Remove => {
+                if tag.element.name == "a" {
+                    if let Some(close) = out[tag.end..].find("</a>") {
+                        let close = tag.end + close;
+                        out.replace_range(close..close + "</a>".len(), "");
+                    }
+                }
+                out.replace_range(tag.start..tag.end, "");
+            }
+        }
+    }
+    out
+}
+
+impl Database {
+    /// Every note id with whether it is in the trash
+    fn note_trash_states(&self) -> SqliteResult<HashMap<String, bool>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT id, is_deleted FROM notes")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// (id, title, content) of live, non-canvas notes
+    fn notes_for_integrity_check(&self) -> SqliteResult<Vec<(String, String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, title, content FROM notes
+             WHERE is_deleted = 0 AND is_canvas = 0
+             ORDER BY title ASC",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
+    }
+
+    /// Store repaired content, making the same repair to the note's CRDT
+    /// document (see `ydoc`), and bump the note so it syncs. Nothing is
+    /// written if the note changed since it was checked, or if its document
+    /// doesn't have the broken elements. Returns whether the note was rewritten.
+    fn save_repaired_content(
+        &self,
+        note_id: &str,
+        checked: &str,
+        content: &str,
+        repair: impl FnOnce(&mut TransactionMut, &XmlFragmentRef) -> bool,
+    ) -> SqliteResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT content FROM notes WHERE id = ?1 AND is_deleted = 0",
+                params![note_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if current.as_deref() != Some(checked) {
+            return Ok(false);
+        }
+
+        let now = now_rfc3339();
+        if edit_stored_doc(&tx, note_id, &now, repair)? == DocEdit::Unchanged {
+            return Ok(false);
+        }
+        tx.execute(
+            "UPDATE notes SET content = ?2, preview = ?3, updated_at = ?4 WHERE id = ?1",
+            params![note_id, content, make_preview(content), &now],
+        )?;
+        index_note_content(&tx, note_id, content, false)?;
+        record_op(&tx, OpEntity::Note, note_id, OpKind::Upsert)?;
+        tx.commit()?;
+        Ok(true)
+    }
+}
+
+/// File names in the assets folder
+fn asset_files(app_data_dir: &Path) -> Result<HashSet<String>, String> {
+    let assets_dir = assets::get_assets_dir(app_data_dir);
+    if !assets_dir.exists() {
+        return Ok(HashSet::new());
+    }
+    let entries =
+        fs::read_dir(&assets_dir).map_err(|e| format!("Failed to read assets directory: {}", e))?;
+    Ok(entries
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect())
+}
+
+/// Scan live notes for broken links and missing assets, applying `fix` if given.
+/// Notes being fixed are locked until they have been rewritten.
+pub fn check_vault_integrity(
+    db: &Database,
+    locks: &NoteLocks,
+    app_data_dir: &Path,
+    fix: Option<IntegrityFix>,
+) -> Result<IntegrityReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let notes = db.note_trash_states().map_err(db_err)?;
+    let asset_files = asset_files(app_data_dir)?;
+    let scanned = db.notes_for_integrity_check().map_err(db_err)?;
+
+    let mut issues = Vec::new();
+    // Per note: content and the tags a fix should touch
+    let mut to_fix: Vec<(String, String, Vec<Tag>)> = Vec::new();
+    for (note_id, note_title, content) in &scanned {
+        let mut fixable = Vec::new();
+        for tag in scan_tags(content) {
+            let Some((kind, target)) = check_tag(&tag, &notes, &asset_files) else {
+                continue;
+            };
+            let fixed = fix.is_some() && kind != IssueKind::TrashedNote;
+            let text = match tag.element.name.as_str() {
+                "img" => tag.element.attr("alt").unwrap_or_default().to_string(),
+                _ => {
+                    let close = content[tag.end..].find("</a>").map(|i| tag.end + i);
+                    close.map_or_else(String::new, |close| {
+                        beck_markdown::html_to_text(&content[tag.end..close])
+                    })
+                }
+            };
+            issues.push(IntegrityIssue {
+                note_id: note_id.clone(),
+                note_title: note_title.clone(),
+                kind,
+                target,
+                element: tag.element.name.clone(),
+                offset: tag.start,
+                text,
+                fixed,
+            });
+            if fixed {
+                fixable.push(tag);
+            }
+        }
+        if !fixable.is_empty() {
+            to_fix.push((note_id.clone(), content.clone(), fixable));
+        }
+    }
+
+    let mut fixed_notes = Vec::new();
+    if let Some(fix) = fix {
+        let _lock = locks.lock(to_fix.iter().map(|(id, _, _)| id.as_str()), "integrity fix")?;
+        for (note_id, content, tags) in &to_fix {
+            let tags: Vec<&Tag> = tags.iter().collect();
+            let repaired = apply_fix(content, &tags, fix);
+            if repaired != *content
+                && db
+                    .save_repaired_content(note_id, content, &repaired, |txn, fragment| {
+                        apply_doc_fix(txn, fragment, &tags, fix)
+                    })
+                    .map_err(db_err)?
+            {
+                fixed_notes.push(note_id.clone());
+            }
+        }
+        // Notes edited since the check, or out of step with their document,
+        // were left alone
+        for issue in &mut issues {
+            issue.fixed &= fixed_notes.contains(&issue.note_id);
+        }
+    }
+
+    Ok(IntegrityReport {
+        notes_scanned: scanned.len(),
+        issues,
+        fixed_notes,
+    })
+}
@@ -0,0 +1,294 @@
+//! Word-frequency index behind the topics view.
+//!
+//! Every saved note's text is split into words, which are lowercased, stripped
+//! of common English suffixes ("links", "linking" and "linked" all count as
+//! "link") and filtered against a stop-word list. The counts per note are kept
+//! in `note_terms`, so the most frequent terms across the vault can be summed
+//! for a tag cloud, and the terms that set one note apart from the rest of the
+//! vault (by tf-idf) can be suggested as its tags.
+
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::database::Database;
+
+/// Terms returned when no limit is given
+const DEFAULT_LIMIT: usize = 50;
+
+/// Words shorter or longer than this aren't indexed
+const MIN_WORD_LEN: usize = 3;
+const MAX_WORD_LEN: usize = 40;
+
+const STOP_WORDS: &str = "\
+    about above after again against all also and any are because been before \
+    being below between both but can could did does doing down during each \
+    even few for from further get got had has have having her here hers \
+    herself him himself his how into its itself just like more most much must \
+    myself nor not now off once one only other our ours ourselves out over own \
+    same she should some such than that the their theirs them themselves then \
+    there these they this those through too under until very was were what \
+    when where which while who whom why will with would yet you your yours \
+    yourself yourselves";
+
+/// A term counted across the vault
+#[derive(Debug, Serialize, Clone)]
+pub struct VaultKeyword {
+    /// Stemmed form the counts are grouped by
+    pub term: String,
+    /// The spelling used most often, for display
+    pub word: String,
+    /// Occurrences across all live notes
+    pub count: i64,
+    /// Notes containing the term
+    pub notes: i64,
+}
+
+/// A suggested tag for a note
+#[derive(Debug, Serialize, Clone)]
+pub struct KeywordSuggestion {
+    pub term: String,
+    pub word: String,
+    /// tf-idf weight; higher is more characteristic of the note
+    pub score: f64,
+}
+
+pub fn ensure_keywords_schema(conn: &Connection) -> SqliteResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'note_terms')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_terms (
+            note_id TEXT NOT NULL,
+            term TEXT NOT NULL,
+            word TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (note_id, term),
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_terms_term ON note_terms(term)",
+        [],
+    )?;
+
+    // Backfill existing notes the first time the index is created
+    if !exists {
+        let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE is_deleted = 0")?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        for (id, content) in notes {
+            index_note_terms(conn, &id, &content, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Strip common English inflections: plurals, -ing, -ed and -ly
+fn stem(word: &str) -> String {
+    let undouble = |stem: &str| {
+        let bytes = stem.as_bytes();
+        let n = bytes.len();
+        if n >= 2
+            && bytes[n - 1].is_ascii_alphabetic()
+            && bytes[n - 1] == bytes[n - 2]
+            && !b"lsz".contains(&bytes[n - 1])
+        {
+            stem[..n - 1].to_string()
+        } else {
+            stem.to_string()
+        }
+    };
+
+    if let Some(stem) = word.strip_suffix("ies").filter(|s| s.len() >= 2) {
+        return format!("{}y", stem);
+    }
+    if let Some(stem) = word.strip_suffix("sses") {
+        return format!("{}ss", stem);
+    }
+    if let Some(stem) = word.strip_suffix("ing").filter(|s| s.len() >= 4) {
+        return undouble(stem);
+    }
+    if let Some(stem) = word.strip_suffix("ed").filter(|s| s.len() >= 4) {
+        return undouble(stem);
+    }
+    if let Some(stem) = word.strip_suffix("ly").filter(|s| s.len() >= 4) {
+        return stem.to_string();
+    }
+    if word.len() > 3
+        && word.ends_with('s')
+        && !word.ends_with("ss")
+        && !word.ends_with("us")
+        && !word.ends_with("is")
+    {
+        return word[..word.len() - 1].to_string();
+    }
+    word.to_string()
+}
+
+/// Count the terms in note HTML: term -> (occurrences, most used spelling)
+fn count_terms(content: &str) -> HashMap<String, (i64, String)> {
+    let text = beck_markdown::html_to_text(content).to_lowercase();
+    let mut spellings: HashMap<String, HashMap<&str, i64>> = HashMap::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let len = word.chars().count();
+        if !(MIN_WORD_LEN..=MAX_WORD_LEN).contains(&len)
+            || word.chars().all(|c| c.is_ascii_digit())
+            || STOP_WORDS.split_whitespace().any(|stop| stop == word)
+        {
+            continue;
+        }
+        *spellings
+            .entry(stem(word))
+            .or_default()
+            .entry(word)
+            .or_default() += 1;
+    }
+
+    spellings
+        .into_iter()
+        .map(|(term, words)| {
+            let count = words.values().sum();
+            let word = most_used(words.into_iter().map(|(w, n)| (w.to_string(), n)));
+            (term, (count, word))
+        })
+        .collect()
+}
+
+/// The spelling with the highest count; ties go to the shortest, then
+/// alphabetical, so the choice is stable
+fn most_used(words: impl Iterator<Item = (String, i64)>) -> String {
+    words
+        .max_by(|(a, a_count), (b, b_count)| {
+            a_count
+                .cmp(b_count)
+                .then(b.len().cmp(&a.len()))
+                .then(b.cmp(a))
+        })
+        .map(|(word, _)| word)
+        .unwrap_or_default()
+}
+
+/// Replace the indexed terms for a note. Deleted notes are dropped from the index.
+pub(crate) fn index_note_terms(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+    is_deleted: bool,
+) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM note_terms WHERE note_id = ?1",
+        params![note_id],
+    )?;
+    if is_deleted {
+        return Ok(());
+    }
+
+    let mut stmt = conn
+        .prepare("INSERT INTO note_terms (note_id, term, word, count) VALUES (?1, ?2, ?3, ?4)")?;
+    for (term, (count, word)) in count_terms(content) {
+        stmt.execute(params![note_id, term, word, count])?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// The most frequent terms across live notes
+    pub fn get_vault_keywords(&self, limit: Option<usize>) -> SqliteResult<Vec<VaultKeyword>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT t.term, t.word, t.count
+             FROM note_terms t
+             JOIN notes n ON n.id = t.note_id
+             WHERE n.is_deleted = 0",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+
+        // term -> (count, notes, spelling -> count)
+        let mut totals: HashMap<String, (i64, i64, HashMap<String, i64>)> = HashMap::new();
+        for row in rows {
+            let (term, word, count) = row?;
+            let entry = totals.entry(term).or_default();
+            entry.0 += count;
+            entry.1 += 1;
+            *entry.2.entry(word).or_default() += count;
+        }
+
+        let mut keywords: Vec<VaultKeyword> = totals
+            .into_iter()
+            .map(|(term, (count, notes, words))| VaultKeyword {
+                term,
+                word: most_used(words.into_iter()),
+                count,
+                notes,
+            })
+            .collect();
+        keywords.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+        keywords.truncate(limit.unwrap_or(DEFAULT_LIMIT));
+        Ok(keywords)
+    }
+
+    /// Terms that are frequent in a note but rare in the rest of the vault,
+    /// best first
+    pub fn suggest_note_keywords(
+        &self,
+        note_id: &str,
+        limit: Option<usize>,
+    ) -> SqliteResult<Vec<KeywordSuggestion>> {
+        let conn = self.conn.lock().unwrap();
+        let total_notes: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM notes WHERE is_deleted = 0",
+            [],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT t.term, t.word, t.count,
+                    (SELECT COUNT(*) FROM note_terms o
+                     JOIN notes n ON n.id = o.note_id
+                     WHERE o.term = t.term AND n.is_deleted = 0)
+             FROM note_terms t
+             WHERE t.note_id = ?1",
+        )?;
+        let rows = stmt.query_map(params![note_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+
+        let mut suggestions = Vec::new();
+        for row in rows {
+            let (term, word, count, notes) = row?;
+            let idf = ((1 + total_notes) as f64 / (1 + notes) as f64).ln() + 1.0;
+            suggestions.push(KeywordSuggestion {
+                term,
+                word,
+                score: count as f64 * idf,
+            });
+        }
+        suggestions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.term.cmp(&b.term))
+        });
+        suggestions.truncate(limit.unwrap_or(DEFAULT_LIMIT));
+        Ok(suggestions)
+    }
+}
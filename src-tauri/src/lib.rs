@@ -1,5 +1,52 @@
+mod analytics;
+mod app_lock;
+mod asset_usage;
+mod attachments;
+mod backups;
+mod boards;
 mod commands;
+mod crash_reports;
+mod crdt_clients;
+mod data_dir;
+mod data_import;
 mod database;
+mod export;
+mod export_profiles;
+mod external_refs;
+mod flashcards;
+mod guest;
+mod highlights;
+mod incoming;
+mod keywords;
+mod integrity;
+mod links;
+mod locks;
+mod mirror;
+mod note_sync;
+mod offline;
+mod oplog;
+mod publish;
+mod recovery;
+mod reindex;
+mod relations;
+mod remote_cache;
+mod retention;
+mod review;
+mod scheduler;
+mod scripting;
+mod settings;
+mod snippets;
+mod sync_profiles;
+mod task_integrations;
+mod timestamp;
+mod template_library;
+mod templates;
+mod titles;
+mod trash;
+mod unfurl;
+mod updates;
+mod ws_hub;
+mod ydoc;
 
 use database::Database;
 use tauri::{Emitter, Manager};
@@ -7,22 +54,70 @@ use tauri::{Emitter, Manager};
 /// Initialize and run the Tauri application
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let guest_mode = guest::GuestMode::from_args().0;
+    let app_lock = app_lock::AppLock::default();
+    let setup_lock = app_lock.clone();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_fs::init())
-        .setup(|app| {
-            // Get the app data directory for database storage
-            let app_data_dir = app
-                .path()
-                .app_data_dir()
-                .expect("Failed to get app data directory");
+        .manage(app_lock.clone())
+        .setup(move |app| {
+            app.manage(guest::GuestMode(guest_mode));
+
+            // Get the data directory for database storage. If it can't be used,
+            // start without a database so the frontend can offer another location.
+            let app_data_dir = match data_dir::open(app.handle(), guest_mode) {
+                Ok(dir) => dir,
+                Err(err) => {
+                    eprintln!("Data directory unavailable: {}", err.message);
+                    app.manage(err);
+                    return Ok(());
+                }
+            };
+            app.manage(data_dir::DataDir(app_data_dir.clone()));
 
-            // Initialize the database
-            let db = Database::new(&app_data_dir).expect("Failed to initialize database");
+            // Keep a report of every panic from here on, whatever else fails
+            crash_reports::install_panic_hook(&app_data_dir);
+
+            // Initialize the database, or open it read-only in guest mode. If it
+            // can't be opened, start in safe mode with only the recovery commands
+            // working instead of panicking.
+            let opened = if guest_mode {
+                Database::open_read_only(&app_data_dir)
+            } else {
+                Database::new(&app_data_dir)
+            };
+            let db = match opened {
+                Ok(db) => db,
+                Err(err) => {
+                    eprintln!("Failed to open database, starting in safe mode: {}", err);
+                    app.manage(recovery::RecoveryState {
+                        error: err.to_string(),
+                    });
+                    return Ok(());
+                }
+            };
+
+            // Start locked if the vault has a passphrase
+            setup_lock.load(&db);
 
             // Store database as managed state
             app.manage(db);
+            app.manage(locks::NoteLocks::default());
+            app.manage(ws_hub::WsHub::default());
+            app.manage(scripting::ScriptHost::new(
+                app.handle().clone(),
+                scripting::get_scripts_dir(&app_data_dir),
+            ));
+
+            // Start periodic background jobs (review reminders, the Markdown mirror,
+            // offline pruning, scheduled scripts, ...). They write to the vault, so
+            // guest mode leaves them off.
+            if !guest_mode {
+                scheduler::start(app.handle().clone());
+            }
 
             // Enable asset protocol for serving local files
             #[cfg(debug_assertions)]
@@ -33,18 +128,23 @@ pub fn run() {
 
             Ok(())
         })
-        .on_window_event(|window, event| {
-            if let tauri::WindowEvent::DragDrop(drag_event) = event {
-                if let tauri::DragDropEvent::Drop { paths, .. } = drag_event {
-                    let paths: Vec<String> = paths
-                        .iter()
-                        .map(|p| p.to_string_lossy().to_string())
-                        .collect();
-                    let _ = window.emit("app://file-drop", paths);
+        .on_window_event(|window, event| match event {
+            tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) => {
+                let paths: Vec<String> = paths
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect();
+                let _ = window.emit("app://file-drop", paths);
+            }
+            // A closed window's note subscriptions go with it
+            tauri::WindowEvent::Destroyed => {
+                if let Some(hub) = window.try_state::<ws_hub::WsHub>() {
+                    hub.remove_window(window.app_handle(), window.label());
                 }
             }
+            _ => {}
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(app_lock::guard(app_lock, guest::guard(guest_mode, tauri::generate_handler![
             // Note commands
             commands::get_all_notes,
             commands::get_note,
@@ -54,6 +154,11 @@ pub fn run() {
             commands::move_note,
             commands::get_notes_updated_since,
             commands::apply_sync_notes,
+            commands::list_incoming_changes,
+            commands::accept_incoming_change,
+            commands::revert_incoming_change,
+            commands::get_incoming_change_diff,
+            commands::merge_incoming_change,
             // Folder commands
             commands::get_all_folders,
             commands::get_folder,
@@ -69,6 +174,12 @@ pub fn run() {
             commands::delete_asset,
             commands::list_assets,
             commands::get_assets_path,
+            commands::save_attachment_from_path,
+            commands::get_attachment,
+            commands::search_attachments,
+            commands::save_audio_bytes,
+            commands::list_audio_assets_for_note,
+            commands::get_asset_usage,
             // CRDT sync commands
             commands::save_crdt_state,
             commands::get_crdt_state,
@@ -77,7 +188,158 @@ pub fn run() {
             commands::delete_crdt_state,
             commands::get_crdt_states_updated_since,
             commands::apply_crdt_update,
-        ])
+            commands::get_crdt_client_id,
+            // Oplog commands
+            commands::get_pending_changes,
+            commands::mark_oplog_pushed,
+            commands::get_oplog_entries,
+            // Backup commands
+            commands::create_backup,
+            commands::list_backups,
+            // Mirror commands
+            commands::mirror_vault_now,
+            // Publish commands
+            commands::set_publish_target,
+            commands::remove_publish_target,
+            commands::list_publish_targets,
+            commands::list_publish_deliveries,
+            commands::publish_note_now,
+            commands::list_task_integrations,
+            commands::save_task_integration,
+            commands::remove_task_integration,
+            commands::push_note_tasks,
+            commands::list_exported_tasks,
+            commands::sync_task_completions,
+            // Offline commands
+            commands::pin_offline,
+            commands::unpin_offline,
+            commands::evict_offline,
+            commands::restore_offline,
+            commands::get_offline_status,
+            commands::prune_offline,
+            // Startup commands
+            commands::get_startup_error,
+            commands::get_data_dir,
+            commands::choose_data_dir,
+            // Recovery commands
+            commands::get_recovery_status,
+            commands::salvage_database,
+            commands::restore_latest_backup,
+            commands::start_fresh_database,
+            // Remote cache commands
+            commands::fetch_remote_asset,
+            commands::fetch_shared_note,
+            commands::clear_remote_cache,
+            // Template commands
+            commands::render_template,
+            commands::list_templates,
+            commands::save_template,
+            commands::delete_template,
+            commands::pull_workspace_templates,
+            // Scripting commands
+            commands::list_scripts,
+            commands::reload_scripts,
+            // Integrity commands
+            commands::check_vault_integrity,
+            commands::rebuild_indexes,
+            // Link preview commands
+            commands::unfurl_url,
+            // Update commands
+            commands::check_client_update,
+            // Board commands
+            commands::get_boards,
+            commands::get_board,
+            commands::save_board,
+            commands::create_board_from_folder,
+            commands::delete_board,
+            commands::save_board_column,
+            commands::delete_board_column,
+            commands::save_board_card,
+            commands::delete_board_card,
+            commands::get_boards_updated_since,
+            commands::apply_sync_boards,
+            // Relation commands
+            commands::add_note_relation,
+            commands::remove_note_relation,
+            commands::get_note_relations,
+            commands::get_relations_updated_since,
+            commands::apply_sync_relations,
+            // Review commands
+            commands::set_note_review_at,
+            commands::get_note_review_at,
+            commands::get_notes_due_for_review,
+            commands::get_stale_notes,
+            // Flashcard commands
+            commands::get_due_cards,
+            commands::get_note_cards,
+            commands::grade_card,
+            // Settings commands
+            commands::get_setting,
+            commands::set_setting,
+            commands::get_guest_mode,
+            // Crash report commands
+            commands::get_recent_crashes,
+            commands::set_crash_report_dsn,
+            // App lock commands
+            commands::get_app_lock_status,
+            commands::set_app_lock,
+            commands::lock_app,
+            commands::unlock_app,
+            // Export commands
+            commands::export_note,
+            commands::get_redaction_rules,
+            commands::set_redaction_rules,
+            commands::list_export_profiles,
+            commands::save_export_profile,
+            commands::delete_export_profile,
+            commands::run_export_profile,
+            commands::import_markdown_file,
+            commands::import_note,
+            commands::import_csv_file,
+            commands::import_ics_file,
+            commands::parse_tabular_clipboard,
+            // Code snippet commands
+            commands::search_code,
+            commands::export_code_snippets,
+            // Highlight commands
+            commands::list_highlights,
+            commands::export_highlights,
+            // Keyword commands
+            commands::get_vault_keywords,
+            commands::suggest_note_keywords,
+            // Sync profile commands
+            commands::list_sync_profiles,
+            commands::get_active_sync_profile,
+            commands::save_sync_profile,
+            commands::set_sync_profile_token,
+            commands::delete_sync_profile,
+            commands::switch_sync_profile,
+            commands::record_sync_pull,
+            // Per-note sync commands
+            commands::pause_sync_for_note,
+            commands::resume_sync_for_note,
+            commands::list_sync_paused_notes,
+            commands::force_push_note,
+            commands::force_pull_note,
+            // Shared WebSocket commands
+            commands::ws_connect,
+            commands::ws_disconnect,
+            commands::ws_status,
+            commands::ws_subscribe_note,
+            commands::ws_unsubscribe_note,
+            commands::ws_send_update,
+            commands::ws_send_canvas_presence,
+            // Trash commands
+            commands::purge_trash,
+            commands::search_purged_notes,
+            commands::restore_purged_note,
+            commands::get_retention_policy,
+            commands::pull_retention_policy,
+            // Analytics commands
+            commands::record_note_open,
+            commands::record_note_edit,
+            commands::get_activity_heatmap,
+        ])))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
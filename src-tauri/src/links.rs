@@ -0,0 +1,216 @@
+//! Index of links between notes.
+//!
+//! A note links to another with an anchor whose href uses the `beck://note/`
+//! scheme. Links are indexed on every save so a renamed note can find the
+//! notes that refer to it and keep their link text in step with its title.
+
+use beck_markdown::html::{self, Element};
+use rusqlite::{params, Connection, Result as SqliteResult};
+
+use crate::database::{index_note_content, make_preview, now_rfc3339, Database};
+use crate::oplog::{record_op, OpEntity, OpKind};
+use crate::ydoc::{self, edit_stored_doc, DocEdit};
+
+/// Href prefix of a link to another note
+pub const NOTE_LINK_PREFIX: &str = "beck://note/";
+
+pub fn ensure_links_schema(conn: &Connection) -> SqliteResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'note_links')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    // No FK on the target: links may point at notes not synced to this device yet
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_links (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_note_id TEXT NOT NULL,
+            target_note_id TEXT NOT NULL,
+            link_text TEXT NOT NULL,
+            FOREIGN KEY (source_note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_links_source ON note_links(source_note_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_links_target ON note_links(target_note_id)",
+        [],
+    )?;
+
+    // Backfill existing notes the first time the index is created
+    if !exists {
+        let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE is_deleted = 0")?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        for (id, content) in notes {
+            index_note_links(conn, &id, &content, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Note id targeted by a link element, if it is a note link
+fn link_target(el: &Element) -> Option<&str> {
+    el.attr("href")?
+        .strip_prefix(NOTE_LINK_PREFIX)
+        .filter(|id| !id.is_empty())
+}
+
+/// Extract (target note id, link text) pairs from note HTML in document order
+pub fn extract_note_links(content: &str) -> Vec<(String, String)> {
+    let nodes = html::parse(content);
+    let mut links = Vec::new();
+    html::walk(&nodes, &mut |el: &Element| {
+        if el.name == "a" {
+            if let Some(target) = link_target(el) {
+                links.push((target.to_string(), el.text()));
+            }
+        }
+    });
+    links
+}
+
+/// Replace the indexed links for a note. Deleted notes are dropped from the index.
+pub(crate) fn index_note_links(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+    is_deleted: bool,
+) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM note_links WHERE source_note_id = ?1",
+        params![note_id],
+    )?;
+    if is_deleted {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO note_links (source_note_id, target_note_id, link_text) VALUES (?1, ?2, ?3)",
+    )?;
+    for (target, text) in extract_note_links(content) {
+        stmt.execute(params![note_id, target, text])?;
+    }
+    Ok(())
+}
+
+/// Rewrite the text of links to `target_id` whose text is exactly `old_text`.
+///
+/// Links with custom display text, or with markup inside the anchor, are left
+/// alone. Returns `None` when nothing changed.
+pub fn rewrite_link_text(
+    content: &str,
+    target_id: &str,
+    old_text: &str,
+    new_text: &str,
+) -> Option<String> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    let mut changed = false;
+
+    while let Some(start) = rest.find("<a ") {
+        let Some(tag_len) = rest[start..].find('>').map(|i| i + 1) else {
+            break;
+        };
+        let open_end = start + tag_len;
+        let Some(close) = rest[open_end..].find("</a>").map(|i| open_end + i) else {
+            break;
+        };
+
+        let tag = &rest[start..open_end];
+        let inner = &rest[open_end..close];
+        let targets_note = html::parse(tag).iter().any(|node| {
+            matches!(node, html::Node::Element(el) if el.name == "a" && link_target(el) == Some(target_id))
+        });
+
+        out.push_str(&rest[..open_end]);
+        if targets_note && !inner.contains('<') && html::decode_entities(inner) == old_text {
+            out.push_str(&html::escape(new_text));
+            changed = true;
+        } else {
+            out.push_str(inner);
+        }
+        rest = &rest[close..];
+    }
+    out.push_str(rest);
+
+    changed.then_some(out)
+}
+
+/// Update the link text in every note linking to a renamed note.
+///
+/// The text is rewritten in both the HTML and the stored CRDT document (see
+/// `ydoc`), and rewritten notes are bumped so they sync. A note whose document
+/// doesn't have the link the HTML has is left alone, as the editor would
+/// restore the document over the HTML anyway.
+/// Callers lock the notes in [`Database::save_note_ids`] for the write.
+/// Returns the ids of the notes that were rewritten.
+pub(crate) fn update_links_for_rename(
+    conn: &Connection,
+    note_id: &str,
+    old_title: &str,
+    new_title: &str,
+) -> SqliteResult<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT n.id, n.content
+         FROM note_links l
+         JOIN notes n ON n.id = l.source_note_id
+         WHERE l.target_note_id = ?1 AND l.link_text = ?2 AND n.is_deleted = 0",
+    )?;
+    let sources = stmt
+        .query_map(params![note_id, old_title], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+
+    let now = now_rfc3339();
+    let href = format!("{}{}", NOTE_LINK_PREFIX, note_id);
+    let mut rewritten = Vec::new();
+    for (source_id, content) in sources {
+        let Some(content) = rewrite_link_text(&content, note_id, old_title, new_title) else {
+            continue;
+        };
+        let doc = edit_stored_doc(conn, &source_id, &now, |txn, fragment| {
+            ydoc::rewrite_link_text(txn, fragment, &href, old_title, new_title)
+        })?;
+        if doc == DocEdit::Unchanged {
+            continue;
+        }
+        conn.execute(
+            "UPDATE notes SET content = ?2, preview = ?3, updated_at = ?4 WHERE id = ?1",
+            params![&source_id, &content, make_preview(&content), &now],
+        )?;
+        index_note_content(conn, &source_id, &content, false)?;
+        record_op(conn, OpEntity::Note, &source_id, OpKind::Upsert)?;
+        rewritten.push(source_id);
+    }
+    Ok(rewritten)
+}
+
+impl Database {
+    /// Notes a save of a note writes, to lock for it: the note and the live
+    /// notes linking to it, whose link text follows a rename.
+    pub fn save_note_ids(&self, note_id: &str) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT l.source_note_id
+             FROM note_links l
+             JOIN notes n ON n.id = l.source_note_id
+             WHERE l.target_note_id = ?1 AND n.is_deleted = 0",
+        )?;
+        let mut ids = stmt
+            .query_map(params![note_id], |row| row.get(0))?
+            .collect::<SqliteResult<Vec<String>>>()?;
+        ids.push(note_id.to_string());
+        Ok(ids)
+    }
+}
@@ -0,0 +1,103 @@
+//! Advisory locks on notes held by long-running jobs.
+//!
+//! Export and import jobs lock the notes they read or write so that edits
+//! arriving while the job runs (from the note commands, sync, incoming change
+//! review, scripts and task integrations) are rejected with a clear error
+//! instead of racing with it. Those writes go through [`NoteLocks::write`]. Locks are released when the guard
+//! returned by [`NoteLocks::lock`] is dropped, including when the job fails.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Registry of locked note ids and the job holding each lock
+#[derive(Default)]
+pub struct NoteLocks {
+    locks: Mutex<HashMap<String, String>>,
+}
+
+/// Releases its notes when dropped
+pub struct NoteLockGuard<'a> {
+    registry: &'a NoteLocks,
+    ids: Vec<String>,
+}
+
+impl NoteLocks {
+    /// Lock a set of notes for `job`. Fails without locking anything if any
+    /// of the notes is already held by another job.
+    pub fn lock<I, S>(&self, ids: I, job: &str) -> Result<NoteLockGuard<'_>, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut ids: Vec<String> = ids.into_iter().map(Into::into).collect();
+        ids.sort();
+        ids.dedup();
+
+        let mut locks = self.locks.lock().unwrap();
+        if let Some((id, holder)) = ids
+            .iter()
+            .find_map(|id| locks.get(id).map(|holder| (id, holder)))
+        {
+            return Err(format!(
+                "Note {} is locked by a running {}; try again when it finishes",
+                id, holder
+            ));
+        }
+        for id in &ids {
+            locks.insert(id.clone(), job.to_string());
+        }
+
+        Ok(NoteLockGuard {
+            registry: self,
+            ids,
+        })
+    }
+
+    /// Run a write to notes unless one of them is locked by a job. The
+    /// registry stays locked while the write runs, so a job can't lock the
+    /// notes between the check and the write.
+    pub fn write<I, S, T, E>(&self, ids: I, write: impl FnOnce() -> Result<T, E>) -> Result<T, E>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+        E: From<String>,
+    {
+        self.write_with_ids(|| Ok(ids), write)
+    }
+
+    /// [`NoteLocks::write`] for writes whose notes have to be looked up, like a
+    /// rename that rewrites the notes linking to it. `ids` runs with the
+    /// registry locked too, so the notes it finds can't be locked before the
+    /// write.
+    pub fn write_with_ids<I, S, T, E>(
+        &self,
+        ids: impl FnOnce() -> Result<I, E>,
+        write: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+        E: From<String>,
+    {
+        let locks = self.locks.lock().unwrap();
+        for id in ids()? {
+            if let Some(holder) = locks.get(id.as_ref()) {
+                return Err(format!(
+                    "Note is locked by a running {}; try again when it finishes",
+                    holder
+                )
+                .into());
+            }
+        }
+        write()
+    }
+}
+
+impl Drop for NoteLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.registry.locks.lock().unwrap();
+        for id in &self.ids {
+            locks.remove(id);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Plain Markdown mirror of the vault.
+//!
+//! When the `mirror_dir` setting names a directory, the scheduler writes every
+//! live note there as `<folder path>/<title>.md` once per `mirror_interval_minutes`
+//! (daily by default). `mirror_files` remembers which file each note was
+//! written to and at which `updated_at`, so a run only rewrites notes that
+//! changed, renames files of moved or retitled notes and removes files of
+//! deleted ones. Files the mirror did not write are never touched.
+
+use beck_markdown::export_file_name;
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::{now_rfc3339, Database, Folder};
+use crate::export::{self, ExportFormat};
+use crate::settings::{MIRROR_DIR, MIRROR_INTERVAL_MINUTES};
+
+/// Settings key holding when the mirror last ran
+const LAST_RUN_KEY: &str = "mirror_last_run";
+
+/// Settings key holding the directory `mirror_files` refers to
+const MIRRORED_DIR_KEY: &str = "mirror_mirrored_dir";
+
+/// Run interval when `mirror_interval_minutes` is not set
+const DEFAULT_INTERVAL_MINUTES: i64 = 24 * 60;
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MirrorReport {
+    pub dir: String,
+    pub written: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+pub fn ensure_mirror_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mirror_files (
+            note_id TEXT PRIMARY KEY NOT NULL,
+            path TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// A live note as the mirror sees it, without its content
+struct MirrorNote {
+    id: String,
+    title: String,
+    folder_id: Option<String>,
+    updated_at: String,
+}
+
+impl Database {
+    fn notes_for_mirror(&self) -> SqliteResult<Vec<MirrorNote>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, title, folder_id, updated_at FROM notes
+             WHERE is_deleted = 0 AND is_canvas = 0
+               AND id NOT IN (SELECT note_id FROM offline_notes WHERE evicted = 1)
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(MirrorNote {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                folder_id: row.get(2)?,
+                updated_at: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// note id -> (relative path, updated_at) of the files written so far
+    fn mirror_files(&self) -> SqliteResult<HashMap<String, (String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT note_id, path, updated_at FROM mirror_files")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+        rows.collect()
+    }
+
+    fn put_mirror_file(&self, note_id: &str, path: &str, updated_at: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO mirror_files (note_id, path, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(note_id) DO UPDATE SET
+                path = excluded.path,
+                updated_at = excluded.updated_at",
+            params![note_id, path, updated_at],
+        )?;
+        Ok(())
+    }
+
+    fn delete_mirror_file(&self, note_id: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM mirror_files WHERE note_id = ?1",
+            params![note_id],
+        )?;
+        Ok(())
+    }
+
+    fn clear_mirror_files(&self) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM mirror_files", [])?;
+        Ok(())
+    }
+}
+
+/// Relative directory of a folder, following parents up to the root
+pub(crate) fn folder_path(folders: &HashMap<&str, &Folder>, folder_id: Option<&str>) -> PathBuf {
+    let mut names = Vec::new();
+    let mut current = folder_id;
+    while let Some(folder) = current.and_then(|id| folders.get(id)) {
+        // Guard against a parent cycle in corrupted data
+        if names.len() > folders.len() {
+            break;
+        }
+        names.push(export_file_name(&folder.name));
+        current = folder.parent_id.as_deref();
+    }
+    names.iter().rev().collect()
+}
+
+/// Remove a mirrored file and any folders it leaves empty
+fn remove_mirrored(dir: &Path, relative: &str) -> Result<(), String> {
+    let path = dir.join(relative);
+    if path.exists() {
+        fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+    let mut parent = path.parent();
+    while let Some(folder) = parent.filter(|p| *p != dir && p.starts_with(dir)) {
+        // Fails (and stops) at the first folder that still has files
+        if fs::remove_dir(folder).is_err() {
+            break;
+        }
+        parent = folder.parent();
+    }
+    Ok(())
+}
+
+/// Bring the mirror in `dir` up to date with the vault
+pub fn mirror_vault(db: &Database, dir: &Path) -> Result<MirrorReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create mirror directory {}: {}", dir.display(), e))?;
+
+    // The records describe another directory: write everything afresh
+    let dir_key = dir.to_string_lossy().to_string();
+    if db.get_setting(MIRRORED_DIR_KEY).map_err(db_err)?.as_deref() != Some(dir_key.as_str()) {
+        db.clear_mirror_files().map_err(db_err)?;
+        db.set_setting(MIRRORED_DIR_KEY, Some(&dir_key))
+            .map_err(db_err)?;
+    }
+
+    let folders = db.get_all_folders().map_err(db_err)?;
+    let folders: HashMap<&str, &Folder> = folders.iter().map(|f| (f.id.as_str(), f)).collect();
+    let recorded = db.mirror_files().map_err(db_err)?;
+
+    // Pick each note's file first; lowercased, since the mirror may live on a
+    // case-insensitive file system
+    let mut used: HashSet<String> = HashSet::new();
+    let mut plan = Vec::new();
+    for note in db.notes_for_mirror().map_err(db_err)? {
+        let folder = folder_path(&folders, note.folder_id.as_deref());
+        let base = export_file_name(&note.title);
+        let mut relative = folder.join(format!("{}.md", base));
+        let mut n = 2;
+        while !used.insert(relative.to_string_lossy().to_lowercase()) {
+            relative = folder.join(format!("{} ({}).md", base, n));
+            n += 1;
+        }
+        plan.push((note, relative.to_string_lossy().to_string()));
+    }
+
+    let mut report = MirrorReport {
+        dir: dir_key,
+        ..Default::default()
+    };
+
+    // Remove files that moved or whose note is gone, unless another note
+    // takes the path over in this run
+    let current: HashMap<&str, &str> = plan
+        .iter()
+        .map(|(note, relative)| (note.id.as_str(), relative.as_str()))
+        .collect();
+    for (note_id, (path, _)) in &recorded {
+        let target = current.get(note_id.as_str());
+        if target != Some(&path.as_str()) && !used.contains(&path.to_lowercase()) {
+            remove_mirrored(dir, path)?;
+        }
+        if target.is_none() {
+            db.delete_mirror_file(note_id).map_err(db_err)?;
+            report.removed += 1;
+        }
+    }
+
+    for (note, relative) in &plan {
+        let path = dir.join(relative);
+        if let Some((recorded_path, updated_at)) = recorded.get(&note.id) {
+            if recorded_path == relative && *updated_at == note.updated_at && path.exists() {
+                report.unchanged += 1;
+                continue;
+            }
+        }
+
+        // The note may have been deleted since the list was read
+        let Some(full) = db.get_note_by_id(&note.id).map_err(db_err)? else {
+            continue;
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, export::render_note(&full, ExportFormat::Markdown))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        db.put_mirror_file(&note.id, relative, &note.updated_at)
+            .map_err(db_err)?;
+        report.written += 1;
+    }
+
+    db.set_setting(LAST_RUN_KEY, Some(&now_rfc3339()))
+        .map_err(db_err)?;
+    Ok(report)
+}
+
+/// Configured mirror directory, if the mirror is turned on
+pub fn mirror_dir(db: &Database) -> SqliteResult<Option<PathBuf>> {
+    Ok(db
+        .get_setting(MIRROR_DIR)?
+        .map(|dir| dir.trim().to_string())
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from))
+}
+
+/// Run the mirror if it is turned on and its interval has passed since the
+/// last run. Returns `None` when nothing was due.
+pub fn mirror_if_due(db: &Database) -> Result<Option<MirrorReport>, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let Some(dir) = mirror_dir(db).map_err(db_err)? else {
+        return Ok(None);
+    };
+    let interval = db
+        .get_setting_i64(MIRROR_INTERVAL_MINUTES)
+        .map_err(db_err)?
+        .unwrap_or(DEFAULT_INTERVAL_MINUTES)
+        .max(1);
+    let last_run = db.get_setting(LAST_RUN_KEY).map_err(db_err)?;
+    let due = last_run
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .is_none_or(|at| {
+            chrono::Utc::now().signed_duration_since(at) >= chrono::Duration::minutes(interval)
+        });
+    if !due {
+        return Ok(None);
+    }
+    mirror_vault(db, &dir).map(Some)
+}
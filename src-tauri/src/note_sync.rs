@@ -0,0 +1,252 @@
+//! Per-note overrides of the sync engine, for debugging a conflicted or
+//! corrupted note without touching the rest of the vault.
+//!
+//! A paused note is left out of pushes and pulls: `get_pending_changes` skips
+//! it and `apply_sync_notes` ignores remote versions of it. Local edits are still journaled; resuming journals the note
+//! again so its current version is pushed even if the push cursor moved past
+//! those edits. Remote edits skipped while paused are not fetched again;
+//! `force_pull` takes the server's version explicitly. Live CRDT updates
+//! exchanged while the note is open in an editor are not paused.
+//!
+//! `force_push` and `force_pull` talk to the active sync profile's server
+//! directly, whether or not the note is paused. A forced push saves the local
+//! version with the server's default `stamp` mode, so it replaces whatever the
+//! server has. A forced pull overwrites the local note and its CRDT state with
+//! the server's, bypassing last-writer-wins and incoming review; it is not
+//! journaled, so it is not pushed back.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::database::{index_note_content, make_preview, Database, Note};
+use crate::incoming::record_sync_base;
+use crate::locks::NoteLocks;
+use crate::offline::{self, RemoteCrdtState};
+use crate::oplog::{record_op, OpEntity, OpKind};
+use crate::sync_profiles;
+use crate::timestamp::Timestamp;
+
+/// Condition on `notes.id` excluding paused notes
+pub(crate) const NOT_PAUSED: &str = "id NOT IN (SELECT note_id FROM sync_paused_notes)";
+
+/// Timeout for forced pushes
+const PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PausedNote {
+    pub note_id: String,
+    pub title: Option<String>,
+    pub paused_at: String,
+}
+
+/// Body of `POST /api/notes`
+#[derive(Debug, Serialize)]
+struct RemoteNoteInput<'a> {
+    id: &'a str,
+    title: &'a str,
+    content: &'a str,
+    folder_id: Option<&'a str>,
+    is_deleted: bool,
+    is_canvas: bool,
+}
+
+pub fn ensure_note_sync_schema(conn: &Connection) -> SqliteResult<()> {
+    // No foreign key: pausing outlives a purge so a re-synced copy stays paused
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_paused_notes (
+            note_id TEXT PRIMARY KEY,
+            paused_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn is_sync_paused(conn: &Connection, note_id: &str) -> SqliteResult<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sync_paused_notes WHERE note_id = ?1)",
+        params![note_id],
+        |row| row.get(0),
+    )
+}
+
+impl Database {
+    /// Stop syncing a note; pausing a paused note keeps its original time
+    pub fn pause_note_sync(&self, note_id: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO sync_paused_notes (note_id, paused_at) VALUES (?1, ?2)
+             ON CONFLICT(note_id) DO NOTHING",
+            params![note_id, Timestamp::now()],
+        )?;
+        Ok(())
+    }
+
+    /// Sync a paused note again. Returns false if it wasn't paused.
+    pub fn resume_note_sync(&self, note_id: &str) -> SqliteResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let resumed = tx.execute(
+            "DELETE FROM sync_paused_notes WHERE note_id = ?1",
+            params![note_id],
+        )? > 0;
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM notes WHERE id = ?1)",
+            params![note_id],
+            |row| row.get(0),
+        )?;
+        if resumed && exists {
+            record_op(&tx, OpEntity::Note, note_id, OpKind::Upsert)?;
+        }
+        tx.commit()?;
+        Ok(resumed)
+    }
+
+    pub fn list_paused_notes(&self) -> SqliteResult<Vec<PausedNote>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT p.note_id, n.title, p.paused_at
+             FROM sync_paused_notes p
+             LEFT JOIN notes n ON n.id = p.note_id
+             ORDER BY p.paused_at DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PausedNote {
+                note_id: row.get(0)?,
+                title: row.get(1)?,
+                paused_at: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Adopt the server's timestamp for a note just pushed, without journaling
+    fn set_pushed_version(&self, note_id: &str, updated_at: &Timestamp) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE notes SET updated_at = ?2 WHERE id = ?1",
+            params![note_id, updated_at],
+        )?;
+        Ok(())
+    }
+
+    /// Replace the local note with the server's, without journaling
+    fn overwrite_with_remote(&self, note: &Note) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let folder_id: Option<String> = match &note.folder_id {
+            Some(fid) => tx
+                .query_row(
+                    "SELECT id FROM folders WHERE id = ?1 AND is_deleted = 0",
+                    params![fid],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            None => None,
+        };
+        tx.execute(
+            "INSERT INTO notes (id, title, content, folder_id, updated_at, is_deleted, is_canvas, preview)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                title = excluded.title,
+                content = excluded.content,
+                folder_id = excluded.folder_id,
+                updated_at = excluded.updated_at,
+                is_deleted = excluded.is_deleted,
+                is_canvas = excluded.is_canvas,
+                preview = excluded.preview",
+            params![
+                note.id,
+                note.title,
+                note.content,
+                folder_id,
+                note.updated_at,
+                note.is_deleted as i32,
+                note.is_canvas as i32,
+                make_preview(&note.content),
+            ],
+        )?;
+        index_note_content(&tx, &note.id, &note.content, note.is_deleted)?;
+        record_sync_base(&tx, note)?;
+        tx.commit()
+    }
+}
+
+/// Save the local version of a note on the server, replacing the server's
+pub async fn force_push(db: &Database, note_id: &str) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let note = db
+        .get_note_by_id(note_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Note not found: {}", note_id))?;
+    if db.offline_note(note_id).map_err(db_err)?.evicted {
+        return Err("The note's content isn't on this device; fetch it first".to_string());
+    }
+    let (base, active) = sync_profiles::active_server(db)?;
+
+    let url = format!("{}/api/notes", base);
+    let client = reqwest::Client::builder()
+        .timeout(PUSH_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let body = serde_json::to_vec(&RemoteNoteInput {
+        id: &note.id,
+        title: &note.title,
+        content: &note.content,
+        folder_id: note.folder_id.as_deref(),
+        is_deleted: note.is_deleted,
+        is_canvas: note.is_canvas,
+    })
+    .map_err(|e| format!("Failed to encode note: {}", e))?;
+    let mut request = client
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    if let Some(token) = &active.token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server returned {} for {}", response.status(), url));
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response from {}: {}", url, e))?;
+    let saved: Note = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("Invalid response from {}: {}", url, e))?;
+
+    db.set_pushed_version(note_id, &saved.updated_at)
+        .map_err(db_err)?;
+    db.get_note_by_id(note_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Note not found: {}", note_id))
+}
+
+/// Replace the local note and its CRDT state with the server's version
+pub async fn force_pull(db: &Database, locks: &NoteLocks, note_id: &str) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let (base, active) = sync_profiles::active_server(db)?;
+    let token = active.token.as_deref();
+    let remote: Note = offline::get_json(
+        &format!("{}/api/notes/{}?include_deleted=true", base, note_id),
+        token,
+    )
+    .await?;
+    let crdt: Option<RemoteCrdtState> =
+        offline::get_json(&format!("{}/api/crdt/{}", base, note_id), token).await?;
+
+    locks.write([note_id], || {
+        db.overwrite_with_remote(&remote).map_err(db_err)
+    })?;
+    if let Some(crdt) = crdt {
+        offline::save_remote_crdt(db, locks, note_id, &crdt)?;
+    }
+    db.get_note_by_id(note_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Note not found: {}", note_id))
+}
@@ -0,0 +1,333 @@
+//! Local storage budget for devices that shouldn't carry the whole vault.
+//!
+//! An evicted note keeps its row, title and preview so it still shows up in
+//! lists and search results, but its content and CRDT state are dropped
+//! locally and fetched again from the sync server when it is opened. Pinned
+//! notes are never evicted. Only notes whose changes have all been pushed can
+//! be evicted, so nothing unsynced is ever thrown away.
+//!
+//! With a budget in `offline_budget_bytes` (on by default in mobile builds),
+//! the scheduler evicts the least recently changed unpinned notes until the
+//! stored content fits. Any new content for a note, from an edit or a sync,
+//! makes it local again.
+
+use base64::Engine;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{CrdtStateInput, Database, Note};
+use crate::locks::NoteLocks;
+use crate::oplog::OpEntity;
+use crate::settings::OFFLINE_BUDGET_BYTES;
+
+/// Budget when `offline_budget_bytes` is not set: phones keep 200 MB of
+/// note content, desktops keep everything
+#[cfg(mobile)]
+const DEFAULT_BUDGET_BYTES: Option<i64> = Some(200 * 1024 * 1024);
+#[cfg(not(mobile))]
+const DEFAULT_BUDGET_BYTES: Option<i64> = None;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OfflineNote {
+    pub note_id: String,
+    pub pinned: bool,
+    pub evicted: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OfflineSummary {
+    pub budget_bytes: Option<i64>,
+    /// Content and CRDT bytes of notes stored locally
+    pub used_bytes: i64,
+    pub pinned: Vec<String>,
+    pub evicted: Vec<String>,
+}
+
+/// CRDT state as returned by the server's `/api/crdt/:note_id`
+#[derive(Debug, Deserialize)]
+pub(crate) struct RemoteCrdtState {
+    ydoc_state: String,
+    state_vector: String,
+}
+
+pub fn ensure_offline_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS offline_notes (
+            note_id TEXT PRIMARY KEY NOT NULL,
+            pinned INTEGER NOT NULL DEFAULT 0,
+            evicted INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// The note has content again; called whenever note content is written
+pub(crate) fn mark_note_present(conn: &Connection, note_id: &str) -> SqliteResult<()> {
+    conn.execute(
+        "UPDATE offline_notes SET evicted = 0 WHERE note_id = ?1 AND evicted = 1",
+        params![note_id],
+    )?;
+    Ok(())
+}
+
+impl Database {
+    pub(crate) fn offline_note(&self, note_id: &str) -> SqliteResult<OfflineNote> {
+        let conn = self.conn.lock().unwrap();
+        let state: Option<(bool, bool)> = conn
+            .query_row(
+                "SELECT pinned, evicted FROM offline_notes WHERE note_id = ?1",
+                params![note_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let (pinned, evicted) = state.unwrap_or_default();
+        Ok(OfflineNote {
+            note_id: note_id.to_string(),
+            pinned,
+            evicted,
+        })
+    }
+
+    fn set_offline_pinned(&self, note_id: &str, pinned: bool) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO offline_notes (note_id, pinned) VALUES (?1, ?2)
+             ON CONFLICT(note_id) DO UPDATE SET pinned = excluded.pinned",
+            params![note_id, pinned],
+        )?;
+        Ok(())
+    }
+
+    /// Drop a note's content and CRDT state locally. Its updated_at is left
+    /// alone and no op is journaled, so the eviction never syncs.
+    fn evict_note_content(&self, note_id: &str) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE notes SET content = '' WHERE id = ?1",
+            params![note_id],
+        )?;
+        tx.execute(
+            "DELETE FROM crdt_states WHERE note_id = ?1",
+            params![note_id],
+        )?;
+        tx.execute(
+            "INSERT INTO offline_notes (note_id, evicted) VALUES (?1, 1)
+             ON CONFLICT(note_id) DO UPDATE SET evicted = 1",
+            params![note_id],
+        )?;
+        tx.commit()
+    }
+
+    /// Put fetched content back without touching the note's metadata
+    fn restore_note_content(&self, note_id: &str, content: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE notes SET content = ?2 WHERE id = ?1",
+            params![note_id, content],
+        )?;
+        mark_note_present(&conn, note_id)
+    }
+
+    /// Bytes of content and CRDT state stored for notes that aren't evicted
+    fn offline_used_bytes(&self) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COALESCE(SUM(LENGTH(CAST(n.content AS BLOB)) + COALESCE(LENGTH(c.ydoc_state), 0)), 0)
+             FROM notes n
+             LEFT JOIN crdt_states c ON c.note_id = n.id",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Unpinned, stored notes with no unpushed changes, least recently
+    /// changed first, with the bytes evicting each would free
+    fn eviction_candidates(&self) -> SqliteResult<Vec<(String, i64)>> {
+        let pending = self.pending_entity_ids(OpEntity::Note)?;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT n.id, LENGTH(CAST(n.content AS BLOB)) + COALESCE(LENGTH(c.ydoc_state), 0)
+             FROM notes n
+             LEFT JOIN crdt_states c ON c.note_id = n.id
+             LEFT JOIN offline_notes o ON o.note_id = n.id
+             WHERE COALESCE(o.pinned, 0) = 0 AND COALESCE(o.evicted, 0) = 0
+               AND n.is_deleted = 0 AND n.content != ''
+             ORDER BY n.updated_at ASC",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows
+            .collect::<SqliteResult<Vec<(String, i64)>>>()?
+            .into_iter()
+            .filter(|(id, _)| !pending.contains(id))
+            .collect())
+    }
+
+    fn offline_note_ids(&self, column: &str) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT note_id FROM offline_notes WHERE {} = 1 ORDER BY note_id",
+            column
+        ))?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    fn offline_budget(&self) -> SqliteResult<Option<i64>> {
+        Ok(self
+            .get_setting_i64(OFFLINE_BUDGET_BYTES)?
+            .or(DEFAULT_BUDGET_BYTES)
+            .filter(|budget| *budget > 0))
+    }
+}
+
+/// Evict one note. Fails for pinned notes and notes with unpushed changes.
+pub fn evict(db: &Database, note_id: &str) -> Result<OfflineNote, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let note = db
+        .get_note_by_id(note_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Note not found: {}", note_id))?;
+    if db.offline_note(note_id).map_err(db_err)?.pinned {
+        return Err("The note is pinned for offline use; unpin it first".to_string());
+    }
+    if db
+        .pending_entity_ids(OpEntity::Note)
+        .map_err(db_err)?
+        .contains(&note.id)
+    {
+        return Err("The note has changes that haven't been synced yet".to_string());
+    }
+    db.evict_note_content(note_id).map_err(db_err)?;
+    db.offline_note(note_id).map_err(db_err)
+}
+
+pub(crate) async fn get_json<T: serde::de::DeserializeOwned>(
+    url: &str,
+    token: Option<&str>,
+) -> Result<T, String> {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server returned {} for {}", response.status(), url));
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response from {}: {}", url, e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid response from {}: {}", url, e))
+}
+
+/// Store a CRDT state fetched from the server as the note's local state
+pub(crate) fn save_remote_crdt(
+    db: &Database,
+    locks: &NoteLocks,
+    note_id: &str,
+    crdt: &RemoteCrdtState,
+) -> Result<(), String> {
+    let decode = |value: &str| {
+        base64::engine::general_purpose::STANDARD
+            .decode(value)
+            .map_err(|e| format!("Invalid CRDT state from server: {}", e))
+    };
+    let input = CrdtStateInput {
+        note_id: note_id.to_string(),
+        ydoc_state: decode(&crdt.ydoc_state)?,
+        state_vector: decode(&crdt.state_vector)?,
+    };
+    locks.write([note_id], || {
+        db.save_crdt_state(input)
+            .map_err(|e| format!("Database error: {}", e))
+    })?;
+    Ok(())
+}
+
+/// Fetch an evicted note's content and CRDT state from the server
+pub async fn restore(
+    db: &Database,
+    locks: &NoteLocks,
+    server_url: &str,
+    token: Option<&str>,
+    note_id: &str,
+) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let base = server_url.trim().trim_end_matches('/');
+    let remote: Note = get_json(&format!("{}/api/notes/{}", base, note_id), token).await?;
+    let crdt: Option<RemoteCrdtState> =
+        get_json(&format!("{}/api/crdt/{}", base, note_id), token).await?;
+
+    // A newer server version is applied like any synced note; otherwise only
+    // the content comes back
+    locks.write([note_id], || {
+        db.apply_sync_notes(vec![remote.clone()]).map_err(db_err)?;
+        if db.offline_note(note_id).map_err(db_err)?.evicted {
+            db.restore_note_content(note_id, &remote.content)
+                .map_err(db_err)?;
+        }
+        Ok::<_, String>(())
+    })?;
+    if let Some(crdt) = crdt {
+        save_remote_crdt(db, locks, note_id, &crdt)?;
+    }
+
+    db.get_note_by_id(note_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Note not found: {}", note_id))
+}
+
+/// Pin or unpin a note. Pinning an evicted note fetches it again, which needs
+/// the server.
+pub async fn set_pinned(
+    db: &Database,
+    locks: &NoteLocks,
+    note_id: &str,
+    pinned: bool,
+    server: Option<(&str, Option<&str>)>,
+) -> Result<OfflineNote, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if pinned && db.offline_note(note_id).map_err(db_err)?.evicted {
+        let (server_url, token) = server
+            .ok_or_else(|| "The note was evicted; a server is needed to pin it".to_string())?;
+        restore(db, locks, server_url, token, note_id).await?;
+    }
+    db.set_offline_pinned(note_id, pinned).map_err(db_err)?;
+    db.offline_note(note_id).map_err(db_err)
+}
+
+pub fn summary(db: &Database) -> Result<OfflineSummary, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    Ok(OfflineSummary {
+        budget_bytes: db.offline_budget().map_err(db_err)?,
+        used_bytes: db.offline_used_bytes().map_err(db_err)?,
+        pinned: db.offline_note_ids("pinned").map_err(db_err)?,
+        evicted: db.offline_note_ids("evicted").map_err(db_err)?,
+    })
+}
+
+/// Evict notes until the stored content fits the budget. Returns the ids of
+/// the evicted notes.
+pub fn prune(db: &Database) -> Result<Vec<String>, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let Some(budget) = db.offline_budget().map_err(db_err)? else {
+        return Ok(Vec::new());
+    };
+    let mut used = db.offline_used_bytes().map_err(db_err)?;
+    let mut evicted = Vec::new();
+    for (note_id, size) in db.eviction_candidates().map_err(db_err)? {
+        if used <= budget {
+            break;
+        }
+        db.evict_note_content(&note_id).map_err(db_err)?;
+        used -= size;
+        evicted.push(note_id);
+    }
+    Ok(evicted)
+}
@@ -0,0 +1,312 @@
+//! Write-ahead journal of local mutations.
+//!
+//! Every local change to a synced row (notes, folders, boards and their
+//! columns and cards, relations) appends an entry with a monotonically
+//! increasing op id in the same statement sequence as the change itself.
+//! Sync pushes the rows touched by ops after the last pushed op id, then
+//! advances that cursor, so "what changed since the last push" no longer
+//! depends on clocks or `updated_at` comparisons. Rows applied from a remote
+//! sync are not journaled, so they are never echoed back.
+
+use rusqlite::{params, Connection, OptionalExtension, Params, Result as SqliteResult};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::analytics::ActivityDay;
+use crate::boards::BoardSyncPayload;
+use crate::database::{now_rfc3339, Database, Folder, Note};
+use crate::incoming::record_pushed_bases;
+use crate::relations::NoteRelation;
+
+/// Settings key holding the highest op id the server has acknowledged. Each
+/// sync profile keeps its own, suffixed with the profile id (see `pushed_op_key`).
+const PUSHED_OP_KEY: &str = "oplog_pushed_op";
+
+/// Pushed ops kept for diagnostics; older ones are pruned
+const RETAINED_PUSHED_OPS: i64 = 10_000;
+
+/// Subquery selecting the ids of one entity journaled in an op range.
+/// Parameters: ?1 entity, ?2 exclusive lower op id, ?3 inclusive upper op id.
+pub(crate) const OPLOG_ENTITY_IDS: &str =
+    "SELECT entity_id FROM oplog WHERE entity = ?1 AND op_id > ?2 AND op_id <= ?3";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpEntity {
+    Note,
+    Folder,
+    Board,
+    BoardColumn,
+    BoardCard,
+    /// Identified by `relation_entity_id`
+    Relation,
+    /// A local day of `analytics` totals, journaled only when syncing them is
+    /// turned on
+    ActivityDay,
+}
+
+impl OpEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpEntity::Note => "note",
+            OpEntity::Folder => "folder",
+            OpEntity::Board => "board",
+            OpEntity::BoardColumn => "board_column",
+            OpEntity::BoardCard => "board_card",
+            OpEntity::Relation => "relation",
+            OpEntity::ActivityDay => "activity_day",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpKind {
+    Upsert,
+    Delete,
+}
+
+impl OpKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpKind::Upsert => "upsert",
+            OpKind::Delete => "delete",
+        }
+    }
+}
+
+/// A journal entry, for diagnostics
+#[derive(Debug, Serialize, Clone)]
+pub struct OplogEntry {
+    pub op_id: i64,
+    pub entity: String,
+    pub entity_id: String,
+    pub op: String,
+    pub recorded_at: String,
+    /// Whether the change has been pushed to the server
+    pub pushed: bool,
+}
+
+/// Local changes not yet pushed to the server
+#[derive(Debug, Serialize)]
+pub struct PendingChanges {
+    /// Highest op id covered; pass to `mark_oplog_pushed` once the push succeeds
+    pub up_to_op: i64,
+    pub notes: Vec<Note>,
+    pub folders: Vec<Folder>,
+    pub boards: BoardSyncPayload,
+    pub relations: Vec<NoteRelation>,
+    /// Empty unless `analytics_sync` is turned on
+    pub activity: Vec<ActivityDay>,
+}
+
+/// Entity id for a relation, which has no single-column key
+pub(crate) fn relation_entity_id(source_note_id: &str, target_note_id: &str, kind: &str) -> String {
+    format!("{}|{}|{}", source_note_id, target_note_id, kind)
+}
+
+pub fn ensure_oplog_schema(conn: &Connection) -> SqliteResult<()> {
+    let exists: Option<i32> = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'oplog'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS oplog (
+            op_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entity TEXT NOT NULL,
+            entity_id TEXT NOT NULL,
+            op TEXT NOT NULL,
+            recorded_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_oplog_entity ON oplog(entity, op_id)",
+        [],
+    )?;
+
+    // Databases from before the journal existed: journal every row once so the
+    // first push after upgrading sends everything, as a first sync would
+    if exists.is_none() {
+        journal_all_rows(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Journal an upsert for every synced row, so the next push sends everything
+pub(crate) fn journal_all_rows(conn: &Connection) -> SqliteResult<()> {
+    let now = now_rfc3339();
+    for (entity, select_ids) in [
+        (OpEntity::Folder, "SELECT id FROM folders"),
+        (OpEntity::Note, "SELECT id FROM notes"),
+        (OpEntity::Board, "SELECT id FROM boards"),
+        (OpEntity::BoardColumn, "SELECT id FROM board_columns"),
+        (OpEntity::BoardCard, "SELECT id FROM board_cards"),
+        (
+            OpEntity::Relation,
+            "SELECT source_note_id || '|' || target_note_id || '|' || kind AS id FROM note_relations",
+        ),
+    ] {
+        conn.execute(
+            &format!(
+                "INSERT INTO oplog (entity, entity_id, op, recorded_at)
+                 SELECT ?1, id, ?2, ?3 FROM ({})",
+                select_ids
+            ),
+            params![entity.as_str(), OpKind::Upsert.as_str(), &now],
+        )?;
+    }
+    Ok(())
+}
+
+/// Highest op id journaled so far
+pub(crate) fn last_op_id(conn: &Connection) -> SqliteResult<i64> {
+    conn.query_row("SELECT COALESCE(MAX(op_id), 0) FROM oplog", [], |row| {
+        row.get(0)
+    })
+}
+
+/// Settings key of the push cursor for a sync profile, or the single cursor
+/// used before any profile exists
+pub(crate) fn pushed_op_key(profile_id: Option<&str>) -> String {
+    match profile_id {
+        Some(id) => format!("{}:{}", PUSHED_OP_KEY, id),
+        None => PUSHED_OP_KEY.to_string(),
+    }
+}
+
+/// Journal one local change
+pub(crate) fn record_op(
+    conn: &Connection,
+    entity: OpEntity,
+    entity_id: &str,
+    op: OpKind,
+) -> SqliteResult<()> {
+    conn.execute(
+        "INSERT INTO oplog (entity, entity_id, op, recorded_at) VALUES (?1, ?2, ?3, ?4)",
+        params![entity.as_str(), entity_id, op.as_str(), now_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Journal a change for every id `select_ids` returns (for cascading updates)
+pub(crate) fn record_ops_for<P: Params>(
+    conn: &Connection,
+    entity: OpEntity,
+    op: OpKind,
+    select_ids: &str,
+    params: P,
+) -> SqliteResult<()> {
+    let mut stmt = conn.prepare(select_ids)?;
+    let ids = stmt
+        .query_map(params, |row| row.get::<_, String>(0))?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    for id in ids {
+        record_op(conn, entity, &id, op)?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Push cursor of the active sync profile
+    fn pushed_op(&self) -> SqliteResult<i64> {
+        let key = pushed_op_key(self.active_sync_profile_id()?.as_deref());
+        Ok(self.get_setting_i64(&key)?.unwrap_or(0))
+    }
+
+    /// Lowest push cursor across sync profiles: ops after it haven't reached
+    /// every server yet. The pre-profile cursor only counts while no profile
+    /// has one; the first profile takes it over.
+    fn oldest_pushed_op(&self) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        let oldest: Option<i64> = conn.query_row(
+            "SELECT COALESCE(
+                (SELECT MIN(CAST(value AS INTEGER)) FROM settings WHERE key LIKE ?1 || ':%'),
+                (SELECT CAST(value AS INTEGER) FROM settings WHERE key = ?1)
+             )",
+            params![PUSHED_OP_KEY],
+            |row| row.get(0),
+        )?;
+        Ok(oldest.unwrap_or(0))
+    }
+
+    /// Rows changed locally since the last acknowledged push
+    pub fn get_pending_changes(&self) -> SqliteResult<PendingChanges> {
+        let after = self.pushed_op()?;
+        let up_to_op = last_op_id(&self.conn.lock().unwrap())?;
+
+        Ok(PendingChanges {
+            up_to_op,
+            notes: self.get_notes_in_oplog(after, up_to_op)?,
+            folders: self.get_folders_in_oplog(after, up_to_op)?,
+            boards: self.get_boards_in_oplog(after, up_to_op)?,
+            relations: self.get_relations_in_oplog(after, up_to_op)?,
+            activity: self.get_activity_in_oplog(after, up_to_op)?,
+        })
+    }
+
+    /// Ids of one entity with changes not yet pushed to the active server
+    pub(crate) fn pending_entity_ids(&self, entity: OpEntity) -> SqliteResult<HashSet<String>> {
+        self.entity_ids_after(entity, self.pushed_op()?)
+    }
+
+    /// Ids of one entity with changes some sync profile's server hasn't received
+    pub(crate) fn unpushed_anywhere_entity_ids(
+        &self,
+        entity: OpEntity,
+    ) -> SqliteResult<HashSet<String>> {
+        self.entity_ids_after(entity, self.oldest_pushed_op()?)
+    }
+
+    fn entity_ids_after(&self, entity: OpEntity, after: i64) -> SqliteResult<HashSet<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT DISTINCT entity_id FROM oplog WHERE entity = ?1 AND op_id > ?2")?;
+        let rows = stmt.query_map(params![entity.as_str(), after], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Record that everything up to `up_to_op` reached the server
+    pub fn mark_oplog_pushed(&self, up_to_op: i64) -> SqliteResult<()> {
+        let pushed = self.pushed_op()?;
+        if up_to_op <= pushed {
+            return Ok(());
+        }
+        let key = pushed_op_key(self.active_sync_profile_id()?.as_deref());
+        self.set_setting(&key, Some(&up_to_op.to_string()))?;
+        // Other profiles may still need older ops
+        let oldest = self.oldest_pushed_op()?;
+        let conn = self.conn.lock().unwrap();
+        record_pushed_bases(&conn, pushed, up_to_op)?;
+        conn.execute(
+            "DELETE FROM oplog WHERE op_id <= ?1",
+            params![oldest - RETAINED_PUSHED_OPS],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent journal entries, newest first
+    pub fn get_oplog_entries(&self, limit: usize) -> SqliteResult<Vec<OplogEntry>> {
+        let pushed = self.pushed_op()?;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT op_id, entity, entity_id, op, recorded_at
+             FROM oplog ORDER BY op_id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            let op_id: i64 = row.get(0)?;
+            Ok(OplogEntry {
+                op_id,
+                entity: row.get(1)?,
+                entity_id: row.get(2)?,
+                op: row.get(3)?,
+                recorded_at: row.get(4)?,
+                pushed: op_id <= pushed,
+            })
+        })?;
+        rows.collect()
+    }
+}
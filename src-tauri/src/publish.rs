@@ -0,0 +1,402 @@
+//! Publishing notes to a webhook when they are saved.
+//!
+//! A folder can be given a publish target: an endpoint (static site
+//! generator, CMS, ...) that receives every note saved in the folder, and by
+//! default its subfolders, rendered as Markdown or HTML. The nearest folder
+//! with a target wins. Delivery happens in the background after the save; the
+//! outcome of the latest delivery of each note is kept in `publish_deliveries`
+//! so the UI can show whether a draft reached the site. Notes are redacted
+//! like exports before they are sent.
+//!
+//! The request is a JSON `POST`:
+//!
+//! ```json
+//! { "event": "note.published", "format": "markdown", "content_type": "text/markdown",
+//!   "note": { "id": "...", "title": "...", "folder_id": "...", "updated_at": "..." },
+//!   "body": "# Title\n\n..." }
+//! ```
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::database::{now_rfc3339, Database, Note};
+use crate::export::{self, ExportFormat};
+
+/// How long a publish request may take
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest response body kept as the error of a failed delivery
+const MAX_ERROR_LEN: usize = 500;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PublishTarget {
+    pub folder_id: String,
+    pub url: String,
+    pub format: ExportFormat,
+    /// Whether a bearer token is sent (the token itself is never returned)
+    pub has_token: bool,
+    pub include_subfolders: bool,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishTargetInput {
+    pub folder_id: String,
+    pub url: String,
+    pub format: Option<ExportFormat>,
+    /// Sent as `Authorization: Bearer <token>`
+    pub token: Option<String>,
+    pub include_subfolders: Option<bool>,
+}
+
+/// Latest delivery of a note
+#[derive(Debug, Serialize, Clone)]
+pub struct PublishDelivery {
+    pub note_id: String,
+    pub folder_id: String,
+    pub url: String,
+    /// `updated_at` of the note version delivered
+    pub note_updated_at: String,
+    /// "pending", "delivered" or "failed"
+    pub status: String,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+    pub attempted_at: String,
+}
+
+/// A note waiting to be sent to its folder's endpoint
+pub struct PublishJob {
+    note: Note,
+    folder_id: String,
+    url: String,
+    format: ExportFormat,
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PublishedNote<'a> {
+    id: &'a str,
+    title: &'a str,
+    folder_id: Option<&'a str>,
+    updated_at: &'a str,
+}
+
+#[derive(Serialize)]
+struct PublishPayload<'a> {
+    event: &'static str,
+    format: &'static str,
+    content_type: &'static str,
+    note: PublishedNote<'a>,
+    body: String,
+}
+
+pub fn ensure_publish_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS publish_targets (
+            folder_id TEXT PRIMARY KEY NOT NULL,
+            url TEXT NOT NULL,
+            format TEXT NOT NULL DEFAULT 'markdown',
+            token TEXT,
+            include_subfolders INTEGER NOT NULL DEFAULT 1,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS publish_deliveries (
+            note_id TEXT PRIMARY KEY NOT NULL,
+            folder_id TEXT NOT NULL,
+            url TEXT NOT NULL,
+            note_updated_at TEXT NOT NULL,
+            status TEXT NOT NULL,
+            http_status INTEGER,
+            error TEXT,
+            attempted_at TEXT NOT NULL,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn format_name(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Markdown => "markdown",
+        ExportFormat::Html => "html",
+    }
+}
+
+fn parse_format(value: &str) -> ExportFormat {
+    match value {
+        "html" => ExportFormat::Html,
+        _ => ExportFormat::Markdown,
+    }
+}
+
+fn row_to_target(row: &rusqlite::Row) -> SqliteResult<PublishTarget> {
+    Ok(PublishTarget {
+        folder_id: row.get(0)?,
+        url: row.get(1)?,
+        format: parse_format(&row.get::<_, String>(2)?),
+        has_token: row.get::<_, Option<String>>(3)?.is_some(),
+        include_subfolders: row.get::<_, i32>(4)? != 0,
+        updated_at: row.get(5)?,
+    })
+}
+
+fn row_to_delivery(row: &rusqlite::Row) -> SqliteResult<PublishDelivery> {
+    Ok(PublishDelivery {
+        note_id: row.get(0)?,
+        folder_id: row.get(1)?,
+        url: row.get(2)?,
+        note_updated_at: row.get(3)?,
+        status: row.get(4)?,
+        http_status: row.get(5)?,
+        error: row.get(6)?,
+        attempted_at: row.get(7)?,
+    })
+}
+
+const TARGET_COLUMNS: &str = "folder_id, url, format, token, include_subfolders, updated_at";
+
+const DELIVERY_COLUMNS: &str =
+    "note_id, folder_id, url, note_updated_at, status, http_status, error, attempted_at";
+
+impl Database {
+    pub fn set_publish_target(&self, input: &PublishTargetInput) -> SqliteResult<PublishTarget> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO publish_targets (folder_id, url, format, token, include_subfolders, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(folder_id) DO UPDATE SET
+                url = excluded.url,
+                format = excluded.format,
+                token = excluded.token,
+                include_subfolders = excluded.include_subfolders,
+                updated_at = excluded.updated_at",
+            params![
+                &input.folder_id,
+                input.url.trim(),
+                format_name(input.format.unwrap_or(ExportFormat::Markdown)),
+                input.token.as_deref().filter(|t| !t.trim().is_empty()),
+                input.include_subfolders.unwrap_or(true) as i32,
+                now_rfc3339(),
+            ],
+        )?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM publish_targets WHERE folder_id = ?1",
+                TARGET_COLUMNS
+            ),
+            params![&input.folder_id],
+            row_to_target,
+        )
+    }
+
+    pub fn remove_publish_target(&self, folder_id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute(
+            "DELETE FROM publish_targets WHERE folder_id = ?1",
+            params![folder_id],
+        )?;
+        Ok(removed > 0)
+    }
+
+    pub fn list_publish_targets(&self) -> SqliteResult<Vec<PublishTarget>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM publish_targets ORDER BY folder_id",
+            TARGET_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], row_to_target)?;
+        rows.collect()
+    }
+
+    /// Latest deliveries, newest first, optionally only those to one folder's
+    /// endpoint
+    pub fn list_publish_deliveries(
+        &self,
+        folder_id: Option<&str>,
+    ) -> SqliteResult<Vec<PublishDelivery>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM publish_deliveries
+             WHERE ?1 IS NULL OR folder_id = ?1
+             ORDER BY attempted_at DESC",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![folder_id], row_to_delivery)?;
+        rows.collect()
+    }
+
+    /// A job for the nearest target of `folder_id` or its ancestors
+    fn publish_job_for(&self, note: &Note, folder_id: &str) -> SqliteResult<Option<PublishJob>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "WITH RECURSIVE ancestors(id, depth) AS (
+                SELECT ?1, 0
+                UNION ALL
+                SELECT f.parent_id, a.depth + 1
+                FROM folders f JOIN ancestors a ON f.id = a.id
+                WHERE f.parent_id IS NOT NULL AND a.depth < 64
+             )
+             SELECT t.folder_id, t.url, t.format, t.token
+             FROM ancestors a JOIN publish_targets t ON t.folder_id = a.id
+             WHERE a.depth = 0 OR t.include_subfolders = 1
+             ORDER BY a.depth
+             LIMIT 1",
+            params![folder_id],
+            |row| {
+                Ok(PublishJob {
+                    note: note.clone(),
+                    folder_id: row.get(0)?,
+                    url: row.get(1)?,
+                    format: parse_format(&row.get::<_, String>(2)?),
+                    token: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    fn record_publish_delivery(&self, delivery: &PublishDelivery) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO publish_deliveries
+                (note_id, folder_id, url, note_updated_at, status, http_status, error, attempted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(note_id) DO UPDATE SET
+                folder_id = excluded.folder_id,
+                url = excluded.url,
+                note_updated_at = excluded.note_updated_at,
+                status = excluded.status,
+                http_status = excluded.http_status,
+                error = excluded.error,
+                attempted_at = excluded.attempted_at",
+            params![
+                &delivery.note_id,
+                &delivery.folder_id,
+                &delivery.url,
+                &delivery.note_updated_at,
+                &delivery.status,
+                delivery.http_status,
+                &delivery.error,
+                &delivery.attempted_at,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl PublishJob {
+    fn delivery(
+        &self,
+        status: &str,
+        http_status: Option<u16>,
+        error: Option<String>,
+    ) -> PublishDelivery {
+        PublishDelivery {
+            note_id: self.note.id.clone(),
+            folder_id: self.folder_id.clone(),
+            url: self.url.clone(),
+            note_updated_at: self.note.updated_at.to_string(),
+            status: status.to_string(),
+            http_status,
+            error,
+            attempted_at: now_rfc3339(),
+        }
+    }
+}
+
+/// Find where a saved note should be published and record the delivery as
+/// pending. Returns `None` for notes outside any folder with a target, canvas
+/// notes and deleted notes.
+pub fn prepare(db: &Database, note: &Note) -> Result<Option<PublishJob>, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let Some(folder_id) = note.folder_id.as_deref() else {
+        return Ok(None);
+    };
+    if note.is_deleted || note.is_canvas {
+        return Ok(None);
+    }
+    let Some(job) = db.publish_job_for(note, folder_id).map_err(db_err)? else {
+        return Ok(None);
+    };
+    db.record_publish_delivery(&job.delivery("pending", None, None))
+        .map_err(db_err)?;
+    Ok(Some(job))
+}
+
+/// Send a note to its endpoint and record the outcome
+pub async fn deliver(db: &Database, job: PublishJob) -> Result<PublishDelivery, String> {
+    let note = export::redacted(db, &job.note)?;
+    let payload = PublishPayload {
+        event: "note.published",
+        format: format_name(job.format),
+        content_type: match job.format {
+            ExportFormat::Markdown => "text/markdown",
+            ExportFormat::Html => "text/html",
+        },
+        note: PublishedNote {
+            id: &note.id,
+            title: &note.title,
+            folder_id: note.folder_id.as_deref(),
+            updated_at: note.updated_at.as_str(),
+        },
+        body: export::render_note(&note, job.format),
+    };
+    let body =
+        serde_json::to_vec(&payload).map_err(|e| format!("Failed to encode payload: {}", e))?;
+
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .user_agent(concat!("Beck/", env!("CARGO_PKG_VERSION"), " (publish)"))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut request = client
+        .post(&job.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    if let Some(token) = &job.token {
+        request = request.bearer_auth(token);
+    }
+
+    let delivery = match request.send().await {
+        Ok(response) if response.status().is_success() => {
+            job.delivery("delivered", Some(response.status().as_u16()), None)
+        }
+        Ok(response) => {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            let mut error = format!("Endpoint returned {}", status);
+            if !text.trim().is_empty() {
+                error.push_str(": ");
+                error.extend(text.trim().chars().take(MAX_ERROR_LEN));
+            }
+            job.delivery("failed", Some(status.as_u16()), Some(error))
+        }
+        Err(err) => job.delivery(
+            "failed",
+            None,
+            Some(format!("Failed to reach {}: {}", job.url, err)),
+        ),
+    };
+    db.record_publish_delivery(&delivery)
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(delivery)
+}
+
+/// Publish a note now, regardless of whether it changed
+pub async fn publish_note(db: &Database, note_id: &str) -> Result<PublishDelivery, String> {
+    let note = db
+        .get_note_by_id(note_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Note not found: {}", note_id))?;
+    let job = prepare(db, &note)?
+        .ok_or_else(|| "The note isn't in a folder with a publish target".to_string())?;
+    deliver(db, job).await
+}
@@ -0,0 +1,204 @@
+//! Safe-mode startup when `notes.db` can't be opened.
+//!
+//! If opening or migrating the database fails, the app starts without it and
+//! manages a [`RecoveryState`] instead of panicking with a blank window. The
+//! frontend checks `get_recovery_status` and offers three ways out:
+//!
+//! - salvage: copy every row that can still be read into a JSON file, table
+//!   by table, skipping rows on damaged pages (like the sqlite3 `.recover`
+//!   command, but without rebuilding a database)
+//! - restore the newest verified backup
+//! - start over with an empty database
+//!
+//! Restoring and starting over move the broken files aside (never delete
+//! them) and restart the app.
+
+use base64::Engine;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backups::{self, BackupInfo};
+use crate::database::DATABASE_FILE;
+
+/// Why the database could not be opened, kept as managed state in safe mode
+pub struct RecoveryState {
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RecoveryStatus {
+    pub error: String,
+    pub database_path: String,
+    /// Backups that can be restored, newest first
+    pub backups: Vec<BackupInfo>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SalvagedTable {
+    pub name: String,
+    pub rows: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SalvageReport {
+    pub path: String,
+    pub tables: Vec<SalvagedTable>,
+    /// Tables or rows that could not be read
+    pub errors: Vec<String>,
+}
+
+pub fn status(state: &RecoveryState, app_data_dir: &Path) -> RecoveryStatus {
+    RecoveryStatus {
+        error: state.error.clone(),
+        database_path: app_data_dir
+            .join(DATABASE_FILE)
+            .to_string_lossy()
+            .to_string(),
+        backups: backups::list_backups(app_data_dir).unwrap_or_default(),
+    }
+}
+
+/// JSON value of a column; blobs (CRDT states) are base64-encoded
+fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+        ValueRef::Blob(b) => base64::engine::general_purpose::STANDARD.encode(b).into(),
+    }
+}
+
+fn row_object(row: &rusqlite::Row, columns: &[String]) -> rusqlite::Result<Value> {
+    let mut object = Map::new();
+    for (i, column) in columns.iter().enumerate() {
+        object.insert(column.clone(), json_value(row.get_ref(i)?));
+    }
+    Ok(Value::Object(object))
+}
+
+/// Every readable row of `table`. Rows are read one rowid at a time so a
+/// damaged page only loses the rows on it; tables without a rowid are read in
+/// one pass up to the first error.
+fn salvage_table(conn: &Connection, table: &str, errors: &mut Vec<String>) -> Vec<Value> {
+    let quoted = format!("\"{}\"", table.replace('"', "\"\""));
+    let columns: Vec<String> = match conn.prepare(&format!("SELECT * FROM {}", quoted)) {
+        Ok(stmt) => stmt.column_names().iter().map(|c| c.to_string()).collect(),
+        Err(err) => {
+            errors.push(format!("{}: {}", table, err));
+            return Vec::new();
+        }
+    };
+
+    let mut rows = Vec::new();
+    let rowids: rusqlite::Result<Vec<i64>> = conn
+        .prepare(&format!("SELECT rowid FROM {}", quoted))
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect());
+    match rowids {
+        Ok(rowids) => {
+            let sql = format!("SELECT * FROM {} WHERE rowid = ?1", quoted);
+            for rowid in rowids {
+                match conn.query_row(&sql, [rowid], |row| row_object(row, &columns)) {
+                    Ok(row) => rows.push(row),
+                    Err(err) => errors.push(format!("{} row {}: {}", table, rowid, err)),
+                }
+            }
+        }
+        Err(_) => {
+            let result = conn
+                .prepare(&format!("SELECT * FROM {}", quoted))
+                .and_then(|mut stmt| {
+                    let mut query = stmt.query([])?;
+                    while let Some(row) = query.next()? {
+                        rows.push(row_object(row, &columns)?);
+                    }
+                    Ok(())
+                });
+            if let Err(err) = result {
+                errors.push(format!("{}: {}", table, err));
+            }
+        }
+    }
+    rows
+}
+
+/// Write every readable row of the broken database to `path` as JSON,
+/// an object of table name to rows
+pub fn salvage(app_data_dir: &Path, path: &Path) -> Result<SalvageReport, String> {
+    let conn = Connection::open_with_flags(
+        app_data_dir.join(DATABASE_FILE),
+        OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )
+    .map_err(|e| format!("Failed to open database: {}", e))?;
+
+    let tables: Vec<String> = conn
+        .prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+             ORDER BY name",
+        )
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .map_err(|e| format!("Failed to read the database schema: {}", e))?;
+
+    let mut errors = Vec::new();
+    let mut summary = Vec::new();
+    let mut output = Map::new();
+    for table in tables {
+        let rows = salvage_table(&conn, &table, &mut errors);
+        summary.push(SalvagedTable {
+            name: table.clone(),
+            rows: rows.len(),
+        });
+        output.insert(table, Value::Array(rows));
+    }
+
+    let json = serde_json::to_string_pretty(&Value::Object(output))
+        .map_err(|e| format!("Failed to serialize rows: {}", e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(SalvageReport {
+        path: path.to_string_lossy().to_string(),
+        tables: summary,
+        errors,
+    })
+}
+
+/// Rename the database and its WAL files to `notes.db.broken-<time>...`.
+/// Returns the new database path.
+fn move_aside(app_data_dir: &Path) -> Result<PathBuf, String> {
+    let stamp = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+    let broken = app_data_dir.join(format!("{}.broken-{}", DATABASE_FILE, stamp));
+    for suffix in ["", "-wal", "-shm"] {
+        let from = app_data_dir.join(format!("{}{}", DATABASE_FILE, suffix));
+        if from.exists() {
+            let to = PathBuf::from(format!("{}{}", broken.display(), suffix));
+            fs::rename(&from, &to)
+                .map_err(|e| format!("Failed to move {} aside: {}", from.display(), e))?;
+        }
+    }
+    Ok(broken)
+}
+
+/// Replace the broken database with the newest backup that passes
+/// verification. Returns the backup used.
+pub fn restore_latest_backup(app_data_dir: &Path) -> Result<BackupInfo, String> {
+    let backup = backups::list_backups(app_data_dir)?
+        .into_iter()
+        .find(|b| backups::verify_backup(Path::new(&b.path)).is_ok())
+        .ok_or_else(|| "No usable backup found".to_string())?;
+
+    move_aside(app_data_dir)?;
+    fs::copy(&backup.path, app_data_dir.join(DATABASE_FILE))
+        .map_err(|e| format!("Failed to restore {}: {}", backup.file_name, e))?;
+    Ok(backup)
+}
+
+/// Move the broken database aside so the next start creates an empty one.
+/// Returns where the broken database was moved.
+pub fn start_fresh(app_data_dir: &Path) -> Result<String, String> {
+    move_aside(app_data_dir).map(|p| p.to_string_lossy().to_string())
+}
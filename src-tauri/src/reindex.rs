@@ -0,0 +1,131 @@
+//! Rebuilding the indexes derived from note content.
+//!
+//! Code snippets, note links, highlights, keyword terms, flashcards and note
+//! previews are normally kept up to date as notes are saved. If a bug or an
+//! importer edge case leaves them stale, `rebuild_indexes` throws the derived
+//! rows away and re-indexes every note in a single transaction, so a failure
+//! leaves the old indexes in place. Flashcards are re-indexed in place rather
+//! than dropped, so their review schedules survive. Evicted notes (see
+//! `offline`) have no content on this device; their index rows are kept as is.
+
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::Serialize;
+
+use crate::database::{index_note_content, make_preview, Database};
+
+/// Event emitted with a `RebuildProgress` while indexes are rebuilt
+pub const REINDEX_PROGRESS_EVENT: &str = "app://reindex-progress";
+
+/// Notes indexed between progress events
+const PROGRESS_STEP: usize = 50;
+
+/// Tables holding only rows derived from note content, with their note column
+const DERIVED_TABLES: &[(&str, &str)] = &[
+    ("code_snippets", "note_id"),
+    ("note_links", "source_note_id"),
+    ("highlights", "note_id"),
+    ("note_terms", "note_id"),
+];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RebuildProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RebuildReport {
+    /// Notes re-indexed, including deleted ones whose rows were cleared
+    pub notes: usize,
+    /// Evicted notes left alone
+    pub evicted: usize,
+    pub snippets: i64,
+    pub links: i64,
+    pub highlights: i64,
+    pub terms: i64,
+    pub cards: i64,
+}
+
+fn count(conn: &Connection, table: &str) -> SqliteResult<i64> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+    })
+}
+
+/// Re-index every note that has its content on this device, calling
+/// `progress` as notes are done
+pub fn rebuild_indexes(
+    db: &Database,
+    mut progress: impl FnMut(RebuildProgress),
+) -> Result<RebuildReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let mut conn = db.conn.lock().unwrap();
+    let tx = conn.transaction().map_err(db_err)?;
+
+    let evicted: usize = tx
+        .query_row(
+            "SELECT COUNT(*) FROM offline_notes WHERE evicted = 1",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(db_err)?;
+    for (table, column) in DERIVED_TABLES {
+        tx.execute(
+            &format!(
+                "DELETE FROM {table} WHERE {column} NOT IN
+                 (SELECT note_id FROM offline_notes WHERE evicted = 1)"
+            ),
+            [],
+        )
+        .map_err(db_err)?;
+    }
+
+    let notes = {
+        let mut stmt = tx
+            .prepare(
+                "SELECT id, content, is_deleted FROM notes
+                 WHERE id NOT IN (SELECT note_id FROM offline_notes WHERE evicted = 1)",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i32>(2)? != 0,
+                ))
+            })
+            .map_err(db_err)?;
+        rows.collect::<SqliteResult<Vec<_>>>().map_err(db_err)?
+    };
+
+    let total = notes.len();
+    progress(RebuildProgress { done: 0, total });
+    for (done, (id, content, is_deleted)) in notes.iter().enumerate() {
+        index_note_content(&tx, id, content, *is_deleted).map_err(db_err)?;
+        tx.execute(
+            "UPDATE notes SET preview = ?2 WHERE id = ?1",
+            params![id, make_preview(content)],
+        )
+        .map_err(db_err)?;
+        if (done + 1) % PROGRESS_STEP == 0 {
+            progress(RebuildProgress {
+                done: done + 1,
+                total,
+            });
+        }
+    }
+
+    let report = RebuildReport {
+        notes: total,
+        evicted,
+        snippets: count(&tx, "code_snippets").map_err(db_err)?,
+        links: count(&tx, "note_links").map_err(db_err)?,
+        highlights: count(&tx, "highlights").map_err(db_err)?,
+        terms: count(&tx, "note_terms").map_err(db_err)?,
+        cards: count(&tx, "cards").map_err(db_err)?,
+    };
+    tx.commit().map_err(db_err)?;
+    progress(RebuildProgress { done: total, total });
+    Ok(report)
+}
@@ -0,0 +1,316 @@
+//! Typed relationships between notes.
+//!
+//! Unlike links embedded in content, relations are explicit edges with a kind:
+//! `references`, `blocks` or `part_of`. A relation is identified by its
+//! `(source, target, kind)` triple, so the same edge created on two devices
+//! syncs to a single row. Removal is a soft delete so it propagates through sync.
+
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{now_rfc3339, Database};
+use crate::oplog::{record_op, relation_entity_id, OpEntity, OpKind, OPLOG_ENTITY_IDS};
+use crate::timestamp::Timestamp;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationKind {
+    References,
+    Blocks,
+    PartOf,
+}
+
+impl RelationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelationKind::References => "references",
+            RelationKind::Blocks => "blocks",
+            RelationKind::PartOf => "part_of",
+        }
+    }
+}
+
+/// A relation row as stored and synced
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteRelation {
+    pub source_note_id: String,
+    pub target_note_id: String,
+    pub kind: RelationKind,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub is_deleted: bool,
+}
+
+/// A relation seen from one note, with the other note's title
+#[derive(Debug, Serialize, Clone)]
+pub struct RelatedNote {
+    pub note_id: String,
+    pub title: String,
+    pub kind: RelationKind,
+    pub created_at: String,
+}
+
+/// Relations of a note in both directions
+#[derive(Debug, Serialize, Clone)]
+pub struct NoteRelations {
+    /// Edges where this note is the source (e.g. this note blocks X)
+    pub outgoing: Vec<RelatedNote>,
+    /// Edges pointing at this note (e.g. X is part of this note)
+    pub incoming: Vec<RelatedNote>,
+}
+
+pub fn ensure_relations_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_relations (
+            source_note_id TEXT NOT NULL,
+            target_note_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            is_deleted INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (source_note_id, target_note_id, kind)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_relations_target ON note_relations(target_note_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_relations_updated_at ON note_relations(updated_at)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn parse_kind(value: String) -> SqliteResult<RelationKind> {
+    match value.as_str() {
+        "references" => Ok(RelationKind::References),
+        "blocks" => Ok(RelationKind::Blocks),
+        "part_of" => Ok(RelationKind::PartOf),
+        _ => Err(rusqlite::Error::FromSqlConversionFailure(
+            0,
+            rusqlite::types::Type::Text,
+            format!("unknown relation kind: {}", value).into(),
+        )),
+    }
+}
+
+impl Database {
+    /// Create (or restore) a relation. Returns an error message for invalid edges.
+    pub fn add_note_relation(
+        &self,
+        source: &str,
+        target: &str,
+        kind: RelationKind,
+    ) -> Result<NoteRelation, String> {
+        if source == target {
+            return Err("A note cannot be related to itself".to_string());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+
+        // part_of forms a hierarchy; refuse edges that would make a cycle
+        if kind == RelationKind::PartOf {
+            let cycle: bool = conn
+                .query_row(
+                    "WITH RECURSIVE ancestors(id) AS (
+                        SELECT ?1
+                        UNION
+                        SELECT r.target_note_id FROM note_relations r
+                        JOIN ancestors a ON r.source_note_id = a.id
+                        WHERE r.kind = 'part_of' AND r.is_deleted = 0
+                     )
+                     SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?2)",
+                    params![target, source],
+                    |row| row.get(0),
+                )
+                .map_err(db_err)?;
+            if cycle {
+                return Err("This would make the note part of itself".to_string());
+            }
+        }
+
+        let now = now_rfc3339();
+        conn.execute(
+            "INSERT INTO note_relations (source_note_id, target_note_id, kind, created_at, updated_at, is_deleted)
+             VALUES (?1, ?2, ?3, ?4, ?4, 0)
+             ON CONFLICT(source_note_id, target_note_id, kind) DO UPDATE SET
+                updated_at = excluded.updated_at,
+                is_deleted = 0",
+            params![source, target, kind.as_str(), &now],
+        )
+        .map_err(db_err)?;
+        record_op(
+            &conn,
+            OpEntity::Relation,
+            &relation_entity_id(source, target, kind.as_str()),
+            OpKind::Upsert,
+        )
+        .map_err(db_err)?;
+
+        conn.query_row(
+            "SELECT created_at, updated_at FROM note_relations
+             WHERE source_note_id = ?1 AND target_note_id = ?2 AND kind = ?3",
+            params![source, target, kind.as_str()],
+            |row| {
+                Ok(NoteRelation {
+                    source_note_id: source.to_string(),
+                    target_note_id: target.to_string(),
+                    kind,
+                    created_at: row.get(0)?,
+                    updated_at: row.get(1)?,
+                    is_deleted: false,
+                })
+            },
+        )
+        .map_err(db_err)
+    }
+
+    /// Remove a relation
+    pub fn remove_note_relation(
+        &self,
+        source: &str,
+        target: &str,
+        kind: RelationKind,
+    ) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute(
+            "UPDATE note_relations SET is_deleted = 1, updated_at = ?4
+             WHERE source_note_id = ?1 AND target_note_id = ?2 AND kind = ?3 AND is_deleted = 0",
+            params![source, target, kind.as_str(), now_rfc3339()],
+        )?;
+        if removed > 0 {
+            record_op(
+                &conn,
+                OpEntity::Relation,
+                &relation_entity_id(source, target, kind.as_str()),
+                OpKind::Delete,
+            )?;
+        }
+        Ok(removed > 0)
+    }
+
+    /// Live relations of a note in both directions, optionally of one kind
+    pub fn get_note_relations(
+        &self,
+        note_id: &str,
+        kind: Option<RelationKind>,
+    ) -> SqliteResult<NoteRelations> {
+        let conn = self.conn.lock().unwrap();
+        let kind = kind.map(|k| k.as_str());
+
+        let query = |near: &str, far: &str| -> SqliteResult<Vec<RelatedNote>> {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT r.{far}, n.title, r.kind, r.created_at
+                 FROM note_relations r
+                 JOIN notes n ON n.id = r.{far}
+                 WHERE r.{near} = ?1 AND r.is_deleted = 0 AND n.is_deleted = 0
+                   AND (?2 IS NULL OR r.kind = ?2)
+                 ORDER BY r.kind ASC, n.title ASC",
+                near = near,
+                far = far
+            ))?;
+            let rows = stmt.query_map(params![note_id, kind], |row| {
+                Ok(RelatedNote {
+                    note_id: row.get(0)?,
+                    title: row.get(1)?,
+                    kind: parse_kind(row.get(2)?)?,
+                    created_at: row.get(3)?,
+                })
+            })?;
+            rows.collect()
+        };
+
+        Ok(NoteRelations {
+            outgoing: query("source_note_id", "target_note_id")?,
+            incoming: query("target_note_id", "source_note_id")?,
+        })
+    }
+
+    /// Relations changed since a timestamp (RFC3339), including removals
+    pub fn get_relations_updated_since(
+        &self,
+        since: Option<&str>,
+    ) -> SqliteResult<Vec<NoteRelation>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT source_note_id, target_note_id, kind, created_at, updated_at, is_deleted
+             FROM note_relations
+             WHERE updated_at > ?1
+             ORDER BY updated_at ASC",
+        )?;
+        let rows = stmt.query_map(params![since.unwrap_or("")], |row| {
+            Ok(NoteRelation {
+                source_note_id: row.get(0)?,
+                target_note_id: row.get(1)?,
+                kind: parse_kind(row.get(2)?)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
+                is_deleted: row.get::<_, i32>(5)? != 0,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Relations journaled in the oplog between two op ids, including removals
+    pub fn get_relations_in_oplog(
+        &self,
+        after_op: i64,
+        up_to_op: i64,
+    ) -> SqliteResult<Vec<NoteRelation>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT source_note_id, target_note_id, kind, created_at, updated_at, is_deleted
+             FROM note_relations
+             WHERE source_note_id || '|' || target_note_id || '|' || kind IN ({})
+             ORDER BY updated_at ASC",
+            OPLOG_ENTITY_IDS
+        ))?;
+        let rows = stmt.query_map(
+            params![OpEntity::Relation.as_str(), after_op, up_to_op],
+            |row| {
+                Ok(NoteRelation {
+                    source_note_id: row.get(0)?,
+                    target_note_id: row.get(1)?,
+                    kind: parse_kind(row.get(2)?)?,
+                    created_at: row.get(3)?,
+                    updated_at: row.get(4)?,
+                    is_deleted: row.get::<_, i32>(5)? != 0,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// Apply relations from a remote sync. Uses last-writer-wins based on updated_at.
+    pub fn apply_sync_relations(&self, relations: Vec<NoteRelation>) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for relation in relations {
+            tx.execute(
+                "INSERT INTO note_relations (source_note_id, target_note_id, kind, created_at, updated_at, is_deleted)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(source_note_id, target_note_id, kind) DO UPDATE SET
+                    updated_at = excluded.updated_at,
+                    is_deleted = excluded.is_deleted
+                 WHERE excluded.updated_at > note_relations.updated_at",
+                params![
+                    relation.source_note_id,
+                    relation.target_note_id,
+                    relation.kind.as_str(),
+                    relation.created_at,
+                    relation.updated_at,
+                    relation.is_deleted as i32,
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Content-addressed cache for assets and shared notes fetched from the sync server.
+//!
+//! Response bodies are stored under `.cache/remote/<sha256>` so identical content
+//! fetched from different URLs is kept once. The `remote_cache` table maps each URL
+//! to its blob and the ETag the server sent, which is replayed as `If-None-Match`
+//! so unchanged resources are revalidated without re-downloading.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::{now_rfc3339, Database, Note};
+
+/// A cached remote resource, as returned to the frontend
+#[derive(Debug, Serialize, Clone)]
+pub struct CachedResource {
+    pub url: String,
+    pub hash: String,
+    pub uri: String,
+    pub path: String,
+    pub content_type: Option<String>,
+    pub size: i64,
+    /// True when the body came from disk (304 or offline fallback)
+    pub from_cache: bool,
+}
+
+/// Index row for a cached URL
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    hash: String,
+    etag: Option<String>,
+    content_type: Option<String>,
+    size: i64,
+}
+
+pub fn ensure_remote_cache_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS remote_cache (
+            url TEXT PRIMARY KEY NOT NULL,
+            hash TEXT NOT NULL,
+            etag TEXT,
+            content_type TEXT,
+            size INTEGER NOT NULL DEFAULT 0,
+            fetched_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_remote_cache_hash ON remote_cache(hash)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Get the remote cache directory path
+pub fn get_remote_cache_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(".cache").join("remote")
+}
+
+impl Database {
+    fn get_remote_cache_entry(&self, url: &str) -> SqliteResult<Option<CacheEntry>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT hash, etag, content_type, size FROM remote_cache WHERE url = ?1",
+            params![url],
+            |row| {
+                Ok(CacheEntry {
+                    hash: row.get(0)?,
+                    etag: row.get(1)?,
+                    content_type: row.get(2)?,
+                    size: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    fn put_remote_cache_entry(&self, url: &str, entry: &CacheEntry) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO remote_cache (url, hash, etag, content_type, size, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(url) DO UPDATE SET
+                hash = excluded.hash,
+                etag = excluded.etag,
+                content_type = excluded.content_type,
+                size = excluded.size,
+                fetched_at = excluded.fetched_at",
+            params![
+                url,
+                &entry.hash,
+                &entry.etag,
+                &entry.content_type,
+                entry.size,
+                now_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn touch_remote_cache_entry(&self, url: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE remote_cache SET fetched_at = ?2 WHERE url = ?1",
+            params![url, now_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn delete_remote_cache_entry(&self, url: &str) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM remote_cache WHERE url = ?1", params![url])?;
+        Ok(())
+    }
+
+    /// Drop all index rows; returns how many URLs were cached
+    fn clear_remote_cache_entries(&self) -> SqliteResult<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM remote_cache", [])
+    }
+}
+
+fn to_resource(
+    url: &str,
+    blob_path: &Path,
+    entry: &CacheEntry,
+    from_cache: bool,
+) -> CachedResource {
+    CachedResource {
+        url: url.to_string(),
+        hash: entry.hash.clone(),
+        uri: format!(
+            "asset://localhost/{}",
+            blob_path.to_string_lossy().replace('\\', "/")
+        ),
+        path: blob_path.to_string_lossy().to_string(),
+        content_type: entry.content_type.clone(),
+        size: entry.size,
+        from_cache,
+    }
+}
+
+/// Fetch a URL through the cache, revalidating with the stored ETag.
+///
+/// Falls back to the cached copy when the server is unreachable.
+pub async fn fetch(
+    db: &Database,
+    app_data_dir: &Path,
+    url: &str,
+    token: Option<&str>,
+) -> Result<CachedResource, String> {
+    let cache_dir = get_remote_cache_dir(app_data_dir);
+    fs::create_dir_all(&cache_dir)
+        .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+
+    // Only trust an index row whose blob is still on disk
+    let cached = db
+        .get_remote_cache_entry(url)
+        .map_err(|e| format!("Database error: {}", e))?
+        .filter(|entry| cache_dir.join(&entry.hash).is_file());
+
+    let client = reqwest::Client::new();
+    let mut request = client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(etag) = cached.as_ref().and_then(|e| e.etag.as_deref()) {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(err) => {
+            return match cached {
+                Some(entry) => Ok(to_resource(url, &cache_dir.join(&entry.hash), &entry, true)),
+                None => Err(format!("Failed to fetch {}: {}", url, err)),
+            };
+        }
+    };
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        if let Some(entry) = cached {
+            db.touch_remote_cache_entry(url)
+                .map_err(|e| format!("Database error: {}", e))?;
+            return Ok(to_resource(url, &cache_dir.join(&entry.hash), &entry, true));
+        }
+    }
+
+    if !response.status().is_success() {
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            // Gone upstream; forget it so a stale copy isn't served forever
+            db.delete_remote_cache_entry(url)
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+        return Err(format!("Server returned {} for {}", response.status(), url));
+    }
+
+    let header_value = |name: reqwest::header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let etag = header_value(reqwest::header::ETAG);
+    let content_type = header_value(reqwest::header::CONTENT_TYPE);
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response body: {}", e))?;
+
+    let hash = hex::encode(Sha256::digest(&body));
+    let blob_path = cache_dir.join(&hash);
+    if !blob_path.is_file() {
+        fs::write(&blob_path, &body).map_err(|e| format!("Failed to write cache file: {}", e))?;
+    }
+
+    let entry = CacheEntry {
+        hash,
+        etag,
+        content_type,
+        size: body.len() as i64,
+    };
+    db.put_remote_cache_entry(url, &entry)
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(to_resource(url, &blob_path, &entry, false))
+}
+
+/// Fetch a shared note's JSON from the server through the cache
+pub async fn fetch_note(
+    db: &Database,
+    app_data_dir: &Path,
+    url: &str,
+    token: Option<&str>,
+) -> Result<Note, String> {
+    let resource = fetch(db, app_data_dir, url, token).await?;
+    let bytes =
+        fs::read(&resource.path).map_err(|e| format!("Failed to read cache file: {}", e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid note payload: {}", e))
+}
+
+/// Remove every cached blob and index row. Returns the number of bytes freed.
+pub fn clear(db: &Database, app_data_dir: &Path) -> Result<u64, String> {
+    let cache_dir = get_remote_cache_dir(app_data_dir);
+    let mut freed = 0;
+
+    if cache_dir.exists() {
+        let entries = fs::read_dir(&cache_dir)
+            .map_err(|e| format!("Failed to read cache directory: {}", e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                freed += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete cache file: {}", e))?;
+            }
+        }
+    }
+
+    db.clear_remote_cache_entries()
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(freed)
+}
@@ -0,0 +1,89 @@
+//! The sync server's retention policy, applied to purges on this device.
+//!
+//! Admins of a workspace with compliance requirements set a minimum retention
+//! and a legal hold on the server (`/api/retention`), which enforces them on
+//! its own deletions. The policy is cached here so emptying the trash follows
+//! it too (see `trash`): under a legal hold nothing is purged, and otherwise
+//! notes stay in the trash for at least the minimum retention. The scheduler
+//! refreshes the cache hourly while a sync server is configured; a failed
+//! refresh keeps the last policy seen rather than dropping it.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{now_rfc3339, Database};
+use crate::offline;
+use crate::sync_profiles;
+
+/// Settings key holding the cached policy, as JSON
+const POLICY_KEY: &str = "workspace_retention_policy";
+
+/// Minutes between refreshes by the scheduler
+const REFRESH_INTERVAL_MINUTES: i64 = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Days a deleted note stays in the trash before it can be purged
+    pub min_retention_days: i64,
+    /// Nothing can be purged while set
+    pub legal_hold: bool,
+    pub legal_hold_reason: Option<String>,
+    /// When this device last fetched the policy
+    #[serde(default)]
+    pub fetched_at: Option<String>,
+}
+
+impl RetentionPolicy {
+    /// Why a purge is refused, if it is
+    pub fn hold_error(&self) -> Option<String> {
+        if !self.legal_hold {
+            return None;
+        }
+        Some(match &self.legal_hold_reason {
+            Some(reason) => format!(
+                "The workspace is under legal hold ({}); nothing can be purged",
+                reason
+            ),
+            None => "The workspace is under legal hold; nothing can be purged".to_string(),
+        })
+    }
+}
+
+/// The policy last fetched from the sync server; the default (no retention,
+/// no hold) if there is none
+pub fn cached_policy(db: &Database) -> Result<RetentionPolicy, String> {
+    let value = db
+        .get_setting(POLICY_KEY)
+        .map_err(|e| format!("Database error: {}", e))?;
+    match value {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid cached retention policy: {}", e)),
+        None => Ok(RetentionPolicy::default()),
+    }
+}
+
+/// Whether the scheduler should refresh the cached policy
+pub fn refresh_due(db: &Database) -> Result<bool, String> {
+    if sync_profiles::active_profile(db)?.is_none() {
+        return Ok(false);
+    }
+    let fetched_at = cached_policy(db)?
+        .fetched_at
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok());
+    Ok(fetched_at.is_none_or(|at| {
+        Utc::now().signed_duration_since(at) >= Duration::minutes(REFRESH_INTERVAL_MINUTES)
+    }))
+}
+
+/// Fetch the active sync server's policy and cache it
+pub async fn pull_retention_policy(db: &Database) -> Result<RetentionPolicy, String> {
+    let (base, active) = sync_profiles::active_server(db)?;
+    let mut policy: RetentionPolicy =
+        offline::get_json(&format!("{}/api/retention", base), active.token.as_deref()).await?;
+    policy.fetched_at = Some(now_rfc3339());
+    let json = serde_json::to_string(&policy)
+        .map_err(|e| format!("Failed to encode retention policy: {}", e))?;
+    db.set_setting(POLICY_KEY, Some(&json))
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(policy)
+}
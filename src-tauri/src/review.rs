@@ -0,0 +1,252 @@
+//! Review dates for resurfacing notes.
+//!
+//! A note can be given a `review_at` date. Notes whose date has passed are listed
+//! by `get_notes_due_for_review`, and the scheduler emits an event once when each
+//! date arrives so the UI can bring the note back to the user's attention.
+//!
+//! Notes nobody set a date on are suggested for review once they go stale:
+//! `stale_notes` lists notes neither edited nor opened on this device (see
+//! `analytics`) for some months, for periodic cleanup of the vault.
+
+use chrono::{DateTime, Months, NaiveDate, NaiveTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::database::Database;
+
+/// A note due (or scheduled) for review
+#[derive(Debug, Serialize, Clone)]
+pub struct ReviewNote {
+    pub id: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub updated_at: String,
+    pub preview: String,
+    pub review_at: String,
+}
+
+/// Stale notes listed when no limit is given
+const DEFAULT_STALE_LIMIT: usize = 200;
+
+/// A note not touched in a while
+#[derive(Debug, Serialize, Clone)]
+pub struct StaleNote {
+    pub id: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub updated_at: String,
+    pub preview: String,
+    /// Last time the note was opened on this device, if ever
+    pub last_opened_at: Option<String>,
+}
+
+pub fn ensure_review_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_reviews (
+            note_id TEXT PRIMARY KEY NOT NULL,
+            review_at TEXT NOT NULL,
+            notified_at TEXT,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_reviews_review_at ON note_reviews(review_at)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn to_rfc3339(dt: DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Parse an RFC3339 timestamp or a plain `YYYY-MM-DD` date. A plain date means
+/// the start of that day, or its end when `end_of_day` is set.
+pub fn parse_review_date(value: &str, end_of_day: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date: {}", value))?;
+    let time = if end_of_day {
+        NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap()
+    } else {
+        NaiveTime::MIN
+    };
+    Ok(date.and_time(time).and_utc())
+}
+
+fn row_to_review_note(row: &rusqlite::Row) -> SqliteResult<ReviewNote> {
+    Ok(ReviewNote {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        folder_id: row.get(2)?,
+        updated_at: row.get(3)?,
+        preview: row.get(4)?,
+        review_at: row.get(5)?,
+    })
+}
+
+impl Database {
+    /// Set or clear a note's review date. Changing the date re-arms its notification.
+    pub fn set_note_review_at(
+        &self,
+        note_id: &str,
+        review_at: Option<DateTime<Utc>>,
+    ) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        match review_at {
+            Some(review_at) => conn.execute(
+                "INSERT INTO note_reviews (note_id, review_at, notified_at) VALUES (?1, ?2, NULL)
+                 ON CONFLICT(note_id) DO UPDATE SET
+                    review_at = excluded.review_at,
+                    notified_at = NULL",
+                params![note_id, to_rfc3339(review_at)],
+            )?,
+            None => conn.execute(
+                "DELETE FROM note_reviews WHERE note_id = ?1",
+                params![note_id],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// A note's review date, if one is set
+    pub fn get_note_review_at(&self, note_id: &str) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT review_at FROM note_reviews WHERE note_id = ?1",
+            params![note_id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    /// Notes whose review date is at or before `until`, oldest first
+    pub fn get_notes_due_for_review(&self, until: DateTime<Utc>) -> SqliteResult<Vec<ReviewNote>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT n.id, n.title, n.folder_id, n.updated_at, n.preview, r.review_at
+             FROM note_reviews r
+             JOIN notes n ON n.id = r.note_id
+             WHERE n.is_deleted = 0 AND r.review_at <= ?1
+             ORDER BY r.review_at ASC",
+        )?;
+        let rows = stmt.query_map(params![to_rfc3339(until)], row_to_review_note)?;
+        rows.collect()
+    }
+
+    /// Due reviews that haven't been announced yet; marks them as announced
+    pub fn take_review_notifications(&self, now: DateTime<Utc>) -> SqliteResult<Vec<ReviewNote>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = to_rfc3339(now);
+
+        let due = {
+            let mut stmt = tx.prepare(
+                "SELECT n.id, n.title, n.folder_id, n.updated_at, n.preview, r.review_at
+                 FROM note_reviews r
+                 JOIN notes n ON n.id = r.note_id
+                 WHERE n.is_deleted = 0 AND r.review_at <= ?1 AND r.notified_at IS NULL
+                 ORDER BY r.review_at ASC",
+            )?;
+            let rows = stmt.query_map(params![&now], row_to_review_note)?;
+            rows.collect::<SqliteResult<Vec<_>>>()?
+        };
+
+        for note in &due {
+            tx.execute(
+                "UPDATE note_reviews SET notified_at = ?2 WHERE note_id = ?1",
+                params![&note.id, &now],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(due)
+    }
+
+    /// Live notes neither edited nor opened since `cutoff`, least recently
+    /// touched first
+    fn get_notes_untouched_since(&self, cutoff: DateTime<Utc>) -> SqliteResult<Vec<StaleNote>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "WITH opened AS (
+                SELECT note_id, MAX(recorded_at) AS at FROM note_activity
+                WHERE kind = 'open' GROUP BY note_id
+             )
+             SELECT n.id, n.title, n.folder_id, n.updated_at, n.preview, o.at
+             FROM notes n
+             LEFT JOIN opened o ON o.note_id = n.id
+             WHERE n.is_deleted = 0 AND n.updated_at < ?1 AND (o.at IS NULL OR o.at < ?1)
+             ORDER BY MAX(n.updated_at, COALESCE(o.at, '')) ASC, n.id",
+        )?;
+        let rows = stmt.query_map(params![to_rfc3339(cutoff)], |row| {
+            Ok(StaleNote {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                folder_id: row.get(2)?,
+                updated_at: row.get(3)?,
+                preview: row.get(4)?,
+                last_opened_at: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// `folder_ids` and all their subfolders
+    fn folders_with_descendants(&self, folder_ids: &[String]) -> SqliteResult<HashSet<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE descendants(id) AS (
+                SELECT id FROM folders WHERE id = ?1
+                UNION
+                SELECT f.id FROM folders f
+                JOIN descendants d ON f.parent_id = d.id
+            )
+            SELECT id FROM descendants",
+        )?;
+        let mut folders = HashSet::new();
+        for folder_id in folder_ids {
+            for id in stmt.query_map(params![folder_id], |row| row.get::<_, String>(0))? {
+                folders.insert(id?);
+            }
+        }
+        Ok(folders)
+    }
+}
+
+/// Notes neither edited nor opened in the last `months` months, least
+/// recently touched first. Notes in `exclude_folders` (e.g. archive folders)
+/// and their subfolders are left out.
+pub fn stale_notes(
+    db: &Database,
+    months: u32,
+    exclude_folders: &[String],
+    limit: Option<usize>,
+) -> Result<Vec<StaleNote>, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if months == 0 {
+        return Err("The threshold must be at least one month".to_string());
+    }
+    let cutoff = Utc::now()
+        .checked_sub_months(Months::new(months))
+        .ok_or_else(|| format!("Invalid threshold: {} months", months))?;
+    let excluded = db
+        .folders_with_descendants(exclude_folders)
+        .map_err(db_err)?;
+    Ok(db
+        .get_notes_untouched_since(cutoff)
+        .map_err(db_err)?
+        .into_iter()
+        .filter(|note| {
+            note.folder_id
+                .as_ref()
+                .is_none_or(|folder_id| !excluded.contains(folder_id))
+        })
+        .take(limit.unwrap_or(DEFAULT_STALE_LIMIT))
+        .collect())
+}
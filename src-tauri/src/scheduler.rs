@@ -0,0 +1,211 @@
+//! Background jobs that run periodically while the app is open.
+//!
+//! A single thread wakes up once a minute and runs each job against the managed
+//! database. Jobs should be quick and must not hold the database lock for long,
+//! since commands share the same connection.
+
+use std::thread;
+use std::time::Duration;
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::app_lock::{self, AppLock};
+use crate::crash_reports;
+use crate::data_dir::DataDir;
+use crate::database::Database;
+use crate::export_profiles;
+use crate::locks::NoteLocks;
+use crate::mirror;
+use crate::offline;
+use crate::retention;
+use crate::scripting::{Hook, ScriptHost};
+use crate::task_integrations;
+use crate::trash;
+
+/// How often jobs run
+const TICK: Duration = Duration::from_secs(60);
+
+/// Event emitted with the notes whose review date has arrived
+pub const REVIEW_DUE_EVENT: &str = "app://review-due";
+
+/// Start the scheduler thread
+pub fn start(app_handle: AppHandle) {
+    thread::spawn(move || loop {
+        run_jobs(&app_handle);
+        thread::sleep(TICK);
+    });
+}
+
+fn run_jobs(app_handle: &AppHandle) {
+    let Some(db) = app_handle.try_state::<Database>() else {
+        return;
+    };
+
+    // Reminders carry note titles, so they wait while the app is locked
+    if !lock_if_idle(app_handle) {
+        notify_due_reviews(app_handle, &db);
+    }
+    update_mirror(&db);
+    run_export_profiles(app_handle, &db);
+    prune_offline(&db);
+    refresh_retention_policy(app_handle, &db);
+    sync_task_completions(app_handle, &db);
+    empty_trash(&db);
+    upload_crash_reports(app_handle, &db);
+
+    if let Some(scripts) = app_handle.try_state::<ScriptHost>() {
+        scripts.dispatch(&db, Hook::Scheduled, None);
+    }
+}
+
+/// Lock the app after the idle timeout and tell the windows. Returns whether
+/// the app is locked.
+fn lock_if_idle(app_handle: &AppHandle) -> bool {
+    let Some(lock) = app_handle.try_state::<AppLock>() else {
+        return false;
+    };
+    if lock.lock_if_idle() {
+        let _ = app_handle.emit(app_lock::LOCKED_EVENT, ());
+    }
+    lock.is_locked()
+}
+
+fn update_mirror(db: &Database) {
+    match mirror::mirror_if_due(db) {
+        Ok(Some(report)) if report.written > 0 || report.removed > 0 => println!(
+            "[scheduler] mirror updated: {} written, {} removed",
+            report.written, report.removed
+        ),
+        Ok(_) => {}
+        Err(err) => eprintln!("[scheduler] mirror failed: {}", err),
+    }
+}
+
+fn run_export_profiles(app_handle: &AppHandle, db: &Database) {
+    let (Some(data_dir), Some(locks)) = (
+        app_handle.try_state::<DataDir>(),
+        app_handle.try_state::<NoteLocks>(),
+    ) else {
+        return;
+    };
+    match export_profiles::run_due_profiles(db, &locks, &data_dir.0) {
+        Ok(runs) => {
+            for run in runs {
+                match run {
+                    Ok(report) => println!(
+                        "[scheduler] export profile {}: {} notes written to {}",
+                        report.profile_id, report.written, report.destination
+                    ),
+                    Err(err) => eprintln!("[scheduler] export profile failed: {}", err),
+                }
+            }
+        }
+        Err(err) => eprintln!("[scheduler] export profile check failed: {}", err),
+    }
+}
+
+fn prune_offline(db: &Database) {
+    match offline::prune(db) {
+        Ok(evicted) if !evicted.is_empty() => {
+            println!(
+                "[scheduler] evicted {} notes to fit the offline budget",
+                evicted.len()
+            )
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("[scheduler] offline pruning failed: {}", err),
+    }
+}
+
+/// Fetch the retention policy in the background, so a slow server doesn't
+/// hold up the other jobs
+fn refresh_retention_policy(app_handle: &AppHandle, db: &Database) {
+    match retention::refresh_due(db) {
+        Ok(true) => {
+            let app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let db = app_handle.state::<Database>();
+                if let Err(err) = retention::pull_retention_policy(&db).await {
+                    eprintln!(
+                        "[scheduler] refreshing the retention policy failed: {}",
+                        err
+                    );
+                }
+            });
+        }
+        Ok(false) => {}
+        Err(err) => eprintln!("[scheduler] retention policy check failed: {}", err),
+    }
+}
+
+/// Poll task managers for completed tasks in the background
+fn sync_task_completions(app_handle: &AppHandle, db: &Database) {
+    match task_integrations::sync_due(db) {
+        Ok(true) => {
+            let app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let db = app_handle.state::<Database>();
+                let locks = app_handle.state::<NoteLocks>();
+                match task_integrations::sync_completions(&db, &locks).await {
+                    Ok(report) => {
+                        if report.ticked > 0 {
+                            println!(
+                                "[scheduler] ticked {} tasks completed in task managers",
+                                report.ticked
+                            );
+                        }
+                        for err in report.errors {
+                            eprintln!("[scheduler] task sync: {}", err);
+                        }
+                    }
+                    Err(err) => eprintln!("[scheduler] task sync failed: {}", err),
+                }
+            });
+        }
+        Ok(false) => {}
+        Err(err) => eprintln!("[scheduler] task sync check failed: {}", err),
+    }
+}
+
+fn empty_trash(db: &Database) {
+    match trash::auto_empty_if_due(db) {
+        Ok(Some(report)) if report.purged > 0 => {
+            println!("[scheduler] purged {} notes from the trash", report.purged)
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("[scheduler] emptying the trash failed: {}", err),
+    }
+}
+
+/// Send crash reports in the background if the user opted in to uploading
+fn upload_crash_reports(app_handle: &AppHandle, db: &Database) {
+    let Some(data_dir) = app_handle.try_state::<DataDir>() else {
+        return;
+    };
+    match crash_reports::upload_due(db, &data_dir.0) {
+        Ok(Some(dsn)) => {
+            let dir = data_dir.0.clone();
+            tauri::async_runtime::spawn(async move {
+                match crash_reports::upload_pending(&dir, &dsn).await {
+                    Ok(sent) if sent > 0 => {
+                        println!("[scheduler] uploaded {} crash reports", sent)
+                    }
+                    Ok(_) => {}
+                    Err(err) => eprintln!("[scheduler] crash report upload failed: {}", err),
+                }
+            });
+        }
+        Ok(None) => {}
+        Err(err) => eprintln!("[scheduler] crash report check failed: {}", err),
+    }
+}
+
+fn notify_due_reviews(app_handle: &AppHandle, db: &Database) {
+    match db.take_review_notifications(chrono::Utc::now()) {
+        Ok(due) if !due.is_empty() => {
+            let _ = app_handle.emit(REVIEW_DUE_EVENT, due);
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("[scheduler] review check failed: {}", err),
+    }
+}
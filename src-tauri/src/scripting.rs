@@ -0,0 +1,441 @@
+//! User automation scripts.
+//!
+//! With the `scripting` feature, `.rhai` files in the `scripts` folder of the
+//! app data directory are loaded into an embedded Rhai engine and may define
+//! any of these hooks:
+//!
+//! - `on_note_created(note)` after a note is created through the editor
+//! - `on_note_saved(note)` after any note is saved through the editor
+//! - `scheduled()` once a minute, from the background scheduler
+//!
+//! `note` is a map with `id`, `title`, `content`, `folder_id` and
+//! `updated_at`. Scripts can only reach the vault through a small API:
+//! `get_note(id)`, `notes_in_folder(folder_id)` (`()` for the root),
+//! `save_note(map)` (creates a note when `id` is missing, otherwise updates
+//! the fields given) and `log(message)`. There is no file, network or process
+//! access, `eval` is disabled and every call is capped in operations and
+//! depth. Writes made by scripts do not fire hooks, so scripts cannot trigger
+//! each other in a loop. Notes have no tag store yet, so tagging is not
+//! exposed.
+//!
+//! Hooks only run while the `scripts_enabled` setting is "true".
+
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::{Database, Note};
+use crate::settings::SCRIPTS_ENABLED;
+
+/// Extension of script files
+const SCRIPT_EXTENSION: &str = "rhai";
+
+/// Lifecycle events scripts can handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    NoteCreated,
+    NoteSaved,
+    Scheduled,
+}
+
+impl Hook {
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn function_name(&self) -> &'static str {
+        match self {
+            Hook::NoteCreated => "on_note_created",
+            Hook::NoteSaved => "on_note_saved",
+            Hook::Scheduled => "scheduled",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ScriptInfo {
+    pub name: String,
+    pub path: String,
+    /// Hook functions the script defines
+    pub hooks: Vec<String>,
+    /// Compile error, or the last error raised by one of its hooks
+    pub error: Option<String>,
+}
+
+/// Get the scripts directory path
+pub fn get_scripts_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("scripts")
+}
+
+/// Script files in `dir`, sorted by name so hooks run in a stable order
+fn script_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read scripts directory: {}", e))?;
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().and_then(|e| e.to_str()) == Some(SCRIPT_EXTENSION))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn script_name(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Loaded scripts and the engine that runs them, kept as managed state
+pub struct ScriptHost {
+    scripts_dir: PathBuf,
+    #[cfg(feature = "scripting")]
+    runtime: runtime::Runtime,
+}
+
+impl ScriptHost {
+    /// Create the host and load the scripts in `scripts_dir`
+    #[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+    pub fn new(app_handle: tauri::AppHandle, scripts_dir: PathBuf) -> Self {
+        let host = ScriptHost {
+            #[cfg(feature = "scripting")]
+            runtime: runtime::Runtime::new(app_handle),
+            scripts_dir,
+        };
+        if let Err(err) = host.reload() {
+            eprintln!("[scripting] failed to load scripts: {}", err);
+        }
+        host
+    }
+
+    /// Re-read every script from disk
+    pub fn reload(&self) -> Result<Vec<ScriptInfo>, String> {
+        let files = script_files(&self.scripts_dir)?;
+        #[cfg(feature = "scripting")]
+        self.runtime.load(&files);
+        #[cfg(not(feature = "scripting"))]
+        let _ = files;
+        self.list()
+    }
+
+    /// Scripts loaded by the engine, with the hooks they define
+    #[cfg(feature = "scripting")]
+    pub fn list(&self) -> Result<Vec<ScriptInfo>, String> {
+        Ok(self.runtime.info())
+    }
+
+    /// Scripts found on disk; without the engine none of them can run
+    #[cfg(not(feature = "scripting"))]
+    pub fn list(&self) -> Result<Vec<ScriptInfo>, String> {
+        Ok(script_files(&self.scripts_dir)?
+            .into_iter()
+            .map(|path| ScriptInfo {
+                name: script_name(&path),
+                path: path.to_string_lossy().to_string(),
+                hooks: Vec::new(),
+                error: Some("Scripting is not available in this build".to_string()),
+            })
+            .collect())
+    }
+
+    /// Run `hook` in every script that defines it. Script errors are logged
+    /// and recorded, never returned, so a broken script can't block a save.
+    #[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+    pub fn dispatch(&self, db: &Database, hook: Hook, note: Option<&Note>) {
+        let enabled = db
+            .get_setting(SCRIPTS_ENABLED)
+            .ok()
+            .flatten()
+            .is_some_and(|v| matches!(v.trim(), "1" | "true"));
+        #[cfg(feature = "scripting")]
+        if enabled {
+            self.runtime.dispatch(hook, note);
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+mod runtime {
+    use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use tauri::{AppHandle, Manager};
+
+    use rusqlite::{params, OptionalExtension};
+
+    use super::{script_name, Hook, ScriptInfo};
+    use crate::database::{save_note_in_tx, Database, Note, NoteInput};
+    use crate::locks::NoteLocks;
+    use crate::ydoc::edit_stored_doc;
+
+    /// Limits applied to every hook call
+    const MAX_OPERATIONS: u64 = 1_000_000;
+    const MAX_CALL_LEVELS: usize = 32;
+    const MAX_EXPR_DEPTH: usize = 64;
+    const MAX_STRING_SIZE: usize = 4 * 1024 * 1024;
+    const MAX_COLLECTION_SIZE: usize = 10_000;
+
+    struct LoadedScript {
+        name: String,
+        path: PathBuf,
+        ast: Option<AST>,
+        error: Option<String>,
+    }
+
+    impl LoadedScript {
+        fn hooks(&self) -> Vec<String> {
+            let Some(ast) = &self.ast else {
+                return Vec::new();
+            };
+            [Hook::NoteCreated, Hook::NoteSaved, Hook::Scheduled]
+                .into_iter()
+                .map(|h| h.function_name())
+                .filter(|name| ast.iter_functions().any(|f| f.name == *name))
+                .map(|name| name.to_string())
+                .collect()
+        }
+    }
+
+    pub struct Runtime {
+        engine: Engine,
+        scripts: Mutex<Vec<LoadedScript>>,
+    }
+
+    type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+    fn note_to_map(note: &Note) -> Map {
+        let mut map = Map::new();
+        map.insert("id".into(), note.id.clone().into());
+        map.insert("title".into(), note.title.clone().into());
+        map.insert("content".into(), note.content.clone().into());
+        map.insert(
+            "folder_id".into(),
+            note.folder_id.clone().map_or(Dynamic::UNIT, Dynamic::from),
+        );
+        map.insert("updated_at".into(), note.updated_at.to_string().into());
+        map
+    }
+
+    /// A string field of a script map; `()` and missing fields are `None`
+    fn map_string(map: &Map, key: &str) -> ScriptResult<Option<String>> {
+        match map.get(key) {
+            None => Ok(None),
+            Some(value) if value.is_unit() => Ok(None),
+            Some(value) => value
+                .clone()
+                .into_string()
+                .map(Some)
+                .map_err(|_| format!("'{}' must be a string", key).into()),
+        }
+    }
+
+    fn db_error(err: rusqlite::Error) -> Box<EvalAltResult> {
+        format!("Database error: {}", err).into()
+    }
+
+    /// Register the vault API. Functions reach the database through the app
+    /// handle, so they see the same state as the commands.
+    /// Save a note a script wrote. Scripts only have the HTML, so the content
+    /// of the note's stored document is replaced with it when it changed.
+    fn save_script_note(db: &Database, input: NoteInput) -> rusqlite::Result<Note> {
+        let mut conn = db.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let previous: Option<String> = match &input.id {
+            Some(id) => tx
+                .query_row(
+                    "SELECT content FROM notes WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            None => None,
+        };
+        let note = save_note_in_tx(&tx, input)?;
+        if previous.as_deref() != Some(note.content.as_str()) {
+            edit_stored_doc(&tx, &note.id, note.updated_at.as_str(), |txn, fragment| {
+                beck_markdown::replace_content(txn, fragment, &note.content);
+                true
+            })?;
+        }
+        tx.commit()?;
+        Ok(note)
+    }
+
+    fn register_api(engine: &mut Engine, app: AppHandle) {
+        let handle = app.clone();
+        engine.register_fn("get_note", move |id: &str| -> ScriptResult<Dynamic> {
+            let db = handle.state::<Database>();
+            Ok(db
+                .get_note_by_id(id)
+                .map_err(db_error)?
+                .map_or(Dynamic::UNIT, |note| note_to_map(&note).into()))
+        });
+
+        let handle = app.clone();
+        engine.register_fn(
+            "notes_in_folder",
+            move |folder_id: Dynamic| -> ScriptResult<Array> {
+                let folder_id = if folder_id.is_unit() {
+                    None
+                } else {
+                    Some(folder_id.into_string().map_err(|_| {
+                        Box::<EvalAltResult>::from("folder id must be a string or ()")
+                    })?)
+                };
+                let db = handle.state::<Database>();
+                let notes = db
+                    .get_notes_by_folder(folder_id.as_deref())
+                    .map_err(db_error)?;
+                Ok(notes
+                    .into_iter()
+                    .map(|n| {
+                        let mut map = Map::new();
+                        map.insert("id".into(), n.id.into());
+                        map.insert("title".into(), n.title.into());
+                        Dynamic::from(map)
+                    })
+                    .collect())
+            },
+        );
+
+        let handle = app.clone();
+        engine.register_fn("save_note", move |fields: Map| -> ScriptResult<Map> {
+            let db = handle.state::<Database>();
+            let id = map_string(&fields, "id")?;
+            let existing = match id.as_deref() {
+                Some(id) => db.get_note_by_id(id).map_err(db_error)?,
+                None => None,
+            };
+            let folder_id = if fields.contains_key("folder_id") {
+                map_string(&fields, "folder_id")?
+            } else {
+                existing.as_ref().and_then(|n| n.folder_id.clone())
+            };
+            let input = NoteInput {
+                id,
+                title: map_string(&fields, "title")?
+                    .or_else(|| existing.as_ref().map(|n| n.title.clone()))
+                    .unwrap_or_default(),
+                content: map_string(&fields, "content")?
+                    .or_else(|| existing.as_ref().map(|n| n.content.clone()))
+                    .unwrap_or_default(),
+                folder_id,
+                updated_at: None,
+                is_deleted: false,
+                is_canvas: existing.as_ref().is_some_and(|n| n.is_canvas),
+            };
+            let id = input.id.clone();
+            let note = handle.state::<NoteLocks>().write_with_ids(
+                || match id.as_deref() {
+                    Some(id) => db.save_note_ids(id).map_err(db_error),
+                    None => Ok(Vec::new()),
+                },
+                || {
+                    if db.unique_titles_enabled().map_err(db_error)? {
+                        if let Some(conflict) = db.find_title_conflict(&input).map_err(db_error)? {
+                            return Err(conflict.message.into());
+                        }
+                    }
+                    save_script_note(&db, input).map_err(db_error)
+                },
+            )?;
+            Ok(note_to_map(&note))
+        });
+
+        engine.register_fn("log", |message: &str| {
+            println!("[script] {}", message);
+        });
+    }
+
+    impl Runtime {
+        pub fn new(app: AppHandle) -> Self {
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            engine.set_max_call_levels(MAX_CALL_LEVELS);
+            engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+            engine.set_max_string_size(MAX_STRING_SIZE);
+            engine.set_max_array_size(MAX_COLLECTION_SIZE);
+            engine.set_max_map_size(MAX_COLLECTION_SIZE);
+            engine.disable_symbol("eval");
+            register_api(&mut engine, app);
+
+            Runtime {
+                engine,
+                scripts: Mutex::new(Vec::new()),
+            }
+        }
+
+        pub fn load(&self, files: &[PathBuf]) {
+            let scripts = files.iter().map(|path| self.compile(path)).collect();
+            *self.scripts.lock().unwrap() = scripts;
+        }
+
+        fn compile(&self, path: &Path) -> LoadedScript {
+            let compiled = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read script: {}", e))
+                .and_then(|source| {
+                    self.engine
+                        .compile(source)
+                        .map_err(|e| format!("Failed to compile script: {}", e))
+                });
+            let (ast, error) = match compiled {
+                Ok(ast) => (Some(ast), None),
+                Err(err) => (None, Some(err)),
+            };
+            LoadedScript {
+                name: script_name(path),
+                path: path.to_path_buf(),
+                ast,
+                error,
+            }
+        }
+
+        pub fn info(&self) -> Vec<ScriptInfo> {
+            self.scripts
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|s| ScriptInfo {
+                    name: s.name.clone(),
+                    path: s.path.to_string_lossy().to_string(),
+                    hooks: s.hooks(),
+                    error: s.error.clone(),
+                })
+                .collect()
+        }
+
+        pub fn dispatch(&self, hook: Hook, note: Option<&Note>) {
+            let function = hook.function_name();
+            let mut scripts = self.scripts.lock().unwrap();
+            for script in scripts.iter_mut() {
+                let Some(ast) = &script.ast else {
+                    continue;
+                };
+                if !ast.iter_functions().any(|f| f.name == function) {
+                    continue;
+                }
+                let mut scope = Scope::new();
+                let result = match note {
+                    Some(note) => self.engine.call_fn::<Dynamic>(
+                        &mut scope,
+                        ast,
+                        function,
+                        (note_to_map(note),),
+                    ),
+                    None => self
+                        .engine
+                        .call_fn::<Dynamic>(&mut scope, ast, function, ()),
+                };
+                if let Err(err) = result {
+                    eprintln!(
+                        "[scripting] {} failed in {}: {}",
+                        function, script.name, err
+                    );
+                    script.error = Some(format!("{}: {}", function, err));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Key/value application settings stored in the notes database.
+//!
+//! Values are stored as strings; callers parse them into the type they need.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+
+use crate::database::{now_rfc3339, Database};
+
+/// "true" to include per-day activity totals (see `analytics`) in sync pushes
+pub const ANALYTICS_SYNC: &str = "analytics_sync";
+
+/// "true" to keep a compressed, searchable copy of notes purged from the trash (see `trash`)
+pub const ARCHIVE_PURGED_NOTES: &str = "archive_purged_notes";
+
+/// Soft limit for the assets folder, in bytes. Exceeding it only produces a warning.
+pub const ASSET_QUOTA_BYTES: &str = "asset_quota_bytes";
+
+/// JSON array of redaction rules applied to exported and published notes (see `export`)
+pub const EXPORT_REDACTION_RULES: &str = "export_redaction_rules";
+
+/// Sentry-compatible DSN to upload crash reports to; unset keeps them on this
+/// device (see `crash_reports`)
+pub const CRASH_REPORT_DSN: &str = "crash_report_dsn";
+
+/// Purges of more notes than this need a confirmation token (default 20)
+pub const PURGE_CONFIRM_THRESHOLD: &str = "purge_confirm_threshold";
+
+/// "true" to refuse saving a note whose title another note in its folder already uses
+pub const UNIQUE_NOTE_TITLES: &str = "unique_note_titles";
+
+/// Number of database backups to keep; older ones are deleted. 0 keeps all.
+pub const BACKUP_RETENTION: &str = "backup_retention";
+
+/// Directory to keep a plain Markdown mirror of the vault in; unset turns the mirror off
+pub const MIRROR_DIR: &str = "mirror_dir";
+
+/// Minutes between mirror runs (default one day)
+pub const MIRROR_INTERVAL_MINUTES: &str = "mirror_interval_minutes";
+
+/// Bytes of note content to keep on this device; older notes beyond it are
+/// evicted and fetched from the server when opened. 0 keeps everything.
+pub const OFFLINE_BUDGET_BYTES: &str = "offline_budget_bytes";
+
+/// "true" to hold synced edits to existing notes for review; real-time CRDT
+/// updates are not held (see `incoming`)
+pub const REVIEW_INCOMING_CHANGES: &str = "review_incoming_changes";
+
+/// "true" to run the hooks defined by user scripts (see `scripting`)
+pub const SCRIPTS_ENABLED: &str = "scripts_enabled";
+
+/// Days a note stays in the trash before the scheduler purges it; unset or 0
+/// keeps notes until the trash is emptied by hand
+pub const TRASH_AUTO_EMPTY_DAYS: &str = "trash_auto_empty_days";
+
+pub fn ensure_settings_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY NOT NULL,
+            value TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Read a setting on a connection the caller already holds, e.g. in a transaction
+pub(crate) fn read_setting(conn: &Connection, key: &str) -> SqliteResult<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Write or clear a setting on a connection the caller already holds
+pub(crate) fn write_setting(conn: &Connection, key: &str, value: Option<&str>) -> SqliteResult<()> {
+    match value {
+        Some(value) => conn.execute(
+            "INSERT INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key) DO UPDATE SET
+                value = excluded.value,
+                updated_at = excluded.updated_at",
+            params![key, value, now_rfc3339()],
+        )?,
+        None => conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?,
+    };
+    Ok(())
+}
+
+impl Database {
+    /// Get a setting value, if set
+    pub fn get_setting(&self, key: &str) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        read_setting(&conn, key)
+    }
+
+    /// Set a setting value, or clear it when `value` is None
+    pub fn set_setting(&self, key: &str, value: Option<&str>) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        write_setting(&conn, key, value)
+    }
+
+    /// Get a setting parsed as an integer; unparsable values are treated as unset
+    pub fn get_setting_i64(&self, key: &str) -> SqliteResult<Option<i64>> {
+        Ok(self.get_setting(key)?.and_then(|v| v.trim().parse().ok()))
+    }
+}
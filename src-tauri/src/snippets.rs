@@ -0,0 +1,281 @@
+//! Index of code blocks found in note content.
+//!
+//! Every saved note is scanned for `<pre><code>` blocks, which are stored in
+//! `code_snippets` with their language so developers can search and export the
+//! snippets they keep in notes without opening each note.
+
+use beck_markdown::html::{self, Element, Node};
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::Database;
+
+/// Maximum number of results returned by a code search
+const SEARCH_LIMIT: i64 = 200;
+
+/// A code block extracted from a note
+#[derive(Debug, Serialize, Clone)]
+pub struct CodeSnippet {
+    pub id: i64,
+    pub note_id: String,
+    pub note_title: String,
+    /// Zero-based index of the block within the note
+    pub position: i64,
+    pub language: Option<String>,
+    pub content: String,
+}
+
+pub fn ensure_snippets_schema(conn: &Connection) -> SqliteResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'code_snippets')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS code_snippets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            language TEXT,
+            content TEXT NOT NULL,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_code_snippets_note_id ON code_snippets(note_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_code_snippets_language ON code_snippets(language)",
+        [],
+    )?;
+
+    // Backfill existing notes the first time the index is created
+    if !exists {
+        let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE is_deleted = 0")?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        for (id, content) in notes {
+            index_note_snippets(conn, &id, &content, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract (language, code) pairs from note HTML in document order
+pub fn extract_code_blocks(content: &str) -> Vec<(Option<String>, String)> {
+    let nodes = html::parse(content);
+    let mut blocks = Vec::new();
+    html::walk(&nodes, &mut |el: &Element| {
+        if el.name == "pre" {
+            blocks.push((code_language(el), el.text()));
+        }
+    });
+    blocks
+}
+
+/// Language from a `language-*` class on the `<pre>` or its `<code>` child
+fn code_language(pre: &Element) -> Option<String> {
+    let code = pre.children.iter().find_map(|n| match n {
+        Node::Element(el) if el.name == "code" => Some(el),
+        _ => None,
+    });
+    [Some(pre), code]
+        .into_iter()
+        .flatten()
+        .filter_map(|el| el.attr("class"))
+        .flat_map(|class| class.split_whitespace())
+        .find_map(|c| c.strip_prefix("language-"))
+        .filter(|lang| !lang.is_empty())
+        .map(|lang| lang.to_ascii_lowercase())
+}
+
+/// Replace the indexed snippets for a note. Deleted notes are dropped from the index.
+pub(crate) fn index_note_snippets(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+    is_deleted: bool,
+) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM code_snippets WHERE note_id = ?1",
+        params![note_id],
+    )?;
+    if is_deleted {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO code_snippets (note_id, position, language, content) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, (language, code)) in extract_code_blocks(content).into_iter().enumerate() {
+        if code.trim().is_empty() {
+            continue;
+        }
+        stmt.execute(params![note_id, position as i64, language, code])?;
+    }
+    Ok(())
+}
+
+/// Escape `%`, `_` and `\` for a LIKE pattern using `ESCAPE '\'`
+pub(crate) fn like_pattern(query: &str) -> String {
+    let mut out = String::from("%");
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('%');
+    out
+}
+
+impl Database {
+    /// Search indexed code snippets by content, optionally filtered by language
+    pub fn search_code(
+        &self,
+        query: &str,
+        language: Option<&str>,
+    ) -> SqliteResult<Vec<CodeSnippet>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT s.id, s.note_id, n.title, s.position, s.language, s.content
+             FROM code_snippets s
+             JOIN notes n ON n.id = s.note_id
+             WHERE n.is_deleted = 0
+               AND s.content LIKE ?1 ESCAPE '\\'
+               AND (?2 IS NULL OR s.language = ?2)
+             ORDER BY n.updated_at DESC, s.position ASC
+             LIMIT ?3",
+        )?;
+        let language = language.map(|l| l.to_ascii_lowercase());
+        let rows = stmt.query_map(
+            params![like_pattern(query), language, SEARCH_LIMIT],
+            |row| {
+                Ok(CodeSnippet {
+                    id: row.get(0)?,
+                    note_id: row.get(1)?,
+                    note_title: row.get(2)?,
+                    position: row.get(3)?,
+                    language: row.get(4)?,
+                    content: row.get(5)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// All snippets, optionally restricted to one note and/or language
+    pub fn get_code_snippets(
+        &self,
+        note_id: Option<&str>,
+        language: Option<&str>,
+    ) -> SqliteResult<Vec<CodeSnippet>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT s.id, s.note_id, n.title, s.position, s.language, s.content
+             FROM code_snippets s
+             JOIN notes n ON n.id = s.note_id
+             WHERE n.is_deleted = 0
+               AND (?1 IS NULL OR s.note_id = ?1)
+               AND (?2 IS NULL OR s.language = ?2)
+             ORDER BY n.title ASC, s.position ASC",
+        )?;
+        let language = language.map(|l| l.to_ascii_lowercase());
+        let rows = stmt.query_map(params![note_id, language], |row| {
+            Ok(CodeSnippet {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                note_title: row.get(2)?,
+                position: row.get(3)?,
+                language: row.get(4)?,
+                content: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+/// File extension for a code block language
+fn extension_for(language: Option<&str>) -> &str {
+    match language {
+        Some("rust") | Some("rs") => "rs",
+        Some("javascript") | Some("js") => "js",
+        Some("typescript") | Some("ts") => "ts",
+        Some("python") | Some("py") => "py",
+        Some("bash") | Some("sh") | Some("shell") | Some("zsh") => "sh",
+        Some("markdown") | Some("md") => "md",
+        Some("yaml") | Some("yml") => "yml",
+        Some("c++") | Some("cpp") => "cpp",
+        Some("csharp") | Some("c#") | Some("cs") => "cs",
+        Some("golang") | Some("go") => "go",
+        Some("kotlin") | Some("kt") => "kt",
+        Some("ruby") | Some("rb") => "rb",
+        Some(lang) if !lang.is_empty() && lang.chars().all(|c| c.is_ascii_alphanumeric()) => lang,
+        _ => "txt",
+    }
+}
+
+/// Make a note title safe to use in a file name
+fn file_stem(title: &str) -> String {
+    let stem: String = title
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = stem.trim_matches('-');
+    if stem.is_empty() {
+        "untitled".to_string()
+    } else {
+        stem.chars().take(64).collect()
+    }
+}
+
+/// Write snippets to individual files in `dir`. Returns the written paths.
+pub fn export_snippets(snippets: &[CodeSnippet], dir: &Path) -> Result<Vec<PathBuf>, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create export directory: {}", e))?;
+
+    let mut used: HashMap<String, usize> = HashMap::new();
+    let mut written = Vec::with_capacity(snippets.len());
+    for snippet in snippets {
+        let base = format!(
+            "{}-{}",
+            file_stem(&snippet.note_title),
+            snippet.position + 1
+        );
+        // Two notes can share a title; keep file names unique
+        let count = used.entry(base.clone()).or_insert(0);
+        *count += 1;
+        let name = if *count == 1 {
+            base
+        } else {
+            format!("{}-{}", base, count)
+        };
+
+        let path = dir.join(format!(
+            "{}.{}",
+            name,
+            extension_for(snippet.language.as_deref())
+        ));
+        fs::write(&path, &snippet.content)
+            .map_err(|e| format!("Failed to write snippet file: {}", e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
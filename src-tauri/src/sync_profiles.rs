@@ -0,0 +1,347 @@
+//! Sync server profiles, e.g. a self-hosted server at home and a work instance.
+//!
+//! Profiles are kept in the `sync_profiles` setting and one of them is active;
+//! their tokens live in the system keyring rather than the database. Each
+//! profile has its own sync cursors: the oplog push cursor (see `oplog`) and
+//! the time of its last pull, so what one server has acknowledged never counts
+//! as pushed to another. The first profile takes over the cursor of the server
+//! the app was already syncing with. A profile added later, or one whose
+//! server URL changes, starts with every row unpushed, as a first sync would.
+//! Switching is refused while notes are evicted (see `offline`), since they
+//! can only be fetched back from the server they were evicted against.
+
+use rusqlite::{Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::oplog::{journal_all_rows, last_op_id, pushed_op_key};
+use crate::settings::{read_setting, write_setting};
+use crate::timestamp::Timestamp;
+
+/// Settings key holding the profiles as a JSON array
+const PROFILES_KEY: &str = "sync_profiles";
+
+/// Settings key holding the id of the active profile
+const ACTIVE_PROFILE_KEY: &str = "active_sync_profile";
+
+/// Prefix of the settings keys holding each profile's last pull time
+const LAST_PULL_KEY: &str = "sync_last_pull";
+
+/// Keyring service the profile tokens are stored under
+const KEYRING_SERVICE: &str = "beck-sync";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncProfile {
+    pub id: String,
+    pub name: String,
+    /// Free-form tag shown with the profile, such as "home" or "work"
+    pub environment: String,
+    pub server_url: String,
+    pub username: Option<String>,
+    pub created_at: Timestamp,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncProfileInput {
+    /// Omit to add a profile
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub environment: String,
+    pub server_url: String,
+    pub username: Option<String>,
+}
+
+/// The active profile with what a sync needs to resume
+#[derive(Debug, Serialize, Clone)]
+pub struct ActiveSyncProfile {
+    pub profile: SyncProfile,
+    /// From the keyring; `None` until the user signs in
+    pub token: Option<String>,
+    /// Server time of the last successful pull; `None` before the first one
+    pub last_pull: Option<Timestamp>,
+}
+
+fn last_pull_key(profile_id: &str) -> String {
+    format!("{}:{}", LAST_PULL_KEY, profile_id)
+}
+
+fn read_profiles(conn: &Connection) -> SqliteResult<Vec<SyncProfile>> {
+    Ok(read_setting(conn, PROFILES_KEY)?
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+fn write_profiles(conn: &Connection, profiles: &[SyncProfile]) -> SqliteResult<()> {
+    let json = serde_json::to_string(profiles)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    write_setting(conn, PROFILES_KEY, Some(&json))
+}
+
+fn read_cursor(conn: &Connection, profile_id: Option<&str>) -> SqliteResult<i64> {
+    Ok(read_setting(conn, &pushed_op_key(profile_id))?
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0))
+}
+
+fn write_cursor(
+    conn: &Connection,
+    profile_id: Option<&str>,
+    op_id: Option<i64>,
+) -> SqliteResult<()> {
+    write_setting(
+        conn,
+        &pushed_op_key(profile_id),
+        op_id.map(|op_id| op_id.to_string()).as_deref(),
+    )
+}
+
+/// Give a profile fresh cursors: every row is journaled again and counts as
+/// unpushed for it. Profiles that had pushed everything skip the new entries.
+fn start_from_scratch(
+    conn: &Connection,
+    profiles: &[SyncProfile],
+    profile_id: &str,
+) -> SqliteResult<()> {
+    let before = last_op_id(conn)?;
+    journal_all_rows(conn)?;
+    let after = last_op_id(conn)?;
+    for other in profiles.iter().filter(|p| p.id != profile_id) {
+        if read_cursor(conn, Some(&other.id))? >= before {
+            write_cursor(conn, Some(&other.id), Some(after))?;
+        }
+    }
+    write_cursor(conn, Some(profile_id), Some(before))?;
+    write_setting(conn, &last_pull_key(profile_id), None)
+}
+
+fn keyring_entry(profile_id: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, profile_id).map_err(|e| format!("Keyring error: {}", e))
+}
+
+fn read_token(profile_id: &str) -> Result<Option<String>, String> {
+    match keyring_entry(profile_id)?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Keyring error: {}", e)),
+    }
+}
+
+/// Store a profile's token in the keyring, or remove it when `token` is None
+pub fn set_token(db: &Database, profile_id: &str, token: Option<&str>) -> Result<(), String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if !db
+        .list_sync_profiles()
+        .map_err(db_err)?
+        .iter()
+        .any(|p| p.id == profile_id)
+    {
+        return Err(format!("Sync profile not found: {}", profile_id));
+    }
+    let entry = keyring_entry(profile_id)?;
+    let result = match token {
+        Some(token) => entry.set_password(token),
+        None => match entry.delete_credential() {
+            Err(keyring::Error::NoEntry) => Ok(()),
+            other => other,
+        },
+    };
+    result.map_err(|e| format!("Keyring error: {}", e))
+}
+
+fn validate(input: &SyncProfileInput) -> Result<(), String> {
+    if input.name.trim().is_empty() {
+        return Err("A sync profile needs a name".to_string());
+    }
+    let url = input.server_url.trim();
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!(
+            "Invalid server URL '{}': use http:// or https://",
+            url
+        ));
+    }
+    Ok(())
+}
+
+impl Database {
+    pub fn list_sync_profiles(&self) -> SqliteResult<Vec<SyncProfile>> {
+        let conn = self.conn.lock().unwrap();
+        read_profiles(&conn)
+    }
+
+    /// Id of the active profile; `None` until a profile is added
+    pub(crate) fn active_sync_profile_id(&self) -> SqliteResult<Option<String>> {
+        self.get_setting(ACTIVE_PROFILE_KEY)
+    }
+
+    fn evicted_note_count(&self) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM offline_notes WHERE evicted = 1",
+            [],
+            |row| row.get(0),
+        )
+    }
+}
+
+/// Add or update a profile. The first profile becomes active and takes over
+/// the existing sync cursor; pointing a profile at another server resets its
+/// cursors and forgets its token.
+pub fn save_profile(db: &Database, input: SyncProfileInput) -> Result<SyncProfile, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    validate(&input)?;
+    let server_url = input.server_url.trim().trim_end_matches('/').to_string();
+
+    let mut conn = db.conn.lock().unwrap();
+    let tx = conn.transaction().map_err(db_err)?;
+    let mut profiles = read_profiles(&tx).map_err(db_err)?;
+
+    let (profile, server_changed) = match &input.id {
+        Some(id) => {
+            let existing = profiles
+                .iter_mut()
+                .find(|p| &p.id == id)
+                .ok_or_else(|| format!("Sync profile not found: {}", id))?;
+            let server_changed = existing.server_url != server_url;
+            existing.name = input.name.trim().to_string();
+            existing.environment = input.environment.trim().to_string();
+            existing.server_url = server_url;
+            existing.username = input.username.clone();
+            (existing.clone(), server_changed)
+        }
+        None => {
+            let profile = SyncProfile {
+                id: Uuid::new_v4().to_string(),
+                name: input.name.trim().to_string(),
+                environment: input.environment.trim().to_string(),
+                server_url,
+                username: input.username.clone(),
+                created_at: Timestamp::now(),
+            };
+            profiles.push(profile.clone());
+            (profile, true)
+        }
+    };
+    write_profiles(&tx, &profiles).map_err(db_err)?;
+
+    if profiles.len() == 1 && input.id.is_none() {
+        // The server the app was already syncing with becomes this profile
+        let cursor = read_cursor(&tx, None).map_err(db_err)?;
+        write_cursor(&tx, Some(&profile.id), Some(cursor)).map_err(db_err)?;
+        write_cursor(&tx, None, None).map_err(db_err)?;
+        write_setting(&tx, ACTIVE_PROFILE_KEY, Some(&profile.id)).map_err(db_err)?;
+    } else if server_changed {
+        start_from_scratch(&tx, &profiles, &profile.id).map_err(db_err)?;
+    }
+    tx.commit().map_err(db_err)?;
+    drop(conn);
+
+    if server_changed && input.id.is_some() {
+        set_token(db, &profile.id, None)?;
+    }
+    Ok(profile)
+}
+
+/// Remove a profile with its cursors and token. The active profile can't be
+/// removed; switch to another one first.
+pub fn delete_profile(db: &Database, profile_id: &str) -> Result<(), String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if db.active_sync_profile_id().map_err(db_err)?.as_deref() == Some(profile_id) {
+        return Err(
+            "The active sync profile can't be deleted; switch to another one first".to_string(),
+        );
+    }
+    set_token(db, profile_id, None)?;
+
+    let mut conn = db.conn.lock().unwrap();
+    let tx = conn.transaction().map_err(db_err)?;
+    let mut profiles = read_profiles(&tx).map_err(db_err)?;
+    profiles.retain(|p| p.id != profile_id);
+    write_profiles(&tx, &profiles).map_err(db_err)?;
+    write_cursor(&tx, Some(profile_id), None).map_err(db_err)?;
+    write_setting(&tx, &last_pull_key(profile_id), None).map_err(db_err)?;
+    tx.commit().map_err(db_err)
+}
+
+/// The active profile with its token and last pull time
+pub fn active_profile(db: &Database) -> Result<Option<ActiveSyncProfile>, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let Some(id) = db.active_sync_profile_id().map_err(db_err)? else {
+        return Ok(None);
+    };
+    let Some(profile) = db
+        .list_sync_profiles()
+        .map_err(db_err)?
+        .into_iter()
+        .find(|p| p.id == id)
+    else {
+        return Ok(None);
+    };
+    let last_pull = db
+        .get_setting(&last_pull_key(&id))
+        .map_err(db_err)?
+        .and_then(|v| Timestamp::parse(&v).ok());
+    Ok(Some(ActiveSyncProfile {
+        token: read_token(&id)?,
+        profile,
+        last_pull,
+    }))
+}
+
+/// The active profile's server URL, without a trailing slash, and the profile
+pub(crate) fn active_server(db: &Database) -> Result<(String, ActiveSyncProfile), String> {
+    let active = active_profile(db)?.ok_or_else(|| "No sync server is configured".to_string())?;
+    let base = active
+        .profile
+        .server_url
+        .trim()
+        .trim_end_matches('/')
+        .to_string();
+    Ok((base, active))
+}
+
+/// Make another profile the one synced with
+pub fn switch_profile(db: &Database, profile_id: &str) -> Result<ActiveSyncProfile, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if !db
+        .list_sync_profiles()
+        .map_err(db_err)?
+        .iter()
+        .any(|p| p.id == profile_id)
+    {
+        return Err(format!("Sync profile not found: {}", profile_id));
+    }
+    if db.active_sync_profile_id().map_err(db_err)?.as_deref() != Some(profile_id) {
+        let evicted = db.evicted_note_count().map_err(db_err)?;
+        if evicted > 0 {
+            return Err(format!(
+                "{} notes are evicted from this device and can only be fetched from the current server; restore or pin them before switching",
+                evicted
+            ));
+        }
+        db.set_setting(ACTIVE_PROFILE_KEY, Some(profile_id))
+            .map_err(db_err)?;
+    }
+    active_profile(db)?.ok_or_else(|| format!("Sync profile not found: {}", profile_id))
+}
+
+/// Record a successful pull from the active profile's server. The cursor
+/// never moves backwards.
+pub fn record_pull(db: &Database, server_time: &Timestamp) -> Result<(), String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let id = db
+        .active_sync_profile_id()
+        .map_err(db_err)?
+        .ok_or_else(|| "No sync profile is active".to_string())?;
+    let key = last_pull_key(&id);
+    let previous = db
+        .get_setting(&key)
+        .map_err(db_err)?
+        .and_then(|v| Timestamp::parse(&v).ok());
+    if previous.is_some_and(|previous| &previous >= server_time) {
+        return Ok(());
+    }
+    db.set_setting(&key, Some(server_time.as_str()))
+        .map_err(db_err)
+}
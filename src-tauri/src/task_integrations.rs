@@ -0,0 +1,1017 @@
+//! Sending a note's tasks to external task managers.
+//!
+//! A task integration is a CalDAV task list (tasks are stored there as
+//! `VTODO`s) or a Todoist REST v2 compatible service. Integrations belong to
+//! the user signed in to the active sync profile, or to the local user when
+//! there is none; their passwords and API tokens live in the system keyring
+//! rather than the database.
+//!
+//! Pushing a note's tasks creates a remote task for every open task list item
+//! not sent before, and closes or reopens the remote tasks of items ticked or
+//! unticked since. The link between the two is kept in `exported_tasks`, keyed
+//! by the item's text and how many items with the same text come before it,
+//! so it survives items being added or moved around it. Completing a task in
+//! the task manager ticks the item in the note when the open tasks are polled
+//! (`sync_completions`, every 15 minutes from the scheduler). Todoist doesn't
+//! return closed tasks, so a task deleted there counts as done; a `VTODO`
+//! deleted from a CalDAV list is marked removed and left alone.
+//!
+//! Task text is redacted like exports, and items tagged with a redacted tag
+//! are never sent. A remote task's notes link back to the note with
+//! `beck://note/<id>`.
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::time::Duration as StdDuration;
+use uuid::Uuid;
+
+use beck_markdown::{extract_tasks, set_task_checked, Redactor, Task};
+
+use crate::database::{now_rfc3339, Database, Note, NoteInput};
+use crate::export;
+use crate::locks::NoteLocks;
+use crate::sync_profiles;
+
+/// Keyring service the integration secrets are stored under
+const KEYRING_SERVICE: &str = "beck-task-integrations";
+
+/// Settings key holding when open tasks were last polled
+const LAST_SYNC_KEY: &str = "task_sync_last_run";
+
+/// Minutes between polls of the open tasks
+const SYNC_INTERVAL_MINUTES: i64 = 15;
+
+/// Todoist's REST API, used when a Todoist integration has no URL
+const TODOIST_API: &str = "https://api.todoist.com/rest/v2";
+
+/// How long a request to a task manager may take
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+/// Longest response body kept in an error
+const MAX_ERROR_LEN: usize = 500;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskService {
+    /// A CalDAV calendar collection holding `VTODO`s
+    CalDav,
+    /// Todoist's REST v2 API, or one compatible with it
+    Todoist,
+}
+
+impl TaskService {
+    fn name(self) -> &'static str {
+        match self {
+            TaskService::CalDav => "caldav",
+            TaskService::Todoist => "todoist",
+        }
+    }
+
+    fn parse(value: &str) -> TaskService {
+        match value {
+            "caldav" => TaskService::CalDav,
+            _ => TaskService::Todoist,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TaskIntegration {
+    pub id: String,
+    pub service: TaskService,
+    pub name: String,
+    /// Calendar collection URL (CalDAV) or API base URL (Todoist)
+    pub url: String,
+    /// CalDAV user name
+    pub username: Option<String>,
+    /// Todoist project id tasks are added to; the inbox when unset
+    pub project: Option<String>,
+    /// Whether a password or token is stored (it is never returned)
+    pub has_secret: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskIntegrationInput {
+    /// Omit to add an integration
+    pub id: Option<String>,
+    pub service: TaskService,
+    pub name: String,
+    /// Required for CalDAV; Todoist defaults to its public API
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub project: Option<String>,
+    /// CalDAV password or API token; omit to keep the stored one
+    pub secret: Option<String>,
+}
+
+/// A task list item sent to a task manager
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportedTask {
+    pub integration_id: String,
+    pub note_id: String,
+    /// The item's text in the note, unredacted
+    pub text: String,
+    /// Items with the same text before this one in the note
+    pub occurrence: usize,
+    pub remote_id: String,
+    pub remote_url: Option<String>,
+    /// "open", "completed" or "removed"
+    pub status: String,
+    pub exported_at: String,
+    pub synced_at: String,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct TaskPushReport {
+    /// Remote tasks created
+    pub created: usize,
+    /// Remote tasks closed or reopened
+    pub updated: usize,
+    /// Items already in step, ticked items never sent and removed tasks
+    pub unchanged: usize,
+    /// Items left out by the redaction rules
+    pub redacted: usize,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct TaskSyncReport {
+    /// Open remote tasks checked
+    pub polled: usize,
+    /// Items ticked in notes because their task was completed
+    pub ticked: usize,
+    /// Tasks deleted from a CalDAV list
+    pub removed: usize,
+    /// Errors, one per integration or note
+    pub errors: Vec<String>,
+}
+
+/// A task created in a task manager
+struct RemoteTask {
+    id: String,
+    url: Option<String>,
+}
+
+/// What Todoist returns for a task
+#[derive(Deserialize)]
+struct TodoistTask {
+    id: String,
+    url: Option<String>,
+    #[serde(default)]
+    is_completed: bool,
+}
+
+#[derive(Serialize)]
+struct TodoistNewTask<'a> {
+    content: &'a str,
+    description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_id: Option<&'a str>,
+}
+
+pub fn ensure_task_integrations_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS task_integrations (
+            id TEXT PRIMARY KEY NOT NULL,
+            owner TEXT NOT NULL,
+            service TEXT NOT NULL,
+            name TEXT NOT NULL,
+            url TEXT NOT NULL,
+            username TEXT,
+            project TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS exported_tasks (
+            integration_id TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            task_text TEXT NOT NULL,
+            occurrence INTEGER NOT NULL,
+            remote_id TEXT NOT NULL,
+            remote_url TEXT,
+            status TEXT NOT NULL,
+            exported_at TEXT NOT NULL,
+            synced_at TEXT NOT NULL,
+            PRIMARY KEY (integration_id, note_id, task_text, occurrence),
+            FOREIGN KEY (integration_id) REFERENCES task_integrations(id) ON DELETE CASCADE,
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_exported_tasks_status ON exported_tasks(status)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+const INTEGRATION_COLUMNS: &str =
+    "id, service, name, url, username, project, created_at, updated_at";
+
+const EXPORTED_COLUMNS: &str = "integration_id, note_id, task_text, occurrence, remote_id, \
+     remote_url, status, exported_at, synced_at";
+
+fn row_to_integration(row: &rusqlite::Row) -> SqliteResult<TaskIntegration> {
+    let id: String = row.get(0)?;
+    Ok(TaskIntegration {
+        has_secret: matches!(read_secret(&id), Ok(Some(_))),
+        id,
+        service: TaskService::parse(&row.get::<_, String>(1)?),
+        name: row.get(2)?,
+        url: row.get(3)?,
+        username: row.get(4)?,
+        project: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn row_to_exported(row: &rusqlite::Row) -> SqliteResult<ExportedTask> {
+    Ok(ExportedTask {
+        integration_id: row.get(0)?,
+        note_id: row.get(1)?,
+        text: row.get(2)?,
+        occurrence: row.get::<_, i64>(3)? as usize,
+        remote_id: row.get(4)?,
+        remote_url: row.get(5)?,
+        status: row.get(6)?,
+        exported_at: row.get(7)?,
+        synced_at: row.get(8)?,
+    })
+}
+
+impl Database {
+    fn save_task_integration(
+        &self,
+        owner: &str,
+        id: &str,
+        input: &TaskIntegrationInput,
+        url: &str,
+    ) -> SqliteResult<TaskIntegration> {
+        let conn = self.conn.lock().unwrap();
+        let now = now_rfc3339();
+        let non_empty = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        conn.execute(
+            "INSERT INTO task_integrations
+                (id, owner, service, name, url, username, project, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                service = excluded.service,
+                name = excluded.name,
+                url = excluded.url,
+                username = excluded.username,
+                project = excluded.project,
+                updated_at = excluded.updated_at",
+            params![
+                id,
+                owner,
+                input.service.name(),
+                input.name.trim(),
+                url,
+                non_empty(&input.username),
+                non_empty(&input.project),
+                now,
+            ],
+        )?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM task_integrations WHERE id = ?1",
+                INTEGRATION_COLUMNS
+            ),
+            params![id],
+            row_to_integration,
+        )
+    }
+
+    fn get_task_integration(&self, owner: &str, id: &str) -> SqliteResult<Option<TaskIntegration>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM task_integrations WHERE id = ?1 AND owner = ?2",
+                INTEGRATION_COLUMNS
+            ),
+            params![id, owner],
+            row_to_integration,
+        )
+        .optional()
+    }
+
+    pub fn list_task_integrations(&self, owner: &str) -> SqliteResult<Vec<TaskIntegration>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM task_integrations WHERE owner = ?1 ORDER BY name COLLATE NOCASE",
+            INTEGRATION_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![owner], row_to_integration)?;
+        rows.collect()
+    }
+
+    fn delete_task_integration(&self, owner: &str, id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute(
+            "DELETE FROM task_integrations WHERE id = ?1 AND owner = ?2",
+            params![id, owner],
+        )?;
+        if removed > 0 {
+            conn.execute(
+                "DELETE FROM exported_tasks WHERE integration_id = ?1",
+                params![id],
+            )?;
+        }
+        Ok(removed > 0)
+    }
+
+    /// Tasks sent from a note, to any of the owner's integrations
+    pub fn list_exported_tasks(
+        &self,
+        owner: &str,
+        note_id: &str,
+    ) -> SqliteResult<Vec<ExportedTask>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM exported_tasks
+             WHERE note_id = ?1
+               AND integration_id IN (SELECT id FROM task_integrations WHERE owner = ?2)
+             ORDER BY integration_id, exported_at",
+            EXPORTED_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![note_id, owner], row_to_exported)?;
+        rows.collect()
+    }
+
+    fn exported_tasks_for(
+        &self,
+        integration_id: &str,
+        note_id: &str,
+    ) -> SqliteResult<Vec<ExportedTask>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM exported_tasks WHERE integration_id = ?1 AND note_id = ?2",
+            EXPORTED_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![integration_id, note_id], row_to_exported)?;
+        rows.collect()
+    }
+
+    /// Open tasks of an integration, oldest poll first
+    fn open_exported_tasks(&self, integration_id: &str) -> SqliteResult<Vec<ExportedTask>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM exported_tasks
+             WHERE integration_id = ?1 AND status = 'open'
+             ORDER BY synced_at",
+            EXPORTED_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![integration_id], row_to_exported)?;
+        rows.collect()
+    }
+
+    fn has_open_exported_tasks(&self, owner: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM exported_tasks e
+             JOIN task_integrations i ON i.id = e.integration_id
+             WHERE e.status = 'open' AND i.owner = ?1)",
+            params![owner],
+            |row| row.get(0),
+        )
+    }
+
+    fn put_exported_task(&self, task: &ExportedTask) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO exported_tasks
+                (integration_id, note_id, task_text, occurrence, remote_id, remote_url,
+                 status, exported_at, synced_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(integration_id, note_id, task_text, occurrence) DO UPDATE SET
+                remote_id = excluded.remote_id,
+                remote_url = excluded.remote_url,
+                status = excluded.status,
+                synced_at = excluded.synced_at",
+            params![
+                &task.integration_id,
+                &task.note_id,
+                &task.text,
+                task.occurrence as i64,
+                &task.remote_id,
+                &task.remote_url,
+                &task.status,
+                &task.exported_at,
+                &task.synced_at,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn keyring_entry(integration_id: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, integration_id)
+        .map_err(|e| format!("Keyring error: {}", e))
+}
+
+fn read_secret(integration_id: &str) -> Result<Option<String>, String> {
+    match keyring_entry(integration_id)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Keyring error: {}", e)),
+    }
+}
+
+fn delete_secret(integration_id: &str) -> Result<(), String> {
+    match keyring_entry(integration_id)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Keyring error: {}", e)),
+    }
+}
+
+/// The user integrations belong to: the active profile's, or "" locally
+pub fn current_owner(db: &Database) -> Result<String, String> {
+    Ok(sync_profiles::active_profile(db)?
+        .and_then(|active| active.profile.username)
+        .unwrap_or_default())
+}
+
+/// Add or update an integration of the current user
+pub fn save_integration(
+    db: &Database,
+    input: &TaskIntegrationInput,
+) -> Result<TaskIntegration, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let owner = current_owner(db)?;
+    if input.name.trim().is_empty() {
+        return Err("A task integration needs a name".to_string());
+    }
+    let url = match (input.service, input.url.as_deref().map(str::trim)) {
+        (_, Some(url)) if !url.is_empty() => url.trim_end_matches('/').to_string(),
+        (TaskService::Todoist, _) => TODOIST_API.to_string(),
+        (TaskService::CalDav, _) => {
+            return Err("A CalDAV integration needs the task list's URL".to_string())
+        }
+    };
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!("Invalid URL '{}': use http:// or https://", url));
+    }
+    if input.service == TaskService::CalDav
+        && input
+            .username
+            .as_deref()
+            .is_none_or(|u| u.trim().is_empty())
+    {
+        return Err("A CalDAV integration needs a user name".to_string());
+    }
+
+    let id = match &input.id {
+        Some(id) => {
+            db.get_task_integration(&owner, id)
+                .map_err(db_err)?
+                .ok_or_else(|| format!("Task integration not found: {}", id))?;
+            id.clone()
+        }
+        None => Uuid::new_v4().to_string(),
+    };
+    let secret = input
+        .secret
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    match secret {
+        Some(secret) => keyring_entry(&id)?
+            .set_password(secret)
+            .map_err(|e| format!("Keyring error: {}", e))?,
+        None if read_secret(&id)?.is_none() => {
+            return Err("A task integration needs a password or API token".to_string())
+        }
+        None => {}
+    }
+    db.save_task_integration(&owner, &id, input, &url)
+        .map_err(db_err)
+}
+
+/// Remove an integration of the current user, forgetting which tasks were
+/// sent to it. The remote tasks are left alone.
+pub fn remove_integration(db: &Database, id: &str) -> Result<bool, String> {
+    let owner = current_owner(db)?;
+    let removed = db
+        .delete_task_integration(&owner, id)
+        .map_err(|e| format!("Database error: {}", e))?;
+    if removed {
+        delete_secret(id)?;
+    }
+    Ok(removed)
+}
+
+/// An integration of the current user with its secret
+fn load(db: &Database, id: &str) -> Result<(TaskIntegration, String), String> {
+    let owner = current_owner(db)?;
+    let integration = db
+        .get_task_integration(&owner, id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Task integration not found: {}", id))?;
+    let secret = read_secret(id)?
+        .ok_or_else(|| format!("No password or token stored for {}", integration.name))?;
+    Ok((integration, secret))
+}
+
+/// Tasks paired with how many tasks with the same text come before them
+fn with_occurrences(tasks: Vec<Task>) -> Vec<(Task, usize)> {
+    let mut seen: Vec<String> = Vec::new();
+    tasks
+        .into_iter()
+        .map(|task| {
+            let occurrence = seen.iter().filter(|t| **t == task.text).count();
+            seen.push(task.text.clone());
+            (task, occurrence)
+        })
+        .collect()
+}
+
+/// Send a note's tasks to an integration: create the open items not sent
+/// before and bring the completion state of those sent in line with the note
+pub async fn push_note_tasks(
+    db: &Database,
+    integration_id: &str,
+    note_id: &str,
+) -> Result<TaskPushReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let (integration, secret) = load(db, integration_id)?;
+    let note = db
+        .get_note_by_id(note_id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Note not found: {}", note_id))?;
+    let redactor = Redactor::new(&export::redaction_rules(db)?)?;
+    let title = export::redacted(db, &note)?.title;
+    let client = http_client()?;
+    let exported = db
+        .exported_tasks_for(integration_id, note_id)
+        .map_err(db_err)?;
+
+    let mut report = TaskPushReport::default();
+    for (task, occurrence) in with_occurrences(extract_tasks(&note.content)) {
+        if redactor.is_tagged_text(&task.text) {
+            report.redacted += 1;
+            continue;
+        }
+        let summary = redactor.redact_text(&task.text);
+        let now = now_rfc3339();
+        let previous = exported
+            .iter()
+            .find(|e| e.text == task.text && e.occurrence == occurrence);
+        match previous {
+            None if !task.checked => {
+                let remote =
+                    create_task(&client, &integration, &secret, &summary, &note, &title).await?;
+                db.put_exported_task(&ExportedTask {
+                    integration_id: integration.id.clone(),
+                    note_id: note.id.clone(),
+                    text: task.text.clone(),
+                    occurrence,
+                    remote_id: remote.id,
+                    remote_url: remote.url,
+                    status: "open".to_string(),
+                    exported_at: now.clone(),
+                    synced_at: now,
+                })
+                .map_err(db_err)?;
+                report.created += 1;
+            }
+            Some(previous)
+                if previous.status != "removed"
+                    && (previous.status == "completed") != task.checked =>
+            {
+                set_completed(
+                    &client,
+                    &integration,
+                    &secret,
+                    previous,
+                    &summary,
+                    &note,
+                    &title,
+                    task.checked,
+                )
+                .await?;
+                let mut updated = previous.clone();
+                updated.status = if task.checked { "completed" } else { "open" }.to_string();
+                updated.synced_at = now;
+                db.put_exported_task(&updated).map_err(db_err)?;
+                report.updated += 1;
+            }
+            _ => report.unchanged += 1,
+        }
+    }
+    Ok(report)
+}
+
+/// Whether the open tasks are due to be polled
+pub fn sync_due(db: &Database) -> Result<bool, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    if !db
+        .has_open_exported_tasks(&current_owner(db)?)
+        .map_err(db_err)?
+    {
+        return Ok(false);
+    }
+    let last_run = db
+        .get_setting(LAST_SYNC_KEY)
+        .map_err(db_err)?
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok());
+    Ok(last_run.is_none_or(|at| {
+        Utc::now().signed_duration_since(at) >= Duration::minutes(SYNC_INTERVAL_MINUTES)
+    }))
+}
+
+/// Poll the current user's open remote tasks and tick the items of those
+/// completed in the task manager. Notes locked by a job are skipped and
+/// picked up by the next poll.
+pub async fn sync_completions(db: &Database, locks: &NoteLocks) -> Result<TaskSyncReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    db.set_setting(LAST_SYNC_KEY, Some(&now_rfc3339()))
+        .map_err(db_err)?;
+    let owner = current_owner(db)?;
+    let client = http_client()?;
+
+    let mut report = TaskSyncReport::default();
+    for integration in db.list_task_integrations(&owner).map_err(db_err)? {
+        let open = db.open_exported_tasks(&integration.id).map_err(db_err)?;
+        if open.is_empty() {
+            continue;
+        }
+        let Some(secret) = read_secret(&integration.id)? else {
+            report
+                .errors
+                .push(format!("{}: no password or token stored", integration.name));
+            continue;
+        };
+        for task in open {
+            report.polled += 1;
+            let completed = match is_completed(&client, &integration, &secret, &task).await {
+                Ok(completed) => completed,
+                Err(err) => {
+                    report.errors.push(format!("{}: {}", integration.name, err));
+                    break;
+                }
+            };
+            let status = match completed {
+                Some(false) => continue,
+                Some(true) => match tick(db, locks, &task) {
+                    Ok(ticked) => {
+                        report.ticked += ticked as usize;
+                        "completed"
+                    }
+                    Err(err) => {
+                        report.errors.push(err);
+                        continue;
+                    }
+                },
+                None => {
+                    report.removed += 1;
+                    "removed"
+                }
+            };
+            let mut updated = task.clone();
+            updated.status = status.to_string();
+            updated.synced_at = now_rfc3339();
+            db.put_exported_task(&updated).map_err(db_err)?;
+        }
+    }
+    Ok(report)
+}
+
+/// Tick the item an exported task came from. Returns whether the note changed;
+/// items edited or deleted since are left alone.
+fn tick(db: &Database, locks: &NoteLocks, task: &ExportedTask) -> Result<bool, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let Some(note) = db.get_note_by_id(&task.note_id).map_err(db_err)? else {
+        return Ok(false);
+    };
+    let item = with_occurrences(extract_tasks(&note.content))
+        .into_iter()
+        .find(|(t, occurrence)| t.text == task.text && *occurrence == task.occurrence);
+    let Some((item, _)) = item else {
+        return Ok(false);
+    };
+    if item.checked {
+        return Ok(false);
+    }
+    let Some(content) = set_task_checked(&note.content, item.index, true) else {
+        return Ok(false);
+    };
+    locks.write([note.id.clone()], || {
+        db.save_note(NoteInput {
+            id: Some(note.id),
+            title: note.title,
+            content,
+            folder_id: note.folder_id,
+            updated_at: None,
+            is_deleted: false,
+            is_canvas: note.is_canvas,
+        })
+        .map_err(db_err)
+    })?;
+    Ok(true)
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("Beck/", env!("CARGO_PKG_VERSION"), " (tasks)"))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// Send a request, turning failures and error statuses into messages
+async fn send(request: reqwest::RequestBuilder, url: &str) -> Result<reqwest::Response, String> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    if response.status().is_success() || response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(response);
+    }
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    let mut error = format!("{} returned {}", url, status);
+    if !text.trim().is_empty() {
+        error.push_str(": ");
+        error.extend(text.trim().chars().take(MAX_ERROR_LEN));
+    }
+    Err(error)
+}
+
+fn not_found(response: &reqwest::Response, url: &str) -> Result<(), String> {
+    match response.status() {
+        reqwest::StatusCode::NOT_FOUND => Err(format!("{} returned 404 Not Found", url)),
+        _ => Ok(()),
+    }
+}
+
+/// What a remote task's notes say about where it came from
+fn description(note: &Note, title: &str) -> String {
+    let title = if title.trim().is_empty() {
+        "Untitled"
+    } else {
+        title
+    };
+    format!("From \"{}\" in Beck: beck://note/{}", title, note.id)
+}
+
+async fn create_task(
+    client: &reqwest::Client,
+    integration: &TaskIntegration,
+    secret: &str,
+    summary: &str,
+    note: &Note,
+    title: &str,
+) -> Result<RemoteTask, String> {
+    match integration.service {
+        TaskService::CalDav => {
+            let uid = Uuid::new_v4().to_string();
+            let url = format!("{}/{}.ics", integration.url, uid);
+            let body = vtodo(&uid, summary, &description(note, title), false);
+            let response = send(
+                client
+                    .put(&url)
+                    .basic_auth(
+                        integration.username.as_deref().unwrap_or_default(),
+                        Some(secret),
+                    )
+                    .header(
+                        reqwest::header::CONTENT_TYPE,
+                        "text/calendar; charset=utf-8",
+                    )
+                    .header(reqwest::header::IF_NONE_MATCH, "*")
+                    .body(body),
+                &url,
+            )
+            .await?;
+            not_found(&response, &url)?;
+            Ok(RemoteTask {
+                id: uid,
+                url: Some(url),
+            })
+        }
+        TaskService::Todoist => {
+            let url = format!("{}/tasks", integration.url);
+            let body = serde_json::to_vec(&TodoistNewTask {
+                content: summary,
+                description: &description(note, title),
+                project_id: integration.project.as_deref(),
+            })
+            .map_err(|e| format!("Failed to encode task: {}", e))?;
+            let response = send(
+                client
+                    .post(&url)
+                    .bearer_auth(secret)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body),
+                &url,
+            )
+            .await?;
+            not_found(&response, &url)?;
+            let task: TodoistTask = parse_json(response, &url).await?;
+            Ok(RemoteTask {
+                id: task.id,
+                url: task.url,
+            })
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn set_completed(
+    client: &reqwest::Client,
+    integration: &TaskIntegration,
+    secret: &str,
+    task: &ExportedTask,
+    summary: &str,
+    note: &Note,
+    title: &str,
+    completed: bool,
+) -> Result<(), String> {
+    match integration.service {
+        TaskService::CalDav => {
+            let url = task
+                .remote_url
+                .clone()
+                .unwrap_or_else(|| format!("{}/{}.ics", integration.url, task.remote_id));
+            let body = vtodo(
+                &task.remote_id,
+                summary,
+                &description(note, title),
+                completed,
+            );
+            let response = send(
+                client
+                    .put(&url)
+                    .basic_auth(
+                        integration.username.as_deref().unwrap_or_default(),
+                        Some(secret),
+                    )
+                    .header(
+                        reqwest::header::CONTENT_TYPE,
+                        "text/calendar; charset=utf-8",
+                    )
+                    .body(body),
+                &url,
+            )
+            .await?;
+            not_found(&response, &url)
+        }
+        TaskService::Todoist => {
+            let action = if completed { "close" } else { "reopen" };
+            let url = format!("{}/tasks/{}/{}", integration.url, task.remote_id, action);
+            let response = send(client.post(&url).bearer_auth(secret), &url).await?;
+            not_found(&response, &url)
+        }
+    }
+}
+
+/// Whether a remote task is completed; `None` if it no longer exists
+async fn is_completed(
+    client: &reqwest::Client,
+    integration: &TaskIntegration,
+    secret: &str,
+    task: &ExportedTask,
+) -> Result<Option<bool>, String> {
+    match integration.service {
+        TaskService::CalDav => {
+            let url = task
+                .remote_url
+                .clone()
+                .unwrap_or_else(|| format!("{}/{}.ics", integration.url, task.remote_id));
+            let response = send(
+                client.get(&url).basic_auth(
+                    integration.username.as_deref().unwrap_or_default(),
+                    Some(secret),
+                ),
+                &url,
+            )
+            .await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let calendar = response
+                .text()
+                .await
+                .map_err(|e| format!("Failed to read response from {}: {}", url, e))?;
+            Ok(Some(vtodo_completed(&calendar)))
+        }
+        TaskService::Todoist => {
+            let url = format!("{}/tasks/{}", integration.url, task.remote_id);
+            let response = send(client.get(&url).bearer_auth(secret), &url).await?;
+            // Closed tasks aren't returned at all
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(Some(true));
+            }
+            let task: TodoistTask = parse_json(response, &url).await?;
+            Ok(Some(task.is_completed))
+        }
+    }
+}
+
+async fn parse_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+    url: &str,
+) -> Result<T, String> {
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response from {}: {}", url, e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid response from {}: {}", url, e))
+}
+
+/// Escape an iCalendar TEXT value
+fn ical_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Fold a content line at 75 octets, as iCalendar requires
+fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + 8);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+/// A calendar holding one `VTODO`
+fn vtodo(uid: &str, summary: &str, description: &str, completed: bool) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//Beck//Beck {}//EN", env!("CARGO_PKG_VERSION")),
+        "BEGIN:VTODO".to_string(),
+        format!("UID:{}", uid),
+        format!("DTSTAMP:{}", stamp),
+        format!("SUMMARY:{}", ical_text(summary)),
+        format!("DESCRIPTION:{}", ical_text(description)),
+    ];
+    if completed {
+        lines.push("STATUS:COMPLETED".to_string());
+        lines.push(format!("COMPLETED:{}", stamp));
+        lines.push("PERCENT-COMPLETE:100".to_string());
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_string());
+    }
+    lines.push("END:VTODO".to_string());
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// Whether a calendar's `VTODO` is completed, by its `STATUS` or `COMPLETED`
+/// property
+fn vtodo_completed(calendar: &str) -> bool {
+    let unfolded = calendar.replace("\r\n ", "").replace("\n ", "");
+    let mut in_todo = false;
+    for line in unfolded.lines() {
+        let line = line.trim_end_matches('\r');
+        match line {
+            "BEGIN:VTODO" => in_todo = true,
+            "END:VTODO" => in_todo = false,
+            _ if in_todo => {
+                let (name, value) = line.split_once(':').unwrap_or((line, ""));
+                let name = name.split(';').next().unwrap_or(name);
+                if name.eq_ignore_ascii_case("STATUS") && value.eq_ignore_ascii_case("COMPLETED")
+                    || name.eq_ignore_ascii_case("COMPLETED")
+                {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
@@ -0,0 +1,236 @@
+//! Saved note templates, local and shared.
+//!
+//! Local templates live only on this device. Workspace templates are shared
+//! through the sync server (`/api/templates`) and cached here read-only: each
+//! pull replaces the cached set with the server's, so edits and deletions made
+//! on the server show up and templates of a previous sync profile go away.
+//! Listing merges both; a local template shadows a workspace template with the
+//! same name (ignoring case), so users can keep their own variant.
+//!
+//! Placeholders in template content are filled in by `templates::render`.
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::offline;
+use crate::sync_profiles;
+use crate::timestamp::Timestamp;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateSource {
+    Local,
+    Workspace,
+}
+
+impl TemplateSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TemplateSource::Local => "local",
+            TemplateSource::Workspace => "workspace",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NoteTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub content: String,
+    pub source: TemplateSource,
+    pub updated_at: Timestamp,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateInput {
+    /// Omit to add a template
+    pub id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub content: String,
+}
+
+/// A template as listed by `GET /api/templates`
+#[derive(Debug, Deserialize)]
+struct RemoteTemplate {
+    id: String,
+    name: String,
+    description: Option<String>,
+    content: String,
+    updated_at: Timestamp,
+}
+
+pub fn ensure_template_library_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_templates (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            description TEXT,
+            content TEXT NOT NULL,
+            source TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn parse_source(value: String) -> SqliteResult<TemplateSource> {
+    match value.as_str() {
+        "local" => Ok(TemplateSource::Local),
+        "workspace" => Ok(TemplateSource::Workspace),
+        _ => Err(rusqlite::Error::InvalidColumnType(
+            4,
+            "source".to_string(),
+            rusqlite::types::Type::Text,
+        )),
+    }
+}
+
+fn row_to_template(row: &rusqlite::Row) -> SqliteResult<NoteTemplate> {
+    Ok(NoteTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        content: row.get(3)?,
+        source: parse_source(row.get(4)?)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+const TEMPLATE_COLUMNS: &str = "id, name, description, content, source, updated_at";
+
+impl Database {
+    /// Local and workspace templates by name, without workspace templates
+    /// shadowed by a local one
+    pub fn list_note_templates(&self) -> SqliteResult<Vec<NoteTemplate>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM note_templates ORDER BY name COLLATE NOCASE, source",
+            TEMPLATE_COLUMNS
+        ))?;
+        let templates = stmt
+            .query_map([], row_to_template)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        let local: HashSet<String> = templates
+            .iter()
+            .filter(|t| t.source == TemplateSource::Local)
+            .map(|t| t.name.to_lowercase())
+            .collect();
+        Ok(templates
+            .into_iter()
+            .filter(|t| {
+                t.source == TemplateSource::Local || !local.contains(&t.name.to_lowercase())
+            })
+            .collect())
+    }
+
+    /// Add a local template (omit `id`) or update one. Local names are
+    /// unique, ignoring case.
+    pub fn save_local_template(&self, input: TemplateInput) -> Result<NoteTemplate, String> {
+        let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+        let name = input.name.trim();
+        if name.is_empty() {
+            return Err("Template name cannot be empty".to_string());
+        }
+        let id = input.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let description = input
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty());
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_err)?;
+        let source: Option<String> = tx
+            .query_row(
+                "SELECT source FROM note_templates WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_err)?;
+        if source.as_deref() == Some(TemplateSource::Workspace.as_str()) {
+            return Err("Workspace templates are edited on the sync server".to_string());
+        }
+        let taken: bool = tx
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM note_templates
+                 WHERE source = 'local' AND name = ?1 COLLATE NOCASE AND id <> ?2)",
+                params![name, id],
+                |row| row.get(0),
+            )
+            .map_err(db_err)?;
+        if taken {
+            return Err(format!("A template named \"{}\" already exists", name));
+        }
+        tx.execute(
+            "INSERT INTO note_templates (id, name, description, content, source, updated_at)
+             VALUES (?1, ?2, ?3, ?4, 'local', ?5)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                content = excluded.content,
+                updated_at = excluded.updated_at",
+            params![id, name, description, input.content, Timestamp::now()],
+        )
+        .map_err(db_err)?;
+        let saved = tx
+            .query_row(
+                &format!(
+                    "SELECT {} FROM note_templates WHERE id = ?1",
+                    TEMPLATE_COLUMNS
+                ),
+                params![id],
+                row_to_template,
+            )
+            .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+        Ok(saved)
+    }
+
+    /// Delete a local template. Returns false if there was none with this id.
+    pub fn delete_local_template(&self, id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "DELETE FROM note_templates WHERE id = ?1 AND source = 'local'",
+            params![id],
+        )? > 0)
+    }
+
+    fn replace_workspace_templates(&self, templates: &[RemoteTemplate]) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM note_templates WHERE source = 'workspace'", [])?;
+        for template in templates {
+            // Ids are UUIDs on both sides; a local template can't collide
+            tx.execute(
+                "INSERT OR IGNORE INTO note_templates (id, name, description, content, source, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, 'workspace', ?5)",
+                params![
+                    template.id,
+                    template.name,
+                    template.description,
+                    template.content,
+                    template.updated_at,
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+/// Replace the cached workspace templates with the active sync server's.
+/// Returns how many there are.
+pub async fn pull_workspace_templates(db: &Database) -> Result<usize, String> {
+    let (base, active) = sync_profiles::active_server(db)?;
+    let templates: Vec<RemoteTemplate> =
+        offline::get_json(&format!("{}/api/templates", base), active.token.as_deref()).await?;
+    db.replace_workspace_templates(&templates)
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(templates.len())
+}
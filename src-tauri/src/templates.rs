@@ -0,0 +1,156 @@
+//! Computed placeholders for note templates.
+//!
+//! A template is note HTML containing `{{ ... }}` placeholders, which are
+//! replaced when a note is created from it. The grammar is deliberately tiny:
+//! no variables, loops or function calls, so a template can never do more than
+//! print a date or a value the caller supplied.
+//!
+//! ```text
+//! placeholder := "{{" name [offset] ["|" format] "}}"
+//! name        := "date" | "time" | "datetime" | "weekday" | "week" | "year"
+//!              | "month" | "day" | "title" | "clipboard" | "cursor"
+//! offset      := ("+" | "-") digits unit        (date names only)
+//! unit        := "d" | "w" | "m" | "y"           (days, weeks, months, years)
+//! format      := a strftime pattern, e.g. "%d %B %Y" (date names only)
+//! ```
+//!
+//! Whitespace inside the braces is ignored. Dates use the local time zone, so
+//! `{{date+7d}}` is a week from today and `{{weekday-1d}}` is yesterday's
+//! weekday. `{{cursor}}` is removed and its position reported so the editor
+//! can place the caret there. Placeholders that don't parse are left as they
+//! are, which keeps unrelated `{{` text in templates intact.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Duration, Local, Months};
+use serde::Serialize;
+
+use beck_markdown::html::escape;
+
+/// Longest placeholder body considered; anything longer is left alone
+const MAX_PLACEHOLDER_LEN: usize = 64;
+
+/// Values supplied by the caller
+#[derive(Debug, Default)]
+pub struct TemplateContext {
+    pub title: Option<String>,
+    pub clipboard: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RenderedTemplate {
+    pub content: String,
+    /// Character offset where `{{cursor}}` was, if the template had one
+    pub cursor: Option<usize>,
+}
+
+/// Shift `now` by an offset such as `+7d` or `-1m`
+fn apply_offset(now: DateTime<Local>, offset: &str) -> Option<DateTime<Local>> {
+    let (sign, rest) = match offset.chars().next()? {
+        '+' => (1, &offset[1..]),
+        '-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let unit = rest.chars().last()?;
+    let amount: u32 = rest[..rest.len() - unit.len_utf8()].trim().parse().ok()?;
+    let months = |n: u32| {
+        if sign > 0 {
+            now.checked_add_months(Months::new(n))
+        } else {
+            now.checked_sub_months(Months::new(n))
+        }
+    };
+    match unit {
+        'd' => now.checked_add_signed(Duration::days(sign * amount as i64)),
+        'w' => now.checked_add_signed(Duration::weeks(sign * amount as i64)),
+        'm' => months(amount),
+        'y' => months(amount.checked_mul(12)?),
+        _ => None,
+    }
+}
+
+/// Format `date` with a user pattern, refusing patterns chrono can't render
+fn format_date(date: DateTime<Local>, pattern: &str) -> Option<String> {
+    let items: Vec<Item> = StrftimeItems::new(pattern).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+    Some(date.format_with_items(items.into_iter()).to_string())
+}
+
+/// Evaluate one placeholder body. `None` leaves the placeholder untouched.
+fn evaluate(body: &str, now: DateTime<Local>, ctx: &TemplateContext) -> Option<String> {
+    let (expr, format) = match body.split_once('|') {
+        Some((expr, format)) => (expr.trim(), Some(format.trim())),
+        None => (body.trim(), None),
+    };
+    let name_end = expr.find(['+', '-']).unwrap_or(expr.len());
+    let (name, offset) = (expr[..name_end].trim(), expr[name_end..].trim());
+
+    let default_format = match name {
+        "date" => "%Y-%m-%d",
+        "time" => "%H:%M",
+        "datetime" => "%Y-%m-%d %H:%M",
+        "weekday" => "%A",
+        "year" => "%Y",
+        "month" => "%B",
+        "day" => "%-d",
+        "week" => "",
+        _ => {
+            // Plain values take neither an offset nor a format
+            if !offset.is_empty() || format.is_some() {
+                return None;
+            }
+            return match name {
+                "title" => Some(escape(ctx.title.as_deref().unwrap_or_default())),
+                "clipboard" => Some(escape(ctx.clipboard.as_deref().unwrap_or_default())),
+                _ => None,
+            };
+        }
+    };
+
+    let date = if offset.is_empty() {
+        now
+    } else {
+        apply_offset(now, offset)?
+    };
+    match (name, format) {
+        ("week", None) => Some(date.iso_week().week().to_string()),
+        (_, Some(pattern)) => format_date(date, pattern).map(|s| escape(&s)),
+        (_, None) => format_date(date, default_format),
+    }
+}
+
+/// Replace the placeholders in `template`
+pub fn render(template: &str, ctx: &TemplateContext) -> RenderedTemplate {
+    let now = Local::now();
+    let mut content = String::with_capacity(template.len());
+    let mut cursor = None;
+    let mut rest = template;
+
+    while let Some(open) = rest.find("{{") {
+        content.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let close = after
+            .find("}}")
+            .filter(|&close| close <= MAX_PLACEHOLDER_LEN && !after[..close].contains("{{"));
+        let Some(close) = close else {
+            content.push_str("{{");
+            rest = after;
+            continue;
+        };
+
+        let body = &after[..close];
+        if body.trim() == "cursor" {
+            cursor.get_or_insert(content.chars().count());
+        } else {
+            match evaluate(body, now, ctx) {
+                Some(value) => content.push_str(&value),
+                None => content.push_str(&rest[open..open + 2 + close + 2]),
+            }
+        }
+        rest = &after[close + 2..];
+    }
+    content.push_str(rest);
+
+    RenderedTemplate { content, cursor }
+}
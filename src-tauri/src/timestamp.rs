@@ -0,0 +1,118 @@
+//! Canonical timestamps.
+//!
+//! Sync conflict resolution compares `updated_at` values as strings, which is
+//! only correct when every value has the same shape. [`Timestamp`] accepts any
+//! RFC 3339 value at the command boundary and stores it in one canonical form,
+//! UTC with millisecond precision (`2024-01-31T09:15:00.000Z`), so string order
+//! matches time order.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// An RFC 3339 timestamp in canonical form
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timestamp(String);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self::from_datetime(Utc::now())
+    }
+
+    pub fn from_datetime(dt: DateTime<Utc>) -> Self {
+        Timestamp(dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+    }
+
+    /// Parse any RFC 3339 timestamp into canonical form
+    pub fn parse(value: &str) -> Result<Self, String> {
+        DateTime::parse_from_rfc3339(value.trim())
+            .map(|dt| Self::from_datetime(dt.with_timezone(&Utc)))
+            .map_err(|e| format!("Invalid timestamp '{}': {}", value, e))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Timestamp {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Timestamp::parse(&value)
+    }
+}
+
+impl From<Timestamp> for String {
+    fn from(ts: Timestamp) -> Self {
+        ts.0
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ToSql for Timestamp {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+impl FromSql for Timestamp {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let raw = value.as_str()?;
+        Timestamp::parse(raw).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
+
+/// Columns holding timestamps that take part in sync comparisons
+const TIMESTAMP_COLUMNS: &[(&str, &str)] = &[
+    ("notes", "updated_at"),
+    ("folders", "created_at"),
+    ("folders", "updated_at"),
+    ("crdt_states", "updated_at"),
+    ("boards", "created_at"),
+    ("boards", "updated_at"),
+    ("board_columns", "updated_at"),
+    ("board_cards", "updated_at"),
+    ("note_relations", "created_at"),
+    ("note_relations", "updated_at"),
+];
+
+/// GLOB matching the canonical form; anything else needs rewriting
+const CANONICAL_GLOB: &str =
+    "[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]T[0-9][0-9]:[0-9][0-9]:[0-9][0-9].[0-9][0-9][0-9]Z";
+
+/// Rewrite stored timestamps that are not in canonical form.
+///
+/// Values that don't parse at all are replaced with the current time: they
+/// were written locally, so treating them as the latest edit keeps them from
+/// being silently overwritten by an older remote copy.
+pub fn normalize_timestamps(conn: &Connection) -> SqliteResult<()> {
+    let now = Timestamp::now();
+    for (table, column) in TIMESTAMP_COLUMNS {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT rowid, {column} FROM {table} WHERE {column} NOT GLOB ?1"
+        ))?;
+        let rows = stmt
+            .query_map(params![CANONICAL_GLOB], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        for (rowid, value) in rows {
+            let fixed = Timestamp::parse(&value).unwrap_or_else(|_| now.clone());
+            conn.execute(
+                &format!("UPDATE {table} SET {column} = ?2 WHERE rowid = ?1"),
+                params![rowid, fixed],
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Optional unique note titles within a folder.
+//!
+//! When the `unique_note_titles` setting is on, saving a note with the same
+//! title (case-insensitive, ignoring surrounding whitespace) as another live
+//! note in the same folder fails with a `TitleConflict` listing free
+//! alternatives. Only saves that change a note's title or folder are checked,
+//! so duplicates from before the setting was enabled stay editable, and notes
+//! arriving through sync are never rejected.
+
+use std::collections::HashSet;
+
+use rusqlite::{params, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+
+use crate::database::{Database, NoteInput};
+use crate::settings::UNIQUE_NOTE_TITLES;
+
+/// How many alternative titles a conflict suggests
+const SUGGESTION_COUNT: usize = 3;
+
+/// A save refused because the folder already has a note with that title
+#[derive(Debug, Serialize, Clone)]
+pub struct TitleConflict {
+    /// Always "title_conflict", so the frontend can tell it from other errors
+    pub error: &'static str,
+    pub message: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub existing_note_id: String,
+    pub suggestions: Vec<String>,
+}
+
+fn normalize(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+/// Split a trailing ` N` counter off a title: "Plan 2" -> ("Plan", 2)
+fn split_counter(title: &str) -> (&str, u32) {
+    if let Some((base, n)) = title.rsplit_once(' ') {
+        if let Ok(n) = n.parse::<u32>() {
+            if !base.trim().is_empty() {
+                return (base.trim_end(), n);
+            }
+        }
+    }
+    (title, 1)
+}
+
+/// Numbered variants of `title` whose normalized form is not in `taken`
+fn suggest(title: &str, taken: &HashSet<String>) -> Vec<String> {
+    let (base, start) = split_counter(title);
+    (start + 1..)
+        .map(|n| format!("{} {}", base, n))
+        .filter(|candidate| !taken.contains(&normalize(candidate)))
+        .take(SUGGESTION_COUNT)
+        .collect()
+}
+
+impl Database {
+    /// Whether titles must be unique within a folder
+    pub fn unique_titles_enabled(&self) -> SqliteResult<bool> {
+        Ok(self
+            .get_setting(UNIQUE_NOTE_TITLES)?
+            .is_some_and(|v| matches!(v.trim(), "1" | "true")))
+    }
+
+    /// Find another note in the input's folder that already uses its title.
+    /// Untitled and deleted notes never conflict.
+    pub fn find_title_conflict(&self, input: &NoteInput) -> SqliteResult<Option<TitleConflict>> {
+        let title = input.title.trim();
+        if title.is_empty() || input.is_deleted {
+            return Ok(None);
+        }
+        let wanted = normalize(title);
+        let conn = self.conn.lock().unwrap();
+
+        if let Some(id) = input.id.as_deref() {
+            let current: Option<(String, Option<String>)> = conn
+                .query_row(
+                    "SELECT title, folder_id FROM notes WHERE id = ?1 AND is_deleted = 0",
+                    params![id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            if let Some((current_title, current_folder)) = current {
+                if normalize(&current_title) == wanted && current_folder == input.folder_id {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT id, title FROM notes
+             WHERE folder_id IS ?1 AND is_deleted = 0 AND id IS NOT ?2",
+        )?;
+        let siblings = stmt
+            .query_map(params![&input.folder_id, &input.id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    normalize(&row.get::<_, String>(1)?),
+                ))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let Some(existing_note_id) = siblings
+            .iter()
+            .find(|(_, sibling)| *sibling == wanted)
+            .map(|(id, _)| id.clone())
+        else {
+            return Ok(None);
+        };
+        let taken = siblings.into_iter().map(|(_, title)| title).collect();
+
+        Ok(Some(TitleConflict {
+            error: "title_conflict",
+            message: format!("A note titled \"{}\" already exists in this folder", title),
+            title: title.to_string(),
+            folder_id: input.folder_id.clone(),
+            existing_note_id,
+            suggestions: suggest(title, &taken),
+        }))
+    }
+}
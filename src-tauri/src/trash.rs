@@ -0,0 +1,387 @@
+//! Purging the trash, with an optional archive of what was purged.
+//!
+//! Deleted notes stay in `notes` as tombstones (`is_deleted = 1`), so the
+//! deletion syncs and the note can be restored from the trash. `purge` removes
+//! tombstones for good; those with changes that haven't been pushed yet are
+//! kept until the deletion has reached every sync server. With
+//! `archive_purged_notes` turned on, each note's title and gzip-compressed
+//! content are first copied to `purged_notes_archive`, where `search_archive`
+//! still finds them and `restore_archived` brings one back as a new note. An
+//! evicted note's content isn't on this device (see `offline`), so only its
+//! title is archived.
+//!
+//! A purge that would remove more than `purge_confirm_threshold` notes is
+//! refused with a confirmation token instead; repeating the call with the
+//! token goes ahead, as long as the same notes would be purged. With
+//! `trash_auto_empty_days` set, the scheduler purges notes older than that
+//! once an hour, without asking.
+//!
+//! Both follow the sync server's retention policy (see `retention`): a legal
+//! hold refuses a purge outright and pauses emptying the trash automatically,
+//! and notes younger than the minimum retention are left in the trash.
+
+use chrono::{Duration, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+use crate::database::{now_rfc3339, Database, Note, NoteInput};
+use crate::oplog::OpEntity;
+use crate::retention;
+use crate::settings::{ARCHIVE_PURGED_NOTES, PURGE_CONFIRM_THRESHOLD, TRASH_AUTO_EMPTY_DAYS};
+use crate::timestamp::Timestamp;
+
+/// Maximum number of archived notes returned by one search
+const SEARCH_LIMIT: usize = 100;
+
+/// Characters of context on each side of a search match
+const SNIPPET_CONTEXT: usize = 80;
+
+/// Purge size needing confirmation when `purge_confirm_threshold` is not set
+const DEFAULT_CONFIRM_THRESHOLD: i64 = 20;
+
+/// Settings key holding when the trash was last emptied automatically
+const AUTO_EMPTY_LAST_RUN_KEY: &str = "trash_auto_empty_last_run";
+
+/// Minutes between automatic purges
+const AUTO_EMPTY_INTERVAL_MINUTES: i64 = 60;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PurgeReport {
+    pub purged: usize,
+    pub archived: usize,
+    /// Tombstones kept because their deletion hasn't been pushed yet
+    pub skipped: usize,
+    /// Set when nothing was purged because the purge needs confirming: pass
+    /// it back as `confirm` to go ahead
+    pub confirm_token: Option<String>,
+}
+
+/// A purged note in the archive
+#[derive(Debug, Serialize, Clone)]
+pub struct ArchivedNote {
+    pub id: i64,
+    pub note_id: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub deleted_at: String,
+    pub purged_at: String,
+    /// Plain text around the first match, or the start of the note
+    pub snippet: String,
+}
+
+/// Title, folder and compressed content of an archived note
+type ArchivedContent = (String, Option<String>, Vec<u8>);
+
+pub fn ensure_trash_schema(conn: &Connection) -> SqliteResult<()> {
+    // No foreign key: the note row is gone once it is archived
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS purged_notes_archive (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            folder_id TEXT,
+            deleted_at TEXT NOT NULL,
+            purged_at TEXT NOT NULL,
+            content BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_purged_notes_archive_purged_at ON purged_notes_archive(purged_at DESC)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn compress(text: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    encoder.finish()
+}
+
+fn decompress(data: &[u8]) -> std::io::Result<String> {
+    let mut text = String::new();
+    GzDecoder::new(data).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Plain text around the first case-insensitive match of `query`
+fn snippet(text: &str, query: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text.to_lowercase().chars().collect();
+    let needle: Vec<char> = query.to_lowercase().chars().collect();
+    // Lowercasing can change the length of some characters; fall back to the start
+    let at = if needle.is_empty() || lower.len() != chars.len() {
+        None
+    } else {
+        lower.windows(needle.len()).position(|w| w == needle)
+    };
+    let start = at.map_or(0, |at| at.saturating_sub(SNIPPET_CONTEXT));
+    let end = at.map_or(2 * SNIPPET_CONTEXT, |at| {
+        at + needle.len() + SNIPPET_CONTEXT
+    });
+    let mut out: String = chars[start..end.min(chars.len())].iter().collect();
+    if start > 0 {
+        out.insert(0, '…');
+    }
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+impl Database {
+    fn archive_enabled(&self) -> SqliteResult<bool> {
+        Ok(self
+            .get_setting(ARCHIVE_PURGED_NOTES)?
+            .is_some_and(|v| matches!(v.trim(), "1" | "true")))
+    }
+
+    /// Tombstones deleted before `cutoff`
+    fn purge_candidates(&self, cutoff: &Timestamp) -> SqliteResult<Vec<Note>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, title, content, folder_id, updated_at, is_deleted, is_canvas
+             FROM notes
+             WHERE is_deleted = 1 AND updated_at < ?1
+             ORDER BY updated_at ASC",
+        )?;
+        let rows = stmt.query_map(params![cutoff], crate::database::note_row_to_note)?;
+        rows.collect()
+    }
+
+    /// Delete notes for good, archiving them first if asked to
+    fn purge_notes(&self, notes: &[Note], archive: bool) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let purged_at = now_rfc3339();
+        for note in notes {
+            if archive {
+                let content = compress(&note.content)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                tx.execute(
+                    "INSERT INTO purged_notes_archive (note_id, title, folder_id, deleted_at, purged_at, content)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        &note.id,
+                        &note.title,
+                        &note.folder_id,
+                        &note.updated_at,
+                        &purged_at,
+                        content
+                    ],
+                )?;
+            }
+            tx.execute("DELETE FROM notes WHERE id = ?1", params![&note.id])?;
+        }
+        tx.commit()
+    }
+
+    /// Archived notes whose title or text contains `query`, most recently
+    /// purged first. An empty query lists the archive.
+    pub fn search_archive(&self, query: &str) -> SqliteResult<Vec<ArchivedNote>> {
+        let query = query.trim();
+        let needle = query.to_lowercase();
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, note_id, title, folder_id, deleted_at, purged_at, content
+             FROM purged_notes_archive
+             ORDER BY purged_at DESC, id DESC",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut found = Vec::new();
+        while let Some(row) = rows.next()? {
+            let title: String = row.get(2)?;
+            let content: Vec<u8> = row.get(6)?;
+            let text = match decompress(&content) {
+                Ok(html) => beck_markdown::html_to_text(&html),
+                Err(err) => {
+                    eprintln!("Skipping unreadable archived note: {}", err);
+                    continue;
+                }
+            };
+            if !needle.is_empty()
+                && !title.to_lowercase().contains(&needle)
+                && !text.to_lowercase().contains(&needle)
+            {
+                continue;
+            }
+            found.push(ArchivedNote {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                title,
+                folder_id: row.get(3)?,
+                deleted_at: row.get(4)?,
+                purged_at: row.get(5)?,
+                snippet: snippet(&text, query),
+            });
+            if found.len() >= SEARCH_LIMIT {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    fn get_archived(&self, id: i64) -> SqliteResult<Option<ArchivedContent>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT title, folder_id, content FROM purged_notes_archive WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+    }
+
+    fn remove_archived(&self, id: i64) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM purged_notes_archive WHERE id = ?1",
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    fn live_folder(&self, folder_id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM folders WHERE id = ?1 AND is_deleted = 0)",
+            params![folder_id],
+            |row| row.get(0),
+        )
+    }
+}
+
+/// Token confirming a purge of exactly these notes
+fn confirm_token(notes: &[Note]) -> String {
+    let mut ids: Vec<&str> = notes.iter().map(|note| note.id.as_str()).collect();
+    ids.sort_unstable();
+    let mut hasher = Sha256::new();
+    for id in ids {
+        hasher.update(id.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/// Purge notes that have been in the trash for at least `older_than_days`
+/// (0 empties the trash, down to the workspace's minimum retention). A purge above the confirmation threshold only goes
+/// ahead with the token an earlier call returned for the same notes.
+pub fn purge(
+    db: &Database,
+    older_than_days: i64,
+    confirm: Option<&str>,
+) -> Result<PurgeReport, String> {
+    purge_notes_older_than(db, older_than_days, confirm, true)
+}
+
+fn purge_notes_older_than(
+    db: &Database,
+    older_than_days: i64,
+    confirm: Option<&str>,
+    guarded: bool,
+) -> Result<PurgeReport, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let policy = retention::cached_policy(db)?;
+    if let Some(err) = policy.hold_error() {
+        return Err(err);
+    }
+    let older_than_days = older_than_days.max(policy.min_retention_days);
+    let cutoff = Timestamp::from_datetime(Utc::now() - Duration::days(older_than_days.max(0)));
+    let pending = db
+        .unpushed_anywhere_entity_ids(OpEntity::Note)
+        .map_err(db_err)?;
+    let (notes, unpushed): (Vec<Note>, Vec<Note>) = db
+        .purge_candidates(&cutoff)
+        .map_err(db_err)?
+        .into_iter()
+        .partition(|note| !pending.contains(&note.id));
+
+    if guarded {
+        let threshold = db
+            .get_setting_i64(PURGE_CONFIRM_THRESHOLD)
+            .map_err(db_err)?
+            .unwrap_or(DEFAULT_CONFIRM_THRESHOLD)
+            .max(0);
+        let token = confirm_token(&notes);
+        if notes.len() as i64 > threshold && confirm != Some(token.as_str()) {
+            return Ok(PurgeReport {
+                purged: 0,
+                archived: 0,
+                skipped: unpushed.len(),
+                confirm_token: Some(token),
+            });
+        }
+    }
+
+    let archive = db.archive_enabled().map_err(db_err)?;
+    db.purge_notes(&notes, archive).map_err(db_err)?;
+    Ok(PurgeReport {
+        purged: notes.len(),
+        archived: if archive { notes.len() } else { 0 },
+        skipped: unpushed.len(),
+        confirm_token: None,
+    })
+}
+
+/// Purge notes older than `trash_auto_empty_days` if that is set and the last
+/// automatic purge was over an hour ago. Returns `None` when nothing was due.
+pub fn auto_empty_if_due(db: &Database) -> Result<Option<PurgeReport>, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let days = db
+        .get_setting_i64(TRASH_AUTO_EMPTY_DAYS)
+        .map_err(db_err)?
+        .unwrap_or(0);
+    if days <= 0 || retention::cached_policy(db)?.legal_hold {
+        return Ok(None);
+    }
+    let last_run = db.get_setting(AUTO_EMPTY_LAST_RUN_KEY).map_err(db_err)?;
+    let due = last_run
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .is_none_or(|at| {
+            Utc::now().signed_duration_since(at) >= Duration::minutes(AUTO_EMPTY_INTERVAL_MINUTES)
+        });
+    if !due {
+        return Ok(None);
+    }
+    let report = purge_notes_older_than(db, days, None, false)?;
+    db.set_setting(AUTO_EMPTY_LAST_RUN_KEY, Some(&now_rfc3339()))
+        .map_err(db_err)?;
+    Ok(Some(report))
+}
+
+/// Bring an archived note back as a new note, in its old folder if that still
+/// exists. It leaves the archive.
+pub fn restore_archived(db: &Database, id: i64) -> Result<Note, String> {
+    let db_err = |e: rusqlite::Error| format!("Database error: {}", e);
+    let (title, folder_id, content) = db
+        .get_archived(id)
+        .map_err(db_err)?
+        .ok_or_else(|| format!("Archived note not found: {}", id))?;
+    let content =
+        decompress(&content).map_err(|e| format!("Failed to read archived note: {}", e))?;
+    let folder_id = match folder_id {
+        Some(folder_id) if db.live_folder(&folder_id).map_err(db_err)? => Some(folder_id),
+        _ => None,
+    };
+
+    let note = db
+        .save_note(NoteInput {
+            id: None,
+            title,
+            content,
+            folder_id,
+            updated_at: None,
+            is_deleted: false,
+            is_canvas: false,
+        })
+        .map_err(db_err)?;
+    db.remove_archived(id).map_err(db_err)?;
+    Ok(note)
+}
@@ -0,0 +1,323 @@
+//! Link previews for URLs pasted into notes.
+//!
+//! The page is fetched once and its title, description and preview image are
+//! read from OpenGraph/Twitter meta tags, falling back to `<title>` and the
+//! plain description meta. The preview image is saved as a local asset so link
+//! cards keep rendering offline. Results are cached in `link_previews` and
+//! refreshed after `CACHE_TTL_DAYS`.
+
+use beck_markdown::html::{self, Element};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::database::{assets, now_rfc3339, Database};
+
+/// Cached previews older than this are fetched again
+const CACHE_TTL_DAYS: i64 = 7;
+
+/// Give up on slow sites rather than leaving the editor waiting
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Only the start of a page is read; the meta tags live in `<head>`
+const MAX_PAGE_BYTES: usize = 1024 * 1024;
+
+/// Larger preview images are skipped
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Metadata for rendering a link card
+#[derive(Debug, Serialize, Clone)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    /// Asset holding the preview image, if the page had one
+    pub image_asset_id: Option<String>,
+    pub image_uri: Option<String>,
+    pub fetched_at: String,
+    /// True when served from the cache without fetching
+    pub from_cache: bool,
+}
+
+pub fn ensure_link_previews_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS link_previews (
+            url TEXT PRIMARY KEY NOT NULL,
+            title TEXT,
+            description TEXT,
+            site_name TEXT,
+            image_asset_id TEXT,
+            image_uri TEXT,
+            fetched_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+impl Database {
+    fn get_link_preview(&self, url: &str) -> SqliteResult<Option<LinkPreview>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT url, title, description, site_name, image_asset_id, image_uri, fetched_at
+             FROM link_previews WHERE url = ?1",
+            params![url],
+            |row| {
+                Ok(LinkPreview {
+                    url: row.get(0)?,
+                    title: row.get(1)?,
+                    description: row.get(2)?,
+                    site_name: row.get(3)?,
+                    image_asset_id: row.get(4)?,
+                    image_uri: row.get(5)?,
+                    fetched_at: row.get(6)?,
+                    from_cache: true,
+                })
+            },
+        )
+        .optional()
+    }
+
+    fn put_link_preview(&self, preview: &LinkPreview) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO link_previews (url, title, description, site_name, image_asset_id, image_uri, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(url) DO UPDATE SET
+                title = excluded.title,
+                description = excluded.description,
+                site_name = excluded.site_name,
+                image_asset_id = excluded.image_asset_id,
+                image_uri = excluded.image_uri,
+                fetched_at = excluded.fetched_at",
+            params![
+                &preview.url,
+                &preview.title,
+                &preview.description,
+                &preview.site_name,
+                &preview.image_asset_id,
+                &preview.image_uri,
+                &preview.fetched_at
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn is_fresh(fetched_at: &str) -> bool {
+    chrono::DateTime::parse_from_rfc3339(fetched_at).is_ok_and(|at| {
+        chrono::Utc::now().signed_duration_since(at) < chrono::Duration::days(CACHE_TTL_DAYS)
+    })
+}
+
+/// Page metadata read from the document head
+#[derive(Debug, Default)]
+struct PageMeta {
+    title: Option<String>,
+    description: Option<String>,
+    site_name: Option<String>,
+    image: Option<String>,
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!value.is_empty()).then_some(value)
+}
+
+/// Read title, description, site name and image URL from page HTML.
+/// OpenGraph tags win over Twitter tags, which win over the plain fallbacks.
+fn parse_page_meta(page: &str) -> PageMeta {
+    let head_end = page
+        .to_ascii_lowercase()
+        .find("</head>")
+        .unwrap_or(page.len());
+    let nodes = html::parse(&page[..head_end]);
+
+    let mut meta = PageMeta::default();
+    let (mut twitter, mut fallback) = (PageMeta::default(), PageMeta::default());
+    html::walk(&nodes, &mut |el: &Element| match el.name.as_str() {
+        "title" if fallback.title.is_none() => fallback.title = non_empty(&el.text()),
+        "meta" => {
+            let key = el
+                .attr("property")
+                .or_else(|| el.attr("name"))
+                .map(|k| k.to_ascii_lowercase());
+            let Some(content) = el.attr("content").and_then(non_empty) else {
+                return;
+            };
+            let slot = match key.as_deref() {
+                Some("og:title") => &mut meta.title,
+                Some("og:description") => &mut meta.description,
+                Some("og:site_name") => &mut meta.site_name,
+                Some("og:image") | Some("og:image:url") => &mut meta.image,
+                Some("twitter:title") => &mut twitter.title,
+                Some("twitter:description") => &mut twitter.description,
+                Some("twitter:image") => &mut twitter.image,
+                Some("description") => &mut fallback.description,
+                _ => return,
+            };
+            slot.get_or_insert(content);
+        }
+        _ => {}
+    });
+
+    PageMeta {
+        title: meta.title.or(twitter.title).or(fallback.title),
+        description: meta
+            .description
+            .or(twitter.description)
+            .or(fallback.description),
+        site_name: meta.site_name,
+        image: meta.image.or(twitter.image),
+    }
+}
+
+/// Read a response body, stopping once it exceeds `limit` bytes.
+/// Returns `None` if the body is larger than the limit and `truncate` is false.
+async fn read_limited(
+    mut response: reqwest::Response,
+    limit: usize,
+    truncate: bool,
+) -> Result<Option<Vec<u8>>, String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read response body: {}", e))?
+    {
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            if !truncate {
+                return Ok(None);
+            }
+            body.truncate(limit);
+            break;
+        }
+    }
+    Ok(Some(body))
+}
+
+/// File extension for an image content type
+fn image_extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    Some(match mime.as_str() {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/svg+xml" => "svg",
+        _ => return None,
+    })
+}
+
+/// Download a preview image into the assets folder
+async fn fetch_image(
+    client: &reqwest::Client,
+    app_data_dir: &Path,
+    url: &reqwest::Url,
+) -> Result<Option<assets::AssetResult>, String> {
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let Some(extension) = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(image_extension)
+    else {
+        return Ok(None);
+    };
+    let Some(data) = read_limited(response, MAX_IMAGE_BYTES, false).await? else {
+        return Ok(None);
+    };
+    assets::save_image_bytes(app_data_dir, &data, extension).map(Some)
+}
+
+/// Build a link preview for `url`, from the cache when it is fresh enough.
+/// `refresh` forces a new fetch.
+pub async fn unfurl(
+    db: &Database,
+    app_data_dir: &Path,
+    url: &str,
+    refresh: bool,
+) -> Result<LinkPreview, String> {
+    let page_url =
+        reqwest::Url::parse(url.trim()).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    if !matches!(page_url.scheme(), "http" | "https") {
+        return Err(format!(
+            "Only http and https links can be unfurled: {}",
+            url
+        ));
+    }
+    let url = page_url.to_string();
+
+    let cached = db
+        .get_link_preview(&url)
+        .map_err(|e| format!("Database error: {}", e))?;
+    if let Some(preview) = &cached {
+        if !refresh && is_fresh(&preview.fetched_at) {
+            return Ok(preview.clone());
+        }
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!(
+            "Beck/",
+            env!("CARGO_PKG_VERSION"),
+            " (link preview)"
+        ))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let response = match client.get(page_url.clone()).send().await {
+        Ok(response) if response.status().is_success() => response,
+        // Offline or the site is down: an old preview beats none
+        result => {
+            return match cached {
+                Some(preview) => Ok(preview),
+                None => Err(match result {
+                    Ok(response) => format!("Server returned {} for {}", response.status(), url),
+                    Err(err) => format!("Failed to fetch {}: {}", url, err),
+                }),
+            };
+        }
+    };
+    // Relative image URLs resolve against the page we ended up on
+    let base_url = response.url().clone();
+    let body = read_limited(response, MAX_PAGE_BYTES, true)
+        .await?
+        .unwrap_or_default();
+    let meta = parse_page_meta(&String::from_utf8_lossy(&body));
+
+    // A broken preview image shouldn't lose the rest of the card
+    let image = match meta.image.as_deref().and_then(|i| base_url.join(i).ok()) {
+        Some(image_url) => fetch_image(&client, app_data_dir, &image_url)
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+    let preview = LinkPreview {
+        url,
+        title: meta.title,
+        description: meta.description,
+        site_name: meta
+            .site_name
+            .or_else(|| base_url.host_str().map(|h| h.to_string())),
+        image_asset_id: image.as_ref().map(|i| i.id.clone()),
+        image_uri: image.map(|i| i.uri),
+        fetched_at: now_rfc3339(),
+        from_cache: false,
+    };
+    db.put_link_preview(&preview)
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(preview)
+}
@@ -0,0 +1,83 @@
+//! Client update check against the sync server.
+//!
+//! The server advertises the latest desktop release and the oldest version it
+//! still supports; comparing those with this build lets the app warn before a
+//! sync protocol change leaves an old client behind.
+
+use serde::{Deserialize, Serialize};
+
+/// Version of this build
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Deserialize)]
+struct ClientRelease {
+    latest_version: Option<String>,
+    min_supported_version: Option<String>,
+    release_notes_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UpdateCheck {
+    pub current_version: String,
+    pub latest_version: Option<String>,
+    pub min_supported_version: Option<String>,
+    pub release_notes_url: Option<String>,
+    /// A newer version is available
+    pub update_available: bool,
+    /// This version is older than the server supports; sync may fail
+    pub unsupported: bool,
+}
+
+/// Parse `MAJOR.MINOR.PATCH`, ignoring a leading `v` and any pre-release or
+/// build suffix. Missing components count as zero.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version
+        .trim()
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+/// Whether `version` is older than `than`; unparseable versions never are
+fn is_older(version: &str, than: Option<&str>) -> bool {
+    match (parse_version(version), than.and_then(parse_version)) {
+        (Some(version), Some(than)) => version < than,
+        _ => false,
+    }
+}
+
+/// Ask the server at `server_url` for the current desktop release
+pub async fn check(server_url: &str) -> Result<UpdateCheck, String> {
+    let url = format!(
+        "{}/api/client/latest",
+        server_url.trim().trim_end_matches('/')
+    );
+    let response = reqwest::Client::new()
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server returned {} for {}", response.status(), url));
+    }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response body: {}", e))?;
+    let release: ClientRelease = serde_json::from_slice(&body)
+        .map_err(|e| format!("Invalid release information from server: {}", e))?;
+
+    Ok(UpdateCheck {
+        current_version: CURRENT_VERSION.to_string(),
+        update_available: is_older(CURRENT_VERSION, release.latest_version.as_deref()),
+        unsupported: is_older(CURRENT_VERSION, release.min_supported_version.as_deref()),
+        latest_version: release.latest_version,
+        min_supported_version: release.min_supported_version,
+        release_notes_url: release.release_notes_url,
+    })
+}
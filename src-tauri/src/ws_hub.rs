@@ -0,0 +1,603 @@
+//! One WebSocket connection to the sync server, shared by every window.
+//!
+//! Windows don't open their own sockets; they subscribe to notes through the
+//! backend, which keeps a single authenticated connection to the active sync
+//! profile's server and counts subscriptions per window. The server sees one
+//! `subscribe` per note no matter how many editors (or the sync engine) want
+//! it, and an `unsubscribe` once the last of them lets go.
+//!
+//! Server messages are re-emitted as `app://ws-message` with the message as
+//! sent: `update`s and canvas presence go only to the windows subscribed to
+//! that note, everything else (metadata, watched changes, maintenance, errors)
+//! to all windows. While the app is locked, only maintenance and error
+//! messages are emitted; the rest carry notes, which the windows catch up on
+//! through CRDT sync after unlocking. Connection changes are emitted as
+//! `app://ws-status`.
+//!
+//! Canvas presence (drag previews, selection boxes) is ephemeral: the server
+//! relays it to the other participants of a canvas note without storing it,
+//! and sends `canvas_leave` when one of them goes. Windows of this app share
+//! one connection, so presence is also relayed locally between windows on the
+//! same canvas, with a `window:<label>` session.
+//!
+//! CRDT updates are batched per note before they go out: fast typing produces
+//! an update per keystroke, so updates are held until the note has been quiet
+//! for `BATCH_DEBOUNCE`, but never longer than `BATCH_MAX_LATENCY`, and sent
+//! as one merged update. A batch is flushed early when the note's last
+//! subscriber lets go, so the update still precedes the `unsubscribe`.
+//!
+//! The connection is opened by the first subscription or by `connect`, and is
+//! re-established with backoff after it drops, subscribing to every note
+//! again. Updates sent while disconnected fail rather than queue; callers fall
+//! back to the REST endpoints, as when the server is unreachable. A batch
+//! still pending when the connection drops is sent once it is back, unless
+//! `connect` or `disconnect` replaced the connection; then it is dropped, and
+//! the note catches up through CRDT sync like after any missed update.
+
+use base64::Engine;
+use futures_util::future::{self, Either};
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::Connector;
+
+use crate::app_lock::AppLock;
+use crate::database::Database;
+use crate::sync_profiles;
+
+/// Event carrying a message from the server
+pub const WS_MESSAGE_EVENT: &str = "app://ws-message";
+
+/// Event carrying the connection status whenever it changes
+pub const WS_STATUS_EVENT: &str = "app://ws-status";
+
+/// First delay before reconnecting, doubled after each failed attempt
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Messages routed only to the windows subscribed to their note
+const NOTE_MESSAGES: &[&str] = &["update", "canvas_presence", "canvas_leave"];
+
+/// Messages still emitted while the app is locked, as they carry no notes
+const LOCKED_MESSAGES: &[&str] = &["maintenance", "error"];
+
+/// Longest delay between reconnection attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Quiet time after a note's last CRDT update before its batch is sent
+const BATCH_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Longest a CRDT update waits in a batch
+const BATCH_MAX_LATENCY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WsState {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WsStatus {
+    pub state: WsState,
+    /// Server of the active sync profile when the connection was opened
+    pub server_url: Option<String>,
+    /// Notes with at least one subscriber
+    pub subscribed_notes: usize,
+    /// Why the last connection attempt failed or the connection dropped
+    pub last_error: Option<String>,
+}
+
+/// CRDT updates of one note waiting to be sent
+struct PendingUpdates {
+    updates: Vec<Vec<u8>>,
+    first_at: Instant,
+    last_at: Instant,
+}
+
+impl PendingUpdates {
+    fn due_at(&self) -> Instant {
+        (self.last_at + BATCH_DEBOUNCE).min(self.first_at + BATCH_MAX_LATENCY)
+    }
+}
+
+#[derive(Default)]
+struct HubState {
+    /// Subscription counts by note, then by window label
+    subscriptions: HashMap<String, HashMap<String, usize>>,
+    /// Messages to the server while connected
+    outgoing: Option<UnboundedSender<Message>>,
+    /// CRDT updates not sent yet, by note
+    pending: HashMap<String, PendingUpdates>,
+    /// Bumped on connect and disconnect so a superseded connection task stops
+    generation: u64,
+    running: bool,
+    state: Option<WsState>,
+    server_url: Option<String>,
+    last_error: Option<String>,
+}
+
+impl HubState {
+    fn status(&self) -> WsStatus {
+        WsStatus {
+            state: self.state.unwrap_or(WsState::Disconnected),
+            server_url: self.server_url.clone(),
+            subscribed_notes: self.subscriptions.len(),
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    fn send(&self, message: &Value) {
+        if let Some(tx) = &self.outgoing {
+            let _ = tx.send(Message::Text(message.to_string()));
+        }
+    }
+
+    /// Send a note's pending CRDT updates as one, if there are any
+    fn flush(&mut self, note_id: &str) {
+        let Some(batch) = self.pending.remove(note_id) else {
+            return;
+        };
+        let updates = match batch.updates.len() {
+            1 => batch.updates,
+            _ => match yrs::merge_updates_v1(&batch.updates) {
+                Ok(merged) => vec![merged],
+                // The server would reject it as well; send them as they came
+                Err(err) => {
+                    eprintln!("[ws] failed to merge updates for {}: {}", note_id, err);
+                    batch.updates
+                }
+            },
+        };
+        for update in updates {
+            self.send(&serde_json::json!({
+                "type": "update",
+                "note_id": note_id,
+                "payload": base64::engine::general_purpose::STANDARD.encode(update),
+            }));
+        }
+    }
+}
+
+/// The shared connection, kept as managed state
+#[derive(Clone, Default)]
+pub struct WsHub {
+    inner: Arc<Mutex<HubState>>,
+}
+
+impl WsHub {
+    pub fn status(&self) -> WsStatus {
+        self.inner.lock().unwrap().status()
+    }
+
+    /// Open the connection to the active sync profile's server, replacing
+    /// any existing one
+    pub fn connect(&self, app: &AppHandle, db: &Database) -> Result<WsStatus, String> {
+        let active = sync_profiles::active_profile(db)?
+            .ok_or_else(|| "No sync server is configured".to_string())?;
+        let url = socket_url(&active.profile.server_url, active.token.as_deref())?;
+
+        let generation = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.generation += 1;
+            inner.outgoing = None;
+            inner.pending.clear();
+            inner.running = true;
+            inner.state = Some(WsState::Connecting);
+            inner.server_url = Some(active.profile.server_url.clone());
+            inner.last_error = None;
+            inner.generation
+        };
+        let _ = app.emit(WS_STATUS_EVENT, self.status());
+        tauri::async_runtime::spawn(run(self.clone(), app.clone(), generation, url));
+        Ok(self.status())
+    }
+
+    /// Close the connection; subscriptions are kept for the next `connect`
+    pub fn disconnect(&self, app: &AppHandle) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.generation += 1;
+            inner.outgoing = None;
+            inner.pending.clear();
+            inner.running = false;
+            inner.state = Some(WsState::Disconnected);
+        }
+        let _ = app.emit(WS_STATUS_EVENT, self.status());
+    }
+
+    /// Reconnect after the active profile or its token changed, if connected
+    pub fn reconnect_if_running(&self, app: &AppHandle, db: &Database) -> Result<(), String> {
+        if self.inner.lock().unwrap().running {
+            self.connect(app, db)?;
+        }
+        Ok(())
+    }
+
+    /// Subscribe a window to a note's updates, connecting if needed
+    pub fn subscribe(
+        &self,
+        app: &AppHandle,
+        db: &Database,
+        note_id: &str,
+        window: &str,
+    ) -> Result<(), String> {
+        let running = {
+            let mut inner = self.inner.lock().unwrap();
+            let subscribers = inner.subscriptions.entry(note_id.to_string()).or_default();
+            *subscribers.entry(window.to_string()).or_default() += 1;
+            if subscribers.values().sum::<usize>() == 1 {
+                inner.send(&serde_json::json!({ "type": "subscribe", "note_id": note_id }));
+            }
+            inner.running
+        };
+        if !running {
+            self.connect(app, db)?;
+        }
+        Ok(())
+    }
+
+    /// Drop one of a window's subscriptions to a note. Returns false if the
+    /// window wasn't subscribed.
+    pub fn unsubscribe(&self, app: &AppHandle, note_id: &str, window: &str) -> bool {
+        let others = {
+            let mut inner = self.inner.lock().unwrap();
+            let Some(subscribers) = inner.subscriptions.get_mut(note_id) else {
+                return false;
+            };
+            let Some(count) = subscribers.get_mut(window) else {
+                return false;
+            };
+            *count -= 1;
+            if *count > 0 {
+                return true;
+            }
+            subscribers.remove(window);
+            let others: Vec<String> = subscribers.keys().cloned().collect();
+            if others.is_empty() {
+                inner.subscriptions.remove(note_id);
+                inner.flush(note_id);
+                inner.send(&serde_json::json!({ "type": "unsubscribe", "note_id": note_id }));
+            }
+            others
+        };
+        emit_local_leave(app, note_id, window, &others);
+        true
+    }
+
+    /// Drop every subscription of a closed window
+    pub fn remove_window(&self, app: &AppHandle, window: &str) {
+        let mut left: Vec<(String, Vec<String>)> = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let mut emptied = Vec::new();
+            inner.subscriptions.retain(|note_id, subscribers| {
+                if subscribers.remove(window).is_some() {
+                    left.push((note_id.clone(), subscribers.keys().cloned().collect()));
+                }
+                if subscribers.is_empty() {
+                    emptied.push(note_id.clone());
+                }
+                !subscribers.is_empty()
+            });
+            for note_id in emptied {
+                inner.flush(&note_id);
+                inner.send(&serde_json::json!({ "type": "unsubscribe", "note_id": note_id }));
+            }
+        }
+        for (note_id, others) in left {
+            emit_local_leave(app, &note_id, window, &others);
+        }
+    }
+
+    /// Queue a CRDT update (base64) for a note, to be sent to the server
+    /// merged with the note's other updates of the moment
+    pub fn send_update(&self, note_id: &str, payload: &str) -> Result<(), String> {
+        let update = base64::engine::general_purpose::STANDARD
+            .decode(payload)
+            .map_err(|e| format!("Invalid update: {}", e))?;
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        if inner.outgoing.is_none() {
+            return Err("Not connected to the sync server".to_string());
+        }
+        if let Some(batch) = inner.pending.get_mut(note_id) {
+            batch.updates.push(update);
+            batch.last_at = now;
+            return Ok(());
+        }
+        inner.pending.insert(
+            note_id.to_string(),
+            PendingUpdates {
+                updates: vec![update],
+                first_at: now,
+                last_at: now,
+            },
+        );
+        tauri::async_runtime::spawn(flush_when_due(self.clone(), note_id.to_string()));
+        Ok(())
+    }
+
+    /// Share a window's ephemeral canvas state (JSON) with the other
+    /// participants of a canvas note: the other windows subscribed to it and,
+    /// when connected, everyone else through the server
+    pub fn send_canvas_presence(
+        &self,
+        app: &AppHandle,
+        note_id: &str,
+        window: &str,
+        payload: &str,
+    ) -> Result<(), String> {
+        let others: Vec<String> = {
+            let inner = self.inner.lock().unwrap();
+            let subscribers = inner
+                .subscriptions
+                .get(note_id)
+                .filter(|subscribers| subscribers.contains_key(window))
+                .ok_or_else(|| "The window isn't subscribed to this note".to_string())?;
+            inner.send(&serde_json::json!({
+                "type": "canvas_presence",
+                "note_id": note_id,
+                "payload": payload,
+            }));
+            subscribers
+                .keys()
+                .filter(|label| label.as_str() != window)
+                .cloned()
+                .collect()
+        };
+        let message = serde_json::json!({
+            "type": "canvas_presence",
+            "note_id": note_id,
+            "payload": payload,
+            "session": local_session(window),
+            "user": null,
+        });
+        for label in others {
+            let _ = app.emit_to(label.as_str(), WS_MESSAGE_EVENT, message.clone());
+        }
+        Ok(())
+    }
+
+    /// Whether `generation` is still the current connection
+    fn is_current(&self, generation: u64) -> bool {
+        self.inner.lock().unwrap().generation == generation
+    }
+
+    /// Record a state change of the current connection; false if superseded
+    fn set_state(
+        &self,
+        app: &AppHandle,
+        generation: u64,
+        state: WsState,
+        error: Option<String>,
+    ) -> bool {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.generation != generation {
+                return false;
+            }
+            inner.state = Some(state);
+            if error.is_some() || state == WsState::Connected {
+                inner.last_error = error;
+            }
+        }
+        let _ = app.emit(WS_STATUS_EVENT, self.status());
+        true
+    }
+
+    /// Install a new connection's sender and subscribe to every note again
+    fn attach(&self, generation: u64, tx: UnboundedSender<Message>) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return false;
+        }
+        for note_id in inner.subscriptions.keys() {
+            let message = serde_json::json!({ "type": "subscribe", "note_id": note_id });
+            let _ = tx.send(Message::Text(message.to_string()));
+        }
+        inner.outgoing = Some(tx);
+        true
+    }
+
+    fn detach(&self, generation: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation == generation {
+            inner.outgoing = None;
+        }
+    }
+
+    /// Forward a server message to the windows it concerns
+    fn dispatch(&self, app: &AppHandle, text: &str) {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return;
+        };
+        let kind = message["type"].as_str().unwrap_or_default();
+        let locked = app
+            .try_state::<AppLock>()
+            .is_some_and(|lock| lock.is_locked());
+        if locked && !LOCKED_MESSAGES.contains(&kind) {
+            return;
+        }
+        let note_id = Some(kind)
+            .filter(|kind| NOTE_MESSAGES.contains(kind))
+            .and_then(|_| message["note_id"].as_str());
+        let Some(note_id) = note_id else {
+            let _ = app.emit(WS_MESSAGE_EVENT, message);
+            return;
+        };
+        let windows: Vec<String> = self
+            .inner
+            .lock()
+            .unwrap()
+            .subscriptions
+            .get(note_id)
+            .map(|subscribers| subscribers.keys().cloned().collect())
+            .unwrap_or_default();
+        for window in windows {
+            let _ = app.emit_to(window.as_str(), WS_MESSAGE_EVENT, message.clone());
+        }
+    }
+}
+
+/// Send a note's batch of updates once it is due
+async fn flush_when_due(hub: WsHub, note_id: String) {
+    loop {
+        let due_at = {
+            let mut inner = hub.inner.lock().unwrap();
+            let Some(batch) = inner.pending.get(&note_id) else {
+                // Flushed early or dropped with the connection
+                return;
+            };
+            let due_at = batch.due_at();
+            if due_at <= Instant::now() {
+                inner.flush(&note_id);
+                return;
+            }
+            due_at
+        };
+        tokio::time::sleep_until(due_at).await;
+    }
+}
+
+/// Canvas presence session of a window of this app
+fn local_session(window: &str) -> String {
+    format!("window:{}", window)
+}
+
+/// Tell the other windows on a note that `window` left it
+fn emit_local_leave(app: &AppHandle, note_id: &str, window: &str, others: &[String]) {
+    let message = serde_json::json!({
+        "type": "canvas_leave",
+        "note_id": note_id,
+        "session": local_session(window),
+        "user": null,
+    });
+    for label in others {
+        let _ = app.emit_to(label.as_str(), WS_MESSAGE_EVENT, message.clone());
+    }
+}
+
+/// `ws(s)://<server>/api/ws?token=` for a sync profile's server URL
+fn socket_url(server_url: &str, token: Option<&str>) -> Result<String, String> {
+    let base = server_url.trim().trim_end_matches('/');
+    let mut url = reqwest::Url::parse(&format!("{}/api/ws", base))
+        .map_err(|e| format!("Invalid server URL {}: {}", base, e))?;
+    let scheme = match url.scheme() {
+        "https" => "wss",
+        "http" => "ws",
+        other => return Err(format!("Unsupported server URL scheme: {}", other)),
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| format!("Invalid server URL {}", base))?;
+    if let Some(token) = token {
+        url.query_pairs_mut().append_pair("token", token);
+    }
+    Ok(url.to_string())
+}
+
+/// TLS settings for `wss://` servers, trusting the bundled web PKI roots like
+/// the HTTP client does
+fn tls_connector() -> Result<Connector, String> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| format!("Failed to set up TLS: {}", e))?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    Ok(Connector::Rustls(Arc::new(config)))
+}
+
+enum Event {
+    Outgoing(Option<Message>),
+    Incoming(Option<Result<Message, tokio_tungstenite::tungstenite::Error>>),
+}
+
+/// Keep a connection open until it is superseded, reconnecting after drops
+async fn run(hub: WsHub, app: AppHandle, generation: u64, url: String) {
+    let mut delay = RECONNECT_DELAY;
+    while hub.is_current(generation) {
+        let connector = match tls_connector() {
+            Ok(connector) => connector,
+            Err(err) => {
+                hub.set_state(&app, generation, WsState::Disconnected, Some(err));
+                return;
+            }
+        };
+        let error = match tokio_tungstenite::connect_async_tls_with_config(
+            url.as_str(),
+            None,
+            false,
+            Some(connector),
+        )
+        .await
+        {
+            Ok((socket, _)) => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                if !hub.attach(generation, tx) {
+                    return;
+                }
+                hub.set_state(&app, generation, WsState::Connected, None);
+                delay = RECONNECT_DELAY;
+                let error = serve(&hub, &app, socket, rx).await;
+                hub.detach(generation);
+                error
+            }
+            Err(err) => Some(format!("Failed to connect: {}", err)),
+        };
+        if !hub.set_state(&app, generation, WsState::Connecting, error) {
+            return;
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Pump messages both ways until the connection closes or is superseded.
+/// Returns why it closed, if it wasn't asked to.
+async fn serve<S>(
+    hub: &WsHub,
+    app: &AppHandle,
+    mut socket: tokio_tungstenite::WebSocketStream<S>,
+    mut rx: UnboundedReceiver<Message>,
+) -> Option<String>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    loop {
+        let event = match future::select(pin!(rx.recv()), socket.next()).await {
+            Either::Left((message, _)) => Event::Outgoing(message),
+            Either::Right((message, _)) => Event::Incoming(message),
+        };
+        match event {
+            Event::Outgoing(Some(message)) => {
+                if let Err(err) = socket.send(message).await {
+                    return Some(format!("Connection lost: {}", err));
+                }
+            }
+            // Superseded by `connect` or `disconnect`
+            Event::Outgoing(None) => {
+                let _ = socket.close(None).await;
+                return None;
+            }
+            Event::Incoming(Some(Ok(Message::Text(text)))) => hub.dispatch(app, &text),
+            Event::Incoming(Some(Ok(Message::Close(_)))) | Event::Incoming(None) => {
+                return Some("Connection closed by the server".to_string());
+            }
+            Event::Incoming(Some(Ok(_))) => {}
+            Event::Incoming(Some(Err(err))) => return Some(format!("Connection lost: {}", err)),
+        }
+    }
+}
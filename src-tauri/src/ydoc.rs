@@ -0,0 +1,231 @@
+//! Edits to notes' stored CRDT documents.
+//!
+//! The editor restores a note from its Yjs document in `crdt_states`, not from
+//! its HTML: the `XmlFragment` named `content`, with an `XmlText` per run of
+//! text whose marks are formatting attributes (`link: {href}`), as laid out in
+//! `beck_markdown::seed_state`. When the backend rewrites a note's HTML itself,
+//! it makes the same edit to the document, so the editor shows it and other
+//! devices merge it like any edit; HTML written from scratch replaces the
+//! document's content. Dropping the document instead has the editor rebuild
+//! it from the HTML as new content, which the server then merges next to the
+//! old content, duplicating the note.
+
+use std::sync::Arc;
+
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Result as SqliteResult};
+use yrs::types::text::YChange;
+use yrs::types::Attrs;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{
+    Any, Doc, Out, ReadTxn, StateVector, Text, Transact, TransactionMut, Update, Xml,
+    XmlElementRef, XmlFragment, XmlFragmentRef, XmlOut, XmlTextRef,
+};
+
+/// Outcome of editing a note's stored document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DocEdit {
+    /// The note has no stored document; the editor builds one from the HTML
+    NoDocument,
+    /// The edit found nothing to change in the document
+    Unchanged,
+    Edited,
+}
+
+/// Apply `edit` to a note's stored document and store the result, stamped
+/// `now`. `edit` returns whether it changed the document.
+pub(crate) fn edit_stored_doc(
+    conn: &Connection,
+    note_id: &str,
+    now: &str,
+    edit: impl FnOnce(&mut TransactionMut, &XmlFragmentRef) -> bool,
+) -> SqliteResult<DocEdit> {
+    let state: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT ydoc_state FROM crdt_states WHERE note_id = ?1",
+            params![note_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(state) = state else {
+        return Ok(DocEdit::NoDocument);
+    };
+    let update = Update::decode_v1(&state)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Blob, Box::new(e)))?;
+
+    let doc = Doc::new();
+    let fragment = doc.get_or_insert_xml_fragment("content");
+    let mut txn = doc.transact_mut();
+    txn.apply_update(update);
+    if !edit(&mut txn, &fragment) {
+        return Ok(DocEdit::Unchanged);
+    }
+
+    conn.execute(
+        "UPDATE crdt_states SET ydoc_state = ?2, state_vector = ?3, updated_at = ?4
+         WHERE note_id = ?1",
+        params![
+            note_id,
+            txn.encode_state_as_update_v1(&StateVector::default()),
+            txn.state_vector().encode_v1(),
+            now,
+        ],
+    )?;
+    Ok(DocEdit::Edited)
+}
+
+/// Every text node in the document, in document order
+fn text_nodes(txn: &TransactionMut, fragment: &XmlFragmentRef) -> Vec<XmlTextRef> {
+    fragment
+        .successors(txn)
+        .filter_map(|node| match node {
+            XmlOut::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Href of the link mark among a run's formatting attributes
+fn link_href(attrs: &Attrs) -> Option<&str> {
+    let Any::Map(link) = attrs.get("link")? else {
+        return None;
+    };
+    match link.get("href")? {
+        Any::String(href) => Some(href),
+        _ => None,
+    }
+}
+
+/// Runs of text in a text node linking to `href`: (offset, text, marks).
+/// Offsets are in the document's units, bytes for a `Doc::new()`.
+fn link_runs(txn: &TransactionMut, text: &XmlTextRef, href: &str) -> Vec<(u32, String, Attrs)> {
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    for chunk in text.diff(txn, YChange::identity) {
+        let Out::Any(Any::String(chunk_text)) = &chunk.insert else {
+            // Embeds take one position
+            offset += 1;
+            continue;
+        };
+        if let Some(attrs) = chunk.attributes {
+            if link_href(&attrs) == Some(href) {
+                runs.push((offset, chunk_text.to_string(), *attrs));
+            }
+        }
+        offset += chunk_text.len() as u32;
+    }
+    runs
+}
+
+/// Replace the text of links to `href` whose text is exactly `old_text`,
+/// keeping their marks. As with `links::rewrite_link_text`, links formatted
+/// differently in parts (and so split into several runs) are left alone.
+pub(crate) fn rewrite_link_text(
+    txn: &mut TransactionMut,
+    fragment: &XmlFragmentRef,
+    href: &str,
+    old_text: &str,
+    new_text: &str,
+) -> bool {
+    let mut changed = false;
+    for text in text_nodes(txn, fragment) {
+        for (offset, run, attrs) in link_runs(txn, &text, href).into_iter().rev() {
+            if run == old_text {
+                text.remove_range(txn, offset, run.len() as u32);
+                text.insert_with_attributes(txn, offset, new_text, attrs);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Remove the link mark from links to `href`, keeping their text
+pub(crate) fn unlink(txn: &mut TransactionMut, fragment: &XmlFragmentRef, href: &str) -> bool {
+    let mut changed = false;
+    for text in text_nodes(txn, fragment) {
+        for (offset, run, _) in link_runs(txn, &text, href) {
+            let unlinked = Attrs::from([("link".into(), Any::Null)]);
+            text.format(txn, offset, run.len() as u32, unlinked);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Set an attribute of the link mark on links to `href`
+pub(crate) fn set_link_attr(
+    txn: &mut TransactionMut,
+    fragment: &XmlFragmentRef,
+    href: &str,
+    name: &str,
+    value: &str,
+) -> bool {
+    let mut changed = false;
+    for text in text_nodes(txn, fragment) {
+        for (offset, run, attrs) in link_runs(txn, &text, href) {
+            let Some(Any::Map(link)) = attrs.get("link") else {
+                continue;
+            };
+            if link.get(name) == Some(&Any::from(value)) {
+                continue;
+            }
+            let mut link = (**link).clone();
+            link.insert(name.to_string(), Any::from(value));
+            let marks = Attrs::from([("link".into(), Any::Map(Arc::new(link)))]);
+            text.format(txn, offset, run.len() as u32, marks);
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn is_image(txn: &TransactionMut, el: &XmlElementRef, src: &str) -> bool {
+    el.tag().as_ref() == "image" && el.get_attribute(txn, "src").as_deref() == Some(src)
+}
+
+/// Remove the images showing `src`
+pub(crate) fn remove_images<P: XmlFragment>(
+    txn: &mut TransactionMut,
+    parent: &P,
+    src: &str,
+) -> bool {
+    let mut changed = false;
+    for index in (0..parent.len(txn)).rev() {
+        let Some(XmlOut::Element(el)) = parent.get(txn, index) else {
+            continue;
+        };
+        if is_image(txn, &el, src) {
+            parent.remove_range(txn, index, 1);
+            changed = true;
+        } else {
+            changed |= remove_images(txn, &el, src);
+        }
+    }
+    changed
+}
+
+/// Set an attribute on the images showing `src`
+pub(crate) fn set_image_attr(
+    txn: &mut TransactionMut,
+    fragment: &XmlFragmentRef,
+    src: &str,
+    name: &str,
+    value: &str,
+) -> bool {
+    let images: Vec<XmlElementRef> = fragment
+        .successors(txn)
+        .filter_map(|node| match node {
+            XmlOut::Element(el) if is_image(txn, &el, src) => Some(el),
+            _ => None,
+        })
+        .collect();
+    let mut changed = false;
+    for image in images {
+        if image.get_attribute(txn, name).as_deref() != Some(value) {
+            image.insert_attribute(txn, name, value);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -9,8 +9,11 @@ export interface CrdtState {
 
 export interface NoteRepository {
   listNotes(folderId?: string | null): Promise<Note[]>;
-  getNote(id: string): Promise<Note | null>;
+  /** Deleted notes are only returned with `includeDeleted` (e.g. for the trash view) */
+  getNote(id: string, includeDeleted?: boolean): Promise<Note | null>;
   saveNote(note: NoteInput): Promise<Note>;
+  /** Store notes received from the server without recording them as local changes */
+  applyRemoteNotes?(notes: Note[]): Promise<void>;
   deleteNote(id: string): Promise<boolean>;
   moveNote(id: string, folderId: string | null): Promise<Note>;
   
@@ -8,6 +8,7 @@ import {
   saveCrdtState as tauriSaveCrdtState,
   getCrdtState as tauriGetCrdtState,
   getAllCrdtStates as tauriGetAllCrdtStates,
+  applySyncNotes,
 } from '../notes';
 import type { NoteRepository, CrdtState } from '../NoteRepository';
 import type { Note, NoteInput, SyncPayload, SyncResult, CrdtSyncRequest, CrdtSyncResponse, NoteMetadataUpdate } from '../../types/note';
@@ -47,8 +48,8 @@ export class TauriAdapter implements NoteRepository {
     return notes.map(mapToShared);
   }
 
-  async getNote(id: string): Promise<Note | null> {
-    const note = await tauriGetNote(id);
+  async getNote(id: string, includeDeleted = false): Promise<Note | null> {
+    const note = await tauriGetNote(id, includeDeleted);
     return note ? mapToShared(note) : null;
   }
 
@@ -77,6 +78,10 @@ export class TauriAdapter implements NoteRepository {
     return mapToShared(saved);
   }
 
+  async applyRemoteNotes(notes: Note[]): Promise<void> {
+    await applySyncNotes(notes);
+  }
+
   async deleteNote(id: string): Promise<boolean> {
     const deleted = await tauriDeleteNote(id);
     if (deleted) {
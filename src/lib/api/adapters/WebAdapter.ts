@@ -11,6 +11,11 @@ function authHeader() {
 
 async function fetchJson<T>(input: RequestInfo | URL, init?: RequestInit): Promise<T> {
   const res = await fetch(input, init);
+  if (res.status === 409) {
+    // Structured conflicts (e.g. duplicate titles) are passed through as-is
+    const body = await res.json().catch(() => null);
+    if (body?.error) throw body;
+  }
   if (!res.ok) {
     throw new Error(`Request failed: ${res.status}`);
   }
@@ -38,8 +43,9 @@ export class WebAdapter implements NoteRepository {
     });
   }
 
-  async getNote(id: string): Promise<Note | null> {
-    return fetchJson<Note>(`${this.baseUrl}/api/notes/${id}`, {
+  async getNote(id: string, includeDeleted = false): Promise<Note | null> {
+    const query = includeDeleted ? '?include_deleted=true' : '';
+    return fetchJson<Note>(`${this.baseUrl}/api/notes/${id}${query}`, {
       headers: { 'Content-Type': 'application/json', ...authHeader() } as Record<string, string>,
     });
   }
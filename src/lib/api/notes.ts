@@ -29,6 +29,7 @@ export interface NoteSummary {
   updated_at: string;
   is_deleted: boolean;
   is_canvas: boolean;
+  preview: string; // Plain-text excerpt of the content, generated on save
   content?: string; // Optional because UI might expect it, but we can make it optional
 }
 
@@ -64,10 +65,10 @@ export async function getAllNotes(): Promise<NoteSummary[]> {
 }
 
 /**
- * Get a single note by ID
+ * Get a single note by ID. Deleted notes are only returned with `includeDeleted`.
  */
-export async function getNote(id: string): Promise<Note | null> {
-  return tauriInvoke<Note | null>('get_note', { id });
+export async function getNote(id: string, includeDeleted = false): Promise<Note | null> {
+  return tauriInvoke<Note | null>('get_note', { id, includeDeleted });
 }
 
 /**
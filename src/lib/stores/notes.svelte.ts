@@ -1,4 +1,4 @@
-import type { Note, NoteSummary, NoteMetadataUpdate } from '$lib/types/note';
+import { isTitleConflict, type Note, type NoteSummary, type NoteMetadataUpdate, type TitleConflict } from '$lib/types/note';
 import { getNoteRepository } from '$lib/api/adapterContext';
 import { getYjsDocManager, uint8ArrayToBase64, type YjsDocManager } from '$lib/sync/YjsDocManager';
 import { getWebSocketSyncProvider, type WebSocketSyncProvider } from '$lib/sync/WebSocketSyncProvider';
@@ -10,6 +10,7 @@ export function createNotesStore() {
   const isTauri = typeof window !== 'undefined' && (window as any).__TAURI__;
   let notes = $state<NoteSummary[]>([]);
   let selectedNote = $state<Note | null>(null);
+  let titleConflict = $state<TitleConflict | null>(null);
   let loading = $state(false);
   let error = $state<string | null>(null);
   
@@ -161,6 +162,7 @@ export function createNotesStore() {
     error = null;
     try {
       await repo.saveNote(note);
+      titleConflict = null;
       // Update the local copy
       const index = notes.findIndex((n) => n.id === note.id);
       if (index !== -1) {
@@ -170,6 +172,11 @@ export function createNotesStore() {
         selectedNote = note;
       }
     } catch (err) {
+      if (isTitleConflict(err)) {
+        // Keep the typed title in the editor so the user can pick a suggestion
+        titleConflict = err;
+        return;
+      }
       error = err instanceof Error ? err.message : 'Failed to update note';
       console.error('Error updating note:', err);
     }
@@ -443,8 +450,14 @@ export function createNotesStore() {
    * 3. Creates local notes for server-only notes
    * 
    * @param lastSync - Timestamp of last successful sync. If provided, only notes modified since then will be pushed.
+   * @param pendingNoteIds - Notes with unpushed local changes from the oplog; takes precedence over `lastSync`.
    */
-  async function syncCrdtToServer(serverUrl: string, token: string, lastSync: string | null = null): Promise<void> {
+  async function syncCrdtToServer(
+    serverUrl: string,
+    token: string,
+    lastSync: string | null = null,
+    pendingNoteIds: Set<string> | null = null
+  ): Promise<void> {
     if (!browser) return;
 
     try {
@@ -462,10 +475,13 @@ export function createNotesStore() {
       // Prepare sync request for ALL local notes
       for (const note of allNotes) {
         // Determine if we should push updates (diffs) for this note
-        // 1. If no lastSync (first sync), push everything
-        // 2. If locally modified since last sync, push
-        // 3. If currently open in editor (might have unsaved changes), push
-        const shouldPushUpdate = !lastSync || note.updated_at > lastSync || manager.hasDoc(note.id);
+        // 1. If the oplog has unpushed changes for it (or, without an oplog,
+        //    there is no lastSync or it was modified since), push
+        // 2. If currently open in editor (might have unsaved changes), push
+        const changedLocally = pendingNoteIds
+          ? pendingNoteIds.has(note.id)
+          : !lastSync || note.updated_at > lastSync;
+        const shouldPushUpdate = changedLocally || manager.hasDoc(note.id);
 
         const contentSnapshot = manager.hasDoc(note.id)
           ? manager.getTextContent(note.id)
@@ -580,6 +596,14 @@ export function createNotesStore() {
       // Track which notes we've processed
       const processedNoteIds = new Set<string>();
 
+      // Store server state locally without journaling it as a local change,
+      // so it isn't pushed straight back on the next sync
+      const applyRemote = async (note: Note): Promise<Note> => {
+        if (!repo.applyRemoteNotes) return repo.saveNote(note);
+        await repo.applyRemoteNotes([note]);
+        return note;
+      };
+
       // Build a map of ALL local note IDs for quick lookup (not just the filtered view)
       const localNoteMap = new Map(allNotes.map(n => [n.id, n]));
 
@@ -606,7 +630,7 @@ export function createNotesStore() {
 
         if (serverMeta.is_deleted) {
           // Delete locally if server marked it deleted
-          await repo.saveNote({
+          await applyRemote({
             id: serverMeta.id,
             title: serverMeta.title,
             content: serverMeta.content ?? '',
@@ -634,13 +658,14 @@ export function createNotesStore() {
           }
           
           // Save the new note to local storage
-          const newNote = await repo.saveNote({
+          const newNote = await applyRemote({
             id: serverMeta.id,
             title: serverMeta.title,
             content,
             folder_id: serverMeta.folder_id,
             is_deleted: serverMeta.is_deleted,
             is_canvas: serverMeta.is_canvas,
+            updated_at: serverMeta.updated_at,
           });
           
           // Add to local notes list
@@ -651,7 +676,7 @@ export function createNotesStore() {
             ? manager.getTextContent(serverMeta.id)
             : (serverMeta.content ?? localNote.content ?? '');
 
-          const updated = await repo.saveNote({
+          const updated = await applyRemote({
             id: serverMeta.id,
             title: serverMeta.title,
             content: updatedContent,
@@ -693,11 +718,16 @@ export function createNotesStore() {
     error = null;
   }
 
+  function clearTitleConflict() {
+    titleConflict = null;
+  }
+
   return {
     get notes() { return notes; },
     get selectedNote() { return selectedNote; },
     get loading() { return loading; },
     get error() { return error; },
+    get titleConflict() { return titleConflict; },
     loadNotes,
     createNote,
     updateNote,
@@ -706,6 +736,7 @@ export function createNotesStore() {
     moveNote,
     selectNote,
     clearError,
+    clearTitleConflict,
     // CRDT methods
     getYjsDoc,
     getYjsFragment,
@@ -11,7 +11,8 @@ import type {
   NoteSyncStatus, 
   NoteMetadataUpdate,
   CrdtSyncRequest,
-  CrdtSyncResponse 
+  CrdtSyncResponse,
+  MaintenanceNotice
 } from '$lib/types/note';
 import { getYjsDocManager, uint8ArrayToBase64 } from './YjsDocManager';
 import { getWebSocketSyncProvider, type SyncProviderOptions } from './WebSocketSyncProvider';
@@ -39,6 +40,8 @@ export function createSyncStore(options: SyncStoreOptions) {
   let lastSyncTime = $state<string | null>(null);
   let syncError = $state<string | null>(null);
   let pendingMetadataUpdates = $state<NoteMetadataUpdate[]>([]);
+  let maintenance = $state<MaintenanceNotice | null>(null);
+  let maintenanceRetry: ReturnType<typeof setTimeout> | null = null;
 
   const docManager = getYjsDocManager({
     onLocalUpdate: (noteId, update) => {
@@ -77,8 +80,32 @@ export function createSyncStore(options: SyncStoreOptions) {
     onSyncError: (error) => {
       syncError = error.message;
     },
+    onMaintenanceChange: (notice) => {
+      setMaintenance(notice.enabled ? notice : null);
+    },
   }) : null;
 
+  /**
+   * Hold writes while the server is in maintenance; changes stay pending
+   * locally and sync is retried once it ends or the retry delay passes
+   */
+  function setMaintenance(notice: MaintenanceNotice | null): void {
+    maintenance = notice;
+    if (maintenanceRetry) {
+      clearTimeout(maintenanceRetry);
+      maintenanceRetry = null;
+    }
+    if (notice) {
+      maintenanceRetry = setTimeout(() => {
+        maintenanceRetry = null;
+        maintenance = null;
+        triggerSync();
+      }, Math.max(notice.retry_after_secs, 5) * 1000);
+    } else {
+      triggerSync();
+    }
+  }
+
   /**
    * Update the sync status for a note
    */
@@ -135,6 +162,9 @@ export function createSyncStore(options: SyncStoreOptions) {
    * Trigger a sync with the server
    */
   async function triggerSync(): Promise<void> {
+    // The server refuses writes during maintenance; keep changes pending
+    if (maintenance) return;
+
     // Get all notes that need syncing
     const noteIds = [
       ...docManager.getNotesWithPendingUpdates(),
@@ -187,6 +217,17 @@ export function createSyncStore(options: SyncStoreOptions) {
         body: JSON.stringify(request),
       });
 
+      if (response.status === 503) {
+        const notice = await response.json().catch(() => null);
+        if (notice?.error === 'maintenance') {
+          for (const noteId of noteIds) {
+            updateNoteSyncStatus(noteId, 'pending');
+          }
+          setMaintenance({ enabled: true, message: notice.message, retry_after_secs: notice.retry_after_secs });
+          return;
+        }
+      }
+
       if (!response.ok) {
         throw new Error(`Sync failed: ${response.status}`);
       }
@@ -270,6 +311,7 @@ export function createSyncStore(options: SyncStoreOptions) {
    * Destroy the sync store
    */
   function destroy(): void {
+    if (maintenanceRetry) clearTimeout(maintenanceRetry);
     wsProvider?.destroy();
     docManager.destroy();
   }
@@ -280,6 +322,7 @@ export function createSyncStore(options: SyncStoreOptions) {
     get noteSyncStatuses() { return noteSyncStatuses; },
     get lastSyncTime() { return lastSyncTime; },
     get syncError() { return syncError; },
+    get maintenance() { return maintenance; },
 
     // Document management
     initializeNote,
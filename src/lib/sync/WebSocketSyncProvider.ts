@@ -5,7 +5,7 @@
  * Implements the y-protocols for efficient CRDT sync.
  */
 
-import type { ConnectionState, WsMessage, CrdtSyncResponse, NoteMetadataUpdate } from '$lib/types/note';
+import type { ConnectionState, WsMessage, CrdtSyncResponse, NoteMetadataUpdate, MaintenanceNotice } from '$lib/types/note';
 import { getYjsDocManager, uint8ArrayToBase64, base64ToUint8Array } from './YjsDocManager';
 
 export interface SyncProviderOptions {
@@ -25,6 +25,8 @@ export interface SyncProviderOptions {
   onMetadataUpdate?: (metadata: NoteMetadataUpdate) => void;
   /** Callback on sync error */
   onSyncError?: (error: Error) => void;
+  /** Callback when the server enters or leaves maintenance mode */
+  onMaintenanceChange?: (notice: MaintenanceNotice) => void;
 }
 
 /**
@@ -253,6 +255,9 @@ export class WebSocketSyncProvider {
         case 'note_metadata':
           this.handleMetadataUpdate(message);
           break;
+        case 'maintenance':
+          this.options.onMaintenanceChange?.(message as unknown as MaintenanceNotice);
+          break;
         case 'awareness':
           // Handle awareness (cursors, presence) - future enhancement
           break;
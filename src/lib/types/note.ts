@@ -1,9 +1,16 @@
+/**
+ * RFC 3339 timestamp. The backends reject anything else and store it as UTC
+ * with millisecond precision (`2024-01-31T09:15:00.000Z`), the form produced
+ * by `Date.prototype.toISOString()`, so string order matches time order.
+ */
+export type Timestamp = string;
+
 export interface Note {
   id: string;
   title: string;
   content: string;
   folder_id: string | null;
-  updated_at: string;
+  updated_at: Timestamp;
   is_deleted: boolean;
   is_canvas: boolean;
 }
@@ -12,7 +19,7 @@ export type NoteSummary = Note;
 
 export type NoteInput = Omit<Note, 'id' | 'updated_at'> & {
   id?: string;
-  updated_at?: string;
+  updated_at?: Timestamp;
 };
 
 // Legacy sync (will be deprecated)
@@ -91,7 +98,8 @@ export type WsMessageType =
   | 'note_metadata'
   | 'awareness'
   | 'subscribe'
-  | 'unsubscribe';
+  | 'unsubscribe'
+  | 'maintenance';
 
 export interface WsMessage {
   type: WsMessageType;
@@ -99,6 +107,33 @@ export interface WsMessage {
   payload: string; // base64-encoded binary data
 }
 
+/**
+ * Save refused because another note in the folder already has the title
+ * (only when unique titles are enabled)
+ */
+export interface TitleConflict {
+  error: 'title_conflict';
+  message: string;
+  title: string;
+  folder_id: string | null;
+  existing_note_id: string;
+  suggestions: string[];
+}
+
+export function isTitleConflict(err: unknown): err is TitleConflict {
+  return typeof err === 'object' && err !== null && (err as { error?: unknown }).error === 'title_conflict';
+}
+
+/**
+ * Server maintenance status. While enabled the server refuses writes with a
+ * 503, so local changes stay queued until it is switched off.
+ */
+export interface MaintenanceNotice {
+  enabled: boolean;
+  message: string;
+  retry_after_secs: number;
+}
+
 /**
  * Connection state for WebSocket sync
  */